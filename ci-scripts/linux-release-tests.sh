@@ -24,6 +24,9 @@ python3 script/headless.py test_shaders
 python3 script/headless.py reftest
 python3 script/headless.py rawtest
 python3 script/headless.py test_invalidation
+# Run the text reftests against the alternative glyph backends too.
+python3 script/headless.py reftest --glyph-backend swash reftests/text
+python3 script/headless.py reftest --glyph-backend fontdue reftests/text
 CXX=clang++ cargo run ${CARGOFLAGS} --release --features=software -- \
   --software --headless reftest
 popd
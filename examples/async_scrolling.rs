@@ -0,0 +1,358 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Async scrolling, the way embedders with their own scrolling logic drive it:
+//! the display list is built once, and wheel and touch events only update
+//! scroll offsets with `set_scroll_offsets`, which doesn't rebuild the scene.
+//! Hit testing picks the scroll frame under the cursor, and each section of
+//! the page has a header that sticks to the top of the window while its
+//! section is visible.
+
+#[path = "common/boilerplate.rs"]
+mod boilerplate;
+
+use crate::boilerplate::{Example, HandyDandyRectBuilder};
+use euclid::SideOffsets2D;
+use webrender::api::*;
+use webrender::render_api::*;
+use webrender::api::units::*;
+use winit::dpi::LogicalPosition;
+
+const VIEWPORT_WIDTH: f32 = 800.0;
+const VIEWPORT_HEIGHT: f32 = 600.0;
+const SECTION_COUNT: usize = 30;
+const SECTION_HEIGHT: f32 = 400.0;
+const HEADER_HEIGHT: f32 = 50.0;
+const CARD_COUNT: usize = 12;
+const CARD_WIDTH: f32 = 220.0;
+const CAROUSEL_HEIGHT: f32 = 200.0;
+const LINE_HEIGHT: f32 = 38.0;
+
+/// Index of the page in `App::scroll_frames`, carousels come after it.
+const PAGE: usize = 0;
+
+/// The state of a scroll frame, as tracked by the embedder.
+struct ScrollFrame {
+    id: ExternalScrollId,
+    offset: LayoutVector2D,
+    max_offset: LayoutVector2D,
+}
+
+impl ScrollFrame {
+    fn new(id: u64, viewport: LayoutSize, content: LayoutSize) -> Self {
+        ScrollFrame {
+            id: ExternalScrollId(id, PipelineId::dummy()),
+            offset: LayoutVector2D::zero(),
+            max_offset: LayoutVector2D::new(
+                (content.width - viewport.width).max(0.0),
+                (content.height - viewport.height).max(0.0),
+            ),
+        }
+    }
+
+    /// Scrolls by `delta`, and returns the part of it that couldn't be
+    /// consumed because the frame reached its bounds.
+    fn scroll_by(&mut self, delta: LayoutVector2D) -> LayoutVector2D {
+        let old_offset = self.offset;
+        self.offset = (self.offset + delta).max(LayoutVector2D::zero()).min(self.max_offset);
+        delta - (self.offset - old_offset)
+    }
+}
+
+struct App {
+    scroll_frames: Vec<ScrollFrame>,
+    cursor_position: WorldPoint,
+    /// The last position of the touch point driving the scroll, if any.
+    touch_position: Option<WorldPoint>,
+}
+
+impl App {
+    fn new() -> Self {
+        let mut scroll_frames = vec![
+            ScrollFrame::new(
+                PAGE as u64,
+                LayoutSize::new(VIEWPORT_WIDTH, VIEWPORT_HEIGHT),
+                LayoutSize::new(VIEWPORT_WIDTH, SECTION_COUNT as f32 * SECTION_HEIGHT),
+            ),
+        ];
+        for _ in (0 .. SECTION_COUNT).filter(|section| Self::has_carousel(*section)) {
+            scroll_frames.push(ScrollFrame::new(
+                scroll_frames.len() as u64,
+                LayoutSize::new(VIEWPORT_WIDTH, CAROUSEL_HEIGHT),
+                LayoutSize::new(CARD_COUNT as f32 * CARD_WIDTH, CAROUSEL_HEIGHT),
+            ));
+        }
+
+        App {
+            scroll_frames,
+            cursor_position: WorldPoint::zero(),
+            touch_position: None,
+        }
+    }
+
+    fn has_carousel(section: usize) -> bool {
+        section % 3 == 1
+    }
+
+    /// Returns the scroll frames containing the point, innermost first. Hit
+    /// test tags are (scroll frame index, item index).
+    fn scroll_frames_at(&self, api: &RenderApi, document_id: DocumentId, point: WorldPoint) -> Vec<usize> {
+        let mut frames: Vec<usize> = api
+            .hit_test(document_id, point)
+            .items
+            .iter()
+            .map(|item| item.tag.0 as usize)
+            .collect();
+        frames.dedup();
+        if !frames.contains(&PAGE) {
+            frames.push(PAGE);
+        }
+        frames
+    }
+
+    /// Scrolls the frames under `point` by `delta`, handing whatever a frame
+    /// can't consume over to its parent, and sends the new offsets.
+    fn scroll(
+        &mut self,
+        api: &mut RenderApi,
+        document_id: DocumentId,
+        point: WorldPoint,
+        mut delta: LayoutVector2D,
+    ) {
+        let mut txn = Transaction::new();
+
+        for index in self.scroll_frames_at(api, document_id, point) {
+            let frame = &mut self.scroll_frames[index];
+            let remaining = frame.scroll_by(delta);
+            if remaining != delta {
+                txn.set_scroll_offsets(
+                    frame.id,
+                    vec![SampledScrollOffset {
+                        offset: frame.offset,
+                        generation: APZScrollGeneration::default(),
+                    }],
+                );
+            }
+            delta = remaining;
+            if delta == LayoutVector2D::zero() {
+                break;
+            }
+        }
+
+        txn.generate_frame(0, RenderReasons::empty());
+        api.send_transaction(document_id, txn);
+    }
+
+    /// Adds a horizontally scrolling row of cards to the page.
+    fn push_carousel(
+        &self,
+        builder: &mut DisplayListBuilder,
+        page_space_and_clip: SpaceAndClipInfo,
+        index: usize,
+        top: f32,
+    ) {
+        let frame_rect = (0, top as i32).by(VIEWPORT_WIDTH as i32, CAROUSEL_HEIGHT as i32);
+        let content_rect = LayoutRect::from_origin_and_size(
+            frame_rect.min,
+            LayoutSize::new(CARD_COUNT as f32 * CARD_WIDTH, CAROUSEL_HEIGHT),
+        );
+        let space = builder.define_scroll_frame(
+            page_space_and_clip.spatial_id,
+            self.scroll_frames[index].id,
+            content_rect,
+            frame_rect,
+            LayoutVector2D::zero(),
+            APZScrollGeneration::default(),
+            HasScrollLinkedEffect::No,
+            SpatialTreeItemKey::new(2, index as u64),
+        );
+        let clip = builder.define_clip_rect(page_space_and_clip.spatial_id, frame_rect);
+        let space_and_clip = SpaceAndClipInfo {
+            spatial_id: space,
+            clip_chain_id: builder.define_clip_chain(Some(page_space_and_clip.clip_chain_id), [clip]),
+        };
+
+        for card in 0 .. CARD_COUNT {
+            let card_rect = LayoutRect::from_origin_and_size(
+                LayoutPoint::new(card as f32 * CARD_WIDTH, top),
+                LayoutSize::new(CARD_WIDTH, CAROUSEL_HEIGHT),
+            ).inflate(-10.0, -10.0);
+            let info = CommonItemProperties::new(card_rect, space_and_clip);
+            builder.push_hit_test(
+                info.clip_rect,
+                info.clip_chain_id,
+                info.spatial_id,
+                info.flags,
+                (index as u64, card as u16),
+            );
+            let shade = card as f32 / CARD_COUNT as f32;
+            builder.push_rect(&info, info.clip_rect, ColorF::new(1.0, shade, 0.2, 1.0));
+        }
+    }
+}
+
+impl Example for App {
+    const TITLE: &'static str = "Async scrolling";
+    const WIDTH: u32 = VIEWPORT_WIDTH as u32;
+    const HEIGHT: u32 = VIEWPORT_HEIGHT as u32;
+
+    fn render(
+        &mut self,
+        _api: &mut RenderApi,
+        builder: &mut DisplayListBuilder,
+        _txn: &mut Transaction,
+        _device_size: DeviceIntSize,
+        pipeline_id: PipelineId,
+        _document_id: DocumentId,
+    ) {
+        let root_space_and_clip = SpaceAndClipInfo::root_scroll(pipeline_id);
+        builder.push_simple_stacking_context(
+            LayoutPoint::zero(),
+            root_space_and_clip.spatial_id,
+            PrimitiveFlags::IS_BACKFACE_VISIBLE,
+        );
+
+        let viewport = LayoutRect::from_size(LayoutSize::new(VIEWPORT_WIDTH, VIEWPORT_HEIGHT));
+        let page = &self.scroll_frames[PAGE];
+        let page_space = builder.define_scroll_frame(
+            root_space_and_clip.spatial_id,
+            page.id,
+            LayoutRect::from_size(LayoutSize::new(VIEWPORT_WIDTH, SECTION_COUNT as f32 * SECTION_HEIGHT)),
+            viewport,
+            LayoutVector2D::zero(),
+            APZScrollGeneration::default(),
+            HasScrollLinkedEffect::No,
+            SpatialTreeItemKey::new(0, 0),
+        );
+        let page_clip = builder.define_clip_rect(root_space_and_clip.spatial_id, viewport);
+        let page_space_and_clip = SpaceAndClipInfo {
+            spatial_id: page_space,
+            clip_chain_id: builder.define_clip_chain(None, [page_clip]),
+        };
+
+        let mut carousel_index = PAGE + 1;
+        for section in 0 .. SECTION_COUNT {
+            let top = section as f32 * SECTION_HEIGHT;
+            let section_rect = LayoutRect::from_origin_and_size(
+                LayoutPoint::new(0.0, top),
+                LayoutSize::new(VIEWPORT_WIDTH, SECTION_HEIGHT),
+            );
+            let shade = if section % 2 == 0 { 0.95 } else { 0.85 };
+            let info = CommonItemProperties::new(section_rect, page_space_and_clip);
+            builder.push_hit_test(
+                info.clip_rect,
+                info.clip_chain_id,
+                info.spatial_id,
+                info.flags,
+                (PAGE as u64, section as u16),
+            );
+            builder.push_rect(&info, info.clip_rect, ColorF::new(shade, shade, shade, 1.0));
+
+            if Self::has_carousel(section) {
+                self.push_carousel(builder, page_space_and_clip, carousel_index, top + 120.0);
+                carousel_index += 1;
+            }
+
+            // The header sticks to the top of the window until the bottom of
+            // its section pushes it out.
+            let header_rect = LayoutRect::from_origin_and_size(
+                LayoutPoint::new(0.0, top),
+                LayoutSize::new(VIEWPORT_WIDTH, HEADER_HEIGHT),
+            );
+            let sticky_id = builder.define_sticky_frame(
+                page_space,
+                header_rect,
+                SideOffsets2D::new(Some(0.0), None, None, None),
+                StickyOffsetBounds::new(0.0, SECTION_HEIGHT - HEADER_HEIGHT),
+                StickyOffsetBounds::new(0.0, 0.0),
+                LayoutVector2D::zero(),
+                SpatialTreeItemKey::new(1, section as u64),
+            );
+            let info = CommonItemProperties::new(
+                header_rect,
+                SpaceAndClipInfo {
+                    spatial_id: sticky_id,
+                    clip_chain_id: page_space_and_clip.clip_chain_id,
+                },
+            );
+            builder.push_hit_test(
+                info.clip_rect,
+                info.clip_chain_id,
+                info.spatial_id,
+                info.flags,
+                (PAGE as u64, section as u16),
+            );
+            let hue = section as f32 / SECTION_COUNT as f32;
+            builder.push_rect(&info, info.clip_rect, ColorF::new(hue, 0.3, 1.0 - hue, 1.0));
+        }
+
+        builder.pop_stacking_context();
+    }
+
+    fn on_event(
+        &mut self,
+        event: winit::event::WindowEvent,
+        window: &winit::window::Window,
+        api: &mut RenderApi,
+        document_id: DocumentId,
+    ) -> bool {
+        match event {
+            winit::event::WindowEvent::CursorMoved { position, .. } => {
+                let pos: LogicalPosition<f32> = position.to_logical(window.scale_factor());
+                self.cursor_position = WorldPoint::new(pos.x, pos.y);
+            }
+            winit::event::WindowEvent::MouseWheel { delta, .. } => {
+                let (dx, dy) = match delta {
+                    winit::event::MouseScrollDelta::LineDelta(dx, dy) => (dx * LINE_HEIGHT, dy * LINE_HEIGHT),
+                    winit::event::MouseScrollDelta::PixelDelta(pos) => (pos.x as f32, pos.y as f32),
+                };
+                // Wheel deltas are positive when scrolling up or left.
+                let point = self.cursor_position;
+                self.scroll(api, document_id, point, LayoutVector2D::new(-dx, -dy));
+            }
+            winit::event::WindowEvent::Touch(touch) => {
+                let pos: LogicalPosition<f32> = touch.location.to_logical(window.scale_factor());
+                let pos = WorldPoint::new(pos.x, pos.y);
+                match touch.phase {
+                    winit::event::TouchPhase::Started => {
+                        self.touch_position = Some(pos);
+                    }
+                    winit::event::TouchPhase::Moved => {
+                        if let Some(last) = self.touch_position.replace(pos) {
+                            // Content follows the finger.
+                            let delta = last - pos;
+                            self.scroll(api, document_id, pos, LayoutVector2D::new(delta.x, delta.y));
+                        }
+                    }
+                    winit::event::TouchPhase::Ended | winit::event::TouchPhase::Cancelled => {
+                        self.touch_position = None;
+                    }
+                }
+            }
+            winit::event::WindowEvent::MouseInput {
+                state: winit::event::ElementState::Pressed,
+                ..
+            } => {
+                let results = api.hit_test(document_id, self.cursor_position);
+                match results.items.first() {
+                    Some(item) if item.tag.0 == PAGE as u64 => {
+                        println!("Clicked section {}", item.tag.1);
+                    }
+                    Some(item) => {
+                        println!("Clicked card {} of carousel {}", item.tag.1, item.tag.0);
+                    }
+                    None => {}
+                }
+            }
+            _ => (),
+        }
+
+        false
+    }
+}
+
+fn main() {
+    let mut app = App::new();
+    boilerplate::main_wrapper(&mut app, None);
+}
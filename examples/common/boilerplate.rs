@@ -176,6 +176,9 @@ pub fn main_wrapper<E: Example>(
         surfman::SurfaceType::Widget { native_widget: widget },
     ).unwrap();
     device.bind_surface_to_context(&mut context, surface).unwrap();
+    // Whether the window surface was released because the application was
+    // suspended (on Android, when it goes to the background).
+    let mut suspended = false;
 
     println!("Loading shaders...");
     let mut debug_flags = DebugFlags::ECHO_DRIVER_MESSAGES | DebugFlags::TEXTURE_CACHE_DBG;
@@ -188,7 +191,7 @@ pub fn main_wrapper<E: Example>(
         ..options.unwrap_or(webrender::WebRenderOptions::default())
     };
 
-    let device_size = {
+    let mut device_size = {
         let size = window
             .inner_size();
         DeviceIntSize::new(size.width as i32, size.height as i32)
@@ -238,71 +241,106 @@ pub fn main_wrapper<E: Example>(
 
         let old_flags = debug_flags;
         let win_event = match global_event {
-            winit::event::Event::WindowEvent { event, .. } => event,
-            _ => return,
-        };
-        match win_event {
-            winit::event::WindowEvent::CloseRequested => {
-                *control_flow = winit::event_loop::ControlFlow::Exit;
+            winit::event::Event::WindowEvent { event, .. } => Some(event),
+            winit::event::Event::Suspended => {
+                // The native window is about to be destroyed. Only release the
+                // surface: the context, the renderer and its documents and
+                // resources are kept, so resuming doesn't need to rebuild them.
+                if let Some(mut surface) = device.unbind_surface_from_context(&mut context).unwrap() {
+                    device.destroy_surface(&mut context, &mut surface).unwrap();
+                }
+                suspended = true;
                 return;
             }
-            winit::event::WindowEvent::AxisMotion { .. } |
-            winit::event::WindowEvent::CursorMoved { .. } => {
-                custom_event = example.on_event(
-                    win_event,
-                    &window,
-                    &mut api,
-                    document_id,
-                );
-                // skip high-frequency events from triggering a frame draw.
-                if !custom_event {
-                    return;
-                }
-            },
-            winit::event::WindowEvent::KeyboardInput {
-                input: winit::event::KeyboardInput {
-                    state: winit::event::ElementState::Pressed,
-                    virtual_keycode: Some(key),
-                    ..
-                },
-                ..
-            } => match key {
-                winit::event::VirtualKeyCode::Escape => {
+            winit::event::Event::Resumed if suspended => {
+                // This relies on the GL context surviving the suspension,
+                // which `make_context_current` fails on otherwise. WebRender
+                // doesn't recreate the GL objects of a lost context, so an
+                // application handling that would have to create a new
+                // renderer and add its documents and resources again.
+                let widget = connection.create_native_widget_from_winit_window(&window).unwrap();
+                let surface = device.create_surface(
+                    &context,
+                    surfman::SurfaceAccess::GPUOnly,
+                    surfman::SurfaceType::Widget { native_widget: widget },
+                ).unwrap();
+                device.bind_surface_to_context(&mut context, surface).unwrap();
+                device.make_context_current(&context).unwrap();
+                suspended = false;
+
+                // The window may have been resized (e.g. rotated) meanwhile.
+                let size = window.inner_size();
+                device_size = DeviceIntSize::new(size.width as i32, size.height as i32);
+                txn.set_document_view(DeviceIntRect::from_size(device_size));
+                renderer.surface_recreated();
+                None
+            }
+            _ => return,
+        };
+        if let Some(win_event) = win_event {
+            match win_event {
+                winit::event::WindowEvent::CloseRequested => {
                     *control_flow = winit::event_loop::ControlFlow::Exit;
                     return;
                 }
-                winit::event::VirtualKeyCode::P => debug_flags.toggle(DebugFlags::PROFILER_DBG),
-                winit::event::VirtualKeyCode::O => debug_flags.toggle(DebugFlags::RENDER_TARGET_DBG),
-                winit::event::VirtualKeyCode::I => debug_flags.toggle(DebugFlags::TEXTURE_CACHE_DBG),
-                winit::event::VirtualKeyCode::T => debug_flags.toggle(DebugFlags::PICTURE_CACHING_DBG),
-                winit::event::VirtualKeyCode::Q => debug_flags.toggle(
-                    DebugFlags::GPU_TIME_QUERIES | DebugFlags::GPU_SAMPLE_QUERIES
-                ),
-                winit::event::VirtualKeyCode::G => debug_flags.toggle(DebugFlags::GPU_CACHE_DBG),
-                winit::event::VirtualKeyCode::M => api.notify_memory_pressure(),
-                winit::event::VirtualKeyCode::C => {
-                    let path: PathBuf = "../captures/example".into();
-                    //TODO: switch between SCENE/FRAME capture types
-                    // based on "shift" modifier, when `glutin` is updated.
-                    let bits = CaptureBits::all();
-                    api.save_capture(path, bits);
-                },
-                _ => {
+                winit::event::WindowEvent::AxisMotion { .. } |
+                winit::event::WindowEvent::CursorMoved { .. } => {
                     custom_event = example.on_event(
                         win_event,
                         &window,
                         &mut api,
                         document_id,
-                    )
+                    );
+                    // skip high-frequency events from triggering a frame draw.
+                    if !custom_event {
+                        return;
+                    }
                 },
-            },
-            other => custom_event = example.on_event(
-                other,
-                &window,
-                &mut api,
-                document_id,
-            ),
-        };
+                winit::event::WindowEvent::KeyboardInput {
+                    input: winit::event::KeyboardInput {
+                        state: winit::event::ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                    ..
+                } => match key {
+                    winit::event::VirtualKeyCode::Escape => {
+                        *control_flow = winit::event_loop::ControlFlow::Exit;
+                        return;
+                    }
+                    winit::event::VirtualKeyCode::P => debug_flags.toggle(DebugFlags::PROFILER_DBG),
+                    winit::event::VirtualKeyCode::O => debug_flags.toggle(DebugFlags::RENDER_TARGET_DBG),
+                    winit::event::VirtualKeyCode::I => debug_flags.toggle(DebugFlags::TEXTURE_CACHE_DBG),
+                    winit::event::VirtualKeyCode::T => debug_flags.toggle(DebugFlags::PICTURE_CACHING_DBG),
+                    winit::event::VirtualKeyCode::Q => debug_flags.toggle(
+                        DebugFlags::GPU_TIME_QUERIES | DebugFlags::GPU_SAMPLE_QUERIES
+                    ),
+                    winit::event::VirtualKeyCode::G => debug_flags.toggle(DebugFlags::GPU_CACHE_DBG),
+                    winit::event::VirtualKeyCode::M => api.notify_memory_pressure(),
+                    winit::event::VirtualKeyCode::C => {
+                        let path: PathBuf = "../captures/example".into();
+                        //TODO: switch between SCENE/FRAME capture types
+                        // based on "shift" modifier, when `glutin` is updated.
+                        let bits = CaptureBits::all();
+                        api.save_capture(path, bits);
+                    },
+                    _ => {
+                        custom_event = example.on_event(
+                            win_event,
+                            &window,
+                            &mut api,
+                            document_id,
+                        )
+                    },
+                },
+                other => custom_event = example.on_event(
+                    other,
+                    &window,
+                    &mut api,
+                    document_id,
+                ),
+            }
+        }
 
         if debug_flags != old_flags {
             api.send_debug_cmd(DebugCommand::SetFlags(debug_flags));
@@ -328,6 +366,12 @@ pub fn main_wrapper<E: Example>(
         }
         api.send_transaction(document_id, txn);
 
+        if suspended {
+            // Keep updating the documents, but there is nothing to draw into.
+            *control_flow = winit::event_loop::ControlFlow::Wait;
+            return;
+        }
+
         let framebuffer_object = device
             .context_surface_info(&context)
             .unwrap()
@@ -0,0 +1,530 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Embedding WebRender in an application that already has its own GL render
+//! loop, as game engines and visualization tools do.
+//!
+//! The host draws a 3D scene, then WebRender renders a UI into an offscreen
+//! framebuffer of the same GL context, which the host composites over its
+//! scene. WebRender changes a lot of GL state while rendering, so the host
+//! saves the state it relies on before calling `Renderer::render` and restores
+//! it afterwards.
+
+use gleam::gl;
+use std::rc::Rc;
+use std::time::Instant;
+use webrender::api::*;
+use webrender::api::units::*;
+use webrender::render_api::*;
+use winit::dpi::LogicalSize;
+use winit::platform::run_return::EventLoopExtRunReturn;
+
+struct Notifier;
+
+impl RenderNotifier for Notifier {
+    fn clone(&self) -> Box<dyn RenderNotifier> {
+        Box::new(Notifier)
+    }
+
+    // The host redraws continuously, so there is no need to wake it up.
+    fn wake_up(&self, _composite_needed: bool) {}
+
+    fn new_frame_ready(&self, _: DocumentId, _: bool, _: bool, _: FramePublishId) {}
+}
+
+/// The parts of the GL state the host relies on across frames.
+struct SavedGlState {
+    program: gl::GLint,
+    vertex_array: gl::GLint,
+    array_buffer: gl::GLint,
+    draw_framebuffer: gl::GLint,
+    read_framebuffer: gl::GLint,
+    active_texture: gl::GLint,
+    texture_2d: gl::GLint,
+    viewport: [gl::GLint; 4],
+    blend_func: [gl::GLint; 4],
+    blend: bool,
+    depth_test: bool,
+    scissor_test: bool,
+    cull_face: bool,
+}
+
+impl SavedGlState {
+    fn save(gl: &dyn gl::Gl) -> Self {
+        let get = |name| {
+            let mut value = [0];
+            unsafe {
+                gl.get_integer_v(name, &mut value);
+            }
+            value[0]
+        };
+        let mut viewport = [0; 4];
+        unsafe {
+            gl.get_integer_v(gl::VIEWPORT, &mut viewport);
+        }
+
+        SavedGlState {
+            program: get(gl::CURRENT_PROGRAM),
+            vertex_array: get(gl::VERTEX_ARRAY_BINDING),
+            array_buffer: get(gl::ARRAY_BUFFER_BINDING),
+            draw_framebuffer: get(gl::DRAW_FRAMEBUFFER_BINDING),
+            read_framebuffer: get(gl::READ_FRAMEBUFFER_BINDING),
+            active_texture: get(gl::ACTIVE_TEXTURE),
+            texture_2d: get(gl::TEXTURE_BINDING_2D),
+            viewport,
+            blend_func: [
+                get(gl::BLEND_SRC_RGB),
+                get(gl::BLEND_DST_RGB),
+                get(gl::BLEND_SRC_ALPHA),
+                get(gl::BLEND_DST_ALPHA),
+            ],
+            blend: gl.is_enabled(gl::BLEND) != 0,
+            depth_test: gl.is_enabled(gl::DEPTH_TEST) != 0,
+            scissor_test: gl.is_enabled(gl::SCISSOR_TEST) != 0,
+            cull_face: gl.is_enabled(gl::CULL_FACE) != 0,
+        }
+    }
+
+    fn restore(&self, gl: &dyn gl::Gl) {
+        gl.use_program(self.program as gl::GLuint);
+        gl.bind_vertex_array(self.vertex_array as gl::GLuint);
+        gl.bind_buffer(gl::ARRAY_BUFFER, self.array_buffer as gl::GLuint);
+        gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, self.draw_framebuffer as gl::GLuint);
+        gl.bind_framebuffer(gl::READ_FRAMEBUFFER, self.read_framebuffer as gl::GLuint);
+        gl.active_texture(self.active_texture as gl::GLenum);
+        gl.bind_texture(gl::TEXTURE_2D, self.texture_2d as gl::GLuint);
+        gl.viewport(self.viewport[0], self.viewport[1], self.viewport[2], self.viewport[3]);
+        gl.blend_func_separate(
+            self.blend_func[0] as gl::GLenum,
+            self.blend_func[1] as gl::GLenum,
+            self.blend_func[2] as gl::GLenum,
+            self.blend_func[3] as gl::GLenum,
+        );
+        for &(cap, enabled) in &[
+            (gl::BLEND, self.blend),
+            (gl::DEPTH_TEST, self.depth_test),
+            (gl::SCISSOR_TEST, self.scissor_test),
+            (gl::CULL_FACE, self.cull_face),
+        ] {
+            if enabled {
+                gl.enable(cap);
+            } else {
+                gl.disable(cap);
+            }
+        }
+    }
+}
+
+/// An offscreen framebuffer WebRender renders the UI into.
+struct UiTarget {
+    size: DeviceIntSize,
+    framebuffer: gl::GLuint,
+    texture: gl::GLuint,
+    depth_stencil: gl::GLuint,
+}
+
+impl UiTarget {
+    fn new(gl: &dyn gl::Gl, size: DeviceIntSize) -> Self {
+        let texture = gl.gen_textures(1)[0];
+        gl.bind_texture(gl::TEXTURE_2D, texture);
+        gl.tex_image_2d(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA8 as gl::GLint,
+            size.width,
+            size.height,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            None,
+        );
+        gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as gl::GLint);
+        gl.tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as gl::GLint);
+        gl.bind_texture(gl::TEXTURE_2D, 0);
+
+        // WebRender uses the depth buffer to reject hidden pixels early.
+        let depth_stencil = gl.gen_renderbuffers(1)[0];
+        gl.bind_renderbuffer(gl::RENDERBUFFER, depth_stencil);
+        gl.renderbuffer_storage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, size.width, size.height);
+        gl.bind_renderbuffer(gl::RENDERBUFFER, 0);
+
+        let framebuffer = gl.gen_framebuffers(1)[0];
+        gl.bind_framebuffer(gl::FRAMEBUFFER, framebuffer);
+        gl.framebuffer_texture_2d(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, texture, 0);
+        gl.framebuffer_renderbuffer(
+            gl::FRAMEBUFFER,
+            gl::DEPTH_STENCIL_ATTACHMENT,
+            gl::RENDERBUFFER,
+            depth_stencil,
+        );
+        assert_eq!(gl.check_frame_buffer_status(gl::FRAMEBUFFER), gl::FRAMEBUFFER_COMPLETE);
+        gl.bind_framebuffer(gl::FRAMEBUFFER, 0);
+
+        UiTarget { size, framebuffer, texture, depth_stencil }
+    }
+
+    fn deinit(self, gl: &dyn gl::Gl) {
+        gl.delete_framebuffers(&[self.framebuffer]);
+        gl.delete_renderbuffers(&[self.depth_stencil]);
+        gl.delete_textures(&[self.texture]);
+    }
+}
+
+const SCENE_VS: &str = "
+in vec3 aPosition;
+in vec3 aColor;
+uniform float uAngle;
+out vec3 vColor;
+void main() {
+    float c = cos(uAngle);
+    float s = sin(uAngle);
+    vec3 p = vec3(c * aPosition.x + s * aPosition.z, aPosition.y, c * aPosition.z - s * aPosition.x);
+    gl_Position = vec4(p.xy, p.z * 0.5, 1.5 + p.z * 0.5);
+    vColor = aColor;
+}
+";
+
+const SCENE_FS: &str = "
+in vec3 vColor;
+out vec4 oColor;
+void main() {
+    oColor = vec4(vColor, 1.0);
+}
+";
+
+// Draws a triangle covering the whole viewport without any vertex buffer.
+const COMPOSITE_VS: &str = "
+out vec2 vUv;
+void main() {
+    vUv = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    gl_Position = vec4(vUv * 2.0 - 1.0, 0.0, 1.0);
+}
+";
+
+const COMPOSITE_FS: &str = "
+uniform sampler2D uTexture;
+in vec2 vUv;
+out vec4 oColor;
+void main() {
+    oColor = texture(uTexture, vUv);
+}
+";
+
+fn compile_program(gl: &dyn gl::Gl, vs: &str, fs: &str) -> gl::GLuint {
+    let header = match gl.get_type() {
+        gl::GlType::Gl => "#version 150\n",
+        gl::GlType::Gles => "#version 300 es\nprecision mediump float;\n",
+    };
+
+    let program = gl.create_program();
+    for &(kind, source) in &[(gl::VERTEX_SHADER, vs), (gl::FRAGMENT_SHADER, fs)] {
+        let shader = gl.create_shader(kind);
+        gl.shader_source(shader, &[header.as_bytes(), source.as_bytes()]);
+        gl.compile_shader(shader);
+        let mut status = [0];
+        unsafe {
+            gl.get_shader_iv(shader, gl::COMPILE_STATUS, &mut status);
+        }
+        assert_ne!(status[0], 0, "{}", gl.get_shader_info_log(shader));
+        gl.attach_shader(program, shader);
+        gl.delete_shader(shader);
+    }
+    gl.bind_attrib_location(program, 0, "aPosition");
+    gl.bind_attrib_location(program, 1, "aColor");
+    gl.link_program(program);
+    let mut status = [0];
+    unsafe {
+        gl.get_program_iv(program, gl::LINK_STATUS, &mut status);
+    }
+    assert_ne!(status[0], 0, "{}", gl.get_program_info_log(program));
+
+    program
+}
+
+/// The host application's own 3D content: a spinning triangle.
+struct Scene {
+    program: gl::GLuint,
+    angle_location: gl::GLint,
+    vertex_array: gl::GLuint,
+    vertex_buffer: gl::GLuint,
+}
+
+impl Scene {
+    fn new(gl: &dyn gl::Gl) -> Self {
+        let program = compile_program(gl, SCENE_VS, SCENE_FS);
+        let angle_location = gl.get_uniform_location(program, "uAngle");
+
+        #[rustfmt::skip]
+        let vertices: [f32; 18] = [
+            // position        color
+            -0.8, -0.7, 0.0,   1.0, 0.2, 0.2,
+             0.8, -0.7, 0.0,   0.2, 1.0, 0.2,
+             0.0,  0.8, 0.0,   0.2, 0.2, 1.0,
+        ];
+        let vertex_array = gl.gen_vertex_arrays(1)[0];
+        gl.bind_vertex_array(vertex_array);
+        let vertex_buffer = gl.gen_buffers(1)[0];
+        gl.bind_buffer(gl::ARRAY_BUFFER, vertex_buffer);
+        gl::buffer_data(gl, gl::ARRAY_BUFFER, &vertices, gl::STATIC_DRAW);
+        let stride = 6 * std::mem::size_of::<f32>() as gl::GLsizei;
+        gl.vertex_attrib_pointer(0, 3, gl::FLOAT, false, stride, 0);
+        gl.vertex_attrib_pointer(1, 3, gl::FLOAT, false, stride, 3 * std::mem::size_of::<f32>() as u32);
+        gl.enable_vertex_attrib_array(0);
+        gl.enable_vertex_attrib_array(1);
+        gl.bind_vertex_array(0);
+
+        Scene { program, angle_location, vertex_array, vertex_buffer }
+    }
+
+    fn draw(&self, gl: &dyn gl::Gl, angle: f32) {
+        gl.clear_color(0.1, 0.1, 0.15, 1.0);
+        gl.clear_depth(1.0);
+        gl.clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        gl.enable(gl::DEPTH_TEST);
+        gl.disable(gl::BLEND);
+        gl.use_program(self.program);
+        gl.uniform_1f(self.angle_location, angle);
+        gl.bind_vertex_array(self.vertex_array);
+        gl.draw_arrays(gl::TRIANGLES, 0, 3);
+    }
+
+    fn deinit(self, gl: &dyn gl::Gl) {
+        gl.delete_vertex_arrays(&[self.vertex_array]);
+        gl.delete_buffers(&[self.vertex_buffer]);
+        gl.delete_program(self.program);
+    }
+}
+
+/// Draws the UI rendered by WebRender over the host's scene.
+struct Compositor {
+    program: gl::GLuint,
+    vertex_array: gl::GLuint,
+}
+
+impl Compositor {
+    fn new(gl: &dyn gl::Gl) -> Self {
+        let program = compile_program(gl, COMPOSITE_VS, COMPOSITE_FS);
+        gl.use_program(program);
+        gl.uniform_1i(gl.get_uniform_location(program, "uTexture"), 0);
+        gl.use_program(0);
+
+        Compositor {
+            program,
+            // Core profiles can't draw without a vertex array bound.
+            vertex_array: gl.gen_vertex_arrays(1)[0],
+        }
+    }
+
+    fn draw(&self, gl: &dyn gl::Gl, ui: &UiTarget) {
+        gl.disable(gl::DEPTH_TEST);
+        gl.enable(gl::BLEND);
+        // WebRender renders premultiplied alpha.
+        gl.blend_func_separate(gl::ONE, gl::ONE_MINUS_SRC_ALPHA, gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
+        gl.use_program(self.program);
+        gl.active_texture(gl::TEXTURE0);
+        gl.bind_texture(gl::TEXTURE_2D, ui.texture);
+        gl.bind_vertex_array(self.vertex_array);
+        gl.draw_arrays(gl::TRIANGLES, 0, 3);
+    }
+
+    fn deinit(self, gl: &dyn gl::Gl) {
+        gl.delete_vertex_arrays(&[self.vertex_array]);
+        gl.delete_program(self.program);
+    }
+}
+
+fn build_ui(pipeline_id: PipelineId, size: DeviceIntSize, frame: u32) -> (PipelineId, BuiltDisplayList) {
+    let mut builder = DisplayListBuilder::new(pipeline_id);
+    builder.begin();
+    let root = SpaceAndClipInfo::root_scroll(pipeline_id);
+
+    builder.push_simple_stacking_context(
+        LayoutPoint::zero(),
+        root.spatial_id,
+        PrimitiveFlags::IS_BACKFACE_VISIBLE,
+    );
+
+    // A translucent side panel, with a row of buttons.
+    let panel = LayoutRect::from_origin_and_size(
+        LayoutPoint::new(20.0, 20.0),
+        LayoutSize::new(240.0, size.height as f32 - 40.0),
+    );
+    let clip = builder.define_clip_rounded_rect(
+        root.spatial_id,
+        ComplexClipRegion::new(panel, BorderRadius::uniform(16.0), ClipMode::Clip),
+    );
+    let panel_space_and_clip = SpaceAndClipInfo {
+        spatial_id: root.spatial_id,
+        clip_chain_id: builder.define_clip_chain(None, [clip]),
+    };
+    builder.push_rect(
+        &CommonItemProperties::new(panel, panel_space_and_clip),
+        panel,
+        ColorF::new(0.9, 0.9, 1.0, 0.6),
+    );
+
+    for i in 0 .. 5 {
+        let button = LayoutRect::from_origin_and_size(
+            LayoutPoint::new(40.0, 40.0 + i as f32 * 70.0),
+            LayoutSize::new(200.0, 50.0),
+        );
+        // Animate the highlighted button to show that the UI updates along
+        // with the host's scene.
+        let highlighted = (frame / 60) % 5 == i;
+        let color = if highlighted {
+            ColorF::new(1.0, 0.6, 0.1, 1.0)
+        } else {
+            ColorF::new(0.3, 0.3, 0.5, 1.0)
+        };
+        builder.push_rect(
+            &CommonItemProperties::new(button, panel_space_and_clip),
+            button,
+            color,
+        );
+    }
+
+    builder.pop_stacking_context();
+    builder.end()
+}
+
+fn main() {
+    env_logger::init();
+
+    let mut event_loop = winit::event_loop::EventLoop::new();
+    let window = winit::window::WindowBuilder::new()
+        .with_title("GL embedding")
+        .with_inner_size(LogicalSize::new(800., 600.))
+        .build(&event_loop)
+        .unwrap();
+
+    let connection = surfman::Connection::from_winit_window(&window).unwrap();
+    let widget = connection.create_native_widget_from_winit_window(&window).unwrap();
+    let adapter = connection.create_adapter().unwrap();
+    let mut device = connection.create_device(&adapter).unwrap();
+    let (major, minor) = match device.gl_api() {
+        surfman::GLApi::GL => (3, 2),
+        surfman::GLApi::GLES => (3, 0),
+    };
+    let context_descriptor = device.create_context_descriptor(&surfman::ContextAttributes {
+        version: surfman::GLVersion {
+            major,
+            minor,
+        },
+        flags: surfman::ContextAttributeFlags::ALPHA |
+        surfman::ContextAttributeFlags::DEPTH |
+        surfman::ContextAttributeFlags::STENCIL,
+    }).unwrap();
+    let mut context = device.create_context(&context_descriptor, None).unwrap();
+    device.make_context_current(&context).unwrap();
+
+    let gl: Rc<dyn gl::Gl> = match device.gl_api() {
+        surfman::GLApi::GL => unsafe {
+            gl::GlFns::load_with(
+                |symbol| device.get_proc_address(&context, symbol) as *const _
+            )
+        },
+        surfman::GLApi::GLES => unsafe {
+            gl::GlesFns::load_with(
+                |symbol| device.get_proc_address(&context, symbol) as *const _
+            )
+        },
+    };
+    let gl = gl::ErrorCheckingGl::wrap(gl);
+
+    let surface = device.create_surface(
+        &context,
+        surfman::SurfaceAccess::GPUOnly,
+        surfman::SurfaceType::Widget { native_widget: widget },
+    ).unwrap();
+    device.bind_surface_to_context(&mut context, surface).unwrap();
+
+    let scene = Scene::new(&*gl);
+    let compositor = Compositor::new(&*gl);
+
+    // WebRender shares the host's context. Clearing to transparent lets the
+    // host's scene show through wherever there is no UI.
+    let opts = webrender::WebRenderOptions {
+        clear_color: ColorF::TRANSPARENT,
+        ..webrender::WebRenderOptions::default()
+    };
+    let (mut renderer, sender) = webrender::create_webrender_instance(
+        gl.clone(),
+        Box::new(Notifier),
+        opts,
+        None,
+    ).unwrap();
+    let mut api = sender.create_api();
+
+    let window_size = |window: &winit::window::Window| {
+        let size = window.inner_size();
+        DeviceIntSize::new(size.width as i32, size.height as i32)
+    };
+    let mut ui_target = UiTarget::new(&*gl, window_size(&window));
+    let document_id = api.add_document(ui_target.size);
+    let pipeline_id = PipelineId(0, 0);
+
+    let start = Instant::now();
+    let mut frame = 0;
+
+    event_loop.run_return(|event, _elwt, control_flow| {
+        *control_flow = winit::event_loop::ControlFlow::Poll;
+        match event {
+            winit::event::Event::WindowEvent {
+                event: winit::event::WindowEvent::CloseRequested,
+                ..
+            } => {
+                *control_flow = winit::event_loop::ControlFlow::Exit;
+                return;
+            }
+            winit::event::Event::MainEventsCleared => {}
+            _ => return,
+        }
+
+        let size = window_size(&window);
+        let mut txn = Transaction::new();
+        if size != ui_target.size {
+            let old_target = std::mem::replace(&mut ui_target, UiTarget::new(&*gl, size));
+            old_target.deinit(&*gl);
+            txn.set_document_view(DeviceIntRect::from_size(size));
+        }
+        txn.set_display_list(Epoch(frame), build_ui(pipeline_id, size, frame));
+        txn.set_root_pipeline(pipeline_id);
+        txn.generate_frame(0, RenderReasons::empty());
+        api.send_transaction(document_id, txn);
+
+        let window_framebuffer = device
+            .context_surface_info(&context)
+            .unwrap()
+            .unwrap()
+            .framebuffer_object;
+
+        // The host draws its scene as usual.
+        gl.bind_framebuffer(gl::FRAMEBUFFER, window_framebuffer);
+        gl.viewport(0, 0, size.width, size.height);
+        scene.draw(&*gl, start.elapsed().as_secs_f32());
+
+        // WebRender renders into whichever framebuffer is bound when `render`
+        // is called, and leaves the GL state modified.
+        let saved_state = SavedGlState::save(&*gl);
+        gl.bind_framebuffer(gl::FRAMEBUFFER, ui_target.framebuffer);
+        renderer.update();
+        renderer.render(size, 0).unwrap();
+        let _ = renderer.flush_pipeline_info();
+        saved_state.restore(&*gl);
+
+        compositor.draw(&*gl, &ui_target);
+
+        let mut surface = device.unbind_surface_from_context(&mut context).unwrap().unwrap();
+        device.present_surface(&context, &mut surface).unwrap();
+        device.bind_surface_to_context(&mut context, surface).unwrap();
+
+        frame += 1;
+    });
+
+    ui_target.deinit(&*gl);
+    compositor.deinit(&*gl);
+    scene.deinit(&*gl);
+    renderer.deinit();
+    device.destroy_context(&mut context).unwrap();
+}
@@ -6,6 +6,7 @@ use gleam::gl;
 use std::fs::File;
 use std::io::Read;
 use std::rc::Rc;
+use std::sync::Arc;
 use webrender::api::*;
 use webrender::api::units::*;
 use webrender::render_api::*;
@@ -45,36 +46,23 @@ impl RenderNotifier for Notifier {
     }
 }
 
-struct Window {
-    window: winit::window::Window,
-    context: surfman::Context,
+/// The GL context and WebRender instance shared by all the windows, so that
+/// shaders, caches and resources exist only once.
+struct Shared {
+    connection: surfman::Connection,
     device: surfman::Device,
+    context: surfman::Context,
     gl: Rc<dyn gl::Gl>,
     renderer: Option<webrender::Renderer>,
-    name: &'static str,
-    pipeline_id: PipelineId,
-    document_id: DocumentId,
-    epoch: Epoch,
     api: RenderApi,
-    font_instance_key: FontInstanceKey,
+    font: ShapingFontInstance,
+    /// Resources to send along with the first document's transaction.
+    resources: Option<Transaction>,
 }
 
-impl Drop for Window {
-    fn drop(&mut self) {
-        self.device.destroy_context(&mut self.context).unwrap();
-        self.renderer.take().unwrap().deinit();
-    }
-}
-
-impl Window {
-    fn new(event_loop: &winit::event_loop::EventLoop<()>, name: &'static str, clear_color: ColorF) -> Self {
-        let window_builder = winit::window::WindowBuilder::new()
-            .with_title(name)
-            .with_inner_size(LogicalSize::new(800., 600.));
-        let window = window_builder.build(event_loop).unwrap();
-
-        let connection = surfman::Connection::from_winit_window(&window).unwrap();
-        let widget = connection.create_native_widget_from_winit_window(&window).unwrap();
+impl Shared {
+    fn new(event_loop: &winit::event_loop::EventLoop<()>, window: &winit::window::Window) -> Self {
+        let connection = surfman::Connection::from_winit_window(window).unwrap();
         let adapter = connection.create_adapter().unwrap();
         let mut device = connection.create_device(&adapter).unwrap();
         let (major, minor) = match device.gl_api() {
@@ -90,7 +78,7 @@ impl Window {
             surfman::ContextAttributeFlags::DEPTH |
             surfman::ContextAttributeFlags::STENCIL,
         }).unwrap();
-        let mut context = device.create_context(&context_descriptor, None).unwrap();
+        let context = device.create_context(&context_descriptor, None).unwrap();
         device.make_context_current(&context).unwrap();
 
         let gl = match device.gl_api() {
@@ -107,54 +95,96 @@ impl Window {
         };
         let gl = gl::ErrorCheckingGl::wrap(gl);
 
-        let surface = device.create_surface(
-            &context,
-            surfman::SurfaceAccess::GPUOnly,
-            surfman::SurfaceType::Widget { native_widget: widget },
+        let notifier = Box::new(Notifier::new(event_loop.create_proxy()));
+        let (renderer, sender) = webrender::create_webrender_instance(
+            gl.clone(),
+            notifier,
+            webrender::WebRenderOptions::default(),
+            None,
         ).unwrap();
-        device.bind_surface_to_context(&mut context, surface).unwrap();
+        let api = sender.create_api();
 
-        let opts = webrender::WebRenderOptions {
-            clear_color,
-            ..webrender::WebRenderOptions::default()
+        // Resources aren't tied to a document, so all the windows use this font.
+        let mut resources = Transaction::new();
+        let font_key = api.generate_font_key();
+        let font_bytes = Arc::new(load_file("../wrench/reftests/text/FreeSans.ttf"));
+        resources.update_resources(vec![
+            ResourceUpdate::AddFont(AddFont::Raw(font_key, Arc::clone(&font_bytes), 0)),
+        ]);
+
+        let font_instance_key = api.generate_font_instance_key();
+        resources.add_font_instance(font_instance_key, font_key, 32.0, None, None, Vec::new());
+        // The display lists shape their text with the font's data.
+        let font = ShapingFontInstance {
+            key: font_instance_key,
+            size: 32.0,
+            font: Arc::new(ShapingFont::new(font_bytes, 0).unwrap()),
         };
 
+        Shared {
+            connection,
+            device,
+            context,
+            gl,
+            renderer: Some(renderer),
+            api,
+            font,
+            resources: Some(resources),
+        }
+    }
+
+    fn deinit(mut self) {
+        self.renderer.take().unwrap().deinit();
+        self.device.destroy_context(&mut self.context).unwrap();
+    }
+}
+
+/// A window presenting its own document of the shared renderer.
+struct Window {
+    window: winit::window::Window,
+    surface: Option<surfman::Surface>,
+    name: &'static str,
+    background: ColorF,
+    pipeline_id: PipelineId,
+    document_id: DocumentId,
+    epoch: Epoch,
+}
+
+impl Window {
+    fn new(
+        window: winit::window::Window,
+        name: &'static str,
+        background: ColorF,
+        pipeline_id: PipelineId,
+        shared: &mut Shared,
+    ) -> Self {
+        // Every window gets a surface of the shared context.
+        let widget = shared.connection.create_native_widget_from_winit_window(&window).unwrap();
+        let surface = shared.device.create_surface(
+            &shared.context,
+            surfman::SurfaceAccess::GPUOnly,
+            surfman::SurfaceType::Widget { native_widget: widget },
+        ).unwrap();
+
         let device_size = {
             let size = window
                 .inner_size();
 
             DeviceIntSize::new(size.width as i32, size.height as i32)
         };
-        let notifier = Box::new(Notifier::new(event_loop.create_proxy()));
-        let (renderer, sender) = webrender::create_webrender_instance(gl.clone(), notifier, opts, None).unwrap();
-        let mut api = sender.create_api();
-        let document_id = api.add_document(device_size);
-
-        let epoch = Epoch(0);
-        let pipeline_id = PipelineId(0, 0);
-        let mut txn = Transaction::new();
-
-        let font_key = api.generate_font_key();
-        let font_bytes = load_file("../wrench/reftests/text/FreeSans.ttf");
-        txn.add_raw_font(font_key, font_bytes, 0);
-
-        let font_instance_key = api.generate_font_instance_key();
-        txn.add_font_instance(font_instance_key, font_key, 32.0, None, None, Vec::new());
-
-        api.send_transaction(document_id, txn);
+        let document_id = shared.api.add_document(device_size);
+        if let Some(resources) = shared.resources.take() {
+            shared.api.send_transaction(document_id, resources);
+        }
 
         Window {
             window,
-            device,
-            context,
-            renderer: Some(renderer),
+            surface: Some(surface),
             name,
-            epoch,
+            background,
             pipeline_id,
             document_id,
-            api,
-            font_instance_key,
-            gl,
+            epoch: Epoch(0),
         }
     }
 
@@ -162,16 +192,19 @@ impl Window {
         self.window.id()
     }
 
-    fn set_flags(&mut self) {
+    fn set_flags(&self, shared: &mut Shared) {
         println!("set flags {}", &self.name);
-        self.api.send_debug_cmd(DebugCommand::SetFlags(DebugFlags::PROFILER_DBG));
+        shared.api.send_debug_cmd(DebugCommand::SetFlags(DebugFlags::PROFILER_DBG));
     }
 
-    fn redraw(&mut self) {
-        let renderer = self.renderer.as_mut().unwrap();
-        let api = &mut self.api;
+    fn redraw(&mut self, shared: &mut Shared) {
+        let renderer = shared.renderer.as_mut().unwrap();
+        let api = &mut shared.api;
 
-        self.device.make_context_current(&self.context).unwrap();
+        // Each window presents its document by binding its surface to the
+        // shared context before rendering.
+        shared.device.bind_surface_to_context(&mut shared.context, self.surface.take().unwrap()).unwrap();
+        shared.device.make_context_current(&shared.context).unwrap();
 
         let device_pixel_ratio = self.window.scale_factor() as f32;
         let device_size = {
@@ -193,6 +226,12 @@ impl Window {
             PrimitiveFlags::IS_BACKFACE_VISIBLE,
         );
 
+        builder.push_rect(
+            &CommonItemProperties::new(bounds, space_and_clip),
+            bounds,
+            self.background,
+        );
+
         builder.push_rect(
             &CommonItemProperties::new(
                 LayoutRect::from_origin_and_size(
@@ -211,65 +250,14 @@ impl Window {
             LayoutPoint::new(100.0, 50.0),
             LayoutSize::new(700.0, 200.0)
         );
-        let glyphs = vec![
-            GlyphInstance {
-                index: 48,
-                point: LayoutPoint::new(100.0, 100.0),
-            },
-            GlyphInstance {
-                index: 68,
-                point: LayoutPoint::new(150.0, 100.0),
-            },
-            GlyphInstance {
-                index: 80,
-                point: LayoutPoint::new(200.0, 100.0),
-            },
-            GlyphInstance {
-                index: 82,
-                point: LayoutPoint::new(250.0, 100.0),
-            },
-            GlyphInstance {
-                index: 81,
-                point: LayoutPoint::new(300.0, 100.0),
-            },
-            GlyphInstance {
-                index: 3,
-                point: LayoutPoint::new(350.0, 100.0),
-            },
-            GlyphInstance {
-                index: 86,
-                point: LayoutPoint::new(400.0, 100.0),
-            },
-            GlyphInstance {
-                index: 79,
-                point: LayoutPoint::new(450.0, 100.0),
-            },
-            GlyphInstance {
-                index: 72,
-                point: LayoutPoint::new(500.0, 100.0),
-            },
-            GlyphInstance {
-                index: 83,
-                point: LayoutPoint::new(550.0, 100.0),
-            },
-            GlyphInstance {
-                index: 87,
-                point: LayoutPoint::new(600.0, 100.0),
-            },
-            GlyphInstance {
-                index: 17,
-                point: LayoutPoint::new(650.0, 100.0),
-            },
-        ];
-
-        builder.push_text(
+        builder.push_text_str(
             &CommonItemProperties::new(
                 text_bounds,
                 space_and_clip,
             ),
-            text_bounds,
-            &glyphs,
-            self.font_instance_key,
+            "Mamon slept.",
+            &shared.font,
+            LayoutPoint::new(100.0, 100.0),
             ColorF::new(1.0, 1.0, 0.0, 1.0),
             None,
         );
@@ -284,34 +272,48 @@ impl Window {
         txn.generate_frame(0, RenderReasons::empty());
         api.send_transaction(self.document_id, txn);
 
-        let framebuffer_object = self
+        let framebuffer_object = shared
             .device
-            .context_surface_info(&self.context)
+            .context_surface_info(&shared.context)
             .unwrap()
             .unwrap()
             .framebuffer_object;
-        self.gl.bind_framebuffer(gl::FRAMEBUFFER, framebuffer_object);
-        assert_eq!(self.gl.check_frame_buffer_status(gleam::gl::FRAMEBUFFER), gl::FRAMEBUFFER_COMPLETE);
+        shared.gl.bind_framebuffer(gl::FRAMEBUFFER, framebuffer_object);
+        assert_eq!(shared.gl.check_frame_buffer_status(gleam::gl::FRAMEBUFFER), gl::FRAMEBUFFER_COMPLETE);
 
         renderer.update();
-        renderer.render(device_size, 0).unwrap();
+        renderer.render_document(self.document_id, device_size, 0).unwrap();
+
+        let mut surface = shared.device.unbind_surface_from_context(&mut shared.context).unwrap().unwrap();
+        shared.device.present_surface(&shared.context, &mut surface).unwrap();
+        self.surface = Some(surface);
+    }
 
-        let mut surface = self.device.unbind_surface_from_context(&mut self.context).unwrap().unwrap();
-        self.device.present_surface(&self.context, &mut surface).unwrap();
-        self.device.bind_surface_to_context(&mut self.context, surface).unwrap();
+    fn deinit(mut self, shared: &mut Shared) {
+        let mut surface = self.surface.take().unwrap();
+        shared.device.destroy_surface(&mut shared.context, &mut surface).unwrap();
     }
 }
 
+fn build_window(event_loop: &winit::event_loop::EventLoop<()>, name: &'static str) -> winit::window::Window {
+    winit::window::WindowBuilder::new()
+        .with_title(name)
+        .with_inner_size(LogicalSize::new(800., 600.))
+        .build(event_loop)
+        .unwrap()
+}
+
 fn main() {
     let mut event_loop = winit::event_loop::EventLoop::new();
     let mut windows = FastHashMap::default();
 
-    let win1 = Window::new(&event_loop, "window1", ColorF::new(0.3, 0.0, 0.0, 1.0));
-    let win1_id = win1.id();
-    windows.insert(win1_id, win1);
-    let win2 = Window::new(&event_loop, "window2", ColorF::new(0.0, 0.3, 0.0, 1.0));
-    let win2_id = win2.id();
-    windows.insert(win2_id, win2);
+    let window1 = build_window(&event_loop, "window1");
+    let mut shared = Shared::new(&event_loop, &window1);
+    let win1 = Window::new(window1, "window1", ColorF::new(0.3, 0.0, 0.0, 1.0), PipelineId(0, 0), &mut shared);
+    windows.insert(win1.id(), win1);
+    let window2 = build_window(&event_loop, "window2");
+    let win2 = Window::new(window2, "window2", ColorF::new(0.0, 0.3, 0.0, 1.0), PipelineId(1, 0), &mut shared);
+    windows.insert(win2.id(), win2);
 
     event_loop.run_return(|global_event, _elwt, control_flow| {
         *control_flow = winit::event_loop::ControlFlow::Wait;
@@ -335,18 +337,22 @@ fn main() {
                     },
                     ..
                 } => {
-                    let window: &mut Window = windows.get_mut(&window_id).unwrap();
-                    window.set_flags();
+                    windows[&window_id].set_flags(&mut shared);
                 }
                 _ => {}
             },
             winit::event::Event::RedrawRequested(window_id) => {
                 let window: &mut Window = windows.get_mut(&window_id).unwrap();
-                window.redraw();
+                window.redraw(&mut shared);
             }
             _ => {}
         }
     });
+
+    for (_, window) in windows {
+        window.deinit(&mut shared);
+    }
+    shared.deinit();
 }
 
 fn load_file(name: &str) -> Vec<u8> {
@@ -0,0 +1,179 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Shows how to go from a UTF-8 string to the positioned glyphs WebRender
+//! draws. WebRender only rasterizes glyphs, so text has to be shaped by the
+//! embedder: here swash maps characters to glyph indices and positions them
+//! (applying kerning and ligatures), and characters missing from the primary
+//! font are drawn with a fallback font.
+//!
+//! The text can be set with the `SHAPING_TEXT` environment variable.
+
+#[path = "common/boilerplate.rs"]
+mod boilerplate;
+
+use crate::boilerplate::Example;
+use std::env;
+use std::fs;
+use swash::FontRef;
+use swash::shape::ShapeContext;
+use webrender::api::*;
+use webrender::render_api::*;
+use webrender::api::units::*;
+
+const DEFAULT_TEXT: &str = "AVAST! Ta fi ffl — Ελληνικά, Кириллица";
+const FONT_SIZE: f32 = 48.0;
+
+/// A font, as seen by both the shaper and WebRender.
+struct Font {
+    data: Vec<u8>,
+    instance_key: FontInstanceKey,
+}
+
+impl Font {
+    fn new(path: &str, api: &mut RenderApi, txn: &mut Transaction) -> Self {
+        let data = fs::read(path).unwrap_or_else(|err| panic!("Unable to read {}: {}", path, err));
+
+        let font_key = api.generate_font_key();
+        txn.add_raw_font(font_key, data.clone(), 0);
+        let instance_key = api.generate_font_instance_key();
+        txn.add_font_instance(instance_key, font_key, FONT_SIZE, None, None, Vec::new());
+
+        Font { data, instance_key }
+    }
+
+    fn font_ref(&self) -> FontRef {
+        FontRef::from_index(&self.data, 0).expect("Unsupported font")
+    }
+
+    fn has_glyph(&self, c: char) -> bool {
+        c.is_whitespace() || self.font_ref().charmap().map(c) != 0
+    }
+}
+
+/// Glyphs to draw with a single font instance.
+struct GlyphRun {
+    instance_key: FontInstanceKey,
+    glyphs: Vec<GlyphInstance>,
+}
+
+/// Splits the text into runs of characters supported by the same font,
+/// preferring the first font of the list.
+fn itemize<'a>(text: &'a str, fonts: &[Font]) -> Vec<(usize, &'a str)> {
+    let mut runs: Vec<(usize, &str)> = Vec::new();
+    let mut run_start = 0;
+    let mut run_font: Option<usize> = None;
+
+    for (offset, c) in text.char_indices() {
+        // Keep characters supported by the current font in the current run,
+        // so that spaces don't split it.
+        let font_index = match run_font {
+            Some(index) if fonts[index].has_glyph(c) => index,
+            _ => fonts.iter().position(|font| font.has_glyph(c)).unwrap_or(0),
+        };
+        if run_font != Some(font_index) {
+            if let Some(index) = run_font {
+                runs.push((index, &text[run_start .. offset]));
+            }
+            run_start = offset;
+            run_font = Some(font_index);
+        }
+    }
+    if let Some(index) = run_font {
+        runs.push((index, &text[run_start ..]));
+    }
+
+    runs
+}
+
+/// Shapes the text on a single line starting at `origin`, the left end of its
+/// baseline.
+fn shape(text: &str, fonts: &[Font], origin: LayoutPoint) -> Vec<GlyphRun> {
+    let mut context = ShapeContext::new();
+    let mut pen = origin;
+
+    itemize(text, fonts).into_iter().map(|(font_index, run)| {
+        let font = &fonts[font_index];
+        let mut shaper = context
+            .builder(font.font_ref())
+            .size(FONT_SIZE)
+            .build();
+        shaper.add_str(run);
+
+        let mut glyphs = Vec::new();
+        shaper.shape_with(|cluster| {
+            for glyph in cluster.glyphs {
+                glyphs.push(GlyphInstance {
+                    index: glyph.id as GlyphIndex,
+                    // swash offsets are y-up, while layout space is y-down.
+                    point: LayoutPoint::new(pen.x + glyph.x, pen.y - glyph.y),
+                });
+                pen.x += glyph.advance;
+            }
+        });
+
+        GlyphRun {
+            instance_key: font.instance_key,
+            glyphs,
+        }
+    }).collect()
+}
+
+struct App {
+    text: String,
+    fonts: Vec<Font>,
+}
+
+impl Example for App {
+    const TITLE: &'static str = "Shaping";
+    const WIDTH: u32 = 1280;
+    const HEIGHT: u32 = 360;
+
+    fn render(
+        &mut self,
+        api: &mut RenderApi,
+        builder: &mut DisplayListBuilder,
+        txn: &mut Transaction,
+        _device_size: DeviceIntSize,
+        pipeline_id: PipelineId,
+        _document_id: DocumentId,
+    ) {
+        if self.fonts.is_empty() {
+            // Bitstream Vera has no Greek or Cyrillic glyphs, so those are
+            // drawn with FreeSans.
+            self.fonts.push(Font::new("../wrench/reftests/text/VeraBd.ttf", api, txn));
+            self.fonts.push(Font::new("../wrench/reftests/text/FreeSans.ttf", api, txn));
+        }
+
+        let bounds = LayoutRect::from_size(LayoutSize::new(Self::WIDTH as f32, Self::HEIGHT as f32));
+        let space_and_clip = SpaceAndClipInfo::root_scroll(pipeline_id);
+
+        builder.push_simple_stacking_context(
+            bounds.min,
+            space_and_clip.spatial_id,
+            PrimitiveFlags::IS_BACKFACE_VISIBLE,
+        );
+
+        for run in shape(&self.text, &self.fonts, LayoutPoint::new(40.0, 160.0)) {
+            builder.push_text(
+                &CommonItemProperties::new(bounds, space_and_clip),
+                bounds,
+                &run.glyphs,
+                run.instance_key,
+                ColorF::BLACK,
+                None,
+            );
+        }
+
+        builder.pop_stacking_context();
+    }
+}
+
+fn main() {
+    let mut app = App {
+        text: env::var("SHAPING_TEXT").unwrap_or_else(|_| DEFAULT_TEXT.to_string()),
+        fonts: Vec::new(),
+    };
+    boilerplate::main_wrapper(&mut app, None);
+}
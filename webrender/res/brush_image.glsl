@@ -11,7 +11,8 @@ varying highp vec2 v_uv;
 
 #ifdef WR_FEATURE_ALPHA_PASS
 flat varying mediump vec4 v_color;
-flat varying mediump vec2 v_mask_swizzle;
+// The z component is set if the red channel of the texture is used as alpha.
+flat varying mediump vec3 v_mask_swizzle;
 flat varying mediump vec2 v_tile_repeat_bounds;
 #endif
 
@@ -285,37 +286,41 @@ void brush_vs(
         case COLOR_MODE_BITMAP_SHADOW:
             #ifdef SWGL_BLEND
                 swgl_blendDropShadow(image_data.color);
-                v_mask_swizzle = vec2(1.0, 0.0);
+                v_mask_swizzle = vec3(1.0, 0.0, 0.0);
                 v_color = vec4(1.0);
             #else
-                v_mask_swizzle = vec2(0.0, 1.0);
+                v_mask_swizzle = vec3(0.0, 1.0, 0.0);
                 v_color = image_data.color;
             #endif
             break;
         case COLOR_MODE_SUBPX_BG_PASS2:
         case COLOR_MODE_IMAGE:
-            v_mask_swizzle = vec2(1.0, 0.0);
+            v_mask_swizzle = vec3(1.0, 0.0, 0.0);
             v_color = image_data.color;
             break;
         case COLOR_MODE_SUBPX_BG_PASS0:
         case COLOR_MODE_COLOR_BITMAP:
-            v_mask_swizzle = vec2(1.0, 0.0);
+            v_mask_swizzle = vec3(1.0, 0.0, 0.0);
             v_color = vec4(image_data.color.a);
             break;
         case COLOR_MODE_SUBPX_BG_PASS1:
-            v_mask_swizzle = vec2(-1.0, 1.0);
+            v_mask_swizzle = vec3(-1.0, 1.0, 0.0);
             v_color = vec4(image_data.color.a) * image_data.background_color;
             break;
         case COLOR_MODE_SUBPX_DUAL_SOURCE:
-            v_mask_swizzle = vec2(image_data.color.a, 0.0);
+            v_mask_swizzle = vec3(image_data.color.a, 0.0, 0.0);
             v_color = image_data.color;
             break;
         case COLOR_MODE_MULTIPLY_DUAL_SOURCE:
-            v_mask_swizzle = vec2(-image_data.color.a, image_data.color.a);
+            v_mask_swizzle = vec3(-image_data.color.a, image_data.color.a, 0.0);
+            v_color = image_data.color;
+            break;
+        case COLOR_MODE_ALPHA_MASK:
+            v_mask_swizzle = vec3(0.0, 1.0, 1.0);
             v_color = image_data.color;
             break;
         default:
-            v_mask_swizzle = vec2(0.0);
+            v_mask_swizzle = vec3(0.0);
             v_color = vec4(1.0);
     }
 #endif
@@ -374,6 +379,7 @@ Fragment brush_fs() {
     #else
         float alpha = 1.0;
     #endif
+    texel = mix(texel, texel.rrrr, bvec4(v_mask_swizzle.z != 0.0));
     #ifndef WR_FEATURE_DUAL_SOURCE_BLENDING
         texel.rgb = texel.rgb * v_mask_swizzle.x + texel.aaa * v_mask_swizzle.y;
     #endif
@@ -398,7 +404,7 @@ void swgl_drawSpanRGBA8() {
     }
 
     #ifdef WR_FEATURE_ALPHA_PASS
-        if (v_mask_swizzle != vec2(1.0, 0.0)) {
+        if (v_mask_swizzle.xy != vec2(1.0, 0.0) || v_mask_swizzle.z != 0.0) {
             return;
         }
     #endif
@@ -41,6 +41,13 @@ uniform mediump vec2 uTextureSize;
 #endif
 #endif
 
+// Optional color filter applied to the composited color (see
+// AccessibilityColorFilter). The offset is scaled by alpha since the color
+// is premultiplied.
+uniform int uColorFilter;
+uniform mediump mat4 uColorMatrix;
+uniform mediump vec4 uColorOffset;
+
 #ifdef WR_VERTEX_SHADER
 // CPU side data is in CompositeInstance (gpu_types.rs) and is
 // converted to GPU data using desc::COMPOSITE (renderer.rs) by
@@ -193,11 +200,19 @@ void main(void) {
     vec4 color = vColor * texel;
 #endif
 #endif
+    if (uColorFilter != 0) {
+        color = uColorMatrix * color + uColorOffset * color.a;
+        color.rgb = clamp(color.rgb, vec3(0.0), vec3(color.a));
+    }
     write_output(color);
 }
 
 #ifdef SWGL_DRAW_SPAN
 void swgl_drawSpanRGBA8() {
+    // Filtered colors are left to the fragment shader.
+    if (uColorFilter != 0) {
+        return;
+    }
 #ifdef WR_FEATURE_YUV
     if (vYuvFormat.x == YUV_FORMAT_PLANAR) {
         swgl_commitTextureLinearYUV(sColor0, vUV_y, vUVBounds_y,
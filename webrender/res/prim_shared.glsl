@@ -35,6 +35,8 @@ varying highp vec2 vClipMaskUv;
 #define COLOR_MODE_COLOR_BITMAP         7
 #define COLOR_MODE_IMAGE                8
 #define COLOR_MODE_MULTIPLY_DUAL_SOURCE 9
+#define COLOR_MODE_ALPHA_MASK           10
+#define COLOR_MODE_SDF                  11
 
 uniform HIGHP_SAMPLER_FLOAT sampler2D sPrimitiveHeadersF;
 uniform HIGHP_SAMPLER_FLOAT isampler2D sPrimitiveHeadersI;
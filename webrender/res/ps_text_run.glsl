@@ -12,6 +12,9 @@ flat varying highp vec4 v_uv_bounds;
 // Interpolated UV coordinates to sample.
 varying highp vec2 v_uv;
 
+// Percentage by which glyph edges are darkened, see IntParameter::TextContrast.
+uniform int uTextContrast;
+
 
 #if defined(WR_FEATURE_GLYPH_TRANSFORM) && !defined(SWGL_CLIP_DIST)
 varying highp vec4 v_uv_clip;
@@ -194,6 +197,11 @@ void main() {
     // - The transform's scale will result in result in very large rasterized glyphs and
     //   we clamped the size. This will imply local raster space.
     vec2 raster_glyph_offset = floor(glyph.offset * glyph_raster_scale + snap_bias) / res.scale;
+    // Distance field glyphs are rasterized at a fixed size rather than in device
+    // pixels, and are drawn without snapping to that size's pixels.
+    if (color_mode == COLOR_MODE_SDF) {
+        raster_glyph_offset = glyph.offset * glyph_raster_scale / res.scale;
+    }
 
     // Compute the glyph rect in local space.
     //
@@ -239,6 +247,10 @@ void main() {
             v_mask_swizzle = vec3(0.0, 1.0, 1.0);
             v_color = text.color;
             break;
+        case COLOR_MODE_SDF:
+            v_mask_swizzle = vec3(0.0, 1.0, 2.0);
+            v_color = text.color;
+            break;
         case COLOR_MODE_BITMAP_SHADOW:
             #ifdef SWGL_BLEND
                 swgl_blendDropShadow(text.color);
@@ -297,6 +309,14 @@ Fragment text_fs(void) {
     // v_mask_swizzle.z != 0 means we are using an R8 texture as alpha,
     // and therefore must swizzle from the r channel to all channels.
     mask = mix(mask, mask.rrrr, bvec4(v_mask_swizzle.z != 0.0));
+    // v_mask_swizzle.z > 1 means the mask is a signed distance field, with the
+    // glyph's edge at 0.5. Coverage is reconstructed over the width of a pixel.
+    if (v_mask_swizzle.z > 1.0) {
+        float sdf_width = max(fwidth(vec2(mask.r)).x, 1.0e-4);
+        mask = vec4(distance_aa(1.0 / sdf_width, 0.5 - mask.r));
+    }
+    // Same contrast curve as the glyph rasterizer's gamma tables.
+    mask += (vec4(1.0) - mask) * mask * (float(uTextContrast) / 100.0);
     #ifndef WR_FEATURE_DUAL_SOURCE_BLENDING
         mask.rgb = mask.rgb * v_mask_swizzle.x + mask.aaa * v_mask_swizzle.y;
     #endif
@@ -338,6 +358,14 @@ void swgl_drawSpanRGBA8() {
     if (v_mask_swizzle.x != 0.0 && v_mask_swizzle.x != 1.0) {
         return;
     }
+    // Distance fields are left to the fragment shader.
+    if (v_mask_swizzle.z > 1.0) {
+        return;
+    }
+    // Contrast adjustments are left to the fragment shader.
+    if (uTextContrast != 0) {
+        return;
+    }
 
     #ifdef WR_FEATURE_DUAL_SOURCE_BLENDING
         swgl_commitTextureLinearRGBA8(sColor0, v_uv, v_uv_bounds);
@@ -4,10 +4,10 @@
 
 use crate::api::{BlobImageKey, ImageDescriptor, DirtyRect, TileSize, DebugFlags};
 use crate::api::{BlobImageHandler, AsyncBlobImageRasterizer, BlobImageData, BlobImageParams};
-use crate::api::{BlobImageRequest, BlobImageDescriptor, FontTemplate};
+use crate::api::{BlobImageRequest, BlobImageDescriptor, FontTemplate, FontInstanceKey};
 use crate::api::units::*;
 use glyph_rasterizer::{SharedFontResources, BaseFontInstance};
-use crate::render_api::{ResourceUpdate, TransactionMsg, AddFont};
+use crate::render_api::{ResourceUpdate, TransactionMsg, AddFont, FontInstanceInfo};
 use crate::image_tiling::*;
 use crate::profiler;
 
@@ -58,6 +58,24 @@ impl ApiResources {
         self.fonts.clone()
     }
 
+    pub fn get_font_instance_info(&self, key: FontInstanceKey) -> Option<FontInstanceInfo> {
+        let shared_key = self.fonts.instance_keys.map_key(&key);
+        let instance = self.fonts.instances.get_font_instance(shared_key)?;
+        // The instance refers to the shared font key, find the one the
+        // instance's namespace knows it by.
+        let font_key = self.fonts.font_keys
+            .find_key(key.0, &instance.font_key)
+            .unwrap_or(instance.font_key);
+
+        Some(FontInstanceInfo {
+            font_key,
+            size: instance.size.into(),
+            options: instance.options,
+            platform_options: instance.platform_options,
+            variations: instance.variations.clone(),
+        })
+    }
+
     pub fn set_debug_flags(&mut self, flags: DebugFlags) {
         self.debug_restrict_blob_size = flags.contains(DebugFlags::RESTRICT_BLOB_SIZE);
     }
@@ -120,16 +138,31 @@ impl ApiResources {
                     self.update_blob_image(*key, None, None, None, &area);
                     blobs_to_rasterize.push(*key);
                 }
-                ResourceUpdate::AddFont(ref font) => {
-                    let (key, template) = match font {
-                        AddFont::Raw(key, bytes, index) => {
-                            (key, FontTemplate::Raw(Arc::clone(bytes), *index))
+                ResourceUpdate::AddFont(ref mut font) => {
+                    let (key, mut template) = match *font {
+                        AddFont::Raw(key, ref bytes, index) |
+                        AddFont::Parsed(key, ref bytes, index, _) => {
+                            (key, FontTemplate::Raw(Arc::clone(bytes), index))
                         }
-                        AddFont::Native(key, native_font_handle) => {
+                        AddFont::File(key, ref path, index) => {
+                            (key, FontTemplate::File { path: path.clone(), index })
+                        }
+                        AddFont::Native(key, ref native_font_handle) => {
                             (key, FontTemplate::Native(native_font_handle.clone()))
                         }
                     };
-                    if let Some(shared_key) = self.fonts.font_keys.add_key(key, &template) {
+                    if let Some(shared_key) = self.fonts.font_keys.add_key(&key, &mut template) {
+                        // The template may now share its data with another font,
+                        // forward that copy to the resource cache as well.
+                        if let FontTemplate::Raw(ref data, _) = template {
+                            match *font {
+                                AddFont::Raw(_, ref mut bytes, _) |
+                                AddFont::Parsed(_, ref mut bytes, _, _) => {
+                                    *bytes = Arc::clone(data);
+                                }
+                                AddFont::File(..) | AddFont::Native(..) => {}
+                            }
+                        }
                         self.fonts.templates.add_font(shared_key, template);
                     }
                 }
@@ -145,6 +178,7 @@ impl ApiResources {
                         mem::take(&mut instance.options),
                         mem::take(&mut instance.platform_options),
                         mem::take(&mut instance.variations),
+                        mem::take(&mut instance.palette_overrides),
                     );
                     if let Some(shared_instance) = self.fonts.instance_keys.add_key(base) {
                         self.fonts.instances.add_font_instance(shared_instance);
@@ -21,7 +21,7 @@ use crate::picture::{Picture3DContext, PictureCompositeMode, calculate_screen_uv
 use crate::prim_store::{PrimitiveInstanceKind, ClipData};
 use crate::prim_store::{PrimitiveInstance, PrimitiveOpacity, SegmentInstanceIndex};
 use crate::prim_store::{BrushSegment, ClipMaskKind, ClipTaskIndex};
-use crate::prim_store::{VECS_PER_SEGMENT, PrimitiveInstanceIndex};
+use crate::prim_store::{VECS_PER_SEGMENT, PrimitiveInstanceIndex, LineSkipInkIndex};
 use crate::render_target::RenderTargetContext;
 use crate::render_task_graph::{RenderTaskId, RenderTaskGraph};
 use crate::render_task::{RenderTaskAddress, RenderTaskKind, SubPass};
@@ -285,7 +285,7 @@ impl BatchRects {
     }
 
     #[inline]
-    fn intersects(&mut self, rect: &PictureRect) -> bool {
+    fn intersects(&self, rect: &PictureRect) -> bool {
         if !self.batch.intersects(rect) {
             return false;
         }
@@ -298,6 +298,17 @@ impl BatchRects {
             true
         }
     }
+
+    fn intersects_batch(&self, other: &BatchRects) -> bool {
+        if !self.batch.intersects(&other.batch) {
+            return false;
+        }
+
+        match other.items {
+            Some(ref items) => items.iter().any(|item| self.intersects(item)),
+            None => self.intersects(&other.batch),
+        }
+    }
 }
 
 
@@ -595,24 +606,67 @@ impl AlphaBatchContainer {
             }
         }
 
-        let mut min_batch_index = 0;
+        // The tasks merged into this container are allocated disjoint rects
+        // of the target, so the batches of different tasks never overlap. A
+        // batch only has to be drawn after the batches of its own task that it
+        // overlaps, which lets it join a compatible batch of another task even
+        // if it was broken from its neighbours by a stacking context or a
+        // surface boundary.
+        //
+        // Like the batch lists, only the last `lookback_count` batches are
+        // considered. Batches of the task that are further back are assumed
+        // to overlap, and container batches that are further back are never
+        // merged into.
+        let lookback_count = builder.lookback_count;
+        let batch_rects = builder.alpha_batch_list.batch_rects;
+        let mut merged_indices: Vec<usize> = Vec::with_capacity(batch_rects.len());
+        // The highest container index of the batches of this task that have
+        // slid out of the lookback window.
+        let mut skipped_index = None;
+        let mut max_merged_index: Option<usize> = None;
+
+        for (other_index, other_batch) in builder.alpha_batch_list.batches.into_iter().enumerate() {
+            let first_index = other_index.saturating_sub(lookback_count);
+            if first_index > 0 {
+                skipped_index = skipped_index.max(Some(merged_indices[first_index - 1]));
+            }
 
-        for other_batch in builder.alpha_batch_list.batches {
-            let batch_index = self.alpha_batches.iter().skip(min_batch_index).position(|batch| {
+            let min_batch_index = match other_batch.key.blend_mode {
+                // Advanced blend batches may have been broken on purpose (see
+                // `break_advanced_blend_batches`), so keep them after all the
+                // batches of their task.
+                BlendMode::Advanced(_) => {
+                    max_merged_index.map_or(0, |index| index + 1)
+                }
+                _ => {
+                    (first_index .. other_index)
+                        .filter(|&index| batch_rects[index].intersects_batch(&batch_rects[other_index]))
+                        .map(|index| merged_indices[index])
+                        .max()
+                        .max(skipped_index)
+                        .unwrap_or(0)
+                }
+            };
+
+            let search_start = min_batch_index.max(self.alpha_batches.len().saturating_sub(lookback_count));
+            let batch_index = self.alpha_batches[search_start ..].iter().position(|batch| {
                 batch.key.is_compatible_with(&other_batch.key)
             });
 
-            match batch_index {
+            let index = match batch_index {
                 Some(batch_index) => {
-                    let index = batch_index + min_batch_index;
+                    let index = batch_index + search_start;
                     self.alpha_batches[index].merge(other_batch);
-                    min_batch_index = index;
+                    index
                 }
                 None => {
                     self.alpha_batches.push(other_batch);
-                    min_batch_index = self.alpha_batches.len();
+                    self.alpha_batches.len() - 1
                 }
-            }
+            };
+
+            max_merged_index = max_merged_index.max(Some(index));
+            merged_indices.push(index);
         }
     }
 }
@@ -631,6 +685,7 @@ pub struct AlphaBatchBuilder {
     pub opaque_batch_list: OpaqueBatchList,
     pub render_task_id: RenderTaskId,
     render_task_address: RenderTaskAddress,
+    lookback_count: usize,
 }
 
 impl AlphaBatchBuilder {
@@ -650,6 +705,7 @@ impl AlphaBatchBuilder {
             opaque_batch_list: OpaqueBatchList::new(batch_area_threshold, lookback_count),
             render_task_id,
             render_task_address,
+            lookback_count,
         }
     }
 
@@ -923,6 +979,7 @@ impl BatchBuilder {
         };
 
         let prim_instance = &prim_instances[prim_instance_index.0 as usize];
+        gpu_cache.set_validation_scope(Some(prim_instance.kind.validation_scope()));
         let is_anti_aliased = ctx.data_stores.prim_has_anti_aliasing(prim_instance);
 
         let brush_flags = if is_anti_aliased {
@@ -1213,6 +1270,12 @@ impl BatchBuilder {
                                     ShaderColorMode::Alpha,
                                 )
                             }
+                            GlyphFormat::Sdf => {
+                                (
+                                    BlendMode::PremultipliedAlpha,
+                                    ShaderColorMode::Sdf,
+                                )
+                            }
                             GlyphFormat::ColorBitmap => {
                                 (
                                     BlendMode::PremultipliedAlpha,
@@ -1301,7 +1364,13 @@ impl BatchBuilder {
                                 for glyph in glyphs {
                                     let glyph_offset = prim_data.glyphs[glyph.index_in_text_run as usize].point + prim_header.local_rect.min.to_vector();
                                     let glyph_scale = LayoutToDeviceScale::new(glyph_raster_scale / glyph.scale);
-                                    let raster_glyph_offset = (glyph_offset * LayoutToDeviceScale::new(glyph_raster_scale) + snap_bias).floor() / glyph.scale;
+                                    let raster_glyph_offset = glyph_offset * LayoutToDeviceScale::new(glyph_raster_scale);
+                                    // Distance field glyphs aren't snapped, see the ps_text_run shader.
+                                    let raster_glyph_offset = if glyph_format == GlyphFormat::Sdf {
+                                        raster_glyph_offset / glyph.scale
+                                    } else {
+                                        (raster_glyph_offset + snap_bias).floor() / glyph.scale
+                                    };
                                     let local_glyph_rect = LayoutRect::from_origin_and_size(
                                         (glyph.offset + raster_glyph_offset.to_vector()) / glyph_scale + text_offset,
                                         glyph.size.to_f32() / glyph_scale,
@@ -1355,7 +1424,7 @@ impl BatchBuilder {
                     },
                 );
             }
-            PrimitiveInstanceKind::LineDecoration { data_handle, ref render_task, .. } => {
+            PrimitiveInstanceKind::LineDecoration { data_handle, ref render_task, skip_ink } => {
                 // The GPU cache data is stored in the template and reused across
                 // frames and display lists.
                 let common_data = &ctx.data_stores.line_decoration[data_handle].common;
@@ -1408,37 +1477,54 @@ impl BatchBuilder {
                     BlendMode::None
                 };
 
-                let prim_header = PrimitiveHeader {
-                    local_rect: prim_rect,
-                    local_clip_rect: prim_info.clip_chain.local_clip_rect,
-                    specific_prim_address: prim_cache_address,
-                    transform_id,
-                };
-
-                let prim_header_index = prim_headers.push(
-                    &prim_header,
-                    z_id,
-                    prim_user_data,
-                );
-
                 let batch_key = BatchKey {
                     blend_mode,
                     kind: BatchKind::Brush(batch_kind),
                     textures,
                 };
 
-                self.add_brush_instance_to_batches(
-                    batch_key,
-                    batch_features,
-                    bounding_rect,
-                    z_id,
-                    INVALID_SEGMENT_INDEX,
-                    common_data.edge_aa_mask,
-                    clip_task_address,
-                    brush_flags | BrushFlags::PERSPECTIVE_INTERPOLATION,
-                    prim_header_index,
-                    specific_resource_address,
-                );
+                // A line that skips the ink of some text is drawn as one
+                // instance per visible part.
+                let visible_parts;
+                let local_rects = if skip_ink == LineSkipInkIndex::INVALID {
+                    std::slice::from_ref(&prim_rect)
+                } else {
+                    let ink_segments = ctx.prim_store.line_skip_inks[skip_ink].ink_segments;
+                    let range = ctx.scratch.line_segment_ranges[ink_segments];
+                    visible_parts = ctx.scratch.line_segments[range]
+                        .iter()
+                        .map(|segment| segment.translate(prim_rect.min.to_vector()))
+                        .collect::<Vec<_>>();
+                    &visible_parts[..]
+                };
+
+                for local_rect in local_rects {
+                    let prim_header = PrimitiveHeader {
+                        local_rect: *local_rect,
+                        local_clip_rect: prim_info.clip_chain.local_clip_rect,
+                        specific_prim_address: prim_cache_address,
+                        transform_id,
+                    };
+
+                    let prim_header_index = prim_headers.push(
+                        &prim_header,
+                        z_id,
+                        prim_user_data,
+                    );
+
+                    self.add_brush_instance_to_batches(
+                        batch_key,
+                        batch_features,
+                        bounding_rect,
+                        z_id,
+                        INVALID_SEGMENT_INDEX,
+                        common_data.edge_aa_mask,
+                        clip_task_address,
+                        brush_flags | BrushFlags::PERSPECTIVE_INTERPOLATION,
+                        prim_header_index,
+                        specific_resource_address,
+                    );
+                }
             }
             PrimitiveInstanceKind::Picture { pic_index, .. } => {
                 let picture = &ctx.prim_store.pictures[pic_index.0];
@@ -2427,7 +2513,11 @@ impl BatchBuilder {
                 let common_data = &ctx.data_stores.image[data_handle].common;
                 let image_instance = &ctx.prim_store.images[image_instance_index];
                 let prim_user_data = ImageBrushData {
-                    color_mode: ShaderColorMode::Image,
+                    color_mode: if image_data.is_alpha_mask {
+                        ShaderColorMode::AlphaMask
+                    } else {
+                        ShaderColorMode::Image
+                    },
                     alpha_type: image_data.alpha_type,
                     raster_space: RasterizationSpace::Local,
                     opacity: 1.0,
@@ -3023,6 +3113,126 @@ impl BatchBuilder {
                 }
             }
             PrimitiveInstanceKind::BackdropCapture { .. } => {}
+            PrimitiveInstanceKind::EmbedderDraw { .. } => {
+                // Drawn by the embedder when compositing, if the primitive
+                // was promoted to a compositor surface.
+            }
+            PrimitiveInstanceKind::Path { data_handle, render_task } => {
+                let task_id = match render_task {
+                    Some(task_id) => task_id,
+                    None => return,
+                };
+                let common_data = &ctx.data_stores.prim[data_handle].common;
+                let prim_cache_address = gpu_cache.get_address(&common_data.gpu_cache_handle);
+
+                let (clip_task_address, clip_mask_texture_id) = ctx.get_prim_clip_task_and_texture(
+                    prim_info.clip_task_index,
+                    render_tasks,
+                ).unwrap();
+
+                let (uv_rect_address, texture) = render_tasks.resolve_location(task_id, gpu_cache).unwrap();
+                let textures = BatchTextures::prim_textured(
+                    texture,
+                    clip_mask_texture_id,
+                );
+                let prim_user_data = ImageBrushData {
+                    color_mode: ShaderColorMode::Image,
+                    alpha_type: AlphaType::PremultipliedAlpha,
+                    raster_space: RasterizationSpace::Local,
+                    opacity: 1.0,
+                }.encode();
+
+                let batch_key = BatchKey {
+                    blend_mode: BlendMode::PremultipliedAlpha,
+                    kind: BatchKind::Brush(BrushBatchKind::Image(texture.image_buffer_kind())),
+                    textures,
+                };
+
+                let prim_header = PrimitiveHeader {
+                    local_rect: prim_rect,
+                    local_clip_rect: prim_info.clip_chain.local_clip_rect,
+                    specific_prim_address: prim_cache_address,
+                    transform_id,
+                };
+
+                let prim_header_index = prim_headers.push(
+                    &prim_header,
+                    z_id,
+                    prim_user_data,
+                );
+
+                self.add_brush_instance_to_batches(
+                    batch_key,
+                    batch_features,
+                    bounding_rect,
+                    z_id,
+                    INVALID_SEGMENT_INDEX,
+                    common_data.edge_aa_mask,
+                    clip_task_address,
+                    brush_flags | BrushFlags::PERSPECTIVE_INTERPOLATION,
+                    prim_header_index,
+                    uv_rect_address.as_int(),
+                );
+            }
+            PrimitiveInstanceKind::VectorPath { data_handle, ref texture_cache_handle } => {
+                let prim_data = &ctx.data_stores.vector_path[data_handle];
+                let common_data = &prim_data.common;
+                let prim_cache_address = gpu_cache.get_address(&common_data.gpu_cache_handle);
+
+                let (clip_task_address, clip_mask_texture_id) = ctx.get_prim_clip_task_and_texture(
+                    prim_info.clip_task_index,
+                    render_tasks,
+                ).unwrap();
+
+                let cache_item = ctx.resource_cache.get_texture_cache_item(texture_cache_handle);
+                let uv_rect_address = gpu_cache.get_address(&cache_item.uv_rect_handle);
+                let textures = BatchTextures::prim_textured(
+                    cache_item.texture_id,
+                    clip_mask_texture_id,
+                );
+                // Solid paths are coverage masks filled with the path color.
+                let prim_user_data = ImageBrushData {
+                    color_mode: match prim_data.kind.shape.gradient {
+                        None => ShaderColorMode::AlphaMask,
+                        Some(..) => ShaderColorMode::Image,
+                    },
+                    alpha_type: AlphaType::PremultipliedAlpha,
+                    raster_space: RasterizationSpace::Local,
+                    opacity: 1.0,
+                }.encode();
+
+                let batch_key = BatchKey {
+                    blend_mode: BlendMode::PremultipliedAlpha,
+                    kind: BatchKind::Brush(BrushBatchKind::Image(cache_item.texture_id.image_buffer_kind())),
+                    textures,
+                };
+
+                let prim_header = PrimitiveHeader {
+                    local_rect: prim_rect,
+                    local_clip_rect: prim_info.clip_chain.local_clip_rect,
+                    specific_prim_address: prim_cache_address,
+                    transform_id,
+                };
+
+                let prim_header_index = prim_headers.push(
+                    &prim_header,
+                    z_id,
+                    prim_user_data,
+                );
+
+                self.add_brush_instance_to_batches(
+                    batch_key,
+                    batch_features,
+                    bounding_rect,
+                    z_id,
+                    INVALID_SEGMENT_INDEX,
+                    common_data.edge_aa_mask,
+                    clip_task_address,
+                    brush_flags | BrushFlags::PERSPECTIVE_INTERPOLATION,
+                    prim_header_index,
+                    uv_rect_address.as_int(),
+                );
+            }
             PrimitiveInstanceKind::BackdropRender { pic_index, .. } => {
                 let prim_cache_address = gpu_cache.get_address(&ctx.globals.default_image_handle);
                 let blend_mode = BlendMode::PremultipliedAlpha;
@@ -3904,3 +4114,103 @@ pub fn add_quad_to_batch<F>(
         f(edge_batch_key, instance.into());
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOLID: BatchKind = BatchKind::Brush(BrushBatchKind::Solid);
+    const PRIMITIVE: BatchKind = BatchKind::Primitive;
+
+    fn rect(x0: f32, y0: f32, x1: f32, y1: f32) -> PictureRect {
+        PictureRect::new(PicturePoint::new(x0, y0), PicturePoint::new(x1, y1))
+    }
+
+    fn builder(lookback_count: usize) -> AlphaBatchBuilder {
+        AlphaBatchBuilder::new(
+            DeviceIntSize::new(1000, 1000),
+            false,
+            lookback_count,
+            RenderTaskId::INVALID,
+            RenderTaskAddress(0),
+        )
+    }
+
+    fn push_batch(builder: &mut AlphaBatchBuilder, kind: BatchKind, rect: PictureRect) {
+        let key = BatchKey::new(kind, BlendMode::PremultipliedAlpha, BatchTextures::empty());
+        let mut batch_rects = BatchRects::new();
+        batch_rects.add_rect(&rect);
+        builder.alpha_batch_list.batches.push(PrimitiveBatch::new(key));
+        builder.alpha_batch_list.batch_rects.push(batch_rects);
+    }
+
+    fn batch_kinds(container: &AlphaBatchContainer) -> Vec<BatchKind> {
+        container.alpha_batches.iter().map(|batch| batch.key.kind).collect()
+    }
+
+    fn container(lookback_count: usize) -> AlphaBatchContainer {
+        let mut task = builder(lookback_count);
+        push_batch(&mut task, SOLID, rect(0.0, 0.0, 10.0, 10.0));
+        push_batch(&mut task, PRIMITIVE, rect(20.0, 0.0, 30.0, 10.0));
+
+        let mut container = AlphaBatchContainer::new(None);
+        container.merge(task, &DeviceIntRect::zero());
+        container
+    }
+
+    #[test]
+    fn test_merge_non_overlapping_batches() {
+        let mut container = container(10);
+
+        let mut task = builder(10);
+        push_batch(&mut task, PRIMITIVE, rect(0.0, 0.0, 10.0, 10.0));
+        push_batch(&mut task, SOLID, rect(20.0, 0.0, 30.0, 10.0));
+        container.merge(task, &DeviceIntRect::zero());
+
+        // Both batches of the second task join a compatible batch.
+        assert_eq!(batch_kinds(&container), vec![SOLID, PRIMITIVE]);
+    }
+
+    #[test]
+    fn test_merge_keeps_order_of_overlapping_batches() {
+        let mut container = container(10);
+
+        let mut task = builder(10);
+        push_batch(&mut task, PRIMITIVE, rect(0.0, 0.0, 10.0, 10.0));
+        push_batch(&mut task, SOLID, rect(5.0, 5.0, 15.0, 15.0));
+        container.merge(task, &DeviceIntRect::zero());
+
+        // The solid batch overlaps the primitive batch it has to be drawn
+        // after, so it can't join the solid batch that comes before it.
+        assert_eq!(batch_kinds(&container), vec![SOLID, PRIMITIVE, SOLID]);
+    }
+
+    #[test]
+    fn test_merge_lookback() {
+        let mut container = container(1);
+
+        let mut task = builder(1);
+        push_batch(&mut task, SOLID, rect(0.0, 0.0, 10.0, 10.0));
+        container.merge(task, &DeviceIntRect::zero());
+
+        // The compatible batch is out of the lookback window.
+        assert_eq!(batch_kinds(&container), vec![SOLID, PRIMITIVE, SOLID]);
+    }
+
+    #[test]
+    fn test_merge_keeps_order_of_batches_out_of_lookback() {
+        let mut container = container(2);
+
+        let mut task = builder(2);
+        push_batch(&mut task, PRIMITIVE, rect(0.0, 0.0, 10.0, 10.0));
+        push_batch(&mut task, PRIMITIVE, rect(20.0, 0.0, 30.0, 10.0));
+        push_batch(&mut task, PRIMITIVE, rect(40.0, 0.0, 50.0, 10.0));
+        push_batch(&mut task, SOLID, rect(5.0, 5.0, 15.0, 15.0));
+        container.merge(task, &DeviceIntRect::zero());
+
+        // The solid batch overlaps the first primitive batch of its task,
+        // which is out of the lookback window by the time the solid batch is
+        // merged. It still has to stay after it.
+        assert_eq!(batch_kinds(&container), vec![SOLID, PRIMITIVE, SOLID]);
+    }
+}
@@ -5,7 +5,7 @@
 use std::fs::File;
 use std::path::{Path, PathBuf};
 
-use api::{ExternalImageData, ImageDescriptor};
+use api::{ExternalImageData, ExternalTextureParams, ImageDescriptor};
 #[cfg(feature = "png")]
 use api::ImageFormat;
 use api::units::TexelRect;
@@ -224,6 +224,14 @@ impl CaptureConfig {
     pub fn save_png(
         path: PathBuf, size: DeviceIntSize, format: ImageFormat, stride: Option<i32>, data: &[u8],
     ) {
+        Self::try_save_png(path, size, format, stride, data).unwrap();
+    }
+
+    /// Like `save_png`, but returns IO errors rather than panicking.
+    #[cfg(feature = "png")]
+    pub fn try_save_png(
+        path: PathBuf, size: DeviceIntSize, format: ImageFormat, stride: Option<i32>, data: &[u8],
+    ) -> std::io::Result<()> {
         use png::{BitDepth, ColorType, Encoder};
         use std::io::BufWriter;
         use std::borrow::Cow;
@@ -251,18 +259,17 @@ impl CaptureConfig {
             ImageFormat::RG8 => ColorType::GrayscaleAlpha,
             _ => {
                 error!("Unable to save PNG of {:?}", format);
-                return;
+                return Ok(());
             }
         };
-        let w = BufWriter::new(File::create(path).unwrap());
+        let w = BufWriter::new(File::create(path)?);
         let mut enc = Encoder::new(w, size.width as u32, size.height as u32);
         enc.set_color(color_type);
         enc.set_depth(BitDepth::Eight);
         enc
-            .write_header()
-            .unwrap()
-            .write_image_data(&*data)
-            .unwrap();
+            .write_header()?
+            .write_image_data(&*data)?;
+        Ok(())
     }
 }
 
@@ -287,4 +294,7 @@ pub struct PlainExternalImage {
     pub external: ExternalImageData,
     /// UV sub-rectangle of the image.
     pub uv: TexelRect,
+    /// Sampling hints of a native texture, see `ExternalTextureParams`.
+    #[serde(default)]
+    pub params: Option<ExternalTextureParams>,
 }
@@ -4,7 +4,7 @@
 
 use api::{ColorF, YuvRangedColorSpace, YuvFormat, ImageRendering, ExternalImageId, ImageBufferKind};
 use api::units::*;
-use api::ColorDepth;
+use api::{AccessibilityColorFilter, ColorDepth, EmbedderDrawId, PostProcessParams};
 use crate::image_source::resolve_image;
 use euclid::Box2D;
 use crate::gpu_cache::GpuCache;
@@ -167,6 +167,10 @@ pub enum ExternalSurfaceDependency {
     Rgb {
         image_dependency: ImageDependency,
     },
+    /// Drawn by the embedder's draw handler, see `EmbedderDrawDisplayItem`.
+    EmbedderDraw {
+        id: EmbedderDrawId,
+    },
 }
 
 /// Describes information about drawing a primitive as a compositor surface.
@@ -233,6 +237,9 @@ pub enum ResolvedExternalSurfaceColorData {
         image_dependency: ImageDependency,
         plane: ExternalPlaneDescriptor,
     },
+    EmbedderDraw {
+        id: EmbedderDrawId,
+    },
 }
 
 /// An ExternalSurfaceDescriptor that has had image keys
@@ -525,6 +532,12 @@ pub struct CompositeState {
     pub transforms: Vec<CompositorTransform>,
     /// Whether we have low quality pinch zoom enabled
     low_quality_pinch_zoom: bool,
+    /// The color filter applied to the composited tiles, see
+    /// `Transaction::set_color_filter`.
+    pub color_filter: Option<AccessibilityColorFilter>,
+    /// The uniforms of the embedder supplied post-processing shader, see
+    /// `Transaction::set_post_process_params`.
+    pub post_process_params: PostProcessParams,
 }
 
 impl CompositeState {
@@ -547,6 +560,8 @@ impl CompositeState {
             picture_cache_debug: PictureCacheDebugInfo::new(),
             transforms: Vec::new(),
             low_quality_pinch_zoom,
+            color_filter: None,
+            post_process_params: PostProcessParams::default(),
         }
     }
 
@@ -747,6 +762,9 @@ impl CompositeState {
                         ExternalSurfaceDependency::Rgb { .. } => {
                             1
                         }
+                        ExternalSurfaceDependency::EmbedderDraw { .. } => {
+                            0
+                        }
                     };
 
                 let mut image_dependencies = [ImageDependency::INVALID; 3];
@@ -759,6 +777,9 @@ impl CompositeState {
                         ExternalSurfaceDependency::Rgb { image_dependency, .. } => {
                             image_dependency
                         }
+                        ExternalSurfaceDependency::EmbedderDraw { .. } => {
+                            unreachable!()
+                        }
                     };
                     image_dependencies[i] = dependency;
                 }
@@ -945,6 +966,13 @@ impl CompositeState {
                     update_params,
                 });
             },
+            ExternalSurfaceDependency::EmbedderDraw { id } => {
+                self.external_surfaces.push(ResolvedExternalSurface {
+                    color_data: ResolvedExternalSurfaceColorData::EmbedderDraw { id },
+                    image_buffer_kind: ImageBufferKind::Texture2D,
+                    update_params,
+                });
+            },
         }
         external_surface_index
     }
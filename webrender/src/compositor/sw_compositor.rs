@@ -168,10 +168,20 @@ impl SwSurface {
     }
 }
 
+// Compositor surfaces are not mipmapped, so a high quality filter can't be
+// honored when compositing them. Only warn about it once.
+static WARNED_HIGH_QUALITY_SURFACE: AtomicBool = AtomicBool::new(false);
+
 fn image_rendering_to_gl_filter(filter: ImageRendering) -> gl::GLenum {
     match filter {
         ImageRendering::Pixelated => gl::NEAREST,
         ImageRendering::Auto | ImageRendering::CrispEdges => gl::LINEAR,
+        ImageRendering::HighQuality => {
+            if !WARNED_HIGH_QUALITY_SURFACE.swap(true, Ordering::Relaxed) {
+                warn!("High quality image rendering is not supported for compositor surfaces, using bilinear filtering");
+            }
+            gl::LINEAR
+        }
     }
 }
 
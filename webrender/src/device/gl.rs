@@ -6,6 +6,7 @@ use super::super::shader_source::{OPTIMIZED_SHADERS, UNOPTIMIZED_SHADERS};
 use api::{ImageDescriptor, ImageFormat, Parameter, BoolParameter, IntParameter, ImageRendering};
 use api::{MixBlendMode, ImageBufferKind, VoidPtrToSizeFn};
 use api::{CrashAnnotator, CrashAnnotation, CrashAnnotatorGuard};
+use api::AccessibilityColorFilter;
 use api::units::*;
 use euclid::default::Transform3D;
 use gleam::gl;
@@ -30,6 +31,7 @@ use std::{
     rc::Rc,
     slice,
     sync::Arc,
+    sync::atomic::{AtomicBool, Ordering},
     thread,
     time::Duration,
 };
@@ -64,6 +66,10 @@ pub struct TextureSlot(pub usize);
 // In some places we need to temporarily bind a texture to any slot.
 const DEFAULT_TEXTURE: TextureSlot = TextureSlot(0);
 
+// External textures are not mipmapped, so a high quality filter can't be
+// honored when one is bound. Only warn about it once.
+static WARNED_HIGH_QUALITY_EXTERNAL: AtomicBool = AtomicBool::new(false);
+
 #[repr(u32)]
 pub enum DepthFunction {
     Always = gl::ALWAYS,
@@ -521,6 +527,11 @@ impl Texture {
         bpp * w * h
     }
 
+    /// The GL name of this texture.
+    pub fn internal_id(&self) -> gl::GLuint {
+        self.id
+    }
+
     #[cfg(feature = "replay")]
     pub fn into_external(mut self) -> ExternalTexture {
         let ext = ExternalTexture {
@@ -551,6 +562,14 @@ pub struct Program {
     u_transform: gl::GLint,
     u_mode: gl::GLint,
     u_texture_size: gl::GLint,
+    u_color_filter: gl::GLint,
+    u_color_matrix: gl::GLint,
+    u_color_offset: gl::GLint,
+    u_text_contrast: gl::GLint,
+    /// The color filter and text contrast last uploaded to the uniforms above,
+    /// so that binding the program only uploads them when they changed.
+    uploaded_color_filter: Cell<Option<Option<AccessibilityColorFilter>>>,
+    uploaded_text_contrast: Cell<Option<i32>>,
     source_info: ProgramSourceInfo,
     is_initialized: bool,
 }
@@ -647,6 +666,29 @@ pub struct VBOId(gl::GLuint);
 #[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
 struct IBOId(gl::GLuint);
 
+/// The state the devices of a share group have in common.
+#[derive(Default)]
+struct ShareGroupState {
+    /// Identifies the next device to join the group.
+    next_device_id: usize,
+    /// Per device of the group, the textures deleted by other devices whose
+    /// FBOs it has yet to delete.
+    deleted_textures: FastHashMap<usize, Vec<gl::GLuint>>,
+    /// A fence after the commands of the last device to end a frame, and the
+    /// device which inserted it.
+    fence: Option<(usize, gl::GLsync)>,
+}
+
+/// A group of devices whose GL contexts are in the same share group, and which
+/// use each other's textures.
+///
+/// Textures are shared between the contexts, but framebuffer objects are not,
+/// so each device attaches its own FBOs to the textures it renders to or reads
+/// from. The devices also wait for each other's commands when they begin a
+/// frame, so that they see the changes made to the textures by the others.
+#[derive(Clone, Default)]
+pub struct DeviceShareGroup(Rc<RefCell<ShareGroupState>>);
+
 #[derive(Clone, Debug)]
 enum ProgramSourceType {
     Unoptimized,
@@ -999,6 +1041,8 @@ pub struct Capabilities {
     pub supports_image_external_essl3: bool,
     /// Whether the VAO must be rebound after an attached VBO has been orphaned.
     pub requires_vao_rebind_after_orphaning: bool,
+    /// Whether timer queries, including GL_TIMESTAMP, are supported.
+    pub supports_timer_queries: bool,
     /// The name of the renderer, as reported by GL
     pub renderer_name: String,
 }
@@ -1075,6 +1119,11 @@ pub struct Device {
     bound_read_fbo: (FBOId, DeviceIntPoint),
     bound_draw_fbo: FBOId,
     program_mode_id: UniformLocation,
+    /// The color filter applied by the composite shaders, see `set_color_filter`.
+    color_filter: Option<AccessibilityColorFilter>,
+    /// The text contrast percentage applied by the text run shaders, see
+    /// `IntParameter::TextContrast`.
+    text_contrast: i32,
     default_read_fbo: FBOId,
     default_draw_fbo: FBOId,
 
@@ -1107,6 +1156,15 @@ pub struct Device {
     /// by sharing these across targets.
     depth_targets: FastHashMap<DeviceIntSize, SharedDepthTarget>,
 
+    /// The share group this device joined, if any, and its identifier there.
+    share_group: Option<(DeviceShareGroup, usize)>,
+
+    /// When in a share group, the FBOs of this device for each texture it
+    /// rendered to or read from, keyed by texture and whether a depth target
+    /// is attached, with the size of the depth target. The FBOs stored in the
+    /// textures only belong to the context of the device which created them.
+    texture_fbos: FastHashMap<(gl::GLuint, bool), (FBOId, Option<DeviceIntSize>)>,
+
     // debug
     inside_frame: bool,
     crash_annotator: Option<Box<dyn CrashAnnotator>>,
@@ -1339,6 +1397,10 @@ pub enum ReadTarget {
     Texture {
         /// ID of the FBO to read from.
         fbo_id: FBOId,
+        /// Native GL texture ID
+        id: gl::GLuint,
+        /// Native GL texture target
+        target: gl::GLuint,
     },
     /// Use an FBO attached to an external texture.
     External {
@@ -1357,6 +1419,8 @@ impl ReadTarget {
     ) -> Self {
         ReadTarget::Texture {
             fbo_id: texture.fbo.unwrap(),
+            id: texture.id,
+            target: texture.target,
         }
     }
 
@@ -1387,8 +1451,8 @@ impl From<DrawTarget> for ReadTarget {
                     offset,
                 }
             }
-            DrawTarget::Texture { fbo_id, .. } => {
-                ReadTarget::Texture { fbo_id }
+            DrawTarget::Texture { fbo_id, id, target, .. } => {
+                ReadTarget::Texture { fbo_id, id, target }
             }
             DrawTarget::External { fbo, .. } => {
                 ReadTarget::External { fbo }
@@ -1642,6 +1706,15 @@ impl Device {
             supports_extension(&extensions, "GL_ARB_copy_image")
         };
 
+        // Timer queries are core since GL 3.3, and need an extension on GLES.
+        let supports_timer_queries = match gl.get_type() {
+            gl::GlType::Gl => {
+                gl_version >= [3, 3] ||
+                supports_extension(&extensions, "GL_ARB_timer_query")
+            }
+            gl::GlType::Gles => supports_extension(&extensions, "GL_EXT_disjoint_timer_query"),
+        };
+
         // We have seen crashes on x86 PowerVR Rogue G6430 devices during GPU cache
         // updates using the scatter shader. It seems likely that GL_EXT_color_buffer_float
         // is broken. See bug 1709408.
@@ -1896,6 +1969,7 @@ impl Device {
                 uses_native_antialiasing,
                 supports_image_external_essl3,
                 requires_vao_rebind_after_orphaning,
+                supports_timer_queries,
                 renderer_name,
             },
 
@@ -1909,9 +1983,14 @@ impl Device {
 
             depth_targets: FastHashMap::default(),
 
+            share_group: None,
+            texture_fbos: FastHashMap::default(),
+
             bound_textures: [0; 16],
             bound_program: 0,
             bound_program_name: Rc::new(std::ffi::CString::new("").unwrap()),
+            color_filter: None,
+            text_contrast: 0,
             bound_vao: 0,
             bound_read_fbo: (FBOId(0), DeviceIntPoint::zero()),
             bound_draw_fbo: FBOId(0),
@@ -1949,6 +2028,71 @@ impl Device {
         &self.gl
     }
 
+    /// Makes this device use textures created by the other devices of `group`.
+    /// The GL contexts of the devices must be in the same share group.
+    pub fn join_share_group(&mut self, group: &DeviceShareGroup) {
+        assert!(self.share_group.is_none(), "Device already is in a share group");
+        let mut state = group.0.borrow_mut();
+        let device_id = state.next_device_id;
+        state.next_device_id += 1;
+        state.deleted_textures.insert(device_id, Vec::new());
+        self.share_group = Some((group.clone(), device_id));
+    }
+
+    /// Deletes the FBOs this device attached to the textures of its share
+    /// group. Must be called before the device is dropped if it joined one.
+    pub fn leave_share_group(&mut self) {
+        let (group, device_id) = match self.share_group.take() {
+            Some(share_group) => share_group,
+            None => return,
+        };
+        for (_, (fbo, depth_size)) in mem::take(&mut self.texture_fbos) {
+            self.gl.delete_framebuffers(&[fbo.0]);
+            if let Some(depth_size) = depth_size {
+                self.release_depth_target(depth_size);
+            }
+        }
+
+        let mut state = group.0.borrow_mut();
+        state.deleted_textures.remove(&device_id);
+        if state.deleted_textures.is_empty() {
+            if let Some((_, fence)) = state.fence.take() {
+                self.gl.delete_sync(fence);
+            }
+        }
+    }
+
+    /// Returns the FBO of this device for drawing to or reading from the
+    /// texture `id`, creating it if this device is in a share group and
+    /// didn't attach one to the texture yet.
+    fn texture_fbo(
+        &mut self,
+        fbo_id: FBOId,
+        id: gl::GLuint,
+        target: gl::GLuint,
+        depth_size: Option<DeviceIntSize>,
+    ) -> FBOId {
+        if self.share_group.is_none() {
+            return fbo_id;
+        }
+        match self.texture_fbos.get(&(id, depth_size.is_some())) {
+            Some(&(fbo, _)) => fbo,
+            None => self.create_texture_fbo(id, target, depth_size),
+        }
+    }
+
+    /// Deletes the FBOs this device attached to the texture `id`.
+    fn delete_texture_fbos(&mut self, id: gl::GLuint) {
+        for with_depth in [false, true] {
+            if let Some((fbo, depth_size)) = self.texture_fbos.remove(&(id, with_depth)) {
+                self.gl.delete_framebuffers(&[fbo.0]);
+                if let Some(depth_size) = depth_size {
+                    self.release_depth_target(depth_size);
+                }
+            }
+        }
+    }
+
     pub fn set_parameter(&mut self, param: &Parameter) {
         match param {
             Parameter::Bool(BoolParameter::PboUploads, enabled) => {
@@ -1971,6 +2115,9 @@ impl Device {
             Parameter::Int(IntParameter::BatchedUploadThreshold, threshold) => {
                 self.batched_upload_threshold = *threshold;
             }
+            Parameter::Int(IntParameter::TextContrast, percent) => {
+                self.text_contrast = (*percent).max(0).min(100);
+            }
             _ => {}
         }
     }
@@ -2066,6 +2213,29 @@ impl Device {
         self.gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, self.bound_draw_fbo.0);
     }
 
+    /// Forgets the cached GL bindings, for when code outside of WebRender has
+    /// used the context in the middle of a frame.
+    pub fn reset_cached_state(&mut self) {
+        self.bound_program = 0;
+        self.program_mode_id = UniformLocation::INVALID;
+        self.gl.use_program(0);
+
+        self.reset_state();
+
+        self.gl.bind_buffer(gl::PIXEL_UNPACK_BUFFER, 0);
+        self.gl.active_texture(gl::TEXTURE0);
+    }
+
+    /// The GL name of the framebuffer bound for `DrawTarget::Default`.
+    pub fn default_draw_fbo_id(&self) -> gl::GLuint {
+        self.default_draw_fbo.0
+    }
+
+    /// The GL name of the currently bound draw framebuffer.
+    pub fn bound_draw_fbo_id(&self) -> gl::GLuint {
+        self.bound_draw_fbo.0
+    }
+
     #[cfg(debug_assertions)]
     fn print_shader_errors(source: &str, log: &str) {
         // hacky way to extract the offending lines
@@ -2161,6 +2331,22 @@ impl Device {
             self.gl = self.base_gl.take().unwrap();
         }
 
+        if let Some((group, device_id)) = self.share_group.clone() {
+            let mut state = group.0.borrow_mut();
+            // Wait for the commands of the device which last ended a frame, so
+            // that we see its changes to the textures we share.
+            if let Some((fence_device_id, fence)) = state.fence {
+                if fence_device_id != device_id {
+                    self.gl.wait_sync(fence, 0, gl::TIMEOUT_IGNORED);
+                }
+            }
+            let deleted_textures = mem::take(state.deleted_textures.get_mut(&device_id).unwrap());
+            drop(state);
+            for id in deleted_textures {
+                self.delete_texture_fbos(id);
+            }
+        }
+
         // Retrieve the currently set FBO.
         let mut default_read_fbo = [0];
         unsafe {
@@ -2225,7 +2411,14 @@ impl Device {
             }
             if let Some(image_rendering) = image_rendering {
                 let filter = match image_rendering {
-                    ImageRendering::Auto | ImageRendering::CrispEdges => gl::LINEAR,
+                    ImageRendering::Auto |
+                    ImageRendering::CrispEdges => gl::LINEAR,
+                    ImageRendering::HighQuality => {
+                        if !WARNED_HIGH_QUALITY_EXTERNAL.swap(true, Ordering::Relaxed) {
+                            warn!("High quality image rendering is not supported for external textures, using bilinear filtering");
+                        }
+                        gl::LINEAR
+                    }
                     ImageRendering::Pixelated => gl::NEAREST,
                 };
                 self.gl.tex_parameter_i(target, gl::TEXTURE_MIN_FILTER, filter as i32);
@@ -2262,6 +2455,32 @@ impl Device {
         );
     }
 
+    /// Sets the wrap mode of an external texture to clamp to edge, and returns
+    /// the wrap modes it had so that they can be restored with
+    /// `set_external_texture_wrap` before the texture is unlocked.
+    pub fn clamp_external_texture_to_edge(
+        &mut self,
+        external_texture: &ExternalTexture,
+    ) -> [gl::GLint; 2] {
+        self.bind_external_texture(TextureSlot(0), external_texture);
+        let wrap = [
+            self.gl.get_tex_parameter_iv(external_texture.target, gl::TEXTURE_WRAP_S),
+            self.gl.get_tex_parameter_iv(external_texture.target, gl::TEXTURE_WRAP_T),
+        ];
+        self.set_external_texture_wrap(external_texture, [gl::CLAMP_TO_EDGE as gl::GLint; 2]);
+        wrap
+    }
+
+    pub fn set_external_texture_wrap(
+        &mut self,
+        external_texture: &ExternalTexture,
+        wrap: [gl::GLint; 2],
+    ) {
+        self.bind_external_texture(TextureSlot(0), external_texture);
+        self.gl.tex_parameter_i(external_texture.target, gl::TEXTURE_WRAP_S, wrap[0]);
+        self.gl.tex_parameter_i(external_texture.target, gl::TEXTURE_WRAP_T, wrap[1]);
+    }
+
     pub fn bind_read_target_impl(
         &mut self,
         fbo_id: FBOId,
@@ -2279,7 +2498,9 @@ impl Device {
     pub fn bind_read_target(&mut self, target: ReadTarget) {
         let fbo_id = match target {
             ReadTarget::Default => self.default_read_fbo,
-            ReadTarget::Texture { fbo_id } => fbo_id,
+            ReadTarget::Texture { fbo_id, id, target } => {
+                self.texture_fbo(fbo_id, id, target, None)
+            }
             ReadTarget::External { fbo } => fbo,
             ReadTarget::NativeSurface { fbo_id, .. } => fbo_id,
         };
@@ -2316,11 +2537,12 @@ impl Device {
             DrawTarget::Default { rect, .. } => {
                 (self.default_draw_fbo, rect, false)
             }
-            DrawTarget::Texture { dimensions, fbo_id, with_depth, .. } => {
+            DrawTarget::Texture { dimensions, fbo_id, with_depth, id, target } => {
                 let rect = FramebufferIntRect::from_size(
                     device_size_as_framebuffer_size(dimensions),
                 );
-                (fbo_id, rect, with_depth)
+                let depth_size = if with_depth { Some(dimensions) } else { None };
+                (self.texture_fbo(fbo_id, id, target, depth_size), rect, with_depth)
             },
             DrawTarget::External { fbo, size } => {
                 (fbo, size.into(), false)
@@ -2534,6 +2756,12 @@ impl Device {
         program.u_transform = self.gl.get_uniform_location(program.id, "uTransform");
         program.u_mode = self.gl.get_uniform_location(program.id, "uMode");
         program.u_texture_size = self.gl.get_uniform_location(program.id, "uTextureSize");
+        program.u_color_filter = self.gl.get_uniform_location(program.id, "uColorFilter");
+        program.u_color_matrix = self.gl.get_uniform_location(program.id, "uColorMatrix");
+        program.u_color_offset = self.gl.get_uniform_location(program.id, "uColorOffset");
+        program.u_text_contrast = self.gl.get_uniform_location(program.id, "uTextContrast");
+        program.uploaded_color_filter.set(None);
+        program.uploaded_text_contrast.set(None);
 
         Ok(())
     }
@@ -2555,6 +2783,12 @@ impl Device {
             self.bound_program_name = program.source_info.full_name_cstr.clone();
             self.program_mode_id = UniformLocation(program.u_mode);
         }
+        self.set_shader_color_filter(program);
+        if program.u_text_contrast != -1 &&
+            program.uploaded_text_contrast.get() != Some(self.text_contrast) {
+            self.gl.uniform_1i(program.u_text_contrast, self.text_contrast);
+            program.uploaded_text_contrast.set(Some(self.text_contrast));
+        }
         true
     }
 
@@ -2759,19 +2993,24 @@ impl Device {
     /// needed.
     pub fn invalidate_render_target(&mut self, texture: &Texture) {
         if self.capabilities.supports_render_target_invalidate {
-            let (fbo, attachments) = if texture.supports_depth() {
-                (&texture.fbo_with_depth,
+            let (mut fbo, attachments) = if texture.supports_depth() {
+                (texture.fbo_with_depth,
                  &[gl::COLOR_ATTACHMENT0, gl::DEPTH_ATTACHMENT] as &[gl::GLenum])
             } else {
-                (&texture.fbo, &[gl::COLOR_ATTACHMENT0] as &[gl::GLenum])
+                (texture.fbo, &[gl::COLOR_ATTACHMENT0] as &[gl::GLenum])
             };
+            if self.share_group.is_some() {
+                fbo = self.texture_fbos
+                    .get(&(texture.id, texture.supports_depth()))
+                    .map(|&(fbo, _)| fbo);
+            }
 
             if let Some(fbo_id) = fbo {
                 let original_bound_fbo = self.bound_draw_fbo;
                 // Note: The invalidate extension may not be supported, in which
                 // case this is a no-op. That's ok though, because it's just a
                 // hint.
-                self.bind_external_draw_target(*fbo_id);
+                self.bind_external_draw_target(fbo_id);
                 self.gl.invalidate_framebuffer(gl::FRAMEBUFFER, attachments);
                 self.bind_external_draw_target(original_bound_fbo);
             }
@@ -2810,19 +3049,34 @@ impl Device {
     }
 
     fn init_fbos(&mut self, texture: &mut Texture, with_depth: bool) {
-        let (fbo, depth_rb) = if with_depth {
-            let depth_target = self.acquire_depth_target(texture.get_dimensions());
-            (&mut texture.fbo_with_depth, Some(depth_target))
+        let depth_size = if with_depth { Some(texture.get_dimensions()) } else { None };
+        let fbo_id = self.create_texture_fbo(texture.id, texture.target, depth_size);
+        let fbo = if with_depth {
+            &mut texture.fbo_with_depth
         } else {
-            (&mut texture.fbo, None)
+            &mut texture.fbo
         };
-
-        // Generate the FBOs.
         assert!(fbo.is_none());
-        let fbo_id = FBOId(*self.gl.gen_framebuffers(1).first().unwrap());
         *fbo = Some(fbo_id);
+    }
+
+    /// Generates an FBO with the texture `id` as color attachment, and a depth
+    /// target of `depth_size` if any.
+    fn create_texture_fbo(
+        &mut self,
+        id: gl::GLuint,
+        target: gl::GLuint,
+        depth_size: Option<DeviceIntSize>,
+    ) -> FBOId {
+        let depth_rb = depth_size.map(|size| self.acquire_depth_target(size));
+
+        // Generate the FBO.
+        let fbo_id = FBOId(*self.gl.gen_framebuffers(1).first().unwrap());
+        if self.share_group.is_some() {
+            self.texture_fbos.insert((id, depth_size.is_some()), (fbo_id, depth_size));
+        }
 
-        // Bind the FBOs.
+        // Bind the FBO.
         let original_bound_fbo = self.bound_draw_fbo;
 
         self.bind_external_draw_target(fbo_id);
@@ -2830,8 +3084,8 @@ impl Device {
         self.gl.framebuffer_texture_2d(
             gl::DRAW_FRAMEBUFFER,
             gl::COLOR_ATTACHMENT0,
-            texture.target,
-            texture.id,
+            target,
+            id,
             0,
         );
 
@@ -2851,6 +3105,8 @@ impl Device {
         );
 
         self.bind_external_draw_target(original_bound_fbo);
+
+        fbo_id
     }
 
     fn acquire_depth_target(&mut self, dimensions: DeviceIntSize) -> RBOId {
@@ -2965,6 +3221,21 @@ impl Device {
 
     pub fn delete_texture(&mut self, mut texture: Texture) {
         debug_assert!(self.inside_frame);
+        if let Some((ref group, device_id)) = self.share_group {
+            // The FBOs of the texture may belong to another device, and the
+            // other devices delete the ones they attached at their next frame.
+            let mut state = group.0.borrow_mut();
+            for (&other_device_id, deleted_textures) in &mut state.deleted_textures {
+                if other_device_id != device_id {
+                    deleted_textures.push(texture.id);
+                }
+            }
+            drop(state);
+            self.delete_texture_fbos(texture.id);
+            texture.fbo = None;
+            texture.fbo_with_depth = None;
+        }
+
         let had_depth = texture.supports_depth();
         if let Some(fbo) = texture.fbo {
             self.gl.delete_framebuffers(&[fbo.0]);
@@ -3046,6 +3317,12 @@ impl Device {
             u_transform: 0,
             u_mode: 0,
             u_texture_size: 0,
+            u_color_filter: 0,
+            u_color_matrix: 0,
+            u_color_offset: 0,
+            u_text_contrast: 0,
+            uploaded_color_filter: Cell::new(None),
+            uploaded_text_contrast: Cell::new(None),
             source_info,
             is_initialized: false,
         };
@@ -3053,6 +3330,86 @@ impl Device {
         Ok(program)
     }
 
+    /// Create and link a shader program from complete GLSL sources rather
+    /// than from the built-in shader files. The program is never stored in
+    /// the program binary cache.
+    pub fn create_custom_program(
+        &mut self,
+        name: &'static str,
+        vs_source: &String,
+        fs_source: &String,
+        descriptor: &VertexDescriptor,
+    ) -> Result<Program, ShaderError> {
+        debug_assert!(self.inside_frame);
+
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::Hasher;
+
+        let mut hasher = DefaultHasher::new();
+        hasher.write(vs_source.as_bytes());
+        hasher.write(fs_source.as_bytes());
+
+        let source_info = ProgramSourceInfo {
+            base_filename: name,
+            features: Vec::new(),
+            full_name_cstr: Rc::new(std::ffi::CString::new(name).unwrap()),
+            source_type: ProgramSourceType::Unoptimized,
+            digest: hasher.into(),
+        };
+
+        let vs_id = self.compile_shader(name, gl::VERTEX_SHADER, vs_source)?;
+        let fs_id = match self.compile_shader(name, gl::FRAGMENT_SHADER, fs_source) {
+            Ok(fs_id) => fs_id,
+            Err(err) => {
+                self.gl.delete_shader(vs_id);
+                return Err(err);
+            }
+        };
+
+        let pid = self.gl.create_program();
+        self.gl.attach_shader(pid, vs_id);
+        self.gl.attach_shader(pid, fs_id);
+        for (i, attr) in descriptor
+            .vertex_attributes
+            .iter()
+            .chain(descriptor.instance_attributes.iter())
+            .enumerate()
+        {
+            self.gl.bind_attrib_location(pid, i as gl::GLuint, attr.name);
+        }
+        self.gl.link_program(pid);
+        self.gl.detach_shader(pid, vs_id);
+        self.gl.detach_shader(pid, fs_id);
+        self.gl.delete_shader(vs_id);
+        self.gl.delete_shader(fs_id);
+
+        let mut link_status = [0];
+        unsafe {
+            self.gl.get_program_iv(pid, gl::LINK_STATUS, &mut link_status);
+        }
+        if link_status[0] == 0 {
+            let error_log = self.gl.get_program_info_log(pid);
+            error!("Failed to link shader program: {}\n{}", name, error_log);
+            self.gl.delete_program(pid);
+            return Err(ShaderError::Link(name.to_owned(), error_log));
+        }
+
+        Ok(Program {
+            id: pid,
+            u_transform: self.gl.get_uniform_location(pid, "uTransform"),
+            u_mode: self.gl.get_uniform_location(pid, "uMode"),
+            u_texture_size: self.gl.get_uniform_location(pid, "uTextureSize"),
+            u_color_filter: -1,
+            u_color_matrix: -1,
+            u_color_offset: -1,
+            u_text_contrast: -1,
+            uploaded_color_filter: Cell::new(None),
+            uploaded_text_contrast: Cell::new(None),
+            source_info,
+            is_initialized: true,
+        })
+    }
+
     fn build_shader_string<F: FnMut(&str)>(
         &self,
         features: &[&'static str],
@@ -3128,6 +3485,59 @@ impl Device {
         }
     }
 
+    /// Sets an array of vec4 uniforms on the currently bound program. Unknown
+    /// uniforms are ignored.
+    pub fn set_shader_vec4_array(
+        &self,
+        program: &Program,
+        name: &str,
+        values: &[f32],
+    ) {
+        debug_assert!(self.inside_frame);
+        debug_assert_eq!(self.bound_program, program.id);
+        debug_assert_eq!(values.len() % 4, 0);
+
+        let location = self.gl.get_uniform_location(program.id, name);
+        if location != -1 {
+            self.gl.uniform_4fv(location, values);
+        }
+    }
+
+    /// Sets the color filter applied by the composite shaders bound from now
+    /// on. Other shaders ignore it.
+    pub fn set_color_filter(&mut self, filter: Option<AccessibilityColorFilter>) {
+        self.color_filter = filter;
+    }
+
+    fn set_shader_color_filter(&self, program: &Program) {
+        if program.u_color_filter == -1 ||
+            program.uploaded_color_filter.get() == Some(self.color_filter) {
+            return;
+        }
+        program.uploaded_color_filter.set(Some(self.color_filter));
+
+        match self.color_filter {
+            Some(filter) => {
+                let (matrix, offset) = filter.to_matrix();
+                // GL expects column-major matrices. A 4x4 matrix that leaves
+                // alpha untouched is used since SWGL only supports those.
+                let mut columns = [0.0; 16];
+                for (i, row) in matrix.iter().enumerate() {
+                    for (j, value) in row.iter().enumerate() {
+                        columns[j * 4 + i] = *value;
+                    }
+                }
+                columns[15] = 1.0;
+                self.gl.uniform_1i(program.u_color_filter, 1);
+                self.gl.uniform_matrix_4fv(program.u_color_matrix, false, &columns);
+                self.gl.uniform_4fv(program.u_color_offset, &[offset[0], offset[1], offset[2], 0.0]);
+            }
+            None => {
+                self.gl.uniform_1i(program.u_color_filter, 0);
+            }
+        }
+    }
+
     pub fn create_pbo(&mut self) -> PBO {
         let id = self.gl.gen_buffers(1)[0];
         PBO {
@@ -3762,6 +4172,18 @@ impl Device {
 
         self.gl.active_texture(gl::TEXTURE0);
 
+        if let Some((ref group, device_id)) = self.share_group {
+            let mut state = group.0.borrow_mut();
+            if let Some((_, fence)) = state.fence.take() {
+                self.gl.delete_sync(fence);
+            }
+            let fence = self.gl.fence_sync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+            state.fence = Some((device_id, fence));
+            // The other devices may wait for the fence before this context
+            // flushes its commands otherwise.
+            self.gl.flush();
+        }
+
         self.frame_id.0 += 1;
 
         // Save any shaders compiled this frame to disk.
@@ -3,11 +3,12 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use gleam::gl;
+use std::cell::Cell;
 use std::mem;
 use std::rc::Rc;
 
 use crate::device::GpuFrameId;
-use crate::profiler::GpuProfileTag;
+use crate::profiler::{self, GpuProfileTag};
 
 #[derive(Copy, Clone, Debug)]
 pub enum GpuDebugMethod {
@@ -66,6 +67,23 @@ impl<T> QuerySet<T> {
     }
 }
 
+/// A GPU timeline registered with the profiler hooks, which GPU zones are
+/// reported to.
+struct GpuZoneContext {
+    id: u8,
+    next_query_id: Cell<u16>,
+}
+
+impl GpuZoneContext {
+    /// The profiler matches timestamps to zone boundaries with a 16 bit id,
+    /// which only needs to be unique among the queries in flight.
+    fn next_query_id(&self) -> u16 {
+        let id = self.next_query_id.get();
+        self.next_query_id.set(id.wrapping_add(1));
+        id
+    }
+}
+
 pub struct GpuFrameProfile {
     gl: Rc<dyn gl::Gl>,
     timers: QuerySet<GpuTimer>,
@@ -73,6 +91,11 @@ pub struct GpuFrameProfile {
     frame_id: GpuFrameId,
     inside_frame: bool,
     debug_method: GpuDebugMethod,
+    /// Timestamp queries at the boundaries of each timer, along with the
+    /// matching profiler query id.
+    timestamps: QuerySet<u16>,
+    gpu_zones: Option<Rc<GpuZoneContext>>,
+    gpu_zone_open: bool,
 }
 
 impl GpuFrameProfile {
@@ -83,8 +106,43 @@ impl GpuFrameProfile {
             samplers: QuerySet::new(),
             frame_id: GpuFrameId::new(0),
             inside_frame: false,
-            debug_method
+            debug_method,
+            timestamps: QuerySet::new(),
+            gpu_zones: None,
+            gpu_zone_open: false,
+        }
+    }
+
+    fn enable_gpu_zones(&mut self, gpu_zones: Rc<GpuZoneContext>, count: i32) {
+        self.timestamps.set = self.gl.gen_queries(count);
+        self.gpu_zones = Some(gpu_zones);
+    }
+
+    /// Place a timestamp query and report the corresponding GPU zone boundary:
+    /// the start of a zone if a label is given, the end of the open zone otherwise.
+    fn gpu_zone_boundary(&mut self, label: Option<&'static str>) {
+        let gpu_zones = match self.gpu_zones {
+            Some(ref gpu_zones) => gpu_zones,
+            None => return,
+        };
+
+        match label {
+            // Only open a zone if there is room left to close it.
+            Some(_) if self.timestamps.data.len() + 2 > self.timestamps.set.len() => return,
+            None if !self.gpu_zone_open => return,
+            _ => {}
+        }
+
+        let query_id = gpu_zones.next_query_id();
+        match label {
+            Some(label) => profiler::begin_gpu_marker(gpu_zones.id, label, query_id),
+            None => profiler::end_gpu_marker(gpu_zones.id, query_id),
         }
+        self.gpu_zone_open = label.is_some();
+
+        let query = self.timestamps.set[self.timestamps.data.len()];
+        self.timestamps.data.push(query_id);
+        self.gl.query_counter(query, gl::TIMESTAMP);
     }
 
     fn enable_timers(&mut self, count: i32) {
@@ -113,6 +171,8 @@ impl GpuFrameProfile {
         self.frame_id = frame_id;
         self.timers.reset();
         self.samplers.reset();
+        self.timestamps.reset();
+        self.gpu_zone_open = false;
         self.inside_frame = true;
     }
 
@@ -128,6 +188,7 @@ impl GpuFrameProfile {
             self.gl.end_query(gl::TIME_ELAPSED);
             self.timers.pending = 0;
         }
+        self.gpu_zone_boundary(None);
     }
 
     fn finish_sampler(&mut self) {
@@ -143,6 +204,8 @@ impl GpuFrameProfile {
 
         let marker = GpuMarker::new(&self.gl, tag.label, self.debug_method);
 
+        self.gpu_zone_boundary(Some(tag.label));
+
         if let Some(query) = self.timers.add(GpuTimer { tag, time_ns: 0 }) {
             self.gl.begin_query(gl::TIME_ELAPSED, query);
         }
@@ -164,6 +227,13 @@ impl GpuFrameProfile {
         debug_assert!(!self.inside_frame);
         let gl = &self.gl;
 
+        if let Some(ref gpu_zones) = self.gpu_zones {
+            self.timestamps.take(|query_id, query| {
+                let gpu_time = gl.get_query_object_i64v(query, gl::QUERY_RESULT);
+                profiler::add_gpu_time(gpu_zones.id, *query_id, gpu_time);
+            });
+        }
+
         (
             self.frame_id,
             self.timers.take(|timer, query| {
@@ -180,6 +250,9 @@ impl Drop for GpuFrameProfile {
     fn drop(&mut self) {
         self.disable_timers();
         self.disable_samplers();
+        if !self.timestamps.set.is_empty() {
+            self.gl.delete_queries(&self.timestamps.set);
+        }
     }
 }
 
@@ -193,10 +266,41 @@ pub struct GpuProfiler {
 }
 
 impl GpuProfiler {
-    pub fn new(gl: Rc<dyn gl::Gl>, debug_method: GpuDebugMethod) -> Self {
+    pub fn new(
+        gl: Rc<dyn gl::Gl>,
+        debug_method: GpuDebugMethod,
+        supports_timer_queries: bool,
+    ) -> Self {
         let f = || GpuFrameProfile::new(Rc::clone(&gl), debug_method);
 
-        let frames = [f(), f(), f(), f()];
+        let mut frames = [f(), f(), f(), f()];
+
+        // GL_TIMESTAMP can only be queried if timer queries are supported.
+        let gpu_zones_id = if supports_timer_queries {
+            profiler::register_gpu_context(|| {
+                let mut gpu_time = [0];
+                unsafe {
+                    gl.get_integer_64v(gl::TIMESTAMP, &mut gpu_time);
+                }
+                gpu_time[0]
+            })
+        } else {
+            None
+        };
+
+        if let Some(id) = gpu_zones_id {
+            // Two timestamps per timer.
+            const MAX_TIMESTAMPS_PER_FRAME: i32 = 512;
+
+            let gpu_zones = Rc::new(GpuZoneContext {
+                id,
+                next_query_id: Cell::new(0),
+            });
+            for frame in &mut frames {
+                frame.enable_gpu_zones(Rc::clone(&gpu_zones), MAX_TIMESTAMPS_PER_FRAME);
+            }
+        }
+
         GpuProfiler {
             gl,
             next_frame: 0,
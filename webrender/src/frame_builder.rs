@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use api::{ColorF, DebugFlags, FontRenderMode, PremultipliedColorF};
+use api::{ColorF, DebugFlags, FontRenderMode, PremultipliedColorF, SubpixelTextPolicy};
 use api::units::*;
 use plane_split::BspSplitter;
 use crate::batch::{BatchBuilder, AlphaBatchBuilder, AlphaBatchContainer};
@@ -35,6 +35,7 @@ use crate::space::SpaceMapper;
 use crate::segment::SegmentBuilder;
 use crate::surface::SurfaceBuilder;
 use std::{f32, mem};
+use std::time::Duration;
 use crate::util::{VecHelper, Preallocator};
 use crate::visibility::{update_prim_visibility, FrameVisibilityState, FrameVisibilityContext};
 
@@ -44,6 +45,8 @@ use crate::visibility::{update_prim_visibility, FrameVisibilityState, FrameVisib
 pub struct FrameBuilderConfig {
     pub default_font_render_mode: FontRenderMode,
     pub dual_source_blending_is_supported: bool,
+    /// Set by the document before each frame is built.
+    pub subpixel_text_policy: SubpixelTextPolicy,
     /// True if we're running tests (i.e. via wrench).
     pub testing: bool,
     pub gpu_supports_fast_clears: bool,
@@ -61,9 +64,14 @@ pub struct FrameBuilderConfig {
     pub max_depth_ids: i32,
     pub max_target_size: i32,
     pub force_invalidation: bool,
+    /// Whether scene building collects the metrics reported by
+    /// `RenderApi::scene_metrics`.
+    pub scene_metrics: bool,
     pub is_software: bool,
     pub low_quality_pinch_zoom: bool,
     pub max_shared_surface_size: i32,
+    pub gpu_cache_reclaim_threshold: f32,
+    pub gpu_cache_reclaim_delay: Duration,
 }
 
 /// A set of common / global resources that are retained between
@@ -165,6 +173,9 @@ pub struct FrameBuildingState<'a> {
     pub dirty_region_stack: Vec<DirtyRegion>,
     pub composite_state: &'a mut CompositeState,
     pub num_visible_primitives: u32,
+    /// The number of text runs that asked for subpixel anti-aliasing but are
+    /// drawn with grayscale anti-aliasing.
+    pub num_subpixel_text_fallbacks: u32,
     pub plane_splitters: &'a mut [PlaneSplitter],
     pub surface_builder: SurfaceBuilder,
     pub cmd_buffers: &'a mut CommandBufferList,
@@ -270,6 +281,7 @@ impl FrameBuilder {
         data_stores: &mut DataStores,
         scratch: &mut ScratchBuffer,
         debug_flags: DebugFlags,
+        scroll_only: bool,
         composite_state: &mut CompositeState,
         tile_caches: &mut FastHashMap<SliceId, Box<TileCacheInstance>>,
         spatial_tree: &SpatialTree,
@@ -322,6 +334,9 @@ impl FrameBuilder {
             &frame_context,
         );
 
+        // The tile cache pictures whose tiles were kept from the last frame.
+        let mut retained_tile_caches = Vec::new();
+
         {
             profile_scope!("UpdateVisibility");
             profile_marker!("UpdateVisibility");
@@ -335,6 +350,7 @@ impl FrameBuilder {
                 scene_properties,
                 config: scene.config,
                 root_spatial_node_index,
+                scroll_only,
             };
 
             for pic_index in scene.tile_cache_pictures.iter().rev() {
@@ -358,16 +374,29 @@ impl FrameBuilder {
                             rg_builder,
                         };
 
-                        // If we have a tile cache for this picture, see if any of the
-                        // relative transforms have changed, which means we need to
-                        // re-map the dependencies of any child primitives.
+                        // When only the compositor transform of the slice changed, its
+                        // tiles are kept and none of its primitives need to be visited.
+                        // Otherwise, see if any of the relative transforms have changed,
+                        // which means we need to re-map the dependencies of any child
+                        // primitives.
                         let surface = &scene.surfaces[surface_index.0];
-                        let world_culling_rect = tile_cache.pre_update(
+                        let tiles_retained = tile_cache.update_scroll_only(
                             surface.unclipped_local_rect,
                             surface_index,
                             &visibility_context,
                             &mut visibility_state,
                         );
+                        let world_culling_rect = if tiles_retained {
+                            retained_tile_caches.push(*pic_index);
+                            WorldRect::zero()
+                        } else {
+                            tile_cache.pre_update(
+                                surface.unclipped_local_rect,
+                                surface_index,
+                                &visibility_context,
+                                &mut visibility_state,
+                            )
+                        };
 
                         // Push a new surface, supplying the list of clips that should be
                         // ignored, since they are handled by clipping when drawing this surface.
@@ -377,18 +406,20 @@ impl FrameBuilder {
                         );
                         visibility_state.clip_tree.push_clip_root_node(tile_cache.shared_clip_node_id);
 
-                        update_prim_visibility(
-                            *pic_index,
-                            None,
-                            &world_culling_rect,
-                            &mut scene.prim_store,
-                            &mut scene.prim_instances,
-                            &mut scene.surfaces,
-                            true,
-                            &visibility_context,
-                            &mut visibility_state,
-                            tile_cache,
-                        );
+                        if !tiles_retained {
+                            update_prim_visibility(
+                                *pic_index,
+                                None,
+                                &world_culling_rect,
+                                &mut scene.prim_store,
+                                &mut scene.prim_instances,
+                                &mut scene.surfaces,
+                                true,
+                                &visibility_context,
+                                &mut visibility_state,
+                                tile_cache,
+                            );
+                        }
 
                         // Build the dirty region(s) for this tile cache.
                         tile_cache.post_update(
@@ -409,6 +440,10 @@ impl FrameBuilder {
             profile.end_time(profiler::FRAME_VISIBILITY_TIME);
         }
 
+        if !retained_tile_caches.is_empty() && retained_tile_caches.len() == scene.tile_cache_pictures.len() {
+            profile.inc(profiler::SCROLL_ONLY_FRAMES);
+        }
+
         profile.start_time(profiler::FRAME_PREPARE_TIME);
 
         let mut frame_state = FrameBuildingState {
@@ -422,6 +457,7 @@ impl FrameBuilder {
             dirty_region_stack: scratch.frame.dirty_region_stack.take(),
             composite_state,
             num_visible_primitives: 0,
+            num_subpixel_text_fallbacks: 0,
             plane_splitters: &mut self.plane_splitters,
             surface_builder: SurfaceBuilder::new(),
             cmd_buffers,
@@ -442,6 +478,10 @@ impl FrameBuilder {
         frame_state.push_dirty_region(default_dirty_region);
 
         for pic_index in &scene.tile_cache_pictures {
+            // Retained tiles have nothing to draw, and the visibility of their
+            // primitives wasn't updated.
+            let tiles_retained = retained_tile_caches.contains(pic_index);
+
             if let Some((pic_context, mut pic_state, mut prim_list)) = scene
                 .prim_store
                 .pictures[pic_index.0]
@@ -457,18 +497,20 @@ impl FrameBuilder {
             {
                 profile_marker!("PreparePrims");
 
-                prepare_primitives(
-                    &mut scene.prim_store,
-                    &mut prim_list,
-                    &pic_context,
-                    &mut pic_state,
-                    &frame_context,
-                    &mut frame_state,
-                    data_stores,
-                    &mut scratch.primitive,
-                    tile_caches,
-                    &mut scene.prim_instances,
-                );
+                if !tiles_retained {
+                    prepare_primitives(
+                        &mut scene.prim_store,
+                        &mut prim_list,
+                        &pic_context,
+                        &mut pic_state,
+                        &frame_context,
+                        &mut frame_state,
+                        data_stores,
+                        &mut scratch.primitive,
+                        tile_caches,
+                        &mut scene.prim_instances,
+                    );
+                }
 
                 let pic = &mut scene.prim_store.pictures[pic_index.0];
                 pic.restore_context(
@@ -486,6 +528,7 @@ impl FrameBuilder {
         frame_state.surface_builder.finalize();
         profile.end_time(profiler::FRAME_PREPARE_TIME);
         profile.set(profiler::VISIBLE_PRIMITIVES, frame_state.num_visible_primitives);
+        profile.set(profiler::SUBPIXEL_TEXT_FALLBACKS, frame_state.num_subpixel_text_fallbacks);
 
         scratch.frame.dirty_region_stack = frame_state.dirty_region_stack.take();
 
@@ -514,6 +557,7 @@ impl FrameBuilder {
         tile_caches: &mut FastHashMap<SliceId, Box<TileCacheInstance>>,
         spatial_tree: &mut SpatialTree,
         dirty_rects_are_valid: bool,
+        scroll_only: bool,
         profile: &mut TransactionProfile,
     ) -> Frame {
         profile_scope!("build");
@@ -569,6 +613,7 @@ impl FrameBuilder {
             data_stores,
             scratch,
             debug_flags,
+            scroll_only,
             &mut composite_state,
             tile_caches,
             spatial_tree,
@@ -868,6 +913,7 @@ pub fn build_render_pass(
                                 segments,
                             );
                         });
+                        gpu_cache.set_validation_scope(None);
 
                         let batcher = batch_builder.finalize();
 
@@ -261,4 +261,9 @@ impl<T, M> FreeList<T, M> {
     pub fn len(&self) -> usize {
         self.active_count
     }
+
+    /// Iterate over the occupied slots, in storage order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().filter_map(|slot| slot.value.as_ref())
+    }
 }
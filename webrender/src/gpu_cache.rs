@@ -29,6 +29,7 @@ use api::{DebugFlags, DocumentId, PremultipliedColorF};
 use api::IdNamespace;
 use api::units::*;
 use euclid::{HomogeneousVector, Box2D};
+use crate::intern::ItemUid;
 use crate::internal_types::{FastHashMap, FastHashSet, FrameStamp, FrameId};
 use crate::profiler::{self, TransactionProfile};
 use crate::prim_store::VECS_PER_SEGMENT;
@@ -49,13 +50,13 @@ const NEW_ROWS_PER_RESIZE: i32 = 10;
 /// The number of frames an entry can go unused before being evicted.
 const FRAMES_BEFORE_EVICTION: u64 = 10;
 
-/// The ratio of utilized blocks to total blocks for which we start the clock
-/// on reclaiming memory.
-const RECLAIM_THRESHOLD: f32 = 0.2;
+/// The default ratio of utilized blocks to total blocks for which we start the
+/// clock on reclaiming memory.
+pub const DEFAULT_RECLAIM_THRESHOLD: f32 = 0.2;
 
-/// The amount of time utilization must be below the above threshold before we
-/// blow away the cache and rebuild it.
-const RECLAIM_DELAY_S: u64 = 5;
+/// The default amount of time utilization must be below the above threshold
+/// before we blow away the cache and rebuild it.
+pub const DEFAULT_RECLAIM_DELAY: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Copy, Clone, Eq, MallocSizeOf, PartialEq)]
 #[cfg_attr(feature = "capture", derive(Serialize))]
@@ -156,6 +157,14 @@ impl GpuCacheHandle {
     }
 }
 
+/// Identifies the primitive whose GPU cache data is being resolved, so that
+/// validation failures can be attributed to it.
+#[derive(Copy, Clone, Debug)]
+pub struct GpuCacheValidationScope {
+    pub kind: &'static str,
+    pub uid: ItemUid,
+}
+
 // A unique address in the GPU cache. These are uploaded
 // as part of the primitive instances, to allow the vertex
 // shader to fetch the specific data.
@@ -720,6 +729,13 @@ pub struct GpuCache {
     /// The set of documents which have had frames built in this update. Used for
     /// sanity checks.
     document_frames_to_build: FastHashSet<DocumentId>,
+    /// The primitive currently being batched, when GPU cache validation is enabled.
+    #[ignore_malloc_size_of = "debug only"]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    validation_scope: Option<GpuCacheValidationScope>,
+    /// See `set_reclaim_policy`.
+    reclaim_threshold: f32,
+    reclaim_delay: Duration,
 }
 
 impl GpuCache {
@@ -734,9 +750,20 @@ impl GpuCache {
             prepared_for_frames: false,
             requires_frame_build: false,
             document_frames_to_build: FastHashSet::default(),
+            validation_scope: None,
+            reclaim_threshold: DEFAULT_RECLAIM_THRESHOLD,
+            reclaim_delay: DEFAULT_RECLAIM_DELAY,
         }
     }
 
+    /// Sets the utilization ratio under which the cache is considered for
+    /// reclaiming, and how long utilization must stay under it before the
+    /// cache is cleared and rebuilt.
+    pub fn set_reclaim_policy(&mut self, threshold: f32, delay: Duration) {
+        self.reclaim_threshold = threshold;
+        self.reclaim_delay = delay;
+    }
+
     /// Creates a GpuCache and sets it up with a valid `FrameStamp`, which
     /// is useful for avoiding panics when instantiating the `GpuCache`
     /// directly from unit test code.
@@ -876,7 +903,7 @@ impl GpuCache {
 
         let reached_threshold =
             self.texture.rows.len() > (GPU_CACHE_INITIAL_HEIGHT as usize) &&
-            self.texture.utilization() < RECLAIM_THRESHOLD;
+            self.texture.utilization() < self.reclaim_threshold;
         if reached_threshold {
             self.texture.reached_reclaim_threshold.get_or_insert_with(Instant::now);
         } else {
@@ -891,7 +918,7 @@ impl GpuCache {
     /// should blow the cache away and rebuild it.
     pub fn should_reclaim_memory(&self) -> bool {
         self.texture.reached_reclaim_threshold
-            .map_or(false, |t| t.elapsed() > Duration::from_secs(RECLAIM_DELAY_S))
+            .map_or(false, |t| t.elapsed() > self.reclaim_delay)
     }
 
     /// Extract the pending updates from the cache.
@@ -914,12 +941,47 @@ impl GpuCache {
         self.texture.debug_flags = flags;
     }
 
+    /// Set the primitive that subsequent `get_address` calls are made on behalf
+    /// of, when GPU cache validation is enabled.
+    pub fn set_validation_scope(&mut self, scope: Option<GpuCacheValidationScope>) {
+        if self.debug_flags.contains(DebugFlags::GPU_CACHE_VALIDATION) {
+            self.validation_scope = scope;
+        }
+    }
+
+    /// Returns a description of the problem if the block referenced by the
+    /// location can't be sampled this frame.
+    fn validate(&self, location: &CacheLocation) -> Option<&'static str> {
+        let block = self.texture.blocks.get(location.block_index.get())?;
+        if block.epoch != location.epoch {
+            return Some("stale handle (the block was freed or invalidated)");
+        }
+        if block.last_access_time != self.now.frame_id() {
+            return Some("handle not requested this frame (the block may be evicted)");
+        }
+
+        None
+    }
+
     /// Get the actual GPU address in the texture for a given slot ID.
     /// It's assumed at this point that the given slot has been requested
     /// and built for this frame. Attempting to get the address for a
-    /// freed or pending slot will panic!
+    /// freed or pending slot will panic, unless GPU cache validation is
+    /// enabled in which case the error is reported and an invalid address
+    /// is returned.
     pub fn get_address(&self, id: &GpuCacheHandle) -> GpuCacheAddress {
         let location = id.location.expect("handle not requested or allocated!");
+        if self.debug_flags.contains(DebugFlags::GPU_CACHE_VALIDATION) {
+            if let Some(error) = self.validate(&location) {
+                error!(
+                    "GPU cache validation: {} in frame {:?}, primitive {:?}",
+                    error,
+                    self.now.frame_id(),
+                    self.validation_scope,
+                );
+                return GpuCacheAddress::INVALID;
+            }
+        }
         let block = &self.texture.blocks[location.block_index.get()];
         debug_assert_eq!(block.epoch, location.epoch);
         debug_assert_eq!(block.last_access_time, self.now.frame_id());
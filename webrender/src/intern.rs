@@ -28,6 +28,12 @@
 //! free-list structure, for content access and memory
 //! usage efficiency.
 //!
+//! The data store also records the epoch each item was
+//! interned in, which handles carry too, to detect a
+//! handle that outlived its item when another item
+//! reuses its slot. `DataStore::get` always checks it,
+//! indexing the data store only in debug builds.
+//!
 //! The epoch is incremented each time a scene is
 //! built. The most recently used scene epoch is
 //! stored inside each handle. This is then used for
@@ -107,6 +113,11 @@ impl ItemUid {
     pub fn get_uid(&self) -> u64 {
         self.uid
     }
+
+    /// The epoch the item was interned in, see `Handle::uid`.
+    fn epoch(&self) -> Epoch {
+        Epoch(self.uid as u32)
+    }
 }
 
 #[cfg_attr(feature = "capture", derive(Serialize))]
@@ -148,17 +159,23 @@ pub trait InternDebug {
 
 /// The data store lives in the frame builder thread. It
 /// contains a free-list of items for fast access.
+///
+/// The epochs the items were interned in are kept in a
+/// separate array, so that they don't add padding to the
+/// (frequently accessed) items.
 #[cfg_attr(feature = "capture", derive(Serialize))]
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 #[derive(MallocSizeOf)]
 pub struct DataStore<I: Internable> {
     items: Vec<Option<I::StoreData>>,
+    epochs: Vec<Epoch>,
 }
 
 impl<I: Internable> Default for DataStore<I> {
     fn default() -> Self {
         DataStore {
             items: Vec::new(),
+            epochs: Vec::new(),
         }
     }
 }
@@ -171,33 +188,68 @@ impl<I: Internable> DataStore<I> {
         update_list: UpdateList<I::Key>,
         profile: &mut TransactionProfile,
     ) {
+        // Grow the slots once for all the new items.
+        if let Some(max_index) = update_list.insertions.iter().map(|insertion| insertion.index).max() {
+            if max_index >= self.items.len() {
+                self.items.reserve(max_index + 1 - self.items.len());
+                self.epochs.reserve(max_index + 1 - self.epochs.len());
+            }
+        }
+
         for insertion in update_list.insertions {
             self.items
                 .entry(insertion.index)
                 .set(Some(insertion.value.into()));
+            self.epochs
+                .entry(insertion.index)
+                .set(insertion.uid.epoch());
         }
 
         for removal in update_list.removals {
+            debug_assert_eq!(self.epochs[removal.index], removal.uid.epoch());
             self.items[removal.index] = None;
         }
 
         profile.set(I::PROFILE_COUNTER, self.items.len());
     }
+
+    /// Retrieve an item from the store via handle, or None if the
+    /// item of the handle has been removed (even if its slot has
+    /// been reused since).
+    pub fn get(&self, handle: Handle<I>) -> Option<&I::StoreData> {
+        let index = handle.index as usize;
+        if self.epochs.get(index) != Some(&handle.epoch) {
+            return None;
+        }
+        self.items[index].as_ref()
+    }
+
+    /// Retrieve a mutable item from the store via handle, see `get`.
+    pub fn get_mut(&mut self, handle: Handle<I>) -> Option<&mut I::StoreData> {
+        let index = handle.index as usize;
+        if self.epochs.get(index) != Some(&handle.epoch) {
+            return None;
+        }
+        self.items[index].as_mut()
+    }
 }
 
 /// Retrieve an item from the store via handle
 impl<I: Internable> ops::Index<Handle<I>> for DataStore<I> {
     type Output = I::StoreData;
     fn index(&self, handle: Handle<I>) -> &I::StoreData {
-        self.items[handle.index as usize].as_ref().expect("Bad datastore lookup")
+        let index = handle.index as usize;
+        debug_assert_eq!(self.epochs[index], handle.epoch, "Bad datastore lookup: stale handle");
+        self.items[index].as_ref().expect("Bad datastore lookup")
     }
 }
 
 /// Retrieve a mutable item from the store via handle
-/// Retrieve an item from the store via handle
 impl<I: Internable> ops::IndexMut<Handle<I>> for DataStore<I> {
     fn index_mut(&mut self, handle: Handle<I>) -> &mut I::StoreData {
-        self.items[handle.index as usize].as_mut().expect("Bad datastore lookup")
+        let index = handle.index as usize;
+        debug_assert_eq!(self.epochs[index], handle.epoch, "Bad datastore lookup: stale handle");
+        self.items[index].as_mut().expect("Bad datastore lookup")
     }
 }
 
@@ -207,8 +259,6 @@ impl<I: Internable> ops::IndexMut<Handle<I>> for DataStore<I> {
 struct ItemDetails<I> {
     /// Frame that this element was first interned
     interned_epoch: Epoch,
-    /// Last frame this element was referenced (used to GC intern items)
-    last_used_epoch: Epoch,
     /// Index into the freelist this item is located
     index: usize,
     /// Type marker for create_handle method
@@ -246,6 +296,14 @@ pub struct Interner<I: Internable> {
     /// The information associated with each interned
     /// item that can be accessed by the interner.
     local_data: Vec<I::InternData>,
+    /// Last frame each item was referenced (used to GC intern
+    /// items), kept out of the map so that items found by
+    /// `lookup` can be marked used without hashing them again.
+    last_used_epochs: Vec<Epoch>,
+    /// The number of lookups of already interned items since the
+    /// last call to `end_frame_and_get_pending_updates`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    hits: usize,
 }
 
 impl<I: Internable> Default for Interner<I> {
@@ -256,6 +314,8 @@ impl<I: Internable> Default for Interner<I> {
             update_list: UpdateList::new(),
             current_epoch: Epoch(1),
             local_data: Vec::new(),
+            last_used_epochs: Vec::new(),
+            hits: 0,
         }
     }
 }
@@ -276,9 +336,10 @@ impl<I: Internable> Interner<I> {
         // Use get_mut rather than entry here to avoid
         // cloning the (sometimes large) key in the common
         // case, where the data already exists in the interner.
-        if let Some(details) = self.map.get_mut(data) {
+        if let Some(details) = self.map.get(data) {
             // Update the last referenced frame for this element
-            details.last_used_epoch = self.current_epoch;
+            self.last_used_epochs[details.index] = self.current_epoch;
+            self.hits += 1;
             // Return a stable handle value for dependency checking
             return details.create_handle();
         }
@@ -314,10 +375,10 @@ impl<I: Internable> Interner<I> {
         // interned, it gets re-used.
         self.map.insert(data.clone(), ItemDetails {
             interned_epoch: self.current_epoch,
-            last_used_epoch: self.current_epoch,
             index,
             _marker: PhantomData,
         });
+        self.last_used_epochs.entry(index).set(self.current_epoch);
 
         // Create the local data for this item that is
         // being interned.
@@ -326,13 +387,45 @@ impl<I: Internable> Interner<I> {
         handle
     }
 
+    /// Find the handle of an item that is already interned,
+    /// without marking it used. This only reads the interner,
+    /// so that the items of a scene can be looked up on several
+    /// threads before they are interned. The handle stays valid
+    /// until `end_frame_and_get_pending_updates` is called, and
+    /// must be passed to `mark_used` to keep the item alive.
+    pub fn lookup(
+        &self,
+        data: &I::Key,
+    ) -> Option<Handle<I>> {
+        self.map.get(data).map(ItemDetails::create_handle)
+    }
+
+    /// Mark an item found by `lookup` as used, as `intern`
+    /// does for the items it finds.
+    pub fn mark_used(
+        &mut self,
+        handle: Handle<I>,
+    ) {
+        let index = handle.index as usize;
+        self.last_used_epochs[index] = self.current_epoch;
+        self.hits += 1;
+    }
+
+    /// Returns the number of lookups that found an existing item and the
+    /// number of items that were newly interned in the current epoch.
+    pub fn lookup_stats(&self) -> (usize, usize) {
+        (self.hits, self.update_list.insertions.len())
+    }
+
     /// Retrieve the pending list of updates for an interner
     /// that need to be applied to the data store. Also run
     /// a GC step that removes old entries.
     pub fn end_frame_and_get_pending_updates(&mut self) -> UpdateList<I::Key> {
         let mut update_list = self.update_list.take_and_preallocate();
+        self.hits = 0;
 
         let free_list = &mut self.free_list;
+        let last_used_epochs = &self.last_used_epochs;
         let current_epoch = self.current_epoch.0;
 
         // First, run a GC step. Walk through the handles, and
@@ -343,7 +436,7 @@ impl<I: Internable> Interner<I> {
         // future to adjust how long items remain in the cache
         // based on the current size of the list.
         self.map.retain(|_, details| {
-            if details.last_used_epoch.0 + 10 < current_epoch {
+            if last_used_epochs[details.index].0 + 10 < current_epoch {
                 // To expire an item:
                 //  - Add index to the free-list for re-use.
                 //  - Add an update to the data store to invalidate this slot.
@@ -391,6 +484,7 @@ macro_rules! enumerate_interners {
             image: Image,
             yuv_image: YuvImage,
             line_decoration: LineDecoration,
+            line_skip_ink: LineSkipInk,
             linear_grad: LinearGradient,
             radial_grad: RadialGradient,
             conic_grad: ConicGradient,
@@ -400,6 +494,7 @@ macro_rules! enumerate_interners {
             backdrop_capture: BackdropCapture,
             backdrop_render: BackdropRender,
             polygon: PolygonIntern,
+            vector_path: VectorPath,
         }
     }
 }
@@ -468,3 +563,116 @@ pub trait Internable: MallocSizeOf {
     // Profile counter indices, see the list in profiler.rs
     const PROFILE_COUNTER: usize;
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg_attr(feature = "capture", derive(Serialize))]
+    #[cfg_attr(feature = "replay", derive(Deserialize))]
+    #[derive(Clone, Debug, Hash, MallocSizeOf, PartialEq, Eq)]
+    struct TestKey(u32);
+
+    impl InternDebug for TestKey {}
+
+    #[derive(MallocSizeOf)]
+    enum TestIntern {}
+
+    impl Internable for TestIntern {
+        type Key = TestKey;
+        type StoreData = TestKey;
+        type InternData = ();
+        const PROFILE_COUNTER: usize = crate::profiler::INTERNED_PRIMITIVES;
+    }
+
+    fn end_frame(interner: &mut Interner<TestIntern>, store: &mut DataStore<TestIntern>) {
+        let updates = interner.end_frame_and_get_pending_updates();
+        store.apply_updates(updates, &mut TransactionProfile::new());
+    }
+
+    #[test]
+    fn stale_handles_are_detected() {
+        let mut interner = Interner::<TestIntern>::default();
+        let mut store = DataStore::<TestIntern>::default();
+
+        let old = interner.intern(&TestKey(1), || ());
+        end_frame(&mut interner, &mut store);
+        assert_eq!(store.get(old), Some(&TestKey(1)));
+
+        // Leave the item unused until it's garbage collected, then intern
+        // another item, which reuses its slot.
+        for _ in 0 .. 11 {
+            end_frame(&mut interner, &mut store);
+        }
+        assert_eq!(store.get(old), None);
+
+        let new = interner.intern(&TestKey(2), || ());
+        end_frame(&mut interner, &mut store);
+        assert_eq!(new.index, old.index);
+        assert_ne!(new.uid(), old.uid());
+
+        assert_eq!(store.get(new), Some(&TestKey(2)));
+        assert_eq!(store.get(old), None);
+        assert!(store.get_mut(old).is_none());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Bad datastore lookup")]
+    fn indexing_with_a_stale_handle_panics() {
+        let mut interner = Interner::<TestIntern>::default();
+        let mut store = DataStore::<TestIntern>::default();
+
+        let old = interner.intern(&TestKey(1), || ());
+        for _ in 0 .. 12 {
+            end_frame(&mut interner, &mut store);
+        }
+        interner.intern(&TestKey(2), || ());
+        end_frame(&mut interner, &mut store);
+
+        let _ = &store[old];
+    }
+
+    #[test]
+    fn handles_to_live_items_are_stable() {
+        let mut interner = Interner::<TestIntern>::default();
+        let mut store = DataStore::<TestIntern>::default();
+
+        let a = interner.intern(&TestKey(1), || ());
+        let b = interner.intern(&TestKey(2), || ());
+        end_frame(&mut interner, &mut store);
+
+        // Keep using the first item only.
+        for _ in 0 .. 12 {
+            assert_eq!(interner.intern(&TestKey(1), || ()).uid(), a.uid());
+            end_frame(&mut interner, &mut store);
+        }
+
+        assert_eq!(store[a], TestKey(1));
+        assert_eq!(store.get(b), None);
+    }
+
+    #[test]
+    fn looked_up_items_are_kept_when_marked_used() {
+        let mut interner = Interner::<TestIntern>::default();
+        let mut store = DataStore::<TestIntern>::default();
+
+        let a = interner.intern(&TestKey(1), || ());
+        let b = interner.intern(&TestKey(2), || ());
+        end_frame(&mut interner, &mut store);
+        assert!(interner.lookup(&TestKey(3)).is_none());
+
+        // Look both items up, but only mark the first one used.
+        for _ in 0 .. 12 {
+            let handle = interner.lookup(&TestKey(1)).unwrap();
+            assert_eq!(handle.uid(), a.uid());
+            interner.mark_used(handle);
+            interner.lookup(&TestKey(2));
+            end_frame(&mut interner, &mut store);
+        }
+
+        assert_eq!(store[a], TestKey(1));
+        assert_eq!(store.get(b), None);
+        assert!(interner.lookup(&TestKey(2)).is_none());
+    }
+}
@@ -695,7 +695,21 @@ pub struct RenderedDocument {
     pub is_new_scene: bool,
     pub profile: TransactionProfile,
     pub render_reasons: RenderReasons,
-    pub frame_stats: Option<FullFrameStats>
+    pub frame_stats: Option<FullFrameStats>,
+    /// Labels of the transactions that contributed to this frame.
+    pub transaction_labels: Vec<String>,
+    /// The oldest frame request this frame answers, if any.
+    pub frame_request: Option<FrameRequestTimes>,
+}
+
+/// Timing of a `generate_frame` request, used to measure frame latency.
+#[derive(Copy, Clone, Debug)]
+pub struct FrameRequestTimes {
+    /// When the frame was requested, in nanoseconds.
+    pub request_time: u64,
+    /// How long the request waited in the render backend before the frame
+    /// started building, in nanoseconds.
+    pub backend_queue_time: u64,
 }
 
 pub enum DebugOutput {
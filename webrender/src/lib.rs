@@ -130,10 +130,13 @@ mod image_tiling;
 mod image_source;
 mod rectangle_occlusion;
 mod picture_textures;
+mod path_rasterizer;
 
 ///
 pub mod intern;
 ///
+pub mod recording;
+///
 pub mod render_api;
 
 pub mod shader_source {
@@ -169,13 +172,17 @@ pub use crate::device::{UploadMethod, VertexUsageHint, get_gl_target, get_unopti
 pub use crate::device::{ProgramBinary, ProgramCache, ProgramCacheObserver, FormatDesc};
 pub use crate::device::Device;
 pub use crate::profiler::{ProfilerHooks, set_profiler_hooks};
+pub use crate::profiler::{CounterExportFormat, CounterSample, FrameCounters, RenderReasonStats};
 pub use crate::renderer::{
     CpuProfile, DebugFlags, GpuProfile, GraphicsApi,
-    GraphicsApiInfo, PipelineInfo, Renderer, RendererError, RenderResults,
+    GraphicsApiInfo, EmbedderDrawHandler, EmbedderDrawParams, Magnification, PathRenderer, PathRenderParams,
+    PipelineInfo, Renderer, RendererError, RenderResults,
     RendererStats, Shaders, SharedShaders, ShaderPrecacheFlags,
-    MAX_VERTEX_TEXTURE_WIDTH,
+    VideoFrameReport, MAX_VERTEX_TEXTURE_WIDTH,
 };
 pub use crate::renderer::init::{WebRenderOptions, create_webrender_instance, AsyncPropertySampler, SceneBuilderHooks, ONE_TIME_USAGE_HINT};
+pub use crate::renderer::init::{WebRenderOptionsBuilder, OptionsError, OptionDowngrade, EffectiveOptions, SharedCaches};
+pub use crate::renderer::init::{ThreadConfig, ThreadConfigurator, ThreadPriority, WebRenderThread};
 pub use crate::hit_test::SharedHitTester;
 pub use crate::internal_types::FastHashMap;
 pub use crate::screen_capture::{AsyncScreenshotHandle, RecordedFrameHandle};
@@ -189,6 +196,10 @@ pub use crate::intern::ItemUid;
 pub use crate::render_api::*;
 pub use crate::tile_cache::{PictureCacheDebugInfo, DirtyTileDebugInfo, TileDebugInfo, SliceDebugInfo};
 pub use glyph_rasterizer;
+pub use glyph_rasterizer::GlyphBackend;
+pub use glyph_rasterizer::profiler::GlyphTelemetrySink;
+#[cfg(feature = "font_backend_swash")]
+pub use glyph_rasterizer::backend::swash::system_fonts::{SystemFont, SystemFontData, SystemFontStyle, SystemFonts};
 
 #[cfg(feature = "sw_compositor")]
 pub use crate::compositor::sw_compositor;
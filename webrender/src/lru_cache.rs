@@ -75,6 +75,11 @@ impl<T, M> LRUCache<T, M> {
         }
     }
 
+    /// Iterate over all elements in the cache, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.entries.iter().map(|entry| &entry.value)
+    }
+
     /// Insert a new element into the cache. Returns a weak handle for callers to
     /// access the data, since the lifetime is managed by the LRU algorithm and it
     /// may be evicted at any time.
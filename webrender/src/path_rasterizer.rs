@@ -0,0 +1,531 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! CPU rasterization of vector path coverage, for `VectorPathDisplayItem`s.
+//!
+//! Paths are flattened to polylines, strokes are turned in to polygons, and
+//! the polygons are accumulated in to an anti-aliased coverage mask using
+//! signed area accumulation.
+
+use api::{FillRule, LineCap, LineJoin, PathOp, StrokeStyle};
+use euclid::default::{Point2D, Vector2D};
+use std::f32::consts::PI;
+
+type Point = Point2D<f32>;
+type Vector = Vector2D<f32>;
+
+/// The maximum distance, in pixels, between a curve and the lines it is
+/// flattened to.
+const TOLERANCE: f32 = 0.2;
+
+/// A flattened contour of a path.
+struct Contour {
+    points: Vec<Point>,
+    closed: bool,
+    /// Whether any segments were added, contours of a single point are only
+    /// stroked if they have a zero length segment.
+    has_segments: bool,
+}
+
+/// Rasterizes the coverage of a path in to a `width` x `height` mask of one
+/// byte per pixel. Path coordinates are multiplied by `scale` to get pixels.
+pub fn rasterize_path(
+    ops: &[PathOp],
+    fill_rule: FillRule,
+    stroke: Option<&StrokeStyle>,
+    scale: Vector,
+    width: usize,
+    height: usize,
+) -> Vec<u8> {
+    let mut accumulator = Accumulator::new(width, height);
+    let tolerance = TOLERANCE / scale.x.max(scale.y);
+
+    match stroke {
+        None => {
+            for contour in flatten(ops, tolerance) {
+                accumulator.add_polygon(&contour.points, scale);
+            }
+            accumulator.coverage(fill_rule)
+        }
+        Some(stroke) => {
+            // Strokes are built in local space so that the stroke width is
+            // scaled along with the path.
+            let mut polygons = Vec::new();
+            for contour in flatten(ops, tolerance) {
+                stroke_contour(&contour, stroke, tolerance, &mut polygons);
+            }
+            for polygon in &polygons {
+                accumulator.add_polygon(polygon, scale);
+            }
+            // All stroke polygons wind the same way, so overlaps add up.
+            accumulator.coverage(FillRule::Nonzero)
+        }
+    }
+}
+
+fn flatten(ops: &[PathOp], tolerance: f32) -> Vec<Contour> {
+    let mut contours = Vec::new();
+    let mut current: Option<Contour> = None;
+    // Where a contour without a `MoveTo` starts.
+    let mut start = Point::zero();
+
+    for op in ops {
+        if let PathOp::MoveTo(p) = *op {
+            contours.extend(current.take());
+            current = Some(Contour {
+                points: vec![p.to_untyped()],
+                closed: false,
+                has_segments: false,
+            });
+            continue;
+        }
+
+        if let PathOp::Close = *op {
+            if let Some(mut contour) = current.take() {
+                start = contour.points[0];
+                contour.closed = true;
+                if contour.points.len() > 1 && contour.points.last() == Some(&start) {
+                    contour.points.pop();
+                }
+                contours.push(contour);
+            }
+            continue;
+        }
+
+        let contour = current.get_or_insert_with(|| Contour {
+            points: vec![start],
+            closed: false,
+            has_segments: false,
+        });
+        contour.has_segments = true;
+        let p0 = *contour.points.last().unwrap();
+        let mut push_point = |point: Point| {
+            if contour.points.last() != Some(&point) {
+                contour.points.push(point);
+            }
+        };
+
+        match *op {
+            PathOp::LineTo(p) => {
+                push_point(p.to_untyped());
+            }
+            PathOp::QuadTo(c, p) => {
+                let (p0, c, p) = (p0.to_vector(), c.to_untyped().to_vector(), p.to_untyped().to_vector());
+                let count = segment_count((p0 - c * 2.0 + p).length() * 0.25, tolerance);
+                for i in 1 ..= count {
+                    let t = i as f32 / count as f32;
+                    let mt = 1.0 - t;
+                    let point = p0 * (mt * mt) + c * (2.0 * mt * t) + p * (t * t);
+                    push_point(point.to_point());
+                }
+            }
+            PathOp::CubicTo(c1, c2, p) => {
+                let (p0, c1, c2, p) = (
+                    p0.to_vector(),
+                    c1.to_untyped().to_vector(),
+                    c2.to_untyped().to_vector(),
+                    p.to_untyped().to_vector(),
+                );
+                let dd = (p0 - c1 * 2.0 + c2).length().max((c1 - c2 * 2.0 + p).length());
+                let count = segment_count(dd * 0.75, tolerance);
+                for i in 1 ..= count {
+                    let t = i as f32 / count as f32;
+                    let mt = 1.0 - t;
+                    let point = p0 * (mt * mt * mt) +
+                        c1 * (3.0 * mt * mt * t) +
+                        c2 * (3.0 * mt * t * t) +
+                        p * (t * t * t);
+                    push_point(point.to_point());
+                }
+            }
+            PathOp::MoveTo(..) | PathOp::Close => unreachable!(),
+        }
+    }
+
+    contours.extend(current.take());
+    contours.retain(|contour| contour.has_segments);
+    contours
+}
+
+/// The number of lines a curve is flattened to, given the length of its
+/// second derivative scaled by the curve's degree.
+fn segment_count(dd: f32, tolerance: f32) -> usize {
+    ((dd / tolerance).sqrt().ceil() as usize).max(1).min(100)
+}
+
+/// Appends polygons covering the stroke of a contour, all wound clockwise.
+fn stroke_contour(
+    contour: &Contour,
+    stroke: &StrokeStyle,
+    tolerance: f32,
+    polygons: &mut Vec<Vec<Point>>,
+) {
+    let half_width = stroke.width * 0.5;
+    if !(half_width > 0.0) {
+        return;
+    }
+
+    let points = &contour.points;
+    if points.len() < 2 {
+        // Like canvas, zero length contours are only drawn with round caps.
+        if !contour.closed && stroke.cap == LineCap::Round {
+            if let Some(&point) = points.first() {
+                polygons.push(circle(point, half_width, tolerance));
+            }
+        }
+        return;
+    }
+
+    let segment_count = if contour.closed { points.len() } else { points.len() - 1 };
+    for i in 0 .. segment_count {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        let normal = normal(b - a) * half_width;
+        push_polygon(polygons, vec![a + normal, b + normal, b - normal, a - normal]);
+    }
+
+    let joins = if contour.closed { 0 .. points.len() } else { 1 .. points.len() - 1 };
+    for i in joins {
+        let prev = points[(i + points.len() - 1) % points.len()];
+        let point = points[i];
+        let next = points[(i + 1) % points.len()];
+        add_join(point, point - prev, next - point, stroke, half_width, tolerance, polygons);
+    }
+
+    if !contour.closed {
+        let last = points.len() - 1;
+        add_cap(points[0], points[0] - points[1], stroke.cap, half_width, tolerance, polygons);
+        add_cap(points[last], points[last] - points[last - 1], stroke.cap, half_width, tolerance, polygons);
+    }
+}
+
+fn add_join(
+    point: Point,
+    d0: Vector,
+    d1: Vector,
+    stroke: &StrokeStyle,
+    half_width: f32,
+    tolerance: f32,
+    polygons: &mut Vec<Vec<Point>>,
+) {
+    let cross = d0.cross(d1);
+    if cross.abs() <= f32::EPSILON * d0.length() * d1.length() && d0.dot(d1) > 0.0 {
+        // The segments are collinear, there is no gap to fill.
+        return;
+    }
+
+    if stroke.join == LineJoin::Round {
+        polygons.push(circle(point, half_width, tolerance));
+        return;
+    }
+
+    // The gap to fill is on the outside of the turn.
+    let side = if cross > 0.0 { -1.0 } else { 1.0 };
+    let n0 = normal(d0) * (half_width * side);
+    let n1 = normal(d1) * (half_width * side);
+
+    if stroke.join == LineJoin::Miter {
+        let bisector = (n0 + n1).normalize();
+        let cos_half_angle = bisector.dot(n0) / half_width;
+        if cos_half_angle > 0.0 && 1.0 / cos_half_angle <= stroke.miter_limit {
+            let miter = bisector * (half_width / cos_half_angle);
+            push_polygon(polygons, vec![point, point + n0, point + miter, point + n1]);
+            return;
+        }
+    }
+
+    push_polygon(polygons, vec![point, point + n0, point + n1]);
+}
+
+/// Adds the cap at the end `point` of an open contour, whose last segment
+/// points away from the contour along `direction`.
+fn add_cap(
+    point: Point,
+    direction: Vector,
+    cap: LineCap,
+    half_width: f32,
+    tolerance: f32,
+    polygons: &mut Vec<Vec<Point>>,
+) {
+    match cap {
+        LineCap::Butt => {}
+        LineCap::Round => {
+            polygons.push(circle(point, half_width, tolerance));
+        }
+        LineCap::Square => {
+            let normal = normal(direction) * half_width;
+            let extent = direction.normalize() * half_width;
+            push_polygon(polygons, vec![
+                point + normal,
+                point + normal + extent,
+                point - normal + extent,
+                point - normal,
+            ]);
+        }
+    }
+}
+
+/// The unit vector perpendicular to `v`.
+fn normal(v: Vector) -> Vector {
+    let v = v.normalize();
+    Vector::new(-v.y, v.x)
+}
+
+fn circle(center: Point, radius: f32, tolerance: f32) -> Vec<Point> {
+    let step = 2.0 * (1.0 - (tolerance / radius).min(1.0)).acos();
+    let count = ((2.0 * PI / step).ceil() as usize).max(8).min(256);
+    (0 .. count)
+        .map(|i| {
+            let angle = i as f32 * 2.0 * PI / count as f32;
+            center + Vector::new(angle.cos(), angle.sin()) * radius
+        })
+        .collect()
+}
+
+/// Pushes a polygon, reversed if needed so that it winds clockwise.
+fn push_polygon(polygons: &mut Vec<Vec<Point>>, mut polygon: Vec<Point>) {
+    let mut area = 0.0f32;
+    for i in 0 .. polygon.len() {
+        let a = polygon[i];
+        let b = polygon[(i + 1) % polygon.len()];
+        area += a.x * b.y - b.x * a.y;
+    }
+    if !area.is_finite() {
+        return;
+    }
+    if area < 0.0 {
+        polygon.reverse();
+    }
+    polygons.push(polygon);
+}
+
+/// Accumulates the signed area covered by lines in each pixel, the running
+/// sum of which along a row is the winding number of the pixels.
+struct Accumulator {
+    width: usize,
+    height: usize,
+    /// Two extra values per row take the contributions of lines on or past
+    /// the right edge.
+    stride: usize,
+    values: Vec<f32>,
+}
+
+impl Accumulator {
+    fn new(width: usize, height: usize) -> Self {
+        let stride = width + 2;
+        Accumulator {
+            width,
+            height,
+            stride,
+            values: vec![0.0; stride * height],
+        }
+    }
+
+    fn add_polygon(&mut self, points: &[Point], scale: Vector) {
+        if points.len() < 2 {
+            return;
+        }
+        let to_pixels = |p: Point| Point::new(p.x * scale.x, p.y * scale.y);
+        for i in 0 .. points.len() {
+            let a = to_pixels(points[i]);
+            let b = to_pixels(points[(i + 1) % points.len()]);
+            self.add_line(a, b);
+        }
+    }
+
+    /// Splits the line where it crosses the left and right edges and moves
+    /// the parts outside on to the edges, where their winding still counts.
+    fn add_line(&mut self, p0: Point, p1: Point) {
+        if !(p0.x.is_finite() && p0.y.is_finite() && p1.x.is_finite() && p1.y.is_finite()) {
+            return;
+        }
+
+        let right = self.width as f32;
+        let mut splits = [0.0, 1.0, 1.0, 1.0];
+        let mut count = 1;
+        for &edge in &[0.0, right] {
+            let t = (edge - p0.x) / (p1.x - p0.x);
+            if t > 0.0 && t < 1.0 {
+                splits[count] = t;
+                count += 1;
+            }
+        }
+        splits[count] = 1.0;
+        splits[.. count + 1].sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let clamp = |p: Point| Point::new(p.x.max(0.0).min(right), p.y);
+        for i in 0 .. count {
+            let a = p0.lerp(p1, splits[i]);
+            let b = p0.lerp(p1, splits[i + 1]);
+            self.add_clamped_line(clamp(a), clamp(b));
+        }
+    }
+
+    fn add_clamped_line(&mut self, p0: Point, p1: Point) {
+        if p0.y == p1.y {
+            return;
+        }
+        let (dir, p0, p1) = if p0.y < p1.y { (1.0, p0, p1) } else { (-1.0, p1, p0) };
+        let dxdy = (p1.x - p0.x) / (p1.y - p0.y);
+        // Keeps rounding errors from stepping past the edges.
+        let right = self.width as f32;
+        let clamp = |x: f32| x.max(0.0).min(right);
+
+        let mut x = p0.x;
+        if p0.y < 0.0 {
+            x = clamp(x - p0.y * dxdy);
+        }
+
+        let y_start = p0.y.max(0.0) as usize;
+        let y_end = (p1.y.ceil().max(0.0) as usize).min(self.height);
+        for y in y_start .. y_end {
+            let row = &mut self.values[y * self.stride .. (y + 1) * self.stride];
+            let dy = ((y + 1) as f32).min(p1.y) - (y as f32).max(p0.y);
+            let x_next = clamp(x + dxdy * dy);
+            let d = dy * dir;
+            let (x0, x1) = if x < x_next { (x, x_next) } else { (x_next, x) };
+            let x0_floor = x0.floor();
+            let x0i = x0_floor as usize;
+            let x1_ceil = x1.ceil();
+            let x1i = x1_ceil as usize;
+
+            if x1i <= x0i + 1 {
+                // The line stays within one pixel of this row.
+                let xmf = 0.5 * (x + x_next) - x0_floor;
+                row[x0i] += d - d * xmf;
+                row[x0i + 1] += d * xmf;
+            } else {
+                let s = (x1 - x0).recip();
+                let x0f = x0 - x0_floor;
+                let a0 = 0.5 * s * (1.0 - x0f) * (1.0 - x0f);
+                let x1f = x1 - x1_ceil + 1.0;
+                let am = 0.5 * s * x1f * x1f;
+                row[x0i] += d * a0;
+                if x1i == x0i + 2 {
+                    row[x0i + 1] += d * (1.0 - a0 - am);
+                } else {
+                    let a1 = s * (1.5 - x0f);
+                    row[x0i + 1] += d * (a1 - a0);
+                    for value in &mut row[x0i + 2 .. x1i - 1] {
+                        *value += d * s;
+                    }
+                    let a2 = a1 + (x1i - x0i - 3) as f32 * s;
+                    row[x1i - 1] += d * (1.0 - a2 - am);
+                }
+                row[x1i] += d * am;
+            }
+
+            x = x_next;
+        }
+    }
+
+    fn coverage(&self, fill_rule: FillRule) -> Vec<u8> {
+        let mut mask = Vec::with_capacity(self.width * self.height);
+        for row in self.values.chunks(self.stride) {
+            let mut winding = 0.0f32;
+            for value in &row[.. self.width] {
+                winding += *value;
+                let coverage = match fill_rule {
+                    FillRule::Nonzero => winding.abs().min(1.0),
+                    FillRule::Evenodd => {
+                        let w = winding.abs() % 2.0;
+                        if w > 1.0 { 2.0 - w } else { w }
+                    }
+                };
+                mask.push((coverage * 255.0 + 0.5) as u8);
+            }
+        }
+        mask
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use api::units::LayoutPoint;
+
+    fn rect_ops(x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<PathOp> {
+        vec![
+            PathOp::MoveTo(LayoutPoint::new(x0, y0)),
+            PathOp::LineTo(LayoutPoint::new(x1, y0)),
+            PathOp::LineTo(LayoutPoint::new(x1, y1)),
+            PathOp::LineTo(LayoutPoint::new(x0, y1)),
+            PathOp::Close,
+        ]
+    }
+
+    fn pixel(mask: &[u8], width: usize, x: usize, y: usize) -> u8 {
+        mask[y * width + x]
+    }
+
+    #[test]
+    fn fill_rect() {
+        let mask = rasterize_path(
+            &rect_ops(2.0, 2.0, 6.0, 6.0),
+            FillRule::Nonzero,
+            None,
+            Vector::new(1.0, 1.0),
+            8,
+            8,
+        );
+        assert_eq!(pixel(&mask, 8, 1, 1), 0);
+        assert_eq!(pixel(&mask, 8, 2, 2), 255);
+        assert_eq!(pixel(&mask, 8, 5, 5), 255);
+        assert_eq!(pixel(&mask, 8, 6, 6), 0);
+        assert_eq!(pixel(&mask, 8, 7, 3), 0);
+    }
+
+    #[test]
+    fn fill_partially_outside() {
+        let mask = rasterize_path(
+            &rect_ops(-4.0, -4.0, 4.5, 12.0),
+            FillRule::Nonzero,
+            None,
+            Vector::new(1.0, 1.0),
+            8,
+            8,
+        );
+        assert_eq!(pixel(&mask, 8, 0, 0), 255);
+        assert_eq!(pixel(&mask, 8, 4, 7), 128);
+        assert_eq!(pixel(&mask, 8, 5, 7), 0);
+    }
+
+    #[test]
+    fn fill_rules() {
+        let mut ops = rect_ops(0.0, 0.0, 8.0, 8.0);
+        ops.extend(rect_ops(2.0, 2.0, 6.0, 6.0));
+
+        let nonzero = rasterize_path(&ops, FillRule::Nonzero, None, Vector::new(1.0, 1.0), 8, 8);
+        assert_eq!(pixel(&nonzero, 8, 4, 4), 255);
+
+        let evenodd = rasterize_path(&ops, FillRule::Evenodd, None, Vector::new(1.0, 1.0), 8, 8);
+        assert_eq!(pixel(&evenodd, 8, 4, 4), 0);
+        assert_eq!(pixel(&evenodd, 8, 1, 1), 255);
+    }
+
+    #[test]
+    fn stroke_line() {
+        let ops = [
+            PathOp::MoveTo(LayoutPoint::new(2.0, 4.0)),
+            PathOp::LineTo(LayoutPoint::new(6.0, 4.0)),
+        ];
+        let stroke = |cap| StrokeStyle {
+            width: 2.0,
+            join: LineJoin::Miter,
+            cap,
+            miter_limit: 10.0,
+        };
+
+        let butt = rasterize_path(&ops, FillRule::Nonzero, Some(&stroke(LineCap::Butt)), Vector::new(1.0, 1.0), 8, 8);
+        assert_eq!(pixel(&butt, 8, 3, 3), 255);
+        assert_eq!(pixel(&butt, 8, 3, 4), 255);
+        assert_eq!(pixel(&butt, 8, 3, 5), 0);
+        assert_eq!(pixel(&butt, 8, 1, 3), 0);
+
+        let square = rasterize_path(&ops, FillRule::Nonzero, Some(&stroke(LineCap::Square)), Vector::new(1.0, 1.0), 8, 8);
+        assert_eq!(pixel(&square, 8, 1, 3), 255);
+        assert_eq!(pixel(&square, 8, 6, 4), 255);
+        assert_eq!(pixel(&square, 8, 0, 3), 0);
+    }
+}
@@ -96,7 +96,7 @@
 
 use api::{MixBlendMode, PremultipliedColorF, FilterPrimitiveKind};
 use api::{PropertyBinding, PropertyBindingId, FilterPrimitive, RasterSpace};
-use api::{DebugFlags, ImageKey, ColorF, ColorU, PrimitiveFlags};
+use api::{DebugFlags, EmbedderDrawId, FontInstanceKey, ImageKey, ColorF, ColorU, PrimitiveFlags};
 use api::{ImageRendering, ColorDepth, YuvRangedColorSpace, YuvFormat, AlphaType};
 use api::units::*;
 use crate::command_buffer::PrimitiveCommand;
@@ -120,6 +120,7 @@ use peek_poke::{PeekPoke, poke_into_vec, peek_from_slice, ensure_red_zone};
 use plane_split::{Clipper, Polygon};
 use crate::prim_store::{PrimitiveTemplateKind, PictureIndex, PrimitiveInstance, PrimitiveInstanceKind};
 use crate::prim_store::{ColorBindingStorage, ColorBindingIndex, PrimitiveScratchBuffer};
+use crate::prim_store::{LineSkipInkIndex, LineSkipInkStorage};
 use crate::print_tree::{PrintTree, PrintTreePrinter};
 use crate::render_backend::DataStores;
 use crate::render_task_graph::RenderTaskId;
@@ -360,6 +361,9 @@ enum PrimitiveDependency {
     Image {
         image: ImageDependency,
     },
+    SkipInk {
+        skip_ink: ItemUid,
+    },
 }
 
 /// A dependency for a transform is defined by the spatial node index + frame it was used
@@ -563,6 +567,9 @@ struct PrimitiveDependencyInfo {
 
     /// Spatial nodes references by the clip dependencies of this primitive.
     spatial_nodes: SmallVec<[SpatialNodeIndex; 4]>,
+
+    /// The text whose ink this primitive skips, if it's a line decoration.
+    skip_ink: Option<ItemUid>,
 }
 
 impl PrimitiveDependencyInfo {
@@ -579,6 +586,7 @@ impl PrimitiveDependencyInfo {
             prim_clip_box,
             clips: SmallVec::new(),
             spatial_nodes: SmallVec::new(),
+            skip_ink: None,
         }
     }
 }
@@ -1099,6 +1107,16 @@ impl Tile {
             );
         }
 
+        if let Some(skip_ink) = info.skip_ink {
+            dep_count += 1;
+            poke_into_vec(
+                &PrimitiveDependency::SkipInk {
+                    skip_ink,
+                },
+                &mut self.current_descriptor.dep_data,
+            );
+        }
+
         self.current_descriptor.prims.push(PrimitiveDescriptor {
             prim_uid: info.prim_uid,
             prim_clip_box,
@@ -1172,6 +1190,70 @@ impl Tile {
         self.update_content_validity(ctx, state, frame_context);
     }
 
+    /// Whether the current tile content is opaque, given the clip rect of the
+    /// tile cache and the backdrop of its first sub-slice.
+    fn compute_is_opaque(
+        &self,
+        local_clip_rect: &PictureRect,
+        backdrop: Option<BackdropInfo>,
+    ) -> bool {
+        let clipped_rect = self.current_descriptor.local_valid_rect
+            .intersection(local_clip_rect)
+            .unwrap_or_else(PictureRect::zero);
+
+        let has_opaque_bg_color = self.background_color.map_or(false, |c| c.a >= 1.0);
+        let has_opaque_backdrop = backdrop.map_or(false, |b| b.opaque_rect.contains_box(&clipped_rect));
+        has_opaque_bg_color || has_opaque_backdrop
+    }
+
+    /// Whether this tile can be kept as-is on a scroll-only frame: either it
+    /// isn't in view, or its dependencies were updated by the previous frame
+    /// of the tile cache, `frame_id`, and its content was drawn.
+    fn can_retain_for_scroll(
+        &self,
+        ctx: &TilePreUpdateContext,
+    ) -> bool {
+        let world_tile_rect = ctx.pic_to_world_mapper
+            .map(&self.local_tile_rect)
+            .expect("bug: map local tile rect");
+
+        if !world_tile_rect.intersects(&ctx.global_screen_world_rect) {
+            return true;
+        }
+
+        let is_drawn = self.is_valid || self.current_descriptor.prims.is_empty();
+        is_drawn && self.current_descriptor.last_updated_frame_id == ctx.frame_id
+    }
+
+    /// Called instead of `pre_update` and `update_dirty_and_valid_rects` on a
+    /// scroll-only frame. Only the world space rects of the tile change, its
+    /// dependencies and content are kept.
+    fn update_for_scroll(
+        &mut self,
+        ctx: &TilePreUpdateContext,
+        global_device_pixel_scale: DevicePixelScale,
+    ) {
+        self.invalidation_reason = None;
+        self.world_tile_rect = ctx.pic_to_world_mapper
+            .map(&self.local_tile_rect)
+            .expect("bug: map local tile rect");
+        self.is_visible = self.world_tile_rect.intersects(&ctx.global_screen_world_rect);
+
+        if !self.is_visible {
+            return;
+        }
+
+        self.world_valid_rect = ctx.pic_to_world_mapper
+            .map(&self.current_descriptor.local_valid_rect)
+            .expect("bug: map local valid rect");
+
+        let device_rect = (self.world_tile_rect * global_device_pixel_scale).round();
+        self.device_valid_rect = (self.world_valid_rect * global_device_pixel_scale)
+            .round_out()
+            .intersection(&device_rect)
+            .unwrap_or_else(DeviceRect::zero);
+    }
+
     /// Called during tile cache instance post_update. Allows invalidation and dirty
     /// rect calculation after primitive dependencies have been updated.
     fn post_update(
@@ -1208,13 +1290,7 @@ impl Tile {
         // Check if this tile can be considered opaque. Opacity state must be updated only
         // after all early out checks have been performed. Otherwise, we might miss updating
         // the native surface next time this tile becomes visible.
-        let clipped_rect = self.current_descriptor.local_valid_rect
-            .intersection(&ctx.local_clip_rect)
-            .unwrap_or_else(PictureRect::zero);
-
-        let has_opaque_bg_color = self.background_color.map_or(false, |c| c.a >= 1.0);
-        let has_opaque_backdrop = ctx.backdrop.map_or(false, |b| b.opaque_rect.contains_box(&clipped_rect));
-        let is_opaque = has_opaque_bg_color || has_opaque_backdrop;
+        let is_opaque = self.compute_is_opaque(&ctx.local_clip_rect, ctx.backdrop);
 
         // Set the correct z_id for this tile
         self.z_id = ctx.z_id;
@@ -1831,6 +1907,16 @@ pub struct TileCacheInstance {
     /// Is there a backdrop associated with this cache
     found_prims_after_backdrop: bool,
     pub backdrop_surface: Option<BackdropSurface>,
+    /// The coverage rects of the images and videos found so far during the
+    /// prim dependency checks, which text may avoid subpixel AA over.
+    image_rects: Vec<PictureRect>,
+    /// Set when the tiles of this frame were kept from the previous frame,
+    /// see `update_scroll_only`.
+    pub tiles_retained: bool,
+    /// The images and font instances used by the primitives of this cache,
+    /// which are marked as used again by frames that retain its tiles.
+    used_images: FastHashSet<ImageKey>,
+    used_font_instances: FastHashSet<FontInstanceKey>,
 }
 
 enum SurfacePromotionResult {
@@ -1893,6 +1979,10 @@ impl TileCacheInstance {
             deferred_dirty_tests: Vec::new(),
             found_prims_after_backdrop: false,
             backdrop_surface: None,
+            image_rects: Vec::new(),
+            tiles_retained: false,
+            used_images: FastHashSet::default(),
+            used_font_instances: FastHashSet::default(),
         }
     }
 
@@ -2023,6 +2113,289 @@ impl TileCacheInstance {
         (p0, p1)
     }
 
+    /// Do a hacky diff of opacity and color binding values from the last
+    /// frame. This is used later on during tile invalidation tests.
+    fn update_property_bindings(
+        &mut self,
+        frame_context: &FrameVisibilityContext,
+    ) {
+        let current_properties = frame_context.scene_properties.float_properties();
+        mem::swap(&mut self.opacity_bindings, &mut self.old_opacity_bindings);
+
+        self.opacity_bindings.clear();
+        for (id, value) in current_properties {
+            let changed = match self.old_opacity_bindings.get(id) {
+                Some(old_property) => !old_property.value.approx_eq(value),
+                None => true,
+            };
+            self.opacity_bindings.insert(*id, OpacityBindingInfo {
+                value: *value,
+                changed,
+            });
+        }
+
+        let current_properties = frame_context.scene_properties.color_properties();
+        mem::swap(&mut self.color_bindings, &mut self.old_color_bindings);
+
+        self.color_bindings.clear();
+        for (id, value) in current_properties {
+            let changed = match self.old_color_bindings.get(id) {
+                Some(old_property) => old_property.value != (*value).into(),
+                None => true,
+            };
+            self.color_bindings.insert(*id, ColorBindingInfo {
+                value: (*value).into(),
+                changed,
+            });
+        }
+    }
+
+    /// If there is a valid set of shared clips, build a clip chain instance for this,
+    /// which will provide a local clip rect. This is useful for establishing things
+    /// like whether the backdrop rect supplied by Gecko can be considered opaque.
+    fn shared_clip_rect(
+        &self,
+        pic_rect: PictureRect,
+        pic_to_world_mapper: &SpaceMapper<PicturePixel, WorldPixel>,
+        frame_context: &FrameVisibilityContext,
+        frame_state: &mut FrameVisibilityState,
+    ) -> PictureRect {
+        let shared_clip_leaf_id = match self.shared_clip_leaf_id {
+            Some(shared_clip_leaf_id) => shared_clip_leaf_id,
+            None => return PictureRect::max_rect(),
+        };
+
+        let map_local_to_surface = SpaceMapper::new(
+            self.spatial_node_index,
+            pic_rect,
+        );
+
+        frame_state.clip_store.set_active_clips(
+            self.spatial_node_index,
+            map_local_to_surface.ref_spatial_node_index,
+            shared_clip_leaf_id,
+            frame_context.spatial_tree,
+            &mut frame_state.data_stores.clip,
+            &frame_state.clip_tree,
+        );
+
+        let clip_chain_instance = frame_state.clip_store.build_clip_chain_instance(
+            pic_rect.cast_unit(),
+            &map_local_to_surface,
+            pic_to_world_mapper,
+            frame_context.spatial_tree,
+            frame_state.gpu_cache,
+            frame_state.resource_cache,
+            frame_context.global_device_pixel_scale,
+            &frame_context.global_screen_world_rect,
+            &mut frame_state.data_stores.clip,
+            frame_state.rg_builder,
+            true,
+        );
+
+        // Ensure that if the entire picture cache is clipped out, the local
+        // clip rect is zero. This makes sure we don't register any occluders
+        // that are actually off-screen.
+        clip_chain_instance.map_or(PictureRect::zero(), |clip_chain_instance| {
+            clip_chain_instance.pic_coverage_rect
+        })
+    }
+
+    /// Returns the transforms from local picture space to compositor surface
+    /// space, and from compositor surface space to device space.
+    fn surface_transforms(
+        &self,
+        frame_context: &FrameVisibilityContext,
+    ) -> (ScaleOffset, ScaleOffset) {
+        // Get the complete scale-offset from local space to device space
+        let local_to_device = get_relative_scale_offset(
+            self.spatial_node_index,
+            frame_context.root_spatial_node_index,
+            frame_context.spatial_tree,
+        );
+
+        // Get the compositor transform, which depends on pinch-zoom mode
+        let mut surface_to_device = local_to_device;
+
+        if frame_context.config.low_quality_pinch_zoom {
+            surface_to_device.scale.x /= self.current_raster_scale;
+            surface_to_device.scale.y /= self.current_raster_scale;
+        } else {
+            surface_to_device.scale.x = 1.0;
+            surface_to_device.scale.y = 1.0;
+        }
+
+        // Use that compositor transform to calculate a relative local to surface
+        let local_to_surface = local_to_device.accumulate(&surface_to_device.inverse());
+
+        (local_to_surface, surface_to_device)
+    }
+
+    /// The grid of tiles needed to cover the visible part of the picture.
+    fn needed_tile_rect(&self, pic_rect: PictureRect) -> TileRect {
+        // Inflate the needed rect a bit, so that we retain tiles that we have drawn
+        // but have just recently gone off-screen. This means that we avoid re-drawing
+        // tiles if the user is scrolling up and down small amounts, at the cost of
+        // a bit of extra texture memory.
+        let desired_rect_in_pic_space = self.screen_rect_in_pic_space
+            .inflate(0.0, 1.0 * self.tile_size.height);
+
+        let needed_rect_in_pic_space = desired_rect_in_pic_space
+            .intersection(&pic_rect)
+            .unwrap_or_else(Box2D::zero);
+
+        let p0 = needed_rect_in_pic_space.min;
+        let p1 = needed_rect_in_pic_space.max;
+
+        let x0 = (p0.x / self.tile_size.width).floor() as i32;
+        let x1 = (p1.x / self.tile_size.width).ceil() as i32;
+
+        let y0 = (p0.y / self.tile_size.height).floor() as i32;
+        let y1 = (p1.y / self.tile_size.height).ceil() as i32;
+
+        TileRect {
+            min: TileOffset::new(x0, y0),
+            max: TileOffset::new(x1, y1),
+        }
+    }
+
+    /// On a frame where only scroll offsets changed, checks whether the tiles
+    /// of the previous frame can be kept as they are, which is the case when
+    /// only the compositor transform of this slice changed and every tile now
+    /// in view is up to date. If so, updates the transforms and tile rects and
+    /// returns true, in which case `pre_update` and the primitive dependency
+    /// updates are skipped. Otherwise nothing is changed.
+    pub fn update_scroll_only(
+        &mut self,
+        pic_rect: PictureRect,
+        surface_index: SurfaceIndex,
+        frame_context: &FrameVisibilityContext,
+        frame_state: &mut FrameVisibilityState,
+    ) -> bool {
+        self.tiles_retained = false;
+
+        if !frame_context.scroll_only ||
+           frame_context.config.force_invalidation ||
+           self.invalidate_all_tiles ||
+           self.frames_until_size_eval == 0 ||
+           self.tile_size_override != frame_context.config.tile_size_override ||
+           surface_index != self.surface_index ||
+           pic_rect != self.local_rect {
+            return false;
+        }
+
+        // Compositor surfaces are set up again by every prim dependency update.
+        if !self.external_native_surface_cache.is_empty() ||
+           self.sub_slices.iter().any(|sub_slice| !sub_slice.compositor_surfaces.is_empty()) {
+            return false;
+        }
+
+        // The content only moves as a whole if the scale to the compositor
+        // and every transform the tiles depend on are the same.
+        const EPSILON: f32 = 0.001;
+        let (local_to_surface, surface_to_device) = self.surface_transforms(frame_context);
+        let scale_changed =
+            !surface_to_device.scale.x.approx_eq_eps(&self.surface_to_device.scale.x, &EPSILON) ||
+            !surface_to_device.scale.y.approx_eq_eps(&self.surface_to_device.scale.y, &EPSILON) ||
+            !local_to_surface.scale.x.approx_eq_eps(&self.local_to_surface.scale.x, &EPSILON) ||
+            !local_to_surface.scale.y.approx_eq_eps(&self.local_to_surface.scale.y, &EPSILON);
+        if scale_changed {
+            return false;
+        }
+
+        let frame_id = self.frame_id;
+        let ref_spatial_node_index = self.spatial_node_index;
+        let transforms_changed = self.spatial_node_comparer.spatial_nodes
+            .iter()
+            .filter(|(key, _)| key.frame_id == frame_id)
+            .any(|(key, transform)| {
+                *transform != get_transform_key(
+                    key.spatial_node_index,
+                    ref_spatial_node_index,
+                    frame_context.spatial_tree,
+                )
+            });
+        if transforms_changed {
+            return false;
+        }
+
+        let pic_to_world_mapper = SpaceMapper::new_with_target(
+            frame_context.root_spatial_node_index,
+            self.spatial_node_index,
+            frame_context.global_screen_world_rect,
+            frame_context.spatial_tree,
+        );
+        let screen_rect_in_pic_space = pic_to_world_mapper
+            .unmap(&frame_context.global_screen_world_rect)
+            .expect("unable to unmap screen rect");
+        let prev_screen_rect_in_pic_space = mem::replace(
+            &mut self.screen_rect_in_pic_space,
+            screen_rect_in_pic_space,
+        );
+
+        let ctx = TilePreUpdateContext {
+            pic_to_world_mapper,
+            background_color: self.background_color,
+            global_screen_world_rect: frame_context.global_screen_world_rect,
+            tile_size: self.tile_size,
+            frame_id,
+        };
+
+        // Tiles coming in to view need their dependencies, and the opacity of
+        // the tiles in view mustn't change with the shared clips.
+        let local_clip_rect = self.shared_clip_rect(
+            pic_rect,
+            &ctx.pic_to_world_mapper,
+            frame_context,
+            frame_state,
+        );
+        let backdrop = self.backdrop;
+        let can_retain = self.needed_tile_rect(pic_rect) == self.tile_rect &&
+            self.sub_slices.iter().enumerate().all(|(i, sub_slice)| {
+                let backdrop = if i == 0 { Some(backdrop) } else { None };
+                sub_slice.tiles.values().all(|tile| {
+                    tile.can_retain_for_scroll(&ctx) &&
+                    tile.compute_is_opaque(&local_clip_rect, backdrop) == tile.is_opaque
+                })
+            });
+        if !can_retain {
+            self.screen_rect_in_pic_space = prev_screen_rect_in_pic_space;
+            return false;
+        }
+
+        self.local_clip_rect = local_clip_rect;
+        self.deferred_dirty_tests.clear();
+        for sub_slice in &mut self.sub_slices {
+            sub_slice.reset();
+        }
+
+        if !surface_to_device.offset.x.approx_eq_eps(&self.surface_to_device.offset.x, &EPSILON) ||
+           !surface_to_device.offset.y.approx_eq_eps(&self.surface_to_device.offset.y, &EPSILON) {
+            frame_state.composite_state.dirty_rects_are_valid = false;
+        }
+        self.surface_to_device = surface_to_device;
+        self.local_to_surface = local_to_surface;
+
+        for sub_slice in &mut self.sub_slices {
+            for tile in sub_slice.tiles.values_mut() {
+                tile.update_for_scroll(&ctx, frame_context.global_device_pixel_scale);
+            }
+        }
+
+        // None of the primitives are visited, so mark the resources they
+        // used in the last frame that visited them.
+        for key in &self.used_images {
+            frame_state.resource_cache.mark_image_used(*key);
+        }
+        for key in &self.used_font_instances {
+            frame_state.resource_cache.mark_font_instance_used(*key);
+        }
+
+        self.tiles_retained = true;
+
+        true
+    }
+
     /// Update transforms, opacity, color bindings and tile rects.
     pub fn pre_update(
         &mut self,
@@ -2033,8 +2406,10 @@ impl TileCacheInstance {
     ) -> WorldRect {
         self.surface_index = surface_index;
         self.local_rect = pic_rect;
-        self.local_clip_rect = PictureRect::max_rect();
         self.deferred_dirty_tests.clear();
+        self.tiles_retained = false;
+        self.used_images.clear();
+        self.used_font_instances.clear();
 
         for sub_slice in &mut self.sub_slices {
             sub_slice.reset();
@@ -2043,6 +2418,7 @@ impl TileCacheInstance {
         // Reset the opaque rect + subpixel mode, as they are calculated
         // during the prim dependency checks.
         self.backdrop = BackdropInfo::empty();
+        self.image_rects.clear();
 
         // Calculate the screen rect in picture space, for later comparison against
         // backdrops, and prims potentially covering backdrops.
@@ -2056,45 +2432,12 @@ impl TileCacheInstance {
             .unmap(&frame_context.global_screen_world_rect)
             .expect("unable to unmap screen rect");
 
-        // If there is a valid set of shared clips, build a clip chain instance for this,
-        // which will provide a local clip rect. This is useful for establishing things
-        // like whether the backdrop rect supplied by Gecko can be considered opaque.
-        if let Some(shared_clip_leaf_id) = self.shared_clip_leaf_id {
-            let map_local_to_surface = SpaceMapper::new(
-                self.spatial_node_index,
-                pic_rect,
-            );
-
-            frame_state.clip_store.set_active_clips(
-                self.spatial_node_index,
-                map_local_to_surface.ref_spatial_node_index,
-                shared_clip_leaf_id,
-                frame_context.spatial_tree,
-                &mut frame_state.data_stores.clip,
-                &frame_state.clip_tree,
-            );
-
-            let clip_chain_instance = frame_state.clip_store.build_clip_chain_instance(
-                pic_rect.cast_unit(),
-                &map_local_to_surface,
-                &pic_to_world_mapper,
-                frame_context.spatial_tree,
-                frame_state.gpu_cache,
-                frame_state.resource_cache,
-                frame_context.global_device_pixel_scale,
-                &frame_context.global_screen_world_rect,
-                &mut frame_state.data_stores.clip,
-                frame_state.rg_builder,
-                true,
-            );
-
-            // Ensure that if the entire picture cache is clipped out, the local
-            // clip rect is zero. This makes sure we don't register any occluders
-            // that are actually off-screen.
-            self.local_clip_rect = clip_chain_instance.map_or(PictureRect::zero(), |clip_chain_instance| {
-                clip_chain_instance.pic_coverage_rect
-            });
-        }
+        self.local_clip_rect = self.shared_clip_rect(
+            pic_rect,
+            &pic_to_world_mapper,
+            frame_context,
+            frame_state,
+        );
 
         // Advance the current frame ID counter for this picture cache (must be done
         // after any retained prev state is taken above).
@@ -2159,26 +2502,7 @@ impl TileCacheInstance {
             self.tile_size_override = frame_context.config.tile_size_override;
         }
 
-        // Get the complete scale-offset from local space to device space
-        let local_to_device = get_relative_scale_offset(
-            self.spatial_node_index,
-            frame_context.root_spatial_node_index,
-            frame_context.spatial_tree,
-        );
-
-        // Get the compositor transform, which depends on pinch-zoom mode
-        let mut surface_to_device = local_to_device;
-
-        if frame_context.config.low_quality_pinch_zoom {
-            surface_to_device.scale.x /= self.current_raster_scale;
-            surface_to_device.scale.y /= self.current_raster_scale;
-        } else {
-            surface_to_device.scale.x = 1.0;
-            surface_to_device.scale.y = 1.0;
-        }
-
-        // Use that compositor transform to calculate a relative local to surface
-        let local_to_surface = local_to_device.accumulate(&surface_to_device.inverse());
+        let (local_to_surface, surface_to_device) = self.surface_transforms(frame_context);
 
         const EPSILON: f32 = 0.001;
         let compositor_translation_changed =
@@ -2202,38 +2526,17 @@ impl TileCacheInstance {
         self.local_to_surface = local_to_surface;
         self.invalidate_all_tiles = surface_scale_changed || frame_context.config.force_invalidation;
 
-        // Do a hacky diff of opacity binding values from the last frame. This is
-        // used later on during tile invalidation tests.
-        let current_properties = frame_context.scene_properties.float_properties();
-        mem::swap(&mut self.opacity_bindings, &mut self.old_opacity_bindings);
-
-        self.opacity_bindings.clear();
-        for (id, value) in current_properties {
-            let changed = match self.old_opacity_bindings.get(id) {
-                Some(old_property) => !old_property.value.approx_eq(value),
-                None => true,
-            };
-            self.opacity_bindings.insert(*id, OpacityBindingInfo {
-                value: *value,
-                changed,
-            });
-        }
-
-        // Do a hacky diff of color binding values from the last frame. This is
-        // used later on during tile invalidation tests.
-        let current_properties = frame_context.scene_properties.color_properties();
-        mem::swap(&mut self.color_bindings, &mut self.old_color_bindings);
-
-        self.color_bindings.clear();
-        for (id, value) in current_properties {
-            let changed = match self.old_color_bindings.get(id) {
-                Some(old_property) => old_property.value != (*value).into(),
-                None => true,
-            };
-            self.color_bindings.insert(*id, ColorBindingInfo {
-                value: (*value).into(),
-                changed,
-            });
+        if frame_context.scroll_only {
+            // Scrolling doesn't change any property binding, so there is
+            // nothing to diff against the last frame.
+            for binding in self.opacity_bindings.values_mut() {
+                binding.changed = false;
+            }
+            for binding in self.color_bindings.values_mut() {
+                binding.changed = false;
+            }
+        } else {
+            self.update_property_bindings(frame_context);
         }
 
         let world_tile_size = WorldSize::new(
@@ -2246,30 +2549,9 @@ impl TileCacheInstance {
             world_tile_size.height / self.local_to_surface.scale.y,
         );
 
-        // Inflate the needed rect a bit, so that we retain tiles that we have drawn
-        // but have just recently gone off-screen. This means that we avoid re-drawing
-        // tiles if the user is scrolling up and down small amounts, at the cost of
-        // a bit of extra texture memory.
-        let desired_rect_in_pic_space = self.screen_rect_in_pic_space
-            .inflate(0.0, 1.0 * self.tile_size.height);
-
-        let needed_rect_in_pic_space = desired_rect_in_pic_space
-            .intersection(&pic_rect)
-            .unwrap_or_else(Box2D::zero);
-
-        let p0 = needed_rect_in_pic_space.min;
-        let p1 = needed_rect_in_pic_space.max;
-
-        let x0 = (p0.x / self.tile_size.width).floor() as i32;
-        let x1 = (p1.x / self.tile_size.width).ceil() as i32;
-
-        let y0 = (p0.y / self.tile_size.height).floor() as i32;
-        let y1 = (p1.y / self.tile_size.height).ceil() as i32;
-
-        let new_tile_rect = TileRect {
-            min: TileOffset::new(x0, y0),
-            max: TileOffset::new(x1, y1),
-        };
+        let new_tile_rect = self.needed_tile_rect(pic_rect);
+        let (x0, y0) = (new_tile_rect.min.x, new_tile_rect.min.y);
+        let (x1, y1) = (new_tile_rect.max.x, new_tile_rect.max.y);
 
         // Determine whether the current bounds of the tile grid will exceed the
         // bounds of the DC virtual surface, taking into account the current
@@ -2781,6 +3063,9 @@ impl TileCacheInstance {
                                     Some(native_surface_size)
                                 }
                             },
+                            ExternalSurfaceDependency::EmbedderDraw { .. } => {
+                                unreachable!("bug: embedder draws are not promoted with native compositors");
+                            }
                         }
                     }
                 };
@@ -2876,6 +3161,7 @@ impl TileCacheInstance {
         pictures: &[PicturePrimitive],
         resource_cache: &mut ResourceCache,
         color_bindings: &ColorBindingStorage,
+        line_skip_inks: &LineSkipInkStorage,
         surface_stack: &[(PictureIndex, SurfaceIndex)],
         composite_state: &mut CompositeState,
         gpu_cache: &mut GpuCache,
@@ -3200,6 +3486,55 @@ impl TileCacheInstance {
                     );
                 }
             }
+            PrimitiveInstanceKind::EmbedderDraw { data_handle } => {
+                let prim_data = &data_stores.prim[data_handle];
+                let id = match prim_data.kind {
+                    PrimitiveTemplateKind::EmbedderDraw { id } => EmbedderDrawId(id.get()),
+                    _ => unreachable!(),
+                };
+
+                // The content only exists when the draw compositor runs, so it
+                // can't be drawn in to a tile or handed to a native compositor.
+                let mut promote_to_surface = match composite_state.compositor_kind {
+                    CompositorKind::Draw { .. } => {
+                        match self.can_promote_to_surface(
+                            prim_data.common.flags,
+                            prim_clip_chain,
+                            prim_spatial_node_index,
+                            is_root_tile_cache,
+                            sub_slice_index,
+                            frame_context,
+                        ) {
+                            SurfacePromotionResult::Failed => false,
+                            SurfacePromotionResult::Success => true,
+                        }
+                    }
+                    CompositorKind::Native { .. } => false,
+                };
+
+                if promote_to_surface {
+                    promote_to_surface = self.setup_compositor_surfaces_impl(
+                        sub_slice_index,
+                        &mut prim_info,
+                        prim_data.common.flags,
+                        local_prim_rect,
+                        prim_spatial_node_index,
+                        pic_coverage_rect,
+                        frame_context,
+                        ExternalSurfaceDependency::EmbedderDraw { id },
+                        &[ImageKey::DUMMY; 3],
+                        resource_cache,
+                        composite_state,
+                        ImageRendering::Auto,
+                        false,
+                    );
+                }
+
+                if promote_to_surface {
+                    prim_instance.vis.state = VisibilityState::Culled;
+                    return;
+                }
+            }
             PrimitiveInstanceKind::ImageBorder { data_handle, .. } => {
                 let border_data = &data_stores.image_border[data_handle].kind;
                 prim_info.images.push(ImageDependency {
@@ -3293,13 +3628,32 @@ impl TileCacheInstance {
                     });
                 }
             }
-            PrimitiveInstanceKind::LineDecoration { .. } |
-            PrimitiveInstanceKind::NormalBorder { .. } |
-            PrimitiveInstanceKind::TextRun { .. } => {
+            PrimitiveInstanceKind::LineDecoration { skip_ink, .. } => {
+                // Lines that skip ink depend on the text they skip.
+                if skip_ink != LineSkipInkIndex::INVALID {
+                    prim_info.skip_ink = Some(line_skip_inks[skip_ink].data_handle.uid());
+                }
+            }
+            PrimitiveInstanceKind::TextRun { data_handle, .. } => {
+                // Glyphs aren't requested for tiles that stay valid, so the
+                // font instance is only recorded as used here.
+                let font = &data_stores.text_run[data_handle].font;
+                resource_cache.mark_font_instance_used(font.base.instance_key);
+                self.used_font_instances.insert(font.base.instance_key);
+            }
+            PrimitiveInstanceKind::Path { .. } |
+            PrimitiveInstanceKind::VectorPath { .. } |
+            PrimitiveInstanceKind::NormalBorder { .. } => {
                 // These don't contribute dependencies
             }
         };
 
+        // Likewise, images drawn by tiles that stay valid aren't requested.
+        for image in &prim_info.images {
+            resource_cache.mark_image_used(image.key);
+            self.used_images.insert(image.key);
+        }
+
         // Calculate the screen rect in local space. When we calculate backdrops, we
         // care only that they cover the visible rect, and don't have any overlapping
         // prims in the visible rect.
@@ -3311,6 +3665,25 @@ impl TileCacheInstance {
         // If this primitive considers itself a backdrop candidate, apply further
         // checks to see if it matches all conditions to be a backdrop.
         let mut vis_flags = PrimitiveVisibilityFlags::empty();
+
+        // Subpixel AA text shows color fringes over photos and videos, so text
+        // that asks for it falls back to grayscale AA over the images drawn
+        // before it in this slice.
+        match prim_instance.kind {
+            PrimitiveInstanceKind::Image { .. } |
+            PrimitiveInstanceKind::YuvImage { .. } => {
+                self.image_rects.push(pic_coverage_rect);
+            }
+            PrimitiveInstanceKind::TextRun { data_handle, .. } => {
+                let flags = data_stores.text_run[data_handle].flags;
+                if flags.contains(PrimitiveFlags::GRAYSCALE_TEXT_OVER_IMAGES) &&
+                   self.image_rects.iter().any(|rect| rect.intersects(&pic_coverage_rect)) {
+                    vis_flags |= PrimitiveVisibilityFlags::GRAYSCALE_TEXT;
+                }
+            }
+            _ => {}
+        }
+
         let sub_slice = &mut self.sub_slices[sub_slice_index];
         if let Some(mut backdrop_candidate) = backdrop_candidate {
             // Update whether the surface that this primitive exists on
@@ -3569,10 +3942,13 @@ impl TileCacheInstance {
         };
 
         // Step through each tile and invalidate if the dependencies have changed. Determine
-        // the current opacity setting and whether it's changed.
-        for sub_slice in &mut self.sub_slices {
-            for tile in sub_slice.tiles.values_mut() {
-                tile.update_dirty_and_valid_rects(&ctx, &mut state, frame_context);
+        // the current opacity setting and whether it's changed. Retained tiles already
+        // updated their rects, and their dependencies are unchanged.
+        if !self.tiles_retained {
+            for sub_slice in &mut self.sub_slices {
+                for tile in sub_slice.tiles.values_mut() {
+                    tile.update_dirty_and_valid_rects(&ctx, &mut state, frame_context);
+                }
             }
         }
 
@@ -6464,6 +6840,11 @@ impl<'a> PrimitiveComparer<'a> {
                         return PrimitiveCompareResult::Image;
                     }
                 }
+                (PrimitiveDependency::SkipInk { skip_ink: prev }, PrimitiveDependency::SkipInk { skip_ink: curr }) => {
+                    if prev != curr {
+                        return PrimitiveCompareResult::Descriptor;
+                    }
+                }
                 _ => {
                     // There was a mismatch between types of dependencies, so something changed
                     return PrimitiveCompareResult::Descriptor;
@@ -7310,3 +7691,93 @@ fn test_drop_filter_dirty_region_outside_prim() {
     ).expect("No surface rect");
     assert_eq!(info.task_size, DeviceIntSize::new(432, 578));
 }
+
+#[test]
+fn test_tile_retained_for_scroll() {
+    // Ensure that on a scroll-only frame, tiles that stay in view keep their
+    // content and only move, while tiles coming in to view need an update.
+
+    use api::{APZScrollGeneration, ExternalScrollId, HasScrollLinkedEffect, PipelineId};
+    use api::{SampledScrollOffset, SpatialTreeItemKey};
+    use crate::spatial_node::{ScrollFrameKind, SpatialNodeUid};
+    use crate::spatial_tree::{SceneSpatialTree, SpatialTree};
+    use crate::internal_types::PipelineInstanceId;
+
+    let mut cst = SceneSpatialTree::new();
+    let root_reference_frame_index = cst.root_reference_frame_index();
+    let scroll_id = ExternalScrollId(1, PipelineId::dummy());
+    let scroll_node_index = cst.add_scroll_frame(
+        root_reference_frame_index,
+        scroll_id,
+        PipelineId::dummy(),
+        &LayoutRect::from_size(LayoutSize::new(400.0, 400.0)),
+        &LayoutSize::new(400.0, 4000.0),
+        ScrollFrameKind::Explicit,
+        LayoutVector2D::zero(),
+        APZScrollGeneration::default(),
+        HasScrollLinkedEffect::No,
+        SpatialNodeUid::external(SpatialTreeItemKey::new(0, 1), PipelineId::dummy(), PipelineInstanceId::new(0)),
+    );
+
+    let mut spatial_tree = SpatialTree::new();
+    spatial_tree.apply_updates(cst.end_frame_and_get_pending_updates());
+    spatial_tree.update_tree(&SceneProperties::new());
+
+    let frame_id = FrameId::first() + 1;
+    let screen_rect = WorldRect::from_size(WorldSize::new(400.0, 400.0));
+    let tile_size = PictureSize::new(400.0, 200.0);
+    let pre_update_context = |spatial_tree: &SpatialTree| {
+        TilePreUpdateContext {
+            pic_to_world_mapper: SpaceMapper::new_with_target(
+                root_reference_frame_index,
+                scroll_node_index,
+                screen_rect,
+                spatial_tree,
+            ),
+            background_color: None,
+            global_screen_world_rect: screen_rect,
+            tile_size,
+            frame_id,
+        }
+    };
+
+    // A tile drawn by the last frame, and one below the screen whose
+    // dependencies were never updated.
+    let mut drawn_tile = Tile::new(TileOffset::new(0, 1));
+    drawn_tile.local_tile_rect = PictureRect::from_origin_and_size(PicturePoint::new(0.0, 200.0), tile_size);
+    drawn_tile.current_descriptor.local_valid_rect = drawn_tile.local_tile_rect;
+    drawn_tile.current_descriptor.last_updated_frame_id = frame_id;
+    drawn_tile.is_valid = true;
+
+    let mut new_tile = Tile::new(TileOffset::new(0, 2));
+    new_tile.local_tile_rect = PictureRect::from_origin_and_size(PicturePoint::new(0.0, 400.0), tile_size);
+
+    let ctx = pre_update_context(&spatial_tree);
+    assert!(drawn_tile.can_retain_for_scroll(&ctx));
+    assert!(new_tile.can_retain_for_scroll(&ctx));
+
+    spatial_tree.set_scroll_offsets(
+        scroll_id,
+        vec![SampledScrollOffset {
+            offset: LayoutVector2D::new(0.0, 300.0),
+            generation: APZScrollGeneration::default(),
+        }],
+    );
+    spatial_tree.update_tree(&SceneProperties::new());
+
+    let ctx = pre_update_context(&spatial_tree);
+    assert!(drawn_tile.can_retain_for_scroll(&ctx));
+    assert!(!new_tile.can_retain_for_scroll(&ctx));
+
+    drawn_tile.update_for_scroll(&ctx, DevicePixelScale::new(1.0));
+    assert!(drawn_tile.is_visible);
+    assert!(drawn_tile.is_valid);
+    assert_eq!(
+        drawn_tile.world_tile_rect,
+        WorldRect::from_origin_and_size(WorldPoint::new(0.0, -100.0), WorldSize::new(400.0, 200.0)),
+    );
+    assert_eq!(
+        drawn_tile.device_valid_rect,
+        DeviceRect::from_origin_and_size(DevicePoint::new(0.0, -100.0), DeviceSize::new(400.0, 200.0)),
+    );
+}
@@ -324,6 +324,24 @@ impl PictureTextures {
         }
     }
 
+    /// Frees the pooled textures that don't currently hold a tile.
+    pub fn free_unused_textures(&mut self, pending_updates: &mut TextureUpdateList) {
+        self.textures.retain(|texture| {
+            if !texture.is_allocated {
+                pending_updates.push_free(texture.texture_id);
+            }
+            texture.is_allocated
+        });
+    }
+
+    /// The amount of GPU memory used by the texture pool, in bytes.
+    pub fn total_bytes(&self) -> usize {
+        self.textures
+            .iter()
+            .map(|texture| (texture.size.area() * ImageFormat::RGBA8.bytes_per_pixel()) as usize)
+            .sum()
+    }
+
     pub fn update_profile(&self, profile: &mut TransactionProfile) {
         profile.set(profiler::PICTURE_TILES, self.textures.len());
     }
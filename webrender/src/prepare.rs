@@ -6,10 +6,11 @@
 //!
 //! TODO: document this!
 
-use api::{ColorF, PremultipliedColorF, PropertyBinding};
-use api::{BoxShadowClipMode, BorderStyle, ClipMode};
+use api::{ColorF, FontRenderMode, PremultipliedColorF, PropertyBinding, SubpixelTextPolicy};
+use api::{BoxShadowClipMode, BorderStyle, ClipMode, PathKey};
 use api::units::*;
 use euclid::Scale;
+use glyph_rasterizer::GlyphRequestPriority;
 use smallvec::SmallVec;
 use crate::command_buffer::{PrimitiveCommand, QuadFlags, CommandBufferIndex};
 use crate::image_tiling::{self, Repetition};
@@ -24,6 +25,7 @@ use crate::internal_types::{FastHashMap, PlaneSplitAnchor, Filter};
 use crate::picture::{PicturePrimitive, SliceId, ClusterFlags, PictureCompositeMode};
 use crate::picture::{PrimitiveList, PrimitiveCluster, SurfaceIndex, TileCacheInstance, SubpixelMode, Picture3DContext};
 use crate::prim_store::line_dec::MAX_LINE_DECORATION_RESOLUTION;
+use crate::prim_store::vector_path::{MAX_VECTOR_PATH_RESOLUTION, VectorPathCacheKey};
 use crate::prim_store::*;
 use crate::prim_store::gradient::GradientGpuBlockBuilder;
 use crate::render_backend::DataStores;
@@ -35,7 +37,7 @@ use crate::renderer::{GpuBufferBuilder, GpuBufferAddress};
 use crate::segment::{EdgeAaSegmentMask, SegmentBuilder};
 use crate::space::SpaceMapper;
 use crate::util::{clamp_to_scale_factor, pack_as_float, MaxRect};
-use crate::visibility::{compute_conservative_visible_rect, PrimitiveVisibility, VisibilityState};
+use crate::visibility::{compute_conservative_visible_rect, PrimitiveVisibility, PrimitiveVisibilityFlags, VisibilityState};
 
 
 const MAX_MASK_SIZE: f32 = 4096.0;
@@ -385,7 +387,7 @@ fn prepare_interned_prim_for_render(
     let device_pixel_scale = frame_state.surfaces[pic_context.surface_index.0].device_pixel_scale;
 
     match &mut prim_instance.kind {
-        PrimitiveInstanceKind::LineDecoration { data_handle, ref mut render_task, .. } => {
+        PrimitiveInstanceKind::LineDecoration { data_handle, ref mut render_task, skip_ink } => {
             profile_scope!("LineDecoration");
             let prim_data = &mut data_stores.line_decoration[*data_handle];
             let common_data = &mut prim_data.common;
@@ -395,6 +397,18 @@ fn prepare_interned_prim_for_render(
             // cache with any shared template data.
             line_dec_data.update(common_data, frame_state);
 
+            // Work out which parts of the line the ink of the text it decorates
+            // leaves visible, if it skips ink.
+            if *skip_ink != LineSkipInkIndex::INVALID {
+                let skip_ink = &mut store.line_skip_inks[*skip_ink];
+                let segments = data_stores.line_skip_ink[skip_ink.data_handle].visible_segments(
+                    common_data.prim_rect.size(),
+                    frame_state.resource_cache,
+                );
+                let range = scratch.line_segments.extend(segments);
+                skip_ink.ink_segments = scratch.line_segment_ranges.push(range);
+            }
+
             // Work out the device pixel size to be used to cache this line decoration.
 
             // If we have a cache key, it's a wavy / dashed / dotted line. Otherwise, it's
@@ -471,6 +485,102 @@ fn prepare_interned_prim_for_render(
                 ));
             }
         }
+        PrimitiveInstanceKind::Path { data_handle, ref mut render_task } => {
+            profile_scope!("Path");
+            let prim_data = &mut data_stores.prim[*data_handle];
+
+            prim_data.update(
+                frame_state,
+                frame_context.scene_properties,
+            );
+
+            let key = match prim_data.kind {
+                PrimitiveTemplateKind::Path { key } => PathKey(key.get()),
+                _ => unreachable!("bug: non-path template for a path prim"),
+            };
+            let local_size = prim_data.common.prim_rect.size();
+
+            // Render the path at the scale it is drawn at, picked in the same
+            // way as for line decorations.
+            let scale = frame_context
+                .spatial_tree
+                .get_world_transform(prim_spatial_node_index)
+                .scale_factors();
+            let scale_width = clamp_to_scale_factor(scale.0, false);
+            let scale_height = clamp_to_scale_factor(scale.1, false);
+            let world_scale = LayoutToWorldScale::new(scale_width.max(scale_height));
+
+            let scale_factor = world_scale * Scale::new(1.0);
+            let mut task_size_f = (local_size * scale_factor).ceil();
+            let max_extent = task_size_f.width.max(task_size_f.height);
+            if max_extent > MAX_PATH_RESOLUTION as f32 {
+                task_size_f = (task_size_f * (MAX_PATH_RESOLUTION as f32 / max_extent)).ceil();
+            }
+            let mut task_size = task_size_f.to_i32();
+            task_size.width = task_size.width.max(1);
+            task_size.height = task_size.height.max(1);
+
+            *render_task = Some(frame_state.resource_cache.request_render_task(
+                RenderTaskCacheKey {
+                    size: task_size,
+                    kind: RenderTaskCacheKeyKind::Path(PathCacheKey {
+                        key,
+                        local_size: local_size.to_au(),
+                    }),
+                },
+                frame_state.gpu_cache,
+                frame_state.frame_gpu_data,
+                frame_state.rg_builder,
+                None,
+                false,
+                RenderTaskParent::Surface(pic_context.surface_index),
+                &mut frame_state.surface_builder,
+                |rg_builder, _| {
+                    rg_builder.add().init(RenderTask::new_dynamic(
+                        task_size,
+                        RenderTaskKind::new_path(key, local_size),
+                    ))
+                }
+            ));
+        }
+        PrimitiveInstanceKind::VectorPath { data_handle, ref mut texture_cache_handle } => {
+            profile_scope!("VectorPath");
+            let prim_data = &mut data_stores.vector_path[*data_handle];
+            let common_data = &mut prim_data.common;
+            let path_data = &mut prim_data.kind;
+
+            path_data.update(common_data, frame_state);
+
+            // Rasterize the path at the scale it is drawn at, in the same way
+            // as line decorations.
+            let local_size = common_data.prim_rect.size();
+            let scale = frame_context
+                .spatial_tree
+                .get_world_transform(prim_spatial_node_index)
+                .scale_factors();
+            let scale_width = clamp_to_scale_factor(scale.0, false);
+            let scale_height = clamp_to_scale_factor(scale.1, false);
+            let world_scale = LayoutToWorldScale::new(scale_width.max(scale_height));
+
+            let scale_factor = world_scale * Scale::new(1.0);
+            let mut size_f = (local_size * scale_factor).ceil();
+            let max_extent = size_f.width.max(size_f.height);
+            if max_extent > MAX_VECTOR_PATH_RESOLUTION as f32 {
+                size_f = (size_f * (MAX_VECTOR_PATH_RESOLUTION as f32 / max_extent)).ceil();
+            }
+            let mut size = size_f.to_i32();
+            size.width = size.width.max(1);
+            size.height = size.height.max(1);
+
+            *texture_cache_handle = frame_state.resource_cache.request_vector_path(
+                VectorPathCacheKey {
+                    shape: path_data.shape.clone(),
+                    local_size: local_size.to_au(),
+                    size,
+                },
+                frame_state.gpu_cache,
+            );
+        }
         PrimitiveInstanceKind::TextRun { run_index, data_handle, .. } => {
             profile_scope!("TextRun");
             let prim_data = &mut data_stores.text_run[*data_handle];
@@ -493,7 +603,7 @@ fn prepare_interned_prim_for_render(
             // If subpixel AA is disabled due to the backing surface the glyphs
             // are being drawn onto, disable it (unless we are using the
             // specifial subpixel mode that estimates background color).
-            let allow_subpixel = match prim_instance.vis.state {
+            let can_blend_subpixel = match prim_instance.vis.state {
                 VisibilityState::Culled |
                 VisibilityState::Unset |
                 VisibilityState::PassThrough => {
@@ -517,6 +627,26 @@ fn prepare_interned_prim_for_render(
                     }
                 }
             };
+            let allow_subpixel = match frame_context.fb_config.subpixel_text_policy {
+                SubpixelTextPolicy::Auto => can_blend_subpixel,
+                SubpixelTextPolicy::Force => true,
+                SubpixelTextPolicy::Disable => false,
+            };
+            // Text that asked for grayscale AA over images, and is over one.
+            let over_image = match prim_instance.vis.state {
+                VisibilityState::Visible { vis_flags, .. } => {
+                    vis_flags.contains(PrimitiveVisibilityFlags::GRAYSCALE_TEXT)
+                }
+                _ => false,
+            };
+            // Visible text runs outside the screen are in picture cache tiles
+            // rendered ahead of scrolling, their glyphs can wait.
+            let priority = match pic_state.map_pic_to_world.map(&prim_instance.vis.clip_chain.pic_coverage_rect) {
+                Some(world_rect) if !world_rect.intersects(&frame_context.global_screen_world_rect) => {
+                    GlyphRequestPriority::NearViewport
+                }
+                _ => GlyphRequestPriority::Visible,
+            };
 
             run.request_resources(
                 prim_offset,
@@ -525,7 +655,8 @@ fn prepare_interned_prim_for_render(
                 &transform.to_transform().with_destination::<_>(),
                 surface,
                 prim_spatial_node_index,
-                allow_subpixel,
+                allow_subpixel && !over_image,
+                priority,
                 frame_context.fb_config.low_quality_pinch_zoom,
                 frame_state.resource_cache,
                 frame_state.gpu_cache,
@@ -533,6 +664,11 @@ fn prepare_interned_prim_for_render(
                 scratch,
             );
 
+            if prim_data.font.base.options.render_mode == FontRenderMode::Subpixel &&
+               run.used_font.render_mode != FontRenderMode::Subpixel {
+                frame_state.num_subpixel_text_fallbacks += 1;
+            }
+
             // Update the template this instane references, which may refresh the GPU
             // cache with any shared template data.
             prim_data.update(frame_state);
@@ -689,6 +825,7 @@ fn prepare_interned_prim_for_render(
                     |request| {
                         prim_data.kind.write_prim_gpu_blocks(
                             request,
+                            prim_data.common.prim_rect.size(),
                             frame_context.scene_properties,
                         );
                     }
@@ -713,6 +850,10 @@ fn prepare_interned_prim_for_render(
                 let prim_data = &data_stores.prim[*data_handle];
 
                 let (color, is_opaque) = match prim_data.kind {
+                    PrimitiveTemplateKind::EmbedderDraw { .. } |
+                    PrimitiveTemplateKind::Path { .. } => {
+                        unreachable!("bug: non-rectangle template for a rectangle prim");
+                    }
                     PrimitiveTemplateKind::Clear => {
                         // Opaque black with operator dest out
                         (ColorF::BLACK, false)
@@ -1389,6 +1530,7 @@ fn prepare_interned_prim_for_render(
                 prim_instance.clear_visibility();
             }
         }
+        PrimitiveInstanceKind::EmbedderDraw { .. } => {}
         PrimitiveInstanceKind::BackdropCapture { .. } => {
             // Register the owner picture of this backdrop primitive as the
             // target for resolve of the sub-graph
@@ -1531,6 +1673,9 @@ fn update_clip_task_for_brush(
         PrimitiveInstanceKind::Picture { .. } |
         PrimitiveInstanceKind::TextRun { .. } |
         PrimitiveInstanceKind::Clear { .. } |
+        PrimitiveInstanceKind::EmbedderDraw { .. } |
+        PrimitiveInstanceKind::Path { .. } |
+        PrimitiveInstanceKind::VectorPath { .. } |
         PrimitiveInstanceKind::LineDecoration { .. } |
         PrimitiveInstanceKind::BackdropCapture { .. } |
         PrimitiveInstanceKind::BackdropRender { .. } => {
@@ -1976,6 +2121,9 @@ fn build_segments_if_needed(
         PrimitiveInstanceKind::ConicGradient { .. } |
         PrimitiveInstanceKind::LineDecoration { .. } |
         PrimitiveInstanceKind::BackdropCapture { .. } |
+        PrimitiveInstanceKind::EmbedderDraw { .. } |
+        PrimitiveInstanceKind::Path { .. } |
+        PrimitiveInstanceKind::VectorPath { .. } |
         PrimitiveInstanceKind::BackdropRender { .. } => {
             // These primitives don't support / need segments.
             return;
@@ -4,7 +4,8 @@
 
 use api::{
     AlphaType, ColorDepth, ColorF, ColorU, ExternalImageData, ExternalImageType,
-    ImageKey as ApiImageKey, ImageBufferKind, ImageRendering, PremultipliedColorF,
+    ImageDescriptorFlags, ImageKey as ApiImageKey, ImageBufferKind, ImageFormat, ImageRendering,
+    PremultipliedColorF,
     RasterSpace, Shadow, YuvColorSpace, ColorRange, YuvFormat,
 };
 use api::units::*;
@@ -111,6 +112,9 @@ pub struct ImageData {
     pub color: ColorF,
     pub image_rendering: ImageRendering,
     pub alpha_type: AlphaType,
+    /// Whether the image is an alpha mask filled with `color`, see
+    /// `ImageDescriptorFlags::ALPHA_MASK`. Set when the template is updated.
+    pub is_alpha_mask: bool,
 }
 
 impl From<Image> for ImageData {
@@ -122,6 +126,7 @@ impl From<Image> for ImageData {
             tile_spacing: image.tile_spacing.into(),
             image_rendering: image.image_rendering,
             alpha_type: image.alpha_type,
+            is_alpha_mask: false,
         }
     }
 }
@@ -146,9 +151,14 @@ impl ImageData {
             .resource_cache
             .get_image_properties(self.key);
 
+        self.is_alpha_mask = image_properties.as_ref().map_or(false, |properties| {
+            properties.descriptor.format == ImageFormat::R8 &&
+                properties.descriptor.flags.contains(ImageDescriptorFlags::ALPHA_MASK)
+        });
+
         common.opacity = match &image_properties {
             Some(properties) => {
-                if properties.descriptor.is_opaque() {
+                if properties.descriptor.is_opaque() && !self.is_alpha_mask {
                     PrimitiveOpacity::from_alpha(self.color.a)
                 } else {
                     PrimitiveOpacity::translucent()
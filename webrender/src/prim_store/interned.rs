@@ -7,8 +7,9 @@
 pub use crate::prim_store::backdrop::{BackdropCapture, BackdropRender};
 pub use crate::prim_store::borders::{ImageBorder, NormalBorderPrim};
 pub use crate::prim_store::image::{Image, YuvImage};
-pub use crate::prim_store::line_dec::{LineDecoration};
+pub use crate::prim_store::line_dec::{LineDecoration, LineSkipInk};
 pub use crate::prim_store::gradient::{LinearGradient, RadialGradient, ConicGradient};
 pub use crate::prim_store::picture::Picture;
 pub use crate::prim_store::text_run::TextRun;
+pub use crate::prim_store::vector_path::VectorPath;
 
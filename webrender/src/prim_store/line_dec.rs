@@ -3,10 +3,11 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use api::{
-    ColorF, ColorU, RasterSpace,
+    ColorF, ColorU, GlyphInstance, RasterSpace,
     LineOrientation, LineStyle, PremultipliedColorF, Shadow,
 };
 use api::units::*;
+use glyph_rasterizer::FontInstance;
 use crate::scene_building::{CreateShadow, IsVisible};
 use crate::frame_builder::{FrameBuildingState};
 use crate::gpu_cache::GpuDataRequest;
@@ -16,7 +17,8 @@ use crate::prim_store::{
     PrimKey, PrimTemplate, PrimTemplateCommonData,
     InternablePrimitive, PrimitiveStore,
 };
-use crate::prim_store::PrimitiveInstanceKind;
+use crate::prim_store::{LineSegmentRangeIndex, LineSkipInkIndex, PrimitiveInstanceKind};
+use crate::resource_cache::ResourceCache;
 
 /// Maximum resolution in device pixels at which line decorations are rasterized.
 pub const MAX_LINE_DECORATION_RESOLUTION: u32 = 4096;
@@ -44,6 +46,72 @@ pub struct LineDecoration {
     pub color: ColorU,
 }
 
+/// The text whose ink a (solid) line decoration skips.
+///
+/// This is interned separately from the line decoration, and referenced by
+/// the primitive instances of the lines that skip ink, so that it doesn't
+/// make the keys and templates of all line decorations bigger.
+#[derive(Clone, Debug, Hash, MallocSizeOf, PartialEq, Eq)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct LineSkipInk {
+    pub font: FontInstance,
+    /// The glyphs, relative to the origin of the line.
+    pub glyphs: Vec<GlyphInstance>,
+    /// The space left between the line and the ink of the glyphs.
+    pub clearance: Au,
+}
+
+impl LineSkipInk {
+    /// Returns the parts of a line of the given size that don't come within
+    /// the clearance of any glyph's ink, relative to the origin of the line.
+    /// Only the rows of a glyph's bitmap that the line (plus the clearance)
+    /// crosses are considered, so the line isn't broken under the parts of
+    /// the glyph that are far from it.
+    pub fn visible_segments(
+        &self,
+        line_size: LayoutSize,
+        resource_cache: &mut ResourceCache,
+    ) -> Vec<LayoutRect> {
+        let clearance = self.clearance.to_f32_px();
+
+        let mut gaps: Vec<(f32, f32)> = self.glyphs
+            .iter()
+            .filter(|glyph| glyph.point.x.is_finite() && glyph.point.y.is_finite())
+            .filter_map(|glyph| {
+                let ink = resource_cache.get_glyph_ink(&self.font, glyph.index)?;
+                let (left, right) = ink.horizontal_extent(
+                    -clearance - glyph.point.y,
+                    line_size.height + clearance - glyph.point.y,
+                )?;
+                Some((glyph.point.x + left - clearance, glyph.point.x + right + clearance))
+            })
+            .collect();
+        gaps.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut segments = Vec::new();
+        let mut x = 0.0;
+        for (start, end) in gaps {
+            if start > x {
+                segments.push(LayoutRect::from_origin_and_size(
+                    LayoutPoint::new(x, 0.0),
+                    LayoutSize::new(start.min(line_size.width) - x, line_size.height),
+                ));
+            }
+            x = x.max(end);
+            if x >= line_size.width {
+                return segments;
+            }
+        }
+        segments.push(LayoutRect::from_origin_and_size(
+            LayoutPoint::new(x, 0.0),
+            LayoutSize::new(line_size.width - x, line_size.height),
+        ));
+
+        segments
+    }
+}
+
 pub type LineDecorationKey = PrimKey<LineDecoration>;
 
 impl LineDecorationKey {
@@ -149,10 +217,32 @@ impl InternablePrimitive for LineDecoration {
         PrimitiveInstanceKind::LineDecoration {
             data_handle,
             render_task: None,
+            skip_ink: LineSkipInkIndex::INVALID,
         }
     }
 }
 
+pub type LineSkipInkDataHandle = intern::Handle<LineSkipInk>;
+
+impl intern::InternDebug for LineSkipInk {}
+
+impl intern::Internable for LineSkipInk {
+    type Key = LineSkipInk;
+    type StoreData = LineSkipInk;
+    type InternData = ();
+    const PROFILE_COUNTER: usize = crate::profiler::INTERNED_LINE_SKIP_INKS;
+}
+
+/// The per-instance data of a line decoration that skips ink.
+#[derive(Debug)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+pub struct LineSkipInkInstance {
+    /// Handle to the interned text whose ink the line skips.
+    pub data_handle: LineSkipInkDataHandle,
+    /// Index to the visible parts of the line this frame.
+    pub ink_segments: LineSegmentRangeIndex,
+}
+
 impl CreateShadow for LineDecoration {
     fn create_shadow(
         &self,
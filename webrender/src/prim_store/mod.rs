@@ -5,7 +5,7 @@
 use api::{BorderRadius, ClipMode, ColorF, ColorU, RasterSpace};
 use api::{ImageRendering, RepeatMode, PrimitiveFlags};
 use api::{PremultipliedColorF, PropertyBinding, Shadow};
-use api::{PrimitiveKeyKind, FillRule, POLYGON_CLIP_VERTEX_MAX};
+use api::{PackedId, PathKey, PrimitiveKeyKind, FillRule, POLYGON_CLIP_VERTEX_MAX};
 use api::units::*;
 use euclid::{SideOffsets2D, Size2D};
 use malloc_size_of::MallocSizeOf;
@@ -17,13 +17,14 @@ use crate::debug_colors;
 use crate::scene_building::{CreateShadow, IsVisible};
 use crate::frame_builder::FrameBuildingState;
 use glyph_rasterizer::GlyphKey;
-use crate::gpu_cache::{GpuCacheAddress, GpuCacheHandle, GpuDataRequest};
+use crate::gpu_cache::{GpuCacheAddress, GpuCacheHandle, GpuCacheValidationScope, GpuDataRequest};
 use crate::gpu_types::{BrushFlags, QuadSegment};
 use crate::intern;
 use crate::picture::PicturePrimitive;
 use crate::render_task_graph::RenderTaskId;
 use crate::resource_cache::ImageProperties;
 use crate::scene::SceneProperties;
+use crate::texture_cache::TextureCacheHandle;
 use std::{hash, ops, u32, usize};
 use crate::util::Recycler;
 use crate::internal_types::{FastHashSet, LayoutPrimitiveInfo};
@@ -36,6 +37,7 @@ pub mod image;
 pub mod line_dec;
 pub mod picture;
 pub mod text_run;
+pub mod vector_path;
 pub mod interned;
 
 mod storage;
@@ -44,9 +46,10 @@ use backdrop::{BackdropCaptureDataHandle, BackdropRenderDataHandle};
 use borders::{ImageBorderDataHandle, NormalBorderDataHandle};
 use gradient::{LinearGradientPrimitive, LinearGradientDataHandle, RadialGradientDataHandle, ConicGradientDataHandle};
 use image::{ImageDataHandle, ImageInstance, YuvImageDataHandle};
-use line_dec::LineDecorationDataHandle;
+use line_dec::{LineDecorationDataHandle, LineSkipInkInstance};
 use picture::PictureDataHandle;
 use text_run::{TextRunDataHandle, TextRunPrimitive};
+use vector_path::VectorPathDataHandle;
 
 pub const VECS_PER_SEGMENT: usize = 2;
 
@@ -477,6 +480,12 @@ pub enum PrimitiveTemplateKind {
         color: PropertyBinding<ColorF>,
     },
     Clear,
+    EmbedderDraw {
+        id: PackedId,
+    },
+    Path {
+        key: PackedId,
+    },
 }
 
 impl PrimitiveTemplateKind {
@@ -484,6 +493,7 @@ impl PrimitiveTemplateKind {
     pub fn write_prim_gpu_blocks(
         &self,
         request: &mut GpuDataRequest,
+        prim_size: LayoutSize,
         scene_properties: &SceneProperties,
     ) {
         match *self {
@@ -494,10 +504,34 @@ impl PrimitiveTemplateKind {
             PrimitiveTemplateKind::Rectangle { ref color, .. } => {
                 request.push(scene_properties.resolve_color(color).premultiplied())
             }
+            PrimitiveTemplateKind::EmbedderDraw { .. } => {
+                // Drawn by the embedder when compositing, nothing is read
+                // from the GPU cache.
+                request.push(PremultipliedColorF::TRANSPARENT);
+            }
+            PrimitiveTemplateKind::Path { .. } => {
+                // Drawn as an image of the rendered path, stretched over
+                // the whole primitive.
+                request.push(PremultipliedColorF::WHITE);
+                request.push(PremultipliedColorF::WHITE);
+                request.push([prim_size.width, prim_size.height, 0.0, 0.0]);
+            }
         }
     }
 }
 
+/// The maximum size, in device pixels, that a path is rendered at.
+pub const MAX_PATH_RESOLUTION: u32 = 4096;
+
+/// Identifies the rendered image of a path in the render task cache.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct PathCacheKey {
+    pub key: PathKey,
+    pub local_size: LayoutSizeAu,
+}
+
 /// Construct the primitive template data from a primitive key. This
 /// is invoked when a primitive key is created and the interner
 /// doesn't currently contain a primitive with this key.
@@ -512,6 +546,12 @@ impl From<PrimitiveKeyKind> for PrimitiveTemplateKind {
                     color: color.into(),
                 }
             }
+            PrimitiveKeyKind::EmbedderDraw { id } => {
+                PrimitiveTemplateKind::EmbedderDraw { id }
+            }
+            PrimitiveKeyKind::Path { key } => {
+                PrimitiveTemplateKind::Path { key }
+            }
         }
     }
 }
@@ -600,11 +640,17 @@ impl PrimitiveTemplate {
         scene_properties: &SceneProperties,
     ) {
         if let Some(mut request) = frame_state.gpu_cache.request(&mut self.common.gpu_cache_handle) {
-            self.kind.write_prim_gpu_blocks(&mut request, scene_properties);
+            self.kind.write_prim_gpu_blocks(
+                &mut request,
+                self.common.prim_rect.size(),
+                scene_properties,
+            );
         }
 
         self.opacity = match self.kind {
-            PrimitiveTemplateKind::Clear => {
+            PrimitiveTemplateKind::Clear |
+            PrimitiveTemplateKind::EmbedderDraw { .. } |
+            PrimitiveTemplateKind::Path { .. } => {
                 PrimitiveOpacity::translucent()
             }
             PrimitiveTemplateKind::Rectangle { ref color, .. } => {
@@ -657,6 +703,17 @@ impl InternablePrimitive for PrimitiveKeyKind {
                     use_legacy_path: false,
                 }
             }
+            PrimitiveKeyKind::EmbedderDraw { .. } => {
+                PrimitiveInstanceKind::EmbedderDraw {
+                    data_handle
+                }
+            }
+            PrimitiveKeyKind::Path { .. } => {
+                PrimitiveInstanceKind::Path {
+                    data_handle,
+                    render_task: None,
+                }
+            }
         }
     }
 }
@@ -915,7 +972,9 @@ impl IsVisible for PrimitiveKeyKind {
     //           primitive types to use this.
     fn is_visible(&self) -> bool {
         match *self {
-            PrimitiveKeyKind::Clear => {
+            PrimitiveKeyKind::Clear |
+            PrimitiveKeyKind::EmbedderDraw { .. } |
+            PrimitiveKeyKind::Path { .. } => {
                 true
             }
             PrimitiveKeyKind::Rectangle { ref color, .. } => {
@@ -944,7 +1003,9 @@ impl CreateShadow for PrimitiveKeyKind {
                     color: PropertyBinding::Value(shadow.color.into()),
                 }
             }
-            PrimitiveKeyKind::Clear => {
+            PrimitiveKeyKind::Clear |
+            PrimitiveKeyKind::EmbedderDraw { .. } |
+            PrimitiveKeyKind::Path { .. } => {
                 panic!("bug: this prim is not supported in shadow contexts");
             }
         }
@@ -981,6 +1042,9 @@ pub enum PrimitiveInstanceKind {
         //           use them directly. This will remove cache_handle,
         //           but also the opacity, clip_task_id etc below.
         render_task: Option<RenderTaskId>,
+        /// Index to the per instance data of the line, if it skips the ink
+        /// of some text.
+        skip_ink: LineSkipInkIndex,
     },
     NormalBorder {
         /// Handle to the common interned data for this primitive.
@@ -1047,6 +1111,24 @@ pub enum PrimitiveInstanceKind {
         data_handle: BackdropRenderDataHandle,
         pic_index: PictureIndex,
     },
+    /// An area drawn by the embedder when compositing. It is only drawn if
+    /// promoted to a compositor surface.
+    EmbedderDraw {
+        /// Handle to the common interned data for this primitive.
+        data_handle: PrimitiveDataHandle,
+    },
+    /// A path rendered by the embedder's path renderer.
+    Path {
+        /// Handle to the common interned data for this primitive.
+        data_handle: PrimitiveDataHandle,
+        render_task: Option<RenderTaskId>,
+    },
+    /// A vector path rasterized by WebRender in to the texture cache.
+    VectorPath {
+        /// Handle to the common interned data for this primitive.
+        data_handle: VectorPathDataHandle,
+        texture_cache_handle: TextureCacheHandle,
+    },
 }
 
 impl PrimitiveInstanceKind {
@@ -1056,6 +1138,33 @@ impl PrimitiveInstanceKind {
             _ => panic!("bug: as_pic called on a prim that is not a picture"),
         }
     }
+
+    /// The kind of primitive and the unique id of its interned data, used to
+    /// attribute GPU cache validation failures.
+    pub fn validation_scope(&self) -> GpuCacheValidationScope {
+        let (kind, uid) = match self {
+            PrimitiveInstanceKind::Picture { data_handle, .. } => ("Picture", data_handle.uid()),
+            PrimitiveInstanceKind::TextRun { data_handle, .. } => ("TextRun", data_handle.uid()),
+            PrimitiveInstanceKind::LineDecoration { data_handle, .. } => ("LineDecoration", data_handle.uid()),
+            PrimitiveInstanceKind::NormalBorder { data_handle, .. } => ("NormalBorder", data_handle.uid()),
+            PrimitiveInstanceKind::ImageBorder { data_handle, .. } => ("ImageBorder", data_handle.uid()),
+            PrimitiveInstanceKind::Rectangle { data_handle, .. } => ("Rectangle", data_handle.uid()),
+            PrimitiveInstanceKind::YuvImage { data_handle, .. } => ("YuvImage", data_handle.uid()),
+            PrimitiveInstanceKind::Image { data_handle, .. } => ("Image", data_handle.uid()),
+            PrimitiveInstanceKind::LinearGradient { data_handle, .. } => ("LinearGradient", data_handle.uid()),
+            PrimitiveInstanceKind::CachedLinearGradient { data_handle, .. } => ("CachedLinearGradient", data_handle.uid()),
+            PrimitiveInstanceKind::RadialGradient { data_handle, .. } => ("RadialGradient", data_handle.uid()),
+            PrimitiveInstanceKind::ConicGradient { data_handle, .. } => ("ConicGradient", data_handle.uid()),
+            PrimitiveInstanceKind::Clear { data_handle } => ("Clear", data_handle.uid()),
+            PrimitiveInstanceKind::BackdropCapture { data_handle } => ("BackdropCapture", data_handle.uid()),
+            PrimitiveInstanceKind::BackdropRender { data_handle, .. } => ("BackdropRender", data_handle.uid()),
+            PrimitiveInstanceKind::EmbedderDraw { data_handle } => ("EmbedderDraw", data_handle.uid()),
+            PrimitiveInstanceKind::Path { data_handle, .. } => ("Path", data_handle.uid()),
+            PrimitiveInstanceKind::VectorPath { data_handle, .. } => ("VectorPath", data_handle.uid()),
+        };
+
+        GpuCacheValidationScope { kind, uid }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -1105,6 +1214,8 @@ impl PrimitiveInstance {
     pub fn uid(&self) -> intern::ItemUid {
         match &self.kind {
             PrimitiveInstanceKind::Clear { data_handle, .. } |
+            PrimitiveInstanceKind::EmbedderDraw { data_handle, .. } |
+            PrimitiveInstanceKind::Path { data_handle, .. } |
             PrimitiveInstanceKind::Rectangle { data_handle, .. } => {
                 data_handle.uid()
             }
@@ -1147,6 +1258,9 @@ impl PrimitiveInstance {
             PrimitiveInstanceKind::BackdropRender { data_handle, .. } => {
                 data_handle.uid()
             }
+            PrimitiveInstanceKind::VectorPath { data_handle, .. } => {
+                data_handle.uid()
+            }
         }
     }
 }
@@ -1172,6 +1286,12 @@ pub type ImageInstanceStorage = storage::Storage<ImageInstance>;
 pub type ImageInstanceIndex = storage::Index<ImageInstance>;
 pub type GradientTileStorage = storage::Storage<VisibleGradientTile>;
 pub type GradientTileRange = storage::Range<VisibleGradientTile>;
+pub type LineSegmentStorage = storage::Storage<LayoutRect>;
+pub type LineSegmentRange = storage::Range<LayoutRect>;
+pub type LineSegmentRangeStorage = storage::Storage<LineSegmentRange>;
+pub type LineSegmentRangeIndex = storage::Index<LineSegmentRange>;
+pub type LineSkipInkStorage = storage::Storage<LineSkipInkInstance>;
+pub type LineSkipInkIndex = storage::Index<LineSkipInkInstance>;
 pub type LinearGradientStorage = storage::Storage<LinearGradientPrimitive>;
 
 /// Contains various vecs of data that is used only during frame building,
@@ -1204,6 +1324,11 @@ pub struct PrimitiveScratchBuffer {
     /// per-tile information.
     pub gradient_tiles: GradientTileStorage,
 
+    /// The visible parts of ink-skipping line decorations, and the range of
+    /// them that each such line instance uses this frame.
+    pub line_segments: LineSegmentStorage,
+    pub line_segment_ranges: LineSegmentRangeStorage,
+
     /// List of debug display items for rendering.
     pub debug_items: Vec<DebugItem>,
 
@@ -1226,6 +1351,8 @@ impl Default for PrimitiveScratchBuffer {
             segments: SegmentStorage::new(0),
             segment_instances: SegmentInstanceStorage::new(0),
             gradient_tiles: GradientTileStorage::new(0),
+            line_segments: LineSegmentStorage::new(0),
+            line_segment_ranges: LineSegmentRangeStorage::new(0),
             debug_items: Vec::new(),
             messages: Vec::new(),
             required_sub_graphs: FastHashSet::default(),
@@ -1242,6 +1369,8 @@ impl PrimitiveScratchBuffer {
         self.segments.recycle(recycler);
         self.segment_instances.recycle(recycler);
         self.gradient_tiles.recycle(recycler);
+        self.line_segments.recycle(recycler);
+        self.line_segment_ranges.recycle(recycler);
         recycler.recycle_vec(&mut self.debug_items);
         recycler.recycle_vec(&mut self.quad_segments);
     }
@@ -1262,6 +1391,9 @@ impl PrimitiveScratchBuffer {
         //           should fix this in the future to retain handles.
         self.gradient_tiles.clear();
 
+        self.line_segments.clear();
+        self.line_segment_ranges.clear();
+
         self.required_sub_graphs.clear();
 
         self.debug_items.clear();
@@ -1360,6 +1492,7 @@ pub struct PrimitiveStoreStats {
     image_count: usize,
     linear_gradient_count: usize,
     color_binding_count: usize,
+    line_skip_ink_count: usize,
 }
 
 impl PrimitiveStoreStats {
@@ -1370,6 +1503,7 @@ impl PrimitiveStoreStats {
             image_count: 0,
             linear_gradient_count: 0,
             color_binding_count: 0,
+            line_skip_ink_count: 0,
         }
     }
 }
@@ -1387,6 +1521,9 @@ pub struct PrimitiveStore {
 
     /// animated color bindings for this primitive.
     pub color_bindings: ColorBindingStorage,
+
+    /// The line decorations that skip the ink of some text.
+    pub line_skip_inks: LineSkipInkStorage,
 }
 
 impl PrimitiveStore {
@@ -1397,6 +1534,7 @@ impl PrimitiveStore {
             images: ImageInstanceStorage::new(stats.image_count),
             color_bindings: ColorBindingStorage::new(stats.color_binding_count),
             linear_gradients: LinearGradientStorage::new(stats.linear_gradient_count),
+            line_skip_inks: LineSkipInkStorage::new(stats.line_skip_ink_count),
         }
     }
 
@@ -1407,6 +1545,7 @@ impl PrimitiveStore {
             image_count: self.images.len(),
             linear_gradient_count: self.linear_gradients.len(),
             color_binding_count: self.color_bindings.len(),
+            line_skip_ink_count: self.line_skip_inks.len(),
         }
     }
 
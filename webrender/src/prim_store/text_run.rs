@@ -6,7 +6,7 @@ use api::{ColorF, FontInstanceFlags, GlyphInstance, RasterSpace, Shadow};
 use api::units::{LayoutToWorldTransform, LayoutVector2D, RasterPixelScale, DevicePixelScale};
 use crate::scene_building::{CreateShadow, IsVisible};
 use crate::frame_builder::FrameBuildingState;
-use glyph_rasterizer::{FontInstance, FontTransform, GlyphKey, FONT_SIZE_LIMIT};
+use glyph_rasterizer::{FontInstance, FontTransform, GlyphKey, GlyphRequestPriority, FONT_SIZE_LIMIT, SDF_FONT_SIZE};
 use crate::gpu_cache::GpuCache;
 use crate::intern;
 use crate::internal_types::LayoutPrimitiveInfo;
@@ -266,12 +266,19 @@ impl TextRunPrimitive {
         let quantized_scale = (dps * raster_scale * 100.0).round() / 100.0;
         let mut device_font_size = font_size * quantized_scale;
 
+        // Distance field glyphs are rasterized at a fixed size whatever the
+        // scale, and scaled when drawn as oversized glyphs are.
+        let sdf = specified_font.flags.contains(FontInstanceFlags::SDF);
+        let size_limit = if sdf { SDF_FONT_SIZE } else { FONT_SIZE_LIMIT };
+
         // Check there is a valid transform that doesn't exceed the font size limit.
         // Ensure the font is supposed to be rasterized in screen-space.
         // Only support transforms that can be coerced to simple 2D transforms.
         // Add texture padding to the rasterized glyph buffer when one anticipates
         // the glyph will need to be scaled when rendered.
-        let (use_subpixel_aa, transform_glyphs, texture_padding, oversized) = if raster_space != RasterSpace::Screen ||
+        let (use_subpixel_aa, transform_glyphs, texture_padding, oversized) = if sdf {
+            (false, false, true, true)
+        } else if raster_space != RasterSpace::Screen ||
             transform.has_perspective_component() || !transform.has_2d_inverse()
         {
             (false, false, true, device_font_size > FONT_SIZE_LIMIT)
@@ -291,8 +298,8 @@ impl TextRunPrimitive {
                 // Font sizes larger than the limit need to be scaled, thus can't use subpixels.
                 // In this case we adjust the font size and raster space to ensure
                 // we rasterize at the limit, to minimize the amount of scaling.
-                let limited_raster_scale = FONT_SIZE_LIMIT / (font_size * dps);
-                device_font_size = FONT_SIZE_LIMIT;
+                let limited_raster_scale = size_limit / (font_size * dps);
+                device_font_size = size_limit;
 
                 // Record the raster space the text needs to be snapped in. The original raster
                 // scale would have been too big.
@@ -439,6 +446,7 @@ impl TextRunPrimitive {
         surface: &SurfaceInfo,
         spatial_node_index: SpatialNodeIndex,
         allow_subpixel: bool,
+        priority: GlyphRequestPriority,
         low_quality_pinch_zoom: bool,
         resource_cache: &mut ResourceCache,
         gpu_cache: &mut GpuCache,
@@ -481,6 +489,7 @@ impl TextRunPrimitive {
 
         resource_cache.request_glyphs(
             self.used_font.clone(),
+            priority,
             &scratch.glyph_keys[self.glyph_keys_range],
             gpu_cache,
         );
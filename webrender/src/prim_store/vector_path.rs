@@ -0,0 +1,441 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use api::{
+    ColorF, ColorU, ExtendMode, FillRule, ImageFormat, LineCap, LineJoin,
+    PathDrawMode, PathOp, PathPaint, PremultipliedColorF, RasterSpace, Shadow, StrokeStyle,
+};
+use api::units::*;
+use crate::scene_building::{CreateShadow, IsVisible};
+use crate::frame_builder::FrameBuildingState;
+use crate::gpu_cache::GpuDataRequest;
+use crate::intern;
+use crate::internal_types::LayoutPrimitiveInfo;
+use crate::path_rasterizer::rasterize_path;
+use crate::prim_store::{
+    FloatKey, PointKey, PrimKey, PrimTemplate, PrimTemplateCommonData,
+    InternablePrimitive, PrimitiveInstanceKind, PrimitiveStore, SizeKey,
+};
+use crate::prim_store::gradient::GradientStopKey;
+use crate::texture_cache::TextureCacheHandle;
+use crate::util::PrimaryArc;
+use euclid::vec2;
+use std::sync::Arc;
+
+/// Maximum resolution in device pixels at which vector paths are rasterized.
+pub const MAX_VECTOR_PATH_RESOLUTION: u32 = 2048;
+
+/// A hashable path segment, see `PathOp`.
+#[derive(Clone, Copy, Debug, Hash, MallocSizeOf, PartialEq, Eq)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub enum PathOpKey {
+    MoveTo(PointKey),
+    LineTo(PointKey),
+    QuadTo(PointKey, PointKey),
+    CubicTo(PointKey, PointKey, PointKey),
+    Close,
+}
+
+impl From<PathOp> for PathOpKey {
+    fn from(op: PathOp) -> Self {
+        match op {
+            PathOp::MoveTo(to) => PathOpKey::MoveTo(to.into()),
+            PathOp::LineTo(to) => PathOpKey::LineTo(to.into()),
+            PathOp::QuadTo(ctrl, to) => PathOpKey::QuadTo(ctrl.into(), to.into()),
+            PathOp::CubicTo(ctrl1, ctrl2, to) => {
+                PathOpKey::CubicTo(ctrl1.into(), ctrl2.into(), to.into())
+            }
+            PathOp::Close => PathOpKey::Close,
+        }
+    }
+}
+
+impl From<PathOpKey> for PathOp {
+    fn from(key: PathOpKey) -> Self {
+        match key {
+            PathOpKey::MoveTo(to) => PathOp::MoveTo(to.into()),
+            PathOpKey::LineTo(to) => PathOp::LineTo(to.into()),
+            PathOpKey::QuadTo(ctrl, to) => PathOp::QuadTo(ctrl.into(), to.into()),
+            PathOpKey::CubicTo(ctrl1, ctrl2, to) => {
+                PathOp::CubicTo(ctrl1.into(), ctrl2.into(), to.into())
+            }
+            PathOpKey::Close => PathOp::Close,
+        }
+    }
+}
+
+/// A hashable `PathDrawMode`.
+#[derive(Clone, Copy, Debug, Hash, MallocSizeOf, PartialEq, Eq)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub enum PathDrawModeKey {
+    Fill(FillRule),
+    Stroke {
+        width: FloatKey,
+        join: LineJoin,
+        cap: LineCap,
+        miter_limit: FloatKey,
+    },
+}
+
+impl From<PathDrawMode> for PathDrawModeKey {
+    fn from(mode: PathDrawMode) -> Self {
+        match mode {
+            PathDrawMode::Fill(fill_rule) => PathDrawModeKey::Fill(fill_rule),
+            PathDrawMode::Stroke(stroke) => PathDrawModeKey::Stroke {
+                width: FloatKey(stroke.width),
+                join: stroke.join,
+                cap: stroke.cap,
+                miter_limit: FloatKey(stroke.miter_limit),
+            },
+        }
+    }
+}
+
+/// The gradient a vector path is painted with, see `PathPaint`.
+#[derive(Clone, Debug, Hash, MallocSizeOf, PartialEq, Eq)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub enum PathGradientKey {
+    Linear {
+        start_point: PointKey,
+        end_point: PointKey,
+        extend_mode: ExtendMode,
+        stops: Vec<GradientStopKey>,
+    },
+    Radial {
+        center: PointKey,
+        radius: SizeKey,
+        start_offset: FloatKey,
+        end_offset: FloatKey,
+        extend_mode: ExtendMode,
+        stops: Vec<GradientStopKey>,
+    },
+}
+
+impl PathGradientKey {
+    /// Returns the unpremultiplied color of the gradient at `point`.
+    fn color_at(&self, point: LayoutPoint) -> ColorF {
+        let (t, extend_mode, stops) = match *self {
+            PathGradientKey::Linear { start_point, end_point, extend_mode, ref stops } => {
+                let start: LayoutPoint = start_point.into();
+                let dir = LayoutPoint::from(end_point) - start;
+                let length_sq = dir.square_length();
+                let t = if length_sq > 0.0 {
+                    (point - start).dot(dir) / length_sq
+                } else {
+                    0.0
+                };
+                (t, extend_mode, stops)
+            }
+            PathGradientKey::Radial { center, radius, start_offset, end_offset, extend_mode, ref stops } => {
+                let radius: LayoutSize = radius.into();
+                let d = point - LayoutPoint::from(center);
+                let distance = if radius.width > 0.0 && radius.height > 0.0 {
+                    vec2::<f32, LayoutPixel>(d.x / radius.width, d.y / radius.height).length()
+                } else {
+                    1.0
+                };
+                let range = end_offset.0 - start_offset.0;
+                let t = if range > 0.0 {
+                    (distance - start_offset.0) / range
+                } else {
+                    1.0
+                };
+                (t, extend_mode, stops)
+            }
+        };
+
+        let t = match extend_mode {
+            ExtendMode::Clamp => t,
+            ExtendMode::Repeat => t - t.floor(),
+        };
+
+        let first = match stops.first() {
+            Some(stop) => stop,
+            None => return ColorF::TRANSPARENT,
+        };
+        if t <= first.offset {
+            return first.color.into();
+        }
+        for pair in stops.windows(2) {
+            let (prev, next) = (&pair[0], &pair[1]);
+            if t <= next.offset {
+                let range = next.offset - prev.offset;
+                let f = if range > 0.0 { (t - prev.offset) / range } else { 1.0 };
+                let (a, b): (ColorF, ColorF) = (prev.color.into(), next.color.into());
+                return ColorF::new(
+                    a.r + (b.r - a.r) * f,
+                    a.g + (b.g - a.g) * f,
+                    a.b + (b.b - a.b) * f,
+                    a.a + (b.a - a.a) * f,
+                );
+            }
+        }
+        stops.last().unwrap().color.into()
+    }
+}
+
+/// The geometry and gradient of a vector path. This is shared between the
+/// primitive key and the cache key of the rasterized path.
+#[derive(Clone, Debug, Hash, MallocSizeOf, PartialEq, Eq)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct VectorPathShape {
+    pub ops: Vec<PathOpKey>,
+    pub mode: PathDrawModeKey,
+    /// Solid paths are cached as coverage masks and colored when drawn, so
+    /// that they can be shared between colors and shadows.
+    pub gradient: Option<PathGradientKey>,
+}
+
+/// Identifying key for a vector path rasterized in to the texture cache.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct VectorPathCacheKey {
+    pub shape: PrimaryArc<VectorPathShape>,
+    /// The local size of the path's bounds.
+    pub local_size: LayoutSizeAu,
+    /// The size the path is rasterized at, in device pixels.
+    pub size: DeviceIntSize,
+}
+
+impl VectorPathCacheKey {
+    /// Rasterizes the path in to pixels of the given format, which is either
+    /// a single channel coverage mask or premultiplied color.
+    pub fn rasterize(&self, format: ImageFormat) -> Vec<u8> {
+        let local_size = LayoutSize::from_au(self.local_size);
+        let scale = vec2(
+            self.size.width as f32 / local_size.width,
+            self.size.height as f32 / local_size.height,
+        );
+        let (width, height) = (self.size.width as usize, self.size.height as usize);
+
+        let ops: Vec<PathOp> = self.shape.ops.iter().map(|&op| op.into()).collect();
+        let coverage = match self.shape.mode {
+            PathDrawModeKey::Fill(fill_rule) => {
+                rasterize_path(&ops, fill_rule, None, scale, width, height)
+            }
+            PathDrawModeKey::Stroke { width: stroke_width, join, cap, miter_limit } => {
+                let stroke = StrokeStyle {
+                    width: stroke_width.0,
+                    join,
+                    cap,
+                    miter_limit: miter_limit.0,
+                };
+                rasterize_path(&ops, FillRule::Nonzero, Some(&stroke), scale, width, height)
+            }
+        };
+
+        let gradient = match self.shape.gradient {
+            Some(ref gradient) => gradient,
+            None if format == ImageFormat::R8 => return coverage,
+            None => {
+                return coverage.iter().flat_map(|&a| vec![a; 4]).collect();
+            }
+        };
+
+        let mut pixels = Vec::with_capacity(coverage.len() * 4);
+        for (i, &a) in coverage.iter().enumerate() {
+            if a == 0 {
+                pixels.extend_from_slice(&[0; 4]);
+                continue;
+            }
+            let point = LayoutPoint::new(
+                ((i % width) as f32 + 0.5) / scale.x,
+                ((i / width) as f32 + 0.5) / scale.y,
+            );
+            let color = gradient
+                .color_at(point)
+                .scale_alpha(a as f32 / 255.0)
+                .premultiplied();
+            let to_u8 = |c: f32| (c * 255.0).round() as u8;
+            let (r, g, b, a) = (to_u8(color.r), to_u8(color.g), to_u8(color.b), to_u8(color.a));
+            match format {
+                ImageFormat::BGRA8 => pixels.extend_from_slice(&[b, g, r, a]),
+                _ => pixels.extend_from_slice(&[r, g, b, a]),
+            }
+        }
+        pixels
+    }
+}
+
+/// Identifying key for a vector path.
+#[derive(Clone, Debug, Hash, MallocSizeOf, PartialEq, Eq)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct VectorPath {
+    pub shape: PrimaryArc<VectorPathShape>,
+    /// The color of a solid path, white for gradients.
+    pub color: ColorU,
+}
+
+impl VectorPath {
+    pub fn new(
+        ops: Vec<PathOpKey>,
+        mode: PathDrawMode,
+        paint: PathPaint,
+        stops: Vec<GradientStopKey>,
+    ) -> Self {
+        let (color, gradient) = match paint {
+            PathPaint::Solid(color) => (color.into(), None),
+            PathPaint::LinearGradient(gradient) => (ColorU::new(255, 255, 255, 255), Some(
+                PathGradientKey::Linear {
+                    start_point: gradient.start_point.into(),
+                    end_point: gradient.end_point.into(),
+                    extend_mode: gradient.extend_mode,
+                    stops,
+                }
+            )),
+            PathPaint::RadialGradient(gradient) => (ColorU::new(255, 255, 255, 255), Some(
+                PathGradientKey::Radial {
+                    center: gradient.center.into(),
+                    radius: gradient.radius.into(),
+                    start_offset: FloatKey(gradient.start_offset),
+                    end_offset: FloatKey(gradient.end_offset),
+                    extend_mode: gradient.extend_mode,
+                    stops,
+                }
+            )),
+        };
+
+        VectorPath {
+            shape: PrimaryArc(Arc::new(VectorPathShape {
+                ops,
+                mode: mode.into(),
+                gradient,
+            })),
+            color,
+        }
+    }
+}
+
+pub type VectorPathKey = PrimKey<VectorPath>;
+
+impl VectorPathKey {
+    pub fn new(
+        info: &LayoutPrimitiveInfo,
+        path: VectorPath,
+    ) -> Self {
+        VectorPathKey {
+            common: info.into(),
+            kind: path,
+        }
+    }
+}
+
+impl intern::InternDebug for VectorPathKey {}
+
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+#[derive(MallocSizeOf)]
+pub struct VectorPathData {
+    pub shape: PrimaryArc<VectorPathShape>,
+    pub color: ColorF,
+}
+
+impl VectorPathData {
+    /// Update the GPU cache for a given primitive template. This may be called multiple
+    /// times per frame, by each primitive reference that refers to this interned
+    /// template. The initial request call to the GPU cache ensures that work is only
+    /// done if the cache entry is invalid (due to first use or eviction).
+    pub fn update(
+        &mut self,
+        common: &mut PrimTemplateCommonData,
+        frame_state: &mut FrameBuildingState,
+    ) {
+        if let Some(ref mut request) = frame_state.gpu_cache.request(&mut common.gpu_cache_handle) {
+            self.write_prim_gpu_blocks(common.prim_rect.size(), request);
+        }
+    }
+
+    fn write_prim_gpu_blocks(
+        &self,
+        size: LayoutSize,
+        request: &mut GpuDataRequest
+    ) {
+        // Drawn as an image brush.
+        request.push(self.color.premultiplied());
+        request.push(PremultipliedColorF::WHITE);
+        request.push([size.width, size.height, 0.0, 0.0]);
+    }
+}
+
+pub type VectorPathTemplate = PrimTemplate<VectorPathData>;
+
+impl From<VectorPathKey> for VectorPathTemplate {
+    fn from(path: VectorPathKey) -> Self {
+        let common = PrimTemplateCommonData::with_key_common(path.common);
+        VectorPathTemplate {
+            common,
+            kind: VectorPathData {
+                shape: path.kind.shape,
+                color: path.kind.color.into(),
+            }
+        }
+    }
+}
+
+pub type VectorPathDataHandle = intern::Handle<VectorPath>;
+
+impl intern::Internable for VectorPath {
+    type Key = VectorPathKey;
+    type StoreData = VectorPathTemplate;
+    type InternData = ();
+    const PROFILE_COUNTER: usize = crate::profiler::INTERNED_VECTOR_PATHS;
+}
+
+impl InternablePrimitive for VectorPath {
+    fn into_key(
+        self,
+        info: &LayoutPrimitiveInfo,
+    ) -> VectorPathKey {
+        VectorPathKey::new(
+            info,
+            self,
+        )
+    }
+
+    fn make_instance_kind(
+        _key: VectorPathKey,
+        data_handle: VectorPathDataHandle,
+        _: &mut PrimitiveStore,
+        _reference_frame_relative_offset: LayoutVector2D,
+    ) -> PrimitiveInstanceKind {
+        PrimitiveInstanceKind::VectorPath {
+            data_handle,
+            texture_cache_handle: TextureCacheHandle::invalid(),
+        }
+    }
+}
+
+impl CreateShadow for VectorPath {
+    fn create_shadow(
+        &self,
+        shadow: &Shadow,
+        _: bool,
+        _: RasterSpace,
+    ) -> Self {
+        // Shadows only need the coverage of the path.
+        let shape = match self.shape.gradient {
+            Some(..) => PrimaryArc(Arc::new(VectorPathShape {
+                gradient: None,
+                ..(**self.shape).clone()
+            })),
+            None => self.shape.clone(),
+        };
+        VectorPath {
+            shape,
+            color: shadow.color.into(),
+        }
+    }
+}
+
+impl IsVisible for VectorPath {
+    fn is_visible(&self) -> bool {
+        self.color.a > 0 && !self.shape.ops.is_empty()
+    }
+}
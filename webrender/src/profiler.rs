@@ -18,8 +18,11 @@
 //! - An empty token to insert a bit of vertical space.
 //! - A '|' token to start a new column.
 //! - A '_' token to start a new row.
+//!
+//! Embedders can add their own counters with `RenderApi::register_profiler_counter`. They are
+//! referred to by name in the UI string like any other counter.
 
-use api::{ColorF, ColorU};
+use api::{ColorF, ColorU, RenderReasons};
 use glyph_rasterizer::profiler::GlyphRasterizeProfiler;
 use crate::renderer::DebugRenderer;
 use crate::device::query::GpuTimer;
@@ -30,6 +33,8 @@ use api::units::DeviceIntSize;
 use std::collections::vec_deque::VecDeque;
 use std::fmt::{Write, Debug};
 use std::f32;
+use std::fs::File;
+use std::io::{self, BufWriter};
 use std::ops::Range;
 use std::time::Duration;
 use time::precise_time_ns;
@@ -66,6 +71,8 @@ static PROFILER_PRESETS: &'static[(&'static str, &'static str)] = &[
     (&"Transaction times", &"DisplayList,Scene building,Content send,API send"),
     // Timing information for per-frame stages.
     (&"Frame times", &"Frame CPU total,Frame building,Visibility,Prepare,Batching,Glyph resolve,Texture cache update,Shader build time,Renderer,GPU"),
+    // Time from frame requests to rendering, and frames that missed their vsync.
+    (&"Latency", &"Frame latency,Backend queue,Missed vsync deadlines"),
     // Stats about the content of the frame.
     (&"Frame stats", &"Primitives,Visible primitives,Draw calls,Vertices,Color passes,Alpha passes,Rendered picture tiles,Rasterized glyphs"),
     // Texture cache allocation stats.
@@ -73,7 +80,8 @@ static PROFILER_PRESETS: &'static[(&'static str, &'static str)] = &[
         Texture cache standalone pressure, Texture cache eviction count, Texture cache youngest evicted, ,
         Atlas RGBA8 linear pixels, Atlas RGBA8 glyphs pixels, Atlas A8 glyphs pixels, Atlas A8 pixels, Atlas A16 pixels, Atlas RGBA8 nearest pixels,
         Atlas RGBA8 linear textures, Atlas RGBA8 glyphs textures, Atlas A8 glyphs textures, Atlas A8 textures, Atlas A16 textures, Atlas RGBA8 nearest textures,
-        Atlas RGBA8 linear pressure, Atlas RGBA8 glyphs pressure, Atlas A8 glyphs pressure, Atlas A8 pressure, Atlas A16 pressure, Atlas RGBA8 nearest pressure,"
+        Atlas RGBA8 linear pressure, Atlas RGBA8 glyphs pressure, Atlas A8 glyphs pressure, Atlas A8 pressure, Atlas A16 pressure, Atlas RGBA8 nearest pressure,
+        Atlas glyphs fragmentation, Atlas images fragmentation,"
     ),
     // Graphs to investigate driver overhead of texture cache updates.
     (&"Texture upload perf", &"#Texture cache update,#Texture cache upload, ,#Staging CPU allocation,#Staging GPU allocation,#Staging CPU copy,#Staging GPU copy,#Upload time, ,#Upload copy batches,#Rasterized glyphs, ,#Cache texture creation,#Cache texture deletion"),
@@ -256,7 +264,26 @@ pub const RENDER_REASON_VSYNC: usize = 119;
 pub const TEXTURES_CREATED: usize = 120;
 pub const TEXTURES_DELETED: usize = 121;
 
-pub const NUM_PROFILER_EVENTS: usize = 122;
+pub const SCROLL_ONLY_FRAMES: usize = 122;
+
+pub const ATLAS_GLYPHS_FRAGMENTATION: usize = 123;
+pub const ATLAS_IMAGES_FRAGMENTATION: usize = 124;
+
+pub const POWER_SAVING_SKIPPED_FRAMES: usize = 125;
+
+pub const SUBPIXEL_TEXT_FALLBACKS: usize = 126;
+
+pub const INTERNED_VECTOR_PATHS: usize = 127;
+
+pub const FRAME_LATENCY: usize = 128;
+pub const BACKEND_QUEUE_TIME: usize = 129;
+pub const MISSED_VSYNC_DEADLINES: usize = 130;
+
+pub const NO_OP_FRAME_REQUESTS: usize = 131;
+
+pub const INTERNED_LINE_SKIP_INKS: usize = 132;
+
+pub const NUM_PROFILER_EVENTS: usize = 133;
 
 pub struct Profiler {
     counters: Vec<Counter>,
@@ -271,6 +298,9 @@ pub struct Profiler {
     frame_timestamps_within_last_second: Vec<u64>,
 
     ui: Vec<Item>,
+    render_reason_stats: RenderReasonStats,
+    // The last UI string, so that it can be re-parsed when new counters are registered.
+    ui_str: String,
 }
 
 impl Profiler {
@@ -287,6 +317,8 @@ impl Profiler {
         // Not in the list below:
         // - "GPU time queries" shows the details of the GPU time queries if selected as a graph.
         // - "GPU cache bars" shows some info about the GPU cache.
+        // - "Render reason totals" shows the number of frames and CPU time attributed to each
+        //   render reason since the statistics were last reset.
 
         // TODO: This should be a global variable but to keep things readable we need to be able to
         // use match in const fn which isn't supported by the current rustc version in gecko's build
@@ -433,6 +465,21 @@ impl Profiler {
 
             int("Textures created", "", TEXTURES_CREATED, expected(0..5)),
             int("Textures deleted", "", TEXTURES_DELETED, Expected::none()),
+
+            int("Scroll-only frames", "", SCROLL_ONLY_FRAMES, Expected::none()),
+
+            float("Atlas glyphs fragmentation", "", ATLAS_GLYPHS_FRAGMENTATION, expected(0.0..0.5)),
+            float("Atlas images fragmentation", "", ATLAS_IMAGES_FRAGMENTATION, expected(0.0..0.7)),
+
+            int("Power-saving skipped frames", "", POWER_SAVING_SKIPPED_FRAMES, Expected::none()),
+            int("Grayscale text fallbacks", "", SUBPIXEL_TEXT_FALLBACKS, Expected::none()),
+            int("Interned vector paths", "", INTERNED_VECTOR_PATHS, Expected::none()),
+
+            float("Frame latency", "ms", FRAME_LATENCY, expected(0.0..34.0).avg(0.0..20.0)),
+            float("Backend queue", "ms", BACKEND_QUEUE_TIME, expected(0.0..4.0).avg(0.0..2.0)),
+            int("Missed vsync deadlines", "", MISSED_VSYNC_DEADLINES, expected(0..0)),
+            int("No-op frame requests", "", NO_OP_FRAME_REQUESTS, Expected::none()),
+            int("Interned line skip inks", "", INTERNED_LINE_SKIP_INKS, Expected::none()),
         ];
 
         let mut counters = Vec::with_capacity(profile_counters.len());
@@ -453,6 +500,8 @@ impl Profiler {
             num_graph_samples: 500, // Would it be useful to control this via a pref?
             frame_timestamps_within_last_second: Vec::new(),
             ui: Vec::new(),
+            render_reason_stats: RenderReasonStats::default(),
+            ui_str: String::new(),
         }
     }
 
@@ -527,6 +576,10 @@ impl Profiler {
 
     // Define the profiler UI, see comment about the syntax at the top of this file.
     pub fn set_ui(&mut self, names: &str) {
+        if names != self.ui_str {
+            self.ui_str = names.to_string();
+        }
+
         let mut selection = Vec::new();
 
         self.append_to_ui(&mut selection, names);
@@ -597,6 +650,10 @@ impl Profiler {
                     flush_counters(&mut counters, selection);
                     selection.push(Item::PaintPhaseGraph);
                 }
+                "Render reason totals" => {
+                    flush_counters(&mut counters, selection);
+                    selection.push(Item::RenderReasonTotals);
+                }
                 _ => {
                     if let Some(idx) = self.index_of(name) {
                         if is_graph {
@@ -634,6 +691,69 @@ impl Profiler {
         self.counters[id].get()
     }
 
+    /// Attribute a rendered frame and its CPU time to each of its render reasons.
+    pub fn record_render_reasons(&mut self, reasons: RenderReasons, cpu_time_ms: f64) {
+        self.render_reason_stats.record(reasons, cpu_time_ms);
+    }
+
+    pub fn render_reason_stats(&self) -> &RenderReasonStats {
+        &self.render_reason_stats
+    }
+
+    pub fn reset_render_reason_stats(&mut self) {
+        self.render_reason_stats = RenderReasonStats::default();
+    }
+
+    /// Add a counter that isn't known to WebRender, typically to display timings
+    /// measured by the embedder. Registering the same name twice has no effect.
+    pub fn register_custom_counter(&mut self, name: &'static str, unit: &'static str) {
+        if self.index_of(name).is_some() {
+            return;
+        }
+
+        self.counters.push(Counter::new(&CounterDescriptor {
+            name,
+            unit,
+            show_as: ShowAs::Float,
+            index: self.counters.len(),
+            expected: Expected::none(),
+        }));
+
+        // The UI may already refer to this counter.
+        let ui_str = std::mem::take(&mut self.ui_str);
+        self.set_ui(&ui_str);
+    }
+
+    /// Set the value of a counter added with `register_custom_counter`.
+    ///
+    /// Returns false if there is no such counter.
+    pub fn set_custom_counter(&mut self, name: &str, value: f64) -> bool {
+        match self.counters[NUM_PROFILER_EVENTS..].iter_mut().find(|counter| counter.name == name) {
+            Some(counter) => {
+                counter.set_f64(value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Take a snapshot of the current value of every counter.
+    ///
+    /// Must be called after `set_counters` and before `update`, since the
+    /// latter resets the per-frame values.
+    pub fn frame_counters(&self, frame_index: u64) -> FrameCounters {
+        FrameCounters {
+            frame_index,
+            counters: self.counters.iter().map(|counter| {
+                CounterSample {
+                    name: counter.name,
+                    unit: counter.unit,
+                    value: counter.get(),
+                }
+            }).collect(),
+        }
+    }
+
     fn draw_counters(
         counters: &[Counter],
         selected: &[usize],
@@ -966,6 +1086,48 @@ impl Profiler {
         total_rect
     }
 
+    fn draw_render_reason_totals(&self, x: f32, y: f32, text_buffer: &mut String, debug_renderer: &mut DebugRenderer) -> default::Rect<f32> {
+        let line_height = debug_renderer.line_height();
+        let x_base = x + PROFILE_PADDING;
+        let mut y = y + PROFILE_PADDING + line_height;
+
+        let mut rect = debug_renderer.add_text(
+            x_base,
+            y,
+            "Render reason totals",
+            ColorU::new(255, 255, 0, 255),
+            None,
+        );
+
+        for (reason, frames, cpu_time_ms) in self.render_reason_stats.iter() {
+            if frames == 0 {
+                continue;
+            }
+            y += line_height;
+            set_text!(text_buffer, "{:?}: {} frames, {:.1} ms", reason, frames, cpu_time_ms);
+            let text_rect = debug_renderer.add_text(
+                x_base,
+                y,
+                text_buffer,
+                ColorU::new(255, 255, 255, 255),
+                None,
+            );
+            rect = rect.union(&text_rect);
+        }
+
+        rect = rect.inflate(PROFILE_PADDING, PROFILE_PADDING);
+        debug_renderer.add_quad(
+            rect.min_x(),
+            rect.min_y(),
+            rect.max_x(),
+            rect.max_y(),
+            BACKGROUND_COLOR,
+            BACKGROUND_COLOR,
+        );
+
+        rect
+    }
+
     // Draws a frame graph for a given frame collection.
     fn draw_frame_graph(
         frame_collection: &ProfilerFrameCollection,
@@ -1134,6 +1296,9 @@ impl Profiler {
                 Item::PaintPhaseGraph => {
                     Profiler::draw_frame_graph(&self.frame_stats, x, y, debug_renderer)
                 }
+                Item::RenderReasonTotals => {
+                    self.draw_render_reason_totals(x, y, &mut text_buffer, debug_renderer)
+                }
                 Item::Text(text) => {
                     let p = 10.0;
                     let mut rect = debug_renderer.add_text(
@@ -1225,6 +1390,178 @@ impl Profiler {
     }
 }
 
+/// Number of rendered frames and CPU time attributed to each render reason.
+///
+/// A frame rendered for several reasons counts toward each of them.
+#[derive(Clone, Debug, Default)]
+pub struct RenderReasonStats {
+    frames: [u64; RenderReasons::NUM_BITS as usize],
+    cpu_time_ms: [f64; RenderReasons::NUM_BITS as usize],
+}
+
+impl RenderReasonStats {
+    pub fn record(&mut self, reasons: RenderReasons, cpu_time_ms: f64) {
+        for i in 0..RenderReasons::NUM_BITS as usize {
+            if reasons.bits() & (1 << i) != 0 {
+                self.frames[i] += 1;
+                self.cpu_time_ms[i] += cpu_time_ms;
+            }
+        }
+    }
+
+    /// Number of frames rendered with the given reason, and their total CPU time in milliseconds.
+    pub fn get(&self, reason: RenderReasons) -> (u64, f64) {
+        let bit = reason.bits().trailing_zeros() as usize;
+        debug_assert_eq!(reason.bits(), 1 << bit);
+        (self.frames[bit], self.cpu_time_ms[bit])
+    }
+
+    /// Iterate over every render reason along with its frame count and CPU time.
+    pub fn iter(&self) -> impl Iterator<Item = (RenderReasons, u64, f64)> + '_ {
+        (0..RenderReasons::NUM_BITS as usize).map(move |i| {
+            (RenderReasons::from_bits_truncate(1 << i), self.frames[i], self.cpu_time_ms[i])
+        })
+    }
+}
+
+/// The value of a single profiler counter in a given frame.
+#[derive(Clone, Debug)]
+pub struct CounterSample {
+    pub name: &'static str,
+    pub unit: &'static str,
+    /// None if the counter was not recorded during the frame.
+    pub value: Option<f64>,
+}
+
+/// The full set of profiler counters for a rendered frame.
+///
+/// This is the programmatic equivalent of what the profiler HUD displays,
+/// intended for perf harnesses and embedder telemetry.
+#[derive(Clone, Debug)]
+pub struct FrameCounters {
+    pub frame_index: u64,
+    pub counters: Vec<CounterSample>,
+}
+
+impl FrameCounters {
+    /// Look up the value of a counter by its displayed name.
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.counters
+            .iter()
+            .find(|counter| counter.name == name)
+            .and_then(|counter| counter.value)
+    }
+
+    /// Write the CSV header naming each counter. Names that contain a comma,
+    /// a quote or a line break are quoted.
+    pub fn write_csv_header(&self, sink: &mut dyn io::Write) -> io::Result<()> {
+        write!(sink, "frame")?;
+        for counter in &self.counters {
+            let name = if counter.unit.is_empty() {
+                counter.name.to_string()
+            } else {
+                format!("{} ({})", counter.name, counter.unit)
+            };
+            if name.contains(&[',', '"', '\n', '\r'][..]) {
+                write!(sink, ",\"{}\"", name.replace('"', "\"\""))?;
+            } else {
+                write!(sink, ",{}", name)?;
+            }
+        }
+        writeln!(sink)
+    }
+
+    /// Write the counters as a CSV row. Missing values are left empty.
+    pub fn write_csv_row(&self, sink: &mut dyn io::Write) -> io::Result<()> {
+        write!(sink, "{}", self.frame_index)?;
+        for counter in &self.counters {
+            match counter.value {
+                Some(value) => write!(sink, ",{}", value)?,
+                None => write!(sink, ",")?,
+            }
+        }
+        writeln!(sink)
+    }
+
+    /// Write the counters as a single line JSON object. Missing values are omitted.
+    pub fn write_json(&self, sink: &mut dyn io::Write) -> io::Result<()> {
+        write!(sink, "{{\"frame\":{},\"counters\":{{", self.frame_index)?;
+        let mut first = true;
+        for counter in &self.counters {
+            if let Some(value) = counter.value {
+                if !first {
+                    write!(sink, ",")?;
+                }
+                write_json_string(sink, counter.name)?;
+                write!(sink, ":{}", value)?;
+                first = false;
+            }
+        }
+        writeln!(sink, "}}}}")
+    }
+}
+
+fn write_json_string(sink: &mut dyn io::Write, s: &str) -> io::Result<()> {
+    write!(sink, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(sink, "\\\"")?,
+            '\\' => write!(sink, "\\\\")?,
+            '\n' => write!(sink, "\\n")?,
+            '\r' => write!(sink, "\\r")?,
+            '\t' => write!(sink, "\\t")?,
+            c if (c as u32) < 0x20 => write!(sink, "\\u{:04x}", c as u32)?,
+            c => write!(sink, "{}", c)?,
+        }
+    }
+    write!(sink, "\"")
+}
+
+/// File format used when streaming profiler counters to disk.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CounterExportFormat {
+    /// One header line followed by one line per frame.
+    Csv,
+    /// One JSON object per line (JSON lines).
+    Json,
+}
+
+/// Streams the profiler counters of each rendered frame to a file.
+pub struct CounterExporter {
+    sink: BufWriter<File>,
+    format: CounterExportFormat,
+    wrote_header: bool,
+}
+
+impl CounterExporter {
+    pub fn new(path: &std::path::Path, format: CounterExportFormat) -> io::Result<Self> {
+        Ok(CounterExporter {
+            sink: BufWriter::new(File::create(path)?),
+            format,
+            wrote_header: false,
+        })
+    }
+
+    pub fn write(&mut self, counters: &FrameCounters) -> io::Result<()> {
+        match self.format {
+            CounterExportFormat::Csv => {
+                if !self.wrote_header {
+                    counters.write_csv_header(&mut self.sink)?;
+                    self.wrote_header = true;
+                }
+                counters.write_csv_row(&mut self.sink)
+            }
+            CounterExportFormat::Json => {
+                counters.write_json(&mut self.sink)
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        io::Write::flush(&mut self.sink)
+    }
+}
+
 /// Defines the interface for hooking up an external profiler to WR.
 pub trait ProfilerHooks : Send + Sync {
     /// Register a thread with the profiler.
@@ -1253,6 +1590,25 @@ pub trait ProfilerHooks : Send + Sync {
 
     /// Returns true if the current thread is being profiled.
     fn thread_is_being_profiled(&self) -> bool;
+
+    /// Called once per renderer, with the current GPU timestamp in nanoseconds,
+    /// to register a GPU timeline. Returns an id for the timeline, or None if
+    /// the profiler doesn't record GPU markers.
+    fn register_gpu_context(&self, _gpu_time_ns: i64) -> Option<u8> {
+        None
+    }
+
+    /// Called at the beginning of a GPU profile scope. The GPU timestamp of
+    /// the boundary is supplied later on, through `add_gpu_time` with the
+    /// same `query_id`.
+    fn begin_gpu_marker(&self, _context: u8, _label: &str, _query_id: u16) {}
+
+    /// Called at the end of the current GPU profile scope.
+    fn end_gpu_marker(&self, _context: u8, _query_id: u16) {}
+
+    /// Called with the GPU timestamp of a GPU marker boundary, in nanoseconds,
+    /// once it is available. This is usually a few frames after the marker.
+    fn add_gpu_time(&self, _context: u8, _query_id: u16, _gpu_time_ns: i64) {}
 }
 
 /// The current global profiler callbacks, if set by embedder.
@@ -1312,6 +1668,41 @@ pub fn add_event_marker(label: &str) {
     }
 }
 
+/// Registers a GPU timeline, see `ProfilerHooks::register_gpu_context`. The
+/// current GPU time is only queried if profiler hooks are set.
+pub fn register_gpu_context<F: FnOnce() -> i64>(gpu_time_ns: F) -> Option<u8> {
+    unsafe {
+        PROFILER_HOOKS.and_then(|h| h.register_gpu_context(gpu_time_ns()))
+    }
+}
+
+/// Records the beginning of a GPU profile scope.
+pub fn begin_gpu_marker(context: u8, label: &str, query_id: u16) {
+    unsafe {
+        if let Some(ref hooks) = PROFILER_HOOKS {
+            hooks.begin_gpu_marker(context, label, query_id);
+        }
+    }
+}
+
+/// Records the end of the current GPU profile scope.
+pub fn end_gpu_marker(context: u8, query_id: u16) {
+    unsafe {
+        if let Some(ref hooks) = PROFILER_HOOKS {
+            hooks.end_gpu_marker(context, query_id);
+        }
+    }
+}
+
+/// Supplies the GPU timestamp of a GPU marker boundary.
+pub fn add_gpu_time(context: u8, query_id: u16, gpu_time_ns: i64) {
+    unsafe {
+        if let Some(ref hooks) = PROFILER_HOOKS {
+            hooks.add_gpu_time(context, query_id, gpu_time_ns);
+        }
+    }
+}
+
 /// Returns true if the current thread is being profiled.
 pub fn thread_is_being_profiled() -> bool {
     unsafe {
@@ -1848,9 +2239,45 @@ enum Item {
     GpuTimeQueries,
     GpuCacheBars,
     PaintPhaseGraph,
+    RenderReasonTotals,
     Text(String),
     Space,
     Column,
     Row,
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn counters_with_name(name: &'static str) -> FrameCounters {
+        FrameCounters {
+            frame_index: 3,
+            counters: vec![
+                CounterSample { name, unit: "ms", value: Some(1.5) },
+                CounterSample { name: "Missing", unit: "", value: None },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_counter_names_are_escaped() {
+        let counters = counters_with_name("a \"b\", c\\d");
+
+        let mut json = Vec::new();
+        counters.write_json(&mut json).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            "{\"frame\":3,\"counters\":{\"a \\\"b\\\", c\\\\d\":1.5}}\n",
+        );
+
+        let mut csv = Vec::new();
+        counters.write_csv_header(&mut csv).unwrap();
+        counters.write_csv_row(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "frame,\"a \"\"b\"\", c\\d (ms)\",Missing\n3,1.5,\n",
+        );
+    }
+}
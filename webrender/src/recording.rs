@@ -0,0 +1,388 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Recordings of the transactions sent by an embedder.
+//!
+//! A recording is started and stopped with `DebugCommand::StartTransactionRecording`
+//! and `DebugCommand::StopTransactionRecording`. While it is active, every
+//! transaction sent through any `RenderApi` of the WebRender instance is appended
+//! to the recording along with the time it was sent, so that tools such as wrench
+//! can replay it with the original timing.
+//!
+//! The file is a bincode stream made of a `RecordingHeader` followed by any number
+//! of `RecordedTransaction`s. Display lists are stored in their binary form, so a
+//! recording can only be replayed by the version of WebRender that produced it.
+//! Recordings should be started before the first transaction of a document, as
+//! the state built by earlier transactions isn't saved.
+
+#[cfg(any(feature = "capture", feature = "replay"))]
+use api::{BuiltDisplayListDescriptor, DocumentId, DynamicProperties, Epoch, ExternalScrollId};
+#[cfg(any(feature = "capture", feature = "replay"))]
+use api::{PipelineId, PropertyBindingId, PropertyValue, QualitySettings, SampledScrollOffset};
+#[cfg(any(feature = "capture", feature = "replay"))]
+use api::units::{DeviceIntRect, LayoutTransform};
+#[cfg(any(feature = "capture", feature = "replay"))]
+use api::{AccessibilityColorFilter, PostProcessParams, SubpixelTextPolicy};
+#[cfg(feature = "replay")]
+use api::{BuiltDisplayList, DisplayListPayload, RenderReasons};
+use std::path::PathBuf;
+#[cfg(feature = "capture")]
+use std::sync::Mutex;
+#[cfg(any(feature = "capture", feature = "replay"))]
+use std::fs::File;
+#[cfg(any(feature = "capture", feature = "replay"))]
+use std::io;
+#[cfg(feature = "capture")]
+use std::io::BufWriter;
+#[cfg(feature = "replay")]
+use std::io::BufReader;
+#[cfg(any(feature = "capture", feature = "replay"))]
+use crate::render_api::ResourceUpdate;
+#[cfg(feature = "capture")]
+use crate::render_api::{FrameMsg, SceneMsg, TransactionMsg};
+#[cfg(feature = "replay")]
+use crate::render_api::Transaction;
+#[cfg(feature = "capture")]
+use time::precise_time_ns;
+
+/// Identifies the format of a recording.
+#[cfg(any(feature = "capture", feature = "replay"))]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct RecordingHeader {
+    /// The version of WebRender that produced the recording.
+    pub webrender_version: String,
+}
+
+#[cfg(any(feature = "capture", feature = "replay"))]
+impl RecordingHeader {
+    fn current() -> Self {
+        RecordingHeader {
+            webrender_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+/// A display list, in its binary form.
+#[cfg(any(feature = "capture", feature = "replay"))]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct RecordedDisplayList {
+    ///
+    pub pipeline_id: PipelineId,
+    ///
+    pub epoch: Epoch,
+    ///
+    pub items_data: Vec<u8>,
+    ///
+    pub cache_data: Vec<u8>,
+    ///
+    pub spatial_tree: Vec<u8>,
+    ///
+    pub descriptor: BuiltDisplayListDescriptor,
+}
+
+/// The recorded counterpart of a `SceneMsg`.
+#[cfg(any(feature = "capture", feature = "replay"))]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub enum RecordedSceneOp {
+    ///
+    UpdateEpoch(PipelineId, Epoch),
+    ///
+    SetRootPipeline(PipelineId),
+    ///
+    RemovePipeline(PipelineId),
+    ///
+    SetDisplayList(RecordedDisplayList),
+    ///
+    SetDocumentView(DeviceIntRect),
+    ///
+    SetQualitySettings(QualitySettings),
+}
+
+/// The recorded counterpart of a `FrameMsg`. Hit testing requests are not
+/// recorded.
+#[cfg(any(feature = "capture", feature = "replay"))]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub enum RecordedFrameOp {
+    ///
+    SetScrollOffsets(ExternalScrollId, Vec<SampledScrollOffset>),
+    ///
+    ResetDynamicProperties,
+    ///
+    AppendDynamicProperties(DynamicProperties),
+    ///
+    AppendDynamicTransformProperties(Vec<PropertyValue<LayoutTransform>>),
+    ///
+    SetIsTransformAsyncZooming(bool, PropertyBindingId),
+    ///
+    SetMaxFrameRate(Option<f32>),
+    ///
+    SetColorFilter(Option<AccessibilityColorFilter>),
+    ///
+    SetPostProcessParams(PostProcessParams),
+    ///
+    SetSubpixelTextPolicy(SubpixelTextPolicy),
+}
+
+/// A transaction, as sent by the embedder.
+#[cfg(any(feature = "capture", feature = "replay"))]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct RecordedTransaction {
+    /// Time at which the transaction was sent, in nanoseconds since the start
+    /// of the recording.
+    pub time_ns: u64,
+    /// The document the transaction was sent to.
+    pub document_id: DocumentId,
+    ///
+    pub scene_ops: Vec<RecordedSceneOp>,
+    ///
+    pub frame_ops: Vec<RecordedFrameOp>,
+    ///
+    pub resource_updates: Vec<ResourceUpdate>,
+    ///
+    pub generate_frame: bool,
+    ///
+    pub invalidate_rendered_frame: bool,
+    ///
+    pub low_priority: bool,
+}
+
+#[cfg(feature = "capture")]
+impl RecordedTransaction {
+    fn new(txn: &TransactionMsg, time_ns: u64) -> Self {
+        let scene_ops = txn.scene_ops.iter().map(|op| match *op {
+            SceneMsg::UpdateEpoch(pipeline_id, epoch) => {
+                RecordedSceneOp::UpdateEpoch(pipeline_id, epoch)
+            }
+            SceneMsg::SetRootPipeline(pipeline_id) => RecordedSceneOp::SetRootPipeline(pipeline_id),
+            SceneMsg::RemovePipeline(pipeline_id) => RecordedSceneOp::RemovePipeline(pipeline_id),
+            SceneMsg::SetDisplayList { ref display_list, epoch, pipeline_id } => {
+                let (payload, descriptor) = display_list.clone().into_data();
+                RecordedSceneOp::SetDisplayList(RecordedDisplayList {
+                    pipeline_id,
+                    epoch,
+                    items_data: payload.items_data,
+                    cache_data: payload.cache_data,
+                    spatial_tree: payload.spatial_tree,
+                    descriptor,
+                })
+            }
+            SceneMsg::SetDocumentView { device_rect } => RecordedSceneOp::SetDocumentView(device_rect),
+            SceneMsg::SetQualitySettings { settings } => RecordedSceneOp::SetQualitySettings(settings),
+        }).collect();
+
+        let frame_ops = txn.frame_ops.iter().filter_map(|op| match *op {
+            // Epoch updates are also in the scene ops, and replayed from there.
+            FrameMsg::UpdateEpoch(..) |
+            FrameMsg::HitTest(..) |
+            FrameMsg::RequestHitTester(..) => None,
+            FrameMsg::SetScrollOffsets(id, ref offsets) => {
+                Some(RecordedFrameOp::SetScrollOffsets(id, offsets.clone()))
+            }
+            FrameMsg::ResetDynamicProperties => Some(RecordedFrameOp::ResetDynamicProperties),
+            FrameMsg::AppendDynamicProperties(ref properties) => {
+                Some(RecordedFrameOp::AppendDynamicProperties(properties.clone()))
+            }
+            FrameMsg::AppendDynamicTransformProperties(ref transforms) => {
+                Some(RecordedFrameOp::AppendDynamicTransformProperties(transforms.clone()))
+            }
+            FrameMsg::SetIsTransformAsyncZooming(is_zooming, id) => {
+                Some(RecordedFrameOp::SetIsTransformAsyncZooming(is_zooming, id))
+            }
+            FrameMsg::SetMaxFrameRate(max_fps) => Some(RecordedFrameOp::SetMaxFrameRate(max_fps)),
+            FrameMsg::SetColorFilter(filter) => Some(RecordedFrameOp::SetColorFilter(filter)),
+            FrameMsg::SetPostProcessParams(params) => Some(RecordedFrameOp::SetPostProcessParams(params)),
+            FrameMsg::SetSubpixelTextPolicy(policy) => Some(RecordedFrameOp::SetSubpixelTextPolicy(policy)),
+        }).collect();
+
+        RecordedTransaction {
+            time_ns,
+            document_id: txn.document_id,
+            scene_ops,
+            frame_ops,
+            resource_updates: txn.resource_updates.clone(),
+            generate_frame: txn.generate_frame.as_bool(),
+            invalidate_rendered_frame: txn.invalidate_rendered_frame,
+            low_priority: txn.low_priority,
+        }
+    }
+}
+
+#[cfg(feature = "replay")]
+impl RecordedTransaction {
+    /// Rebuilds a transaction that can be sent to a document.
+    pub fn into_transaction(self) -> Transaction {
+        let mut txn = Transaction::new();
+
+        for op in self.scene_ops {
+            match op {
+                RecordedSceneOp::UpdateEpoch(pipeline_id, epoch) => txn.update_epoch(pipeline_id, epoch),
+                RecordedSceneOp::SetRootPipeline(pipeline_id) => txn.set_root_pipeline(pipeline_id),
+                RecordedSceneOp::RemovePipeline(pipeline_id) => txn.remove_pipeline(pipeline_id),
+                RecordedSceneOp::SetDisplayList(dl) => {
+                    let payload = DisplayListPayload {
+                        items_data: dl.items_data,
+                        cache_data: dl.cache_data,
+                        spatial_tree: dl.spatial_tree,
+                    };
+                    let display_list = BuiltDisplayList::from_data(payload, dl.descriptor);
+                    txn.set_display_list(dl.epoch, (dl.pipeline_id, display_list));
+                }
+                RecordedSceneOp::SetDocumentView(device_rect) => txn.set_document_view(device_rect),
+                RecordedSceneOp::SetQualitySettings(settings) => txn.set_quality_settings(settings),
+            }
+        }
+
+        for op in self.frame_ops {
+            match op {
+                RecordedFrameOp::SetScrollOffsets(id, offsets) => txn.set_scroll_offsets(id, offsets),
+                RecordedFrameOp::ResetDynamicProperties => txn.reset_dynamic_properties(),
+                RecordedFrameOp::AppendDynamicProperties(properties) => {
+                    txn.append_dynamic_properties(properties)
+                }
+                RecordedFrameOp::AppendDynamicTransformProperties(transforms) => {
+                    txn.append_dynamic_transform_properties(transforms)
+                }
+                RecordedFrameOp::SetIsTransformAsyncZooming(is_zooming, id) => {
+                    txn.set_is_transform_async_zooming(is_zooming, id)
+                }
+                RecordedFrameOp::SetMaxFrameRate(max_fps) => txn.set_max_frame_rate(max_fps),
+                RecordedFrameOp::SetColorFilter(filter) => txn.set_color_filter(filter),
+                RecordedFrameOp::SetPostProcessParams(params) => txn.set_post_process_params(params),
+                RecordedFrameOp::SetSubpixelTextPolicy(policy) => txn.set_subpixel_text_policy(policy),
+            }
+        }
+
+        txn.update_resources(self.resource_updates);
+        txn.set_low_priority(self.low_priority);
+        if self.generate_frame {
+            txn.generate_frame(0, RenderReasons::TESTING);
+        }
+        if self.invalidate_rendered_frame {
+            txn.invalidate_rendered_frame(RenderReasons::TESTING);
+        }
+
+        txn
+    }
+}
+
+/// Writes the transactions of an active recording.
+#[cfg(feature = "capture")]
+struct TransactionRecorder {
+    writer: BufWriter<File>,
+    start_time: u64,
+}
+
+/// The recording state shared by all the API objects of a WebRender instance.
+#[derive(Default)]
+pub struct SharedRecorder {
+    #[cfg(feature = "capture")]
+    recorder: Mutex<Option<TransactionRecorder>>,
+}
+
+impl SharedRecorder {
+    /// Starts recording to a new file, stopping any active recording.
+    #[cfg(feature = "capture")]
+    pub fn start(&self, path: PathBuf) {
+        let result = File::create(&path).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            bincode::serialize_into(&mut writer, &RecordingHeader::current())
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+            Ok(writer)
+        });
+
+        let mut recorder = self.recorder.lock().unwrap();
+        *recorder = match result {
+            Ok(writer) => Some(TransactionRecorder {
+                writer,
+                start_time: precise_time_ns(),
+            }),
+            Err(err) => {
+                error!("Unable to start recording transactions to {:?}: {:?}", path, err);
+                None
+            }
+        };
+    }
+
+    #[cfg(not(feature = "capture"))]
+    pub fn start(&self, path: PathBuf) {
+        warn!("Unable to record transactions to {:?} without the 'capture' feature", path);
+    }
+
+    /// Stops the active recording, if any.
+    pub fn stop(&self) {
+        #[cfg(feature = "capture")]
+        {
+            use std::io::Write;
+
+            if let Some(mut recorder) = self.recorder.lock().unwrap().take() {
+                recorder.writer.flush().ok();
+            }
+        }
+    }
+
+    /// Appends a transaction to the active recording, if any.
+    #[cfg(feature = "capture")]
+    pub(crate) fn record(&self, txn: &TransactionMsg) {
+        let mut lock = self.recorder.lock().unwrap();
+        let recorder = match *lock {
+            Some(ref mut recorder) => recorder,
+            None => return,
+        };
+
+        let recorded = RecordedTransaction::new(txn, precise_time_ns() - recorder.start_time);
+        if let Err(err) = bincode::serialize_into(&mut recorder.writer, &recorded) {
+            error!("Unable to record transaction, stopping the recording: {:?}", err);
+            *lock = None;
+        }
+    }
+}
+
+/// Reads the transactions of a recording in order.
+#[cfg(feature = "replay")]
+pub struct RecordingReader {
+    reader: BufReader<File>,
+}
+
+#[cfg(feature = "replay")]
+impl RecordingReader {
+    /// Opens a recording, checking that it was made by this version of WebRender.
+    pub fn open(path: &std::path::Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let header: RecordingHeader = bincode::deserialize_from(&mut reader)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if header.webrender_version != RecordingHeader::current().webrender_version {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("recording made by WebRender {}", header.webrender_version),
+            ));
+        }
+
+        Ok(RecordingReader { reader })
+    }
+}
+
+#[cfg(feature = "replay")]
+impl Iterator for RecordingReader {
+    type Item = RecordedTransaction;
+
+    fn next(&mut self) -> Option<RecordedTransaction> {
+        match bincode::deserialize_from(&mut self.reader) {
+            Ok(txn) => Some(txn),
+            Err(err) => {
+                if let bincode::ErrorKind::Io(ref err) = *err {
+                    if err.kind() == io::ErrorKind::UnexpectedEof {
+                        return None;
+                    }
+                }
+                error!("Unable to read recorded transaction: {:?}", err);
+                None
+            }
+        }
+    }
+}
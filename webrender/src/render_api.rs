@@ -8,26 +8,35 @@ use std::cell::Cell;
 use std::fmt;
 use std::marker::PhantomData;
 use std::path::PathBuf;
+use std::mem;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::u32;
 use time::precise_time_ns;
 use crate::api::channel::{Sender, single_msg_channel, unbounded_channel};
 use crate::api::{BuiltDisplayList, IdNamespace, ExternalScrollId, Parameter, BoolParameter};
-use crate::api::{FontKey, FontInstanceKey, NativeFontHandle};
-use crate::api::{BlobImageData, BlobImageKey, ImageData, ImageDescriptor, ImageKey, Epoch, QualitySettings};
+use crate::api::IntParameter;
+use crate::api::{FontKey, FontInstanceKey, NativeFontHandle, ParsedFontInfo};
+use crate::api::{BlobImageData, BlobImageKey, ExternalImageId, ImageData, ImageDescriptor, ImageKey, Epoch, QualitySettings};
+use crate::api::{AccessibilityColorFilter, DirtyRect, ImageFormat, PostProcessParams, SubpixelTextPolicy};
 use crate::api::{BlobImageParams, BlobImageRequest, BlobImageResult, AsyncBlobImageRasterizer, BlobImageHandler};
 use crate::api::{DocumentId, PipelineId, PropertyBindingId, PropertyBindingKey, ExternalEvent};
 use crate::api::{HitTestResult, HitTesterRequest, ApiHitTester, PropertyValue, DynamicProperties};
 use crate::api::{SampledScrollOffset, TileSize, NotificationRequest, DebugFlags};
 use crate::api::{GlyphDimensionRequest, GlyphIndexRequest, GlyphIndex, GlyphDimensions};
+use crate::api::GlyphVariantIndexRequest;
+use crate::api::{GlyphOutlineRequest, PathCommand};
 use crate::api::{FontInstanceOptions, FontInstancePlatformOptions, FontVariation, RenderReasons};
+use crate::api::FontPaletteOverride;
 use crate::api::DEFAULT_TILE_SIZE;
 use crate::api::units::*;
 use crate::api_resources::ApiResources;
+use crate::recording::SharedRecorder;
+use crate::internal_types::FastHashMap;
 use glyph_rasterizer::SharedFontResources;
 use crate::scene_builder_thread::{SceneBuilderRequest, SceneBuilderResult};
 use crate::intern::InterningMemoryReport;
-use crate::profiler::{self, TransactionProfile};
+use crate::profiler::{self, CounterExportFormat, TransactionProfile};
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
@@ -115,6 +124,9 @@ pub enum GenerateFrame {
         /// An id that allows tracking the frame transaction through the various
         /// frame stages. Specified by the caller of generate_frame().
         id: u64,
+        /// When generate_frame() was called, in nanoseconds. Used to measure
+        /// the latency of the frame.
+        request_time: u64,
     },
     /// Don't generate a frame even if something has changed.
     No,
@@ -132,7 +144,15 @@ impl GenerateFrame {
     /// Return the frame ID, if a frame is generated.
     pub fn id(&self) -> Option<u64> {
         match self {
-            GenerateFrame::Yes { id } => Some(*id),
+            GenerateFrame::Yes { id, .. } => Some(*id),
+            GenerateFrame::No => None,
+        }
+    }
+
+    /// Return the time the frame was requested at, if a frame is generated.
+    pub fn request_time(&self) -> Option<u64> {
+        match self {
+            GenerateFrame::Yes { request_time, .. } => Some(*request_time),
             GenerateFrame::No => None,
         }
     }
@@ -180,6 +200,10 @@ pub struct Transaction {
 
     ///
     pub render_reasons: RenderReasons,
+
+    /// An optional user label, reported along with the frames this transaction
+    /// contributed to.
+    label: Option<String>,
 }
 
 impl Transaction {
@@ -196,6 +220,7 @@ impl Transaction {
             invalidate_rendered_frame: false,
             low_priority: false,
             render_reasons: RenderReasons::empty(),
+            label: None,
         }
     }
 
@@ -352,10 +377,18 @@ impl Transaction {
     ///
     /// [notifier]: trait.RenderNotifier.html#tymethod.new_frame_ready
     pub fn generate_frame(&mut self, id: u64, reasons: RenderReasons) {
-        self.generate_frame = GenerateFrame::Yes{ id };
+        self.generate_frame = GenerateFrame::Yes { id, request_time: precise_time_ns() };
         self.render_reasons |= reasons;
     }
 
+    /// Attach a short label to this transaction. The label shows up in profiler
+    /// markers, captures and `RenderResults::transaction_labels` for the frames
+    /// this transaction contributed to, which helps tracking down which update
+    /// caused a given frame.
+    pub fn set_label(&mut self, label: impl Into<String>) {
+        self.label = Some(label.into());
+    }
+
     /// Invalidate rendered frame. It ensure that frame will be rendered during
     /// next frame generation. WebRender could skip frame rendering if there
     /// is no update.
@@ -409,6 +442,7 @@ impl Transaction {
             rasterized_blobs: Vec::new(),
             profile: TransactionProfile::new(),
             render_reasons: self.render_reasons,
+            label: self.label,
         })
     }
 
@@ -441,6 +475,32 @@ impl Transaction {
             descriptor,
             data,
             dirty_rect: *dirty_rect,
+            dirty_rects: Vec::new(),
+        }));
+    }
+
+    /// Like `update_image`, but with a list of dirty rects that are uploaded
+    /// individually instead of as their bounding box.
+    ///
+    /// This is useful when several small, far apart regions of a large image
+    /// changed.
+    pub fn update_image_rects(
+        &mut self,
+        key: ImageKey,
+        descriptor: ImageDescriptor,
+        data: ImageData,
+        dirty_rects: &[DeviceIntRect],
+    ) {
+        let dirty_rect = dirty_rects
+            .iter()
+            .fold(ImageDirtyRect::empty(), |acc, rect| acc.union(&DirtyRect::Partial(*rect)));
+
+        self.resource_updates.push(ResourceUpdate::UpdateImage(UpdateImage {
+            key,
+            descriptor,
+            data,
+            dirty_rect,
+            dirty_rects: dirty_rects.to_vec(),
         }));
     }
 
@@ -499,12 +559,29 @@ impl Transaction {
         self.resource_updates.push(ResourceUpdate::SetBlobImageVisibleArea(key, area));
     }
 
-    /// See `ResourceUpdate::AddFont`.
+    /// See `ResourceUpdate::AddFont`. With the `woff` feature, `bytes` may
+    /// also be a WOFF or WOFF2 font, which the glyph rasterizer's workers
+    /// decode when they load it.
     pub fn add_raw_font(&mut self, key: FontKey, bytes: Vec<u8>, index: u32) {
         self.resource_updates
             .push(ResourceUpdate::AddFont(AddFont::Raw(key, Arc::new(bytes), index)));
     }
 
+    /// Adds a font that the embedder already parsed, sharing its data. See
+    /// `ResourceUpdate::AddFont`.
+    pub fn add_parsed_font(&mut self, key: FontKey, bytes: Arc<Vec<u8>>, index: u32, info: ParsedFontInfo) {
+        self.resource_updates
+            .push(ResourceUpdate::AddFont(AddFont::Parsed(key, bytes, index, info)));
+    }
+
+    /// Adds the font at `index` in the font file at `path`, which is loaded
+    /// by the glyph rasterizer, mapped into memory where the backend supports
+    /// it. See `ResourceUpdate::AddFont`.
+    pub fn add_file_font(&mut self, key: FontKey, path: PathBuf, index: u32) {
+        self.resource_updates
+            .push(ResourceUpdate::AddFont(AddFont::File(key, path, index)));
+    }
+
     /// See `ResourceUpdate::AddFont`.
     pub fn add_native_font(&mut self, key: FontKey, native_handle: NativeFontHandle) {
         self.resource_updates
@@ -525,6 +602,30 @@ impl Transaction {
         options: Option<FontInstanceOptions>,
         platform_options: Option<FontInstancePlatformOptions>,
         variations: Vec<FontVariation>,
+    ) {
+        self.add_font_instance_with_palette_overrides(
+            key,
+            font_key,
+            glyph_size,
+            options,
+            platform_options,
+            variations,
+            Vec::new(),
+        );
+    }
+
+    /// Adds a font instance whose color glyphs are drawn with some entries of
+    /// its palette, see `FontInstanceOptions::palette_index`, replaced. See
+    /// `ResourceUpdate::AddFontInstance`.
+    pub fn add_font_instance_with_palette_overrides(
+        &mut self,
+        key: FontInstanceKey,
+        font_key: FontKey,
+        glyph_size: f32,
+        options: Option<FontInstanceOptions>,
+        platform_options: Option<FontInstancePlatformOptions>,
+        variations: Vec<FontVariation>,
+        palette_overrides: Vec<FontPaletteOverride>,
     ) {
         self.resource_updates
             .push(ResourceUpdate::AddFontInstance(AddFontInstance {
@@ -534,6 +635,7 @@ impl Transaction {
                 options,
                 platform_options,
                 variations,
+                palette_overrides,
             }));
     }
 
@@ -542,6 +644,35 @@ impl Transaction {
         self.resource_updates.push(ResourceUpdate::DeleteFontInstance(key));
     }
 
+    /// Limits how often frames are built for the document, for example to 30
+    /// frames per second for a document in the background. Frames requested
+    /// sooner than that after the previous one are deferred and merged into a
+    /// single frame. `None` removes the limit, which is the default.
+    pub fn set_max_frame_rate(&mut self, max_fps: Option<f32>) {
+        self.frame_ops.push(FrameMsg::SetMaxFrameRate(max_fps));
+    }
+
+    /// Applies a color filter to the whole document when it is composited,
+    /// for example to assist users with impaired color vision. `None` removes
+    /// the filter, which is the default. Only supported with the draw
+    /// compositor.
+    pub fn set_color_filter(&mut self, filter: Option<AccessibilityColorFilter>) {
+        self.frame_ops.push(FrameMsg::SetColorFilter(filter));
+    }
+
+    /// Sets the uniform values of the post-processing shader installed with
+    /// `Renderer::set_post_process_shader`. The values persist until they are
+    /// set again.
+    pub fn set_post_process_params(&mut self, params: PostProcessParams) {
+        self.frame_ops.push(FrameMsg::SetPostProcessParams(params));
+    }
+
+    /// Sets how the document draws text that asks for subpixel
+    /// anti-aliasing, see `SubpixelTextPolicy`. The default is `Auto`.
+    pub fn set_subpixel_text_policy(&mut self, policy: SubpixelTextPolicy) {
+        self.frame_ops.push(FrameMsg::SetSubpixelTextPolicy(policy));
+    }
+
     /// A hint that this transaction can be processed at a lower priority. High-
     /// priority transactions can jump ahead of regular-priority transactions,
     /// but both high- and regular-priority transactions are processed in order
@@ -554,6 +685,26 @@ impl Transaction {
     pub fn is_low_priority(&self) -> bool {
         self.low_priority
     }
+
+    /// Appends the content of a transaction that was sent after this one, so
+    /// that both are applied at once.
+    fn coalesce(&mut self, later: Transaction) {
+        self.scene_ops.extend(later.scene_ops);
+        self.frame_ops.extend(later.frame_ops);
+        self.notifications.extend(later.notifications);
+        self.resource_updates.extend(later.resource_updates);
+        self.use_scene_builder_thread |= later.use_scene_builder_thread;
+        if later.generate_frame.as_bool() {
+            self.generate_frame = later.generate_frame;
+        }
+        self.invalidate_rendered_frame |= later.invalidate_rendered_frame;
+        self.low_priority &= later.low_priority;
+        self.render_reasons |= later.render_reasons;
+        self.label = match (self.label.take(), later.label) {
+            (Some(label), Some(later_label)) => Some(format!("{}, {}", label, later_label)),
+            (label, later_label) => label.or(later_label),
+        };
+    }
 }
 
 ///
@@ -598,15 +749,18 @@ pub struct TransactionMsg {
     pub profile: TransactionProfile,
     /// Keep track of who asks rendering to happen.
     pub render_reasons: RenderReasons,
+    /// See `Transaction::set_label`.
+    pub label: Option<String>,
 }
 
 impl fmt::Debug for TransactionMsg {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "threaded={}, genframe={:?}, invalidate={}, low_priority={}",
+        writeln!(f, "threaded={}, genframe={:?}, invalidate={}, low_priority={}, label={:?}",
                         self.use_scene_builder_thread,
                         self.generate_frame,
                         self.invalidate_rendered_frame,
                         self.low_priority,
+                        self.label,
                     ).unwrap();
         for scene_op in &self.scene_ops {
             writeln!(f, "\t\t{:?}", scene_op).unwrap();
@@ -670,6 +824,9 @@ pub struct UpdateImage {
     ///
     /// The data provided must still represent the entire image.
     pub dirty_rect: ImageDirtyRect,
+    /// If not empty, the individual rects making up `dirty_rect`. Each of
+    /// them is uploaded separately.
+    pub dirty_rects: Vec<DeviceIntRect>,
 }
 
 /// Creates a blob-image resource with provided parameters.
@@ -727,6 +884,10 @@ pub struct UpdateBlobImage {
 pub enum AddFont {
     ///
     Raw(FontKey, Arc<Vec<u8>>, u32),
+    /// Like `Raw`, with what the embedder found when parsing the font.
+    Parsed(FontKey, Arc<Vec<u8>>, u32, ParsedFontInfo),
+    /// A font file to load, and the index of the font in it.
+    File(FontKey, PathBuf, u32),
     ///
     Native(FontKey, NativeFontHandle),
 }
@@ -750,6 +911,8 @@ pub struct AddFontInstance {
     pub platform_options: Option<FontInstancePlatformOptions>,
     ///
     pub variations: Vec<FontVariation>,
+    /// Colors replacing entries of the palette of color glyphs.
+    pub palette_overrides: Vec<FontPaletteOverride>,
 }
 
 /// Frame messages affect building the scene.
@@ -799,6 +962,14 @@ pub enum FrameMsg {
     AppendDynamicTransformProperties(Vec<PropertyValue<LayoutTransform>>),
     ///
     SetIsTransformAsyncZooming(bool, PropertyBindingId),
+    ///
+    SetMaxFrameRate(Option<f32>),
+    ///
+    SetColorFilter(Option<AccessibilityColorFilter>),
+    ///
+    SetPostProcessParams(PostProcessParams),
+    ///
+    SetSubpixelTextPolicy(SubpixelTextPolicy),
 }
 
 impl fmt::Debug for SceneMsg {
@@ -823,6 +994,10 @@ impl fmt::Debug for FrameMsg {
             FrameMsg::SetScrollOffsets(..) => "FrameMsg::SetScrollOffsets",
             FrameMsg::ResetDynamicProperties => "FrameMsg::ResetDynamicProperties",
             FrameMsg::AppendDynamicProperties(..) => "FrameMsg::AppendDynamicProperties",
+            FrameMsg::SetMaxFrameRate(..) => "FrameMsg::SetMaxFrameRate",
+            FrameMsg::SetColorFilter(..) => "FrameMsg::SetColorFilter",
+            FrameMsg::SetPostProcessParams(..) => "FrameMsg::SetPostProcessParams",
+            FrameMsg::SetSubpixelTextPolicy(..) => "FrameMsg::SetSubpixelTextPolicy",
             FrameMsg::AppendDynamicTransformProperties(..) => "FrameMsg::AppendDynamicTransformProperties",
             FrameMsg::SetIsTransformAsyncZooming(..) => "FrameMsg::SetIsTransformAsyncZooming",
         })
@@ -862,6 +1037,125 @@ bitflags!{
     }
 }
 
+/// How aggressively to release memory in response to memory pressure.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MemoryPressureLevel {
+    /// Drop the cached items that are cheap to regenerate, such as
+    /// automatically evicted texture cache entries and unused picture cache
+    /// textures, and keep everything else.
+    Moderate,
+    /// Drop everything that can be rebuilt. The next frame will have to
+    /// re-upload and re-render all of its resources.
+    Critical,
+}
+
+bitflags!{
+    /// The subsystems a memory pressure notification applies to.
+    pub struct MemoryPressureTargets: u8 {
+        /// Images and cached render tasks in the texture cache.
+        const TEXTURE_CACHE = 0b1;
+        /// Picture cache tiles, including native compositor surfaces when
+        /// the level is critical.
+        const PICTURE_TILES = 0b10;
+        /// Rasterized glyphs and glyph dimensions.
+        const GLYPHS = 0b100;
+    }
+}
+
+/// The number of bytes each pass of a memory pressure notification freed.
+///
+/// Texture cache entries are counted when they are evicted, the shared
+/// textures that end up empty are released at the end of the next frame.
+/// Memory that isn't attributed to any of the targets (the GPU cache, frame
+/// building scratch buffers), which is only released for critical pressure
+/// on all targets, is not counted.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryPressureReport {
+    ///
+    pub texture_cache: usize,
+    ///
+    pub picture_tiles: usize,
+    ///
+    pub glyphs: usize,
+}
+
+impl MemoryPressureReport {
+    /// The total number of bytes freed.
+    pub fn total(&self) -> usize {
+        self.texture_cache + self.picture_tiles + self.glyphs
+    }
+}
+
+/// The longest time, in milliseconds, for which `RenderApi::send_transaction`
+/// holds transactions back while the render backend is behind.
+pub const MAX_COALESCING_DELAY_MS: u64 = 100;
+
+/// Tracks the work submitted to WebRender that wasn't consumed yet, so that
+/// embedders producing transactions faster than frames can be built are able
+/// to throttle themselves. Shared by the API objects, the render backend and
+/// the renderer.
+#[derive(Debug, Default)]
+pub struct Backpressure {
+    transactions: AtomicUsize,
+    frames: AtomicUsize,
+    throttled: AtomicBool,
+    idle: AtomicBool,
+}
+
+impl Backpressure {
+    /// The number of transactions sent to WebRender that the render backend
+    /// hasn't applied yet.
+    pub fn pending_transactions(&self) -> usize {
+        self.transactions.load(Ordering::Acquire)
+    }
+
+    /// The number of frames built by the render backend that the renderer
+    /// hasn't picked up yet.
+    pub fn pending_frames(&self) -> usize {
+        self.frames.load(Ordering::Acquire)
+    }
+
+    /// Whether WebRender is idle, see `RenderNotifier::idle`, and the
+    /// renderer picked up all of the frames built so far.
+    pub fn is_idle(&self) -> bool {
+        self.idle.load(Ordering::Acquire) &&
+            self.pending_transactions() == 0 &&
+            self.pending_frames() == 0
+    }
+
+    /// Counts a transaction sent to the render backend, either by the API or
+    /// by WebRender itself, such as the ones of a loaded capture.
+    pub(crate) fn transaction_sent(&self) {
+        self.transactions.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Returns true if the render backend caught up after an API object held
+    /// transactions back, in which case the embedder should be notified.
+    pub(crate) fn transactions_applied(&self, count: usize) -> bool {
+        let previous = self.transactions.fetch_sub(count, Ordering::AcqRel);
+        debug_assert!(previous >= count, "applied transactions that weren't counted");
+        previous == count && self.throttled.swap(false, Ordering::AcqRel)
+    }
+
+    /// Returns true if an API object is holding transactions back, in which
+    /// case the embedder should be notified so that it sends them along with
+    /// its next frame.
+    pub(crate) fn frame_published(&self) -> bool {
+        self.frames.fetch_add(1, Ordering::AcqRel);
+        self.throttled.swap(false, Ordering::AcqRel)
+    }
+
+    pub(crate) fn frame_consumed(&self) {
+        self.frames.fetch_sub(1, Ordering::AcqRel);
+    }
+
+    /// Records whether the render backend is idle. Returns true if it just
+    /// became idle, in which case the embedder should be notified.
+    pub(crate) fn set_idle(&self, idle: bool) -> bool {
+        !self.idle.swap(idle, Ordering::AcqRel) && idle
+    }
+}
+
 /// Information about a loaded capture of each document
 /// that is returned by `RenderBackend`.
 #[derive(Clone, Debug)]
@@ -885,6 +1179,11 @@ pub enum DebugCommand {
     StartCaptureSequence(PathBuf, CaptureBits),
     /// Stop capturing a sequence of scene/frame changes.
     StopCaptureSequence,
+    /// Start recording the transactions sent by all the API objects to a file,
+    /// so that they can be replayed later by wrench.
+    StartTransactionRecording(PathBuf),
+    /// Stop recording transactions.
+    StopTransactionRecording,
     /// Clear cached resources, forcing them to be re-uploaded from templates.
     ClearCaches(ClearCache),
     /// Enable/disable native compositor usage
@@ -900,6 +1199,22 @@ pub enum DebugCommand {
     SetPictureTileSize(Option<DeviceIntSize>),
     /// Set an override for max off-screen surface size
     SetMaximumSurfaceSize(Option<usize>),
+    /// Stream the profiler counters of every rendered frame to the given file,
+    /// or stop streaming if None.
+    ExportProfilerCounters(Option<(PathBuf, CounterExportFormat)>),
+    /// Write every texture cache texture to PNG in the given directory, along
+    /// with a manifest of the entries they contain.
+    DumpTextureCache(PathBuf),
+    /// Add an embedder-defined counter to the profiler. Once registered, the
+    /// counter can be referred to by name in the profiler UI string.
+    RegisterProfilerCounter {
+        /// Name displayed in the profiler and used in the UI string.
+        name: &'static str,
+        /// Unit displayed next to the value, for example "ms".
+        unit: &'static str,
+    },
+    /// Record the value of an embedder-defined counter for the next rendered frame.
+    SetProfilerCounter(&'static str, f64),
 }
 
 /// Message sent by the `RenderApi` to the render backend thread.
@@ -908,14 +1223,29 @@ pub enum ApiMsg {
     CloneApi(Sender<IdNamespace>),
     /// Adds a new document namespace.
     CloneApiByClient(IdNamespace),
+    /// Reserves a range of namespaces for the client to assign.
+    ReserveNamespaces(u32, Sender<IdNamespaceRange>),
     /// Adds a new document with given initial size.
     AddDocument(DocumentId, DeviceIntSize),
     /// A message targeted at a particular document.
     UpdateDocuments(Vec<Box<TransactionMsg>>),
-    /// Flush from the caches anything that isn't necessary, to free some memory.
-    MemoryPressure,
+    /// Flush from the caches anything that isn't necessary, to free some memory,
+    /// optionally reporting how much was freed.
+    MemoryPressure(MemoryPressureLevel, MemoryPressureTargets, Option<Sender<MemoryPressureReport>>),
     /// Collects a memory report.
     ReportMemory(Sender<Box<MemoryReport>>),
+    /// Collects a memory report for each document.
+    ReportDocumentMemory(Sender<Vec<(DocumentId, MemoryReport)>>),
+    /// Collects the resources of a document's namespace that no frame built
+    /// since the given epoch used.
+    ReportUnusedResources(DocumentId, Epoch, Sender<UnusedResources>),
+    /// Lists the resources registered by each namespace.
+    ListResources(Sender<Vec<NamespaceResources>>),
+    /// Collects the metrics of the last scene built for a document.
+    GetSceneMetrics(DocumentId, Sender<Option<SceneMetrics>>),
+    /// The frames presented by the video frame queues of these external images
+    /// changed, sent by the renderer.
+    ExternalImagesChanged(Vec<ExternalImageId>),
     /// Change debugging options.
     DebugCommand(DebugCommand),
     /// Message from the scene builder thread.
@@ -927,10 +1257,16 @@ impl fmt::Debug for ApiMsg {
         f.write_str(match *self {
             ApiMsg::CloneApi(..) => "ApiMsg::CloneApi",
             ApiMsg::CloneApiByClient(..) => "ApiMsg::CloneApiByClient",
+            ApiMsg::ReserveNamespaces(..) => "ApiMsg::ReserveNamespaces",
             ApiMsg::AddDocument(..) => "ApiMsg::AddDocument",
             ApiMsg::UpdateDocuments(..) => "ApiMsg::UpdateDocuments",
-            ApiMsg::MemoryPressure => "ApiMsg::MemoryPressure",
+            ApiMsg::MemoryPressure(..) => "ApiMsg::MemoryPressure",
             ApiMsg::ReportMemory(..) => "ApiMsg::ReportMemory",
+            ApiMsg::ReportDocumentMemory(..) => "ApiMsg::ReportDocumentMemory",
+            ApiMsg::ReportUnusedResources(..) => "ApiMsg::ReportUnusedResources",
+            ApiMsg::ListResources(..) => "ApiMsg::ListResources",
+            ApiMsg::GetSceneMetrics(..) => "ApiMsg::GetSceneMetrics",
+            ApiMsg::ExternalImagesChanged(..) => "ApiMsg::ExternalImagesChanged",
             ApiMsg::DebugCommand(..) => "ApiMsg::DebugCommand",
             ApiMsg::SceneBuilderResult(..) => "ApiMsg::SceneBuilderResult",
         })
@@ -947,6 +1283,8 @@ pub struct RenderApiSender {
     low_priority_scene_sender: Sender<SceneBuilderRequest>,
     blob_image_handler: Option<Box<dyn BlobImageHandler>>,
     fonts: SharedFontResources,
+    backpressure: Arc<Backpressure>,
+    recorder: Arc<SharedRecorder>,
 }
 
 impl RenderApiSender {
@@ -957,6 +1295,8 @@ impl RenderApiSender {
         low_priority_scene_sender: Sender<SceneBuilderRequest>,
         blob_image_handler: Option<Box<dyn BlobImageHandler>>,
         fonts: SharedFontResources,
+        backpressure: Arc<Backpressure>,
+        recorder: Arc<SharedRecorder>,
     ) -> Self {
         RenderApiSender {
             api_sender,
@@ -964,9 +1304,32 @@ impl RenderApiSender {
             low_priority_scene_sender,
             blob_image_handler,
             fonts,
+            backpressure,
+            recorder,
         }
     }
 
+    /// Creates a sender for the same render backend.
+    pub(crate) fn create_similar(&self) -> Self {
+        RenderApiSender {
+            api_sender: self.api_sender.clone(),
+            scene_sender: self.scene_sender.clone(),
+            low_priority_scene_sender: self.low_priority_scene_sender.clone(),
+            blob_image_handler: self.blob_image_handler.as_ref().map(|handler| handler.create_similar()),
+            fonts: self.fonts.clone(),
+            backpressure: Arc::clone(&self.backpressure),
+            recorder: Arc::clone(&self.recorder),
+        }
+    }
+
+    pub(crate) fn api_sender(&self) -> &Sender<ApiMsg> {
+        &self.api_sender
+    }
+
+    pub(crate) fn backpressure(&self) -> &Arc<Backpressure> {
+        &self.backpressure
+    }
+
     /// Creates a new resource API object with a dedicated namespace.
     pub fn create_api(&self) -> RenderApi {
         let (sync_tx, sync_rx) = single_msg_channel();
@@ -983,13 +1346,34 @@ impl RenderApiSender {
                 self.blob_image_handler.as_ref().map(|handler| handler.create_similar()),
                 self.fonts.clone(),
             ),
+            backpressure: Arc::clone(&self.backpressure),
+            recorder: Arc::clone(&self.recorder),
+            max_pending_transactions: None,
+            coalesced_transactions: Vec::new(),
+            coalescing_start: None,
         }
     }
 
+    /// Reserves `count` consecutive namespaces that won't be handed out by
+    /// `create_api`, so that the embedder can assign them itself.
+    ///
+    /// This lets an embedder forwarding the transactions of several processes
+    /// through a single sender give each process its own namespaces to
+    /// generate keys in, and later create the matching `RenderApi` with
+    /// `create_api_by_client`. Can't be used when
+    /// WebRenderOptions::namespace_alloc_by_client is true.
+    pub fn reserve_namespaces(&self, count: u32) -> IdNamespaceRange {
+        let (sync_tx, sync_rx) = single_msg_channel();
+        let msg = ApiMsg::ReserveNamespaces(count, sync_tx);
+        self.api_sender.send(msg).expect("Failed to send ReserveNamespaces message");
+        sync_rx.recv().expect("Failed to receive ReserveNamespaces reply")
+    }
+
     /// Creates a new resource API object with a dedicated namespace.
     /// Namespace id is allocated by client.
     ///
-    /// The function could be used only when WebRenderOptions::namespace_alloc_by_client is true.
+    /// The function could be used only when WebRenderOptions::namespace_alloc_by_client is true,
+    /// or with a namespace returned by reserve_namespaces().
     /// When the option is true, create_api() could not be used to prevent namespace id conflict.
     pub fn create_api_by_client(&self, namespace_id: IdNamespace) -> RenderApi {
         let msg = ApiMsg::CloneApiByClient(namespace_id);
@@ -1004,6 +1388,11 @@ impl RenderApiSender {
                 self.blob_image_handler.as_ref().map(|handler| handler.create_similar()),
                 self.fonts.clone(),
             ),
+            backpressure: Arc::clone(&self.backpressure),
+            recorder: Arc::clone(&self.recorder),
+            max_pending_transactions: None,
+            coalesced_transactions: Vec::new(),
+            coalescing_start: None,
         }
     }
 }
@@ -1016,6 +1405,15 @@ pub struct RenderApi {
     namespace_id: IdNamespace,
     next_id: Cell<ResourceId>,
     resources: ApiResources,
+    backpressure: Arc<Backpressure>,
+    recorder: Arc<SharedRecorder>,
+    /// See `set_max_pending_transactions`.
+    max_pending_transactions: Option<usize>,
+    /// Transactions held back because of `max_pending_transactions`, in the
+    /// order they were sent.
+    coalesced_transactions: Vec<(DocumentId, Transaction)>,
+    /// When the oldest of `coalesced_transactions` was held back, in ns.
+    coalescing_start: Option<u64>,
 }
 
 impl RenderApi {
@@ -1032,6 +1430,8 @@ impl RenderApi {
             self.low_priority_scene_sender.clone(),
             self.resources.blob_image_handler.as_ref().map(|handler| handler.create_similar()),
             self.resources.get_fonts(),
+            Arc::clone(&self.backpressure),
+            Arc::clone(&self.recorder),
         )
     }
 
@@ -1087,6 +1487,15 @@ impl RenderApi {
         FontInstanceKey::new(self.namespace_id, new_id)
     }
 
+    /// Returns the options a font instance was created with, or `None` if
+    /// the key isn't registered.
+    ///
+    /// Options that were omitted when adding the instance are reported with
+    /// their default values.
+    pub fn get_font_instance_info(&self, key: FontInstanceKey) -> Option<FontInstanceInfo> {
+        self.resources.get_font_instance_info(key)
+    }
+
     /// Gets the dimensions for the supplied glyph keys
     ///
     /// Note: Internally, the internal texture cache doesn't store
@@ -1120,6 +1529,36 @@ impl RenderApi {
         rx.recv().unwrap()
     }
 
+    /// Gets the glyph indices for the supplied variation sequences, each a base
+    /// character and a variation selector, such as U+FE0E and U+FE0F for
+    /// text and emoji presentation. A sequence's index is None if the font
+    /// doesn't map it, in which case the base character's glyph may be used.
+    /// The backends that can't read variation sequences map the base
+    /// characters only.
+    pub fn get_glyph_variant_indices(&self, key: FontKey, sequences: &[(char, char)]) -> Vec<Option<u32>> {
+        let (sender, rx) = single_msg_channel();
+        let msg = SceneBuilderRequest::GetGlyphVariantIndices(GlyphVariantIndexRequest {
+            key,
+            sequences: sequences.to_vec(),
+            sender,
+        });
+        self.low_priority_scene_sender.send(msg).unwrap();
+        rx.recv().unwrap()
+    }
+
+    /// Gets the outline of a glyph, in font units. The outline is empty if
+    /// the glyph has none, or if the glyph rasterizer can't extract outlines.
+    pub fn get_glyph_outline(&self, key: FontKey, glyph_index: GlyphIndex) -> Vec<PathCommand> {
+        let (sender, rx) = single_msg_channel();
+        let msg = SceneBuilderRequest::GetGlyphOutline(GlyphOutlineRequest {
+            key,
+            glyph_index,
+            sender,
+        });
+        self.low_priority_scene_sender.send(msg).unwrap();
+        rx.recv().unwrap()
+    }
+
     /// Creates an `ImageKey`.
     pub fn generate_image_key(&self) -> ImageKey {
         let new_id = self.next_unique_id();
@@ -1142,7 +1581,24 @@ impl RenderApi {
     /// Notify WebRender that now is a good time to flush caches and release
     /// as much memory as possible.
     pub fn notify_memory_pressure(&self) {
-        self.api_sender.send(ApiMsg::MemoryPressure).unwrap();
+        let msg = ApiMsg::MemoryPressure(
+            MemoryPressureLevel::Critical,
+            MemoryPressureTargets::all(),
+            None,
+        );
+        self.api_sender.send(msg).unwrap();
+    }
+
+    /// Synchronously releases memory held by the given subsystems, and reports
+    /// how many bytes each of them freed.
+    pub fn notify_targeted_memory_pressure(
+        &self,
+        level: MemoryPressureLevel,
+        targets: MemoryPressureTargets,
+    ) -> MemoryPressureReport {
+        let (tx, rx) = single_msg_channel();
+        self.api_sender.send(ApiMsg::MemoryPressure(level, targets, Some(tx))).unwrap();
+        rx.recv().unwrap()
     }
 
     /// Synchronously requests memory report.
@@ -1152,6 +1608,83 @@ impl RenderApi {
         *rx.recv().unwrap()
     }
 
+    /// Synchronously requests a memory report for each document.
+    ///
+    /// Only the memory that can be attributed to a single document is filled
+    /// in: clip stores, hit testers, interned data, display lists and picture
+    /// cache tiles. Images and fonts are owned by an API namespace rather than
+    /// a document, so they are reported against every document belonging to
+    /// that namespace. Shared structures such as the GPU cache and texture
+    /// cache are only available through `report_memory`.
+    pub fn report_document_memory(
+        &self,
+        _ops: malloc_size_of::MallocSizeOfOps,
+    ) -> Vec<(DocumentId, MemoryReport)> {
+        let (tx, rx) = single_msg_channel();
+        self.api_sender.send(ApiMsg::ReportDocumentMemory(tx)).unwrap();
+        rx.recv().unwrap()
+    }
+
+    /// Synchronously lists the images, blob images, fonts and font instances
+    /// that are currently registered, grouped by namespace and sorted by key.
+    ///
+    /// This is meant to help finding keys that the embedder forgot to delete:
+    /// resources that haven't been used by a frame for a long time are good
+    /// candidates. Resources deleted by a transaction that hasn't been
+    /// processed yet are still listed.
+    pub fn list_resources(&self) -> Vec<NamespaceResources> {
+        let (tx, rx) = single_msg_channel();
+        self.api_sender.send(ApiMsg::ListResources(tx)).unwrap();
+        rx.recv().unwrap()
+    }
+
+    /// Synchronously requests the metrics of the last scene built for
+    /// `document_id`, or `None` if the document doesn't exist or no scene
+    /// has been built for it yet. The metrics are only collected while
+    /// `DebugFlags::SCENE_METRICS` is set.
+    ///
+    /// The request is ordered after any transaction previously sent through
+    /// this API, so the metrics include the display lists it set.
+    pub fn scene_metrics(&self, document_id: DocumentId) -> Option<SceneMetrics> {
+        let (tx, rx) = single_msg_channel();
+        self.api_sender.send(ApiMsg::GetSceneMetrics(document_id, tx)).unwrap();
+        rx.recv().unwrap()
+    }
+
+    /// Deletes the images, blob images and font instances of this API's
+    /// namespace that haven't been used by any frame of `document_id` built
+    /// from a display list of epoch `older_than` or later.
+    ///
+    /// Resources are only considered unused once a frame from such a display
+    /// list has been built, so nothing is purged if the document hasn't
+    /// rendered `older_than` yet. The returned keys are deleted and must not
+    /// be used or deleted again.
+    pub fn purge_unused_resources(
+        &mut self,
+        document_id: DocumentId,
+        older_than: Epoch,
+    ) -> UnusedResources {
+        let (tx, rx) = single_msg_channel();
+        self.api_sender.send(ApiMsg::ReportUnusedResources(document_id, older_than, tx)).unwrap();
+        let unused = rx.recv().unwrap();
+
+        if !unused.is_empty() {
+            let mut txn = Transaction::new();
+            for &key in &unused.images {
+                txn.delete_image(key);
+            }
+            for &key in &unused.blob_images {
+                txn.delete_blob_image(key);
+            }
+            for &key in &unused.font_instances {
+                txn.delete_font_instance(key);
+            }
+            self.send_transaction(document_id, txn);
+        }
+
+        unused
+    }
+
     /// Update debugging flags.
     pub fn set_debug_flags(&mut self, flags: DebugFlags) {
         self.resources.set_debug_flags(flags);
@@ -1219,6 +1752,7 @@ impl RenderApi {
             rasterized_blobs: Vec::new(),
             profile: TransactionProfile::new(),
             render_reasons: RenderReasons::empty(),
+            label: None,
         })
     }
 
@@ -1227,14 +1761,69 @@ impl RenderApi {
         // This assertion fails on Servo use-cases, because it creates different
         // `RenderApi` instances for layout and compositor.
         //assert_eq!(document_id.0, self.namespace_id);
+        self.backpressure.transaction_sent();
         self.api_sender
             .send(ApiMsg::UpdateDocuments(vec![self.frame_message(msg, document_id)]))
             .unwrap()
     }
 
     /// Send a transaction to WebRender.
+    ///
+    /// If `set_max_pending_transactions` was used and the render backend is
+    /// behind, the transaction is held back and merged with the following ones
+    /// instead. Transactions aren't held back for longer than
+    /// `MAX_COALESCING_DELAY_MS`: the held back transactions are sent along
+    /// with the first transaction sent after that.
     pub fn send_transaction(&mut self, document_id: DocumentId, transaction: Transaction) {
+        if let Some(max) = self.max_pending_transactions {
+            let now = precise_time_ns();
+            let start = *self.coalescing_start.get_or_insert(now);
+            let expired = now - start >= MAX_COALESCING_DELAY_MS * 1_000_000;
+            if !expired && self.backpressure.pending_transactions() >= max {
+                self.backpressure.throttled.store(true, Ordering::Release);
+                match self.coalesced_transactions.last_mut() {
+                    Some((id, pending)) if *id == document_id => pending.coalesce(transaction),
+                    _ => self.coalesced_transactions.push((document_id, transaction)),
+                }
+                return;
+            }
+        }
+
+        self.flush_coalesced_transactions();
+        self.send_transaction_impl(document_id, transaction);
+    }
+
+    /// Sends the transactions held back because of `set_max_pending_transactions`,
+    /// regardless of the current number of pending transactions. Embedders
+    /// typically do this when `RenderNotifier::ready_for_more_transactions` is
+    /// called, as nothing else would send them until the next transaction.
+    pub fn flush_coalesced_transactions(&mut self) {
+        self.coalescing_start = None;
+        for (document_id, transaction) in mem::take(&mut self.coalesced_transactions) {
+            self.send_transaction_impl(document_id, transaction);
+        }
+    }
+
+    /// Caps the number of transactions that can be waiting for the render
+    /// backend. Past the cap, transactions are merged together and held back
+    /// until the render backend catches up. `None`, the default, never holds
+    /// transactions back.
+    pub fn set_max_pending_transactions(&mut self, max: Option<usize>) {
+        self.max_pending_transactions = max;
+    }
+
+    /// Returns the backpressure signal shared by all the API objects of this
+    /// WebRender instance.
+    pub fn backpressure(&self) -> &Backpressure {
+        &self.backpressure
+    }
+
+    fn send_transaction_impl(&mut self, document_id: DocumentId, transaction: Transaction) {
         let mut transaction = transaction.finalize(document_id);
+        self.backpressure.transaction_sent();
+
+        #[cfg(feature = "capture")]
+        self.recorder.record(&transaction);
 
         self.resources.update(&mut transaction);
 
@@ -1338,10 +1927,67 @@ impl RenderApi {
         self.send_message(msg);
     }
 
+    /// Start recording the transactions sent to this WebRender instance, so
+    /// that they can be replayed with `wrench play_recording`.
+    pub fn start_transaction_recording(&self, path: PathBuf) {
+        self.send_debug_cmd(DebugCommand::StartTransactionRecording(path));
+    }
+
+    /// Stop recording transactions.
+    pub fn stop_transaction_recording(&self) {
+        self.send_debug_cmd(DebugCommand::StopTransactionRecording);
+    }
+
     /// Update the state of builtin debugging facilities.
     pub fn send_debug_cmd(&self, cmd: DebugCommand) {
-        let msg = ApiMsg::DebugCommand(cmd);
-        self.send_message(msg);
+        match cmd {
+            // Transactions are recorded as they are sent, so this is handled
+            // here rather than by the render backend.
+            DebugCommand::StartTransactionRecording(path) => self.recorder.start(path),
+            DebugCommand::StopTransactionRecording => self.recorder.stop(),
+            _ => {
+                let msg = ApiMsg::DebugCommand(cmd);
+                self.send_message(msg);
+            }
+        }
+    }
+
+    /// Add a named counter to the profiler overlay, so that embedder timings
+    /// (for example "Layout time") can be displayed alongside WebRender's own.
+    pub fn register_profiler_counter(&self, name: &'static str, unit: &'static str) {
+        self.send_debug_cmd(DebugCommand::RegisterProfilerCounter { name, unit });
+    }
+
+    /// Set the value of a counter registered with `register_profiler_counter`.
+    pub fn set_profiler_counter(&self, name: &'static str, value: f64) {
+        self.send_debug_cmd(DebugCommand::SetProfilerCounter(name, value));
+    }
+
+    /// Enable or disable the power-saving mode, for example when the embedder
+    /// detects that the device runs on battery.
+    ///
+    /// In this mode, frames requested only because animated properties
+    /// changed are built at half the requested rate. A skipped frame is
+    /// still built if no other frame is requested in time, so the final
+    /// state of an animation is always rendered. The "Power-saving skipped
+    /// frames" profiler counter shows the frames that were avoided.
+    ///
+    /// Only the rate of these frames is affected. The mode doesn't defer any
+    /// rasterization or change how frames are presented. To save more work,
+    /// embedders can shrink the area that blob images are rasterized in ahead
+    /// of scrolling with `Transaction::set_blob_image_visible_area`, and
+    /// enable partial present through `CompositorConfig::partial_present`.
+    pub fn set_power_saving(&mut self, enabled: bool) {
+        self.set_parameter(Parameter::Bool(BoolParameter::PowerSaving, enabled));
+    }
+
+    /// Darkens glyph edges by the given percentage (0 to 100), applied when
+    /// text is drawn. This makes text heavier without changing fonts, for
+    /// example for low-vision users. Zero, the default, leaves the rasterized
+    /// glyphs untouched. Cached tiles are invalidated, so the change shows up
+    /// in the next generated frame.
+    pub fn set_text_contrast(&mut self, percent: u8) {
+        self.set_parameter(Parameter::Int(IntParameter::TextContrast, percent.min(100) as i32));
     }
 
     /// Update a instance-global parameter.
@@ -1373,6 +2019,219 @@ fn window_size_sanity_check(size: DeviceIntSize) {
     }
 }
 
+/// A range of namespaces reserved with `RenderApiSender::reserve_namespaces`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IdNamespaceRange {
+    start: u32,
+    end: u32,
+}
+
+impl IdNamespaceRange {
+    pub(crate) fn new(start: u32, count: u32) -> Self {
+        IdNamespaceRange {
+            start,
+            end: start + count,
+        }
+    }
+
+    /// Number of namespaces in the range.
+    pub fn len(&self) -> usize {
+        (self.end - self.start) as usize
+    }
+
+    /// Returns true if the range contains no namespace.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Returns the namespace at `index` in the range.
+    pub fn get(&self, index: usize) -> Option<IdNamespace> {
+        if index < self.len() {
+            Some(IdNamespace(self.start + index as u32))
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if `namespace` belongs to the range.
+    pub fn contains(&self, namespace: IdNamespace) -> bool {
+        namespace.0 >= self.start && namespace.0 < self.end
+    }
+
+    /// Iterates over the namespaces of the range.
+    pub fn iter(&self) -> impl Iterator<Item = IdNamespace> {
+        (self.start .. self.end).map(IdNamespace)
+    }
+}
+
+/// Resource keys released by `RenderApi::purge_unused_resources`.
+#[derive(Clone, Debug, Default)]
+pub struct UnusedResources {
+    /// Images, not including blob images.
+    pub images: Vec<ImageKey>,
+    ///
+    pub blob_images: Vec<BlobImageKey>,
+    ///
+    pub font_instances: Vec<FontInstanceKey>,
+}
+
+impl UnusedResources {
+    /// Returns true if there are no unused resources.
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty() &&
+        self.blob_images.is_empty() &&
+        self.font_instances.is_empty()
+    }
+}
+
+/// The options a font instance was created with, see
+/// `RenderApi::get_font_instance_info`.
+#[derive(Clone, Debug)]
+pub struct FontInstanceInfo {
+    /// The font the instance was created from.
+    pub font_key: FontKey,
+    ///
+    pub size: f32,
+    ///
+    pub options: FontInstanceOptions,
+    ///
+    pub platform_options: Option<FontInstancePlatformOptions>,
+    ///
+    pub variations: Vec<FontVariation>,
+}
+
+/// An image registered with `Transaction::add_image`, see
+/// `RenderApi::list_resources`.
+#[derive(Clone, Debug)]
+pub struct RegisteredImage {
+    ///
+    pub key: ImageKey,
+    ///
+    pub size: DeviceIntSize,
+    ///
+    pub format: ImageFormat,
+    /// Number of frames built since the image was last used by a frame, or
+    /// `None` if no frame was built since it was added.
+    pub frames_since_use: Option<u64>,
+}
+
+/// A blob image registered with `Transaction::add_blob_image`, see
+/// `RenderApi::list_resources`.
+#[derive(Clone, Debug)]
+pub struct RegisteredBlobImage {
+    ///
+    pub key: BlobImageKey,
+    /// Size of the visible area of the blob.
+    pub size: DeviceIntSize,
+    /// Number of frames built since the blob was last used by a frame, or
+    /// `None` if no frame was built since it was added.
+    pub frames_since_use: Option<u64>,
+}
+
+/// A font registered with `Transaction::add_raw_font` or
+/// `Transaction::add_native_font`, see `RenderApi::list_resources`.
+#[derive(Clone, Debug)]
+pub struct RegisteredFont {
+    ///
+    pub key: FontKey,
+    /// Number of frames built since any instance of the font was last used by
+    /// a frame, or `None` if it has no instance that was ever used.
+    pub frames_since_use: Option<u64>,
+}
+
+/// A font instance registered with `Transaction::add_font_instance`, see
+/// `RenderApi::list_resources`.
+#[derive(Clone, Debug)]
+pub struct RegisteredFontInstance {
+    ///
+    pub key: FontInstanceKey,
+    /// The font the instance was created from.
+    pub font_key: FontKey,
+    ///
+    pub size: f32,
+    /// Number of frames built since the instance was last used by a frame, or
+    /// `None` if no frame was built since it was added.
+    pub frames_since_use: Option<u64>,
+}
+
+/// The resources currently registered by a namespace, see
+/// `RenderApi::list_resources`.
+#[derive(Clone, Debug)]
+pub struct NamespaceResources {
+    ///
+    pub namespace: IdNamespace,
+    ///
+    pub images: Vec<RegisteredImage>,
+    ///
+    pub blob_images: Vec<RegisteredBlobImage>,
+    ///
+    pub fonts: Vec<RegisteredFont>,
+    ///
+    pub font_instances: Vec<RegisteredFontInstance>,
+}
+
+impl NamespaceResources {
+    pub(crate) fn new(namespace: IdNamespace) -> Self {
+        NamespaceResources {
+            namespace,
+            images: Vec::new(),
+            blob_images: Vec::new(),
+            fonts: Vec::new(),
+            font_instances: Vec::new(),
+        }
+    }
+}
+
+/// Complexity metrics of the display list of a single pipeline, gathered
+/// while building the scene that contains it.
+///
+/// If a pipeline is embedded by several iframes, its items and nodes are
+/// counted once per instance.
+#[derive(Clone, Debug)]
+pub struct DisplayListMetrics {
+    /// Epoch of the display list the metrics were gathered from.
+    pub epoch: Epoch,
+    /// Number of display items of each type, keyed by item name.
+    pub item_counts: FastHashMap<&'static str, usize>,
+    /// Size of the serialized display list.
+    pub serialized_bytes: usize,
+    /// Number of spatial nodes (reference, scroll and sticky frames).
+    pub spatial_nodes: usize,
+    /// Number of clip items.
+    pub clip_nodes: usize,
+}
+
+impl DisplayListMetrics {
+    pub(crate) fn new() -> Self {
+        DisplayListMetrics {
+            epoch: Epoch::invalid(),
+            item_counts: FastHashMap::default(),
+            serialized_bytes: 0,
+            spatial_nodes: 0,
+            clip_nodes: 0,
+        }
+    }
+
+    /// Total number of display items.
+    pub fn total_items(&self) -> usize {
+        self.item_counts.values().sum()
+    }
+}
+
+/// Metrics of the last scene built for a document, see
+/// `RenderApi::scene_metrics`.
+#[derive(Clone, Debug, Default)]
+pub struct SceneMetrics {
+    /// Metrics of each pipeline that was part of the scene.
+    pub pipelines: FastHashMap<PipelineId, DisplayListMetrics>,
+    /// Number of interned items that were already present in the interners.
+    pub interning_hits: usize,
+    /// Number of interned items that had to be added to the interners.
+    pub interning_misses: usize,
+    /// Time spent building the scene, in milliseconds.
+    pub build_time_ms: f64,
+}
+
 /// Collection of heap sizes, in bytes.
 /// cbindgen:derive-eq=false
 /// cbindgen:derive-ostream=false
@@ -1390,6 +2249,8 @@ pub struct MemoryReport {
     pub hit_testers: usize,
     pub fonts: usize,
     pub weak_fonts: usize,
+    /// Rasterized glyph images kept by the glyph rasterizer backend.
+    pub glyph_images: usize,
     pub images: usize,
     pub rasterized_blobs: usize,
     pub shader_cache: usize,
@@ -1404,6 +2265,10 @@ pub struct MemoryReport {
     pub gpu_cache_textures: usize,
     pub vertex_data_textures: usize,
     pub render_target_textures: usize,
+    /// Render targets retained in the pool between frames.
+    pub render_target_pool_textures: usize,
+    /// Render targets of the pool that were not used by the last frame.
+    pub render_target_pool_idle_textures: usize,
     pub picture_tile_textures: usize,
     pub atlas_textures: usize,
     pub standalone_textures: usize,
@@ -8,24 +8,27 @@
 //! See the comment at the top of the `renderer` module for a description of
 //! how these two pieces interact.
 
-use api::{DebugFlags, Parameter, BoolParameter, PrimitiveFlags};
-use api::{DocumentId, ExternalScrollId, HitTestResult};
-use api::{IdNamespace, PipelineId, RenderNotifier, SampledScrollOffset};
+use api::{DebugFlags, Parameter, BoolParameter, IntParameter, PrimitiveFlags, ImageFormat};
+use api::{DocumentId, Epoch, ExternalScrollId, HitTestResult};
+use api::{ColorF, IdNamespace, PipelineId, PropertyBindingId, RenderNotifier, SampledScrollOffset};
 use api::{NotificationRequest, Checkpoint, QualitySettings};
-use api::{FramePublishId, PrimitiveKeyKind, RenderReasons};
+use api::{AccessibilityColorFilter, FramePublishId, PostProcessParams, PrimitiveKeyKind, RenderReasons};
+use api::SubpixelTextPolicy;
 use api::units::*;
-use api::channel::{single_msg_channel, Sender, Receiver};
+use api::channel::{single_msg_channel, Sender, Receiver, RecvTimeoutError};
 use crate::AsyncPropertySampler;
 #[cfg(any(feature = "capture", feature = "replay"))]
 use crate::render_api::CaptureBits;
 #[cfg(feature = "replay")]
 use crate::render_api::CapturedDocument;
-use crate::render_api::{MemoryReport, TransactionMsg, ResourceUpdate, ApiMsg, FrameMsg, ClearCache, DebugCommand};
+use crate::render_api::{MemoryReport, TransactionMsg, ResourceUpdate, ApiMsg, FrameMsg, DebugCommand};
+use crate::render_api::{Backpressure, MemoryPressureLevel, MemoryPressureTargets};
+use crate::render_api::IdNamespaceRange;
 use crate::clip::{ClipIntern, PolygonIntern, ClipStoreScratchBuffer};
 use crate::filterdata::FilterDataIntern;
 #[cfg(any(feature = "capture", feature = "replay"))]
 use crate::capture::CaptureConfig;
-use crate::composite::{CompositorKind, CompositeDescriptor};
+use crate::composite::{CompositorKind, CompositeDescriptor, TileSurfaceKind};
 use crate::frame_builder::{FrameBuilder, FrameBuilderConfig, FrameScratchBuffer};
 use glyph_rasterizer::{FontInstance};
 use crate::gpu_cache::GpuCache;
@@ -33,7 +36,7 @@ use crate::hit_test::{HitTest, HitTester, SharedHitTester};
 use crate::intern::DataStore;
 #[cfg(any(feature = "capture", feature = "replay"))]
 use crate::internal_types::{DebugOutput};
-use crate::internal_types::{FastHashMap, RenderedDocument, ResultMsg, FrameId, FrameStamp};
+use crate::internal_types::{FastHashMap, FrameRequestTimes, RenderedDocument, ResultMsg, FrameId, FrameStamp};
 use malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
 use crate::picture::{PictureScratchBuffer, SliceId, TileCacheInstance, TileCacheParams, SurfaceInfo, RasterConfig};
 use crate::picture::{PicturePrimitive};
@@ -60,6 +63,7 @@ use crate::telemetry::Telemetry;
 use serde::{Serialize, Deserialize};
 #[cfg(feature = "replay")]
 use std::collections::hash_map::Entry::{Occupied, Vacant};
+use std::collections::VecDeque;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::{mem, u32};
@@ -71,6 +75,14 @@ use time::precise_time_ns;
 use core::time::Duration;
 use crate::util::{Recycler, VecHelper, drain_filter};
 
+/// How many display list epochs each document remembers the first frame of,
+/// see `RenderApi::purge_unused_resources`.
+const MAX_EPOCH_FRAMES: usize = 64;
+
+/// The longest a frame skipped by the power-saving mode can be delayed, in
+/// nanoseconds.
+const MAX_SKIPPED_ANIMATION_FRAME_DELAY_NS: u64 = 50_000_000;
+
 #[cfg_attr(feature = "capture", derive(Serialize))]
 #[cfg_attr(feature = "replay", derive(Deserialize))]
 #[derive(Copy, Clone)]
@@ -231,7 +243,9 @@ impl DataStores {
     ) -> &PrimTemplateCommonData {
         match prim_inst.kind {
             PrimitiveInstanceKind::Rectangle { data_handle, .. } |
-            PrimitiveInstanceKind::Clear { data_handle, .. } => {
+            PrimitiveInstanceKind::Clear { data_handle, .. } |
+            PrimitiveInstanceKind::EmbedderDraw { data_handle, .. } |
+            PrimitiveInstanceKind::Path { data_handle, .. } => {
                 let prim_data = &self.prim[data_handle];
                 &prim_data.common
             }
@@ -247,6 +261,10 @@ impl DataStores {
                 let prim_data = &self.line_decoration[data_handle];
                 &prim_data.common
             }
+            PrimitiveInstanceKind::VectorPath { data_handle, .. } => {
+                let prim_data = &self.vector_path[data_handle];
+                &prim_data.common
+            }
             PrimitiveInstanceKind::LinearGradient { data_handle, .. }
             | PrimitiveInstanceKind::CachedLinearGradient { data_handle, .. } => {
                 let prim_data = &self.linear_grad[data_handle];
@@ -359,6 +377,12 @@ struct Document {
     frame_is_valid: bool,
     hit_tester_is_valid: bool,
     rendered_frame_is_valid: bool,
+    /// Set when scroll offsets changed since the last built frame. This
+    /// invalidates the frame, see `frame_is_valid`.
+    scroll_offsets_changed: bool,
+    /// Set when the next frame only needs to be built because scroll offsets
+    /// changed, which lets frame building skip some work.
+    scroll_only_frame: bool,
     /// We track this information to be able to display debugging information from the
     /// renderer.
     has_built_scene: bool,
@@ -385,6 +409,69 @@ struct Document {
 
     profile: TransactionProfile,
     frame_stats: Option<FullFrameStats>,
+
+    /// Labels of the transactions applied since the last built frame.
+    transaction_labels: Vec<String>,
+
+    /// The first frame (in `ResourceCache::frames_built` terms) built from
+    /// each display list epoch, oldest first.
+    epoch_frames: VecDeque<(Epoch, u64)>,
+
+    /// The minimum time between two frames in nanoseconds, see
+    /// `Transaction::set_max_frame_rate`.
+    min_frame_interval: Option<u64>,
+    /// When the last frame was requested, in nanoseconds.
+    last_frame_time: u64,
+    /// The render reasons of a frame request that was deferred because of
+    /// `min_frame_interval` or of the power-saving mode.
+    deferred_frame: Option<RenderReasons>,
+    /// When the last frame was requested, in nanoseconds.
+    last_frame_request_time: u64,
+    /// If the last animation frame was skipped by the power-saving mode, the
+    /// time by which it must be built if no other frame is requested.
+    skipped_animation_frame: Option<u64>,
+
+    /// The color filter applied when compositing, see
+    /// `Transaction::set_color_filter`.
+    color_filter: Option<AccessibilityColorFilter>,
+
+    /// The uniforms of the post-processing shader, see
+    /// `Transaction::set_post_process_params`.
+    post_process_params: PostProcessParams,
+
+    /// How text asking for subpixel anti-aliasing is drawn, see
+    /// `Transaction::set_subpixel_text_policy`.
+    subpixel_text_policy: SubpixelTextPolicy,
+
+    /// Whether all picture cache tiles must be redrawn by the next frame, for
+    /// example because the text contrast changed.
+    invalidate_all_tiles: bool,
+
+    /// When the blinking carets of the scene were last shown by a new scene,
+    /// in nanoseconds.
+    caret_blink_start: u64,
+    /// The scroll position of each scrollbar thumb of the scene, keyed by its
+    /// transform binding, used to fade thumbs once scrolling stops.
+    scrollbar_activity: FastHashMap<PropertyBindingId, ScrollbarActivity>,
+    /// When the next frame must be built to show or hide a blinking caret, or
+    /// to fade a scrollbar thumb.
+    next_timed_update: Option<u64>,
+
+    /// When the oldest frame request that no frame answered yet was made, and
+    /// when the render backend received it, in nanoseconds.
+    pending_frame_request: Option<(u64, u64)>,
+}
+
+/// Makes sure a frame is built at `time`.
+fn schedule_timed_update(next_timed_update: &mut Option<u64>, time: u64) {
+    *next_timed_update = Some(next_timed_update.map_or(time, |next| next.min(time)));
+}
+
+/// When a scrollbar thumb last moved, and the color it was last drawn with.
+struct ScrollbarActivity {
+    offset: f32,
+    last_scrolled: u64,
+    color: ColorF,
 }
 
 impl Document {
@@ -410,6 +497,8 @@ impl Document {
             frame_is_valid: false,
             hit_tester_is_valid: false,
             rendered_frame_is_valid: false,
+            scroll_offsets_changed: false,
+            scroll_only_frame: false,
             has_built_scene: false,
             data_stores: DataStores::default(),
             spatial_tree: SpatialTree::new(),
@@ -421,13 +510,118 @@ impl Document {
             profile: TransactionProfile::new(),
             rg_builder: RenderTaskGraphBuilder::new(),
             frame_stats: None,
+            transaction_labels: Vec::new(),
+            epoch_frames: VecDeque::new(),
+            min_frame_interval: None,
+            last_frame_time: 0,
+            deferred_frame: None,
+            last_frame_request_time: 0,
+            skipped_animation_frame: None,
+            color_filter: None,
+            post_process_params: PostProcessParams::default(),
+            subpixel_text_policy: SubpixelTextPolicy::Auto,
+            invalidate_all_tiles: false,
+            caret_blink_start: 0,
+            scrollbar_activity: FastHashMap::default(),
+            next_timed_update: None,
+            pending_frame_request: None,
+        }
+    }
+
+    /// Records a frame request made at `request_time`, unless an older one is
+    /// still waiting for a frame.
+    fn note_frame_request(&mut self, request_time: u64) {
+        if self.pending_frame_request.is_none() {
+            self.pending_frame_request = Some((request_time, precise_time_ns()));
+        }
+    }
+
+    /// When the deferred frame request, if any, can be honored.
+    fn deferred_frame_deadline(&self) -> Option<u64> {
+        let deferred_frame = self.deferred_frame.map(|_| {
+            let deadline = self.last_frame_time + self.min_frame_interval.unwrap_or(0);
+            deadline.max(self.skipped_animation_frame.unwrap_or(0))
+        });
+        match (deferred_frame, self.next_timed_update) {
+            (Some(deadline), Some(update)) => Some(deadline.min(update)),
+            (deadline, update) => deadline.or(update),
+        }
+    }
+
+    /// Sets the animated colors of the blinking carets for a frame built at
+    /// `now`, and schedules the frame that next shows or hides one. Returns
+    /// whether any caret was shown or hidden.
+    fn update_carets(&mut self, now: u64) -> bool {
+        if self.has_built_scene {
+            self.caret_blink_start = now;
+        }
+        let elapsed = now.saturating_sub(self.caret_blink_start);
+        let mut colors_changed = false;
+
+        for caret in &self.scene.carets {
+            colors_changed |= self.dynamic_properties.set_color(caret.binding_id, caret.color_at(elapsed));
+            let toggle = self.caret_blink_start + caret.next_toggle(elapsed);
+            schedule_timed_update(&mut self.next_timed_update, toggle);
+        }
+
+        colors_changed
+    }
+
+    /// Moves the scrollbar thumbs to match the offsets of their scroll frames
+    /// for a frame built at `now`, fades out those that stopped scrolling, and
+    /// schedules the next frame of a fade. Returns whether the color of any
+    /// thumb changed.
+    fn update_scrollbar_thumbs(&mut self, now: u64) -> bool {
+        let mut colors_changed = false;
+        let mut previous_activity = mem::take(&mut self.scrollbar_activity);
+
+        for thumb in &self.scene.scrollbar_thumbs {
+            let scroll_frame = self.spatial_tree.get_spatial_node(thumb.scroll_node_index);
+            let offset = thumb.offset(scroll_frame);
+
+            // Thumbs show up when they are first drawn, and whenever they move.
+            let (last_scrolled, previous_color) = match previous_activity.remove(&thumb.transform_id) {
+                Some(activity) if activity.offset == offset => (activity.last_scrolled, Some(activity.color)),
+                Some(activity) => (now, Some(activity.color)),
+                None => (now, None),
+            };
+            let idle = now.saturating_sub(last_scrolled);
+            let color = thumb.color_at(idle);
+            colors_changed |= previous_color != Some(color);
+
+            self.dynamic_properties.set_transform(thumb.transform_id, thumb.transform(offset));
+            self.dynamic_properties.set_color(thumb.color_id, color);
+            self.scrollbar_activity.insert(thumb.transform_id, ScrollbarActivity {
+                offset,
+                last_scrolled,
+                color,
+            });
+
+            if let Some(change) = thumb.next_color_change(idle) {
+                schedule_timed_update(&mut self.next_timed_update, last_scrolled + change);
+            }
         }
+
+        colors_changed
     }
 
     fn can_render(&self) -> bool {
         self.scene.has_root_pipeline
     }
 
+    /// Whether the last frame of the document is up to date, and nothing is
+    /// scheduled to change it.
+    fn is_idle(&self) -> bool {
+        if !self.can_render() {
+            return true;
+        }
+
+        self.frame_is_valid &&
+            self.rendered_frame_is_valid &&
+            self.deferred_frame.is_none() &&
+            self.next_timed_update.is_none()
+    }
+
     fn has_pixels(&self) -> bool {
         !self.view.scene.device_rect.is_empty()
     }
@@ -462,7 +656,7 @@ impl Document {
 
                 if self.set_scroll_offsets(id, offset) {
                     self.hit_tester_is_valid = false;
-                    self.frame_is_valid = false;
+                    self.scroll_offsets_changed = true;
                 }
 
                 return DocumentOps {
@@ -489,6 +683,35 @@ impl Document {
                     }
                 }
             }
+            FrameMsg::SetMaxFrameRate(max_fps) => {
+                self.min_frame_interval = max_fps
+                    .filter(|&fps| fps > 0.0)
+                    .map(|fps| (1_000_000_000.0 / fps) as u64);
+            }
+            FrameMsg::SetColorFilter(filter) => {
+                if self.color_filter != filter {
+                    self.color_filter = filter;
+                    // Every tile has to be composited again with the new filter.
+                    self.frame_is_valid = false;
+                    self.dirty_rects_are_valid = false;
+                    self.rendered_frame_is_valid = false;
+                }
+            }
+            FrameMsg::SetPostProcessParams(params) => {
+                if self.post_process_params != params {
+                    self.post_process_params = params;
+                    // The frame is unchanged but has to be presented again.
+                    self.rendered_frame_is_valid = false;
+                }
+            }
+            FrameMsg::SetSubpixelTextPolicy(policy) => {
+                if self.subpixel_text_policy != policy {
+                    self.subpixel_text_policy = policy;
+                    // The glyphs of every tile may have to be rasterized again.
+                    self.invalidate_all_tiles = true;
+                    self.frame_is_valid = false;
+                }
+            }
         }
 
         DocumentOps::nop()
@@ -511,8 +734,26 @@ impl Document {
         assert!(self.stamp.frame_id() != FrameId::INVALID,
                 "First frame increment must happen before build_frame()");
 
+        let mut scroll_only = mem::replace(&mut self.scroll_only_frame, false);
+
+        self.next_timed_update = None;
+        // Scroll-only frames don't pick up changes to animated colors.
+        if self.update_carets(frame_build_start_time) {
+            scroll_only = false;
+        }
+        if self.update_scrollbar_thumbs(frame_build_start_time) {
+            scroll_only = false;
+        }
+
+        self.scene.config.subpixel_text_policy = self.subpixel_text_policy;
+
+        let force_invalidation = self.scene.config.force_invalidation;
+        if mem::replace(&mut self.invalidate_all_tiles, false) {
+            self.scene.config.force_invalidation = true;
+        }
+
         let frame = {
-            let frame = self.frame_builder.build(
+            let mut frame = self.frame_builder.build(
                 &mut self.scene,
                 resource_cache,
                 gpu_cache,
@@ -526,18 +767,40 @@ impl Document {
                 tile_caches,
                 &mut self.spatial_tree,
                 self.dirty_rects_are_valid,
+                scroll_only,
                 &mut self.profile,
             );
 
+            frame.composite_state.color_filter = self.color_filter;
+            frame.composite_state.post_process_params = self.post_process_params;
+
             frame
         };
 
+        self.scene.config.force_invalidation = force_invalidation;
+
         self.frame_is_valid = true;
         self.dirty_rects_are_valid = true;
 
+        if let Some(&epoch) = self.scene.pipeline_epochs.values().max() {
+            if self.epoch_frames.back().map_or(true, |&(last, _)| last != epoch) {
+                if self.epoch_frames.len() == MAX_EPOCH_FRAMES {
+                    self.epoch_frames.pop_front();
+                }
+                self.epoch_frames.push_back((epoch, resource_cache.frames_built(self.id)));
+            }
+        }
+
         let is_new_scene = self.has_built_scene;
         self.has_built_scene = false;
 
+        let frame_request = self.pending_frame_request.take().map(|(request_time, received_time)| {
+            FrameRequestTimes {
+                request_time,
+                backend_queue_time: frame_build_start_time.saturating_sub(received_time),
+            }
+        });
+
         let frame_build_time_ms =
             profiler::ns_to_ms(precise_time_ns() - frame_build_start_time);
         self.profile.set(profiler::FRAME_BUILDING_TIME, frame_build_time_ms);
@@ -553,9 +816,21 @@ impl Document {
             profile: self.profile.take_and_reset(),
             frame_stats: frame_stats,
             render_reasons,
+            transaction_labels: mem::take(&mut self.transaction_labels),
+            frame_request,
         }
     }
 
+    /// Returns the first frame built from a display list of epoch `epoch` or
+    /// later, if any. When `epoch` predates the recorded history the oldest
+    /// known frame is returned, which keeps more resources alive than needed.
+    fn first_frame_since(&self, epoch: Epoch) -> Option<u64> {
+        self.epoch_frames
+            .iter()
+            .find(|&&(frame_epoch, _)| frame_epoch >= epoch)
+            .map(|&(_, frame)| frame)
+    }
+
     fn rebuild_hit_tester(&mut self) {
         self.spatial_tree.update_tree(&self.dynamic_properties);
 
@@ -702,6 +977,8 @@ pub struct RenderBackend {
     size_of_ops: Option<MallocSizeOfOps>,
     debug_flags: DebugFlags,
     namespace_alloc_by_client: bool,
+    /// Namespaces reserved for the client with `ApiMsg::ReserveNamespaces`.
+    reserved_namespaces: Vec<IdNamespaceRange>,
 
     recycler: Recycler,
 
@@ -720,6 +997,11 @@ pub struct RenderBackend {
 
     /// The id of the latest PublishDocument
     frame_publish_id: FramePublishId,
+
+    backpressure: Arc<Backpressure>,
+
+    /// See `RenderApi::set_power_saving`.
+    power_saving: bool,
 }
 
 impl RenderBackend {
@@ -734,13 +1016,20 @@ impl RenderBackend {
         size_of_ops: Option<MallocSizeOfOps>,
         debug_flags: DebugFlags,
         namespace_alloc_by_client: bool,
+        backpressure: Arc<Backpressure>,
     ) -> RenderBackend {
+        let mut gpu_cache = GpuCache::new();
+        gpu_cache.set_reclaim_policy(
+            frame_config.gpu_cache_reclaim_threshold,
+            frame_config.gpu_cache_reclaim_delay,
+        );
+
         RenderBackend {
             api_rx,
             result_tx,
             scene_tx,
             resource_cache,
-            gpu_cache: GpuCache::new(),
+            gpu_cache,
             frame_config,
             default_compositor_kind : frame_config.compositor_kind,
             documents: FastHashMap::default(),
@@ -749,6 +1038,7 @@ impl RenderBackend {
             size_of_ops,
             debug_flags,
             namespace_alloc_by_client,
+            reserved_namespaces: Vec::new(),
             recycler: Recycler::new(),
             #[cfg(feature = "capture")]
             capture_config: None,
@@ -756,6 +1046,8 @@ impl RenderBackend {
             loaded_resource_sequence_id: 0,
             tile_caches: FastHashMap::default(),
             frame_publish_id: FramePublishId::first(),
+            backpressure,
+            power_saving: false,
         }
     }
 
@@ -763,6 +1055,11 @@ impl RenderBackend {
         IdNamespace(NEXT_NAMESPACE_ID.fetch_add(1, Ordering::Relaxed) as u32)
     }
 
+    fn next_namespace_ids(count: u32) -> IdNamespaceRange {
+        let start = NEXT_NAMESPACE_ID.fetch_add(count as usize, Ordering::Relaxed) as u32;
+        IdNamespaceRange::new(start, count)
+    }
+
     pub fn run(&mut self) {
         let mut frame_counter: u32 = 0;
         let mut status = RenderBackendStatus::Continue;
@@ -772,11 +1069,38 @@ impl RenderBackend {
         }
 
         while let RenderBackendStatus::Continue = status {
-            status = match self.api_rx.recv() {
-                Ok(msg) => {
+            self.update_idle_state();
+
+            let deadline = self.documents
+                .values()
+                .filter_map(|doc| doc.deferred_frame_deadline())
+                .min();
+
+            let msg = match deadline {
+                Some(deadline) => {
+                    let now = precise_time_ns();
+                    let timeout = Duration::from_nanos(deadline.saturating_sub(now));
+                    match self.api_rx.recv_timeout(timeout) {
+                        Ok(msg) => Some(msg),
+                        Err(RecvTimeoutError::Timeout) => {
+                            // The wait can end a bit early, in which case the
+                            // deadline is waited for again.
+                            if precise_time_ns() >= deadline {
+                                self.build_deferred_frames(&mut frame_counter);
+                            }
+                            continue;
+                        }
+                        Err(RecvTimeoutError::Disconnected) => None,
+                    }
+                }
+                None => self.api_rx.recv().ok(),
+            };
+
+            status = match msg {
+                Some(msg) => {
                     self.process_api_msg(msg, &mut frame_counter)
                 }
-                Err(..) => { RenderBackendStatus::ShutDown(None) }
+                None => { RenderBackendStatus::ShutDown(None) }
             };
         }
 
@@ -857,6 +1181,10 @@ impl RenderBackend {
                 } else {
                     Some(txn.frame_stats)
                 };
+                doc.transaction_labels.extend(txn.label.take());
+                if let Some(request_time) = txn.frame_request_time {
+                    doc.note_frame_request(request_time);
+                }
 
                 // Before updating the spatial tree, save the most recently sampled
                 // scroll offsets (which include async deltas).
@@ -957,9 +1285,18 @@ impl RenderBackend {
                 sender.send(Self::next_namespace_id()).unwrap();
             }
             ApiMsg::CloneApiByClient(namespace_id) => {
-                assert!(self.namespace_alloc_by_client);
+                assert!(
+                    self.namespace_alloc_by_client ||
+                    self.reserved_namespaces.iter().any(|range| range.contains(namespace_id))
+                );
                 debug_assert!(!self.documents.iter().any(|(did, _doc)| did.namespace_id == namespace_id));
             }
+            ApiMsg::ReserveNamespaces(count, sender) => {
+                assert!(!self.namespace_alloc_by_client);
+                let range = Self::next_namespace_ids(count);
+                self.reserved_namespaces.push(range);
+                sender.send(range).unwrap();
+            }
             ApiMsg::AddDocument(document_id, initial_size) => {
                 let document = Document::new(
                     document_id,
@@ -968,26 +1305,36 @@ impl RenderBackend {
                 let old = self.documents.insert(document_id, document);
                 debug_assert!(old.is_none());
             }
-            ApiMsg::MemoryPressure => {
-                // This is drastic. It will basically flush everything out of the cache,
-                // and the next frame will have to rebuild all of its resources.
-                // We may want to look into something less extreme, but on the other hand this
-                // should only be used in situations where are running low enough on memory
-                // that we risk crashing if we don't do something about it.
-                // The advantage of clearing the cache completely is that it gets rid of any
-                // remaining fragmentation that could have persisted if we kept around the most
-                // recently used resources.
-                self.resource_cache.clear(ClearCache::all());
-
-                self.gpu_cache.clear();
-
-                for (_, doc) in &mut self.documents {
-                    doc.scratch.memory_pressure();
+            ApiMsg::MemoryPressure(level, targets, tx) => {
+                let critical = level == MemoryPressureLevel::Critical;
+
+                let report = self.resource_cache.on_memory_pressure(level, targets);
+
+                if critical && targets.contains(MemoryPressureTargets::PICTURE_TILES) {
                     for tile_cache in self.tile_caches.values_mut() {
                         tile_cache.memory_pressure(&mut self.resource_cache);
                     }
                 }
 
+                // Critical pressure on everything is drastic. It will basically flush
+                // everything out of the caches, and the next frame will have to rebuild
+                // all of its resources. This should only be used in situations where
+                // we are running low enough on memory that we risk crashing if we
+                // don't do something about it. The advantage of clearing the caches
+                // completely is that it gets rid of any remaining fragmentation that
+                // could have persisted if we kept around the most recently used resources.
+                if critical && targets == MemoryPressureTargets::all() {
+                    self.gpu_cache.clear();
+
+                    for (_, doc) in &mut self.documents {
+                        doc.scratch.memory_pressure();
+                    }
+                }
+
+                if let Some(tx) = tx {
+                    tx.send(report).unwrap();
+                }
+
                 let resource_updates = self.resource_cache.pending_updates();
                 let msg = ResultMsg::UpdateResources {
                     resource_updates,
@@ -999,6 +1346,63 @@ impl RenderBackend {
             ApiMsg::ReportMemory(tx) => {
                 self.report_memory(tx);
             }
+            ApiMsg::ReportDocumentMemory(tx) => {
+                self.report_document_memory(tx);
+            }
+            ApiMsg::ReportUnusedResources(document_id, epoch, tx) => {
+                let unused = self.documents
+                    .get(&document_id)
+                    .and_then(|doc| doc.first_frame_since(epoch))
+                    .map(|frame| self.resource_cache.unused_resources(document_id.namespace_id, document_id, frame))
+                    .unwrap_or_default();
+                tx.send(unused).unwrap();
+            }
+            ApiMsg::ListResources(tx) => {
+                tx.send(self.resource_cache.list_resources()).unwrap();
+            }
+            ApiMsg::ExternalImagesChanged(ids) => {
+                // Bumping the generation of the images invalidates the tiles
+                // they were drawn in, and re-uploads buffer images, when the
+                // next frame is built.
+                if self.resource_cache.invalidate_external_images(&ids) {
+                    // Build the frame right away rather than waiting for the
+                    // embedder to request one, so that the new video frames
+                    // are shown at the next composite.
+                    let document_ids: Vec<DocumentId> = self.documents.keys().cloned().collect();
+                    self.prepare_for_frames();
+                    self.maybe_force_nop_documents(
+                        frame_counter,
+                        |document_id| document_ids.contains(&document_id),
+                    );
+                    let mut built_frame = false;
+                    for document_id in document_ids {
+                        self.documents.get_mut(&document_id).unwrap().frame_is_valid = false;
+                        built_frame |= self.update_document(
+                            document_id,
+                            Vec::default(),
+                            Vec::default(),
+                            Vec::default(),
+                            true,
+                            RenderReasons::ASYNC_IMAGE,
+                            None,
+                            false,
+                            frame_counter,
+                            false,
+                            None,
+                        );
+                    }
+                    if built_frame {
+                        #[cfg(feature = "capture")]
+                        self.save_capture_sequence();
+                    }
+                    self.bookkeep_after_frames();
+                }
+            }
+            ApiMsg::GetSceneMetrics(document_id, tx) => {
+                self.send_backend_message(
+                    SceneBuilderRequest::GetSceneMetrics(document_id, tx)
+                );
+            }
             ApiMsg::DebugCommand(option) => {
                 let msg = match option {
                     DebugCommand::SetPictureTileSize(tile_size) => {
@@ -1086,6 +1490,15 @@ impl RenderBackend {
 
                         return RenderBackendStatus::Continue;
                     }
+                    DebugCommand::DumpTextureCache(ref root) => {
+                        if let Err(e) = self.resource_cache.texture_cache.dump_to_directory(root) {
+                            error!("Unable to dump the texture cache to {:?}: {:?}", root, e);
+                            return RenderBackendStatus::Continue;
+                        }
+
+                        // The renderer saves the texture contents.
+                        ResultMsg::DebugCommand(option)
+                    }
                     DebugCommand::SimulateLongSceneBuild(time_ms) => {
                         let _ = self.scene_tx.send(SceneBuilderRequest::SimulateLongSceneBuild(time_ms));
                         return RenderBackendStatus::Continue;
@@ -1095,8 +1508,11 @@ impl RenderBackend {
                         self.gpu_cache.set_debug_flags(flags);
 
                         let force_invalidation = flags.contains(DebugFlags::FORCE_PICTURE_INVALIDATION);
-                        if self.frame_config.force_invalidation != force_invalidation {
+                        let scene_metrics = flags.contains(DebugFlags::SCENE_METRICS);
+                        if self.frame_config.force_invalidation != force_invalidation ||
+                            self.frame_config.scene_metrics != scene_metrics {
                             self.frame_config.force_invalidation = force_invalidation;
+                            self.frame_config.scene_metrics = scene_metrics;
                             self.update_frame_builder_config();
                         }
 
@@ -1123,10 +1539,12 @@ impl RenderBackend {
                 self.notifier.wake_up(true);
             }
             ApiMsg::UpdateDocuments(transaction_msgs) => {
+                let count = transaction_msgs.len();
                 self.prepare_transactions(
                     transaction_msgs,
                     frame_counter,
                 );
+                self.transactions_applied(count);
             }
             ApiMsg::SceneBuilderResult(msg) => {
                 return self.process_scene_builder_result(msg, frame_counter);
@@ -1136,6 +1554,24 @@ impl RenderBackend {
         RenderBackendStatus::Continue
     }
 
+    /// Notifies the embedder when all of the work it submitted was done, see
+    /// `RenderNotifier::idle`.
+    fn update_idle_state(&mut self) {
+        let idle = self.backpressure.pending_transactions() == 0 &&
+            self.documents.values().all(|doc| doc.is_idle());
+        if self.backpressure.set_idle(idle) {
+            self.notifier.idle();
+        }
+    }
+
+    /// Records that `count` of the transactions sent to the render backend
+    /// were applied, see `Backpressure`.
+    fn transactions_applied(&self, count: usize) {
+        if self.backpressure.transactions_applied(count) {
+            self.notifier.ready_for_more_transactions();
+        }
+    }
+
     fn process_scene_builder_result(
         &mut self,
         msg: SceneBuilderResult,
@@ -1145,12 +1581,14 @@ impl RenderBackend {
 
         match msg {
             SceneBuilderResult::Transactions(txns, result_tx) => {
+                let count = txns.len();
                 self.process_transaction(
                     txns,
                     result_tx,
                     frame_counter,
                 );
                 self.bookkeep_after_frames();
+                self.transactions_applied(count);
             },
             #[cfg(feature = "capture")]
             SceneBuilderResult::CapturedTransactions(txns, capture_config, result_tx) => {
@@ -1164,6 +1602,7 @@ impl RenderBackend {
                     self.capture_config = Some(capture_config);
                 }
 
+                let count = txns.len();
                 let built_frame = self.process_transaction(
                     txns,
                     result_tx,
@@ -1175,6 +1614,7 @@ impl RenderBackend {
                 }
 
                 self.bookkeep_after_frames();
+                self.transactions_applied(count);
             },
             #[cfg(feature = "capture")]
             SceneBuilderResult::StopCaptureSequence => {
@@ -1201,6 +1641,19 @@ impl RenderBackend {
                 }
                 request.sender.send(glyph_indices).unwrap();
             }
+            SceneBuilderResult::GetGlyphVariantIndices(request) => {
+                let font_key = self.resource_cache.map_font_key(request.key);
+                let glyph_indices = request.sequences
+                    .iter()
+                    .map(|&(ch, selector)| self.resource_cache.get_glyph_variant_index(font_key, ch, selector))
+                    .collect();
+                request.sender.send(glyph_indices).unwrap();
+            }
+            SceneBuilderResult::GetGlyphOutline(request) => {
+                let font_key = self.resource_cache.map_font_key(request.key);
+                let outline = self.resource_cache.get_glyph_outline(font_key, request.glyph_index);
+                request.sender.send(outline).unwrap();
+            }
             SceneBuilderResult::FlushComplete(tx) => {
                 tx.send(()).ok();
             }
@@ -1209,14 +1662,36 @@ impl RenderBackend {
             }
             SceneBuilderResult::ClearNamespace(id) => {
                 self.resource_cache.clear_namespace(id);
-                self.documents.retain(|doc_id, _doc| doc_id.namespace_id != id);
+                let resource_cache = &mut self.resource_cache;
+                self.documents.retain(|&doc_id, _doc| {
+                    if doc_id.namespace_id != id {
+                        return true;
+                    }
+                    resource_cache.delete_document(doc_id);
+                    false
+                });
             }
             SceneBuilderResult::DeleteDocument(document_id) => {
                 self.documents.remove(&document_id);
+                self.resource_cache.delete_document(document_id);
             }
             SceneBuilderResult::SetParameter(param) => {
-                if let Parameter::Bool(BoolParameter::Multithreading, enabled) = param {
-                    self.resource_cache.enable_multithreading(enabled);
+                match param {
+                    Parameter::Bool(BoolParameter::Multithreading, enabled) => {
+                        self.resource_cache.enable_multithreading(enabled);
+                    }
+                    Parameter::Bool(BoolParameter::PowerSaving, enabled) => {
+                        self.power_saving = enabled;
+                    }
+                    Parameter::Int(IntParameter::TextContrast, _) => {
+                        // Text drawn with the previous contrast is cached in
+                        // the picture cache tiles.
+                        for doc in self.documents.values_mut() {
+                            doc.invalidate_all_tiles = true;
+                            doc.frame_is_valid = false;
+                        }
+                    }
+                    _ => {}
                 }
                 let _ = self.result_tx.send(ResultMsg::SetParameter(param));
             }
@@ -1268,7 +1743,12 @@ impl RenderBackend {
                 txn.profile.end_time(profiler::API_SEND_TIME);
             }
 
-            self.documents.get_mut(&txn.document_id).unwrap().profile.merge(&mut txn.profile);
+            let doc = self.documents.get_mut(&txn.document_id).unwrap();
+            doc.profile.merge(&mut txn.profile);
+            doc.transaction_labels.extend(txn.label.take());
+            if let Some(request_time) = txn.generate_frame.request_time() {
+                doc.note_frame_request(request_time);
+            }
 
             built_frame |= self.update_document(
                 txn.document_id,
@@ -1291,6 +1771,56 @@ impl RenderBackend {
         self.bookkeep_after_frames();
     }
 
+    /// Builds the frames that were deferred by `Transaction::set_max_frame_rate`
+    /// and are now due.
+    fn build_deferred_frames(&mut self, frame_counter: &mut u32) {
+        let now = precise_time_ns();
+        let due: Vec<(DocumentId, RenderReasons)> = self.documents
+            .iter_mut()
+            .filter(|(_, doc)| doc.deferred_frame_deadline().map_or(false, |deadline| deadline <= now))
+            .filter_map(|(&id, doc)| {
+                let mut reasons = doc.deferred_frame.take();
+                if doc.next_timed_update.map_or(false, |update| update <= now) {
+                    doc.next_timed_update = None;
+                    doc.frame_is_valid = false;
+                    reasons = Some(reasons.unwrap_or(RenderReasons::empty()) | RenderReasons::ANIMATED_PROPERTY);
+                }
+                reasons.map(|reasons| (id, reasons))
+            })
+            .collect();
+
+        if due.is_empty() {
+            return;
+        }
+
+        self.prepare_for_frames();
+        self.maybe_force_nop_documents(
+            frame_counter,
+            |document_id| due.iter().any(|&(id, _)| id == document_id));
+
+        let mut built_frame = false;
+        for (document_id, render_reasons) in due {
+            built_frame |= self.update_document(
+                document_id,
+                Vec::new(),
+                Vec::new(),
+                Vec::new(),
+                true,
+                render_reasons,
+                None,
+                false,
+                frame_counter,
+                false,
+                None,
+            );
+        }
+        if built_frame {
+            #[cfg(feature = "capture")]
+            self.save_capture_sequence();
+        }
+        self.bookkeep_after_frames();
+    }
+
     /// In certain cases, resources shared by multiple documents have to run
     /// maintenance operations, like cleaning up unused cache items. In those
     /// cases, we are forced to build frames for all documents, however we
@@ -1346,6 +1876,14 @@ impl RenderBackend {
     ) -> bool {
         let requested_frame = render_frame;
 
+        // Frames requested only to update animated properties can be
+        // throttled by the power-saving mode.
+        let animation_only = self.power_saving &&
+            !has_built_scene &&
+            resource_updates.is_empty() &&
+            !render_reasons.is_empty() &&
+            (RenderReasons::ANIMATED_PROPERTY | RenderReasons::VSYNC).contains(render_reasons);
+
         let requires_frame_build = self.requires_frame_build();
         let doc = self.documents.get_mut(&document_id).unwrap();
 
@@ -1375,17 +1913,42 @@ impl RenderBackend {
                 doc.frame_is_valid = false;
             }
         }
+        let has_resource_updates = !resource_updates.is_empty();
 
         self.resource_cache.post_scene_building_update(
             resource_updates,
             &mut doc.profile,
         );
 
+        let resource_cache = &self.resource_cache;
+        drain_filter(
+            &mut notifications,
+            |n| { n.when() == Checkpoint::FontsLoaded },
+            |n| { resource_cache.notify_fonts_loaded(n); },
+        );
+
+        // Fonts that aren't valid are rejected when they are added, report
+        // them right away rather than with the next frame.
+        let resource_errors = self.resource_cache.take_resource_errors(document_id.namespace_id);
+        if !resource_errors.is_empty() {
+            self.notifier.resource_errors(document_id, resource_errors);
+        }
+
         if doc.dynamic_properties.flush_pending_updates() {
             doc.frame_is_valid = false;
             doc.hit_tester_is_valid = false;
         }
 
+        // If the frame was still valid before the scroll offsets changed, and
+        // no resource changed, the next frame can take the scroll-only path.
+        // This is only done for a frame built right away, since the frame may
+        // be invalidated by something else before a deferred frame is built.
+        let scroll_only = doc.scroll_offsets_changed && doc.frame_is_valid && !has_resource_updates;
+        if doc.scroll_offsets_changed {
+            doc.frame_is_valid = false;
+            doc.scroll_offsets_changed = false;
+        }
+
         if !doc.can_render() {
             // TODO: this happens if we are building the first scene asynchronously and
             // scroll at the same time. we should keep track of the fact that we skipped
@@ -1393,6 +1956,34 @@ impl RenderBackend {
             render_frame = false;
         }
 
+        if render_frame {
+            let now = precise_time_ns();
+            let too_soon = doc.min_frame_interval
+                .map_or(false, |interval| now < doc.last_frame_time + interval);
+            // In power-saving mode, every other animation frame is skipped.
+            let skip_animation_frame = animation_only && doc.skipped_animation_frame.is_none();
+            if too_soon || skip_animation_frame {
+                if !too_soon {
+                    // If the animation stops, the skipped frame is built at
+                    // about the time the next one would have been requested.
+                    let interval = now.saturating_sub(doc.last_frame_request_time)
+                        .min(MAX_SKIPPED_ANIMATION_FRAME_DELAY_NS);
+                    doc.skipped_animation_frame = Some(now + interval);
+                    doc.profile.inc(profiler::POWER_SAVING_SKIPPED_FRAMES);
+                }
+                // The frame will be built by build_deferred_frames once the
+                // interval elapsed.
+                let reasons = doc.deferred_frame.unwrap_or(RenderReasons::empty());
+                doc.deferred_frame = Some(reasons | render_reasons);
+                render_frame = false;
+            } else {
+                doc.last_frame_time = now;
+                doc.deferred_frame = None;
+                doc.skipped_animation_frame = None;
+            }
+            doc.last_frame_request_time = now;
+        }
+
         // Avoid re-building the frame if the current built frame is still valid.
         // However, if the resource_cache requires a frame build, _always_ do that, unless
         // doc.can_render() is false, as in that case a frame build can't happen anyway.
@@ -1401,6 +1992,11 @@ impl RenderBackend {
         let build_frame = (render_frame && !doc.frame_is_valid && doc.has_pixels()) ||
             (requires_frame_build && doc.can_render());
 
+        // A request that needs no new frame isn't waiting for one.
+        if !build_frame && doc.deferred_frame.is_none() {
+            doc.pending_frame_request = None;
+        }
+
         // Request composite is true when we want to composite frame even when
         // there is no frame update. This happens when video frame is updated under
         // external image with NativeTexture or when platform requested to composite frame.
@@ -1426,6 +2022,7 @@ impl RenderBackend {
 
                 let frame_stats = doc.frame_stats.take();
 
+                doc.scroll_only_frame = scroll_only;
                 let rendered_document = doc.build_frame(
                     &mut self.resource_cache,
                     &mut self.gpu_cache,
@@ -1443,6 +2040,15 @@ impl RenderBackend {
 
                 Telemetry::stop_and_accumulate_framebuild_time(timer_id);
 
+                let missing_glyphs = self.resource_cache.take_missing_glyphs(document_id.namespace_id);
+                if !missing_glyphs.is_empty() {
+                    self.notifier.missing_glyphs(document_id, missing_glyphs);
+                }
+                let resource_errors = self.resource_cache.take_resource_errors(document_id.namespace_id);
+                if !resource_errors.is_empty() {
+                    self.notifier.resource_errors(document_id, resource_errors);
+                }
+
                 let pending_update = self.resource_cache.pending_updates();
                 (pending_update, rendered_document)
             };
@@ -1481,6 +2087,11 @@ impl RenderBackend {
                         config.serialize_for_frame(&rendered_document.frame, file_name);
                     }
 
+                    if !rendered_document.transaction_labels.is_empty() {
+                        let labels_name = format!("transaction-labels-{}-{}", document_id.namespace_id.0, document_id.id);
+                        config.serialize_for_frame(&rendered_document.transaction_labels, labels_name);
+                    }
+
                     let data_stores_name = format!("data-stores-{}-{}", document_id.namespace_id.0, document_id.id);
                     config.serialize_for_frame(&doc.data_stores, data_stores_name);
 
@@ -1498,6 +2109,9 @@ impl RenderBackend {
 
             // Publish the frame
             self.frame_publish_id.advance();
+            if self.backpressure.frame_published() {
+                self.notifier.ready_for_more_transactions();
+            }
             let msg = ResultMsg::PublishDocument(
                 self.frame_publish_id,
                 document_id,
@@ -1534,6 +2148,11 @@ impl RenderBackend {
             } else if render_frame {
                 doc.rendered_frame_is_valid = true;
             }
+            // Neither building nor compositing a frame, unless the request
+            // was deferred.
+            if !build_frame && !render_frame && doc.deferred_frame.is_none() {
+                doc.profile.inc(profiler::NO_OP_FRAME_REQUESTS);
+            }
             self.notifier.new_frame_ready(document_id, scroll, render_frame, self.frame_publish_id);
         }
 
@@ -1576,6 +2195,41 @@ impl RenderBackend {
         );
     }
 
+    fn report_document_memory(&mut self, tx: Sender<Vec<(DocumentId, MemoryReport)>>) {
+        let ops = self.size_of_ops.as_mut().unwrap();
+        let op = ops.size_of_op;
+        let tile_size = self.resource_cache.picture_textures.default_tile_size();
+        let tile_bytes = (tile_size.area() * ImageFormat::RGBA8.bytes_per_pixel()) as usize;
+
+        let mut reports = Vec::with_capacity(self.documents.len());
+        for (id, doc) in &self.documents {
+            let mut report = self.resource_cache.report_namespace_memory(op, id.namespace_id);
+            report.clip_stores += doc.scene.clip_store.size_of(ops);
+            report.hit_testers += match &doc.hit_tester {
+                Some(hit_tester) => hit_tester.size_of(ops),
+                None => 0,
+            };
+            doc.data_stores.report_memory(ops, &mut report);
+
+            // Picture cache tiles are allocated from a shared pool, so estimate
+            // the share of this document from the tiles it last composited.
+            let texture_tiles = doc.prev_composite_descriptor.surfaces
+                .iter()
+                .flat_map(|surface| surface.tile_descriptors.iter())
+                .filter(|tile| tile.surface_kind == TileSurfaceKind::Texture)
+                .count();
+            report.picture_tile_textures += texture_tiles * tile_bytes;
+
+            reports.push((*id, report));
+        }
+
+        // As with `report_memory`, the scene builder adds the interned data and
+        // display lists before replying.
+        self.send_backend_message(
+            SceneBuilderRequest::ReportDocumentMemory(reports, tx)
+        );
+    }
+
     #[cfg(feature = "capture")]
     fn save_capture_sequence(&mut self) {
         if let Some(ref mut config) = self.capture_config {
@@ -1877,6 +2531,8 @@ impl RenderBackend {
                         frame_is_valid: false,
                         hit_tester_is_valid: false,
                         rendered_frame_is_valid: false,
+                        scroll_offsets_changed: false,
+                        scroll_only_frame: false,
                         has_built_scene: false,
                         data_stores,
                         scratch: ScratchBuffer::default(),
@@ -1887,6 +2543,21 @@ impl RenderBackend {
                         profile: TransactionProfile::new(),
                         rg_builder: RenderTaskGraphBuilder::new(),
                         frame_stats: None,
+                        transaction_labels: Vec::new(),
+                        epoch_frames: VecDeque::new(),
+                        min_frame_interval: None,
+                        last_frame_time: 0,
+                        deferred_frame: None,
+                        last_frame_request_time: 0,
+                        skipped_animation_frame: None,
+                        color_filter: None,
+                        post_process_params: PostProcessParams::default(),
+                        subpixel_text_policy: SubpixelTextPolicy::Auto,
+                        invalidate_all_tiles: false,
+                        caret_blink_start: 0,
+                        scrollbar_activity: FastHashMap::default(),
+                        next_timed_update: None,
+                        pending_frame_request: None,
                     };
                     entry.insert(doc);
                 }
@@ -1902,6 +2573,9 @@ impl RenderBackend {
                     self.result_tx.send(msg_update).unwrap();
 
                     self.frame_publish_id.advance();
+                    if self.backpressure.frame_published() {
+                        self.notifier.ready_for_more_transactions();
+                    }
                     let msg_publish = ResultMsg::PublishDocument(
                         self.frame_publish_id,
                         id,
@@ -1911,6 +2585,8 @@ impl RenderBackend {
                             profile: TransactionProfile::new(),
                             render_reasons: RenderReasons::empty(),
                             frame_stats: None,
+                            transaction_labels: Vec::new(),
+                            frame_request: None,
                         },
                         self.resource_cache.pending_updates(),
                     );
@@ -4,7 +4,7 @@
 
 
 use api::{units::*, PremultipliedColorF, ClipMode};
-use api::{ColorF, ImageFormat, LineOrientation, BorderStyle};
+use api::{ColorF, ImageFormat, LineOrientation, BorderStyle, PathKey};
 use crate::batch::{AlphaBatchBuilder, AlphaBatchContainer, BatchTextures, add_quad_to_batch};
 use crate::batch::{ClipBatcher, BatchBuilder, INVALID_SEGMENT_INDEX, ClipMaskInstanceList};
 use crate::command_buffer::{CommandBufferList, QuadFlags};
@@ -331,6 +331,7 @@ impl RenderTarget for ColorRenderTarget {
                             segments,
                         );
                     });
+                    gpu_cache.set_validation_scope(None);
 
                     let alpha_batch_builder = batch_builder.finalize();
 
@@ -442,7 +443,8 @@ impl RenderTarget for ColorRenderTarget {
             RenderTaskKind::ConicGradient(..) |
             RenderTaskKind::TileComposite(..) |
             RenderTaskKind::Empty(..) |
-            RenderTaskKind::LineDecoration(..) => {
+            RenderTaskKind::LineDecoration(..) |
+            RenderTaskKind::Path(..) => {
                 panic!("Should not be added to color target!");
             }
             RenderTaskKind::Readback(..) => {}
@@ -546,6 +548,7 @@ impl RenderTarget for AlphaRenderTarget {
             RenderTaskKind::Blit(..) |
             RenderTaskKind::Border(..) |
             RenderTaskKind::LineDecoration(..) |
+            RenderTaskKind::Path(..) |
             RenderTaskKind::FastLinearGradient(..) |
             RenderTaskKind::LinearGradient(..) |
             RenderTaskKind::RadialGradient(..) |
@@ -683,6 +686,7 @@ pub struct TextureCacheRenderTarget {
     pub border_segments_solid: Vec<BorderInstance>,
     pub clears: Vec<DeviceIntRect>,
     pub line_decorations: Vec<LineDecorationJob>,
+    pub paths: Vec<PathJob>,
     pub fast_linear_gradients: Vec<FastLinearGradientInstance>,
     pub linear_gradients: Vec<LinearGradientInstance>,
     pub radial_gradients: Vec<RadialGradientInstance>,
@@ -699,6 +703,7 @@ impl TextureCacheRenderTarget {
             border_segments_solid: vec![],
             clears: vec![],
             line_decorations: vec![],
+            paths: vec![],
             fast_linear_gradients: vec![],
             linear_gradients: vec![],
             radial_gradients: vec![],
@@ -732,6 +737,15 @@ impl TextureCacheRenderTarget {
                     wavy_line_thickness: info.wavy_line_thickness,
                 });
             }
+            RenderTaskKind::Path(ref info) => {
+                self.clears.push(target_rect);
+
+                self.paths.push(PathJob {
+                    key: info.key,
+                    task_rect: target_rect,
+                    local_size: info.local_size,
+                });
+            }
             RenderTaskKind::HorizontalBlur(..) => {
                 add_blur_instances(
                     &mut self.horizontal_blurs,
@@ -956,6 +970,15 @@ pub struct LineDecorationJob {
     pub axis_select: f32,
 }
 
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+#[derive(Clone, Debug)]
+pub struct PathJob {
+    pub key: PathKey,
+    pub task_rect: DeviceIntRect,
+    pub local_size: LayoutSize,
+}
+
 fn build_mask_tasks(
     info: &MaskSubPass,
     render_task_address: RenderTaskAddress,
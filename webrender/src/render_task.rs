@@ -3,7 +3,7 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use api::{CompositeOperator, FilterPrimitive, FilterPrimitiveInput, FilterPrimitiveKind};
-use api::{LineStyle, LineOrientation, ClipMode, MixBlendMode, ColorF, ColorSpace};
+use api::{LineStyle, LineOrientation, PathKey, ClipMode, MixBlendMode, ColorF, ColorSpace};
 use api::MAX_RENDER_TASK_SIZE;
 use api::units::*;
 use crate::clip::{ClipDataStore, ClipItemKind, ClipStore, ClipNodeRange};
@@ -306,6 +306,14 @@ pub struct LineDecorationTask {
     pub local_size: LayoutSize,
 }
 
+#[derive(Debug)]
+#[cfg_attr(feature = "capture", derive(Serialize))]
+#[cfg_attr(feature = "replay", derive(Deserialize))]
+pub struct PathTask {
+    pub key: PathKey,
+    pub local_size: LayoutSize,
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "capture", derive(Serialize))]
 #[cfg_attr(feature = "replay", derive(Deserialize))]
@@ -364,6 +372,7 @@ pub enum RenderTaskKind {
     Blit(BlitTask),
     Border(BorderTask),
     LineDecoration(LineDecorationTask),
+    Path(PathTask),
     FastLinearGradient(FastLinearGradientTask),
     LinearGradient(LinearGradientTask),
     RadialGradient(RadialGradientTask),
@@ -415,6 +424,7 @@ impl RenderTaskKind {
             RenderTaskKind::Blit(..) => "Blit",
             RenderTaskKind::Border(..) => "Border",
             RenderTaskKind::LineDecoration(..) => "LineDecoration",
+            RenderTaskKind::Path(..) => "Path",
             RenderTaskKind::FastLinearGradient(..) => "FastLinearGradient",
             RenderTaskKind::LinearGradient(..) => "LinearGradient",
             RenderTaskKind::RadialGradient(..) => "RadialGradient",
@@ -432,6 +442,7 @@ impl RenderTaskKind {
         match *self {
             RenderTaskKind::Image(..) |
             RenderTaskKind::LineDecoration(..) |
+            RenderTaskKind::Path(..) |
             RenderTaskKind::Readback(..) |
             RenderTaskKind::Border(..) |
             RenderTaskKind::FastLinearGradient(..) |
@@ -565,6 +576,16 @@ impl RenderTaskKind {
         })
     }
 
+    pub fn new_path(
+        key: PathKey,
+        local_size: LayoutSize,
+    ) -> Self {
+        RenderTaskKind::Path(PathTask {
+            key,
+            local_size,
+        })
+    }
+
     pub fn new_border_segment(
         instances: Vec<BorderInstance>,
     ) -> Self {
@@ -768,6 +789,7 @@ impl RenderTaskKind {
             RenderTaskKind::Scaling(..) |
             RenderTaskKind::Border(..) |
             RenderTaskKind::LineDecoration(..) |
+            RenderTaskKind::Path(..) |
             RenderTaskKind::FastLinearGradient(..) |
             RenderTaskKind::LinearGradient(..) |
             RenderTaskKind::RadialGradient(..) |
@@ -18,6 +18,7 @@ use crate::prim_store::gradient::{
     ConicGradientCacheKey,
 };
 use crate::prim_store::line_dec::LineDecorationCacheKey;
+use crate::prim_store::PathCacheKey;
 use crate::resource_cache::CacheItem;
 use std::{mem, usize, f32, i32};
 use crate::surface::SurfaceBuilder;
@@ -49,6 +50,7 @@ pub enum RenderTaskCacheKeyKind {
     Image(ImageCacheKey),
     BorderSegment(BorderSegmentCacheKey),
     LineDecoration(LineDecorationCacheKey),
+    Path(PathCacheKey),
     FastLinearGradient(FastLinearGradientCacheKey),
     LinearGradient(LinearGradientCacheKey),
     RadialGradient(RadialGradientCacheKey),
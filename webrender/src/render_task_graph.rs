@@ -1156,6 +1156,29 @@ fn fg_test_4() {
     ]);
 }
 
+#[test]
+fn fg_test_4_larger_target() {
+    // Test that a pooled render target which is larger than needed, but not
+    // too much, is reused instead of allocating a new one.
+
+    let mut gb = RenderTaskGraphBuilder::new();
+
+    let pc_root = gb.add().init(task_location(pc_target(0, 0, 0)));
+
+    let child_pic_0 = gb.add().init(task_dynamic(2500));
+    let child_pic_1 = gb.add().init(task_dynamic(128));
+    let child_pic_2 = gb.add().init(task_dynamic(3000));
+
+    gb.add_dependency(pc_root, child_pic_0);
+    gb.add_dependency(child_pic_0, child_pic_1);
+    gb.add_dependency(child_pic_1, child_pic_2);
+
+    gb.test_expect(4, 3, &[
+        (3072, 3072, ImageFormat::RGBA8),
+        (2048, 2048, ImageFormat::RGBA8),
+    ]);
+}
+
 #[test]
 fn fg_test_5() {
     // Test that a task that is used as an input by direct parent and also
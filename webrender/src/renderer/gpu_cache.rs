@@ -425,9 +425,9 @@ impl super::Renderer {
 
         // For an artificial stress test of GPU cache resizing,
         // always pass an extra update list with at least one block in it.
-        let gpu_cache_height = self.gpu_cache_texture.get_height();
+        let gpu_cache_height = self.caches.gpu_cache_texture.get_height();
         if gpu_cache_height != 0 && GPU_CACHE_RESIZE_TEST {
-            self.pending_gpu_cache_updates.push(GpuCacheUpdateList {
+            self.caches.pending_gpu_cache_updates.push(GpuCacheUpdateList {
                 frame_id: FrameId::INVALID,
                 clear: false,
                 height: gpu_cache_height,
@@ -438,37 +438,38 @@ impl super::Renderer {
         }
 
         let (updated_blocks, max_requested_height) = self
+            .caches
             .pending_gpu_cache_updates
             .iter()
             .fold((0, gpu_cache_height), |(count, height), list| {
                 (count + list.blocks.len(), cmp::max(height, list.height))
             });
 
-        if max_requested_height > self.get_max_texture_size() && !self.gpu_cache_overflow {
-            self.gpu_cache_overflow = true;
+        if max_requested_height > self.get_max_texture_size() && !self.caches.gpu_cache_overflow {
+            self.caches.gpu_cache_overflow = true;
             self.renderer_errors.push(super::RendererError::MaxTextureSize);
         }
 
         // Note: if we decide to switch to scatter-style GPU cache update
         // permanently, we can have this code nicer with `BufferUploader` kind
         // of helper, similarly to how `TextureUploader` API is used.
-        self.gpu_cache_texture.prepare_for_updates(
+        self.caches.gpu_cache_texture.prepare_for_updates(
             &mut self.device,
             updated_blocks,
             max_requested_height,
         );
 
-        for update_list in self.pending_gpu_cache_updates.drain(..) {
+        for update_list in self.caches.pending_gpu_cache_updates.drain(..) {
             assert!(update_list.height <= max_requested_height);
-            if update_list.frame_id > self.gpu_cache_frame_id {
-                self.gpu_cache_frame_id = update_list.frame_id
+            if update_list.frame_id > self.caches.gpu_cache_frame_id {
+                self.caches.gpu_cache_frame_id = update_list.frame_id
             }
-            self.gpu_cache_texture
+            self.caches.gpu_cache_texture
                 .update(&mut self.device, &update_list);
         }
 
         self.profile.start_time(profiler::GPU_CACHE_UPLOAD_TIME);
-        let updated_rows = self.gpu_cache_texture.flush(
+        let updated_rows = self.caches.gpu_cache_texture.flush(
             &mut self.device,
             &mut self.texture_upload_pbo_pool
         );
@@ -482,17 +483,17 @@ impl super::Renderer {
         &mut self,
         deferred_resolves: &[DeferredResolve],
     ) -> Result<(), super::RendererError> {
-        if self.pending_gpu_cache_clear {
+        if self.caches.pending_gpu_cache_clear {
             let use_scatter =
-                matches!(self.gpu_cache_texture.bus, GpuCacheBus::Scatter { .. });
+                matches!(self.caches.gpu_cache_texture.bus, GpuCacheBus::Scatter { .. });
             let new_cache = GpuCacheTexture::new(&mut self.device, use_scatter)?;
-            let old_cache = mem::replace(&mut self.gpu_cache_texture, new_cache);
+            let old_cache = mem::replace(&mut self.caches.gpu_cache_texture, new_cache);
             old_cache.deinit(&mut self.device);
-            self.pending_gpu_cache_clear = false;
+            self.caches.pending_gpu_cache_clear = false;
         }
 
         let deferred_update_list = self.update_deferred_resolves(deferred_resolves);
-        self.pending_gpu_cache_updates.extend(deferred_update_list);
+        self.caches.pending_gpu_cache_updates.extend(deferred_update_list);
 
         self.update_gpu_cache();
 
@@ -500,7 +501,7 @@ impl super::Renderer {
         // so we need to bind it here.
         self.device.bind_texture(
             super::TextureSampler::GpuCache,
-            self.gpu_cache_texture.texture.as_ref().unwrap(),
+            self.caches.gpu_cache_texture.texture.as_ref().unwrap(),
             Swizzle::default(),
         );
 
@@ -508,18 +509,20 @@ impl super::Renderer {
     }
 
     pub fn read_gpu_cache(&mut self) -> (DeviceIntSize, Vec<u8>) {
-        let texture = self.gpu_cache_texture.texture.as_ref().unwrap();
-        let size = device_size_as_framebuffer_size(texture.get_dimensions());
-        let mut texels = vec![0; (size.width * size.height * 16) as usize];
-        self.device.begin_frame();
-        self.device.bind_read_target(ReadTarget::from_texture(texture));
-        self.device.read_pixels_into(
-            size.into(),
-            api::ImageFormat::RGBAF32,
-            &mut texels,
-        );
-        self.device.reset_read_target();
-        self.device.end_frame();
-        (texture.get_dimensions(), texels)
+        self.with_shared_caches(|renderer| {
+            let texture = renderer.caches.gpu_cache_texture.texture.as_ref().unwrap();
+            let size = device_size_as_framebuffer_size(texture.get_dimensions());
+            let mut texels = vec![0; (size.width * size.height * 16) as usize];
+            renderer.device.begin_frame();
+            renderer.device.bind_read_target(ReadTarget::from_texture(texture));
+            renderer.device.read_pixels_into(
+                size.into(),
+                api::ImageFormat::RGBAF32,
+                &mut texels,
+            );
+            renderer.device.reset_read_target();
+            renderer.device.end_frame();
+            (texture.get_dimensions(), texels)
+        })
     }
 }
@@ -3,22 +3,27 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use api::{BlobImageHandler, ColorF, IdNamespace, DocumentId, CrashAnnotator};
-use api::{VoidPtrToSizeFn, FontRenderMode, ImageFormat};
-use api::{RenderNotifier, ImageBufferKind};
+use api::{VoidPtrToSizeFn, FontRenderMode, ImageFormat, SubpixelTextPolicy};
+use api::{RenderNotifier, ImageBufferKind, Parameter, IntParameter};
 use api::units::*;
-use api::channel::unbounded_channel;
+use api::channel::{Receiver, unbounded_channel};
 pub use api::DebugFlags;
 
-use crate::render_api::{RenderApiSender, FrameMsg};
+use crate::recording::SharedRecorder;
+use crate::render_api::{Backpressure, RenderApiSender, FrameMsg};
 use crate::composite::{CompositorKind, CompositorConfig};
 use crate::device::{
-    UploadMethod, UploadPBOPool, VertexUsageHint, Device, ProgramCache, TextureFilter
+    UploadMethod, UploadPBOPool, VertexUsageHint, Device, DeviceShareGroup, ProgramCache,
+    TextureFilter, TextureFormatPair,
 };
 use crate::frame_builder::FrameBuilderConfig;
 use crate::glyph_cache::GlyphCache;
-use glyph_rasterizer::{GlyphRasterizer, SharedFontResources};
+use glyph_rasterizer::{GlyphBackend, GlyphRasterizer, SharedFontResources, DEFAULT_GLYPH_IMAGE_CACHE_BUDGET};
+use glyph_rasterizer::profiler::GlyphTelemetrySink;
 use crate::gpu_types::PrimitiveInstanceData;
-use crate::internal_types::{FastHashMap, FastHashSet, FrameId};
+use crate::internal_types::{FastHashSet, ResultMsg, SwizzleSettings};
+#[cfg(feature = "replay")]
+use crate::internal_types::FastHashMap;
 use crate::picture;
 use crate::profiler::{self, Profiler, TransactionProfile};
 use crate::device::query::{GpuProfiler, GpuDebugMethod};
@@ -29,10 +34,11 @@ use crate::texture_cache::{TextureCache, TextureCacheConfig};
 use crate::picture_textures::PictureTextures;
 use crate::renderer::{
     debug, gpu_cache, vertex, gl,
-    Renderer, DebugOverlayState, BufferDamageTracker, PipelineInfo, TextureResolver,
+    Renderer, RendererCaches, DebugOverlayState, BufferDamageTracker, PipelineInfo, TextureResolver,
     RendererError, ShaderPrecacheFlags, VERTEX_DATA_TEXTURE_COUNT,
     upload::UploadTexturePool,
-    shade::{Shaders, SharedShaders},
+    video::VideoFrameQueues,
+    shade::{ShaderOverrideWatcher, Shaders, SharedShaders},
 };
 
 use std::{
@@ -44,6 +50,7 @@ use std::{
     sync::{Arc, atomic::{AtomicBool, Ordering}},
     num::NonZeroUsize,
     path::PathBuf,
+    time::Duration,
 };
 
 use tracy_rs::register_thread_with_profiler;
@@ -111,8 +118,226 @@ pub trait AsyncPropertySampler {
     fn deregister(&self);
 }
 
+/// The threads spawned by WebRender.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum WebRenderThread {
+    RenderBackend,
+    SceneBuilder,
+    LowPrioritySceneBuilder,
+    /// A thread of the worker pool, with its index in the pool.
+    Worker(usize),
+    /// A thread of the pool dedicated to rasterizing glyphs, see
+    /// `WebRenderOptions::glyph_raster_threads`, with its index in the pool.
+    GlyphWorker(usize),
+}
+
+/// Platform-independent scheduling priority of a thread.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ThreadPriority {
+    Low,
+    Normal,
+    High,
+}
+
+/// Scheduling preferences for a WebRender thread.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ThreadConfig {
+    /// If set, the priority the thread should run at.
+    pub priority: Option<ThreadPriority>,
+    /// If not empty, the cores the thread should be restricted to.
+    /// On big.LITTLE systems this can keep the critical path on the
+    /// performance cores.
+    pub core_ids: Vec<usize>,
+}
+
+/// Applies `ThreadConfig`s to WebRender's threads.
+///
+/// WebRender does not depend on platform threading APIs, so setting the
+/// priority and affinity of a thread is left to the embedder. The
+/// configurator is called on each thread as it starts, before it does any
+/// work.
+pub trait ThreadConfigurator: Send + Sync {
+    fn configure_current_thread(&self, thread: WebRenderThread, config: &ThreadConfig);
+}
+
+fn configure_current_thread(
+    configurator: &Option<Arc<dyn ThreadConfigurator>>,
+    thread: WebRenderThread,
+    config: &ThreadConfig,
+) {
+    if let Some(configurator) = configurator {
+        configurator.configure_current_thread(thread, config);
+    }
+}
+
+/// The smallest texture size the texture cache can work with.
+const MIN_TEXTURE_SIZE: i32 = 2048;
+
+/// An invalid combination of `WebRenderOptions`, see
+/// `WebRenderOptions::validate`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptionsError {
+    /// `max_internal_texture_size` is below the 2048 pixels the texture
+    /// cache needs.
+    MaxInternalTextureSizeTooSmall(i32),
+    /// `image_tiling_threshold` isn't positive.
+    InvalidImageTilingThreshold(i32),
+    /// `max_shared_surface_size` isn't positive.
+    InvalidMaxSharedSurfaceSize(i32),
+    /// `picture_tile_size` is empty.
+    InvalidPictureTileSize(DeviceIntSize),
+    /// `upload_method` uses pixel buffers, but `upload_pbo_default_size` is 0.
+    EmptyUploadBuffers,
+    /// `text_contrast` is above 100 percent.
+    InvalidTextContrast(u8),
+    /// `gpu_cache_reclaim_threshold` isn't in the (0, 1] range.
+    InvalidGpuCacheReclaimThreshold(f32),
+    /// `namespace_alloc_by_client` is set without a `shared_font_namespace`.
+    MissingSharedFontNamespace,
+    /// `shared_caches` is set with a native compositor, or for Software
+    /// WebRender, whose contexts can't share objects.
+    UnsupportedSharedCaches,
+}
+
+/// An option that the GL context doesn't support, and that WebRender runs
+/// without, see `EffectiveOptions`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptionDowngrade {
+    /// `allow_dual_source_blending`: subpixel text is drawn with grayscale
+    /// anti-aliasing, see `SubpixelTextPolicy`.
+    DualSourceBlending,
+    /// `allow_advanced_blend_equation`: mix blend modes are emulated with
+    /// intermediate surfaces.
+    AdvancedBlendEquation,
+    /// `allow_texture_swizzling`: textures are uploaded in the formats the
+    /// driver expects, which may require converting them.
+    TextureSwizzling,
+    /// `max_internal_texture_size` is above the device's maximum texture size.
+    MaxInternalTextureSize { requested: i32, effective: i32 },
+    /// `upload_method` uses pixel buffers, but the device uploads texels
+    /// directly.
+    PixelBufferUploads,
+    /// `glyph_backend` wasn't built in, and the default backend is used.
+    GlyphBackend { requested: GlyphBackend, effective: GlyphBackend },
+}
+
+/// The configuration WebRender runs with, once `WebRenderOptions` were
+/// checked against the capabilities of the GL context. See
+/// `Renderer::effective_options`.
+#[derive(Clone, Debug)]
+pub struct EffectiveOptions {
+    pub dual_source_blending: bool,
+    pub advanced_blend_equation: bool,
+    pub texture_swizzling: bool,
+    pub enable_clear_scissor: bool,
+    pub max_internal_texture_size: i32,
+    pub image_tiling_threshold: i32,
+    pub upload_method: UploadMethod,
+    pub glyph_backend: GlyphBackend,
+    /// The options that were requested but are turned off.
+    pub downgrades: Vec<OptionDowngrade>,
+}
+
+/// A texture cache, glyph cache and GPU cache shared by several renderers,
+/// see `WebRenderOptions::shared_caches`.
+///
+/// Embedders that need a GL context per window can create their contexts in
+/// the same GL share group, and their renderers with the same `SharedCaches`,
+/// so that fonts and images are rasterized and uploaded once for all windows.
+/// The first renderer created with a `SharedCaches` spawns a render backend as
+/// usual. The renderers created with it later don't spawn one, and their
+/// `RenderApiSender` talks to the render backend of the first renderer, which
+/// is notified with the `RenderNotifier` of the first renderer.
+///
+/// The renderers of a share group render the documents of all the windows, so
+/// each window creates its own document and renders it with
+/// `Renderer::render_document` on its renderer. Calling `Renderer::update` on
+/// any renderer of the group processes the results for all of them, and
+/// `Renderer::flush_pipeline_info` returns the epochs of all the documents.
+/// The renderers of a group must have the same external image handlers, and
+/// run on the same thread.
+#[derive(Clone, Default)]
+pub struct SharedCaches(Rc<RefCell<SharedCachesState>>);
+
+#[derive(Default)]
+struct SharedCachesState {
+    device_group: DeviceShareGroup,
+    /// A sender for the render backend of the group, once the first renderer
+    /// spawned it.
+    sender: Option<RenderApiSender>,
+    glyph_backend: GlyphBackend,
+    /// The caches of the group, when no renderer is updating or rendering.
+    caches: Option<RendererCaches>,
+    renderers: usize,
+}
+
+impl SharedCaches {
+    pub fn new() -> Self {
+        SharedCaches::default()
+    }
+
+    fn is_initialized(&self) -> bool {
+        self.0.borrow().sender.is_some()
+    }
+
+    /// Returns a sender for the render backend of the group, if the first
+    /// renderer spawned it, and the glyph backend it runs with.
+    fn render_api_sender(&self) -> Option<(RenderApiSender, GlyphBackend)> {
+        let state = self.0.borrow();
+        let sender = state.sender.as_ref()?;
+        Some((sender.create_similar(), state.glyph_backend))
+    }
+
+    /// Adds a renderer to the group. The first one hands its caches over to
+    /// the group, and keeps `own_caches` to swap with them.
+    fn add_renderer(
+        &self,
+        renderer: &mut Renderer,
+        own_caches: Option<RendererCaches>,
+        sender: &RenderApiSender,
+    ) {
+        let mut state = self.0.borrow_mut();
+        renderer.device.join_share_group(&state.device_group);
+        if let Some(own_caches) = own_caches {
+            state.caches = Some(mem::replace(&mut renderer.caches, own_caches));
+            state.sender = Some(sender.create_similar());
+            state.glyph_backend = renderer.effective_options.glyph_backend;
+        }
+        state.renderers += 1;
+    }
+
+    /// Removes a renderer from the group, returning the caches of the group if
+    /// it was the last one.
+    pub(super) fn remove_renderer(&self) -> Option<RendererCaches> {
+        let mut state = self.0.borrow_mut();
+        state.renderers -= 1;
+        if state.renderers == 0 {
+            state.sender = None;
+            state.caches.take()
+        } else {
+            None
+        }
+    }
+
+    /// Swaps the caches of the group with the ones of a renderer. The renderer
+    /// swaps them in before it updates or renders, and back out after.
+    pub(super) fn swap(&self, caches: &mut RendererCaches) {
+        let mut state = self.0.borrow_mut();
+        mem::swap(state.caches.as_mut().unwrap(), caches);
+    }
+
+    /// Calls `f` with the caches of the group.
+    pub(super) fn with_caches<R>(&self, f: impl FnOnce(&RendererCaches) -> R) -> R {
+        f(self.0.borrow().caches.as_ref().unwrap())
+    }
+}
+
 pub struct WebRenderOptions {
     pub resource_override_path: Option<PathBuf>,
+    /// Watch `resource_override_path` for modified shader sources, and reload
+    /// the shaders between frames when they change. This is a debugging aid for
+    /// shader development, and has no effect without an override path.
+    pub watch_shader_overrides: bool,
     /// Whether to use shaders that have been optimized at build time.
     pub use_optimized_shaders: bool,
     pub enable_aa: bool,
@@ -139,6 +364,14 @@ pub struct WebRenderOptions {
     pub debug_flags: DebugFlags,
     pub renderer_id: Option<u64>,
     pub scene_builder_hooks: Option<Box<dyn SceneBuilderHooks + Send>>,
+    /// Applies the thread configs below. Without it they are ignored.
+    pub thread_configurator: Option<Arc<dyn ThreadConfigurator>>,
+    pub render_backend_thread_config: ThreadConfig,
+    /// Also used by the low priority scene builder thread.
+    pub scene_builder_thread_config: ThreadConfig,
+    /// Only used if WebRender creates the worker pool, i.e. if `workers` is
+    /// not set.
+    pub worker_thread_config: ThreadConfig,
     pub sampler: Option<Box<dyn AsyncPropertySampler + Send>>,
     pub support_low_priority_transactions: bool,
     pub namespace_alloc_by_client: bool,
@@ -176,6 +409,8 @@ pub struct WebRenderOptions {
     /// performance impact, so only use when debugging specific problems!
     pub panic_on_gl_error: bool,
     pub picture_tile_size: Option<DeviceIntSize>,
+    /// Atlas sizes and allocator tuning. See the `TextureCacheConfig`
+    /// presets.
     pub texture_cache_config: TextureCacheConfig,
     /// If true, we'll use instanced vertex attributes. Each instace is a quad.
     /// If false, we'll duplicate the instance attributes per vertex and issue
@@ -191,9 +426,137 @@ pub struct WebRenderOptions {
     /// make the result look quite close to the high-quality zoom, except for glyphs.
     pub low_quality_pinch_zoom: bool,
     pub max_shared_surface_size: i32,
+    /// If less than this ratio of the GPU cache is in use for
+    /// `gpu_cache_reclaim_delay`, the cache is cleared and rebuilt at a
+    /// smaller size.
+    pub gpu_cache_reclaim_threshold: f32,
+    pub gpu_cache_reclaim_delay: Duration,
+    /// If true, read back the composited output of every frame and return a
+    /// hash of it in `RenderResults::frame_checksum`. Useful for pixel
+    /// regression testing, at the cost of a GPU sync each frame.
+    pub enable_frame_checksum: bool,
+    /// If set, external images are locked with `ExternalImageHandler::try_lock`
+    /// and this timeout, rather than with `ExternalImageHandler::lock`, so that
+    /// a stalled image source can't block the renderer indefinitely.
+    pub external_image_lock_timeout: Option<Duration>,
+    /// Initial percentage (0 to 100) by which glyph edges are darkened, see
+    /// `RenderApi::set_text_contrast`.
+    pub text_contrast: u8,
+    /// The backend that rasterizes glyphs, among the ones WebRender is built
+    /// with. The default is the platform's font library, or the first of
+    /// the swash, fontdue, ab_glyph and tiny-skia backends that is built in.
+    pub glyph_backend: GlyphBackend,
+    /// The number of bytes of rasterized glyph images the glyph backend keeps
+    /// to rasterize them again quickly, for backends that keep them. Shared
+    /// by all the WebRender instances of the process.
+    pub glyph_image_cache_budget: usize,
+    /// The number of threads of a pool dedicated to rasterizing glyphs, so
+    /// that they don't compete with scene building on `workers`. None
+    /// rasterizes glyphs on `workers`, and 0 on the render backend thread,
+    /// as when `enable_multithreading` is false. The threads are configured
+    /// with `worker_thread_config`.
+    pub glyph_raster_threads: Option<usize>,
+    /// The prefix of the names of the threads of the glyph rasterizer pool,
+    /// which are followed by their index.
+    pub glyph_raster_thread_name: String,
+    /// Whether the glyphs of a font are rasterized on several threads, or
+    /// all on one thread. None leaves it to the glyph backend.
+    pub glyph_raster_distribute_across_threads: Option<bool>,
+    /// Receives the statistics of each requested and rasterized glyph, to
+    /// forward them into the embedder's telemetry.
+    pub glyph_telemetry_sink: Option<Arc<dyn GlyphTelemetrySink>>,
+    /// Shares the texture, glyph and GPU caches of this renderer with the
+    /// other renderers created with the same `SharedCaches`, whose GL contexts
+    /// must be in the same share group as this one.
+    pub shared_caches: Option<SharedCaches>,
 }
 
 impl WebRenderOptions {
+    /// Returns a builder starting from the default options.
+    pub fn builder() -> WebRenderOptionsBuilder {
+        WebRenderOptionsBuilder::new(WebRenderOptions::default())
+    }
+
+    /// Checks for combinations of options that WebRender can't be created
+    /// with. `create_webrender_instance` fails with
+    /// `RendererError::InvalidOptions` for these. Options that only the GL
+    /// context can't honor are turned off instead, see `EffectiveOptions`.
+    pub fn validate(&self) -> Result<(), OptionsError> {
+        if let Some(size) = self.max_internal_texture_size {
+            if size < MIN_TEXTURE_SIZE {
+                return Err(OptionsError::MaxInternalTextureSizeTooSmall(size));
+            }
+        }
+        if self.image_tiling_threshold <= 0 {
+            return Err(OptionsError::InvalidImageTilingThreshold(self.image_tiling_threshold));
+        }
+        if self.max_shared_surface_size <= 0 {
+            return Err(OptionsError::InvalidMaxSharedSurfaceSize(self.max_shared_surface_size));
+        }
+        if let Some(size) = self.picture_tile_size {
+            if size.is_empty() {
+                return Err(OptionsError::InvalidPictureTileSize(size));
+            }
+        }
+        if let UploadMethod::PixelBuffer(_) = self.upload_method {
+            if self.upload_pbo_default_size == 0 {
+                return Err(OptionsError::EmptyUploadBuffers);
+            }
+        }
+        if self.text_contrast > 100 {
+            return Err(OptionsError::InvalidTextContrast(self.text_contrast));
+        }
+        if !(self.gpu_cache_reclaim_threshold > 0.0 && self.gpu_cache_reclaim_threshold <= 1.0) {
+            return Err(OptionsError::InvalidGpuCacheReclaimThreshold(self.gpu_cache_reclaim_threshold));
+        }
+        if self.namespace_alloc_by_client && self.shared_font_namespace.is_none() {
+            return Err(OptionsError::MissingSharedFontNamespace);
+        }
+        if self.shared_caches.is_some() {
+            if let CompositorConfig::Native { .. } = self.compositor_config {
+                return Err(OptionsError::UnsupportedSharedCaches);
+            }
+        }
+        Ok(())
+    }
+
+    /// Options for devices with 1-2 GB of memory, such as low end Android
+    /// phones and embedded boards.
+    ///
+    /// Compared to the defaults:
+    /// - Texture cache atlases are smaller (`TextureCacheConfig::LOW_MEMORY`),
+    ///   which can increase the number of draw calls on image or text heavy
+    ///   content.
+    /// - Picture cache tiles are smaller, so partially visible tiles waste
+    ///   less memory, at the cost of more tiles to invalidate and composite.
+    /// - Intermediate surfaces and textures are capped at 2048 pixels, and
+    ///   images are tiled from that size. Content that would need larger
+    ///   surfaces is rendered at a lower resolution.
+    /// - The GPU cache shrinks sooner after utilization drops.
+    /// - Pinch-zoom scales the cached tiles instead of re-rasterizing them,
+    ///   which blurs text until the zoom ends, and dithering is disabled,
+    ///   which can make gradients show banding.
+    /// - Upload buffers are smaller, which can mean more upload batches.
+    /// - Fewer rasterized glyph images are kept, so that text that scrolls
+    ///   back into view is more often rasterized again.
+    pub fn low_memory_profile() -> Self {
+        WebRenderOptions {
+            texture_cache_config: TextureCacheConfig::LOW_MEMORY,
+            picture_tile_size: Some(DeviceIntSize::new(512, 256)),
+            max_shared_surface_size: 1024,
+            max_internal_texture_size: Some(2048),
+            image_tiling_threshold: 2048,
+            gpu_cache_reclaim_threshold: 0.5,
+            gpu_cache_reclaim_delay: Duration::from_secs(1),
+            low_quality_pinch_zoom: true,
+            enable_dithering: false,
+            upload_pbo_default_size: 256 * 256 * 4,
+            batched_upload_threshold: 256 * 256,
+            glyph_image_cache_budget: 8 * 1024 * 1024,
+            .. WebRenderOptions::default()
+        }
+    }
+
     /// Number of batches to look back in history for adding the current
     /// transparent instance into.
     const BATCH_LOOKBACK_COUNT: usize = 10;
@@ -211,6 +574,7 @@ impl Default for WebRenderOptions {
     fn default() -> Self {
         WebRenderOptions {
             resource_override_path: None,
+            watch_shader_overrides: false,
             use_optimized_shaders: false,
             enable_aa: true,
             enable_dithering: false,
@@ -236,6 +600,10 @@ impl Default for WebRenderOptions {
             renderer_id: None,
             cached_programs: None,
             scene_builder_hooks: None,
+            thread_configurator: None,
+            render_backend_thread_config: ThreadConfig::default(),
+            scene_builder_thread_config: ThreadConfig::default(),
+            worker_thread_config: ThreadConfig::default(),
             sampler: None,
             support_low_priority_transactions: false,
             namespace_alloc_by_client: false,
@@ -260,12 +628,143 @@ impl Default for WebRenderOptions {
             reject_software_rasterizer: false,
             low_quality_pinch_zoom: false,
             max_shared_surface_size: 2048,
+            gpu_cache_reclaim_threshold: crate::gpu_cache::DEFAULT_RECLAIM_THRESHOLD,
+            gpu_cache_reclaim_delay: crate::gpu_cache::DEFAULT_RECLAIM_DELAY,
+            enable_frame_checksum: false,
+            external_image_lock_timeout: None,
+            text_contrast: 0,
+            glyph_backend: GlyphBackend::default(),
+            glyph_image_cache_budget: DEFAULT_GLYPH_IMAGE_CACHE_BUDGET,
+            glyph_raster_threads: None,
+            glyph_raster_thread_name: "WRGlyphWorker".to_string(),
+            glyph_raster_distribute_across_threads: None,
+            glyph_telemetry_sink: None,
+            shared_caches: None,
         }
     }
 }
 
+/// Builds `WebRenderOptions`, checking them with `WebRenderOptions::validate`.
+/// Options without a dedicated method can be set with `with`.
+pub struct WebRenderOptionsBuilder {
+    options: WebRenderOptions,
+}
+
+impl WebRenderOptionsBuilder {
+    /// Starts from the given options, such as
+    /// `WebRenderOptions::low_memory_profile()`.
+    pub fn new(options: WebRenderOptions) -> Self {
+        WebRenderOptionsBuilder { options }
+    }
+
+    pub fn clear_color(mut self, color: ColorF) -> Self {
+        self.options.clear_color = color;
+        self
+    }
+
+    pub fn enable_aa(mut self, enable: bool) -> Self {
+        self.options.enable_aa = enable;
+        self
+    }
+
+    pub fn enable_subpixel_aa(mut self, enable: bool) -> Self {
+        self.options.enable_subpixel_aa = enable;
+        self
+    }
+
+    pub fn debug_flags(mut self, flags: DebugFlags) -> Self {
+        self.options.debug_flags = flags;
+        self
+    }
+
+    pub fn precache_flags(mut self, flags: ShaderPrecacheFlags) -> Self {
+        self.options.precache_flags = flags;
+        self
+    }
+
+    pub fn workers(mut self, workers: Arc<ThreadPool>) -> Self {
+        self.options.workers = Some(workers);
+        self
+    }
+
+    pub fn upload_method(mut self, method: UploadMethod) -> Self {
+        self.options.upload_method = method;
+        self
+    }
+
+    pub fn max_internal_texture_size(mut self, size: i32) -> Self {
+        self.options.max_internal_texture_size = Some(size);
+        self
+    }
+
+    pub fn picture_tile_size(mut self, size: DeviceIntSize) -> Self {
+        self.options.picture_tile_size = Some(size);
+        self
+    }
+
+    pub fn texture_cache_config(mut self, config: TextureCacheConfig) -> Self {
+        self.options.texture_cache_config = config;
+        self
+    }
+
+    pub fn allow_dual_source_blending(mut self, allow: bool) -> Self {
+        self.options.allow_dual_source_blending = allow;
+        self
+    }
+
+    pub fn allow_advanced_blend_equation(mut self, allow: bool) -> Self {
+        self.options.allow_advanced_blend_equation = allow;
+        self
+    }
+
+    pub fn allow_texture_swizzling(mut self, allow: bool) -> Self {
+        self.options.allow_texture_swizzling = allow;
+        self
+    }
+
+    pub fn allow_texture_storage_support(mut self, allow: bool) -> Self {
+        self.options.allow_texture_storage_support = allow;
+        self
+    }
+
+    pub fn glyph_backend(mut self, backend: GlyphBackend) -> Self {
+        self.options.glyph_backend = backend;
+        self
+    }
+
+    pub fn glyph_image_cache_budget(mut self, bytes: usize) -> Self {
+        self.options.glyph_image_cache_budget = bytes;
+        self
+    }
+
+    pub fn glyph_raster_threads(mut self, count: usize) -> Self {
+        self.options.glyph_raster_threads = Some(count);
+        self
+    }
+
+    pub fn glyph_telemetry_sink(mut self, sink: Arc<dyn GlyphTelemetrySink>) -> Self {
+        self.options.glyph_telemetry_sink = Some(sink);
+        self
+    }
+
+    /// Sets any other option.
+    pub fn with<F: FnOnce(&mut WebRenderOptions)>(mut self, f: F) -> Self {
+        f(&mut self.options);
+        self
+    }
+
+    pub fn build(self) -> Result<WebRenderOptions, OptionsError> {
+        self.options.validate()?;
+        Ok(self.options)
+    }
+}
+
 /// Initializes WebRender and creates a `Renderer` and `RenderApiSender`.
 ///
+/// Fails with `RendererError::InvalidOptions` if the options don't pass
+/// `WebRenderOptions::validate`. The options that the GL context doesn't
+/// support are turned off, see `Renderer::effective_options`.
+///
 /// # Examples
 /// Initializes a `Renderer` with some reasonable values. For more information see
 /// [`WebRenderOptions`][WebRenderOptions].
@@ -287,6 +786,8 @@ pub fn create_webrender_instance(
     mut options: WebRenderOptions,
     shaders: Option<&SharedShaders>,
 ) -> Result<(Renderer, RenderApiSender), RendererError> {
+    options.validate()?;
+
     if !wr_has_been_initialized() {
         // If the profiler feature is enabled, try to load the profiler shared library
         // if the path was provided.
@@ -303,8 +804,7 @@ pub fn create_webrender_instance(
 
     HAS_BEEN_INITIALIZED.store(true, Ordering::SeqCst);
 
-    let (api_tx, api_rx) = unbounded_channel();
-    let (result_tx, result_rx) = unbounded_channel();
+    let shared_caches = options.shared_caches.take();
     let gl_type = gl.get_type();
 
     let mut device = Device::new(
@@ -321,6 +821,11 @@ pub fn create_webrender_instance(
         options.surface_origin_is_top_left,
         options.panic_on_gl_error,
     );
+    device.set_parameter(&Parameter::Int(IntParameter::TextContrast, options.text_contrast as i32));
+
+    if shared_caches.is_some() && device.get_capabilities().renderer_name.starts_with("Software WebRender") {
+        return Err(OptionsError::UnsupportedSharedCaches.into());
+    }
 
     let color_cache_formats = device.preferred_color_formats();
     let swizzle_settings = device.swizzle_settings();
@@ -337,8 +842,6 @@ pub fn create_webrender_instance(
         .enable_clear_scissor
         .unwrap_or(device.get_capabilities().prefers_clear_scissor);
 
-    // 2048 is the minimum that the texture cache can work with.
-    const MIN_TEXTURE_SIZE: i32 = 2048;
     let mut max_internal_texture_size = device.max_texture_size();
     if max_internal_texture_size < MIN_TEXTURE_SIZE {
         // Broken GL contexts can return a max texture size of zero (See #1260).
@@ -350,7 +853,6 @@ pub fn create_webrender_instance(
         return Err(RendererError::MaxTextureSize);
     }
     if let Some(internal_limit) = options.max_internal_texture_size {
-        assert!(internal_limit >= MIN_TEXTURE_SIZE);
         max_internal_texture_size = max_internal_texture_size.min(internal_limit);
     }
 
@@ -364,6 +866,42 @@ pub fn create_webrender_instance(
     let image_tiling_threshold = options.image_tiling_threshold
         .min(max_internal_texture_size);
 
+    let mut downgrades = Vec::new();
+    if options.allow_dual_source_blending && !use_dual_source_blending {
+        downgrades.push(OptionDowngrade::DualSourceBlending);
+    }
+    if options.allow_advanced_blend_equation && !ext_blend_equation_advanced {
+        downgrades.push(OptionDowngrade::AdvancedBlendEquation);
+    }
+    if options.allow_texture_swizzling && swizzle_settings.is_none() {
+        downgrades.push(OptionDowngrade::TextureSwizzling);
+    }
+    if let Some(requested) = options.max_internal_texture_size {
+        if requested > max_internal_texture_size {
+            downgrades.push(OptionDowngrade::MaxInternalTextureSize {
+                requested,
+                effective: max_internal_texture_size,
+            });
+        }
+    }
+    if let (UploadMethod::PixelBuffer(_), UploadMethod::Immediate) = (&options.upload_method, device.upload_method()) {
+        downgrades.push(OptionDowngrade::PixelBufferUploads);
+    }
+    for downgrade in &downgrades {
+        info!("Option not supported by the device: {:?}", downgrade);
+    }
+    let mut effective_options = EffectiveOptions {
+        dual_source_blending: use_dual_source_blending,
+        advanced_blend_equation: ext_blend_equation_advanced,
+        texture_swizzling: swizzle_settings.is_some(),
+        enable_clear_scissor,
+        max_internal_texture_size,
+        image_tiling_threshold,
+        upload_method: device.upload_method().clone(),
+        glyph_backend: options.glyph_backend,
+        downgrades,
+    };
+
     device.begin_frame();
 
     let shaders = match shaders {
@@ -371,6 +909,13 @@ pub fn create_webrender_instance(
         None => Rc::new(RefCell::new(Shaders::new(&mut device, gl_type, &options)?)),
     };
 
+    let shader_watcher = match options.resource_override_path {
+        Some(ref path) if options.watch_shader_overrides => {
+            Some(ShaderOverrideWatcher::new(path.clone()))
+        }
+        _ => None,
+    };
+
     let dither_matrix_texture = if options.enable_dithering {
         let dither_matrix: [u8; 64] = [
             0,
@@ -483,15 +1028,31 @@ pub fn create_webrender_instance(
     // On other GL platforms, like macOS or Android, creating many PBOs is very inefficient.
     // This is what happens in GPU cache updates in PBO path. Instead, we switch everything
     // except software GL to use the GPU scattered updates.
+    //
+    // The scatter updates draw with a vertex array object, which GL contexts
+    // don't share, so renderers sharing their caches use pixel buffers.
     let supports_scatter = device.get_capabilities().supports_color_buffer_float;
     let gpu_cache_texture = gpu_cache::GpuCacheTexture::new(
         &mut device,
-        supports_scatter && !is_software,
+        supports_scatter && !is_software && shared_caches.is_none(),
     )?;
 
-    device.end_frame();
+    // The first renderer of a share group hands the caches above over to the
+    // group, and keeps empty ones to swap with them, like the renderers which
+    // join the group later.
+    let own_caches = match shared_caches {
+        Some(ref shared_caches) if !shared_caches.is_initialized() => {
+            let (_, result_rx) = unbounded_channel();
+            Some(RendererCaches::new(
+                result_rx,
+                TextureResolver::new(&mut device),
+                gpu_cache::GpuCacheTexture::new(&mut device, false)?,
+            ))
+        }
+        _ => None,
+    };
 
-    let backend_notifier = notifier.clone();
+    device.end_frame();
 
     let clear_alpha_targets_with_quads = !device.get_capabilities().supports_alpha_target_clears;
 
@@ -518,6 +1079,7 @@ pub fn create_webrender_instance(
     let config = FrameBuilderConfig {
         default_font_render_mode,
         dual_source_blending_is_supported: use_dual_source_blending,
+        subpixel_text_policy: SubpixelTextPolicy::Auto,
         testing: options.testing,
         gpu_supports_fast_clears: options.gpu_supports_fast_clears,
         gpu_supports_advanced_blend: ext_blend_equation_advanced,
@@ -532,9 +1094,12 @@ pub fn create_webrender_instance(
         max_depth_ids: device.max_depth_ids(),
         max_target_size: max_internal_texture_size,
         force_invalidation: false,
+        scene_metrics: options.debug_flags.contains(DebugFlags::SCENE_METRICS),
         is_software,
         low_quality_pinch_zoom: options.low_quality_pinch_zoom,
         max_shared_surface_size: options.max_shared_surface_size,
+        gpu_cache_reclaim_threshold: options.gpu_cache_reclaim_threshold,
+        gpu_cache_reclaim_delay: options.gpu_cache_reclaim_delay,
     };
     info!("WR {:?}", config);
 
@@ -543,6 +1108,158 @@ pub fn create_webrender_instance(
     let enclosing_size_of_op = options.enclosing_size_of_op;
     let make_size_of_ops =
         move || size_of_op.map(|o| MallocSizeOfOps::new(o, enclosing_size_of_op));
+    let (sender, result_rx) = match shared_caches.as_ref().and_then(|shared| shared.render_api_sender()) {
+        Some((sender, glyph_backend)) => {
+            // The render backend of the share group sends its results to the
+            // caches of the group, so this renderer's own caches get none.
+            let (_, result_rx) = unbounded_channel();
+            if glyph_backend != effective_options.glyph_backend {
+                effective_options.downgrades.push(OptionDowngrade::GlyphBackend {
+                    requested: effective_options.glyph_backend,
+                    effective: glyph_backend,
+                });
+            }
+            effective_options.glyph_backend = glyph_backend;
+            (sender, result_rx)
+        }
+        None => spawn_render_backend(
+            &mut options,
+            config,
+            notifier.clone(),
+            device.get_capabilities().supports_r8_texture_upload,
+            color_cache_formats,
+            swizzle_settings,
+            &mut effective_options,
+        )?,
+    };
+
+    let debug_method = if !options.enable_gpu_markers {
+        // The GPU markers are disabled.
+        GpuDebugMethod::None
+    } else if device.supports_extension("GL_KHR_debug") {
+        GpuDebugMethod::KHR
+    } else if device.supports_extension("GL_EXT_debug_marker") {
+        GpuDebugMethod::MarkerEXT
+    } else {
+        warn!("asking to enable_gpu_markers but no supporting extension was found");
+        GpuDebugMethod::None
+    };
+
+    info!("using {:?}", debug_method);
+
+    let gpu_profiler = GpuProfiler::new(
+        Rc::clone(device.rc_gl()),
+        debug_method,
+        device.get_capabilities().supports_timer_queries,
+    );
+    #[cfg(feature = "capture")]
+    let read_fbo = device.create_fbo();
+
+    let mut renderer = Renderer {
+        api_tx: sender.api_sender().clone(),
+        device,
+        caches: RendererCaches::new(result_rx, texture_resolver, gpu_cache_texture),
+        shared_caches: shared_caches.clone(),
+        pending_shader_updates: Vec::new(),
+        shader_watcher,
+        shaders,
+        debug: debug::LazyInitializedDebugRenderer::new(),
+        debug_flags: DebugFlags::empty(),
+        profile: TransactionProfile::new(),
+        frame_counter: 0,
+        resource_upload_time: 0.0,
+        gpu_cache_upload_time: 0.0,
+        profiler: Profiler::new(),
+        max_recorded_profiles: options.max_recorded_profiles,
+        enable_frame_checksum: options.enable_frame_checksum,
+        clear_color: options.clear_color,
+        enable_clear_scissor,
+        enable_advanced_blend_barriers: !ext_blend_equation_advanced_coherent,
+        clear_caches_with_quads: options.clear_caches_with_quads,
+        clear_alpha_targets_with_quads,
+        last_time: 0,
+        gpu_profiler,
+        vaos,
+        vertex_data_textures,
+        current_vertex_data_textures: 0,
+        dither_matrix_texture,
+        external_image_handler: None,
+        embedder_draw_handler: None,
+        path_renderer: None,
+        external_image_lock_timeout: options.external_image_lock_timeout,
+        failed_external_image_locks: FastHashSet::default(),
+        clamped_external_textures: Vec::new(),
+        video_frames: VideoFrameQueues::default(),
+        next_vsync_time: None,
+        vsync_interval: None,
+        effective_options,
+        notifier,
+        size_of_ops: make_size_of_ops(),
+        cpu_profiles: VecDeque::new(),
+        gpu_profiles: VecDeque::new(),
+        frame_counters: VecDeque::new(),
+        counter_exporter: None,
+        texture_upload_pbo_pool,
+        staging_texture_pool,
+        renderer_errors: Vec::new(),
+        async_frame_recorder: None,
+        async_screenshots: None,
+        #[cfg(feature = "capture")]
+        read_fbo,
+        #[cfg(feature = "replay")]
+        owned_external_images: FastHashMap::default(),
+        device_size: None,
+        zoom_debug_texture: None,
+        cursor_position: DeviceIntPoint::zero(),
+        force_redraw: true,
+        magnification: None,
+        post_process: None,
+        pending_post_process_shader: None,
+        compositor_config: options.compositor_config,
+        current_compositor_kind: compositor_kind,
+        allocated_native_surfaces: FastHashSet::default(),
+        debug_overlay_state: DebugOverlayState::new(),
+        buffer_damage_tracker: BufferDamageTracker::default(),
+        max_primitive_instance_count,
+        enable_instancing: options.enable_instancing,
+        consecutive_oom_frames: 0,
+        backpressure: Arc::clone(sender.backpressure()),
+    };
+
+    // We initially set the flags to default and then now call set_debug_flags
+    // to ensure any potential transition when enabling a flag is run.
+    renderer.set_debug_flags(debug_flags);
+
+    if let Some(shared_caches) = shared_caches {
+        shared_caches.add_renderer(&mut renderer, own_caches, &sender);
+    }
+
+    Ok((renderer, sender))
+}
+
+/// Spawns the render backend and scene builder threads, returning a sender
+/// for their API and the receiver of the results for the renderer.
+fn spawn_render_backend(
+    options: &mut WebRenderOptions,
+    config: FrameBuilderConfig,
+    backend_notifier: Box<dyn RenderNotifier>,
+    supports_r8_texture_upload: bool,
+    color_cache_formats: TextureFormatPair<ImageFormat>,
+    swizzle_settings: Option<SwizzleSettings>,
+    effective_options: &mut EffectiveOptions,
+) -> Result<(RenderApiSender, Receiver<ResultMsg>), RendererError> {
+    let (api_tx, api_rx) = unbounded_channel();
+    let (result_tx, result_rx) = unbounded_channel();
+    let max_internal_texture_size = effective_options.max_internal_texture_size;
+    let image_tiling_threshold = effective_options.image_tiling_threshold;
+    let debug_flags = options.debug_flags;
+    let size_of_op = options.size_of_op;
+    let enclosing_size_of_op = options.enclosing_size_of_op;
+    let make_size_of_ops =
+        move || size_of_op.map(|o| MallocSizeOfOps::new(o, enclosing_size_of_op));
+    let thread_configurator = options.thread_configurator.take();
+    let worker_configurator = thread_configurator.clone();
+    let worker_thread_config = options.worker_thread_config.clone();
     let workers = options
         .workers
         .take()
@@ -550,6 +1267,11 @@ pub fn create_webrender_instance(
             let worker = ThreadPoolBuilder::new()
                 .thread_name(|idx|{ format!("WRWorker#{}", idx) })
                 .start_handler(move |idx| {
+                    configure_current_thread(
+                        &worker_configurator,
+                        WebRenderThread::Worker(idx),
+                        &worker_thread_config,
+                    );
                     register_thread_with_profiler(format!("WRWorker#{}", idx));
                     profiler::register_thread(&format!("WRWorker#{}", idx));
                 })
@@ -559,7 +1281,7 @@ pub fn create_webrender_instance(
                 .build();
             Arc::new(worker.unwrap())
         });
-    let sampler = options.sampler;
+    let sampler = options.sampler.take();
     let namespace_alloc_by_client = options.namespace_alloc_by_client;
 
     // Ensure shared font keys exist within their own unique namespace so
@@ -570,20 +1292,73 @@ pub fn create_webrender_instance(
         RenderBackend::next_namespace_id()
     };
     let fonts = SharedFontResources::new(font_namespace);
+    let backpressure = Arc::new(Backpressure::default());
+    let rb_backpressure = Arc::clone(&backpressure);
 
     let blob_image_handler = options.blob_image_handler.take();
-    let scene_builder_hooks = options.scene_builder_hooks;
+    let scene_builder_hooks = options.scene_builder_hooks.take();
     let rb_thread_name = format!("WRRenderBackend#{}", options.renderer_id.unwrap_or(0));
     let scene_thread_name = format!("WRSceneBuilder#{}", options.renderer_id.unwrap_or(0));
     let lp_scene_thread_name = format!("WRSceneBuilderLP#{}", options.renderer_id.unwrap_or(0));
-    let glyph_rasterizer = GlyphRasterizer::new(workers, device.get_capabilities().supports_r8_texture_upload);
+    let sb_workers = if options.enable_multithreading {
+        Some(Arc::clone(&workers))
+    } else {
+        None
+    };
+    let glyph_workers = match options.glyph_raster_threads {
+        Some(count) if count > 0 => {
+            let glyph_configurator = thread_configurator.clone();
+            let glyph_thread_config = options.worker_thread_config.clone();
+            let thread_name = options.glyph_raster_thread_name.clone();
+            let profiler_thread_name = thread_name.clone();
+            let pool = ThreadPoolBuilder::new()
+                .num_threads(count)
+                .thread_name(move |idx| format!("{}#{}", thread_name, idx))
+                .start_handler(move |idx| {
+                    configure_current_thread(
+                        &glyph_configurator,
+                        WebRenderThread::GlyphWorker(idx),
+                        &glyph_thread_config,
+                    );
+                    let name = format!("{}#{}", profiler_thread_name, idx);
+                    register_thread_with_profiler(name.clone());
+                    profiler::register_thread(&name);
+                })
+                .exit_handler(move |_idx| {
+                    profiler::unregister_thread();
+                })
+                .build();
+            Arc::new(pool.unwrap())
+        }
+        _ => workers,
+    };
+    let mut glyph_rasterizer = GlyphRasterizer::with_backend(
+        glyph_workers,
+        supports_r8_texture_upload,
+        options.glyph_backend,
+    );
+    glyph_rasterizer.set_image_cache_budget(options.glyph_image_cache_budget);
+    glyph_rasterizer.set_distribute_across_threads(options.glyph_raster_distribute_across_threads);
+    glyph_rasterizer.set_telemetry_sink(options.glyph_telemetry_sink.clone());
+    let glyph_backend = glyph_rasterizer.backend();
+    if glyph_backend != options.glyph_backend {
+        effective_options.downgrades.push(OptionDowngrade::GlyphBackend {
+            requested: options.glyph_backend,
+            effective: glyph_backend,
+        });
+    }
+    effective_options.glyph_backend = glyph_backend;
 
     let (scene_builder_channels, scene_tx) =
         SceneBuilderThreadChannels::new(api_tx.clone());
 
     let sb_fonts = fonts.clone();
+    let sb_backpressure = Arc::clone(&backpressure);
+    let sb_configurator = thread_configurator.clone();
+    let sb_thread_config = options.scene_builder_thread_config.clone();
 
     thread::Builder::new().name(scene_thread_name.clone()).spawn(move || {
+        configure_current_thread(&sb_configurator, WebRenderThread::SceneBuilder, &sb_thread_config);
         register_thread_with_profiler(scene_thread_name.clone());
         profiler::register_thread(&scene_thread_name);
 
@@ -593,6 +1368,8 @@ pub fn create_webrender_instance(
             make_size_of_ops(),
             scene_builder_hooks,
             scene_builder_channels,
+            sb_backpressure,
+            sb_workers,
         );
         scene_builder.run();
 
@@ -606,7 +1383,15 @@ pub fn create_webrender_instance(
             tx: scene_tx.clone(),
         };
 
+        let lp_configurator = thread_configurator.clone();
+        let lp_thread_config = options.scene_builder_thread_config.clone();
+
         thread::Builder::new().name(lp_scene_thread_name.clone()).spawn(move || {
+            configure_current_thread(
+                &lp_configurator,
+                WebRenderThread::LowPrioritySceneBuilder,
+                &lp_thread_config,
+            );
             register_thread_with_profiler(lp_scene_thread_name.clone());
             profiler::register_thread(&lp_scene_thread_name);
 
@@ -639,8 +1424,10 @@ pub fn create_webrender_instance(
 
     let rb_scene_tx = scene_tx.clone();
     let rb_fonts = fonts.clone();
-    let enable_multithreading = options.enable_multithreading;
+    let enable_multithreading = options.enable_multithreading && options.glyph_raster_threads != Some(0);
+    let rb_thread_config = options.render_backend_thread_config.clone();
     thread::Builder::new().name(rb_thread_name.clone()).spawn(move || {
+        configure_current_thread(&thread_configurator, WebRenderThread::RenderBackend, &rb_thread_config);
         register_thread_with_profiler(rb_thread_name.clone());
         profiler::register_thread(&rb_thread_name);
 
@@ -681,108 +1468,20 @@ pub fn create_webrender_instance(
             make_size_of_ops(),
             debug_flags,
             namespace_alloc_by_client,
+            rb_backpressure,
         );
         backend.run();
         profiler::unregister_thread();
     })?;
 
-    let debug_method = if !options.enable_gpu_markers {
-        // The GPU markers are disabled.
-        GpuDebugMethod::None
-    } else if device.supports_extension("GL_KHR_debug") {
-        GpuDebugMethod::KHR
-    } else if device.supports_extension("GL_EXT_debug_marker") {
-        GpuDebugMethod::MarkerEXT
-    } else {
-        warn!("asking to enable_gpu_markers but no supporting extension was found");
-        GpuDebugMethod::None
-    };
-
-    info!("using {:?}", debug_method);
-
-    let gpu_profiler = GpuProfiler::new(Rc::clone(device.rc_gl()), debug_method);
-    #[cfg(feature = "capture")]
-    let read_fbo = device.create_fbo();
-
-    let mut renderer = Renderer {
-        result_rx,
-        api_tx: api_tx.clone(),
-        device,
-        active_documents: FastHashMap::default(),
-        pending_texture_updates: Vec::new(),
-        pending_texture_cache_updates: false,
-        pending_native_surface_updates: Vec::new(),
-        pending_gpu_cache_updates: Vec::new(),
-        pending_gpu_cache_clear: false,
-        pending_shader_updates: Vec::new(),
-        shaders,
-        debug: debug::LazyInitializedDebugRenderer::new(),
-        debug_flags: DebugFlags::empty(),
-        profile: TransactionProfile::new(),
-        frame_counter: 0,
-        resource_upload_time: 0.0,
-        gpu_cache_upload_time: 0.0,
-        profiler: Profiler::new(),
-        max_recorded_profiles: options.max_recorded_profiles,
-        clear_color: options.clear_color,
-        enable_clear_scissor,
-        enable_advanced_blend_barriers: !ext_blend_equation_advanced_coherent,
-        clear_caches_with_quads: options.clear_caches_with_quads,
-        clear_alpha_targets_with_quads,
-        last_time: 0,
-        gpu_profiler,
-        vaos,
-        vertex_data_textures,
-        current_vertex_data_textures: 0,
-        pipeline_info: PipelineInfo::default(),
-        dither_matrix_texture,
-        external_image_handler: None,
-        size_of_ops: make_size_of_ops(),
-        cpu_profiles: VecDeque::new(),
-        gpu_profiles: VecDeque::new(),
-        gpu_cache_texture,
-        gpu_cache_debug_chunks: Vec::new(),
-        gpu_cache_frame_id: FrameId::INVALID,
-        gpu_cache_overflow: false,
-        texture_upload_pbo_pool,
-        staging_texture_pool,
-        texture_resolver,
-        renderer_errors: Vec::new(),
-        async_frame_recorder: None,
-        async_screenshots: None,
-        #[cfg(feature = "capture")]
-        read_fbo,
-        #[cfg(feature = "replay")]
-        owned_external_images: FastHashMap::default(),
-        notifications: Vec::new(),
-        device_size: None,
-        zoom_debug_texture: None,
-        cursor_position: DeviceIntPoint::zero(),
-        shared_texture_cache_cleared: false,
-        documents_seen: FastHashSet::default(),
-        force_redraw: true,
-        compositor_config: options.compositor_config,
-        current_compositor_kind: compositor_kind,
-        allocated_native_surfaces: FastHashSet::default(),
-        debug_overlay_state: DebugOverlayState::new(),
-        buffer_damage_tracker: BufferDamageTracker::default(),
-        max_primitive_instance_count,
-        enable_instancing: options.enable_instancing,
-        consecutive_oom_frames: 0,
-        target_frame_publish_id: None,
-        pending_result_msg: None,
-    };
-
-    // We initially set the flags to default and then now call set_debug_flags
-    // to ensure any potential transition when enabling a flag is run.
-    renderer.set_debug_flags(debug_flags);
-
     let sender = RenderApiSender::new(
         api_tx,
         scene_tx,
         low_priority_scene_tx,
         blob_image_handler,
         fonts,
+        backpressure,
+        Arc::new(SharedRecorder::default()),
     );
-    Ok((renderer, sender))
+    Ok((sender, result_rx))
 }
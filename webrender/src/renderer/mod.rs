@@ -36,19 +36,19 @@
 
 use api::{ColorF, ColorU, MixBlendMode};
 use api::{DocumentId, Epoch, ExternalImageHandler, RenderReasons};
-#[cfg(feature = "replay")]
-use api::ExternalImageId;
+use api::{ExternalImage, ExternalImageId, ExternalImageLockError, RenderNotifier};
 use api::{ExternalImageSource, ExternalImageType, ImageFormat, PremultipliedColorF};
 use api::{PipelineId, ImageRendering, Checkpoint, NotificationRequest, ImageBufferKind};
+use api::VideoFrame;
 #[cfg(feature = "replay")]
-use api::ExternalImage;
-use api::FramePublishId;
+use api::ExternalTextureParams;
+use api::{EmbedderDrawId, FramePublishId, PathKey};
 use api::units::*;
 use api::channel::{Sender, Receiver};
 pub use api::DebugFlags;
 use core::time::Duration;
 
-use crate::render_api::{DebugCommand, ApiMsg, MemoryReport};
+use crate::render_api::{Backpressure, DebugCommand, ApiMsg, MemoryReport, MemoryPressureLevel, MemoryPressureTargets};
 use crate::batch::{AlphaBatchContainer, BatchKind, BatchFeatures, BatchTextures, BrushBatchKind, ClipBatchList};
 use crate::batch::{ClipMaskInstanceList};
 #[cfg(any(feature = "capture", feature = "replay"))]
@@ -80,6 +80,7 @@ use crate::picture::ResolvedSurfaceTexture;
 use crate::prim_store::DeferredResolve;
 use crate::profiler::{self, GpuProfileTag, TransactionProfile};
 use crate::profiler::{Profiler, add_event_marker, add_text_marker, thread_is_being_profiled};
+use crate::profiler::{CounterExporter, FrameCounters, RenderReasonStats};
 use crate::device::query::GpuProfiler;
 use crate::render_target::{ResolveOp};
 use crate::render_task_graph::{RenderTaskGraph};
@@ -87,19 +88,19 @@ use crate::render_task::{RenderTask, RenderTaskKind, ReadbackTask};
 use crate::screen_capture::AsyncScreenshotGrabber;
 use crate::render_target::{AlphaRenderTarget, ColorRenderTarget, PictureCacheTarget, PictureCacheTargetKind};
 use crate::render_target::{RenderTarget, TextureCacheRenderTarget};
-use crate::render_target::{RenderTargetKind, BlitJob};
+use crate::render_target::{RenderTargetKind, BlitJob, PathJob};
 use crate::telemetry::Telemetry;
 use crate::tile_cache::PictureCacheDebugInfo;
-use crate::util::drain_filter;
+use crate::util::{drain_filter, ScaleOffset};
 use crate::rectangle_occlusion as occlusion;
 use upload::{upload_to_texture_cache, UploadTexturePool};
+use video::VideoFrameQueues;
 use init::*;
 
 use euclid::{rect, Transform3D, Scale, default};
 use gleam::gl;
 use malloc_size_of::MallocSizeOfOps;
 
-#[cfg(feature = "replay")]
 use std::sync::Arc;
 
 use std::{
@@ -119,15 +120,19 @@ use time::precise_time_ns;
 mod debug;
 mod gpu_buffer;
 mod gpu_cache;
+mod post_process;
 mod shade;
 mod vertex;
 mod upload;
+mod video;
 pub(crate) mod init;
 
 pub use debug::DebugRenderer;
 pub use shade::{Shaders, SharedShaders};
+use shade::ShaderOverrideWatcher;
 pub use vertex::{desc, VertexArrayKind, MAX_VERTEX_TEXTURE_WIDTH};
 pub use gpu_buffer::{GpuBuffer, GpuBufferBuilder, GpuBufferAddress};
+pub use video::VideoFrameReport;
 
 /// The size of the array of each type of vertex data texture that
 /// is round-robin-ed each frame during bind_frame_data. Doing this
@@ -312,6 +317,11 @@ pub enum ShaderColorMode {
     ColorBitmap = 7,
     Image = 8,
     MultiplyDualSource = 9,
+    /// The red channel of the texture is used as alpha, see
+    /// `ImageDescriptorFlags::ALPHA_MASK`.
+    AlphaMask = 10,
+    /// The red channel of the texture is a signed distance field.
+    Sdf = 11,
 }
 
 impl From<GlyphFormat> for ShaderColorMode {
@@ -324,6 +334,7 @@ impl From<GlyphFormat> for ShaderColorMode {
                 panic!("Subpixel glyph formats must be handled separately.");
             }
             GlyphFormat::ColorBitmap => ShaderColorMode::ColorBitmap,
+            GlyphFormat::Sdf => ShaderColorMode::Sdf,
         }
     }
 }
@@ -466,6 +477,10 @@ struct TextureResolver {
     /// the output of the previous pass but are actually running in the first
     /// pass.
     dummy_cache_texture: Texture,
+
+    /// 1x1 transparent textures, by kind, that stand in for external images
+    /// which failed to lock. Created on first use.
+    transparent_textures: FastHashMap<ImageBufferKind, Texture>,
 }
 
 impl TextureResolver {
@@ -488,12 +503,17 @@ impl TextureResolver {
             texture_cache_map: FastHashMap::default(),
             external_images: FastHashMap::default(),
             dummy_cache_texture,
+            transparent_textures: FastHashMap::default(),
         }
     }
 
     fn deinit(self, device: &mut Device) {
         device.delete_texture(self.dummy_cache_texture);
 
+        for (_kind, texture) in self.transparent_textures {
+            device.delete_texture(texture);
+        }
+
         for (_id, item) in self.texture_cache_map {
             device.delete_texture(item.texture);
         }
@@ -502,6 +522,34 @@ impl TextureResolver {
     fn begin_frame(&mut self) {
     }
 
+    /// Get a 1x1 transparent texture of the given kind, or None if textures
+    /// of that kind can only be created by the embedder.
+    fn transparent_texture(
+        &mut self,
+        device: &mut Device,
+        kind: ImageBufferKind,
+    ) -> Option<&Texture> {
+        match kind {
+            ImageBufferKind::Texture2D | ImageBufferKind::TextureRect => {}
+            ImageBufferKind::TextureExternal => return None,
+        }
+
+        let texture = self.transparent_textures.entry(kind).or_insert_with(|| {
+            let texture = device.create_texture(
+                kind,
+                ImageFormat::RGBA8,
+                1,
+                1,
+                TextureFilter::Nearest,
+                None,
+            );
+            device.upload_texture_immediate(&texture, &[0u8; 4]);
+            texture
+        });
+
+        Some(texture)
+    }
+
     fn end_pass(
         &mut self,
         device: &mut Device,
@@ -756,21 +804,21 @@ impl BufferDamageTracker {
 /// We have a separate `Renderer` instance for each instance of WebRender (generally
 /// one per OS window), and all instances share the same thread.
 pub struct Renderer {
-    result_rx: Receiver<ResultMsg>,
     api_tx: Sender<ApiMsg>,
     pub device: Device,
-    pending_texture_updates: Vec<TextureUpdateList>,
-    /// True if there are any TextureCacheUpdate pending.
-    pending_texture_cache_updates: bool,
-    pending_native_surface_updates: Vec<NativeSurfaceOperation>,
-    pending_gpu_cache_updates: Vec<GpuCacheUpdateList>,
-    pending_gpu_cache_clear: bool,
+    /// The caches of this renderer, or of its share group while it updates or
+    /// renders, see `SharedCaches`.
+    caches: RendererCaches,
+    /// The share group of this renderer, if any.
+    shared_caches: Option<SharedCaches>,
     pending_shader_updates: Vec<PathBuf>,
-    active_documents: FastHashMap<DocumentId, RenderedDocument>,
+    /// Watches the shader override directory, if shader hot reloading is enabled.
+    shader_watcher: Option<ShaderOverrideWatcher>,
 
     shaders: Rc<RefCell<Shaders>>,
 
     max_recorded_profiles: usize,
+    enable_frame_checksum: bool,
 
     clear_color: ColorF,
     enable_clear_scissor: bool,
@@ -791,23 +839,9 @@ pub struct Renderer {
     pub gpu_profiler: GpuProfiler,
     vaos: vertex::RendererVAOs,
 
-    gpu_cache_texture: gpu_cache::GpuCacheTexture,
     vertex_data_textures: Vec<vertex::VertexDataTextures>,
     current_vertex_data_textures: usize,
 
-    /// When the GPU cache debugger is enabled, we keep track of the live blocks
-    /// in the GPU cache so that we can use them for the debug display. This
-    /// member stores those live blocks, indexed by row.
-    gpu_cache_debug_chunks: Vec<Vec<GpuCacheDebugChunk>>,
-
-    gpu_cache_frame_id: FrameId,
-    gpu_cache_overflow: bool,
-
-    pipeline_info: PipelineInfo,
-
-    // Manages and resolves source textures IDs to real texture IDs.
-    texture_resolver: TextureResolver,
-
     texture_upload_pbo_pool: UploadPBOPool,
     staging_texture_pool: UploadTexturePool,
 
@@ -817,6 +851,39 @@ pub struct Renderer {
     /// application to provide external buffers for image data.
     external_image_handler: Option<Box<dyn ExternalImageHandler>>,
 
+    /// Optional trait object that draws the content of embedder draw items.
+    embedder_draw_handler: Option<Box<dyn EmbedderDrawHandler>>,
+
+    /// Optional trait object that renders path items.
+    path_renderer: Option<Box<dyn PathRenderer>>,
+
+    /// See `WebRenderOptions::external_image_lock_timeout`.
+    external_image_lock_timeout: Option<Duration>,
+
+    /// Deferred resolves whose external image failed to lock this frame, and
+    /// must not be unlocked.
+    failed_external_image_locks: FastHashSet<DeferredResolveIndex>,
+
+    /// Deferred resolves whose texture wrap mode was clamped to edge for this
+    /// frame, with the wrap modes to restore before they are unlocked.
+    clamped_external_textures: Vec<(DeferredResolveIndex, [gl::GLint; 2])>,
+
+    /// Timestamped frames enqueued for external images.
+    video_frames: VideoFrameQueues,
+
+    /// The vsync time to select video frames for at the next render, see
+    /// `set_next_vsync_time`.
+    next_vsync_time: Option<u64>,
+
+    /// See `set_vsync_interval`.
+    vsync_interval: Option<u64>,
+
+    /// See `effective_options`.
+    effective_options: EffectiveOptions,
+
+    /// Used to report failures that happen on the renderer thread.
+    notifier: Box<dyn RenderNotifier>,
+
     /// Optional function pointers for measuring memory used by a given
     /// heap-allocated pointer.
     size_of_ops: Option<MallocSizeOfOps>,
@@ -830,9 +897,11 @@ pub struct Renderer {
     /// via get_frame_profiles().
     cpu_profiles: VecDeque<CpuProfile>,
     gpu_profiles: VecDeque<GpuProfile>,
-
-    /// Notification requests to be fulfilled after rendering.
-    notifications: Vec<NotificationRequest>,
+    /// Profiler counters of previous frames. Can be retrieved via
+    /// get_frame_counters().
+    frame_counters: VecDeque<FrameCounters>,
+    /// If set, the profiler counters of each frame are streamed to a file.
+    counter_exporter: Option<CounterExporter>,
 
     device_size: Option<DeviceIntSize>,
 
@@ -843,13 +912,6 @@ pub struct Renderer {
     /// functionality only, such as the debug zoom widget.
     cursor_position: DeviceIntPoint,
 
-    /// Guards to check if we might be rendering a frame with expired texture
-    /// cache entries.
-    shared_texture_cache_cleared: bool,
-
-    /// The set of documents which we've seen a publish for since last render.
-    documents_seen: FastHashSet<DocumentId>,
-
     #[cfg(feature = "capture")]
     read_fbo: FBOId,
     #[cfg(feature = "replay")]
@@ -870,6 +932,15 @@ pub struct Renderer {
     /// be drawn on the next render.
     force_redraw: bool,
 
+    /// See `set_magnification`.
+    magnification: Option<Magnification>,
+
+    /// See `set_post_process_shader`.
+    post_process: Option<post_process::PostProcessPass>,
+    /// A post-processing shader source to compile when the next frame is
+    /// composited, `Some(None)` removing the current shader.
+    pending_post_process_shader: Option<Option<String>>,
+
     /// State related to the debug / profiling overlays
     debug_overlay_state: DebugOverlayState,
 
@@ -885,6 +956,46 @@ pub struct Renderer {
     /// in a loop.
     consecutive_oom_frames: u32,
 
+    backpressure: Arc<Backpressure>,
+}
+
+/// The state a renderer keeps of the frames and resources sent by its render
+/// backend, and the textures of its caches.
+struct RendererCaches {
+    result_rx: Receiver<ResultMsg>,
+    pending_texture_updates: Vec<TextureUpdateList>,
+    /// True if there are any TextureCacheUpdate pending.
+    pending_texture_cache_updates: bool,
+    pending_native_surface_updates: Vec<NativeSurfaceOperation>,
+    pending_gpu_cache_updates: Vec<GpuCacheUpdateList>,
+    pending_gpu_cache_clear: bool,
+    active_documents: FastHashMap<DocumentId, RenderedDocument>,
+
+    gpu_cache_texture: gpu_cache::GpuCacheTexture,
+
+    /// When the GPU cache debugger is enabled, we keep track of the live blocks
+    /// in the GPU cache so that we can use them for the debug display. This
+    /// member stores those live blocks, indexed by row.
+    gpu_cache_debug_chunks: Vec<Vec<GpuCacheDebugChunk>>,
+
+    gpu_cache_frame_id: FrameId,
+    gpu_cache_overflow: bool,
+
+    pipeline_info: PipelineInfo,
+
+    // Manages and resolves source textures IDs to real texture IDs.
+    texture_resolver: TextureResolver,
+
+    /// Notification requests to be fulfilled after rendering.
+    notifications: Vec<NotificationRequest>,
+
+    /// Guards to check if we might be rendering a frame with expired texture
+    /// cache entries.
+    shared_texture_cache_cleared: bool,
+
+    /// The set of documents which we've seen a publish for since last render.
+    documents_seen: FastHashSet<DocumentId>,
+
     /// update() defers processing of ResultMsg, if frame_publish_id of
     /// ResultMsg::PublishDocument exceeds target_frame_publish_id.
     target_frame_publish_id: Option<FramePublishId>,
@@ -893,6 +1004,40 @@ pub struct Renderer {
     pending_result_msg: Option<ResultMsg>,
 }
 
+impl RendererCaches {
+    fn new(
+        result_rx: Receiver<ResultMsg>,
+        texture_resolver: TextureResolver,
+        gpu_cache_texture: gpu_cache::GpuCacheTexture,
+    ) -> Self {
+        RendererCaches {
+            result_rx,
+            pending_texture_updates: Vec::new(),
+            pending_texture_cache_updates: false,
+            pending_native_surface_updates: Vec::new(),
+            pending_gpu_cache_updates: Vec::new(),
+            pending_gpu_cache_clear: false,
+            active_documents: FastHashMap::default(),
+            gpu_cache_texture,
+            gpu_cache_debug_chunks: Vec::new(),
+            gpu_cache_frame_id: FrameId::INVALID,
+            gpu_cache_overflow: false,
+            pipeline_info: PipelineInfo::default(),
+            texture_resolver,
+            notifications: Vec::new(),
+            shared_texture_cache_cleared: false,
+            documents_seen: FastHashSet::default(),
+            target_frame_publish_id: None,
+            pending_result_msg: None,
+        }
+    }
+
+    fn deinit(self, device: &mut Device) {
+        self.gpu_cache_texture.deinit(device);
+        self.texture_resolver.deinit(device);
+    }
+}
+
 #[derive(Debug)]
 pub enum RendererError {
     Shader(ShaderError),
@@ -900,6 +1045,13 @@ pub enum RendererError {
     MaxTextureSize,
     SoftwareRasterizer,
     OutOfMemory,
+    InvalidOptions(OptionsError),
+}
+
+impl From<OptionsError> for RendererError {
+    fn from(err: OptionsError) -> Self {
+        RendererError::InvalidOptions(err)
+    }
 }
 
 impl From<ShaderError> for RendererError {
@@ -927,6 +1079,19 @@ impl Renderer {
         self.cursor_position = position;
     }
 
+    /// Whether subpixel anti-aliased text is drawn with dual-source blending.
+    /// If not, all text is drawn with grayscale anti-aliasing, see
+    /// `SubpixelTextPolicy`.
+    pub fn uses_dual_source_blending(&self) -> bool {
+        self.shaders.borrow().ps_text_run_dual_source.is_some()
+    }
+
+    /// The configuration the renderer runs with, including the options the
+    /// GL context doesn't support.
+    pub fn effective_options(&self) -> &EffectiveOptions {
+        &self.effective_options
+    }
+
     pub fn get_max_texture_size(&self) -> i32 {
         self.device.max_texture_size()
     }
@@ -952,22 +1117,47 @@ impl Renderer {
     }
 
     pub fn flush_pipeline_info(&mut self) -> PipelineInfo {
-        mem::replace(&mut self.pipeline_info, PipelineInfo::default())
+        self.with_shared_caches(|renderer| {
+            mem::replace(&mut renderer.caches.pipeline_info, PipelineInfo::default())
+        })
     }
 
     /// Returns the Epoch of the current frame in a pipeline.
     pub fn current_epoch(&self, document_id: DocumentId, pipeline_id: PipelineId) -> Option<Epoch> {
-        self.pipeline_info.epochs.get(&(pipeline_id, document_id)).cloned()
+        self.with_caches(|caches| {
+            caches.pipeline_info.epochs.get(&(pipeline_id, document_id)).cloned()
+        })
+    }
+
+    /// Calls `f` with the caches of the share group of this renderer swapped
+    /// in, if it has one. See `SharedCaches`.
+    fn with_shared_caches<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        if let Some(ref shared_caches) = self.shared_caches {
+            shared_caches.swap(&mut self.caches);
+        }
+        let result = f(self);
+        if let Some(ref shared_caches) = self.shared_caches {
+            shared_caches.swap(&mut self.caches);
+        }
+        result
+    }
+
+    /// Calls `f` with the caches of this renderer, or of its share group.
+    fn with_caches<R>(&self, f: impl FnOnce(&RendererCaches) -> R) -> R {
+        match self.shared_caches {
+            Some(ref shared_caches) => shared_caches.with_caches(f),
+            None => f(&self.caches),
+        }
     }
 
     fn get_next_result_msg(&mut self) -> Option<ResultMsg> {
-        if self.pending_result_msg.is_none() {
-            if let Ok(msg) = self.result_rx.try_recv() {
-                self.pending_result_msg = Some(msg);
+        if self.caches.pending_result_msg.is_none() {
+            if let Ok(msg) = self.caches.result_rx.try_recv() {
+                self.caches.pending_result_msg = Some(msg);
             }
         }
 
-        match (&self.pending_result_msg, &self.target_frame_publish_id) {
+        match (&self.caches.pending_result_msg, &self.caches.target_frame_publish_id) {
           (Some(ResultMsg::PublishDocument(frame_publish_id, _, _, _)), Some(target_id)) => {
             if frame_publish_id > target_id {
               return None;
@@ -976,23 +1166,31 @@ impl Renderer {
           _ => {}
         }
 
-        self.pending_result_msg.take()
+        self.caches.pending_result_msg.take()
     }
 
     /// Processes the result queue.
     ///
     /// Should be called before `render()`, as texture cache updates are done here.
     pub fn update(&mut self) {
+        self.with_shared_caches(|renderer| renderer.update_impl())
+    }
+
+    fn update_impl(&mut self) {
         profile_scope!("update");
 
+        if let Some(ref mut shader_watcher) = self.shader_watcher {
+            shader_watcher.poll(&mut self.pending_shader_updates);
+        }
+
         // Pull any pending results and return the most recent.
         while let Some(msg) = self.get_next_result_msg() {
             match msg {
                 ResultMsg::PublishPipelineInfo(mut pipeline_info) => {
                     for ((pipeline_id, document_id), epoch) in pipeline_info.epochs {
-                        self.pipeline_info.epochs.insert((pipeline_id, document_id), epoch);
+                        self.caches.pipeline_info.epochs.insert((pipeline_id, document_id), epoch);
                     }
-                    self.pipeline_info.removed_pipelines.extend(pipeline_info.removed_pipelines.drain(..));
+                    self.caches.pipeline_info.removed_pipelines.extend(pipeline_info.removed_pipelines.drain(..));
                 }
                 ResultMsg::PublishDocument(
                     _,
@@ -1000,6 +1198,8 @@ impl Renderer {
                     mut doc,
                     resource_update_list,
                 ) => {
+                    self.backpressure.frame_consumed();
+
                     // Add a new document to the active set
 
                     // If the document we are replacing must be drawn (in order to
@@ -1008,7 +1208,7 @@ impl Renderer {
                     // because a) we don't need to render to the main framebuffer
                     // so it is cheaper not to, and b) doing so without a
                     // subsequent present would break partial present.
-                    if let Some(mut prev_doc) = self.active_documents.remove(&document_id) {
+                    if let Some(mut prev_doc) = self.caches.active_documents.remove(&document_id) {
                         doc.profile.merge(&mut prev_doc.profile);
 
                         if prev_doc.frame.must_be_drawn() {
@@ -1020,9 +1220,21 @@ impl Renderer {
                                 0,
                             ).ok();
                         }
+
+                        // Labels of a frame that was never presented carry
+                        // over to the one that replaces it.
+                        if !prev_doc.transaction_labels.is_empty() {
+                            prev_doc.transaction_labels.append(&mut doc.transaction_labels);
+                            doc.transaction_labels = prev_doc.transaction_labels;
+                        }
+
+                        // So does the latency of its frame request.
+                        if prev_doc.frame_request.is_some() {
+                            doc.frame_request = prev_doc.frame_request;
+                        }
                     }
 
-                    self.active_documents.insert(document_id, doc);
+                    self.caches.active_documents.insert(document_id, doc);
 
                     // IMPORTANT: The pending texture cache updates must be applied
                     //            *after* the previous frame has been rendered above
@@ -1035,36 +1247,36 @@ impl Renderer {
                     //            3) bad stuff happens.
 
                     //TODO: associate `document_id` with target window
-                    self.pending_texture_cache_updates |= !resource_update_list.texture_updates.updates.is_empty();
-                    self.pending_texture_updates.push(resource_update_list.texture_updates);
-                    self.pending_native_surface_updates.extend(resource_update_list.native_surface_updates);
-                    self.documents_seen.insert(document_id);
+                    self.caches.pending_texture_cache_updates |= !resource_update_list.texture_updates.updates.is_empty();
+                    self.caches.pending_texture_updates.push(resource_update_list.texture_updates);
+                    self.caches.pending_native_surface_updates.extend(resource_update_list.native_surface_updates);
+                    self.caches.documents_seen.insert(document_id);
                 }
                 ResultMsg::UpdateGpuCache(mut list) => {
                     if list.clear {
-                        self.pending_gpu_cache_clear = true;
+                        self.caches.pending_gpu_cache_clear = true;
                     }
                     if list.clear {
-                        self.gpu_cache_debug_chunks = Vec::new();
+                        self.caches.gpu_cache_debug_chunks = Vec::new();
                     }
                     for cmd in mem::replace(&mut list.debug_commands, Vec::new()) {
                         match cmd {
                             GpuCacheDebugCmd::Alloc(chunk) => {
                                 let row = chunk.address.v as usize;
-                                if row >= self.gpu_cache_debug_chunks.len() {
-                                    self.gpu_cache_debug_chunks.resize(row + 1, Vec::new());
+                                if row >= self.caches.gpu_cache_debug_chunks.len() {
+                                    self.caches.gpu_cache_debug_chunks.resize(row + 1, Vec::new());
                                 }
-                                self.gpu_cache_debug_chunks[row].push(chunk);
+                                self.caches.gpu_cache_debug_chunks[row].push(chunk);
                             },
                             GpuCacheDebugCmd::Free(address) => {
-                                let chunks = &mut self.gpu_cache_debug_chunks[address.v as usize];
+                                let chunks = &mut self.caches.gpu_cache_debug_chunks[address.v as usize];
                                 let pos = chunks.iter()
                                     .position(|x| x.address == address).unwrap();
                                 chunks.remove(pos);
                             },
                         }
                     }
-                    self.pending_gpu_cache_updates.push(list);
+                    self.caches.pending_gpu_cache_updates.push(list);
                 }
                 ResultMsg::UpdateResources {
                     resource_updates,
@@ -1081,7 +1293,7 @@ impl Renderer {
                         // have picture/texture cache targets, force a render so that
                         // those targets are updated.
                         let active_documents = mem::replace(
-                            &mut self.active_documents,
+                            &mut self.caches.active_documents,
                             FastHashMap::default(),
                         );
                         for (doc_id, mut doc) in active_documents {
@@ -1099,9 +1311,9 @@ impl Renderer {
                         }
                     }
 
-                    self.pending_texture_cache_updates |= !resource_updates.texture_updates.updates.is_empty();
-                    self.pending_texture_updates.push(resource_updates.texture_updates);
-                    self.pending_native_surface_updates.extend(resource_updates.native_surface_updates);
+                    self.caches.pending_texture_cache_updates |= !resource_updates.texture_updates.updates.is_empty();
+                    self.caches.pending_texture_updates.push(resource_updates.texture_updates);
+                    self.caches.pending_native_surface_updates.extend(resource_updates.native_surface_updates);
                     self.device.begin_frame();
 
                     self.update_texture_cache();
@@ -1125,14 +1337,14 @@ impl Renderer {
                     // pending_texture_updates. They may simply be nops, which do not
                     // need to prevent issuing the notification, and if so, may not
                     // cause a timely frame render to occur to wake up any listeners.
-                    if !self.pending_texture_cache_updates {
+                    if !self.caches.pending_texture_cache_updates {
                         drain_filter(
                             &mut notifications,
                             |n| { n.when() == Checkpoint::FrameTexturesUpdated },
                             |n| { n.notify(); },
                         );
                     }
-                    self.notifications.append(&mut notifications);
+                    self.caches.notifications.append(&mut notifications);
                 }
                 ResultMsg::ForceRedraw => {
                     self.force_redraw = true;
@@ -1150,7 +1362,7 @@ impl Renderer {
                     }
                     #[cfg(feature = "replay")]
                     DebugOutput::LoadCapture(config, plain_externals) => {
-                        self.active_documents.clear();
+                        self.caches.active_documents.clear();
                         self.load_capture(config, plain_externals);
                     }
                 },
@@ -1164,7 +1376,9 @@ impl Renderer {
     /// update() defers processing of ResultMsg, if frame_publish_id of
     /// ResultMsg::PublishDocument exceeds target_frame_publish_id.
     pub fn set_target_frame_publish_id(&mut self, publish_id: FramePublishId) {
-        self.target_frame_publish_id = Some(publish_id);
+        self.with_shared_caches(|renderer| {
+            renderer.caches.target_frame_publish_id = Some(publish_id);
+        })
     }
 
     fn handle_debug_command(&mut self, command: DebugCommand) {
@@ -1179,16 +1393,49 @@ impl Renderer {
             DebugCommand::StopCaptureSequence => {
                 panic!("Capture commands are not welcome here! Did you build with 'capture' feature?")
             }
+            DebugCommand::StartTransactionRecording(..) |
+            DebugCommand::StopTransactionRecording => {
+                panic!("Should be handled by RenderApi");
+            }
             DebugCommand::ClearCaches(_)
             | DebugCommand::SimulateLongSceneBuild(_)
             | DebugCommand::EnableNativeCompositor(_)
             | DebugCommand::SetBatchingLookback(_) => {}
             DebugCommand::InvalidateGpuCache => {
-                self.gpu_cache_texture.invalidate();
+                self.caches.gpu_cache_texture.invalidate();
             }
             DebugCommand::SetFlags(flags) => {
                 self.set_debug_flags(flags);
             }
+            DebugCommand::RegisterProfilerCounter { name, unit } => {
+                self.profiler.register_custom_counter(name, unit);
+            }
+            DebugCommand::SetProfilerCounter(name, value) => {
+                if !self.profiler.set_custom_counter(name, value) {
+                    warn!("Unknown profiler counter {:?}", name);
+                }
+            }
+            DebugCommand::DumpTextureCache(root) => {
+                #[cfg(feature = "capture")]
+                self.dump_texture_cache(&root);
+                #[cfg(not(feature = "capture"))]
+                warn!("Unable to dump the texture cache to {:?} without the 'capture' feature", root);
+            }
+            DebugCommand::ExportProfilerCounters(export) => {
+                if let Some(mut exporter) = self.counter_exporter.take() {
+                    exporter.flush().ok();
+                }
+                if let Some((path, format)) = export {
+                    match CounterExporter::new(&path, format) {
+                        Ok(exporter) => {
+                            self.counter_exporter = Some(exporter);
+                        }
+                        Err(e) => {
+                            error!("Unable to export profiler counters to {:?}: {:?}", path, e);
+                        }
+                    }
+                }
+            }
         }
     }
 
@@ -1197,6 +1444,62 @@ impl Renderer {
         self.external_image_handler = Some(handler);
     }
 
+    /// Set a callback for drawing the content of embedder draw items, which
+    /// are left empty without one.
+    ///
+    /// The handler is only called when a frame is composited, so when the
+    /// content changes the embedder has to request a composite, for example
+    /// with `Transaction::invalidate_rendered_frame`.
+    pub fn set_embedder_draw_handler(&mut self, handler: Box<dyn EmbedderDrawHandler>) {
+        self.embedder_draw_handler = Some(handler);
+    }
+
+    /// Set the renderer for path items, which are left empty without one.
+    ///
+    /// Rendered paths are cached until their key or size changes, so the
+    /// path renderer should be set before the first frame with paths in it
+    /// is rendered.
+    pub fn set_path_renderer(&mut self, renderer: Box<dyn PathRenderer>) {
+        self.path_renderer = Some(renderer);
+    }
+
+    /// Enqueue timestamped frames for an external image.
+    ///
+    /// When rendering with a vsync time set via `set_next_vsync_time`, the most
+    /// recent frame due by that time is selected and locked in place of the
+    /// external image `queue_id`. Until a frame is selected, `queue_id` itself
+    /// is locked. Changes of the presented frame are reported in
+    /// `RenderResults::video_frames`. The render backend then invalidates the
+    /// content drawn from `queue_id` and builds a new frame with it, notifying
+    /// the embedder through `RenderNotifier::new_frame_ready` without a
+    /// `generate_frame` being needed.
+    pub fn enqueue_video_frames<I>(&mut self, queue_id: ExternalImageId, frames: I)
+    where
+        I: IntoIterator<Item = VideoFrame>,
+    {
+        self.video_frames.enqueue(queue_id, frames);
+    }
+
+    /// Stop substituting frames for an external image, returning the frames
+    /// that were still queued or presented.
+    pub fn remove_video_frame_queue(&mut self, queue_id: ExternalImageId) -> Vec<VideoFrame> {
+        self.video_frames.remove(queue_id)
+    }
+
+    /// Set the time, in nanoseconds, of the vsync the next rendered frame will
+    /// be presented at. This drives the selection of enqueued video frames.
+    pub fn set_next_vsync_time(&mut self, time_ns: u64) {
+        self.next_vsync_time = Some(time_ns);
+    }
+
+    /// Set the time between vsyncs, in nanoseconds. This is used to count how
+    /// many vsync deadlines a late frame missed, see
+    /// `RendererStats::missed_vsync_deadlines`. Without it, a late frame
+    /// counts as missing a single deadline.
+    pub fn set_vsync_interval(&mut self, interval_ns: u64) {
+        self.vsync_interval = Some(interval_ns).filter(|&interval| interval > 0);
+    }
+
     /// Retrieve (and clear) the current list of recorded frame profiles.
     pub fn get_frame_profiles(&mut self) -> (Vec<CpuProfile>, Vec<GpuProfile>) {
         let cpu_profiles = self.cpu_profiles.drain(..).collect();
@@ -1204,12 +1507,83 @@ impl Renderer {
         (cpu_profiles, gpu_profiles)
     }
 
+    /// Retrieve (and clear) the profiler counters recorded for previous frames.
+    ///
+    /// Counters are only recorded if `max_recorded_profiles` is non-zero.
+    pub fn get_frame_counters(&mut self) -> Vec<FrameCounters> {
+        self.frame_counters.drain(..).collect()
+    }
+
+    /// Number of frames and CPU time attributed to each render reason since the
+    /// renderer was created or `reset_render_reason_stats` was last called.
+    pub fn render_reason_stats(&self) -> RenderReasonStats {
+        self.profiler.render_reason_stats().clone()
+    }
+
+    pub fn reset_render_reason_stats(&mut self) {
+        self.profiler.reset_render_reason_stats();
+    }
+
     /// Reset the current partial present state. This forces the entire framebuffer
     /// to be refreshed next time `render` is called.
     pub fn force_redraw(&mut self) {
         self.force_redraw = true;
     }
 
+    /// Notifies the renderer that the surface it draws into was destroyed and
+    /// created again while the GL context was kept, as happens when an Android
+    /// application is paused and resumed.
+    ///
+    /// Documents, resources and GPU caches are preserved, the next call to
+    /// `render` redraws the whole surface from the last frame.
+    ///
+    /// Losing the GL context is not supported: the textures, programs, VAOs
+    /// and other objects the renderer created in it are not recreated. If the
+    /// context was lost, for example when EGL reports `EGL_CONTEXT_LOST` on
+    /// resume, the embedder has to drop this renderer and create a new one
+    /// with a new context, then add its documents and resources again.
+    pub fn surface_recreated(&mut self) {
+        // The new surface has no valid content to partially present over.
+        self.force_redraw();
+    }
+
+    /// Magnifies the composited frame around a focal point, for screen
+    /// magnifiers. The cached picture tiles are composited again with the
+    /// zoom applied, so changing it doesn't require building a new scene or
+    /// frame: calling `render` after this is enough. The tiles are scaled
+    /// rather than rasterized at the higher resolution, so magnified content
+    /// looks blurrier than content zoomed in the display list.
+    ///
+    /// Only supported with the draw compositor, native compositors ignore it.
+    pub fn set_magnification(&mut self, magnification: Option<Magnification>) {
+        let magnification = magnification.filter(|m| m.scale != 1.0);
+        if magnification != self.magnification {
+            self.magnification = magnification;
+            self.force_redraw();
+        }
+    }
+
+    /// Installs a fragment shader that is applied to the composited frame
+    /// before it is presented, or removes it if `source` is `None`.
+    ///
+    /// The source is GLSL without a `#version` directive and has to define
+    /// `vec4 post_process(vec2 uv)`, returning the premultiplied color of the
+    /// output pixel at `uv`, which ranges from 0 to 1 across the frame in
+    /// framebuffer orientation. It can use the following declarations:
+    ///
+    /// - `uniform sampler2D sFrame`, the composited frame.
+    /// - `uniform vec2 uTextureSize`, the size of the frame in pixels.
+    /// - `uniform vec4 uParams[4]`, the values set with
+    ///   `Transaction::set_post_process_params`.
+    ///
+    /// The shader is compiled when the next frame is rendered, and compile
+    /// errors are returned from `render`. Only supported with the draw
+    /// compositor, native compositors ignore it.
+    pub fn set_post_process_shader(&mut self, source: Option<String>) {
+        self.pending_post_process_shader = Some(source);
+        self.force_redraw();
+    }
+
     /// Renders the current frame.
     ///
     /// A Frame is supplied by calling [`generate_frame()`][webrender_api::Transaction::generate_frame].
@@ -1219,24 +1593,51 @@ impl Renderer {
         &mut self,
         device_size: DeviceIntSize,
         buffer_age: usize,
+    ) -> Result<RenderResults, Vec<RendererError>> {
+        self.with_shared_caches(|renderer| {
+            // Select the last added document as the one to render (Gecko only
+            // ever creates a single document per renderer right now). Embedders
+            // with several documents use `render_document` instead.
+            let doc_id = renderer.caches.active_documents.keys().last().cloned();
+            renderer.render_document_impl(doc_id, device_size, buffer_age)
+        })
+    }
+
+    /// Renders the current frame of a specific document into the framebuffer
+    /// bound when this is called.
+    ///
+    /// This lets a single renderer, with a single set of shaders and caches,
+    /// present several documents to different surfaces (e.g. one document per
+    /// window), by binding each surface in turn on the shared context before
+    /// rendering its document. Does nothing if the document has no frame yet.
+    /// Partial present only tracks a single surface, so it can't be used in
+    /// this configuration.
+    pub fn render_document(
+        &mut self,
+        document_id: DocumentId,
+        device_size: DeviceIntSize,
+        buffer_age: usize,
+    ) -> Result<RenderResults, Vec<RendererError>> {
+        self.with_shared_caches(|renderer| {
+            renderer.render_document_impl(Some(document_id), device_size, buffer_age)
+        })
+    }
+
+    fn render_document_impl(
+        &mut self,
+        doc_id: Option<DocumentId>,
+        device_size: DeviceIntSize,
+        buffer_age: usize,
     ) -> Result<RenderResults, Vec<RendererError>> {
         self.device_size = Some(device_size);
 
-        // TODO(gw): We want to make the active document that is
-        //           being rendered configurable via the public
-        //           API in future. For now, just select the last
-        //           added document as the active one to render
-        //           (Gecko only ever creates a single document
-        //           per renderer right now).
-        let doc_id = self.active_documents.keys().last().cloned();
-
-        let result = match doc_id {
-            Some(doc_id) => {
-                // Remove the doc from the map to appease the borrow checker
-                let mut doc = self.active_documents
-                    .remove(&doc_id)
-                    .unwrap();
+        // Remove the doc from the map to appease the borrow checker
+        let doc = doc_id.and_then(|doc_id| {
+            self.caches.active_documents.remove(&doc_id).map(|doc| (doc_id, doc))
+        });
 
+        let result = match doc {
+            Some((doc_id, mut doc)) => {
                 let result = self.render_impl(
                     doc_id,
                     &mut doc,
@@ -1244,7 +1645,7 @@ impl Renderer {
                     buffer_age,
                 );
 
-                self.active_documents.insert(doc_id, doc);
+                self.caches.active_documents.insert(doc_id, doc);
 
                 result
             }
@@ -1255,7 +1656,7 @@ impl Renderer {
         };
 
         drain_filter(
-            &mut self.notifications,
+            &mut self.caches.notifications,
             |n| { n.when() == Checkpoint::FrameRendered },
             |n| { n.notify(); },
         );
@@ -1271,7 +1672,11 @@ impl Renderer {
         }
 
         if oom {
-            let _ = self.api_tx.send(ApiMsg::MemoryPressure);
+            let _ = self.api_tx.send(ApiMsg::MemoryPressure(
+                MemoryPressureLevel::Critical,
+                MemoryPressureTargets::all(),
+                None,
+            ));
             // Ensure we don't get stuck in a loop.
             self.consecutive_oom_frames += 1;
             assert!(self.consecutive_oom_frames < 5, "Renderer out of memory");
@@ -1282,7 +1687,7 @@ impl Renderer {
         // This is the end of the rendering pipeline. If some notifications are is still there,
         // just clear them and they will autimatically fire the Checkpoint::TransactionDropped
         // event. Otherwise they would just pile up in this vector forever.
-        self.notifications.clear();
+        self.caches.notifications.clear();
 
         tracy_frame_marker!();
 
@@ -1424,8 +1829,23 @@ impl Renderer {
     ) -> Result<RenderResults, Vec<RendererError>> {
         profile_scope!("render");
         let mut results = RenderResults::default();
+        results.transaction_labels = mem::take(&mut active_doc.transaction_labels);
+        if !results.transaction_labels.is_empty() && thread_is_being_profiled() {
+            add_text_marker("TransactionLabels", &results.transaction_labels.join(", "), Duration::new(0, 0));
+        }
         self.profile.start_time(profiler::RENDERER_TIME);
 
+        let vsync_time = self.next_vsync_time.take();
+        if let Some(vsync_time) = vsync_time {
+            results.video_frames = self.video_frames.select_frames(vsync_time);
+            if !results.video_frames.is_empty() {
+                // Let the backend invalidate the tiles the previous frames were
+                // drawn in and build a frame showing the new ones.
+                let ids = results.video_frames.iter().map(|report| report.queue_id).collect();
+                let _ = self.api_tx.send(ApiMsg::ExternalImagesChanged(ids));
+            }
+        }
+
         self.staging_texture_pool.begin_frame();
 
         let compositor_kind = active_doc.frame.composite_state.compositor_kind;
@@ -1464,7 +1884,14 @@ impl Renderer {
         // that point. Otherwise, the bind / invalidate / bind logic trips up the
         // render pass logic in tiled / mobile GPUs, resulting in an extra copy /
         // resolve step when the debug overlay is enabled.
-        self.texture_resolver.begin_frame();
+        self.caches.texture_resolver.begin_frame();
+
+        // Reload shaders between frames if their sources were modified.
+        if !self.pending_shader_updates.is_empty() {
+            info!("Reloading shaders, modified: {:?}", self.pending_shader_updates);
+            self.pending_shader_updates.clear();
+            self.shaders.borrow_mut().reset(&mut self.device);
+        }
 
         if let Some(device_size) = device_size {
             self.update_gpu_profile(device_size);
@@ -1504,16 +1931,16 @@ impl Renderer {
         let profile = &mut active_doc.profile;
         assert!(self.current_compositor_kind == frame.composite_state.compositor_kind);
 
-        if self.shared_texture_cache_cleared {
-            assert!(self.documents_seen.contains(&doc_id),
+        if self.caches.shared_texture_cache_cleared {
+            assert!(self.caches.documents_seen.contains(&doc_id),
                     "Cleared texture cache without sending new document frame.");
         }
 
         match self.prepare_gpu_cache(&frame.deferred_resolves) {
             Ok(..) => {
-                assert!(frame.gpu_cache_frame_id <= self.gpu_cache_frame_id,
+                assert!(frame.gpu_cache_frame_id <= self.caches.gpu_cache_frame_id,
                     "Received frame depends on a later GPU cache epoch ({:?}) than one we received last via `UpdateGpuCache` ({:?})",
-                    frame.gpu_cache_frame_id, self.gpu_cache_frame_id);
+                    frame.gpu_cache_frame_id, self.caches.gpu_cache_frame_id);
 
                 {
                     profile_scope!("gl.flush");
@@ -1577,6 +2004,23 @@ impl Renderer {
         if device_size.is_some() {
             let time = profiler::ns_to_ms(current_time - self.last_time);
             self.profile.set(profiler::FRAME_TIME, time);
+
+            if let Some(request) = active_doc.frame_request.take() {
+                results.stats.frame_latency = profiler::ns_to_ms(current_time.saturating_sub(request.request_time));
+                results.stats.backend_queue_time = profiler::ns_to_ms(request.backend_queue_time);
+                self.profile.set(profiler::FRAME_LATENCY, results.stats.frame_latency);
+                self.profile.set(profiler::BACKEND_QUEUE_TIME, results.stats.backend_queue_time);
+            }
+
+            if let Some(vsync_time) = vsync_time {
+                let lateness = current_time.saturating_sub(vsync_time);
+                results.stats.missed_vsync_deadlines = if lateness == 0 {
+                    0
+                } else {
+                    self.vsync_interval.map_or(1, |interval| 1 + (lateness / interval) as usize)
+                };
+                self.profile.set(profiler::MISSED_VSYNC_DEADLINES, results.stats.missed_vsync_deadlines);
+            }
         }
 
         if self.max_recorded_profiles > 0 {
@@ -1598,7 +2042,7 @@ impl Renderer {
             add_text_marker("NumDrawCalls", &message, duration);
         }
 
-        let report = self.texture_resolver.report_memory();
+        let report = self.caches.texture_resolver.report_memory();
         self.profile.set(profiler::RENDER_TARGET_MEM, profiler::bytes_to_mb(report.render_target_textures));
         self.profile.set(profiler::PICTURE_TILES_MEM, profiler::bytes_to_mb(report.picture_tile_textures));
         self.profile.set(profiler::ATLAS_TEXTURES_MEM, profiler::bytes_to_mb(report.atlas_textures));
@@ -1629,6 +2073,11 @@ impl Renderer {
         // of frames that had the bit set over a half second window whereas max shows whether
         // the bit as been set at least once during that time window.
         // We could implement better ways to visualize this information.
+        let frame_cpu_time = self.profile.get(profiler::TOTAL_FRAME_CPU_TIME)
+            .or(self.profile.get(profiler::RENDERER_TIME))
+            .unwrap_or(0.0);
+        self.profiler.record_render_reasons(active_doc.render_reasons, frame_cpu_time);
+
         let add_markers = thread_is_being_profiled();
         for i in 0..RenderReasons::NUM_BITS {
             let counter = profiler::RENDER_REASON_FIRST + i as usize;
@@ -1646,11 +2095,27 @@ impl Renderer {
         active_doc.render_reasons = RenderReasons::empty();
 
 
-        self.texture_resolver.update_profile(&mut self.profile);
+        self.caches.texture_resolver.update_profile(&mut self.profile);
 
         // Note: this clears the values in self.profile.
         self.profiler.set_counters(&mut self.profile);
 
+        if self.max_recorded_profiles > 0 || self.counter_exporter.is_some() {
+            let counters = self.profiler.frame_counters(self.frame_counter);
+            if let Some(exporter) = self.counter_exporter.as_mut() {
+                if let Err(e) = exporter.write(&counters) {
+                    error!("Failed to export profiler counters: {:?}", e);
+                    self.counter_exporter = None;
+                }
+            }
+            if self.max_recorded_profiles > 0 {
+                while self.frame_counters.len() >= self.max_recorded_profiles {
+                    self.frame_counters.pop_front();
+                }
+                self.frame_counters.push_back(counters);
+            }
+        }
+
         // Note: profile counters must be set before this or they will count for next frame.
         self.profiler.update();
 
@@ -1713,8 +2178,8 @@ impl Renderer {
             }
         }
 
-        self.documents_seen.clear();
-        self.shared_texture_cache_cleared = false;
+        self.caches.documents_seen.clear();
+        self.caches.shared_texture_cache_cleared = false;
 
         self.check_gl_errors();
 
@@ -1771,8 +2236,8 @@ impl Renderer {
         profile_scope!("update_texture_cache");
 
         let _gm = self.gpu_profiler.start_marker("texture cache update");
-        let mut pending_texture_updates = mem::replace(&mut self.pending_texture_updates, vec![]);
-        self.pending_texture_cache_updates = false;
+        let mut pending_texture_updates = mem::replace(&mut self.caches.pending_texture_updates, vec![]);
+        self.caches.pending_texture_cache_updates = false;
 
         self.profile.start_time(profiler::TEXTURE_CACHE_UPDATE_TIME);
 
@@ -1783,7 +2248,7 @@ impl Renderer {
             // Handle copies from one texture to another.
             for ((src_tex, dst_tex), copies) in &update_list.copies {
 
-                let dest_texture = &self.texture_resolver.texture_cache_map[&dst_tex].texture;
+                let dest_texture = &self.caches.texture_resolver.texture_cache_map[&dst_tex].texture;
                 let dst_texture_size = dest_texture.get_dimensions().to_f32();
 
                 let mut copy_instances = Vec::new();
@@ -1822,7 +2287,7 @@ impl Renderer {
             // Find any textures that will need to be deleted in this group of allocations.
             let mut pending_deletes = Vec::new();
             for allocation in &update_list.allocations {
-                let old = self.texture_resolver.texture_cache_map.remove(&allocation.id);
+                let old = self.caches.texture_resolver.texture_cache_map.remove(&allocation.id);
                 match allocation.kind {
                     TextureCacheAllocationKind::Alloc(_) => {
                         assert!(old.is_none(), "Renderer and backend disagree!");
@@ -1926,7 +2391,7 @@ impl Renderer {
 
                         create_cache_texture_time += precise_time_ns() - create_cache_texture_start;
 
-                        self.texture_resolver.texture_cache_map.insert(allocation.id, CacheTexture {
+                        self.caches.texture_resolver.texture_cache_map.insert(allocation.id, CacheTexture {
                             texture,
                             category: info.category,
                         });
@@ -1958,7 +2423,7 @@ impl Renderer {
         Telemetry::record_texture_cache_update_time(Duration::from_micros((t * 1000.00) as u64));
 
         drain_filter(
-            &mut self.notifications,
+            &mut self.caches.notifications,
             |n| { n.when() == Checkpoint::FrameTexturesUpdated },
             |n| { n.notify(); },
         );
@@ -1975,14 +2440,14 @@ impl Renderer {
 
     fn bind_textures(&mut self, textures: &BatchTextures) {
         for i in 0 .. 3 {
-            self.texture_resolver.bind(
+            self.caches.texture_resolver.bind(
                 &textures.input.colors[i],
                 TextureSampler::color(i),
                 &mut self.device,
             );
         }
 
-        self.texture_resolver.bind(
+        self.caches.texture_resolver.bind(
             &textures.clip_mask,
             TextureSampler::ClipMask,
             &mut self.device,
@@ -2067,7 +2532,7 @@ impl Renderer {
             readback.get_target_texture(),
             Swizzle::default(),
         );
-        let (cache_texture, _) = self.texture_resolver
+        let (cache_texture, _) = self.caches.texture_resolver
             .resolve(&texture_source).expect("bug: no source texture");
 
         // Before submitting the composite batch, do the
@@ -2404,7 +2869,7 @@ impl Renderer {
             };
 
             debug_assert_eq!(source_rect.size(), blit.target_rect.size());
-            let (texture, swizzle) = self.texture_resolver
+            let (texture, swizzle) = self.caches.texture_resolver
                 .resolve(&source)
                 .expect("BUG: invalid source texture");
 
@@ -2450,7 +2915,7 @@ impl Renderer {
             let instances = match source {
                 TextureSource::External(..) => {
                     uv_override_instances = instances.iter().map(|instance| {
-                        let texel_rect: TexelRect = self.texture_resolver.get_uv_rect(
+                        let texel_rect: TexelRect = self.caches.texture_resolver.get_uv_rect(
                             &source,
                             instance.source_rect.cast().into()
                         ).into();
@@ -2470,7 +2935,7 @@ impl Renderer {
                 .bind(
                     &mut self.device,
                     &projection,
-                    Some(self.texture_resolver.get_texture_size(source).to_f32()),
+                    Some(self.caches.texture_resolver.get_texture_size(source).to_f32()),
                     &mut self.renderer_errors,
                     &mut self.profile,
                 );
@@ -2577,7 +3042,7 @@ impl Renderer {
                     src_task.get_target_texture(),
                     Swizzle::default(),
                 );
-                let (cache_texture, _) = self.texture_resolver
+                let (cache_texture, _) = self.caches.texture_resolver
                     .resolve(&texture_source).expect("bug: no source texture");
 
                 let read_target = ReadTarget::from_texture(cache_texture);
@@ -2696,7 +3161,7 @@ impl Renderer {
             }
             PictureCacheTargetKind::Blit { task_id, sub_rect_offset } => {
                 let src_task = &render_tasks[task_id];
-                let (texture, _swizzle) = self.texture_resolver
+                let (texture, _swizzle) = self.caches.texture_resolver
                     .resolve(&src_task.get_texture_source())
                     .expect("BUG: invalid source texture");
 
@@ -3043,9 +3508,9 @@ impl Renderer {
                     // the frame render. To handle this, query the texture resolver for the
                     // UV rect if it's an external texture, otherwise use the default UV rect.
                     let uv_rects = [
-                        self.texture_resolver.get_uv_rect(&textures.input.colors[0], planes[0].uv_rect),
-                        self.texture_resolver.get_uv_rect(&textures.input.colors[1], planes[1].uv_rect),
-                        self.texture_resolver.get_uv_rect(&textures.input.colors[2], planes[2].uv_rect),
+                        self.caches.texture_resolver.get_uv_rect(&textures.input.colors[0], planes[0].uv_rect),
+                        self.caches.texture_resolver.get_uv_rect(&textures.input.colors[1], planes[1].uv_rect),
+                        self.caches.texture_resolver.get_uv_rect(&textures.input.colors[2], planes[2].uv_rect),
                     ];
 
                     let instance = CompositeInstance::new_yuv(
@@ -3078,7 +3543,7 @@ impl Renderer {
                         );
 
                     let textures = BatchTextures::composite_rgb(plane.texture);
-                    let uv_rect = self.texture_resolver.get_uv_rect(&textures.input.colors[0], plane.uv_rect);
+                    let uv_rect = self.caches.texture_resolver.get_uv_rect(&textures.input.colors[0], plane.uv_rect);
                     let instance = CompositeInstance::new_rgb(
                         surface_rect.cast_unit().to_f32(),
                         surface_rect.to_f32(),
@@ -3089,6 +3554,9 @@ impl Renderer {
 
                     ( textures, instance )
                 },
+                ResolvedExternalSurfaceColorData::EmbedderDraw { .. } => {
+                    unreachable!("bug: embedder draws are not promoted with native compositors");
+                }
             };
 
             self.draw_instanced_batch(
@@ -3114,6 +3582,7 @@ impl Renderer {
         composite_state: &CompositeState,
         external_surfaces: &[ResolvedExternalSurface],
         projection: &default::Transform3D<f32>,
+        draw_target: DrawTarget,
         stats: &mut RendererStats,
     ) {
         let mut current_shader_params = (
@@ -3200,9 +3669,9 @@ impl Renderer {
                             // the frame render. To handle this, query the texture resolver for the
                             // UV rect if it's an external texture, otherwise use the default UV rect.
                             let uv_rects = [
-                                self.texture_resolver.get_uv_rect(&textures.input.colors[0], planes[0].uv_rect),
-                                self.texture_resolver.get_uv_rect(&textures.input.colors[1], planes[1].uv_rect),
-                                self.texture_resolver.get_uv_rect(&textures.input.colors[2], planes[2].uv_rect),
+                                self.caches.texture_resolver.get_uv_rect(&textures.input.colors[0], planes[0].uv_rect),
+                                self.caches.texture_resolver.get_uv_rect(&textures.input.colors[1], planes[1].uv_rect),
+                                self.caches.texture_resolver.get_uv_rect(&textures.input.colors[2], planes[2].uv_rect),
                             ];
 
                             (
@@ -3225,7 +3694,7 @@ impl Renderer {
                             )
                         },
                         ResolvedExternalSurfaceColorData::Rgb { ref plane, .. } => {
-                            let uv_rect = self.texture_resolver.get_uv_rect(&plane.texture, plane.uv_rect);
+                            let uv_rect = self.caches.texture_resolver.get_uv_rect(&plane.texture, plane.uv_rect);
                             let instance = CompositeInstance::new_rgb(
                                 tile_rect,
                                 clip_rect,
@@ -3241,10 +3710,47 @@ impl Renderer {
                                     CompositeSurfaceFormat::Rgba,
                                     surface.image_buffer_kind,
                                     features,
-                                    Some(self.texture_resolver.get_texture_size(&plane.texture).to_f32()),
+                                    Some(self.caches.texture_resolver.get_texture_size(&plane.texture).to_f32()),
                                 ),
                             )
                         },
+                        ResolvedExternalSurfaceColorData::EmbedderDraw { id } => {
+                            // Everything below has to be drawn before the
+                            // embedder draws on top of it.
+                            if !instances.is_empty() {
+                                self.draw_instanced_batch(
+                                    &instances,
+                                    VertexArrayKind::Composite,
+                                    &current_textures,
+                                    stats,
+                                );
+                                instances.clear();
+                            }
+
+                            self.draw_embedder_content(
+                                id,
+                                composite_state.get_device_transform(tile.transform_index),
+                                clip_rect,
+                                draw_target,
+                                projection,
+                            );
+
+                            // The handler may have bound another program.
+                            self.shaders
+                                .borrow_mut()
+                                .get_composite_shader(
+                                    current_shader_params.0,
+                                    current_shader_params.1,
+                                    current_shader_params.2,
+                                ).bind(
+                                    &mut self.device,
+                                    projection,
+                                    current_shader_params.3,
+                                    &mut self.renderer_errors,
+                                    &mut self.profile,
+                                );
+                            continue;
+                        }
                     }
                 }
                 CompositeTileSurface::Clear => {
@@ -3316,6 +3822,111 @@ impl Renderer {
         }
     }
 
+    /// Lets the embedder draw the content of an embedder draw item in to the
+    /// framebuffer, between the composited tiles below and above it. Embedder
+    /// draw surfaces are never opaque, so this only happens while compositing
+    /// alpha tiles.
+    fn draw_embedder_content(
+        &mut self,
+        id: EmbedderDrawId,
+        local_to_device: ScaleOffset,
+        clip_rect: DeviceRect,
+        draw_target: DrawTarget,
+        projection: &default::Transform3D<f32>,
+    ) {
+        let viewport = match draw_target {
+            DrawTarget::Default { rect, .. } => rect,
+            _ => unreachable!("bug: compositing in to an offscreen target"),
+        };
+
+        // Map the clip rect through the projection rather than flipping it,
+        // so that magnification is taken in to account.
+        let clip_rect = match projection.outer_transformed_box2d(&clip_rect.to_untyped()) {
+            Some(ndc_rect) => {
+                let to_framebuffer = |x: f32, y: f32| default::Point2D::new(
+                    viewport.min.x as f32 + (x + 1.0) * 0.5 * viewport.width() as f32,
+                    viewport.min.y as f32 + (y + 1.0) * 0.5 * viewport.height() as f32,
+                );
+                default::Box2D::new(
+                    to_framebuffer(ndc_rect.min.x, ndc_rect.min.y),
+                    to_framebuffer(ndc_rect.max.x, ndc_rect.max.y),
+                ).round().to_i32().cast_unit()
+            }
+            None => return,
+        };
+
+        let params = EmbedderDrawParams {
+            fbo_id: self.device.default_draw_fbo_id(),
+            viewport,
+            clip_rect,
+            transform: local_to_device.to_transform().then(projection),
+        };
+
+        let handler = match self.embedder_draw_handler {
+            Some(ref mut handler) => handler,
+            None => return,
+        };
+
+        let _gm = self.gpu_profiler.start_marker("embedder draw");
+        handler.draw(id, &params);
+
+        // The handler is free to change any GL state.
+        self.device.reset_cached_state();
+        self.device.bind_draw_target(draw_target);
+        self.device.disable_depth();
+        self.device.disable_depth_write();
+        self.device.disable_scissor();
+        self.set_blend(true, FramebufferKind::Main);
+        self.set_blend_mode_premultiplied_alpha(FramebufferKind::Main);
+    }
+
+    /// Lets the path renderer render paths in to their render tasks, which
+    /// have been cleared to transparent.
+    fn draw_paths(
+        &mut self,
+        jobs: &[PathJob],
+        draw_target: DrawTarget,
+        projection: &default::Transform3D<f32>,
+    ) {
+        let path_renderer = match self.path_renderer {
+            Some(ref mut path_renderer) => path_renderer,
+            None => return,
+        };
+
+        let _gm = self.gpu_profiler.start_marker("paths");
+        let fbo_id = self.device.bound_draw_fbo_id();
+
+        for job in jobs {
+            if job.local_size.is_empty() {
+                continue;
+            }
+
+            let task_rect = job.task_rect.to_f32();
+            let transform = default::Transform3D::scale(
+                task_rect.width() / job.local_size.width,
+                task_rect.height() / job.local_size.height,
+                1.0,
+            )
+            .then_translate(default::Vector3D::new(task_rect.min.x, task_rect.min.y, 0.0))
+            .then(projection);
+
+            let params = PathRenderParams {
+                fbo_id,
+                target_rect: draw_target.to_framebuffer_rect(job.task_rect),
+                transform,
+            };
+            path_renderer.render(job.key, &params);
+        }
+
+        // The path renderer is free to change any GL state.
+        self.device.reset_cached_state();
+        self.device.bind_draw_target(draw_target);
+        self.device.disable_depth();
+        self.device.disable_depth_write();
+        self.device.disable_scissor();
+        self.set_blend(false, FramebufferKind::Other);
+    }
+
     /// Composite picture cache tiles into the framebuffer. This is currently
     /// the only way that picture cache tiles get drawn. In future, the tiles
     /// will often be handed to the OS compositor, and this method will be
@@ -3431,6 +4042,7 @@ impl Renderer {
                 &composite_state,
                 &composite_state.external_surfaces,
                 projection,
+                draw_target,
                 &mut results.stats,
             );
             self.gpu_profiler.finish_sampler(opaque_sampler);
@@ -3445,6 +4057,7 @@ impl Renderer {
                 &composite_state,
                 &composite_state.external_surfaces,
                 projection,
+                draw_target,
                 &mut results.stats,
             );
             self.gpu_profiler.finish_sampler(transparent_sampler);
@@ -3460,6 +4073,7 @@ impl Renderer {
                 &composite_state,
                 &composite_state.external_surfaces,
                 projection,
+                draw_target,
                 &mut results.stats,
             );
             self.gpu_profiler.finish_sampler(transparent_sampler);
@@ -3964,7 +4578,7 @@ impl Renderer {
 
         self.set_blend(false, FramebufferKind::Other);
 
-        let texture = &self.texture_resolver.texture_cache_map[texture].texture;
+        let texture = &self.caches.texture_resolver.texture_cache_map[texture].texture;
         let target_size = texture.get_dimensions();
 
         let projection = Transform3D::ortho(
@@ -4103,6 +4717,11 @@ impl Renderer {
             self.set_blend(false, FramebufferKind::Other);
         }
 
+        // Let the path renderer render any paths for this target.
+        if !target.paths.is_empty() {
+            self.draw_paths(&target.paths, draw_target, &projection);
+        }
+
         // Draw any fast path linear gradients for this target.
         if !target.fast_linear_gradients.is_empty() {
             let _timer = self.gpu_profiler.start_timer(GPU_TAG_CACHE_FAST_LINEAR_GRADIENT);
@@ -4238,7 +4857,7 @@ impl Renderer {
         let mut list = GpuCacheUpdateList {
             frame_id: FrameId::INVALID,
             clear: false,
-            height: self.gpu_cache_texture.get_height(),
+            height: self.caches.gpu_cache_texture.get_height(),
             blocks: Vec::new(),
             updates: Vec::new(),
             debug_commands: Vec::new(),
@@ -4250,58 +4869,83 @@ impl Renderer {
             let ext_image = props
                 .external_image
                 .expect("BUG: Deferred resolves must be external images!");
-            // Provide rendering information for NativeTexture external images.
-            let image = handler.lock(ext_image.id, ext_image.channel_index);
+            let (id, channel_index) = self.video_frames.resolve(ext_image.id, ext_image.channel_index);
             let texture_target = match ext_image.image_type {
                 ExternalImageType::TextureHandle(target) => target,
                 ExternalImageType::Buffer => {
                     panic!("not a suitable image type in update_deferred_resolves()");
                 }
             };
+            // Provide rendering information for NativeTexture external images.
+            let image = match lock_external_image(
+                handler,
+                self.external_image_lock_timeout,
+                id,
+                channel_index,
+            ) {
+                Ok(image) => image,
+                Err(error) => {
+                    warn!("Failed to lock ext-image {:?}: {:?}", id, error);
+                    self.notifier.external_image_lock_failed(id, channel_index, error);
+                    self.failed_external_image_locks.insert(DeferredResolveIndex(i as u32));
+                    let placeholder = self.caches.texture_resolver
+                        .transparent_texture(&mut self.device, texture_target)
+                        .map(Texture::internal_id);
+                    failed_lock_external_image(placeholder)
+                }
+            };
 
             // In order to produce the handle, the external image handler may call into
             // the GL context and change some states.
             self.device.reset_state();
 
-            let texture = match image.source {
-                ExternalImageSource::NativeTexture(texture_id) => {
-                    ExternalTexture::new(
-                        texture_id,
-                        texture_target,
-                        image.uv,
-                        deferred_resolve.rendering,
-                    )
-                }
-                ExternalImageSource::Invalid => {
-                    warn!("Invalid ext-image");
-                    debug!(
-                        "For ext_id:{:?}, channel:{}.",
-                        ext_image.id,
-                        ext_image.channel_index
-                    );
-                    // Just use 0 as the gl handle for this failed case.
-                    ExternalTexture::new(
-                        0,
-                        texture_target,
-                        image.uv,
-                        deferred_resolve.rendering,
-                    )
-                }
-                ExternalImageSource::RawData(_) => {
-                    panic!("Raw external data is not expected for deferred resolves!");
+            let native_texture = native_external_texture(
+                &image,
+                texture_target,
+                deferred_resolve.rendering,
+            );
+            let (texture, clamp_to_edge) = match native_texture {
+                Some(native_texture) => native_texture,
+                None => match image.source {
+                    ExternalImageSource::Invalid => {
+                        warn!("Invalid ext-image");
+                        debug!(
+                            "For ext_id:{:?}, channel:{}.",
+                            ext_image.id,
+                            ext_image.channel_index
+                        );
+                        // Just use 0 as the gl handle for this failed case.
+                        let texture = ExternalTexture::new(
+                            0,
+                            texture_target,
+                            image.uv,
+                            deferred_resolve.rendering,
+                        );
+                        (texture, false)
+                    }
+                    _ => {
+                        panic!("Raw external data is not expected for deferred resolves!");
+                    }
                 }
             };
 
-            self.texture_resolver
+            let index = DeferredResolveIndex(i as u32);
+            if clamp_to_edge {
+                let wrap = self.device.clamp_external_texture_to_edge(&texture);
+                self.clamped_external_textures.push((index, wrap));
+            }
+
+            let uv = texture.get_uv_rect();
+            self.caches.texture_resolver
                 .external_images
-                .insert(DeferredResolveIndex(i as u32), texture);
+                .insert(index, texture);
 
             list.updates.push(GpuCacheUpdate::Copy {
                 block_index: list.blocks.len(),
                 block_count: BLOCKS_PER_UV_RECT,
                 address: deferred_resolve.address,
             });
-            list.blocks.push(image.uv.into());
+            list.blocks.push(uv.into());
             list.blocks.push([0f32; 4].into());
         }
 
@@ -4312,17 +4956,28 @@ impl Renderer {
         &mut self,
         deferred_resolves: &[DeferredResolve],
     ) {
-        if !self.texture_resolver.external_images.is_empty() {
+        if !self.caches.texture_resolver.external_images.is_empty() {
+            // Hand the textures back with the wrap modes they were locked with,
+            // in reverse order in case a texture was resolved more than once.
+            for (index, wrap) in self.clamped_external_textures.drain(..).rev() {
+                let texture = &self.caches.texture_resolver.external_images[&index];
+                self.device.set_external_texture_wrap(texture, wrap);
+            }
+
             let handler = self.external_image_handler
                 .as_mut()
                 .expect("Found external image, but no handler set!");
 
-            for (index, _) in self.texture_resolver.external_images.drain() {
+            for (index, _) in self.caches.texture_resolver.external_images.drain() {
+                if self.failed_external_image_locks.remove(&index) {
+                    continue;
+                }
                 let props = &deferred_resolves[index.0 as usize].image_properties;
                 let ext_image = props
                     .external_image
                     .expect("BUG: Deferred resolves must be external images!");
-                handler.unlock(ext_image.id, ext_image.channel_index);
+                let (id, channel_index) = self.video_frames.resolve(ext_image.id, ext_image.channel_index);
+                handler.unlock(id, channel_index);
             }
         }
     }
@@ -4338,6 +4993,26 @@ impl Renderer {
         results: &mut RenderResults,
     ) -> Option<PartialPresentMode> {
         let mut partial_present_mode = None;
+        let fb_rect = DeviceRect::from_size(draw_target_dimensions.to_f32());
+
+        let dirty_rects_are_valid =
+            composite_state.dirty_rects_are_valid &&
+            !self.force_redraw &&
+            self.magnification.is_none() &&
+            self.post_process.is_none() &&
+            !self.debug_overlay_state.is_enabled;
+
+        // Report the damage of the frame even if partial present isn't used,
+        // see `RenderResults::damage_rects`.
+        if dirty_rects_are_valid {
+            results.damage_rects.extend(
+                tile_dirty_rects(composite_state, &fb_rect)
+                    .map(|dirty_rect| dirty_rect.round_out().to_i32())
+                    .filter(|dirty_rect| !dirty_rect.is_empty())
+            );
+        } else {
+            results.damage_rects.push(fb_rect.to_i32());
+        }
 
         let (max_partial_present_rects, draw_previous_partial_present_regions) = match self.current_compositor_kind {
             CompositorKind::Native { .. } => {
@@ -4362,33 +5037,16 @@ impl Renderer {
             };
 
             let can_use_partial_present =
-                composite_state.dirty_rects_are_valid &&
-                !self.force_redraw &&
-                !(prev_frames_damage_rect.is_none() && draw_previous_partial_present_regions) &&
-                !self.debug_overlay_state.is_enabled;
+                dirty_rects_are_valid &&
+                !(prev_frames_damage_rect.is_none() && draw_previous_partial_present_regions);
 
             if can_use_partial_present {
                 let mut combined_dirty_rect = DeviceRect::zero();
-                let fb_rect = DeviceRect::from_size(draw_target_dimensions.to_f32());
 
                 // Work out how many dirty rects WR produced, and if that's more than
                 // what the device supports.
-                for tile in &composite_state.tiles {
-                    if tile.kind == TileKind::Clear {
-                        continue;
-                    }
-                    let dirty_rect = composite_state.get_device_rect(
-                        &tile.local_dirty_rect,
-                        tile.transform_index,
-                    );
-
-                    // In pathological cases where a tile is extremely zoomed, it
-                    // may end up with device coords outside the range of an i32,
-                    // so clamp it to the frame buffer rect here, before it gets
-                    // casted to an i32 rect below.
-                    if let Some(dirty_rect) = dirty_rect.intersection(&fb_rect) {
-                        combined_dirty_rect = combined_dirty_rect.union(&dirty_rect);
-                    }
+                for dirty_rect in tile_dirty_rects(composite_state, &fb_rect) {
+                    combined_dirty_rect = combined_dirty_rect.union(&dirty_rect);
                 }
 
                 let combined_dirty_rect = combined_dirty_rect.round();
@@ -4456,7 +5114,7 @@ impl Renderer {
 
         match self.compositor_config {
             CompositorConfig::Native { ref mut compositor, .. } => {
-                for op in self.pending_native_surface_updates.drain(..) {
+                for op in self.caches.pending_native_surface_updates.drain(..) {
                     match op.details {
                         NativeSurfaceOperationDetails::CreateSurface { id, virtual_offset, tile_size, is_opaque } => {
                             let _inserted = self.allocated_native_surfaces.insert(id);
@@ -4507,7 +5165,7 @@ impl Renderer {
             CompositorConfig::Draw { .. } => {
                 // Ensure nothing is added in simple composite mode, since otherwise
                 // memory will leak as this doesn't get drained
-                debug_assert!(self.pending_native_surface_updates.is_empty());
+                debug_assert!(self.caches.pending_native_surface_updates.is_empty());
             }
         }
     }
@@ -4660,7 +5318,7 @@ impl Renderer {
 
                     let draw_target = match picture_target.surface {
                         ResolvedSurfaceTexture::TextureCache { ref texture } => {
-                            let (texture, _) = self.texture_resolver
+                            let (texture, _) = self.caches.texture_resolver
                                 .resolve(texture)
                                 .expect("bug");
 
@@ -4730,7 +5388,7 @@ impl Renderer {
 
                 let texture_id = target.texture_id();
 
-                let alpha_tex = self.texture_resolver.get_cache_texture_mut(&texture_id);
+                let alpha_tex = self.caches.texture_resolver.get_cache_texture_mut(&texture_id);
 
                 let draw_target = DrawTarget::from_texture(
                     alpha_tex,
@@ -4762,7 +5420,7 @@ impl Renderer {
 
                 let texture_id = target.texture_id();
 
-                let color_tex = self.texture_resolver.get_cache_texture_mut(&texture_id);
+                let color_tex = self.caches.texture_resolver.get_cache_texture_mut(&texture_id);
 
                 self.device.reuse_render_target::<u8>(
                     color_tex,
@@ -4804,7 +5462,7 @@ impl Renderer {
             // frame buffer until the implicit end_pass in end_frame allows
             // debug draw overlays to be added without triggering a copy
             // resolve stage in mobile / tiled GPUs.
-            self.texture_resolver.end_pass(
+            self.caches.texture_resolver.end_pass(
                 &mut self.device,
                 &pass.textures_to_invalidate,
             );
@@ -4861,6 +5519,17 @@ impl Renderer {
                 self.device.ortho_far_plane(),
             );
 
+            // Magnification is applied in device space, before the projection.
+            let projection = match self.magnification {
+                Some(Magnification { scale, focal_point }) => {
+                    Transform3D::translation(-focal_point.x, -focal_point.y, 0.0)
+                        .then_scale(scale, scale, 1.0)
+                        .then_translate(default::Vector3D::new(focal_point.x, focal_point.y, 0.0))
+                        .then(&projection)
+                }
+                None => projection,
+            };
+
             let fb_scale = Scale::<_, _, FramebufferPixel>::new(1i32);
             let mut fb_rect = frame.device_rect * fb_scale;
 
@@ -4889,6 +5558,7 @@ impl Renderer {
                     );
                 }
                 CompositorKind::Draw { .. } => {
+                    self.device.set_color_filter(frame.composite_state.color_filter);
                     self.composite_simple(
                         &frame.composite_state,
                         draw_target,
@@ -4896,6 +5566,32 @@ impl Renderer {
                         results,
                         present_mode,
                     );
+                    self.device.set_color_filter(None);
+
+                    if let Some(source) = self.pending_post_process_shader.take() {
+                        if let Some(pass) = self.post_process.take() {
+                            pass.deinit(&mut self.device);
+                        }
+                        if let Some(source) = source {
+                            match post_process::PostProcessPass::new(&mut self.device, &source) {
+                                Ok(pass) => self.post_process = Some(pass),
+                                Err(e) => self.renderer_errors.push(RendererError::Shader(e)),
+                            }
+                        }
+                    }
+                    if let Some(ref mut pass) = self.post_process {
+                        let _gm = self.gpu_profiler.start_marker("post process");
+                        pass.apply(
+                            &mut self.device,
+                            fb_rect,
+                            draw_target,
+                            &frame.composite_state.post_process_params,
+                        );
+                    }
+
+                    if self.enable_frame_checksum {
+                        results.frame_checksum = Some(self.compute_frame_checksum(fb_rect));
+                    }
                 }
             }
         } else {
@@ -4905,6 +5601,30 @@ impl Renderer {
         }
     }
 
+    /// Hash the composited contents of the given framebuffer rect.
+    ///
+    /// The hash is stable across runs and platforms for identical pixels, but
+    /// rows are read in framebuffer order, so it depends on the surface origin.
+    fn compute_frame_checksum(&mut self, fb_rect: FramebufferIntRect) -> u64 {
+        let _gm = self.gpu_profiler.start_marker("frame checksum");
+        let mut pixels = vec![0; fb_rect.area() as usize * 4];
+        self.device.bind_read_target(ReadTarget::Default);
+        self.device.read_pixels_into(fb_rect, ImageFormat::RGBA8, &mut pixels);
+
+        // 64 bit FNV-1a.
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in pixels {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        hash
+    }
+
+    /// Enable or disable the computation of `RenderResults::frame_checksum`.
+    pub fn set_frame_checksum_enabled(&mut self, enabled: bool) {
+        self.enable_frame_checksum = enabled;
+    }
+
     pub fn debug_renderer(&mut self) -> Option<&mut DebugRenderer> {
         self.debug.get_mut(&mut self.device)
     }
@@ -4986,7 +5706,7 @@ impl Renderer {
             None => return,
         };
 
-        let textures = self.texture_resolver
+        let textures = self.caches.texture_resolver
             .texture_cache_map
             .values()
             .filter(|item| item.category == TextureCacheCategory::RenderTarget)
@@ -5098,7 +5818,7 @@ impl Renderer {
             None => return,
         };
 
-        let textures = self.texture_resolver
+        let textures = self.caches.texture_resolver
             .texture_cache_map
             .values()
             .filter(|item| item.category == TextureCacheCategory::Atlas)
@@ -5234,7 +5954,7 @@ impl Renderer {
         let y0: f32 = 30.0;
         let mut y = y0;
         let mut text_width = 0.0;
-        for ((pipeline, document_id), epoch) in  &self.pipeline_info.epochs {
+        for ((pipeline, document_id), epoch) in  &self.caches.pipeline_info.epochs {
             y += dy;
             let w = debug_renderer.add_text(
                 x0, y,
@@ -5300,7 +6020,7 @@ impl Renderer {
         };
 
         let (x_off, y_off) = (30f32, 30f32);
-        let height = self.gpu_cache_texture.get_height()
+        let height = self.caches.gpu_cache_texture.get_height()
             .min(device_size.height - (y_off as i32) * 2) as usize;
         debug_renderer.add_quad(
             x_off,
@@ -5311,8 +6031,8 @@ impl Renderer {
             ColorU::new(80, 80, 80, 80),
         );
 
-        let upper = self.gpu_cache_debug_chunks.len().min(height);
-        for chunk in self.gpu_cache_debug_chunks[0..upper].iter().flatten() {
+        let upper = self.caches.gpu_cache_debug_chunks.len().min(height);
+        for chunk in self.caches.gpu_cache_debug_chunks[0..upper].iter().flatten() {
             let color = ColorU::new(250, 0, 0, 200);
             debug_renderer.add_quad(
                 x_off + chunk.address.u as f32,
@@ -5352,19 +6072,26 @@ impl Renderer {
             }
             compositor.deinit(&mut self.device);
         }
-        self.gpu_cache_texture.deinit(&mut self.device);
         if let Some(dither_matrix_texture) = self.dither_matrix_texture {
             self.device.delete_texture(dither_matrix_texture);
         }
         if let Some(zoom_debug_texture) = self.zoom_debug_texture {
             self.device.delete_texture(zoom_debug_texture);
         }
+        if let Some(post_process) = self.post_process {
+            post_process.deinit(&mut self.device);
+        }
         for textures in self.vertex_data_textures.drain(..) {
             textures.deinit(&mut self.device);
         }
         self.texture_upload_pbo_pool.deinit(&mut self.device);
         self.staging_texture_pool.delete_textures(&mut self.device);
-        self.texture_resolver.deinit(&mut self.device);
+        if let Some(shared_caches) = self.shared_caches.take() {
+            if let Some(caches) = shared_caches.remove_renderer() {
+                caches.deinit(&mut self.device);
+            }
+        }
+        self.caches.deinit(&mut self.device);
         self.vaos.deinit(&mut self.device);
         self.debug.deinit(&mut self.device);
 
@@ -5387,6 +6114,7 @@ impl Renderer {
             self.device.delete_external_texture(ext);
         }
         self.device.end_frame();
+        self.device.leave_share_group();
     }
 
     fn size_of<T>(&self, ptr: *const T) -> usize {
@@ -5395,35 +6123,38 @@ impl Renderer {
     }
 
     /// Collects a memory report.
+    /// The renderers of a share group each report the memory of its caches.
     pub fn report_memory(&self, swgl: *mut c_void) -> MemoryReport {
-        let mut report = MemoryReport::default();
+        self.with_caches(|caches| {
+            let mut report = MemoryReport::default();
 
-        // GPU cache CPU memory.
-        self.gpu_cache_texture.report_memory_to(&mut report, self.size_of_ops.as_ref().unwrap());
+            // GPU cache CPU memory.
+            caches.gpu_cache_texture.report_memory_to(&mut report, self.size_of_ops.as_ref().unwrap());
 
-        self.staging_texture_pool.report_memory_to(&mut report, self.size_of_ops.as_ref().unwrap());
+            self.staging_texture_pool.report_memory_to(&mut report, self.size_of_ops.as_ref().unwrap());
 
-        // Render task CPU memory.
-        for (_id, doc) in &self.active_documents {
-            report.render_tasks += self.size_of(doc.frame.render_tasks.tasks.as_ptr());
-            report.render_tasks += self.size_of(doc.frame.render_tasks.task_data.as_ptr());
-        }
+            // Render task CPU memory.
+            for (_id, doc) in &caches.active_documents {
+                report.render_tasks += self.size_of(doc.frame.render_tasks.tasks.as_ptr());
+                report.render_tasks += self.size_of(doc.frame.render_tasks.task_data.as_ptr());
+            }
 
-        // Vertex data GPU memory.
-        for textures in &self.vertex_data_textures {
-            report.vertex_data_textures += textures.size_in_bytes();
-        }
+            // Vertex data GPU memory.
+            for textures in &self.vertex_data_textures {
+                report.vertex_data_textures += textures.size_in_bytes();
+            }
 
-        // Texture cache and render target GPU memory.
-        report += self.texture_resolver.report_memory();
+            // Texture cache and render target GPU memory.
+            report += caches.texture_resolver.report_memory();
 
-        // Texture upload PBO memory.
-        report += self.texture_upload_pbo_pool.report_memory();
+            // Texture upload PBO memory.
+            report += self.texture_upload_pbo_pool.report_memory();
 
-        // Textures held internally within the device layer.
-        report += self.device.report_memory(self.size_of_ops.as_ref().unwrap(), swgl);
+            // Textures held internally within the device layer.
+            report += self.device.report_memory(self.size_of_ops.as_ref().unwrap(), swgl);
 
-        report
+            report
+        })
     }
 
     // Sets the blend mode. Blend is unconditionally set if the "show overdraw" debugging mode is
@@ -5497,6 +6228,65 @@ bitflags! {
     }
 }
 
+/// Draws the content of embedder draw items, see
+/// `Renderer::set_embedder_draw_handler`.
+pub trait EmbedderDrawHandler {
+    /// Draws the content of the embedder draw item with the given id, using
+    /// the renderer's GL context. The framebuffer and viewport are already
+    /// bound and content is blended with premultiplied alpha. Any GL state
+    /// can be changed.
+    fn draw(&mut self, id: EmbedderDrawId, params: &EmbedderDrawParams);
+}
+
+/// Where an embedder draw item is drawn, see `EmbedderDrawHandler`.
+#[derive(Copy, Clone, Debug)]
+pub struct EmbedderDrawParams {
+    /// The GL name of the bound framebuffer.
+    pub fbo_id: u32,
+    /// The viewport of the frame in the framebuffer.
+    pub viewport: FramebufferIntRect,
+    /// The visible part of the item in the framebuffer, drawing outside of
+    /// it has to be prevented with a scissor rect.
+    pub clip_rect: FramebufferIntRect,
+    /// Maps points in the item's bounds, relative to their top left corner,
+    /// to clip space.
+    pub transform: default::Transform3D<f32>,
+}
+
+/// Renders the content of path items, see `Renderer::set_path_renderer`.
+///
+/// Paths are rendered in to the texture cache, from where they are drawn like
+/// images, so this is called only when a path is first drawn at a given size.
+pub trait PathRenderer {
+    /// Renders the path with the given key, using the renderer's GL context.
+    /// The framebuffer and viewport are already bound and the target rect has
+    /// been cleared to transparent. The output is expected to be premultiplied
+    /// alpha. Any GL state can be changed.
+    fn render(&mut self, key: PathKey, params: &PathRenderParams);
+}
+
+/// Where a path is rendered, see `PathRenderer`.
+#[derive(Copy, Clone, Debug)]
+pub struct PathRenderParams {
+    /// The GL name of the bound framebuffer.
+    pub fbo_id: u32,
+    /// The part of the framebuffer the path is rendered in to, drawing
+    /// outside of it has to be prevented with a scissor rect.
+    pub target_rect: FramebufferIntRect,
+    /// Maps points in the path item's bounds, relative to their top left
+    /// corner, to clip space.
+    pub transform: default::Transform3D<f32>,
+}
+
+/// A zoom applied when compositing the frame, see `Renderer::set_magnification`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Magnification {
+    /// The zoom factor, 1.0 being no magnification.
+    pub scale: f32,
+    /// The point of the frame, in device pixels, that stays in place.
+    pub focal_point: DevicePoint,
+}
+
 /// The cumulative times spent in each painting phase to generate this frame.
 #[derive(Debug, Default)]
 pub struct FullFrameStats {
@@ -5541,6 +6331,16 @@ pub struct RendererStats {
     pub frame_build_time: f64,
     pub full_display_list: bool,
     pub full_paint: bool,
+    /// Time from the oldest `generate_frame` request answered by this frame
+    /// to the end of rendering, in milliseconds. Zero if the frame wasn't
+    /// requested, for example when it was rendered again.
+    pub frame_latency: f64,
+    /// How long that request waited in the render backend before the frame
+    /// started building, in milliseconds.
+    pub backend_queue_time: f64,
+    /// How many vsync deadlines passed between the vsync given by
+    /// `Renderer::set_next_vsync_time` and the end of rendering.
+    pub missed_vsync_deadlines: usize,
 }
 
 impl RendererStats {
@@ -5571,9 +6371,27 @@ pub struct RenderResults {
     ///           specific picture cache slice / OS compositor surface).
     pub dirty_rects: Vec<DeviceIntRect>,
 
+    /// The device rects whose content changed in this frame, whether or not
+    /// partial present is used. This is the whole framebuffer if the damage
+    /// isn't known, for example after a resize. Embedders that present or
+    /// stream frames themselves can use it to avoid diffing pixels.
+    pub damage_rects: Vec<DeviceIntRect>,
+
     /// Information about the state of picture cache tiles. This is only
     /// allocated and stored if config.testing is true (such as wrench)
     pub picture_cache_debug: PictureCacheDebugInfo,
+
+    /// A hash of the composited output, if `enable_frame_checksum` is set and
+    /// the frame was composited by WebRender rather than a native compositor.
+    pub frame_checksum: Option<u64>,
+
+    /// Labels of the transactions that contributed to this frame, see
+    /// `Transaction::set_label`.
+    pub transaction_labels: Vec<String>,
+
+    /// Video frame queues whose presented frame changed for this frame, see
+    /// `Renderer::enqueue_video_frames`.
+    pub video_frames: Vec<VideoFrameReport>,
 }
 
 #[cfg(any(feature = "capture", feature = "replay"))]
@@ -5608,7 +6426,7 @@ struct PlainExternalResources {
 
 #[cfg(feature = "replay")]
 enum CapturedExternalImageData {
-    NativeTexture(gl::GLuint),
+    NativeTexture(gl::GLuint, Option<ExternalTextureParams>),
     Buffer(Arc<Vec<u8>>),
 }
 
@@ -5624,7 +6442,10 @@ impl ExternalImageHandler for DummyExternalImageHandler {
         ExternalImage {
             uv: *uv,
             source: match *captured_data {
-                CapturedExternalImageData::NativeTexture(tid) => ExternalImageSource::NativeTexture(tid),
+                CapturedExternalImageData::NativeTexture(tid, None) => ExternalImageSource::NativeTexture(tid),
+                CapturedExternalImageData::NativeTexture(tid, Some(params)) => {
+                    ExternalImageSource::NativeTextureWithParams(tid, params)
+                }
                 CapturedExternalImageData::Buffer(ref arc) => ExternalImageSource::RawData(&*arc),
             }
         }
@@ -5695,6 +6516,68 @@ impl Renderer {
         }
     }
 
+    /// Save the contents of every texture cache texture into `root`. The
+    /// render backend has already written the entry manifest there.
+    #[cfg(feature = "capture")]
+    fn dump_texture_cache(&mut self, root: &PathBuf) {
+        self.device.begin_frame();
+        let _gm = self.gpu_profiler.start_marker("dump texture cache");
+        self.device.bind_read_target_impl(self.read_fbo, DeviceIntPoint::zero());
+
+        // A full disk or an unwritable directory only fails the dump.
+        if let Err(e) = self.write_texture_cache(root) {
+            error!("Unable to dump the texture cache to {:?}: {:?}", root, e);
+        }
+
+        self.device.reset_read_target();
+        self.device.end_frame();
+    }
+
+    #[cfg(feature = "capture")]
+    fn write_texture_cache(&mut self, root: &PathBuf) -> std::io::Result<()> {
+        use std::fs;
+        use std::io::Write;
+
+        let mut manifest = fs::File::create(root.join("textures.txt"))?;
+        writeln!(manifest, "# texture category width height format")?;
+
+        let mut ids: Vec<CacheTextureId> = self.caches.texture_resolver.texture_cache_map.keys().cloned().collect();
+        ids.sort_by_key(|id| id.0);
+
+        for id in ids {
+            let item = &self.caches.texture_resolver.texture_cache_map[&id];
+            let size = item.texture.get_dimensions();
+            let format = item.texture.get_format();
+
+            writeln!(
+                manifest,
+                "{} {:?} {} {} {:?}",
+                id.0, item.category, size.width, size.height, format,
+            )?;
+
+            let mut data = vec![0; (size.width * size.height * format.bytes_per_pixel()) as usize];
+            self.device.attach_read_texture(&item.texture);
+            self.device.read_pixels_into(
+                device_size_as_framebuffer_size(size).into(),
+                format,
+                &mut data,
+            );
+
+            #[cfg(feature = "png")]
+            CaptureConfig::try_save_png(
+                root.join(format!("texture-{}.png", id.0)),
+                size,
+                format,
+                None,
+                &data,
+            )?;
+            #[cfg(not(feature = "png"))]
+            fs::File::create(root.join(format!("texture-{}.raw", id.0)))?.write_all(&data)?;
+        }
+
+        Ok(())
+    }
+
     #[cfg(feature = "replay")]
     fn load_texture(
         target: ImageBufferKind,
@@ -5768,7 +6651,8 @@ impl Renderer {
                             }
                         }
                     }
-                    ExternalImageSource::NativeTexture(gl_id) => {
+                    ExternalImageSource::NativeTexture(gl_id) |
+                    ExternalImageSource::NativeTextureWithParams(gl_id, _) => {
                         let tex_id = tex_map.len() + 1;
                         match tex_map.entry(gl_id) {
                             Entry::Occupied(e) => {
@@ -5806,10 +6690,15 @@ impl Renderer {
                         &bytes,
                     );
                 }
+                let params = match ext_image.source {
+                    ExternalImageSource::NativeTextureWithParams(_, params) => Some(params),
+                    _ => None,
+                };
                 let plain = PlainExternalImage {
                     data: short_path,
                     external: def.external,
                     uv: ext_image.uv,
+                    params,
                 };
                 config.serialize_for_resource(&plain, &def.short_path);
             }
@@ -5833,15 +6722,15 @@ impl Renderer {
             let mut plain_self = PlainRenderer {
                 device_size: self.device_size,
                 gpu_cache: Self::save_texture(
-                    self.gpu_cache_texture.get_texture(),
+                    self.caches.gpu_cache_texture.get_texture(),
                     None, "gpu", &root, &mut self.device,
                 ),
-                gpu_cache_frame_id: self.gpu_cache_frame_id,
+                gpu_cache_frame_id: self.caches.gpu_cache_frame_id,
                 textures: FastHashMap::default(),
             };
 
             info!("saving cached textures");
-            for (id, item) in &self.texture_resolver.texture_cache_map {
+            for (id, item) in &self.caches.texture_resolver.texture_cache_map {
                 let file_name = format!("cache-{}", plain_self.textures.len() + 1);
                 info!("\t{}", file_name);
                 let plain = Self::save_texture(&item.texture, Some(item.category), &file_name, &root, &mut self.device);
@@ -5874,7 +6763,7 @@ impl Renderer {
         use std::{fs::File, io::Read};
 
         info!("loading external buffer-backed images");
-        assert!(self.texture_resolver.external_images.is_empty());
+        assert!(self.caches.texture_resolver.external_images.is_empty());
         let mut raw_map = FastHashMap::<String, Arc<Vec<u8>>>::default();
         let mut image_handler = DummyExternalImageHandler {
             data: FastHashMap::default(),
@@ -5939,19 +6828,19 @@ impl Renderer {
                     }
                 };
 
-                let value = (CapturedExternalImageData::NativeTexture(tid), plain_ext.uv);
+                let value = (CapturedExternalImageData::NativeTexture(tid, plain_ext.params), plain_ext.uv);
                 image_handler.data.insert(key, value);
             }
         }
 
         self.device.begin_frame();
-        self.gpu_cache_texture.remove_texture(&mut self.device);
+        self.caches.gpu_cache_texture.remove_texture(&mut self.device);
 
         if let Some(renderer) = config.deserialize_for_resource::<PlainRenderer, _>("renderer") {
             info!("loading cached textures");
             self.device_size = renderer.device_size;
 
-            for (_id, item) in self.texture_resolver.texture_cache_map.drain() {
+            for (_id, item) in self.caches.texture_resolver.texture_cache_map.drain() {
                 self.device.delete_texture(item.texture);
             }
             for (id, texture) in renderer.textures {
@@ -5964,7 +6853,7 @@ impl Renderer {
                     &root,
                     &mut self.device
                 );
-                self.texture_resolver.texture_cache_map.insert(id, CacheTexture {
+                self.caches.texture_resolver.texture_cache_map.insert(id, CacheTexture {
                     texture: t.0,
                     category: texture.category.unwrap_or(TextureCacheCategory::Standalone),
                 });
@@ -5978,12 +6867,12 @@ impl Renderer {
                 &root,
                 &mut self.device,
             );
-            self.gpu_cache_texture.load_from_data(t, gpu_cache_data);
-            self.gpu_cache_frame_id = renderer.gpu_cache_frame_id;
+            self.caches.gpu_cache_texture.load_from_data(t, gpu_cache_data);
+            self.caches.gpu_cache_frame_id = renderer.gpu_cache_frame_id;
         } else {
             info!("loading cached textures");
             self.device.begin_frame();
-            for (_id, item) in self.texture_resolver.texture_cache_map.drain() {
+            for (_id, item) in self.caches.texture_resolver.texture_cache_map.drain() {
                 self.device.delete_texture(item.texture);
             }
         }
@@ -6000,6 +6889,84 @@ enum FramebufferKind {
     Other,
 }
 
+/// The image that an external image which failed to lock is resolved to: the
+/// given 1x1 transparent placeholder texture, or an invalid image if there is
+/// no placeholder for the image's texture kind.
+fn failed_lock_external_image(placeholder: Option<gl::GLuint>) -> ExternalImage<'static> {
+    match placeholder {
+        Some(texture_id) => ExternalImage {
+            uv: TexelRect::new(0.0, 0.0, 1.0, 1.0),
+            source: ExternalImageSource::NativeTexture(texture_id),
+        },
+        None => ExternalImage {
+            uv: TexelRect::invalid(),
+            source: ExternalImageSource::Invalid,
+        },
+    }
+}
+
+/// Locks an external image, giving up after `timeout` if one is set.
+fn lock_external_image(
+    handler: &mut Box<dyn ExternalImageHandler>,
+    timeout: Option<Duration>,
+    id: ExternalImageId,
+    channel_index: u8,
+) -> Result<ExternalImage, ExternalImageLockError> {
+    match timeout {
+        Some(timeout) => handler.try_lock(id, channel_index, timeout),
+        None => Ok(handler.lock(id, channel_index)),
+    }
+}
+
+/// Returns the texture of an external image backed by a native texture, and
+/// whether its wrap mode must be clamped to edge while it is drawn.
+fn native_external_texture(
+    image: &ExternalImage,
+    target: ImageBufferKind,
+    rendering: ImageRendering,
+) -> Option<(ExternalTexture, bool)> {
+    match image.source {
+        ExternalImageSource::NativeTexture(texture_id) => {
+            let texture = ExternalTexture::new(texture_id, target, image.uv, rendering);
+            Some((texture, false))
+        }
+        ExternalImageSource::NativeTextureWithParams(texture_id, params) => {
+            let texture = ExternalTexture::new(
+                texture_id,
+                target,
+                params.texel_uv(image.uv),
+                params.filter.unwrap_or(rendering),
+            );
+            Some((texture, params.clamp_to_edge))
+        }
+        ExternalImageSource::RawData(..) |
+        ExternalImageSource::Invalid => None,
+    }
+}
+
+/// Returns the dirty rects of the tiles of a frame in device space, clamped
+/// to the frame buffer rect.
+fn tile_dirty_rects<'a>(
+    composite_state: &'a CompositeState,
+    fb_rect: &'a DeviceRect,
+) -> impl Iterator<Item = DeviceRect> + 'a {
+    composite_state.tiles
+        .iter()
+        .filter(|tile| tile.kind != TileKind::Clear)
+        .filter_map(move |tile| {
+            let dirty_rect = composite_state.get_device_rect(
+                &tile.local_dirty_rect,
+                tile.transform_index,
+            );
+
+            // In pathological cases where a tile is extremely zoomed, it
+            // may end up with device coords outside the range of an i32,
+            // so clamp it to the frame buffer rect here, before it gets
+            // casted to an i32 rect.
+            dirty_rect.intersection(fb_rect)
+        })
+}
+
 fn should_skip_batch(kind: &BatchKind, flags: DebugFlags) -> bool {
     match kind {
         BatchKind::TextRun(_) => {
@@ -6069,4 +7036,62 @@ mod tests {
         assert_eq!(tracker.get_damage_rect(3), Some(combined));
         assert_eq!(tracker.get_damage_rect(4), None);
     }
+
+    #[test]
+    fn test_native_external_texture_params() {
+        use super::native_external_texture;
+        use api::{ExternalImage, ExternalImageSource, ExternalTextureParams};
+        use api::{ImageBufferKind, ImageRendering};
+        use api::units::{DeviceSize, TexelRect};
+
+        let uv = TexelRect::new(0.0, 0.0, 1.0, 0.5);
+        let target = ImageBufferKind::TextureRect;
+
+        let image = ExternalImage {
+            uv,
+            source: ExternalImageSource::NativeTexture(1),
+        };
+        let (texture, clamp_to_edge) = native_external_texture(&image, target, ImageRendering::Auto).unwrap();
+        assert_eq!(texture.get_uv_rect(), uv);
+        assert!(!clamp_to_edge);
+
+        let params = ExternalTextureParams {
+            filter: Some(ImageRendering::Pixelated),
+            clamp_to_edge: true,
+            normalized_uv_size: Some(DeviceSize::new(64.0, 32.0)),
+        };
+        let image = ExternalImage {
+            uv,
+            source: ExternalImageSource::NativeTextureWithParams(1, params),
+        };
+        let (texture, clamp_to_edge) = native_external_texture(&image, target, ImageRendering::Auto).unwrap();
+        assert_eq!(texture.get_uv_rect(), TexelRect::new(0.0, 0.0, 64.0, 16.0));
+        assert!(clamp_to_edge);
+
+        let image = ExternalImage {
+            uv,
+            source: ExternalImageSource::Invalid,
+        };
+        assert!(native_external_texture(&image, target, ImageRendering::Auto).is_none());
+    }
+
+    #[test]
+    fn test_failed_lock_external_image() {
+        use super::{failed_lock_external_image, native_external_texture};
+        use api::{ExternalImageSource, ImageBufferKind, ImageRendering};
+        use api::units::TexelRect;
+
+        let target = ImageBufferKind::Texture2D;
+
+        // Images that fail to lock sample the whole transparent placeholder.
+        let image = failed_lock_external_image(Some(7));
+        assert!(matches!(image.source, ExternalImageSource::NativeTexture(7)));
+        let (texture, clamp_to_edge) = native_external_texture(&image, target, ImageRendering::Auto).unwrap();
+        assert_eq!(texture.get_uv_rect(), TexelRect::new(0.0, 0.0, 1.0, 1.0));
+        assert!(!clamp_to_edge);
+
+        let image = failed_lock_external_image(None);
+        assert!(matches!(image.source, ExternalImageSource::Invalid));
+        assert!(native_external_texture(&image, target, ImageRendering::Auto).is_none());
+    }
 }
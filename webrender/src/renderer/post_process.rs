@@ -0,0 +1,166 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! An embedder supplied fragment shader applied to the composited frame
+//! before it is presented, see `Renderer::set_post_process_shader`.
+
+use api::{ImageBufferKind, ImageFormat, PostProcessParams};
+use api::units::*;
+use crate::device::{Device, DrawTarget, Program, ReadTarget, ShaderError, Texture, TextureFilter};
+use crate::device::{VertexAttribute, VertexAttributeKind, VertexDescriptor, VertexUsageHint, VAO};
+use crate::internal_types::{RenderTargetInfo, Swizzle};
+use crate::renderer::TextureSampler;
+use gleam::gl::GlType;
+
+const DESC_POST_PROCESS: VertexDescriptor = VertexDescriptor {
+    vertex_attributes: &[
+        VertexAttribute {
+            name: "aPosition",
+            count: 2,
+            kind: VertexAttributeKind::F32,
+        },
+    ],
+    instance_attributes: &[],
+};
+
+const VERTEX_SHADER: &str = "
+in vec2 aPosition;
+out vec2 vUv;
+
+void main() {
+    vUv = aPosition;
+    gl_Position = vec4(aPosition * 2.0 - 1.0, 0.0, 1.0);
+}
+";
+
+/// Declarations available to the embedder's source, which has to define
+/// `vec4 post_process(vec2 uv)` returning the premultiplied output color.
+const FRAGMENT_SHADER_PREFIX: &str = "
+uniform sampler2D sFrame;
+uniform vec2 uTextureSize;
+uniform vec4 uParams[4];
+in vec2 vUv;
+out vec4 oFragColor;
+";
+
+const FRAGMENT_SHADER_MAIN: &str = "
+void main() {
+    oFragColor = post_process(vUv);
+}
+";
+
+#[repr(C)]
+struct PostProcessVertex {
+    x: f32,
+    y: f32,
+}
+
+pub struct PostProcessPass {
+    program: Program,
+    vao: VAO,
+    /// A copy of the composited frame, sampled by the program.
+    frame_texture: Option<Texture>,
+}
+
+impl PostProcessPass {
+    /// Compiles the embedder's fragment shader source. Must be called inside
+    /// a frame.
+    pub fn new(device: &mut Device, source: &str) -> Result<Self, ShaderError> {
+        let header = match device.gl().get_type() {
+            GlType::Gl => "#version 150\n",
+            GlType::Gles => "#version 300 es\nprecision highp float;\n",
+        };
+        let vs_source = format!("{}{}", header, VERTEX_SHADER);
+        let fs_source = format!(
+            "{}{}{}{}",
+            header,
+            FRAGMENT_SHADER_PREFIX,
+            source,
+            FRAGMENT_SHADER_MAIN,
+        );
+
+        let program = device.create_custom_program(
+            "post_process",
+            &vs_source,
+            &fs_source,
+            &DESC_POST_PROCESS,
+        )?;
+        device.bind_program(&program);
+        device.bind_shader_samplers(&program, &[("sFrame", TextureSampler::Color0)]);
+
+        let vao = device.create_vao(&DESC_POST_PROCESS, 1);
+        let vertices = [
+            PostProcessVertex { x: 0.0, y: 0.0 },
+            PostProcessVertex { x: 1.0, y: 0.0 },
+            PostProcessVertex { x: 0.0, y: 1.0 },
+            PostProcessVertex { x: 1.0, y: 1.0 },
+        ];
+        let indices: [u32; 6] = [0, 1, 2, 2, 1, 3];
+        device.bind_vao(&vao);
+        device.update_vao_indices(&vao, &indices, VertexUsageHint::Static);
+        device.update_vao_main_vertices(&vao, &vertices, VertexUsageHint::Static);
+
+        Ok(PostProcessPass {
+            program,
+            vao,
+            frame_texture: None,
+        })
+    }
+
+    /// Copies `fb_rect` of the default framebuffer and draws it back through
+    /// the embedder's shader.
+    pub fn apply(
+        &mut self,
+        device: &mut Device,
+        fb_rect: FramebufferIntRect,
+        draw_target: DrawTarget,
+        params: &PostProcessParams,
+    ) {
+        let size = fb_rect.size();
+        if size.is_empty() {
+            return;
+        }
+
+        if self.frame_texture.as_ref().map_or(false, |t| t.get_dimensions() != size.cast_unit()) {
+            device.delete_texture(self.frame_texture.take().unwrap());
+        }
+        let texture = self.frame_texture.get_or_insert_with(|| {
+            device.create_texture(
+                ImageBufferKind::Texture2D,
+                ImageFormat::BGRA8,
+                size.width,
+                size.height,
+                TextureFilter::Linear,
+                Some(RenderTargetInfo { has_depth: false }),
+            )
+        });
+
+        device.blit_render_target(
+            ReadTarget::Default,
+            fb_rect,
+            DrawTarget::from_texture(texture, false),
+            FramebufferIntRect::from_size(size),
+            TextureFilter::Nearest,
+        );
+
+        device.bind_draw_target(draw_target);
+        device.disable_depth();
+        device.disable_scissor();
+        device.set_blend(false);
+        device.bind_program(&self.program);
+        device.set_shader_texture_size(&self.program, size.to_f32().cast_unit());
+        device.set_shader_vec4_array(&self.program, "uParams", &params.values);
+        device.bind_texture(TextureSampler::Color0, texture, Swizzle::default());
+        device.bind_vao(&self.vao);
+        device.draw_triangles_u32(0, 6);
+    }
+
+    pub fn deinit(self, device: &mut Device) {
+        if let Some(texture) = self.frame_texture {
+            device.delete_texture(texture);
+        }
+        device.delete_program(self.program);
+        device.delete_vao(self.vao);
+    }
+}
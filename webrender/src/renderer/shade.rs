@@ -6,6 +6,7 @@ use api::{ImageBufferKind, units::DeviceSize};
 use crate::batch::{BatchKey, BatchKind, BrushBatchKind, BatchFeatures};
 use crate::composite::{CompositeFeatures, CompositeSurfaceFormat};
 use crate::device::{Device, Program, ShaderError};
+use crate::internal_types::FastHashMap;
 use euclid::default::Transform3D;
 use glyph_rasterizer::GlyphFormat;
 use crate::renderer::{
@@ -19,7 +20,10 @@ use gleam::gl::GlType;
 use time::precise_time_ns;
 
 use std::cell::RefCell;
+use std::fs;
+use std::path::PathBuf;
 use std::rc::Rc;
+use std::time::SystemTime;
 
 use webrender_build::shader::{ShaderFeatures, ShaderFeatureFlags, get_shader_features};
 
@@ -309,6 +313,15 @@ impl LazilyCompiledShader {
         Ok(program)
     }
 
+    /// Delete the program, so that it is compiled again from the current
+    /// shader sources the next time it is used.
+    fn reset(&mut self, device: &mut Device) {
+        if let Some(program) = self.program.take() {
+            device.delete_program(program);
+        }
+        self.cached_projection = Transform3D::identity();
+    }
+
     fn deinit(self, device: &mut Device) {
         if let Some(program) = self.program {
             device.delete_program(program);
@@ -462,6 +475,18 @@ impl BrushShader {
         }
     }
 
+    fn reset(&mut self, device: &mut Device) {
+        self.opaque.reset(device);
+        self.alpha.reset(device);
+        if let Some(ref mut advanced_blend) = self.advanced_blend {
+            advanced_blend.reset(device);
+        }
+        if let Some(ref mut dual_source) = self.dual_source {
+            dual_source.reset(device);
+        }
+        self.debug_overdraw.reset(device);
+    }
+
     fn deinit(self, device: &mut Device) {
         self.opaque.deinit(device);
         self.alpha.deinit(device);
@@ -546,12 +571,19 @@ impl TextShader {
             GlyphFormat::Alpha |
             GlyphFormat::Subpixel |
             GlyphFormat::Bitmap |
-            GlyphFormat::ColorBitmap => &mut self.simple,
+            GlyphFormat::ColorBitmap |
+            GlyphFormat::Sdf => &mut self.simple,
             GlyphFormat::TransformedAlpha |
             GlyphFormat::TransformedSubpixel => &mut self.glyph_transform,
         }
     }
 
+    fn reset(&mut self, device: &mut Device) {
+        self.simple.reset(device);
+        self.glyph_transform.reset(device);
+        self.debug_overdraw.reset(device);
+    }
+
     fn deinit(self, device: &mut Device) {
         self.simple.deinit(device);
         self.glyph_transform.deinit(device);
@@ -580,7 +612,7 @@ fn create_clip_shader(
 }
 
 // NB: If you add a new shader here, make sure to deinitialize it
-// in `Shaders::deinit()` below.
+// in `Shaders::deinit()` below, and to reset it in `Shaders::reset()`.
 pub struct Shaders {
     // These are "cache shaders". These shaders are used to
     // draw intermediate results to cache targets. The results
@@ -1253,6 +1285,55 @@ impl Shaders {
         }
     }
 
+    /// Delete all compiled programs, so that they are compiled again from the
+    /// current shader sources as they are used. This is used to reload shaders
+    /// from `resource_override_path` while the renderer is running.
+    pub fn reset(&mut self, device: &mut Device) {
+        for shader in self.cs_scale.iter_mut().flatten() {
+            shader.reset(device);
+        }
+        self.cs_blur_a8.reset(device);
+        self.cs_blur_rgba8.reset(device);
+        self.cs_svg_filter.reset(device);
+        self.brush_solid.reset(device);
+        self.brush_blend.reset(device);
+        self.brush_mix_blend.reset(device);
+        self.brush_linear_gradient.reset(device);
+        self.brush_opacity.reset(device);
+        self.brush_opacity_aa.reset(device);
+        self.cs_clip_rectangle_slow.reset(device);
+        self.cs_clip_rectangle_fast.reset(device);
+        self.cs_clip_box_shadow.reset(device);
+        self.cs_clip_image.reset(device);
+        self.ps_text_run.reset(device);
+        if let Some(ref mut shader) = self.ps_text_run_dual_source {
+            shader.reset(device);
+        }
+        for shader in self.brush_image.iter_mut().flatten() {
+            shader.reset(device);
+        }
+        for shader in self.brush_fast_image.iter_mut().flatten() {
+            shader.reset(device);
+        }
+        for shader in self.brush_yuv_image.iter_mut().flatten() {
+            shader.reset(device);
+        }
+        self.cs_border_solid.reset(device);
+        self.cs_fast_linear_gradient.reset(device);
+        self.cs_linear_gradient.reset(device);
+        self.cs_radial_gradient.reset(device);
+        self.cs_conic_gradient.reset(device);
+        self.cs_line_decoration.reset(device);
+        self.cs_border_segment.reset(device);
+        self.ps_split_composite.reset(device);
+        self.ps_quad_textured.reset(device);
+        self.ps_mask.reset(device);
+        self.ps_mask_fast.reset(device);
+        self.ps_clear.reset(device);
+        self.ps_copy.reset(device);
+        self.composite.reset(device);
+    }
+
     pub fn deinit(mut self, device: &mut Device) {
         for shader in self.cs_scale {
             if let Some(shader) = shader {
@@ -1463,6 +1544,18 @@ impl CompositorShaders {
         buffer_kind as usize
     }
 
+    fn reset(&mut self, device: &mut Device) {
+        for shader in self.rgba.iter_mut().flatten() {
+            shader.reset(device);
+        }
+        for shader in self.rgba_fast_path.iter_mut().flatten() {
+            shader.reset(device);
+        }
+        for shader in self.yuv.iter_mut().flatten() {
+            shader.reset(device);
+        }
+    }
+
     pub fn deinit(&mut self, device: &mut Device) {
         for shader in self.rgba.drain(..) {
             if let Some(shader) = shader {
@@ -1482,6 +1575,65 @@ impl CompositorShaders {
     }
 }
 
+/// Polls the shader override directory for modified shader sources, so that
+/// shaders can be reloaded while the renderer is running.
+pub struct ShaderOverrideWatcher {
+    path: PathBuf,
+    modified_times: FastHashMap<PathBuf, SystemTime>,
+    last_poll_time: u64,
+}
+
+impl ShaderOverrideWatcher {
+    /// The minimum time between two scans of the override directory.
+    const POLL_INTERVAL_NS: u64 = 250 * 1_000_000;
+
+    pub fn new(path: PathBuf) -> Self {
+        let mut watcher = ShaderOverrideWatcher {
+            path,
+            modified_times: FastHashMap::default(),
+            last_poll_time: precise_time_ns(),
+        };
+        watcher.scan(&mut Vec::new());
+        watcher
+    }
+
+    /// Add any shader sources that were modified since the last poll to `changed`.
+    pub fn poll(&mut self, changed: &mut Vec<PathBuf>) {
+        let now = precise_time_ns();
+        if now - self.last_poll_time < Self::POLL_INTERVAL_NS {
+            return;
+        }
+        self.last_poll_time = now;
+        self.scan(changed);
+    }
+
+    fn scan(&mut self, changed: &mut Vec<PathBuf>) {
+        let entries = match fs::read_dir(&self.path) {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!("Unable to watch shader overrides in {:?}: {}", self.path, err);
+                return;
+            }
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().map_or(true, |ext| ext != "glsl") {
+                continue;
+            }
+
+            let modified = match entry.metadata().and_then(|metadata| metadata.modified()) {
+                Ok(modified) => modified,
+                Err(..) => continue,
+            };
+
+            if self.modified_times.insert(path.clone(), modified) != Some(modified) {
+                changed.push(path);
+            }
+        }
+    }
+}
+
 fn get_shader_feature_flags(gl_type: GlType, texture_external_version: TextureExternalVersion) -> ShaderFeatureFlags {
     match gl_type {
         GlType::Gl => ShaderFeatureFlags::GL,
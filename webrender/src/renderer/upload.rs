@@ -26,7 +26,8 @@ use malloc_size_of::MallocSizeOfOps;
 use api::units::*;
 use api::{ExternalImageSource, ImageBufferKind, ImageFormat};
 use crate::renderer::{
-    Renderer, VertexArrayKind, RendererStats, TextureSampler, TEXTURE_CACHE_DBG_CLEAR_COLOR
+    Renderer, VertexArrayKind, RendererStats, TextureSampler, TEXTURE_CACHE_DBG_CLEAR_COLOR,
+    lock_external_image,
 };
 use crate::internal_types::{
     FastHashMap, TextureUpdateSource, Swizzle, TextureCacheUpdate,
@@ -84,22 +85,38 @@ pub fn upload_to_texture_cache(
     let num_updates = update_list.len();
 
     for (texture_id, updates) in update_list {
-        let texture = &renderer.texture_resolver.texture_cache_map[&texture_id].texture;
+        let texture = &renderer.caches.texture_resolver.texture_cache_map[&texture_id].texture;
         for update in updates {
             let TextureCacheUpdate { rect, stride, offset, format_override, source } = update;
             let mut arc_data = None; 
             let dummy_data;
+            let mut external_lock_failed = false;
             let data = match source {
                 TextureUpdateSource::Bytes { ref data } => {
                     arc_data = Some(data.clone());
                     &data[offset as usize ..]
                 }
                 TextureUpdateSource::External { id, channel_index } => {
+                    let (id, channel_index) = renderer.video_frames.resolve(id, channel_index);
                     let handler = renderer.external_image_handler
                         .as_mut()
                         .expect("Found external image, but no handler set!");
+                    let source = match lock_external_image(
+                        handler,
+                        renderer.external_image_lock_timeout,
+                        id,
+                        channel_index,
+                    ) {
+                        Ok(image) => image.source,
+                        Err(error) => {
+                            warn!("Failed to lock ext-image {:?}: {:?}", id, error);
+                            renderer.notifier.external_image_lock_failed(id, channel_index, error);
+                            external_lock_failed = true;
+                            ExternalImageSource::Invalid
+                        }
+                    };
                     // The filter is only relevant for NativeTexture external images.
-                    match handler.lock(id, channel_index).source {
+                    match source {
                         ExternalImageSource::RawData(data) => {
                             &data[offset as usize ..]
                         }
@@ -109,11 +126,15 @@ pub fn upload_to_texture_cache(
                             let width = stride.unwrap_or(rect.width() * bpp);
                             let total_size = width * rect.height();
                             // WR haven't support RGBAF32 format in texture_cache, so
-                            // we use u8 type here.
-                            dummy_data = vec![0xFFu8; total_size as usize];
+                            // we use u8 type here. Images that failed to lock are
+                            // left transparent, so that a video frame that isn't
+                            // available in time doesn't flash white.
+                            let fill = if external_lock_failed { 0 } else { 0xFFu8 };
+                            dummy_data = vec![fill; total_size as usize];
                             &dummy_data
                         }
-                        ExternalImageSource::NativeTexture(eid) => {
+                        ExternalImageSource::NativeTexture(eid) |
+                        ExternalImageSource::NativeTextureWithParams(eid, _) => {
                             panic!("Unexpected external texture {:?} for the texture cache update of {:?}", eid, id);
                         }
                     }
@@ -191,6 +212,10 @@ pub fn upload_to_texture_cache(
             }
 
             if let TextureUpdateSource::External { id, channel_index } = source {
+                if external_lock_failed {
+                    continue;
+                }
+                let (id, channel_index) = renderer.video_frames.resolve(id, channel_index);
                 let handler = renderer.external_image_handler
                     .as_mut()
                     .expect("Found external image, but no handler set!");
@@ -511,7 +536,7 @@ fn copy_from_staging_to_cache(
     batch_upload_copies: Vec<BatchUploadCopy>,
 ) {
     for copy in batch_upload_copies {
-        let dest_texture = &renderer.texture_resolver.texture_cache_map[&copy.dest_texture_id].texture;
+        let dest_texture = &renderer.caches.texture_resolver.texture_cache_map[&copy.dest_texture_id].texture;
 
         renderer.device.copy_texture_sub_region(
             &batch_upload_textures[copy.src_texture_index],
@@ -562,7 +587,7 @@ fn copy_from_staging_to_cache_using_draw_calls(
         }
 
         if dst_changed {
-            let dest_texture = &renderer.texture_resolver.texture_cache_map[&copy.dest_texture_id].texture;
+            let dest_texture = &renderer.caches.texture_resolver.texture_cache_map[&copy.dest_texture_id].texture;
             dst_texture_size = dest_texture.get_dimensions().to_f32();
 
             let draw_target = DrawTarget::from_texture(dest_texture, false);
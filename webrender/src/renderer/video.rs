@@ -0,0 +1,154 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Queues of timestamped video frames attached to external images.
+//!
+//! Instead of exposing a single frame through an external image and guessing
+//! when it will reach the screen, the embedder can enqueue several decoded frames
+//! ahead of time. Each time the renderer composites with a known vsync time, it
+//! selects the most recent frame that is due by that vsync and substitutes it for
+//! the external image when locking it.
+
+use api::{ExternalImageId, VideoFrame};
+use crate::internal_types::FastHashMap;
+use std::collections::VecDeque;
+
+/// Describes a change of the frame presented by a video frame queue.
+#[derive(Clone, Debug)]
+pub struct VideoFrameReport {
+    /// The external image the queue is attached to.
+    pub queue_id: ExternalImageId,
+    /// The frame that is now being presented.
+    pub presented: VideoFrame,
+    /// Frames that were due but got replaced by a more recent one before
+    /// being presented. They can be recycled by the embedder.
+    pub dropped: Vec<VideoFrame>,
+    /// Whether the presented frame was already due at the previous vsync.
+    pub late: bool,
+}
+
+struct VideoFrameQueue {
+    /// Frames waiting to be presented, sorted by presentation time.
+    pending: VecDeque<VideoFrame>,
+    /// The frame currently substituted for the queue's external image.
+    current: Option<VideoFrame>,
+}
+
+#[derive(Default)]
+pub(super) struct VideoFrameQueues {
+    queues: FastHashMap<ExternalImageId, VideoFrameQueue>,
+    /// The vsync time used for the previous selection.
+    last_vsync_time: Option<u64>,
+}
+
+impl VideoFrameQueues {
+    pub fn enqueue<I>(&mut self, queue_id: ExternalImageId, frames: I)
+    where
+        I: IntoIterator<Item = VideoFrame>,
+    {
+        let queue = self.queues.entry(queue_id).or_insert_with(|| VideoFrameQueue {
+            pending: VecDeque::new(),
+            current: None,
+        });
+
+        for frame in frames {
+            let index = queue.pending
+                .iter()
+                .position(|pending| pending.presentation_time > frame.presentation_time)
+                .unwrap_or(queue.pending.len());
+            queue.pending.insert(index, frame);
+        }
+    }
+
+    /// Removes a queue, returning the frames it still references.
+    pub fn remove(&mut self, queue_id: ExternalImageId) -> Vec<VideoFrame> {
+        match self.queues.remove(&queue_id) {
+            Some(queue) => queue.current.into_iter().chain(queue.pending).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Selects, for each queue, the most recent frame due by `vsync_time`.
+    pub fn select_frames(&mut self, vsync_time: u64) -> Vec<VideoFrameReport> {
+        let mut reports = Vec::new();
+
+        for (queue_id, queue) in &mut self.queues {
+            let mut selected = None;
+            let mut dropped = Vec::new();
+
+            while queue.pending.front().map_or(false, |frame| frame.presentation_time <= vsync_time) {
+                let frame = queue.pending.pop_front().unwrap();
+                if let Some(previous) = selected.replace(frame) {
+                    dropped.push(previous);
+                }
+            }
+
+            if let Some(presented) = selected {
+                let late = self.last_vsync_time.map_or(false, |last| presented.presentation_time <= last);
+                if late {
+                    warn!("Video frame for {:?} presented late", queue_id);
+                }
+                queue.current = Some(presented);
+                reports.push(VideoFrameReport {
+                    queue_id: *queue_id,
+                    presented,
+                    dropped,
+                    late,
+                });
+            }
+        }
+
+        self.last_vsync_time = Some(vsync_time);
+
+        reports
+    }
+
+    /// Maps an external image to the frame its queue currently presents, if any.
+    pub fn resolve(&self, id: ExternalImageId, channel_index: u8) -> (ExternalImageId, u8) {
+        match self.queues.get(&id).and_then(|queue| queue.current) {
+            Some(frame) => (frame.id, frame.channel_index),
+            None => (id, channel_index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(id: u64, presentation_time: u64) -> VideoFrame {
+        VideoFrame {
+            id: ExternalImageId(id),
+            channel_index: 0,
+            presentation_time,
+        }
+    }
+
+    #[test]
+    fn test_select_and_resolve_frames() {
+        let queue_id = ExternalImageId(1);
+        let mut queues = VideoFrameQueues::default();
+        queues.enqueue(queue_id, vec![frame(12, 20), frame(10, 0), frame(11, 10)]);
+
+        // Until a frame is due, the queue's own image is used.
+        assert_eq!(queues.resolve(queue_id, 0), (queue_id, 0));
+
+        // The most recent due frame is presented, older ones are dropped.
+        let reports = queues.select_frames(15);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].presented, frame(11, 10));
+        assert_eq!(reports[0].dropped, vec![frame(10, 0)]);
+        assert_eq!(queues.resolve(queue_id, 0), (ExternalImageId(11), 0));
+
+        // Nothing new is due, so the presented frame doesn't change and
+        // nothing has to be invalidated.
+        assert!(queues.select_frames(16).is_empty());
+        assert_eq!(queues.resolve(queue_id, 0), (ExternalImageId(11), 0));
+
+        let reports = queues.select_frames(30);
+        assert_eq!(reports[0].presented, frame(12, 20));
+        assert!(!reports[0].late);
+        assert_eq!(queues.resolve(queue_id, 0), (ExternalImageId(12), 0));
+    }
+}
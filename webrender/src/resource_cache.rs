@@ -3,14 +3,18 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use api::{BlobImageRequest, RasterizedBlobImage, ImageFormat, ImageDescriptorFlags};
-use api::{DebugFlags, FontInstanceKey, FontKey, FontTemplate, GlyphIndex};
+use api::{DebugFlags, FontInstanceKey, FontKey, FontTemplate, GlyphIndex, MissingGlyph, ParsedFontInfo};
+use api::{DocumentId, ResourceError};
 use api::{ExternalImageData, ExternalImageType, ExternalImageId, BlobImageResult};
-use api::{DirtyRect, GlyphDimensions, IdNamespace, DEFAULT_TILE_SIZE};
+use api::{DirtyRect, GlyphDimensions, IdNamespace, PathCommand, DEFAULT_TILE_SIZE};
 use api::{ColorF, ImageData, ImageDescriptor, ImageKey, ImageRendering, TileSize};
-use api::{BlobImageHandler, BlobImageKey, VoidPtrToSizeFn};
+use api::{BlobImageHandler, BlobImageKey, NotificationRequest, VoidPtrToSizeFn};
 use api::units::*;
 use euclid::size2;
-use crate::{render_api::{ClearCache, AddFont, ResourceUpdate, MemoryReport}, util::WeakTable};
+use crate::{render_api::{ClearCache, AddFont, ResourceUpdate, MemoryReport, UnusedResources}, util::WeakTable};
+use crate::render_api::{NamespaceResources, RegisteredImage, RegisteredBlobImage};
+use crate::render_api::{RegisteredFont, RegisteredFontInstance};
+use crate::render_api::{MemoryPressureLevel, MemoryPressureReport, MemoryPressureTargets};
 use crate::image_tiling::{compute_tile_size, compute_tile_range};
 #[cfg(feature = "capture")]
 use crate::capture::ExternalCaptureImage;
@@ -23,6 +27,7 @@ use crate::device::TextureFilter;
 use crate::glyph_cache::{GlyphCache, CachedGlyphInfo};
 use crate::glyph_cache::GlyphCacheEntry;
 use glyph_rasterizer::{GLYPH_FLASHING, FontInstance, GlyphFormat, GlyphKey, GlyphRasterizer, GlyphRasterJob};
+use glyph_rasterizer::{GlyphInk, GlyphRequestPriority};
 use glyph_rasterizer::{SharedFontResources, BaseFontInstance};
 use crate::gpu_cache::{GpuCache, GpuCacheAddress, GpuCacheHandle};
 use crate::gpu_types::UvRectKind;
@@ -37,6 +42,7 @@ use crate::render_task_cache::{RenderTaskCacheEntry, RenderTaskCacheEntryHandle}
 use crate::renderer::GpuBufferBuilder;
 use crate::surface::SurfaceBuilder;
 use euclid::point2;
+use malloc_size_of::MallocSizeOfOps;
 use smallvec::SmallVec;
 use std::collections::hash_map::Entry::{self, Occupied, Vacant};
 use std::collections::hash_map::{Iter, IterMut};
@@ -52,6 +58,7 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 use std::u32;
 use crate::texture_cache::{TextureCache, TextureCacheHandle, Eviction, TargetShader};
 use crate::picture_textures::PictureTextures;
+use crate::prim_store::vector_path::VectorPathCacheKey;
 use peek_poke::PeekPoke;
 
 // Counter for generating unique native surface ids
@@ -191,6 +198,50 @@ struct ImageResource {
     /// but with only a visible sub-set that is valid at a given time.
     visible_rect: DeviceIntRect,
     generation: ImageGeneration,
+    /// The frames that last used this image, see
+    /// `ResourceCache::unused_resources`.
+    usage: ResourceUsage,
+}
+
+/// The last frame of each document that used an image or font instance, in
+/// terms of that document's `ResourceCache::frames_built`.
+#[derive(Clone, Debug, Default)]
+struct ResourceUsage {
+    documents: SmallVec<[(DocumentId, u64); 1]>,
+}
+
+impl ResourceUsage {
+    fn mark_used(&mut self, document_id: DocumentId, frame: u64) {
+        match self.documents.iter_mut().find(|(id, _)| *id == document_id) {
+            Some((_, last_used)) => *last_used = frame,
+            None => self.documents.push((document_id, frame)),
+        }
+    }
+
+    /// Whether `document_id` hasn't used the resource since its frame
+    /// `since`, and no other document used it in its latest frame.
+    fn is_unused(
+        &self,
+        document_id: DocumentId,
+        since: u64,
+        frames_built: &FastHashMap<DocumentId, u64>,
+    ) -> bool {
+        self.documents.iter().all(|&(id, frame)| {
+            if id == document_id {
+                frame < since
+            } else {
+                frames_built.get(&id).map_or(true, |&built| frame < built)
+            }
+        })
+    }
+
+    /// The fewest frames any live document built since it used the resource.
+    fn frames_since_use(&self, frames_built: &FastHashMap<DocumentId, u64>) -> Option<u64> {
+        self.documents
+            .iter()
+            .filter_map(|&(id, frame)| frames_built.get(&id)?.checked_sub(frame))
+            .min()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -227,14 +278,56 @@ impl ImageTemplates {
 struct CachedImageInfo {
     texture_cache_handle: TextureCacheHandle,
     dirty_rect: ImageDirtyRect,
+    /// The individual rects making up a partial `dirty_rect`, if known.
+    /// Empty when the whole `dirty_rect` must be uploaded at once.
+    dirty_rects: Vec<DeviceIntRect>,
     manual_eviction: bool,
 }
 
+/// Past this many pending dirty rects, an image entry falls back to
+/// uploading their bounding box.
+const MAX_DIRTY_RECTS_PER_IMAGE: usize = 16;
+
+/// A pooled render target is reused for a smaller request as long as its area
+/// is at most this many times the requested area.
+const MAX_RENDER_TARGET_AREA_RATIO: usize = 2;
+
 impl CachedImageInfo {
+    fn new() -> Self {
+        CachedImageInfo {
+            texture_cache_handle: TextureCacheHandle::invalid(),
+            dirty_rect: DirtyRect::All,
+            dirty_rects: Vec::new(),
+            manual_eviction: false,
+        }
+    }
+
     fn mark_unused(&mut self, texture_cache: &mut TextureCache) {
         texture_cache.evict_handle(&self.texture_cache_handle);
         self.manual_eviction = false;
     }
+
+    fn add_dirty_region(&mut self, dirty_rect: &ImageDirtyRect, rects: &[DeviceIntRect]) {
+        if dirty_rect.is_empty() {
+            return;
+        }
+
+        // The list of rects is only kept while every update since the last
+        // upload provided one.
+        let was_clean = self.dirty_rect.is_empty();
+        self.dirty_rect = self.dirty_rect.union(dirty_rect);
+        match self.dirty_rect {
+            DirtyRect::Partial(..) if !rects.is_empty() && (was_clean || !self.dirty_rects.is_empty()) => {
+                self.dirty_rects.extend(rects.iter().filter(|rect| !rect.is_empty()));
+                if self.dirty_rects.len() > MAX_DIRTY_RECTS_PER_IMAGE {
+                    self.dirty_rects.clear();
+                }
+            }
+            _ => {
+                self.dirty_rects.clear();
+            }
+        }
+    }
 }
 
 #[cfg(debug_assertions)]
@@ -414,6 +507,10 @@ struct Resources {
 // for internal font instances we should change the hash key accordingly.
 pub type GlyphDimensionsCache = FastHashMap<(FontInstanceKey, GlyphIndex), Option<GlyphDimensions>>;
 
+// Like the glyph dimensions, the ink of glyphs is only needed for the font
+// instances of the API (to skip it with line decorations).
+type GlyphInkCache = FastHashMap<(FontInstanceKey, GlyphIndex), Option<GlyphInk>>;
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct BlobImageRasterizerEpoch(usize);
 
@@ -449,6 +546,8 @@ pub struct ResourceCache {
     cached_glyphs: GlyphCache,
     cached_images: ImageCache,
     cached_render_tasks: RenderTaskCache,
+    /// Vector paths rasterized in to the texture cache.
+    cached_vector_paths: FastHashMap<VectorPathCacheKey, TextureCacheHandle>,
 
     resources: Resources,
     state: State,
@@ -465,6 +564,7 @@ pub struct ResourceCache {
 
     /// TODO(gw): We should expire (parts of) this cache semi-regularly!
     cached_glyph_dimensions: GlyphDimensionsCache,
+    cached_glyph_ink: GlyphInkCache,
     glyph_rasterizer: GlyphRasterizer,
 
     /// The set of images that aren't present or valid in the texture cache,
@@ -489,8 +589,24 @@ pub struct ResourceCache {
     image_templates_memory: usize,
     font_templates_memory: usize,
 
+    /// The glyphs rasterized since the last call to `take_missing_glyphs`
+    /// that their font can't render, with shared font keys.
+    missing_glyphs: Vec<MissingGlyph>,
+
+    /// The fonts the glyph rasterizer failed to load that weren't reported to
+    /// their namespace yet, with shared font keys.
+    failed_fonts: Vec<FontKey>,
+
     /// A pool of render targets for use by the render task graph
     render_target_pool: Vec<RenderTarget>,
+
+    /// Counts the frames built by each document, which is what resource
+    /// usage is recorded against.
+    frames_built: FastHashMap<DocumentId, u64>,
+    /// The document whose frame is being built.
+    current_document: Option<DocumentId>,
+    /// The frames that last used each shared font instance key.
+    font_instance_usage: FastHashMap<FontInstanceKey, ResourceUsage>,
 }
 
 impl ResourceCache {
@@ -506,12 +622,14 @@ impl ResourceCache {
             cached_glyphs,
             cached_images: ResourceClassCache::new(),
             cached_render_tasks: RenderTaskCache::new(),
+            cached_vector_paths: FastHashMap::default(),
             resources: Resources {
                 fonts,
                 image_templates: ImageTemplates::default(),
                 weak_fonts: WeakTable::new(),
             },
             cached_glyph_dimensions: FastHashMap::default(),
+            cached_glyph_ink: FastHashMap::default(),
             texture_cache,
             picture_textures,
             state: State::Idle,
@@ -527,7 +645,12 @@ impl ResourceCache {
             capture_dirty: true,
             image_templates_memory: 0,
             font_templates_memory: 0,
+            missing_glyphs: Vec::new(),
+            failed_fonts: Vec::new(),
             render_target_pool: Vec::new(),
+            frames_built: FastHashMap::default(),
+            current_document: None,
+            font_instance_usage: FastHashMap::default(),
         }
     }
 
@@ -619,6 +742,45 @@ impl ResourceCache {
         ).expect("Failed to request a render task from the resource cache!")
     }
 
+    /// Requests a vector path to be drawn this frame, rasterizing it in to the
+    /// texture cache if it isn't there already. Solid paths are rasterized as
+    /// coverage masks, in R8 when the texture cache supports it.
+    pub fn request_vector_path(
+        &mut self,
+        key: VectorPathCacheKey,
+        gpu_cache: &mut GpuCache,
+    ) -> TextureCacheHandle {
+        let texture_cache = &mut self.texture_cache;
+        let handle = self.cached_vector_paths.entry(key.clone()).or_insert_with(TextureCacheHandle::invalid);
+        if texture_cache.request(handle, gpu_cache) {
+            let format = match key.shape.gradient {
+                None => texture_cache.shared_alpha_expected_format(),
+                Some(..) => texture_cache.shared_color_expected_format(),
+            };
+            let pixels = key.rasterize(format);
+            texture_cache.update(
+                handle,
+                ImageDescriptor {
+                    size: key.size,
+                    stride: None,
+                    format,
+                    flags: ImageDescriptorFlags::empty(),
+                    offset: 0,
+                },
+                TextureFilter::Linear,
+                Some(CachedImageData::Raw(Arc::new(pixels))),
+                [0.0; 4],
+                DirtyRect::All,
+                gpu_cache,
+                None,
+                UvRectKind::Rect,
+                Eviction::Auto,
+                TargetShader::Default,
+            );
+        }
+        handle.clone()
+    }
+
     pub fn post_scene_building_update(
         &mut self,
         updates: Vec<ResourceUpdate>,
@@ -650,7 +812,13 @@ impl ResourceCache {
                     profile.set(profiler::IMAGE_TEMPLATES, self.resources.image_templates.images.len());
                 }
                 ResourceUpdate::UpdateImage(img) => {
-                    self.update_image_template(img.key, img.descriptor, img.data.into(), &img.dirty_rect);
+                    self.update_image_template(
+                        img.key,
+                        img.descriptor,
+                        img.data.into(),
+                        &img.dirty_rect,
+                        &img.dirty_rects,
+                    );
                 }
                 ResourceUpdate::AddBlobImage(img) => {
                     self.add_image_template(
@@ -669,6 +837,7 @@ impl ResourceCache {
                         &to_image_dirty_rect(
                             &img.dirty_rect
                         ),
+                        &[],
                     );
                     self.discard_tiles_outside_visible_area(img.key, &img.visible_rect); // TODO: remove?
                     self.set_image_visible_rect(img.key.as_image(), &img.visible_rect);
@@ -694,6 +863,7 @@ impl ResourceCache {
                 ResourceUpdate::DeleteFontInstance(font) => {
                     if let Some(shared_key) = self.resources.fonts.instance_keys.delete_key(&font) {
                         self.delete_font_instance(shared_key);
+                        self.font_instance_usage.remove(&shared_key);
                     }
                     if let Some(ref mut handler) = &mut self.blob_image_handler {
                         handler.delete_font_instance(font);
@@ -706,23 +876,33 @@ impl ResourceCache {
                 ResourceUpdate::AddFont(font) => {
                     // The shared key was already added in ApiResources, but the first time it is
                     // seen on the backend we still need to do some extra initialization here.
-                    let (key, template) = match font {
+                    let (key, template, parsed) = match font {
                         AddFont::Raw(key, bytes, index) => {
-                            (key, FontTemplate::Raw(bytes, index))
+                            (key, FontTemplate::Raw(bytes, index), None)
+                        }
+                        AddFont::Parsed(key, bytes, index, info) => {
+                            (key, FontTemplate::Raw(bytes, index), Some(info))
+                        }
+                        AddFont::File(key, path, index) => {
+                            (key, FontTemplate::File { path, index }, None)
                         }
                         AddFont::Native(key, native_font_handle) => {
-                            (key, FontTemplate::Native(native_font_handle))
+                            (key, FontTemplate::Native(native_font_handle), None)
                         }
                     };
                     let shared_key = self.resources.fonts.font_keys.map_key(&key);
                     if !self.glyph_rasterizer.has_font(shared_key) {
-                        self.add_font_template(shared_key, template);
+                        self.add_font_template(shared_key, template, parsed);
                         profile.set(profiler::FONT_TEMPLATES, self.resources.fonts.templates.len());
                         profile.set(profiler::FONT_TEMPLATES_MEM, bytes_to_mb(self.font_templates_memory));
                     }
                 }
-                ResourceUpdate::AddFontInstance(..) => {
-                    // Already added in ApiResources.
+                ResourceUpdate::AddFontInstance(instance) => {
+                    // Already added in ApiResources, only count it as used by
+                    // the next frame.
+                    let shared_key = self.resources.fonts.instance_keys.map_key(&instance.key);
+                    let usage = self.new_resource_usage();
+                    self.font_instance_usage.insert(shared_key, usage);
                 }
             }
         }
@@ -767,17 +947,31 @@ impl ResourceCache {
         }
     }
 
-    pub fn add_font_template(&mut self, font_key: FontKey, template: FontTemplate) {
+    pub fn add_font_template(
+        &mut self,
+        font_key: FontKey,
+        template: FontTemplate,
+        parsed: Option<ParsedFontInfo>,
+    ) {
         // Push the new font to the font renderer, and also store
         // it locally for glyph metric requests.
         if let FontTemplate::Raw(ref data, _) = template {
             self.resources.weak_fonts.insert(Arc::downgrade(data));
             self.font_templates_memory += data.len();
         }
-        self.glyph_rasterizer.add_font(font_key, template.clone());
+        match parsed {
+            Some(info) => self.glyph_rasterizer.add_parsed_font(font_key, template.clone(), info),
+            None => self.glyph_rasterizer.add_font(font_key, template.clone()),
+        }
         self.resources.fonts.templates.add_font(font_key, template);
     }
 
+    /// Notifies `request` once the fonts added so far are ready to be used
+    /// for rasterizing glyphs.
+    pub fn notify_fonts_loaded(&self, request: NotificationRequest) {
+        self.glyph_rasterizer.notify_fonts_loaded(move || request.notify());
+    }
+
     pub fn delete_font_template(&mut self, font_key: FontKey) {
         self.glyph_rasterizer.delete_font(font_key);
         if let Some(FontTemplate::Raw(data, _)) = self.resources.fonts.templates.delete_font(&font_key) {
@@ -823,6 +1017,7 @@ impl ResourceCache {
             tiling,
             visible_rect: *visible_rect,
             generation: ImageGeneration(0),
+            usage: self.new_resource_usage(),
         };
 
         self.resources.image_templates.insert(image_key, resource);
@@ -834,6 +1029,7 @@ impl ResourceCache {
         descriptor: ImageDescriptor,
         data: CachedImageData,
         dirty_rect: &ImageDirtyRect,
+        dirty_rects: &[DeviceIntRect],
     ) {
         let tiling_threshold = self.tiling_threshold();
         let image = match self.resources.image_templates.get_mut(image_key) {
@@ -846,37 +1042,68 @@ impl ResourceCache {
             tiling = Some(DEFAULT_TILE_SIZE);
         }
 
+        // If the image grew past the tiling threshold, or previously didn't fit in a
+        // texture, the cached entries no longer correspond to the template and will
+        // never be requested again, so release them. The image will be uploaded as
+        // tiles (or retried) the next time it is requested.
+        let is_error = match self.cached_images.try_get(&image_key) {
+            Some(&ImageResult::Err(..)) => true,
+            _ => false,
+        };
+        if tiling != image.tiling || is_error {
+            if let Some(mut cached) = self.cached_images.remove(&image_key) {
+                cached.drop_from_cache(&mut self.texture_cache);
+            }
+        }
+
         // Each cache entry stores its own copy of the image's dirty rect. This allows them to be
         // updated independently.
         match self.cached_images.try_get_mut(&image_key) {
             Some(&mut ImageResult::UntiledAuto(ref mut entry)) => {
-                entry.dirty_rect = entry.dirty_rect.union(dirty_rect);
+                entry.add_dirty_region(dirty_rect, dirty_rects);
             }
             Some(&mut ImageResult::Multi(ref mut entries)) => {
                 for (key, entry) in entries.iter_mut() {
                     // We want the dirty rect relative to the tile and not the whole image.
-                    let local_dirty_rect = match (tiling, key.tile) {
+                    match (tiling, key.tile) {
                         (Some(tile_size), Some(tile)) => {
-                            dirty_rect.map(|mut rect|{
-                                let tile_offset = DeviceIntPoint::new(
-                                    tile.x as i32,
-                                    tile.y as i32,
-                                ) * tile_size as i32;
-                                rect = rect.translate(-tile_offset.to_vector());
-
-                                let tile_rect = compute_tile_size(
-                                    &descriptor.size.into(),
-                                    tile_size,
-                                    tile,
-                                ).into();
-
-                                rect.intersection(&tile_rect).unwrap_or_else(DeviceIntRect::zero)
-                            })
+                            let tile_offset = DeviceIntPoint::new(
+                                tile.x as i32,
+                                tile.y as i32,
+                            ) * tile_size as i32;
+
+                            let tile_rect = compute_tile_size(
+                                &descriptor.size.into(),
+                                tile_size,
+                                tile,
+                            ).into();
+
+                            let to_local = |rect: DeviceIntRect| {
+                                rect.translate(-tile_offset.to_vector())
+                                    .intersection(&tile_rect)
+                                    .unwrap_or_else(DeviceIntRect::zero)
+                            };
+
+                            let local_rects: Vec<DeviceIntRect> = dirty_rects
+                                .iter()
+                                .map(|rect| to_local(*rect))
+                                .filter(|rect| !rect.is_empty())
+                                .collect();
+
+                            let local_dirty_rect = if dirty_rects.is_empty() {
+                                dirty_rect.map(to_local)
+                            } else {
+                                local_rects.iter().fold(
+                                    DirtyRect::empty(),
+                                    |acc, rect| acc.union(&DirtyRect::Partial(*rect)),
+                                )
+                            };
+
+                            entry.add_dirty_region(&local_dirty_rect, &local_rects);
                         }
-                        (None, Some(..)) => DirtyRect::All,
-                        _ => *dirty_rect,
-                    };
-                    entry.dirty_rect = entry.dirty_rect.union(&local_dirty_rect);
+                        (None, Some(..)) => entry.add_dirty_region(&DirtyRect::All, &[]),
+                        _ => entry.add_dirty_region(dirty_rect, dirty_rects),
+                    }
                 }
             }
             _ => {}
@@ -892,9 +1119,32 @@ impl ResourceCache {
             tiling,
             visible_rect: descriptor.size.into(),
             generation: ImageGeneration(image.generation.0 + 1),
+            usage: mem::take(&mut image.usage),
         };
     }
 
+    /// Marks the images backed by the given external images as updated, so
+    /// that whatever was drawn from them gets invalidated. Returns true if any
+    /// image was affected.
+    pub fn invalidate_external_images(&mut self, ids: &[ExternalImageId]) -> bool {
+        let updated: Vec<(ImageKey, ImageDescriptor, CachedImageData)> = self.resources.image_templates
+            .images
+            .iter()
+            .filter(|(_, image)| match image.data {
+                CachedImageData::External(ref ext_data) => ids.contains(&ext_data.id),
+                _ => false,
+            })
+            .map(|(key, image)| (*key, image.descriptor, image.data.clone()))
+            .collect();
+
+        let any_updated = !updated.is_empty();
+        for (key, descriptor, data) in updated {
+            self.update_image_template(key, descriptor, data, &DirtyRect::All, &[]);
+        }
+
+        any_updated
+    }
+
     pub fn delete_image_template(&mut self, image_key: ImageKey) {
         // Remove the template.
         let value = self.resources.image_templates.remove(image_key);
@@ -921,6 +1171,173 @@ impl ResourceCache {
         }
     }
 
+    /// The number of frames built so far for `document_id`.
+    pub fn frames_built(&self, document_id: DocumentId) -> u64 {
+        self.frames_built.get(&document_id).cloned().unwrap_or(0)
+    }
+
+    /// Forgets the frames built for a deleted document, so that resources
+    /// its last frame used no longer count as in use.
+    pub fn delete_document(&mut self, document_id: DocumentId) {
+        self.frames_built.remove(&document_id);
+    }
+
+    /// Usage for a new resource, which counts as used by the next frame of
+    /// every document.
+    fn new_resource_usage(&self) -> ResourceUsage {
+        let mut usage = ResourceUsage::default();
+        for (&document_id, &frames_built) in &self.frames_built {
+            usage.mark_used(document_id, frames_built + 1);
+        }
+        usage
+    }
+
+    /// Records that the frame being built uses an image, including when the
+    /// image is only drawn by picture cache tiles that stay valid.
+    pub fn mark_image_used(&mut self, key: ImageKey) {
+        let document_id = match self.current_document {
+            Some(document_id) => document_id,
+            None => return,
+        };
+        let frame = self.frames_built(document_id);
+        if let Some(template) = self.resources.image_templates.get_mut(key) {
+            template.usage.mark_used(document_id, frame);
+        }
+    }
+
+    /// Records that the frame being built uses a (shared) font instance key.
+    pub fn mark_font_instance_used(&mut self, key: FontInstanceKey) {
+        let document_id = match self.current_document {
+            Some(document_id) => document_id,
+            None => return,
+        };
+        let frame = self.frames_built(document_id);
+        self.font_instance_usage
+            .entry(key)
+            .or_default()
+            .mark_used(document_id, frame);
+    }
+
+    /// Collects the image, blob image and font instance keys of a namespace
+    /// that haven't been used by any frame of `document_id` since `since` (a
+    /// value previously returned by `frames_built`), nor by the latest frame
+    /// of another document.
+    pub fn unused_resources(
+        &self,
+        namespace: IdNamespace,
+        document_id: DocumentId,
+        since: u64,
+    ) -> UnusedResources {
+        let mut unused = UnusedResources::default();
+
+        for (&key, template) in &self.resources.image_templates.images {
+            if key.0 != namespace || !template.usage.is_unused(document_id, since, &self.frames_built) {
+                continue;
+            }
+            if template.data.is_blob() {
+                unused.blob_images.push(BlobImageKey(key));
+            } else {
+                unused.images.push(key);
+            }
+        }
+
+        for key in self.resources.fonts.instance_keys.keys() {
+            if key.0 != namespace {
+                continue;
+            }
+            let shared_key = self.resources.fonts.instance_keys.map_key(&key);
+            let is_unused = self.font_instance_usage
+                .get(&shared_key)
+                .map_or(true, |usage| usage.is_unused(document_id, since, &self.frames_built));
+            if is_unused {
+                unused.font_instances.push(key);
+            }
+        }
+
+        unused
+    }
+
+    /// Lists the resources registered by each namespace, see
+    /// `RenderApi::list_resources`.
+    pub fn list_resources(&self) -> Vec<NamespaceResources> {
+        let frames_built = &self.frames_built;
+
+        let mut namespaces: FastHashMap<IdNamespace, NamespaceResources> = FastHashMap::default();
+
+        for (&key, template) in &self.resources.image_templates.images {
+            let resources = namespaces
+                .entry(key.0)
+                .or_insert_with(|| NamespaceResources::new(key.0));
+            if template.data.is_blob() {
+                resources.blob_images.push(RegisteredBlobImage {
+                    key: BlobImageKey(key),
+                    size: template.visible_rect.size(),
+                    frames_since_use: template.usage.frames_since_use(frames_built),
+                });
+            } else {
+                resources.images.push(RegisteredImage {
+                    key,
+                    size: template.descriptor.size,
+                    format: template.descriptor.format,
+                    frames_since_use: template.usage.frames_since_use(frames_built),
+                });
+            }
+        }
+
+        // Fonts aren't requested directly, so a font counts as used whenever
+        // one of its instances is.
+        let mut font_frames_since_use: FastHashMap<FontKey, u64> = FastHashMap::default();
+
+        for key in self.resources.fonts.instance_keys.keys() {
+            let shared_key = self.resources.fonts.instance_keys.map_key(&key);
+            let instance = match self.resources.fonts.instances.get_font_instance(shared_key) {
+                Some(instance) => instance,
+                None => continue,
+            };
+            let frames_since_use = self.font_instance_usage
+                .get(&shared_key)
+                .and_then(|usage| usage.frames_since_use(frames_built));
+            if let Some(frames_since_use) = frames_since_use {
+                let font_frames = font_frames_since_use.entry(instance.font_key).or_insert(u64::MAX);
+                *font_frames = (*font_frames).min(frames_since_use);
+            }
+
+            namespaces
+                .entry(key.0)
+                .or_insert_with(|| NamespaceResources::new(key.0))
+                .font_instances
+                .push(RegisteredFontInstance {
+                    key,
+                    font_key: instance.font_key,
+                    size: instance.size.into(),
+                    frames_since_use,
+                });
+        }
+
+        for key in self.resources.fonts.font_keys.keys() {
+            let shared_key = self.resources.fonts.font_keys.map_key(&key);
+            namespaces
+                .entry(key.0)
+                .or_insert_with(|| NamespaceResources::new(key.0))
+                .fonts
+                .push(RegisteredFont {
+                    key,
+                    frames_since_use: font_frames_since_use.get(&shared_key).cloned(),
+                });
+        }
+
+        let mut namespaces: Vec<NamespaceResources> = namespaces.into_iter().map(|(_, r)| r).collect();
+        namespaces.sort_by_key(|resources| resources.namespace);
+        for resources in &mut namespaces {
+            resources.images.sort_by_key(|image| image.key.1);
+            resources.blob_images.sort_by_key(|image| (image.key.0).1);
+            resources.fonts.sort_by_key(|font| font.key);
+            resources.font_instances.sort_by_key(|instance| instance.key);
+        }
+
+        namespaces
+    }
+
     /// Return the current generation of an image template
     pub fn get_image_generation(&self, key: ImageKey) -> ImageGeneration {
         self.resources
@@ -939,6 +1356,8 @@ impl ResourceCache {
     ) -> DeviceIntSize {
         debug_assert_eq!(self.state, State::AddResources);
 
+        self.mark_image_used(request.key);
+
         let template = match self.resources.image_templates.get(request.key) {
             Some(template) => template,
             None => {
@@ -958,13 +1377,20 @@ impl ResourceCache {
             return size;
         }
 
-        let side_size =
-            template.tiling.map_or(cmp::max(template.descriptor.size.width, template.descriptor.size.height),
-                                   |tile_size| tile_size as i32);
+        // Tiled images are uploaded one tile at a time, but primitives that don't
+        // support tiling (e.g. image borders) still request the whole image.
+        let side_size = match (template.tiling, request.tile) {
+            (Some(tile_size), Some(..)) => tile_size as i32,
+            _ => cmp::max(template.descriptor.size.width, template.descriptor.size.height),
+        };
         if side_size > self.texture_cache.max_texture_size() {
             // The image or tiling size is too big for hardware texture size.
             warn!("Dropping image, image:(w:{},h:{}, tile:{}) is too big for hardware!",
                   template.descriptor.size.width, template.descriptor.size.height, template.tiling.unwrap_or(0));
+            if template.tiling.is_some() {
+                // Only this request is dropped, the tiles may be in use by other primitives.
+                return DeviceIntSize::zero();
+            }
             self.cached_images.insert(request.key, ImageResult::Err(ImageCacheError::OverLimitSize));
             return DeviceIntSize::zero();
         }
@@ -979,11 +1405,7 @@ impl ResourceCache {
                 if !request.is_untiled_auto() {
                     let untiled_entry = match entry {
                         &mut ImageResult::UntiledAuto(ref mut entry) => {
-                            Some(mem::replace(entry, CachedImageInfo {
-                                texture_cache_handle: TextureCacheHandle::invalid(),
-                                dirty_rect: DirtyRect::All,
-                                manual_eviction: false,
-                            }))
+                            Some(mem::replace(entry, CachedImageInfo::new()))
                         }
                         _ => None
                     };
@@ -1002,11 +1424,7 @@ impl ResourceCache {
             }
             Vacant(entry) => {
                 entry.insert(if request.is_untiled_auto() {
-                    ImageResult::UntiledAuto(CachedImageInfo {
-                        texture_cache_handle: TextureCacheHandle::invalid(),
-                        dirty_rect: DirtyRect::All,
-                        manual_eviction: false,
-                    })
+                    ImageResult::UntiledAuto(CachedImageInfo::new())
                 } else {
                     ImageResult::Multi(ResourceClassCache::new())
                 })
@@ -1019,11 +1437,7 @@ impl ResourceCache {
             ImageResult::UntiledAuto(ref mut entry) => entry,
             ImageResult::Multi(ref mut entries) => {
                 entries.entry(request.into())
-                    .or_insert(CachedImageInfo {
-                        texture_cache_handle: TextureCacheHandle::invalid(),
-                        dirty_rect: DirtyRect::All,
-                        manual_eviction: false,
-                    })
+                    .or_insert_with(CachedImageInfo::new)
             },
             ImageResult::Err(_) => panic!("Errors should already have been handled"),
         };
@@ -1101,16 +1515,20 @@ impl ResourceCache {
     pub fn request_glyphs(
         &mut self,
         mut font: FontInstance,
+        priority: GlyphRequestPriority,
         glyph_keys: &[GlyphKey],
         gpu_cache: &mut GpuCache,
     ) {
         debug_assert_eq!(self.state, State::AddResources);
 
+        self.mark_font_instance_used(font.base.instance_key);
+
         self.glyph_rasterizer.prepare_font(&mut font);
         let glyph_key_cache = self.cached_glyphs.insert_glyph_key_cache_for_font(&font);
         let texture_cache = &mut self.texture_cache;
         self.glyph_rasterizer.request_glyphs(
             font,
+            priority,
             glyph_keys,
             |key| {
                 if let Some(entry) = glyph_key_cache.try_get(key) {
@@ -1215,10 +1633,32 @@ impl ResourceCache {
         }
     }
 
+    pub fn get_glyph_ink(
+        &mut self,
+        font: &FontInstance,
+        glyph_index: GlyphIndex,
+    ) -> Option<&GlyphInk> {
+        match self.cached_glyph_ink.entry((font.instance_key, glyph_index)) {
+            Occupied(entry) => entry.into_mut().as_ref(),
+            Vacant(entry) => entry.insert(
+                self.glyph_rasterizer
+                    .get_glyph_ink(font, glyph_index),
+            ).as_ref(),
+        }
+    }
+
     pub fn get_glyph_index(&mut self, font_key: FontKey, ch: char) -> Option<u32> {
         self.glyph_rasterizer.get_glyph_index(font_key, ch)
     }
 
+    pub fn get_glyph_variant_index(&mut self, font_key: FontKey, ch: char, selector: char) -> Option<u32> {
+        self.glyph_rasterizer.get_glyph_variant_index(font_key, ch, selector)
+    }
+
+    pub fn get_glyph_outline(&mut self, font_key: FontKey, glyph_index: GlyphIndex) -> Vec<PathCommand> {
+        self.glyph_rasterizer.get_glyph_outline(font_key, glyph_index)
+    }
+
     #[inline]
     pub fn get_cached_image(&self, request: ImageRequest) -> Result<CacheItem, ()> {
         debug_assert_eq!(self.state, State::QueryResources);
@@ -1237,10 +1677,10 @@ impl ResourceCache {
     fn get_image_info(&self, request: ImageRequest) -> Result<&CachedImageInfo, ()> {
         // TODO(Jerry): add a debug option to visualize the corresponding area for
         // the Err() case of CacheItem.
-        match *self.cached_images.get(&request.key) {
-            ImageResult::UntiledAuto(ref image_info) => Ok(image_info),
-            ImageResult::Multi(ref entries) => Ok(entries.get(&request.into())),
-            ImageResult::Err(_) => Err(()),
+        match self.cached_images.try_get(&request.key) {
+            Some(&ImageResult::UntiledAuto(ref image_info)) => Ok(image_info),
+            Some(&ImageResult::Multi(ref entries)) => entries.try_get(&request.into()).ok_or(()),
+            Some(&ImageResult::Err(_)) | None => Err(()),
         }
     }
 
@@ -1285,7 +1725,11 @@ impl ResourceCache {
             &mut self.glyph_rasterizer,
         );
         self.cached_render_tasks.begin_frame(&mut self.texture_cache);
+        let texture_cache = &self.texture_cache;
+        self.cached_vector_paths.retain(|_, handle| texture_cache.is_allocated(handle));
         self.current_frame_id = stamp.frame_id();
+        self.current_document = Some(stamp.document_id());
+        *self.frames_built.entry(stamp.document_id()).or_insert(0) += 1;
 
         // pop the old frame and push a new one
         self.deleted_blob_keys.pop_front();
@@ -1306,10 +1750,17 @@ impl ResourceCache {
 
         let cached_glyphs = &mut self.cached_glyphs;
         let texture_cache = &mut self.texture_cache;
+        let missing_glyphs = &mut self.missing_glyphs;
 
         self.glyph_rasterizer.resolve_glyphs(
             |job, can_use_r8_format| {
                 let GlyphRasterJob { font, key, result } = job;
+                if key.index() == 0 || result.is_err() {
+                    missing_glyphs.push(MissingGlyph {
+                        font_key: font.font_key,
+                        glyph_index: key.index(),
+                    });
+                }
                 let glyph_key_cache = cached_glyphs.get_glyph_key_cache_for_font_mut(&*font);
                 let glyph_info = match result {
                     Err(_) => GlyphCacheEntry::Blank,
@@ -1353,6 +1804,45 @@ impl ResourceCache {
         self.update_texture_cache(gpu_cache);
     }
 
+    /// Returns the glyphs rasterized since the last call that their font
+    /// can't render, with the font keys of `namespace`.
+    pub fn take_missing_glyphs(&mut self, namespace: IdNamespace) -> Vec<MissingGlyph> {
+        let mut missing_glyphs = mem::replace(&mut self.missing_glyphs, Vec::new());
+        let font_keys = &self.resources.fonts.font_keys;
+        for glyph in &mut missing_glyphs {
+            if let Some(font_key) = font_keys.find_key(namespace, &glyph.font_key) {
+                glyph.font_key = font_key;
+            }
+        }
+        // Glyphs are rasterized for each size they are used at.
+        missing_glyphs.sort();
+        missing_glyphs.dedup();
+        missing_glyphs
+    }
+
+    /// Returns the resources of `namespace` that failed to load since the last
+    /// call, with the keys of `namespace`.
+    pub fn take_resource_errors(&mut self, namespace: IdNamespace) -> Vec<ResourceError> {
+        self.failed_fonts.extend(self.glyph_rasterizer.take_failed_fonts());
+        let font_keys = &self.resources.fonts.font_keys;
+        let mut errors = Vec::new();
+        self.failed_fonts.retain(|shared_key| {
+            let font_key = if shared_key.0 == namespace {
+                Some(*shared_key)
+            } else {
+                font_keys.find_key(namespace, shared_key)
+            };
+            match font_key {
+                Some(font_key) => {
+                    errors.push(ResourceError::InvalidFont(font_key));
+                    false
+                }
+                None => true,
+            }
+        });
+        errors
+    }
+
     fn update_texture_cache(&mut self, gpu_cache: &mut GpuCache) {
         profile_scope!("update_texture_cache");
         for request in self.pending_image_requests.drain() {
@@ -1386,6 +1876,7 @@ impl ResourceCache {
 
                 let mut descriptor = image_template.descriptor.clone();
                 let mut dirty_rect = entry.dirty_rect.replace_with_empty();
+                let dirty_rects = mem::take(&mut entry.dirty_rects);
 
                 if let Some(tile) = request.tile {
                     let tile_size = image_template.tiling.unwrap();
@@ -1420,6 +1911,9 @@ impl ResourceCache {
                     ImageRendering::Pixelated => {
                         TextureFilter::Nearest
                     }
+                    ImageRendering::HighQuality => {
+                        TextureFilter::Trilinear
+                    }
                     ImageRendering::Auto | ImageRendering::CrispEdges => {
                         // If the texture uses linear filtering, enable mipmaps and
                         // trilinear filtering, for better image quality. We only
@@ -1449,20 +1943,31 @@ impl ResourceCache {
                     Eviction::Auto
                 };
 
-                //Note: at this point, the dirty rectangle is local to the descriptor space
-                self.texture_cache.update(
-                    &mut entry.texture_cache_handle,
-                    descriptor,
-                    filter,
-                    Some(image_data),
-                    [0.0; 4],
-                    dirty_rect,
-                    gpu_cache,
-                    None,
-                    UvRectKind::Rect,
-                    eviction,
-                    TargetShader::Default,
-                );
+                // Images updated with a list of dirty rects upload each of them
+                // separately rather than their bounding box.
+                let upload_rects: SmallVec<[ImageDirtyRect; 1]> = match dirty_rect {
+                    DirtyRect::Partial(..) if !dirty_rects.is_empty() => {
+                        dirty_rects.iter().map(|rect| DirtyRect::Partial(*rect)).collect()
+                    }
+                    _ => SmallVec::from_elem(dirty_rect, 1),
+                };
+
+                //Note: at this point, the dirty rectangles are local to the descriptor space
+                for dirty_rect in upload_rects {
+                    self.texture_cache.update(
+                        &mut entry.texture_cache_handle,
+                        descriptor,
+                        filter,
+                        Some(image_data.clone()),
+                        [0.0; 4],
+                        dirty_rect,
+                        gpu_cache,
+                        None,
+                        UvRectKind::Rect,
+                        eviction,
+                        TargetShader::Default,
+                    );
+                }
             }
         }
     }
@@ -1601,12 +2106,16 @@ impl ResourceCache {
         // There is also a second "red line" memory threshold which prevents
         // memory exhaustion if many render targets are allocated within a small
         // number of frames. For now this is set at 320 MB (10x the normal memory threshold).
+        // Targets that haven't been used in the last 600 frames are dropped regardless
+        // of the pool size, so that memory isn't held forever after a burst of
+        // filter-heavy content.
         //
         // [1] https://bugzilla.mozilla.org/show_bug.cgi?id=1494099
         self.gc_render_targets(
             64 * 1024 * 1024,
             32 * 1024 * 1024 * 10,
             60,
+            600,
         );
 
         self.texture_cache.end_frame(profile);
@@ -1634,10 +2143,16 @@ impl ResourceCache {
         }
         if what.contains(ClearCache::GLYPH_DIMENSIONS) {
             self.cached_glyph_dimensions.clear();
+            self.cached_glyph_ink.clear();
         }
         if what.contains(ClearCache::RENDER_TASKS) {
             self.cached_render_tasks.clear();
         }
+        if what.contains(ClearCache::IMAGES) {
+            for (_key, handle) in self.cached_vector_paths.drain() {
+                self.texture_cache.evict_handle(&handle);
+            }
+        }
         if what.contains(ClearCache::TEXTURE_CACHE) {
             self.texture_cache.clear_all();
             self.picture_textures.clear(&mut self.texture_cache.pending_updates);
@@ -1647,6 +2162,58 @@ impl ResourceCache {
         }
     }
 
+    /// Releases the memory held by the targeted caches, see `MemoryPressureLevel`.
+    ///
+    /// Native compositor surfaces of picture cache tiles are owned by the tile
+    /// caches and must be released separately.
+    pub fn on_memory_pressure(
+        &mut self,
+        level: MemoryPressureLevel,
+        targets: MemoryPressureTargets,
+    ) -> MemoryPressureReport {
+        let critical = level == MemoryPressureLevel::Critical;
+        let mut report = MemoryPressureReport::default();
+
+        if targets.contains(MemoryPressureTargets::TEXTURE_CACHE) {
+            let bytes_before = self.texture_cache.bytes_allocated(false);
+            if critical {
+                self.clear(ClearCache::IMAGES | ClearCache::RENDER_TASKS | ClearCache::RENDER_TARGETS);
+            }
+            self.texture_cache.evict_automatic_entries(false);
+            report.texture_cache = bytes_before - self.texture_cache.bytes_allocated(false);
+        }
+
+        if targets.contains(MemoryPressureTargets::GLYPHS) {
+            let bytes_before = self.texture_cache.bytes_allocated(true);
+            self.clear(ClearCache::GLYPH_DIMENSIONS);
+            if critical {
+                self.clear(ClearCache::GLYPHS);
+            }
+            self.texture_cache.evict_automatic_entries(true);
+            report.glyphs = bytes_before - self.texture_cache.bytes_allocated(true);
+            report.glyphs += self.glyph_rasterizer.on_memory_pressure(critical);
+        }
+
+        if targets.contains(MemoryPressureTargets::PICTURE_TILES) {
+            let bytes_before = self.picture_textures.total_bytes();
+            if critical {
+                self.picture_textures.clear(&mut self.texture_cache.pending_updates);
+            } else {
+                self.picture_textures.free_unused_textures(&mut self.texture_cache.pending_updates);
+            }
+            report.picture_tiles = bytes_before - self.picture_textures.total_bytes();
+        }
+
+        if critical && targets == MemoryPressureTargets::all() {
+            // Everything has been evicted at this point, this releases the
+            // shared textures right away instead of at the end of the next
+            // frame.
+            self.texture_cache.clear_all();
+        }
+
+        report
+    }
+
     pub fn clear_namespace(&mut self, namespace: IdNamespace) {
         self.clear_images(|k| k.0 == namespace);
 
@@ -1655,13 +2222,18 @@ impl ResourceCache {
         let deleted_keys = self.resources.fonts.templates.clear_namespace(namespace);
         self.glyph_rasterizer.delete_fonts(&deleted_keys);
         self.cached_glyphs.clear_namespace(namespace);
+        self.failed_fonts.retain(|key| key.0 != namespace);
         if let Some(handler) = &mut self.blob_image_handler {
             handler.clear_namespace(namespace);
         }
 
         // Check for any shared instance keys that were remapped from the namespace.
         let shared_instance_keys = self.resources.fonts.instance_keys.clear_namespace(namespace);
+        self.font_instance_usage.retain(|key, _| key.0 != namespace);
         if !shared_instance_keys.is_empty() {
+            for key in &shared_instance_keys {
+                self.font_instance_usage.remove(key);
+            }
             self.resources.fonts.instances.delete_font_instances(&shared_instance_keys);
             self.cached_glyphs.delete_font_instances(&shared_instance_keys, &mut self.glyph_rasterizer);
             // Blob font instances are not shared across namespaces, so there is no
@@ -1709,6 +2281,8 @@ impl ResourceCache {
             }
         }
 
+        report.glyph_images = self.glyph_rasterizer.report_memory(&mut MallocSizeOfOps::new(op, None));
+
         // Measure images.
         for (_, image) in self.resources.image_templates.images.iter() {
             report.images += match image.data {
@@ -1717,6 +2291,14 @@ impl ResourceCache {
             }
         }
 
+        // Measure the render target pool.
+        for target in &self.render_target_pool {
+            report.render_target_pool_textures += target.size_in_bytes();
+            if target.last_frame_used != self.current_frame_id {
+                report.render_target_pool_idle_textures += target.size_in_bytes();
+            }
+        }
+
         // Mesure rasterized blobs.
         // TODO(gw): Temporarily disabled while we roll back a crash. We can re-enable
         //           these when that crash is fixed.
@@ -1735,6 +2317,33 @@ impl ResourceCache {
         report
     }
 
+    /// Reports the memory held by the font and image templates that were
+    /// added through the given namespace.
+    pub fn report_namespace_memory(&self, op: VoidPtrToSizeFn, namespace: IdNamespace) -> MemoryReport {
+        let mut report = MemoryReport::default();
+
+        for (key, font) in self.resources.fonts.templates.lock().iter() {
+            if key.0 != namespace {
+                continue;
+            }
+            if let FontTemplate::Raw(ref raw, _) = font {
+                report.fonts += unsafe { op(raw.as_ptr() as *const c_void) };
+            }
+        }
+
+        for (key, image) in self.resources.image_templates.images.iter() {
+            if key.0 != namespace {
+                continue;
+            }
+            report.images += match image.data {
+                CachedImageData::Raw(ref v) => unsafe { op(v.as_ptr() as *const c_void) },
+                CachedImageData::Blob | CachedImageData::External(..) => 0,
+            }
+        }
+
+        report
+    }
+
     /// Properly deletes all images matching the predicate.
     fn clear_images<F: Fn(&ImageKey) -> bool>(&mut self, f: F) {
         let keys = self.resources.image_templates.images.keys().filter(|k| f(*k))
@@ -1759,21 +2368,32 @@ impl ResourceCache {
 
     /// Get a render target from the pool, or allocate a new one if none are
     /// currently available that match the requested parameters.
+    ///
+    /// The returned target may be larger than `size`, but never more than
+    /// `MAX_RENDER_TARGET_AREA_RATIO` times its area.
     pub fn get_or_create_render_target_from_pool(
         &mut self,
         size: DeviceIntSize,
         format: ImageFormat,
     ) -> CacheTextureId {
-        for target in &mut self.render_target_pool {
-            if target.size == size &&
-               target.format == format &&
-               !target.is_active {
-                // Found a target that's not currently in use which matches. Update
-                // the last_frame_used for GC purposes.
-                target.is_active = true;
-                target.last_frame_used = self.current_frame_id;
-                return target.texture_id;
-            }
+        let max_area = size.area() as usize * MAX_RENDER_TARGET_AREA_RATIO;
+        let best_target = self.render_target_pool
+            .iter_mut()
+            .filter(|target| {
+                !target.is_active &&
+                target.format == format &&
+                target.size.width >= size.width &&
+                target.size.height >= size.height &&
+                target.size.area() as usize <= max_area
+            })
+            .min_by_key(|target| target.size.area());
+
+        if let Some(target) = best_target {
+            // Found a target that's not currently in use which is large enough.
+            // Update the last_frame_used for GC purposes.
+            target.is_active = true;
+            target.last_frame_used = self.current_frame_id;
+            return target.texture_id;
         }
 
         // Need to create a new render target and add it to the pool
@@ -1825,6 +2445,7 @@ impl ResourceCache {
         total_bytes_threshold: usize,
         total_bytes_red_line_threshold: usize,
         frames_threshold: u64,
+        idle_frames_threshold: u64,
     ) {
         // Get the total GPU memory size used by the current render target pool
         let mut rt_pool_size_in_bytes: usize = self.render_target_pool
@@ -1833,8 +2454,11 @@ impl ResourceCache {
             .sum();
 
         // If the total size of the pool is less than the threshold, don't bother
-        // trying to GC any targets
-        if rt_pool_size_in_bytes <= total_bytes_threshold {
+        // trying to GC any targets, unless some of them have been idle for long
+        if rt_pool_size_in_bytes <= total_bytes_threshold &&
+           self.render_target_pool
+               .iter()
+               .all(|t| t.used_recently(self.current_frame_id, idle_frames_threshold)) {
             return;
         }
 
@@ -1855,8 +2479,9 @@ impl ResourceCache {
             let above_threshold = rt_pool_size_in_bytes > total_bytes_threshold;
             let used_recently = target.used_recently(self.current_frame_id, frames_threshold);
             let used_this_frame = target.last_frame_used == self.current_frame_id;
+            let is_idle = !target.used_recently(self.current_frame_id, idle_frames_threshold);
 
-            if !used_this_frame && (above_red_line || (above_threshold && !used_recently)) {
+            if !used_this_frame && (above_red_line || (above_threshold && !used_recently) || is_idle) {
                 rt_pool_size_in_bytes -= target.size_in_bytes();
                 self.texture_cache.free_render_target(target.texture_id);
             } else {
@@ -1981,7 +2606,7 @@ impl ResourceCache {
         for template in res.fonts.templates.lock().values() {
             let data: &[u8] = match *template {
                 FontTemplate::Raw(ref arc, _) => arc,
-                FontTemplate::Native(_) => continue,
+                FontTemplate::File { .. } | FontTemplate::Native(_) => continue,
             };
             let font_id = res.fonts.templates.len() + 1;
             let entry = match font_paths.entry(data.as_ptr()) {
@@ -2087,6 +2712,12 @@ impl ResourceCache {
                         index,
                     }
                 }
+                FontTemplate::File { path, index } => {
+                    PlainFontTemplate {
+                        data: path.to_string_lossy().to_string(),
+                        index,
+                    }
+                }
                 #[cfg(feature = "font_backend_swash")]
                 FontTemplate::Native(native) => {
                     PlainFontTemplate {
@@ -2094,14 +2725,14 @@ impl ResourceCache {
                         index: 0,
                     }
                 }
-                #[cfg(all(not(feature = "font_backend_swash"), not(feature = "font_backend_fontdue"), not(target_os = "macos")))]
+                #[cfg(all(not(feature = "font_backend_swash"), not(feature = "font_backend_fontdue"), not(feature = "font_backend_ab_glyph"), not(feature = "font_backend_tiny_skia"), not(target_os = "macos")))]
                 FontTemplate::Native(native) => {
                     PlainFontTemplate {
                         data: native.path.to_string_lossy().to_string(),
                         index: native.index,
                     }
                 }
-                #[cfg(all(not(feature = "font_backend_swash"), not(feature = "font_backend_fontdue"), target_os = "macos"))]
+                #[cfg(all(not(feature = "font_backend_swash"), not(feature = "font_backend_fontdue"), not(feature = "font_backend_ab_glyph"), not(feature = "font_backend_tiny_skia"), target_os = "macos"))]
                 FontTemplate::Native(native) => {
                     PlainFontTemplate {
                         data: native.name,
@@ -2243,9 +2874,9 @@ impl ResourceCache {
                 }
             };
 
-            let template = FontTemplate::Raw(arc, plain_template.index);
+            let mut template = FontTemplate::Raw(arc, plain_template.index);
             // Only add the template if this is the first time it has been seen.
-            if let Some(shared_key) = res.fonts.font_keys.add_key(&key, &template) {
+            if let Some(shared_key) = res.fonts.font_keys.add_key(&key, &mut template) {
                 self.glyph_rasterizer.add_font(shared_key, template.clone());
                 res.fonts.templates.add_font(shared_key, template);
             }
@@ -2294,6 +2925,7 @@ impl ResourceCache {
                 tiling: template.tiling,
                 visible_rect: template.descriptor.size.into(),
                 generation: template.generation,
+                usage: ResourceUsage::default(),
             });
         }
 
@@ -2313,3 +2945,106 @@ impl ResourceCache {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use api::{DocumentId, ImageDescriptorFlags};
+    use crate::internal_types::FrameStamp;
+
+    fn build_frame(
+        resource_cache: &mut ResourceCache,
+        gpu_cache: &mut GpuCache,
+        stamp: &mut FrameStamp,
+        images: &[ImageKey],
+    ) {
+        let mut profile = TransactionProfile::new();
+        stamp.advance();
+        gpu_cache.prepare_for_frames();
+        gpu_cache.begin_frame(*stamp);
+        resource_cache.begin_frame(*stamp, gpu_cache, &mut profile);
+        // Retained picture cache tiles only record their image dependencies.
+        for &key in images {
+            resource_cache.mark_image_used(key);
+        }
+        resource_cache.block_until_all_resources_added(gpu_cache, &mut profile);
+        resource_cache.end_frame(&mut profile);
+        gpu_cache.end_frame(&mut profile);
+        gpu_cache.bookkeep_after_frames();
+    }
+
+    #[test]
+    fn test_unused_resources_with_retained_tiles() {
+        let mut resource_cache = ResourceCache::new_for_testing();
+        let mut gpu_cache = GpuCache::new_for_testing();
+        // The testing texture cache starts out in a frame.
+        resource_cache.texture_cache.end_frame(&mut TransactionProfile::new());
+        let namespace = IdNamespace(0);
+        let doc_a = DocumentId::new(namespace, 1);
+        let doc_b = DocumentId::new(namespace, 2);
+        let mut stamp_a = FrameStamp::first(doc_a);
+        let mut stamp_b = FrameStamp::first(doc_b);
+
+        let descriptor = ImageDescriptor::new(4, 4, ImageFormat::BGRA8, ImageDescriptorFlags::empty());
+        let data = CachedImageData::Raw(Arc::new(vec![0; 64]));
+        let cached = ImageKey::new(namespace, 1);
+        let dropped = ImageKey::new(namespace, 2);
+        let shared = ImageKey::new(namespace, 3);
+        for &key in &[cached, dropped, shared] {
+            resource_cache.add_image_template(key, descriptor, data.clone(), &descriptor.size.into(), None);
+        }
+
+        build_frame(&mut resource_cache, &mut gpu_cache, &mut stamp_a, &[cached, dropped, shared]);
+        build_frame(&mut resource_cache, &mut gpu_cache, &mut stamp_b, &[shared]);
+
+        // The tile drawing `cached` stays valid well past the purge threshold.
+        let since = resource_cache.frames_built(doc_a) + 1;
+        for _ in 0 .. 10 {
+            build_frame(&mut resource_cache, &mut gpu_cache, &mut stamp_a, &[cached]);
+        }
+
+        // Document B's latest frame still uses `shared`.
+        let unused = resource_cache.unused_resources(namespace, doc_a, since);
+        assert_eq!(unused.images, vec![dropped]);
+
+        // Frames of another document don't age the images document A uses.
+        for _ in 0 .. 10 {
+            build_frame(&mut resource_cache, &mut gpu_cache, &mut stamp_b, &[]);
+        }
+        let since = resource_cache.frames_built(doc_a);
+        let mut unused = resource_cache.unused_resources(namespace, doc_a, since);
+        unused.images.sort_by_key(|key| key.1);
+        assert_eq!(unused.images, vec![dropped, shared]);
+    }
+
+    #[test]
+    fn test_invalidate_external_images() {
+        let mut resource_cache = ResourceCache::new_for_testing();
+        let namespace = IdNamespace(0);
+        let descriptor = ImageDescriptor::new(4, 4, ImageFormat::BGRA8, ImageDescriptorFlags::empty());
+        let external = |id| CachedImageData::External(ExternalImageData {
+            id: ExternalImageId(id),
+            channel_index: 0,
+            image_type: ExternalImageType::Buffer,
+        });
+
+        let video = ImageKey::new(namespace, 1);
+        let other = ImageKey::new(namespace, 2);
+        let raw = ImageKey::new(namespace, 3);
+        resource_cache.add_image_template(video, descriptor, external(1), &descriptor.size.into(), None);
+        resource_cache.add_image_template(other, descriptor, external(2), &descriptor.size.into(), None);
+        resource_cache.add_image_template(raw, descriptor, CachedImageData::Raw(Arc::new(vec![0; 64])), &descriptor.size.into(), None);
+
+        let generations = |resource_cache: &ResourceCache| {
+            [video, other, raw].map(|key| resource_cache.get_image_generation(key).0)
+        };
+        assert_eq!(generations(&resource_cache), [0, 0, 0]);
+
+        // Only the image backed by the changed external image is updated.
+        assert!(resource_cache.invalidate_external_images(&[ExternalImageId(1)]));
+        assert_eq!(generations(&resource_cache), [1, 0, 0]);
+
+        assert!(!resource_cache.invalidate_external_images(&[ExternalImageId(3)]));
+        assert_eq!(generations(&resource_cache), [1, 0, 0]);
+    }
+}
@@ -4,13 +4,16 @@
 
 use api::{BuiltDisplayList, DisplayListWithCache, ColorF, DynamicProperties, Epoch, FontRenderMode};
 use api::{PipelineId, PropertyBinding, PropertyBindingId, PropertyValue, MixBlendMode, StackingContext};
+use api::{ScrollbarOrientation, SubpixelTextPolicy};
 use api::units::*;
 use malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
-use crate::render_api::MemoryReport;
+use crate::render_api::{DisplayListMetrics, MemoryReport};
 use crate::composite::CompositorKind;
 use crate::clip::{ClipStore, ClipTree};
-use crate::spatial_tree::SpatialTree;
+use crate::spatial_node::{SpatialNode, SpatialNodeType};
+use crate::spatial_tree::{SpatialNodeIndex, SpatialTree};
 use crate::frame_builder::{FrameBuilderConfig};
+use crate::gpu_cache;
 use crate::hit_test::{HitTester, HitTestingScene, HitTestingSceneStats};
 use crate::internal_types::FastHashMap;
 use crate::picture::SurfaceInfo;
@@ -166,6 +169,19 @@ impl SceneProperties {
         &self.color_properties
     }
 
+    /// Set the value of a color property that is animated by WebRender
+    /// itself. The value must be set again after each flush. Returns whether
+    /// the value changed.
+    pub fn set_color(&mut self, id: PropertyBindingId, color: ColorF) -> bool {
+        self.color_properties.insert(id, color) != Some(color)
+    }
+
+    /// Set the value of a transform property that is animated by WebRender
+    /// itself. The value must be set again after each flush.
+    pub fn set_transform(&mut self, id: PropertyBindingId, transform: LayoutTransform) {
+        self.transform_properties.insert(id, transform);
+    }
+
 }
 
 /// A representation of the layout within the display port for a given document or iframe.
@@ -268,6 +284,107 @@ impl StackingContextHelpers for StackingContext {
 }
 
 
+/// A blinking caret, see `CaretDisplayItem`.
+pub struct Caret {
+    pub binding_id: PropertyBindingId,
+    pub color: ColorF,
+    /// How long the caret stays visible, then hidden, in nanoseconds.
+    pub blink_interval: u64,
+}
+
+impl Caret {
+    /// The color of the caret `elapsed` nanoseconds after it was shown.
+    pub fn color_at(&self, elapsed: u64) -> ColorF {
+        if (elapsed / self.blink_interval) % 2 == 0 {
+            self.color
+        } else {
+            ColorF::TRANSPARENT
+        }
+    }
+
+    /// When the caret is next shown or hidden, in nanoseconds after it was
+    /// shown.
+    pub fn next_toggle(&self, elapsed: u64) -> u64 {
+        (elapsed / self.blink_interval + 1) * self.blink_interval
+    }
+}
+
+/// How often a frame is built while a scrollbar thumb fades out, in
+/// nanoseconds.
+const SCROLLBAR_FADE_FRAME_INTERVAL: u64 = 16_000_000;
+
+/// The thumb of an overlay scrollbar, see `ScrollbarThumbDisplayItem`.
+pub struct ScrollbarThumb {
+    pub scroll_node_index: SpatialNodeIndex,
+    pub orientation: ScrollbarOrientation,
+    /// How far the thumb moves from the start to the end of its track.
+    pub travel: f32,
+    pub transform_id: PropertyBindingId,
+    pub color_id: PropertyBindingId,
+    pub color: ColorF,
+    /// How long the thumb stays visible after scrolling, in nanoseconds. Zero
+    /// if the thumb doesn't fade.
+    pub fade_delay: u64,
+    pub fade_duration: u64,
+}
+
+impl ScrollbarThumb {
+    /// The offset of the thumb along its track for the current offset of its
+    /// scroll frame.
+    pub fn offset(&self, scroll_frame: &SpatialNode) -> f32 {
+        let info = match scroll_frame.node_type {
+            SpatialNodeType::ScrollFrame(ref info) => info,
+            _ => return 0.0,
+        };
+
+        let (position, range) = match self.orientation {
+            ScrollbarOrientation::Vertical => (-info.offset().y, info.scrollable_size.height),
+            ScrollbarOrientation::Horizontal => (-info.offset().x, info.scrollable_size.width),
+        };
+        if range <= 0.0 {
+            return 0.0;
+        }
+
+        (position / range).max(0.0).min(1.0) * self.travel
+    }
+
+    pub fn transform(&self, offset: f32) -> LayoutTransform {
+        match self.orientation {
+            ScrollbarOrientation::Vertical => LayoutTransform::translation(0.0, offset, 0.0),
+            ScrollbarOrientation::Horizontal => LayoutTransform::translation(offset, 0.0, 0.0),
+        }
+    }
+
+    /// The color of the thumb `idle` nanoseconds after its scroll frame last
+    /// scrolled.
+    pub fn color_at(&self, idle: u64) -> ColorF {
+        if self.fade_delay == 0 || idle < self.fade_delay {
+            return self.color;
+        }
+
+        let faded = idle - self.fade_delay;
+        if faded >= self.fade_duration {
+            return ColorF::TRANSPARENT;
+        }
+
+        let opacity = 1.0 - faded as f32 / self.fade_duration as f32;
+        self.color.scale_alpha(opacity)
+    }
+
+    /// When the color of the thumb next changes, in nanoseconds after its
+    /// scroll frame last scrolled.
+    pub fn next_color_change(&self, idle: u64) -> Option<u64> {
+        let fade_end = self.fade_delay + self.fade_duration;
+        if self.fade_delay == 0 || idle >= fade_end {
+            None
+        } else if idle < self.fade_delay {
+            Some(self.fade_delay)
+        } else {
+            Some((idle + SCROLLBAR_FADE_FRAME_INTERVAL).min(fade_end))
+        }
+    }
+}
+
 /// WebRender's internal representation of the scene.
 pub struct BuiltScene {
     pub has_root_pipeline: bool,
@@ -284,6 +401,11 @@ pub struct BuiltScene {
     pub prim_instances: Vec<PrimitiveInstance>,
     pub surfaces: Vec<SurfaceInfo>,
     pub clip_tree: ClipTree,
+    pub carets: Vec<Caret>,
+    pub scrollbar_thumbs: Vec<ScrollbarThumb>,
+    /// Metrics of each pipeline of the scene, if `FrameBuilderConfig::scene_metrics`
+    /// is set.
+    pub display_list_metrics: Option<FastHashMap<PipelineId, DisplayListMetrics>>,
 }
 
 impl BuiltScene {
@@ -302,9 +424,13 @@ impl BuiltScene {
             prim_instances: Vec::new(),
             surfaces: Vec::new(),
             clip_tree: ClipTree::new(),
+            carets: Vec::new(),
+            scrollbar_thumbs: Vec::new(),
+            display_list_metrics: None,
             config: FrameBuilderConfig {
                 default_font_render_mode: FontRenderMode::Mono,
                 dual_source_blending_is_supported: false,
+                subpixel_text_policy: SubpixelTextPolicy::Auto,
                 testing: false,
                 gpu_supports_fast_clears: false,
                 gpu_supports_advanced_blend: false,
@@ -319,9 +445,12 @@ impl BuiltScene {
                 max_depth_ids: 0,
                 max_target_size: 0,
                 force_invalidation: false,
+                scene_metrics: false,
                 is_software: false,
                 low_quality_pinch_zoom: false,
                 max_shared_surface_size: 2048,
+                gpu_cache_reclaim_threshold: gpu_cache::DEFAULT_RECLAIM_THRESHOLD,
+                gpu_cache_reclaim_delay: gpu_cache::DEFAULT_RECLAIM_DELAY,
             },
         }
     }
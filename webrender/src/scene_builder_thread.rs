@@ -5,10 +5,12 @@
 use api::{AsyncBlobImageRasterizer, BlobImageResult, Parameter};
 use api::{DocumentId, PipelineId, ExternalEvent, BlobImageRequest};
 use api::{NotificationRequest, Checkpoint, IdNamespace, QualitySettings};
-use api::{PrimitiveKeyKind, GlyphDimensionRequest, GlyphIndexRequest};
+use api::{PrimitiveKeyKind, GlyphDimensionRequest, GlyphIndexRequest, GlyphOutlineRequest};
+use api::GlyphVariantIndexRequest;
 use api::channel::{unbounded_channel, single_msg_channel, Receiver, Sender};
 use api::units::*;
 use crate::render_api::{ApiMsg, FrameMsg, SceneMsg, ResourceUpdate, TransactionMsg, MemoryReport};
+use crate::render_api::{Backpressure, SceneMetrics};
 #[cfg(feature = "capture")]
 use crate::capture::CaptureConfig;
 use crate::frame_builder::FrameBuilderConfig;
@@ -19,14 +21,16 @@ use glyph_rasterizer::SharedFontResources;
 use crate::intern::{Internable, Interner, UpdateList};
 use crate::internal_types::{FastHashMap, FastHashSet};
 use malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
+use rayon::ThreadPool;
 use crate::prim_store::backdrop::{BackdropCapture, BackdropRender};
 use crate::prim_store::borders::{ImageBorder, NormalBorderPrim};
 use crate::prim_store::gradient::{LinearGradient, RadialGradient, ConicGradient};
 use crate::prim_store::image::{Image, YuvImage};
-use crate::prim_store::line_dec::LineDecoration;
+use crate::prim_store::line_dec::{LineDecoration, LineSkipInk};
 use crate::prim_store::picture::Picture;
 use crate::prim_store::text_run::TextRun;
-use crate::profiler::{self, TransactionProfile};
+use crate::prim_store::vector_path::VectorPath;
+use crate::profiler::{self, TransactionProfile, add_text_marker, thread_is_being_profiled};
 use crate::render_backend::SceneView;
 use crate::renderer::{FullFrameStats, PipelineInfo};
 use crate::scene::{Scene, BuiltScene, SceneStats};
@@ -34,6 +38,7 @@ use crate::spatial_tree::{SceneSpatialTree, SpatialTreeUpdates};
 use crate::telemetry::Telemetry;
 use crate::SceneBuilderHooks;
 use std::iter;
+use std::sync::Arc;
 use time::precise_time_ns;
 use crate::util::drain_filter;
 use std::thread;
@@ -68,9 +73,12 @@ pub struct BuiltTransaction {
     pub interner_updates: Option<InternerUpdates>,
     pub spatial_tree_updates: Option<SpatialTreeUpdates>,
     pub render_frame: bool,
+    /// See `GenerateFrame::request_time`.
+    pub frame_request_time: Option<u64>,
     pub invalidate_rendered_frame: bool,
     pub profile: TransactionProfile,
     pub frame_stats: FullFrameStats,
+    pub label: Option<String>,
 }
 
 #[cfg(feature = "replay")]
@@ -92,6 +100,8 @@ pub enum SceneBuilderRequest {
     DeleteDocument(DocumentId),
     GetGlyphDimensions(GlyphDimensionRequest),
     GetGlyphIndices(GlyphIndexRequest),
+    GetGlyphVariantIndices(GlyphVariantIndexRequest),
+    GetGlyphOutline(GlyphOutlineRequest),
     ClearNamespace(IdNamespace),
     SimulateLongSceneBuild(u32),
     ExternalEvent(ExternalEvent),
@@ -102,6 +112,8 @@ pub enum SceneBuilderRequest {
     SetFrameBuilderConfig(FrameBuilderConfig),
     SetParameter(Parameter),
     ReportMemory(Box<MemoryReport>, Sender<Box<MemoryReport>>),
+    ReportDocumentMemory(Vec<(DocumentId, MemoryReport)>, Sender<Vec<(DocumentId, MemoryReport)>>),
+    GetSceneMetrics(DocumentId, Sender<Option<SceneMetrics>>),
     #[cfg(feature = "capture")]
     SaveScene(CaptureConfig),
     #[cfg(feature = "replay")]
@@ -121,6 +133,8 @@ pub enum SceneBuilderResult {
     ClearNamespace(IdNamespace),
     GetGlyphDimensions(GlyphDimensionRequest),
     GetGlyphIndices(GlyphIndexRequest),
+    GetGlyphVariantIndices(GlyphVariantIndexRequest),
+    GetGlyphOutline(GlyphOutlineRequest),
     SetParameter(Parameter),
     StopRenderBackend,
     ShutDown(Option<Sender<()>>),
@@ -188,6 +202,18 @@ macro_rules! declare_interners {
                 )+
             }
 
+            /// Returns the total number of interner hits and misses since
+            /// the last call to `end_frame_and_get_pending_updates`.
+            fn lookup_stats(&self) -> (usize, usize) {
+                let mut stats = (0, 0);
+                $(
+                    let (hits, misses) = self.$name.lookup_stats();
+                    stats.0 += hits;
+                    stats.1 += misses;
+                )+
+                stats
+            }
+
             fn end_frame_and_get_pending_updates(&mut self) -> InternerUpdates {
                 InternerUpdates {
                     $(
@@ -211,6 +237,8 @@ struct Document {
     stats: SceneStats,
     view: SceneView,
     spatial_tree: SceneSpatialTree,
+    /// Metrics of the last scene built for this document.
+    metrics: Option<SceneMetrics>,
 }
 
 impl Document {
@@ -224,6 +252,7 @@ impl Document {
                 device_rect,
                 quality_settings: QualitySettings::default(),
             },
+            metrics: None,
         }
     }
 }
@@ -238,6 +267,11 @@ pub struct SceneBuilderThread {
     hooks: Option<Box<dyn SceneBuilderHooks + Send>>,
     simulate_slow_ms: u32,
     removed_pipelines: FastHashSet<PipelineId>,
+    /// Counts the transactions of loaded captures, which don't come from the API.
+    #[cfg_attr(not(feature = "replay"), allow(dead_code))]
+    backpressure: Arc<Backpressure>,
+    /// The thread pool the pipelines of a scene are flattened on, if any.
+    workers: Option<Arc<ThreadPool>>,
     #[cfg(feature = "capture")]
     capture_config: Option<CaptureConfig>,
 }
@@ -269,6 +303,8 @@ impl SceneBuilderThread {
         size_of_ops: Option<MallocSizeOfOps>,
         hooks: Option<Box<dyn SceneBuilderHooks + Send>>,
         channels: SceneBuilderThreadChannels,
+        backpressure: Arc<Backpressure>,
+        workers: Option<Arc<ThreadPool>>,
     ) -> Self {
         let SceneBuilderThreadChannels { rx, tx } = channels;
 
@@ -282,6 +318,8 @@ impl SceneBuilderThread {
             hooks,
             simulate_slow_ms: 0,
             removed_pipelines: FastHashSet::default(),
+            backpressure,
+            workers,
             #[cfg(feature = "capture")]
             capture_config: None,
         }
@@ -343,6 +381,12 @@ impl SceneBuilderThread {
                 Ok(SceneBuilderRequest::GetGlyphIndices(request)) => {
                     self.send(SceneBuilderResult::GetGlyphIndices(request));
                 }
+                Ok(SceneBuilderRequest::GetGlyphVariantIndices(request)) => {
+                    self.send(SceneBuilderResult::GetGlyphVariantIndices(request));
+                }
+                Ok(SceneBuilderRequest::GetGlyphOutline(request)) => {
+                    self.send(SceneBuilderResult::GetGlyphOutline(request));
+                }
                 Ok(SceneBuilderRequest::StopRenderBackend) => {
                     self.send(SceneBuilderResult::StopRenderBackend);
                 }
@@ -357,6 +401,16 @@ impl SceneBuilderThread {
                     (*report) += self.report_memory();
                     tx.send(report).unwrap();
                 }
+                Ok(SceneBuilderRequest::ReportDocumentMemory(mut reports, tx)) => {
+                    self.report_document_memory(&mut reports);
+                    tx.send(reports).unwrap();
+                }
+                Ok(SceneBuilderRequest::GetSceneMetrics(document_id, tx)) => {
+                    let metrics = self.documents
+                        .get(&document_id)
+                        .and_then(|doc| doc.metrics.clone());
+                    tx.send(metrics).unwrap();
+                }
                 Ok(SceneBuilderRequest::SetFrameBuilderConfig(cfg)) => {
                     self.config = cfg;
                 }
@@ -434,6 +488,7 @@ impl SceneBuilderThread {
                     &mut item.interners,
                     &mut item.spatial_tree,
                     &SceneStats::empty(),
+                    self.workers.as_deref(),
                 ));
 
                 interner_updates = Some(
@@ -453,12 +508,14 @@ impl SceneBuilderThread {
                     stats: SceneStats::empty(),
                     view: item.view.clone(),
                     spatial_tree: item.spatial_tree,
+                    metrics: None,
                 },
             );
 
             let txns = vec![Box::new(BuiltTransaction {
                 document_id: item.document_id,
                 render_frame: item.build_frame,
+                frame_request_time: None,
                 invalidate_rendered_frame: false,
                 built_scene,
                 view: item.view,
@@ -472,8 +529,10 @@ impl SceneBuilderThread {
                 spatial_tree_updates,
                 profile: TransactionProfile::new(),
                 frame_stats: FullFrameStats::default(),
+                label: None,
             })];
 
+            self.backpressure.transaction_sent();
             self.forward_built_transactions(txns);
         }
     }
@@ -589,8 +648,9 @@ impl SceneBuilderThread {
         let mut spatial_tree_updates = None;
 
         if scene.has_root_pipeline() && rebuild_scene {
+            let build_start = precise_time_ns();
 
-            let built = SceneBuilder::build(
+            let mut built = SceneBuilder::build(
                 &scene,
                 self.fonts.clone(),
                 &doc.view,
@@ -598,8 +658,17 @@ impl SceneBuilderThread {
                 &mut doc.interners,
                 &mut doc.spatial_tree,
                 &doc.stats,
+                self.workers.as_deref(),
             );
 
+            let (interning_hits, interning_misses) = doc.interners.lookup_stats();
+            doc.metrics = built.display_list_metrics.take().map(|pipelines| SceneMetrics {
+                pipelines,
+                interning_hits,
+                interning_misses,
+                build_time_ms: profiler::ns_to_ms(precise_time_ns() - build_start),
+            });
+
             // Update the allocation stats for next scene
             doc.stats = built.get_stats();
 
@@ -621,6 +690,13 @@ impl SceneBuilderThread {
 
         frame_stats.scene_build_time += scene_build_time_ms;
 
+        if let Some(ref label) = txn.label {
+            if thread_is_being_profiled() {
+                let duration = Duration::from_micros((scene_build_time_ms * 1000.0) as u64);
+                add_text_marker("TransactionLabel", label, duration);
+            }
+        }
+
         if !txn.blob_requests.is_empty() {
             profile.start_time(profiler::BLOB_RASTERIZATION_TIME);
 
@@ -644,6 +720,7 @@ impl SceneBuilderThread {
         Box::new(BuiltTransaction {
             document_id: txn.document_id,
             render_frame: txn.generate_frame.as_bool(),
+            frame_request_time: txn.generate_frame.request_time(),
             invalidate_rendered_frame: txn.invalidate_rendered_frame,
             built_scene,
             view: doc.view,
@@ -657,6 +734,7 @@ impl SceneBuilderThread {
             spatial_tree_updates,
             profile,
             frame_stats,
+            label: txn.label.take(),
         })
     }
 
@@ -745,6 +823,16 @@ impl SceneBuilderThread {
 
         report
     }
+
+    fn report_document_memory(&mut self, reports: &mut [(DocumentId, MemoryReport)]) {
+        let ops = self.size_of_ops.as_mut().unwrap();
+        for (document_id, report) in reports {
+            if let Some(doc) = self.documents.get(document_id) {
+                doc.interners.report_memory(ops, report);
+                doc.scene.report_memory(ops, report);
+            }
+        }
+    }
 }
 
 /// A scene builder thread which executes expensive operations such as blob rasterization
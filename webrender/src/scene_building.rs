@@ -36,15 +36,18 @@
 //!
 
 use api::{AlphaType, BorderDetails, BorderDisplayItem, BuiltDisplayListIter, BuiltDisplayList, PrimitiveFlags};
-use api::{ClipId, ColorF, CommonItemProperties, ComplexClipRegion, ComponentTransferFuncType, RasterSpace};
+use api::{ClipId, ColorF, ColorU, CommonItemProperties, ComplexClipRegion, ComponentTransferFuncType, RasterSpace};
 use api::{DisplayItem, DisplayItemRef, ExtendMode, ExternalScrollId, FilterData};
 use api::{FilterOp, FilterPrimitive, FontInstanceKey, FontSize, GlyphInstance, GlyphOptions, GradientStop};
+use api::{GlyphGridCell, GlyphGridDisplayItem, MAX_TEXT_RUN_LENGTH};
+use api::{BorderRadius, SelectionHighlightDisplayItem, SkipInkLineDisplayItem};
 use api::{IframeDisplayItem, ImageKey, ImageRendering, ItemRange, ColorDepth, QualitySettings};
 use api::{LineOrientation, LineStyle, NinePatchBorderSource, PipelineId, MixBlendMode, StackingContextFlags};
 use api::{PropertyBinding, ReferenceFrameKind, ScrollFrameDescriptor, ReferenceFrameMapper};
 use api::{APZScrollGeneration, HasScrollLinkedEffect, Shadow, SpatialId, StickyFrameDescriptor, ImageMask, ItemTag};
 use api::{ClipMode, PrimitiveKeyKind, TransformStyle, YuvColorSpace, ColorRange, YuvData, TempFilterData};
-use api::{ReferenceTransformBinding, Rotation, FillRule, SpatialTreeItem, ReferenceFrameDescriptor};
+use api::{PathPaint, ScrollbarOrientation};
+use api::{ReferenceTransformBinding, Rotation, FillRule, SpatialTreeItem, ReferenceFrameDescriptor, SpatialTreeItemKey};
 use api::units::*;
 use crate::image_tiling::simplify_repeated_primitive;
 use crate::clip::{ClipItemKey, ClipStore, ClipItemKeyKind, ClipIntern};
@@ -55,14 +58,14 @@ use crate::spatial_tree::{SceneSpatialTree, SpatialNodeContainer, SpatialNodeInd
 use crate::frame_builder::{FrameBuilderConfig};
 use glyph_rasterizer::{FontInstance, SharedFontResources};
 use crate::hit_test::HitTestingScene;
-use crate::intern::Interner;
-use crate::internal_types::{FastHashMap, LayoutPrimitiveInfo, Filter, PlaneSplitterIndex, PipelineInstanceId};
+use crate::intern::{Handle, Internable, Interner};
+use crate::internal_types::{FastHashMap, FastHashSet, LayoutPrimitiveInfo, Filter, PlaneSplitterIndex, PipelineInstanceId};
 use crate::picture::{Picture3DContext, PictureCompositeMode, PicturePrimitive};
 use crate::picture::{BlitReason, OrderedPictureChild, PrimitiveList, SurfaceInfo, PictureFlags};
 use crate::picture_graph::PictureGraph;
 use crate::prim_store::{PrimitiveInstance};
 use crate::prim_store::{PrimitiveInstanceKind, NinePatchDescriptor, PrimitiveStore};
-use crate::prim_store::{InternablePrimitive, PictureIndex};
+use crate::prim_store::{InternablePrimitive, LineSegmentRangeIndex, PictureIndex};
 use crate::prim_store::{PolygonKey};
 use crate::prim_store::backdrop::{BackdropCapture, BackdropRender};
 use crate::prim_store::borders::{ImageBorder, NormalBorderPrim};
@@ -73,11 +76,14 @@ use crate::prim_store::gradient::{
 };
 use crate::prim_store::image::{Image, YuvImage};
 use crate::prim_store::line_dec::{LineDecoration, LineDecorationCacheKey, get_line_decoration_size};
+use crate::prim_store::line_dec::{LineSkipInk, LineSkipInkDataHandle, LineSkipInkInstance};
 use crate::prim_store::picture::{Picture, PictureCompositeKey, PictureKey};
 use crate::prim_store::text_run::TextRun;
+use crate::prim_store::vector_path::{PathOpKey, VectorPath};
+use crate::render_api::DisplayListMetrics;
 use crate::render_backend::SceneView;
 use crate::resource_cache::ImageRequest;
-use crate::scene::{Scene, ScenePipeline, BuiltScene, SceneStats, StackingContextHelpers};
+use crate::scene::{Caret, Scene, BuiltScene, SceneStats, ScrollbarThumb, StackingContextHelpers};
 use crate::scene_builder_thread::Interners;
 use crate::space::SpaceSnapper;
 use crate::spatial_node::{
@@ -85,10 +91,12 @@ use crate::spatial_node::{
 };
 use crate::tile_cache::TileCacheBuilder;
 use euclid::approxeq::ApproxEq;
-use std::{f32, mem, usize};
+use rayon::ThreadPool;
+use rayon::prelude::*;
+use std::{f32, mem, usize, vec};
 use std::collections::vec_deque::VecDeque;
 use std::sync::Arc;
-use crate::util::{VecHelper, MaxRect};
+use crate::util::{VecHelper, MaxRect, MatrixHelpers};
 use crate::filterdata::{SFilterDataComponent, SFilterData, SFilterDataKey};
 
 /// Offsets primitives (and clips) by the external scroll offset
@@ -123,6 +131,63 @@ impl ScrollOffsetMapper {
     }
 }
 
+/// The height of a content band, in layout pixels.
+const CONTENT_BAND_HEIGHT: f32 = 65536.0;
+
+/// Explicit scroll frames with content taller than this get a content band.
+const MIN_BANDED_CONTENT_HEIGHT: f32 = 4.0 * CONTENT_BAND_HEIGHT;
+
+/// The content band of a very tall scroll frame.
+///
+/// Picture cache tiles are positioned in the local space of the scroll root,
+/// so a single slice for a very tall document ends up with huge tile and
+/// primitive coordinates, which lose precision and exceed surface limits.
+/// Instead, the content is placed in a translation-only reference frame whose
+/// origin is the start of the band of `CONTENT_BAND_HEIGHT` that contains the
+/// scroll offset, and which is selected as the scroll root in place of the
+/// scroll frame. The content around the viewport then stays close to the
+/// origin of the slice, and the band only moves when the display list is
+/// built with a scroll offset in another band.
+///
+/// Reference frames with a static translation inside the band that would
+/// otherwise place the content around the viewport far from their origin get
+/// a content band of their own.
+struct ContentBand {
+    node_index: SpatialNodeIndex,
+    origin: LayoutVector2D,
+}
+
+/// Counts of the display items of a pipeline, accumulated while traversing
+/// them before being added to its `DisplayListMetrics`.
+struct ItemMetrics {
+    pipeline_id: PipelineId,
+    item_counts: FastHashMap<&'static str, usize>,
+    clip_nodes: usize,
+}
+
+impl ItemMetrics {
+    fn new(pipeline_id: PipelineId) -> Self {
+        ItemMetrics {
+            pipeline_id,
+            item_counts: FastHashMap::default(),
+            clip_nodes: 0,
+        }
+    }
+
+    fn record(&mut self, item: &DisplayItem) {
+        *self.item_counts.entry(item.debug_name()).or_insert(0) += 1;
+
+        match item {
+            DisplayItem::RectClip(..) |
+            DisplayItem::RoundedRectClip(..) |
+            DisplayItem::ImageMaskClip(..) => {
+                self.clip_nodes += 1;
+            }
+            _ => {}
+        }
+    }
+}
+
 /// A data structure that keeps track of mapping between API Ids for spatials and the indices
 /// used internally in the SpatialTree to avoid having to do HashMap lookups for primitives
 /// and clips during frame building.
@@ -142,6 +207,448 @@ impl NodeIdToIndexMapper {
     }
 }
 
+/// The position of a display item in the scene, resolved while preparing
+/// its pipeline instance.
+#[derive(Clone, Copy)]
+struct ItemLayout {
+    spatial_node_index: SpatialNodeIndex,
+    /// The rect and clip rect of the item, snapped unless it's antialiased.
+    layout: LayoutPrimitiveInfo,
+    /// The rect of the item (or its clip rect if it has no bounds) before
+    /// snapping.
+    unsnapped_rect: LayoutRect,
+}
+
+/// The interning key of a primitive, built while preparing its pipeline
+/// instance.
+struct PreparedKey<P: Internable> {
+    /// The rect and flags the primitive is added with.
+    info: LayoutPrimitiveInfo,
+    key: P::Key,
+    /// The handle of the key, if it was interned by a previous scene.
+    handle: Option<Handle<P>>,
+}
+
+/// The primitive of a display item that is added as a single primitive,
+/// built while preparing its pipeline instance.
+enum PreparedPrimitive {
+    Rectangle(Box<PreparedKey<PrimitiveKeyKind>>),
+    /// A text run, or None if its font doesn't draw anything.
+    TextRun(Option<Box<PreparedKey<TextRun>>>),
+    Image(Box<PreparedKey<Image>>),
+}
+
+/// The part of the flattening of a display item that is done before the
+/// traversal that builds the primitive lists, see `ItemPreparer`.
+enum PreparedItem {
+    /// An item that is only flattened by the traversal.
+    None,
+    /// An item positioned by its common properties or its hit test rect.
+    Positioned {
+        layout: ItemLayout,
+        prim: Option<PreparedPrimitive>,
+    },
+    /// An iframe, with the index of the pipeline instance it embeds, or
+    /// None if its pipeline is missing.
+    Iframe(Option<usize>),
+}
+
+impl PreparedItem {
+    fn layout(&self) -> &ItemLayout {
+        match *self {
+            PreparedItem::Positioned { ref layout, .. } => layout,
+            _ => panic!("bug: the item wasn't prepared"),
+        }
+    }
+
+    fn into_prim(self) -> Option<PreparedPrimitive> {
+        match self {
+            PreparedItem::Positioned { prim, .. } => prim,
+            _ => None,
+        }
+    }
+}
+
+/// An occurrence of a pipeline in the tree of iframes of the scene.
+struct PipelineInstance {
+    pipeline_id: PipelineId,
+    instance_id: PipelineInstanceId,
+    /// Maps the spatial ids of the display list to the spatial nodes of
+    /// this instance.
+    mapper: NodeIdToIndexMapper,
+    /// One entry per item of the traversal of the display list.
+    items: Vec<PreparedItem>,
+}
+
+/// An iframe found while preparing a pipeline instance. The instance of its
+/// pipeline is added once all the instances of the same depth are prepared.
+struct PendingIframe {
+    /// The index of the iframe in the items of the instance.
+    item_index: usize,
+    info: IframeDisplayItem,
+    spatial_node_index: SpatialNodeIndex,
+}
+
+/// The state of the scene that pipeline instances are prepared with, which
+/// is only read so that the instances can be prepared on several threads.
+struct PrepareContext<'a> {
+    scene: &'a Scene,
+    spatial_tree: &'a SceneSpatialTree,
+    content_bands: &'a FastHashMap<SpatialNodeIndex, ContentBand>,
+    content_band_offsets: &'a FastHashMap<SpatialNodeIndex, LayoutVector2D>,
+    fonts: &'a SharedFontResources,
+    config: &'a FrameBuilderConfig,
+    interners: &'a Interners,
+}
+
+/// Prepares the display items of a pipeline instance, ie. does the part of
+/// their flattening that only depends on the spatial tree and on the items
+/// before them in the same instance: resolving their spaces and snapping
+/// their rects, and building the interning keys of the most common
+/// primitives, which are then looked up in the interners.
+///
+/// The pipeline instances of a scene are prepared in parallel, depth by
+/// depth in the tree of iframes. The traversal that builds the primitive
+/// lists then interns the keys that weren't found, in its order, so the
+/// scene doesn't depend on how the instances were prepared.
+struct ItemPreparer<'a> {
+    context: &'a PrepareContext<'a>,
+    mapper: &'a NodeIdToIndexMapper,
+    rf_mapper: ReferenceFrameMapper,
+    external_scroll_mapper: ScrollOffsetMapper,
+    snap_to_device: SpaceSnapper,
+    /// The raster spaces of the stacking contexts. This starts with the
+    /// screen, even for an iframe in a stacking context with a local raster
+    /// space, so the text runs are checked before being added.
+    raster_space_stack: Vec<RasterSpace>,
+}
+
+impl<'a> ItemPreparer<'a> {
+    fn new(
+        context: &'a PrepareContext<'a>,
+        mapper: &'a NodeIdToIndexMapper,
+    ) -> Self {
+        ItemPreparer {
+            context,
+            mapper,
+            rf_mapper: ReferenceFrameMapper::new(),
+            external_scroll_mapper: ScrollOffsetMapper::new(),
+            snap_to_device: SpaceSnapper::new(
+                context.spatial_tree.root_reference_frame_index(),
+                RasterPixelScale::new(1.0),
+            ),
+            raster_space_stack: vec![RasterSpace::Screen],
+        }
+    }
+
+    /// Prepare the display list of a pipeline. Returns one entry per item
+    /// of its traversal, and the iframes it embeds.
+    fn prepare(
+        mut self,
+        pipeline_id: PipelineId,
+    ) -> (Vec<PreparedItem>, Vec<PendingIframe>) {
+        enum Scope {
+            StackingContext,
+            ReferenceFrame,
+        }
+
+        let pipeline = &self.context.scene.pipelines[&pipeline_id];
+        let mut items = Vec::new();
+        let mut iframes = Vec::new();
+        let mut scopes = Vec::new();
+        let mut traversal = pipeline.display_list.iter();
+
+        while let Some(item) = traversal.next() {
+            let prepared = match *item.item() {
+                DisplayItem::PushStackingContext(ref info) => {
+                    // Skip empty stacking contexts, as `build_all` does.
+                    let mut subtraversal = item.sub_iter();
+                    if subtraversal.current_stacking_context_empty() {
+                        subtraversal.skip_current_stacking_context();
+                        traversal = subtraversal;
+                        items.push(PreparedItem::None);
+                        continue;
+                    }
+
+                    self.rf_mapper.push_offset(info.origin.to_vector());
+                    let raster_space = raster_space_for_child(
+                        self.raster_space_stack.last(),
+                        info.stacking_context.raster_space,
+                    );
+                    self.raster_space_stack.push(raster_space);
+                    scopes.push(Scope::StackingContext);
+                    PreparedItem::None
+                }
+                DisplayItem::PushReferenceFrame(..) => {
+                    self.rf_mapper.push_scope();
+                    scopes.push(Scope::ReferenceFrame);
+                    PreparedItem::None
+                }
+                DisplayItem::PopReferenceFrame |
+                DisplayItem::PopStackingContext => {
+                    match scopes.pop() {
+                        Some(Scope::StackingContext) => {
+                            self.rf_mapper.pop_offset();
+                            self.raster_space_stack.pop().unwrap();
+                        }
+                        Some(Scope::ReferenceFrame) => {
+                            self.rf_mapper.pop_scope();
+                        }
+                        None => {}
+                    }
+                    PreparedItem::None
+                }
+                DisplayItem::Iframe(ref info) => {
+                    iframes.push(PendingIframe {
+                        item_index: items.len(),
+                        info: *info,
+                        spatial_node_index: self.mapper.get_spatial_node_index(info.space_and_clip.spatial_id),
+                    });
+                    PreparedItem::None
+                }
+                DisplayItem::Rectangle(ref info) => {
+                    let layout = self.resolve_common_properties(&info.common, Some(&info.bounds));
+                    let prim = prepare_key(
+                        &layout.layout,
+                        PrimitiveKeyKind::Rectangle {
+                            color: info.color.into(),
+                        },
+                        &self.context.interners.prim,
+                    );
+
+                    PreparedItem::Positioned {
+                        layout,
+                        prim: prim.map(PreparedPrimitive::Rectangle),
+                    }
+                }
+                DisplayItem::Text(ref info) => {
+                    let layout = self.resolve_common_properties(&info.common, Some(&info.bounds));
+                    let prim = match create_font_instance(
+                        self.context.fonts,
+                        self.context.config,
+                        &info.font_key,
+                        &info.color,
+                        info.glyph_options,
+                    ) {
+                        Some(font) => {
+                            let offset = self.current_offset(layout.spatial_node_index);
+                            let text_run = create_text_run(
+                                font,
+                                item.glyphs(),
+                                layout.layout.rect.min.to_vector() - offset,
+                                *self.raster_space_stack.last().unwrap(),
+                            );
+
+                            prepare_key(
+                                &layout.layout,
+                                text_run,
+                                &self.context.interners.text_run,
+                            ).map(|prim| PreparedPrimitive::TextRun(Some(prim)))
+                        }
+                        None => Some(PreparedPrimitive::TextRun(None)),
+                    };
+
+                    PreparedItem::Positioned {
+                        layout,
+                        prim,
+                    }
+                }
+                DisplayItem::Image(ref info) => {
+                    let layout = self.resolve_common_properties(&info.common, Some(&info.bounds));
+                    let (prim_info, image) = create_image(
+                        &layout.layout,
+                        layout.layout.rect.size(),
+                        LayoutSize::zero(),
+                        info.image_key,
+                        info.image_rendering,
+                        info.alpha_type,
+                        info.color,
+                    );
+                    let prim = prepare_key(&prim_info, image, &self.context.interners.image);
+
+                    PreparedItem::Positioned {
+                        layout,
+                        prim: prim.map(PreparedPrimitive::Image),
+                    }
+                }
+                DisplayItem::RepeatingImage(ref info) => {
+                    let layout = self.resolve_common_properties(&info.common, Some(&info.bounds));
+                    let stretch_size = process_repeat_size(
+                        &layout.layout.rect,
+                        &layout.unsnapped_rect,
+                        info.stretch_size,
+                    );
+                    let (prim_info, image) = create_image(
+                        &layout.layout,
+                        stretch_size,
+                        info.tile_spacing,
+                        info.image_key,
+                        info.image_rendering,
+                        info.alpha_type,
+                        info.color,
+                    );
+                    let prim = prepare_key(&prim_info, image, &self.context.interners.image);
+
+                    PreparedItem::Positioned {
+                        layout,
+                        prim: prim.map(PreparedPrimitive::Image),
+                    }
+                }
+                DisplayItem::HitTest(ref info) => {
+                    let spatial_node_index = self.get_item_space(info.spatial_id);
+                    let current_offset = self.current_offset(spatial_node_index);
+                    let unsnapped_rect = info.rect.translate(current_offset);
+                    let rect = self.snap_rect(&unsnapped_rect, spatial_node_index);
+
+                    PreparedItem::Positioned {
+                        layout: ItemLayout {
+                            spatial_node_index,
+                            layout: LayoutPrimitiveInfo {
+                                rect,
+                                clip_rect: rect,
+                                flags: info.flags,
+                            },
+                            unsnapped_rect,
+                        },
+                        prim: None,
+                    }
+                }
+                DisplayItem::BackdropFilter(ref info) => {
+                    PreparedItem::Positioned {
+                        layout: self.resolve_common_properties(&info.common, None),
+                        prim: None,
+                    }
+                }
+                DisplayItem::YuvImage(ref info) => self.prepare_layout(&info.common, &info.bounds),
+                DisplayItem::GlyphGrid(ref info) => self.prepare_layout(&info.common, &info.bounds),
+                DisplayItem::SelectionHighlight(ref info) => self.prepare_layout(&info.common, &info.bounds),
+                DisplayItem::Caret(ref info) => self.prepare_layout(&info.common, &info.bounds),
+                DisplayItem::ScrollbarThumb(ref info) => self.prepare_layout(&info.common, &info.bounds),
+                DisplayItem::ClearRectangle(ref info) => self.prepare_layout(&info.common, &info.bounds),
+                DisplayItem::EmbedderDraw(ref info) => self.prepare_layout(&info.common, &info.bounds),
+                DisplayItem::Path(ref info) => self.prepare_layout(&info.common, &info.bounds),
+                DisplayItem::VectorPath(ref info) => self.prepare_layout(&info.common, &info.bounds),
+                DisplayItem::Line(ref info) => self.prepare_layout(&info.common, &info.area),
+                DisplayItem::SkipInkLine(ref info) => self.prepare_layout(&info.common, &info.area),
+                DisplayItem::Gradient(ref info) => self.prepare_layout(&info.common, &info.bounds),
+                DisplayItem::RadialGradient(ref info) => self.prepare_layout(&info.common, &info.bounds),
+                DisplayItem::ConicGradient(ref info) => self.prepare_layout(&info.common, &info.bounds),
+                DisplayItem::BoxShadow(ref info) => self.prepare_layout(&info.common, &info.box_bounds),
+                DisplayItem::Border(ref info) => self.prepare_layout(&info.common, &info.bounds),
+                DisplayItem::ImageMaskClip(..) |
+                DisplayItem::RoundedRectClip(..) |
+                DisplayItem::RectClip(..) |
+                DisplayItem::ClipChain(..) |
+                DisplayItem::PushShadow(..) |
+                DisplayItem::PopAllShadows |
+                DisplayItem::SetGradientStops |
+                DisplayItem::SetFilterOps |
+                DisplayItem::SetFilterData |
+                DisplayItem::SetFilterPrimitives |
+                DisplayItem::SetPoints |
+                DisplayItem::SetPathOps |
+                DisplayItem::ReuseItems(..) |
+                DisplayItem::RetainedItems(..) => PreparedItem::None,
+            };
+
+            items.push(prepared);
+        }
+
+        (items, iframes)
+    }
+
+    fn prepare_layout(
+        &mut self,
+        common: &CommonItemProperties,
+        bounds: &LayoutRect,
+    ) -> PreparedItem {
+        PreparedItem::Positioned {
+            layout: self.resolve_common_properties(common, Some(bounds)),
+            prim: None,
+        }
+    }
+
+    /// Return the spatial node that positions the items of a space, ie.
+    /// its content band if it has one.
+    fn get_item_space(
+        &self,
+        spatial_id: SpatialId,
+    ) -> SpatialNodeIndex {
+        let spatial_node_index = self.mapper.get_spatial_node_index(spatial_id);
+        self.context.content_bands
+            .get(&spatial_node_index)
+            .map_or(spatial_node_index, |band| band.node_index)
+    }
+
+    fn current_offset(
+        &mut self,
+        spatial_node_index: SpatialNodeIndex,
+    ) -> LayoutVector2D {
+        item_offset(
+            self.rf_mapper.current_offset(),
+            spatial_node_index,
+            &mut self.external_scroll_mapper,
+            self.context.spatial_tree,
+            self.context.content_band_offsets,
+        )
+    }
+
+    fn snap_rect(
+        &mut self,
+        rect: &LayoutRect,
+        target_spatial_node: SpatialNodeIndex,
+    ) -> LayoutRect {
+        self.snap_to_device.set_target_spatial_node(
+            target_spatial_node,
+            self.context.spatial_tree,
+        );
+        self.snap_to_device.snap_rect(rect)
+    }
+
+    fn resolve_common_properties(
+        &mut self,
+        common: &CommonItemProperties,
+        bounds: Option<&LayoutRect>,
+    ) -> ItemLayout {
+        let spatial_node_index = self.get_item_space(common.spatial_id);
+        let current_offset = self.current_offset(spatial_node_index);
+
+        let unsnapped_clip_rect = common.clip_rect.translate(current_offset);
+        let unsnapped_rect = bounds.map(|bounds| {
+            bounds.translate(current_offset)
+        });
+
+        // If no bounds rect is given, default to clip rect.
+        let (rect, clip_rect) = if common.flags.contains(PrimitiveFlags::ANTIALISED) {
+            (unsnapped_rect.unwrap_or(unsnapped_clip_rect), unsnapped_clip_rect)
+        } else {
+            let clip_rect = self.snap_rect(
+                &unsnapped_clip_rect,
+                spatial_node_index,
+            );
+
+            let rect = unsnapped_rect.map_or(clip_rect, |bounds| {
+                self.snap_rect(
+                    &bounds,
+                    spatial_node_index,
+                )
+            });
+
+            (rect, clip_rect)
+        };
+
+        ItemLayout {
+            spatial_node_index,
+            layout: LayoutPrimitiveInfo {
+                rect,
+                clip_rect,
+                flags: common.flags,
+            },
+            unsnapped_rect: unsnapped_rect.unwrap_or(unsnapped_clip_rect),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct CompositeOps {
     // Requires only a single texture as input (e.g. most filters)
@@ -520,6 +1027,10 @@ pub struct SceneBuilder<'a> {
     /// pipeline instance ids as they are encountered.
     pipeline_instance_ids: FastHashMap<PipelineId, u32>,
 
+    /// The pipeline instances of the scene, the root one first, and each
+    /// depth of the tree of iframes after the previous one.
+    pipeline_instances: Vec<PipelineInstance>,
+
     /// A list of surfaces (backing textures) that are relevant for this scene.
     /// Every picture is assigned to a surface (either a new surface if the picture
     /// has a composite mode, or the parent surface if it's a pass-through).
@@ -527,6 +1038,27 @@ pub struct SceneBuilder<'a> {
 
     /// Used to build a ClipTree from the clip-chains, clips and state during scene building.
     clip_tree_builder: ClipTreeBuilder,
+
+    /// Complexity metrics of each pipeline encountered while building the scene.
+    /// Only collected when `FrameBuilderConfig::scene_metrics` is set.
+    display_list_metrics: Option<FastHashMap<PipelineId, DisplayListMetrics>>,
+
+    /// The blinking carets of the scene.
+    carets: Vec<Caret>,
+
+    /// The overlay scrollbar thumbs of the scene.
+    scrollbar_thumbs: Vec<ScrollbarThumb>,
+
+    /// The spatial nodes added to move the scrollbar thumbs, used to catch
+    /// thumbs that share a binding within a pipeline instance.
+    scrollbar_thumb_uids: FastHashSet<SpatialNodeUid>,
+
+    /// The content band of each spatial node that has one.
+    content_bands: FastHashMap<SpatialNodeIndex, ContentBand>,
+
+    /// The offset added to the items of each content band, and of the scroll
+    /// and sticky frames inside of it, to move them in to the band.
+    content_band_offsets: FastHashMap<SpatialNodeIndex, LayoutVector2D>,
 }
 
 impl<'a> SceneBuilder<'a> {
@@ -538,12 +1070,12 @@ impl<'a> SceneBuilder<'a> {
         interners: &mut Interners,
         spatial_tree: &mut SceneSpatialTree,
         stats: &SceneStats,
+        workers: Option<&ThreadPool>,
     ) -> BuiltScene {
         profile_scope!("build_scene");
 
         // We checked that the root pipeline is available on the render backend.
         let root_pipeline_id = scene.root_pipeline_id.unwrap();
-        let root_pipeline = scene.pipelines.get(&root_pipeline_id).unwrap();
         let root_reference_frame_index = spatial_tree.root_reference_frame_index();
 
         // During scene building, we assume a 1:1 picture -> raster pixel scale
@@ -580,14 +1112,26 @@ impl<'a> SceneBuilder<'a> {
             next_plane_splitter_index: 0,
             prim_instances: Vec::new(),
             pipeline_instance_ids: FastHashMap::default(),
+            pipeline_instances: Vec::new(),
             surfaces: Vec::new(),
             clip_tree_builder: ClipTreeBuilder::new(),
+            display_list_metrics: if frame_builder_config.scene_metrics {
+                Some(FastHashMap::default())
+            } else {
+                None
+            },
+            carets: Vec::new(),
+            scrollbar_thumbs: Vec::new(),
+            scrollbar_thumb_uids: FastHashSet::default(),
+            content_bands: FastHashMap::default(),
+            content_band_offsets: FastHashMap::default(),
         };
 
-        builder.build_all(
+        builder.prepare_pipeline_instances(
             root_pipeline_id,
-            &root_pipeline,
+            workers,
         );
+        builder.build_all();
 
         // Construct the picture cache primitive instance(s) from the tile cache builder
         let (tile_cache_config, tile_cache_pictures) = builder.tile_cache_builder.build(
@@ -614,6 +1158,15 @@ impl<'a> SceneBuilder<'a> {
 
         let clip_tree = builder.clip_tree_builder.finalize();
 
+        for (pipeline_id, metrics) in builder.display_list_metrics.iter_mut().flatten() {
+            if let Some(pipeline) = scene.pipelines.get(pipeline_id) {
+                metrics.serialized_bytes = pipeline.display_list.display_list.size_in_bytes();
+            }
+            if let Some(epoch) = scene.pipeline_epochs.get(pipeline_id) {
+                metrics.epoch = *epoch;
+            }
+        }
+
         BuiltScene {
             has_root_pipeline: scene.has_root_pipeline(),
             pipeline_epochs: scene.pipeline_epochs.clone(),
@@ -629,6 +1182,9 @@ impl<'a> SceneBuilder<'a> {
             prim_instances: builder.prim_instances,
             surfaces: builder.surfaces,
             clip_tree,
+            carets: builder.carets,
+            scrollbar_thumbs: builder.scrollbar_thumbs,
+            display_list_metrics: builder.display_list_metrics,
         }
     }
 
@@ -781,15 +1337,13 @@ impl<'a> SceneBuilder<'a> {
         // Get the current offset from stacking context <-> reference frame space.
         let rf_offset = self.rf_mapper.current_offset();
 
-        // Get the external scroll offset, if applicable.
-        let scroll_offset = self
-            .external_scroll_mapper
-            .external_scroll_offset(
-                spatial_node_index,
-                self.spatial_tree,
-            );
-
-        rf_offset + scroll_offset
+        item_offset(
+            rf_offset,
+            spatial_node_index,
+            &mut self.external_scroll_mapper,
+            self.spatial_tree,
+            &self.content_band_offsets,
+        )
     }
 
     fn build_spatial_tree_for_display_list(
@@ -798,7 +1352,9 @@ impl<'a> SceneBuilder<'a> {
         pipeline_id: PipelineId,
         instance_id: PipelineInstanceId,
     ) {
+        let mut spatial_nodes = 0;
         dl.iter_spatial_tree(|item| {
+            spatial_nodes += 1;
             match item {
                 SpatialTreeItem::ScrollFrame(descriptor) => {
                     let parent_space = self.get_space(descriptor.parent_space);
@@ -831,32 +1387,30 @@ impl<'a> SceneBuilder<'a> {
                 }
             }
         });
+
+        if let Some(ref mut display_list_metrics) = self.display_list_metrics {
+            display_list_metrics
+                .entry(pipeline_id)
+                .or_insert_with(DisplayListMetrics::new)
+                .spatial_nodes += spatial_nodes;
+        }
     }
 
-    fn build_all(
+    /// Build the spatial tree of each pipeline instance of the scene and
+    /// prepare its display items, see `ItemPreparer`. The instances of each
+    /// depth of the tree of iframes are prepared in parallel on `workers`,
+    /// then the instances of the iframes they embed are added in order.
+    fn prepare_pipeline_instances(
         &mut self,
         root_pipeline_id: PipelineId,
-        root_pipeline: &ScenePipeline,
+        workers: Option<&ThreadPool>,
     ) {
-        enum ContextKind<'a> {
-            Root,
-            StackingContext {
-                sc_info: StackingContextInfo,
-            },
-            ReferenceFrame,
-            Iframe {
-                parent_traversal: BuiltDisplayListIter<'a>,
-            }
-        }
-        struct BuildContext<'a> {
-            pipeline_id: PipelineId,
-            kind: ContextKind<'a>,
-        }
-
-        self.id_to_index_mapper_stack.push(NodeIdToIndexMapper::default());
+        profile_scope!("prepare_pipeline_instances");
 
         let instance_id = self.get_next_instance_id_for_pipeline(root_pipeline_id);
+        let root_pipeline = &self.scene.pipelines[&root_pipeline_id];
 
+        self.id_to_index_mapper_stack.push(NodeIdToIndexMapper::default());
         self.push_root(
             root_pipeline_id,
             instance_id,
@@ -866,52 +1420,226 @@ impl<'a> SceneBuilder<'a> {
             root_pipeline_id,
             instance_id,
         );
-
-        let mut stack = vec![BuildContext {
+        self.pipeline_instances.push(PipelineInstance {
             pipeline_id: root_pipeline_id,
-            kind: ContextKind::Root,
-        }];
-        let mut traversal = root_pipeline.display_list.iter();
+            instance_id,
+            mapper: self.id_to_index_mapper_stack.pop().unwrap(),
+            items: Vec::new(),
+        });
 
-        'outer: while let Some(bc) = stack.pop() {
-            loop {
-                let item = match traversal.next() {
-                    Some(item) => item,
-                    None => break,
-                };
+        let mut level = 0 .. self.pipeline_instances.len();
+        let mut is_root_pipeline = true;
+
+        while !level.is_empty() {
+            let context = PrepareContext {
+                scene: self.scene,
+                spatial_tree: self.spatial_tree,
+                content_bands: &self.content_bands,
+                content_band_offsets: &self.content_band_offsets,
+                fonts: &self.fonts,
+                config: &self.config,
+                interners: self.interners,
+            };
+            let instances = &self.pipeline_instances[level.clone()];
+            let prepare = |instance: &PipelineInstance| {
+                ItemPreparer::new(&context, &instance.mapper).prepare(instance.pipeline_id)
+            };
 
-                match item.item() {
-                    DisplayItem::PushStackingContext(ref info) => {
-                        profile_scope!("build_stacking_context");
-                        let spatial_node_index = self.get_space(info.spatial_id);
-                        let mut subtraversal = item.sub_iter();
-                        // Avoid doing unnecessary work for empty stacking contexts.
-                        if subtraversal.current_stacking_context_empty() {
-                            subtraversal.skip_current_stacking_context();
-                            traversal = subtraversal;
-                            continue;
-                        }
+            let prepared: Vec<_> = match workers {
+                Some(workers) if instances.len() > 1 => {
+                    workers.install(|| instances.par_iter().map(prepare).collect())
+                }
+                _ => instances.iter().map(prepare).collect(),
+            };
 
-                        let composition_operations = CompositeOps::new(
-                            filter_ops_for_compositing(item.filters()),
-                            filter_datas_for_compositing(item.filter_datas()),
-                            filter_primitives_for_compositing(item.filter_primitives()),
-                            info.stacking_context.mix_blend_mode_for_compositing(),
-                        );
+            let next_level_start = self.pipeline_instances.len();
+            for (instance_index, (items, iframes)) in level.zip(prepared) {
+                self.pipeline_instances[instance_index].items = items;
 
-                        let sc_info = self.push_stacking_context(
-                            composition_operations,
-                            info.stacking_context.transform_style,
-                            info.prim_flags,
-                            spatial_node_index,
-                            info.stacking_context.clip_chain_id,
-                            info.stacking_context.raster_space,
-                            info.stacking_context.flags,
-                        );
+                for iframe in iframes {
+                    let iframe_instance = self.add_iframe_instance(
+                        &iframe,
+                        is_root_pipeline,
+                    );
+                    self.pipeline_instances[instance_index].items[iframe.item_index] =
+                        PreparedItem::Iframe(iframe_instance);
+                }
+            }
 
-                        self.rf_mapper.push_offset(info.origin.to_vector());
-                        let new_context = BuildContext {
-                            pipeline_id: bc.pipeline_id,
+            level = next_level_start .. self.pipeline_instances.len();
+            is_root_pipeline = false;
+        }
+    }
+
+    /// Add the pipeline instance of an iframe, and build its spatial tree.
+    /// Returns the index of the instance, or None if the pipeline is missing.
+    fn add_iframe_instance(
+        &mut self,
+        iframe: &PendingIframe,
+        is_root_pipeline: bool,
+    ) -> Option<usize> {
+        let info = &iframe.info;
+        let iframe_pipeline_id = info.pipeline_id;
+        let pipeline = match self.scene.pipelines.get(&iframe_pipeline_id) {
+            Some(pipeline) => pipeline,
+            None => {
+                debug_assert!(info.ignore_missing_pipeline);
+                return None
+            },
+        };
+
+        let instance_id = self.get_next_instance_id_for_pipeline(iframe_pipeline_id);
+
+        self.id_to_index_mapper_stack.push(NodeIdToIndexMapper::default());
+
+        let bounds = self.snap_rect(
+            &info.bounds,
+            iframe.spatial_node_index,
+        );
+
+        let (spatial_node_index, band_offset) = self.content_band_parent(iframe.spatial_node_index);
+        let bounds = bounds.translate(band_offset);
+
+        let spatial_node_index = self.push_reference_frame(
+            SpatialId::root_reference_frame(iframe_pipeline_id),
+            spatial_node_index,
+            iframe_pipeline_id,
+            TransformStyle::Flat,
+            PropertyBinding::Value(LayoutTransform::identity()),
+            ReferenceFrameKind::Transform {
+                is_2d_scale_translation: true,
+                should_snap: true,
+                paired_with_perspective: false,
+            },
+            bounds.min.to_vector(),
+            SpatialNodeUid::root_reference_frame(iframe_pipeline_id, instance_id),
+        );
+
+        let iframe_rect = LayoutRect::from_size(bounds.size());
+
+        self.add_scroll_frame(
+            SpatialId::root_scroll_node(iframe_pipeline_id),
+            spatial_node_index,
+            ExternalScrollId(0, iframe_pipeline_id),
+            iframe_pipeline_id,
+            &iframe_rect,
+            &bounds.size(),
+            ScrollFrameKind::PipelineRoot {
+                is_root_pipeline,
+            },
+            LayoutVector2D::zero(),
+            APZScrollGeneration::default(),
+            HasScrollLinkedEffect::No,
+            SpatialNodeUid::root_scroll_frame(iframe_pipeline_id, instance_id),
+        );
+
+        // The computed transforms of the reference frames of the iframe
+        // depend on its size.
+        self.iframe_size.push(info.bounds.size());
+        self.build_spatial_tree_for_display_list(
+            &pipeline.display_list.display_list,
+            iframe_pipeline_id,
+            instance_id,
+        );
+        self.iframe_size.pop();
+
+        self.pipeline_instances.push(PipelineInstance {
+            pipeline_id: iframe_pipeline_id,
+            instance_id,
+            mapper: self.id_to_index_mapper_stack.pop().unwrap(),
+            items: Vec::new(),
+        });
+
+        Some(self.pipeline_instances.len() - 1)
+    }
+
+    fn build_all(&mut self) {
+        enum ContextKind<'a> {
+            Root,
+            StackingContext {
+                sc_info: StackingContextInfo,
+            },
+            ReferenceFrame,
+            Iframe {
+                parent_traversal: BuiltDisplayListIter<'a>,
+                parent_items: vec::IntoIter<PreparedItem>,
+            }
+        }
+        struct BuildContext<'a> {
+            pipeline_id: PipelineId,
+            instance_id: PipelineInstanceId,
+            kind: ContextKind<'a>,
+        }
+
+        let root_instance = &mut self.pipeline_instances[0];
+        let root_pipeline_id = root_instance.pipeline_id;
+        let instance_id = root_instance.instance_id;
+        let mut items = mem::take(&mut root_instance.items).into_iter();
+        let root_mapper = mem::take(&mut root_instance.mapper);
+        self.id_to_index_mapper_stack.push(root_mapper);
+
+        let mut stack = vec![BuildContext {
+            pipeline_id: root_pipeline_id,
+            instance_id,
+            kind: ContextKind::Root,
+        }];
+        let mut traversal = self.scene.pipelines[&root_pipeline_id].display_list.iter();
+        let collect_metrics = self.display_list_metrics.is_some();
+        // The items are counted locally, and added to the metrics of their
+        // pipeline when the traversal moves to another one.
+        let mut item_metrics = ItemMetrics::new(root_pipeline_id);
+
+        'outer: while let Some(bc) = stack.pop() {
+            if collect_metrics && bc.pipeline_id != item_metrics.pipeline_id {
+                let done = mem::replace(&mut item_metrics, ItemMetrics::new(bc.pipeline_id));
+                self.add_item_metrics(done);
+            }
+
+            loop {
+                let item = match traversal.next() {
+                    Some(item) => item,
+                    None => break,
+                };
+
+                let prepared = items.next().expect("bug: the item wasn't prepared");
+
+                if collect_metrics {
+                    item_metrics.record(item.item());
+                }
+
+                match item.item() {
+                    DisplayItem::PushStackingContext(ref info) => {
+                        profile_scope!("build_stacking_context");
+                        let spatial_node_index = self.get_space(info.spatial_id);
+                        let mut subtraversal = item.sub_iter();
+                        // Avoid doing unnecessary work for empty stacking contexts.
+                        if subtraversal.current_stacking_context_empty() {
+                            subtraversal.skip_current_stacking_context();
+                            traversal = subtraversal;
+                            continue;
+                        }
+
+                        let composition_operations = CompositeOps::new(
+                            filter_ops_for_compositing(item.filters()),
+                            filter_datas_for_compositing(item.filter_datas()),
+                            filter_primitives_for_compositing(item.filter_primitives()),
+                            info.stacking_context.mix_blend_mode_for_compositing(),
+                        );
+
+                        let sc_info = self.push_stacking_context(
+                            composition_operations,
+                            info.stacking_context.transform_style,
+                            info.prim_flags,
+                            spatial_node_index,
+                            info.stacking_context.clip_chain_id,
+                            info.stacking_context.raster_space,
+                            info.stacking_context.flags,
+                        );
+
+                        self.rf_mapper.push_offset(info.origin.to_vector());
+                        let new_context = BuildContext {
+                            pipeline_id: bc.pipeline_id,
+                            instance_id: bc.instance_id,
                             kind: ContextKind::StackingContext {
                                 sc_info,
                             },
@@ -930,6 +1658,7 @@ impl<'a> SceneBuilder<'a> {
                         self.rf_mapper.push_scope();
                         let new_context = BuildContext {
                             pipeline_id: bc.pipeline_id,
+                            instance_id: bc.instance_id,
                             kind: ContextKind::ReferenceFrame,
                         };
                         stack.push(bc);
@@ -944,16 +1673,18 @@ impl<'a> SceneBuilder<'a> {
                     DisplayItem::Iframe(ref info) => {
                         profile_scope!("iframe");
 
-                        let space = self.get_space(info.space_and_clip.spatial_id);
-                        let subtraversal = match self.push_iframe(info, space) {
-                            Some(pair) => pair,
-                            None => continue,
+                        let instance_index = match prepared {
+                            PreparedItem::Iframe(Some(instance_index)) => instance_index,
+                            _ => continue,
                         };
+                        let (subtraversal, instance_id, subitems) = self.push_iframe(info, instance_index);
 
                         let new_context = BuildContext {
                             pipeline_id: info.pipeline_id,
+                            instance_id,
                             kind: ContextKind::Iframe {
                                 parent_traversal: mem::replace(&mut traversal, subtraversal),
+                                parent_items: mem::replace(&mut items, subitems),
                             },
                         };
                         stack.push(bc);
@@ -961,7 +1692,7 @@ impl<'a> SceneBuilder<'a> {
                         continue 'outer;
                     }
                     _ => {
-                        self.build_item(item);
+                        self.build_item(item, bc.instance_id, prepared);
                     }
                 };
             }
@@ -975,7 +1706,7 @@ impl<'a> SceneBuilder<'a> {
                 ContextKind::ReferenceFrame => {
                     self.rf_mapper.pop_scope();
                 }
-                ContextKind::Iframe { parent_traversal } => {
+                ContextKind::Iframe { parent_traversal, parent_items } => {
                     self.iframe_size.pop();
                     self.rf_mapper.pop_scope();
                     self.clip_tree_builder.pop_clip();
@@ -990,6 +1721,7 @@ impl<'a> SceneBuilder<'a> {
                     self.id_to_index_mapper_stack.pop().unwrap();
 
                     traversal = parent_traversal;
+                    items = parent_items;
                 }
             }
 
@@ -1010,20 +1742,41 @@ impl<'a> SceneBuilder<'a> {
             }
         }
 
+        if collect_metrics {
+            self.add_item_metrics(item_metrics);
+        }
+
         debug_assert!(self.sc_stack.is_empty());
 
         self.id_to_index_mapper_stack.pop().unwrap();
         assert!(self.id_to_index_mapper_stack.is_empty());
     }
 
+    fn add_item_metrics(&mut self, item_metrics: ItemMetrics) {
+        let display_list_metrics = match self.display_list_metrics {
+            Some(ref mut display_list_metrics) => display_list_metrics,
+            None => return,
+        };
+        let metrics = display_list_metrics
+            .entry(item_metrics.pipeline_id)
+            .or_insert_with(DisplayListMetrics::new);
+
+        for (name, count) in item_metrics.item_counts {
+            *metrics.item_counts.entry(name).or_insert(0) += count;
+        }
+        metrics.clip_nodes += item_metrics.clip_nodes;
+    }
+
     fn build_sticky_frame(
         &mut self,
         info: &StickyFrameDescriptor,
         parent_node_index: SpatialNodeIndex,
         instance_id: PipelineInstanceId,
     ) {
+        let (parent_node_index, band_offset) = self.content_band_parent(parent_node_index);
+
         let sticky_frame_info = StickyFrameInfo::new(
-            info.bounds,
+            info.bounds.translate(band_offset),
             info.margins,
             info.vertical_offset_bounds,
             info.horizontal_offset_bounds,
@@ -1038,6 +1791,7 @@ impl<'a> SceneBuilder<'a> {
             instance_id,
         );
         self.id_to_index_mapper_stack.last_mut().unwrap().add_spatial_node(info.id, index);
+        self.add_to_content_band(parent_node_index, index);
     }
 
     fn build_reference_frame(
@@ -1097,16 +1851,44 @@ impl<'a> SceneBuilder<'a> {
             },
         };
 
-        self.push_reference_frame(
+        let (parent_space, band_offset) = self.content_band_parent(parent_space);
+        let origin = info.origin.to_vector() + band_offset;
+
+        // A reference frame in a content band that only translates its content
+        // gets a band of its own if the content around the viewport would
+        // otherwise be far from its origin.
+        let mut band_origin = None;
+        if self.content_band_offsets.contains_key(&parent_space) {
+            if let (ReferenceFrameKind::Transform { .. }, PropertyBinding::Value(ref transform)) = (info.reference_frame.kind, &transform) {
+                if transform.is_simple_2d_translation() {
+                    let viewport_y = -(origin.y + transform.m42);
+                    if viewport_y.abs() >= CONTENT_BAND_HEIGHT {
+                        band_origin = Some(LayoutVector2D::new(0.0, viewport_y));
+                    }
+                }
+            }
+        }
+
+        let index = self.push_reference_frame(
             info.reference_frame.id,
             parent_space,
             pipeline_id,
             info.reference_frame.transform_style,
             transform,
             info.reference_frame.kind,
-            info.origin.to_vector(),
+            origin,
             SpatialNodeUid::external(info.reference_frame.key, pipeline_id, instance_id),
         );
+
+        if let Some(band_origin) = band_origin {
+            self.add_content_band(
+                index,
+                band_origin,
+                info.reference_frame.key,
+                pipeline_id,
+                instance_id,
+            );
+        }
     }
 
     fn build_scroll_frame(
@@ -1121,12 +1903,15 @@ impl<'a> SceneBuilder<'a> {
         // positioning offsets.
         let content_size = info.content_rect.size();
 
-        self.add_scroll_frame(
+        let (parent_node_index, band_offset) = self.content_band_parent(parent_node_index);
+        let frame_rect = info.frame_rect.translate(band_offset);
+
+        let node_index = self.add_scroll_frame(
             info.scroll_frame_id,
             parent_node_index,
             info.external_id,
             pipeline_id,
-            &info.frame_rect,
+            &frame_rect,
             &content_size,
             ScrollFrameKind::Explicit,
             info.external_scroll_offset,
@@ -1134,6 +1919,88 @@ impl<'a> SceneBuilder<'a> {
             info.has_scroll_linked_effect,
             SpatialNodeUid::external(info.key, pipeline_id, instance_id),
         );
+        self.add_to_content_band(parent_node_index, node_index);
+
+        if content_size.height > MIN_BANDED_CONTENT_HEIGHT {
+            // Start the band at the multiple of the band height that contains
+            // the top of the viewport.
+            let viewport_y = frame_rect.min.y + info.external_scroll_offset.y;
+            let band_y = (viewport_y / CONTENT_BAND_HEIGHT).floor() * CONTENT_BAND_HEIGHT;
+
+            self.add_content_band(
+                node_index,
+                LayoutVector2D::new(0.0, band_y),
+                info.key,
+                pipeline_id,
+                instance_id,
+            );
+        }
+    }
+
+    /// Add a content band at the given origin in the space of a spatial node.
+    /// The items and the child nodes of the spatial node are then placed in
+    /// the band instead.
+    fn add_content_band(
+        &mut self,
+        spatial_node_index: SpatialNodeIndex,
+        origin: LayoutVector2D,
+        key: SpatialTreeItemKey,
+        pipeline_id: PipelineId,
+        instance_id: PipelineInstanceId,
+    ) {
+        let node_index = self.spatial_tree.add_reference_frame(
+            spatial_node_index,
+            TransformStyle::Flat,
+            PropertyBinding::Value(LayoutTransform::identity()),
+            ReferenceFrameKind::Transform {
+                is_2d_scale_translation: true,
+                should_snap: false,
+                paired_with_perspective: false,
+            },
+            origin,
+            pipeline_id,
+            SpatialNodeUid::content_band(key, pipeline_id, instance_id),
+        );
+
+        // External scroll offsets don't propagate across reference frames, so
+        // the items of the band keep the offset they would have had in the
+        // spatial node, less the origin of the band.
+        let mut node_offset = get_external_scroll_offset(self.spatial_tree, spatial_node_index);
+        if let Some(band_offset) = self.content_band_offsets.get(&spatial_node_index) {
+            node_offset += *band_offset;
+        }
+
+        self.content_band_offsets.insert(node_index, node_offset - origin);
+        self.content_bands.insert(spatial_node_index, ContentBand {
+            node_index,
+            origin,
+        });
+    }
+
+    /// Return the content band of a spatial node, or the spatial node itself
+    /// if it doesn't have one.
+    /// Return the spatial node that the children of a spatial node are added
+    /// to, along with the offset to apply to their position.
+    fn content_band_parent(
+        &self,
+        spatial_node_index: SpatialNodeIndex,
+    ) -> (SpatialNodeIndex, LayoutVector2D) {
+        match self.content_bands.get(&spatial_node_index) {
+            Some(band) => (band.node_index, -band.origin),
+            None => (spatial_node_index, LayoutVector2D::zero()),
+        }
+    }
+
+    /// Place a scroll or sticky frame in the content band of its parent, if
+    /// the parent is in one.
+    fn add_to_content_band(
+        &mut self,
+        parent_index: SpatialNodeIndex,
+        spatial_node_index: SpatialNodeIndex,
+    ) {
+        if let Some(&band_offset) = self.content_band_offsets.get(&parent_index) {
+            self.content_band_offsets.insert(spatial_node_index, band_offset);
+        }
     }
 
     /// Advance and return the next instance id for a given pipeline id
@@ -1154,16 +2021,10 @@ impl<'a> SceneBuilder<'a> {
     fn push_iframe(
         &mut self,
         info: &IframeDisplayItem,
-        spatial_node_index: SpatialNodeIndex,
-    ) -> Option<BuiltDisplayListIter<'a>> {
+        instance_index: usize,
+    ) -> (BuiltDisplayListIter<'a>, PipelineInstanceId, vec::IntoIter<PreparedItem>) {
         let iframe_pipeline_id = info.pipeline_id;
-        let pipeline = match self.scene.pipelines.get(&iframe_pipeline_id) {
-            Some(pipeline) => pipeline,
-            None => {
-                debug_assert!(info.ignore_missing_pipeline);
-                return None
-            },
-        };
+        let pipeline = &self.scene.pipelines[&iframe_pipeline_id];
 
         self.clip_tree_builder.push_clip_chain(Some(info.space_and_clip.clip_chain_id), false);
 
@@ -1176,49 +2037,6 @@ impl<'a> SceneBuilder<'a> {
 
         self.clip_tree_builder.push_clip_id(ClipId::root(iframe_pipeline_id));
 
-        let instance_id = self.get_next_instance_id_for_pipeline(iframe_pipeline_id);
-
-        self.id_to_index_mapper_stack.push(NodeIdToIndexMapper::default());
-
-        let bounds = self.snap_rect(
-            &info.bounds,
-            spatial_node_index,
-        );
-
-        let spatial_node_index = self.push_reference_frame(
-            SpatialId::root_reference_frame(iframe_pipeline_id),
-            spatial_node_index,
-            iframe_pipeline_id,
-            TransformStyle::Flat,
-            PropertyBinding::Value(LayoutTransform::identity()),
-            ReferenceFrameKind::Transform {
-                is_2d_scale_translation: true,
-                should_snap: true,
-                paired_with_perspective: false,
-            },
-            bounds.min.to_vector(),
-            SpatialNodeUid::root_reference_frame(iframe_pipeline_id, instance_id),
-        );
-
-        let iframe_rect = LayoutRect::from_size(bounds.size());
-        let is_root_pipeline = self.iframe_size.is_empty();
-
-        self.add_scroll_frame(
-            SpatialId::root_scroll_node(iframe_pipeline_id),
-            spatial_node_index,
-            ExternalScrollId(0, iframe_pipeline_id),
-            iframe_pipeline_id,
-            &iframe_rect,
-            &bounds.size(),
-            ScrollFrameKind::PipelineRoot {
-                is_root_pipeline,
-            },
-            LayoutVector2D::zero(),
-            APZScrollGeneration::default(),
-            HasScrollLinkedEffect::No,
-            SpatialNodeUid::root_scroll_frame(iframe_pipeline_id, instance_id),
-        );
-
         // If this is a root iframe, force a new tile cache both before and after
         // adding primitives for this iframe.
         if self.iframe_size.is_empty() {
@@ -1229,13 +2047,13 @@ impl<'a> SceneBuilder<'a> {
         self.iframe_size.push(info.bounds.size());
         self.rf_mapper.push_scope();
 
-        self.build_spatial_tree_for_display_list(
-            &pipeline.display_list.display_list,
-            iframe_pipeline_id,
-            instance_id,
-        );
+        let instance = &mut self.pipeline_instances[instance_index];
+        let instance_id = instance.instance_id;
+        let items = mem::take(&mut instance.items).into_iter();
+        let mapper = mem::take(&mut instance.mapper);
+        self.id_to_index_mapper_stack.push(mapper);
 
-        Some(pipeline.display_list.iter())
+        (pipeline.display_list.iter(), instance_id, items)
     }
 
     fn get_space(
@@ -1254,60 +2072,20 @@ impl<'a> SceneBuilder<'a> {
         )
     }
 
+    /// Return the layout of an item prepared by `ItemPreparer`, and build
+    /// the clip node of its clip chain.
     fn process_common_properties(
         &mut self,
         common: &CommonItemProperties,
-        bounds: Option<&LayoutRect>,
+        prepared: &PreparedItem,
     ) -> (LayoutPrimitiveInfo, LayoutRect, SpatialNodeIndex, ClipNodeId) {
-        let spatial_node_index = self.get_space(common.spatial_id);
-        let current_offset = self.current_offset(spatial_node_index);
-
-        let unsnapped_clip_rect = common.clip_rect.translate(current_offset);
-        let unsnapped_rect = bounds.map(|bounds| {
-            bounds.translate(current_offset)
-        });
-
-        // If no bounds rect is given, default to clip rect.
-        let (rect, clip_rect) = if common.flags.contains(PrimitiveFlags::ANTIALISED) {
-            (unsnapped_rect.unwrap_or(unsnapped_clip_rect), unsnapped_clip_rect)
-        } else {
-            let clip_rect = self.snap_rect(
-                &unsnapped_clip_rect,
-                spatial_node_index,
-            );
-
-            let rect = unsnapped_rect.map_or(clip_rect, |bounds| {
-                self.snap_rect(
-                    &bounds,
-                    spatial_node_index,
-                )
-            });
-
-            (rect, clip_rect)
-        };
+        let layout = prepared.layout();
 
         let clip_node_id = self.get_clip_node(
             common.clip_chain_id,
         );
 
-        let layout = LayoutPrimitiveInfo {
-            rect,
-            clip_rect,
-            flags: common.flags,
-        };
-
-        (layout, unsnapped_rect.unwrap_or(unsnapped_clip_rect), spatial_node_index, clip_node_id)
-    }
-
-    fn process_common_properties_with_bounds(
-        &mut self,
-        common: &CommonItemProperties,
-        bounds: &LayoutRect,
-    ) -> (LayoutPrimitiveInfo, LayoutRect, SpatialNodeIndex, ClipNodeId) {
-        self.process_common_properties(
-            common,
-            Some(bounds),
-        )
+        (layout.layout, layout.unsnapped_rect, layout.spatial_node_index, clip_node_id)
     }
 
     pub fn snap_rect(
@@ -1325,16 +2103,23 @@ impl<'a> SceneBuilder<'a> {
     fn build_item<'b>(
         &'b mut self,
         item: DisplayItemRef,
+        instance_id: PipelineInstanceId,
+        prepared: PreparedItem,
     ) {
         match *item.item() {
             DisplayItem::Image(ref info) => {
                 profile_scope!("image");
 
-                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties_with_bounds(
+                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
                     &info.common,
-                    &info.bounds,
+                    &prepared,
                 );
 
+                if let Some(PreparedPrimitive::Image(prim)) = self.take_prepared_primitive(prepared) {
+                    self.add_prepared_primitive(spatial_node_index, clip_node_id, *prim);
+                    return;
+                }
+
                 self.add_image(
                     spatial_node_index,
                     clip_node_id,
@@ -1350,11 +2135,16 @@ impl<'a> SceneBuilder<'a> {
             DisplayItem::RepeatingImage(ref info) => {
                 profile_scope!("repeating_image");
 
-                let (layout, unsnapped_rect, spatial_node_index, clip_node_id) = self.process_common_properties_with_bounds(
+                let (layout, unsnapped_rect, spatial_node_index, clip_node_id) = self.process_common_properties(
                     &info.common,
-                    &info.bounds,
+                    &prepared,
                 );
 
+                if let Some(PreparedPrimitive::Image(prim)) = self.take_prepared_primitive(prepared) {
+                    self.add_prepared_primitive(spatial_node_index, clip_node_id, *prim);
+                    return;
+                }
+
                 let stretch_size = process_repeat_size(
                     &layout.rect,
                     &unsnapped_rect,
@@ -1376,9 +2166,9 @@ impl<'a> SceneBuilder<'a> {
             DisplayItem::YuvImage(ref info) => {
                 profile_scope!("yuv_image");
 
-                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties_with_bounds(
+                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
                     &info.common,
-                    &info.bounds,
+                    &prepared,
                 );
 
                 self.add_yuv_image(
@@ -1401,11 +2191,25 @@ impl<'a> SceneBuilder<'a> {
                 // are subtle interactions between the primitive origin and the glyph offset
                 // which appear to be significant (presumably due to some sort of accumulated
                 // error throughout the layers). We should fix this at some point.
-                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties_with_bounds(
+                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
                     &info.common,
-                    &info.bounds,
+                    &prepared,
                 );
 
+                match self.take_prepared_primitive(prepared) {
+                    Some(PreparedPrimitive::TextRun(Some(prim))) => {
+                        // The text run is only valid in the raster space it
+                        // was prepared in.
+                        let requested_raster_space = *self.raster_space_stack.last().unwrap();
+                        if prim.key.requested_raster_space == requested_raster_space {
+                            self.add_prepared_primitive(spatial_node_index, clip_node_id, *prim);
+                            return;
+                        }
+                    }
+                    Some(PreparedPrimitive::TextRun(None)) => return,
+                    _ => {}
+                }
+
                 self.add_text(
                     spatial_node_index,
                     clip_node_id,
@@ -1416,12 +2220,73 @@ impl<'a> SceneBuilder<'a> {
                     info.glyph_options,
                 );
             }
+            DisplayItem::GlyphGrid(ref info) => {
+                profile_scope!("glyph_grid");
+
+                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
+                    &info.common,
+                    &prepared,
+                );
+
+                self.add_glyph_grid(
+                    spatial_node_index,
+                    clip_node_id,
+                    &layout,
+                    info,
+                    item.glyph_grid_cells(),
+                );
+            }
             DisplayItem::Rectangle(ref info) => {
                 profile_scope!("rect");
 
-                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties_with_bounds(
+                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
+                    &info.common,
+                    &prepared,
+                );
+
+                match self.take_prepared_primitive(prepared) {
+                    Some(PreparedPrimitive::Rectangle(prim)) => {
+                        self.add_prepared_primitive(spatial_node_index, clip_node_id, *prim);
+                    }
+                    _ => {
+                        self.add_primitive(
+                            spatial_node_index,
+                            clip_node_id,
+                            &layout,
+                            Vec::new(),
+                            PrimitiveKeyKind::Rectangle {
+                                color: info.color.into(),
+                            },
+                        );
+                    }
+                }
+
+                if info.common.flags.contains(PrimitiveFlags::CHECKERBOARD_BACKGROUND) {
+                    self.add_tile_cache_barrier_if_needed(SliceFlags::empty());
+                }
+            }
+            DisplayItem::SelectionHighlight(ref info) => {
+                profile_scope!("selection_highlight");
+
+                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
+                    &info.common,
+                    &prepared,
+                );
+
+                self.add_selection_highlight(
+                    spatial_node_index,
+                    clip_node_id,
+                    &layout,
+                    info,
+                    item.selection_rects(),
+                );
+            }
+            DisplayItem::Caret(ref info) => {
+                profile_scope!("caret");
+
+                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
                     &info.common,
-                    &info.bounds,
+                    &prepared,
                 );
 
                 self.add_primitive(
@@ -1430,31 +2295,88 @@ impl<'a> SceneBuilder<'a> {
                     &layout,
                     Vec::new(),
                     PrimitiveKeyKind::Rectangle {
-                        color: info.color.into(),
+                        color: PropertyBinding::Binding(info.key, info.color).into(),
                     },
                 );
 
-                if info.common.flags.contains(PrimitiveFlags::CHECKERBOARD_BACKGROUND) {
-                    self.add_tile_cache_barrier_if_needed(SliceFlags::empty());
+                if info.blink_interval_ms > 0 {
+                    self.carets.push(Caret {
+                        binding_id: info.key.id,
+                        color: info.color,
+                        blink_interval: info.blink_interval_ms as u64 * 1_000_000,
+                    });
                 }
             }
-            DisplayItem::HitTest(ref info) => {
-                profile_scope!("hit_test");
+            DisplayItem::ScrollbarThumb(ref info) => {
+                profile_scope!("scrollbar_thumb");
 
-                let spatial_node_index = self.get_space(info.spatial_id);
-                let current_offset = self.current_offset(spatial_node_index);
-                let unsnapped_rect = info.rect.translate(current_offset);
+                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
+                    &info.common,
+                    &prepared,
+                );
 
-                let rect = self.snap_rect(
-                    &unsnapped_rect,
+                // The thumb is moved along its track by a reference frame at
+                // the origin of its space, so its rect doesn't change.
+                let pipeline_id = info.common.spatial_id.pipeline_id();
+                let uid = SpatialNodeUid::scrollbar_thumb(info.transform_key.id, pipeline_id, instance_id);
+                if !self.scrollbar_thumb_uids.insert(uid) {
+                    // The binding can only move one thumb, so draw the others
+                    // where they are rather than adding a duplicate node.
+                    warn!("Scrollbar thumbs share the binding {:?}", info.transform_key.id);
+                    self.add_primitive(
+                        spatial_node_index,
+                        clip_node_id,
+                        &layout,
+                        Vec::new(),
+                        PrimitiveKeyKind::Rectangle {
+                            color: PropertyBinding::Value(info.color).into(),
+                        },
+                    );
+                    return;
+                }
+                let thumb_node_index = self.spatial_tree.add_reference_frame(
                     spatial_node_index,
+                    TransformStyle::Flat,
+                    PropertyBinding::Binding(info.transform_key, LayoutTransform::identity()),
+                    ReferenceFrameKind::Transform {
+                        is_2d_scale_translation: true,
+                        should_snap: false,
+                        paired_with_perspective: false,
+                    },
+                    LayoutVector2D::zero(),
+                    pipeline_id,
+                    uid,
                 );
 
-                let layout = LayoutPrimitiveInfo {
-                    rect,
-                    clip_rect: rect,
-                    flags: info.flags,
+                self.add_primitive(
+                    thumb_node_index,
+                    clip_node_id,
+                    &layout,
+                    Vec::new(),
+                    PrimitiveKeyKind::Rectangle {
+                        color: PropertyBinding::Binding(info.color_key, info.color).into(),
+                    },
+                );
+
+                let thumb_length = match info.orientation {
+                    ScrollbarOrientation::Vertical => info.bounds.height(),
+                    ScrollbarOrientation::Horizontal => info.bounds.width(),
                 };
+                self.scrollbar_thumbs.push(ScrollbarThumb {
+                    scroll_node_index: self.get_space(info.scroll_frame),
+                    orientation: info.orientation,
+                    travel: (info.track_length - thumb_length).max(0.0),
+                    transform_id: info.transform_key.id,
+                    color_id: info.color_key.id,
+                    color: info.color,
+                    fade_delay: info.fade_delay_ms as u64 * 1_000_000,
+                    fade_duration: info.fade_duration_ms as u64 * 1_000_000,
+                });
+            }
+            DisplayItem::HitTest(ref info) => {
+                profile_scope!("hit_test");
+
+                let ItemLayout { layout, spatial_node_index, .. } = *prepared.layout();
 
                 let spatial_node = self.spatial_tree.get_node_info(spatial_node_index);
                 let anim_id: u64 =  match spatial_node.node_type {
@@ -1471,30 +2393,89 @@ impl<'a> SceneBuilder<'a> {
                     &layout,
                     spatial_node_index,
                     clip_node_id,
-                    info.tag,
-                    anim_id,
+                    info.tag,
+                    anim_id,
+                );
+            }
+            DisplayItem::ClearRectangle(ref info) => {
+                profile_scope!("clear");
+
+                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
+                    &info.common,
+                    &prepared,
+                );
+
+                self.add_clear_rectangle(
+                    spatial_node_index,
+                    clip_node_id,
+                    &layout,
+                );
+            }
+            DisplayItem::EmbedderDraw(ref info) => {
+                profile_scope!("embedder_draw");
+
+                let (mut layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
+                    &info.common,
+                    &prepared,
+                );
+
+                // The embedder draws the content when compositing, so it needs
+                // a compositor surface of its own.
+                layout.flags |= PrimitiveFlags::PREFER_COMPOSITOR_SURFACE;
+
+                self.add_primitive(
+                    spatial_node_index,
+                    clip_node_id,
+                    &layout,
+                    Vec::new(),
+                    PrimitiveKeyKind::EmbedderDraw { id: info.id.0.into() },
+                );
+            }
+            DisplayItem::Path(ref info) => {
+                profile_scope!("path");
+
+                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
+                    &info.common,
+                    &prepared,
+                );
+
+                self.add_primitive(
+                    spatial_node_index,
+                    clip_node_id,
+                    &layout,
+                    Vec::new(),
+                    PrimitiveKeyKind::Path { key: info.key.0.into() },
                 );
             }
-            DisplayItem::ClearRectangle(ref info) => {
-                profile_scope!("clear");
+            DisplayItem::VectorPath(ref info) => {
+                profile_scope!("vector_path");
 
-                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties_with_bounds(
+                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
                     &info.common,
-                    &info.bounds,
+                    &prepared,
                 );
 
-                self.add_clear_rectangle(
+                let ops = item.path_ops().iter().map(PathOpKey::from).collect();
+                let stops = match info.paint {
+                    PathPaint::Solid(..) => Vec::new(),
+                    PathPaint::LinearGradient(..) |
+                    PathPaint::RadialGradient(..) => read_gradient_stops(item.gradient_stops()),
+                };
+
+                self.add_primitive(
                     spatial_node_index,
                     clip_node_id,
                     &layout,
+                    Vec::new(),
+                    VectorPath::new(ops, info.mode, info.paint, stops),
                 );
             }
             DisplayItem::Line(ref info) => {
                 profile_scope!("line");
 
-                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties_with_bounds(
+                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
                     &info.common,
-                    &info.area,
+                    &prepared,
                 );
 
                 self.add_line(
@@ -1507,6 +2488,22 @@ impl<'a> SceneBuilder<'a> {
                     info.style,
                 );
             }
+            DisplayItem::SkipInkLine(ref info) => {
+                profile_scope!("skip_ink_line");
+
+                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
+                    &info.common,
+                    &prepared,
+                );
+
+                self.add_skip_ink_line(
+                    spatial_node_index,
+                    clip_node_id,
+                    &layout,
+                    info,
+                    item.glyphs(),
+                );
+            }
             DisplayItem::Gradient(ref info) => {
                 profile_scope!("gradient");
 
@@ -1514,9 +2511,9 @@ impl<'a> SceneBuilder<'a> {
                     return;
                 }
 
-                let (mut layout, unsnapped_rect, spatial_node_index, clip_node_id) = self.process_common_properties_with_bounds(
+                let (mut layout, unsnapped_rect, spatial_node_index, clip_node_id) = self.process_common_properties(
                     &info.common,
-                    &info.bounds,
+                    &prepared,
                 );
 
                 let mut tile_size = process_repeat_size(
@@ -1592,9 +2589,9 @@ impl<'a> SceneBuilder<'a> {
                     return;
                 }
 
-                let (mut layout, unsnapped_rect, spatial_node_index, clip_node_id) = self.process_common_properties_with_bounds(
+                let (mut layout, unsnapped_rect, spatial_node_index, clip_node_id) = self.process_common_properties(
                     &info.common,
-                    &info.bounds,
+                    &prepared,
                 );
 
                 let mut center = info.gradient.center;
@@ -1670,9 +2667,9 @@ impl<'a> SceneBuilder<'a> {
                     return;
                 }
 
-                let (mut layout, unsnapped_rect, spatial_node_index, clip_node_id) = self.process_common_properties_with_bounds(
+                let (mut layout, unsnapped_rect, spatial_node_index, clip_node_id) = self.process_common_properties(
                     &info.common,
-                    &info.bounds,
+                    &prepared,
                 );
 
                 let tile_size = process_repeat_size(
@@ -1715,9 +2712,9 @@ impl<'a> SceneBuilder<'a> {
             DisplayItem::BoxShadow(ref info) => {
                 profile_scope!("box_shadow");
 
-                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties_with_bounds(
+                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
                     &info.common,
-                    &info.box_bounds,
+                    &prepared,
                 );
 
                 self.add_box_shadow(
@@ -1735,9 +2732,9 @@ impl<'a> SceneBuilder<'a> {
             DisplayItem::Border(ref info) => {
                 profile_scope!("border");
 
-                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties_with_bounds(
+                let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
                     &info.common,
-                    &info.bounds,
+                    &prepared,
                 );
 
                 self.add_border(
@@ -1791,7 +2788,7 @@ impl<'a> SceneBuilder<'a> {
 
                 let (layout, _, spatial_node_index, clip_node_id) = self.process_common_properties(
                     &info.common,
-                    None,
+                    &prepared,
                 );
 
                 let filters = filter_ops_for_compositing(item.filters());
@@ -1813,7 +2810,8 @@ impl<'a> SceneBuilder<'a> {
             DisplayItem::SetFilterOps |
             DisplayItem::SetFilterData |
             DisplayItem::SetFilterPrimitives |
-            DisplayItem::SetPoints => {}
+            DisplayItem::SetPoints |
+            DisplayItem::SetPathOps => {}
 
             // Special items that are handled in the parent method
             DisplayItem::PushStackingContext(..) |
@@ -1868,10 +2866,36 @@ impl<'a> SceneBuilder<'a> {
         // Build a primitive key.
         let prim_key = prim.into_key(info);
 
+        self.create_primitive_from_key(
+            prim_key,
+            None,
+            spatial_node_index,
+            clip_leaf_id,
+        )
+    }
+
+    /// Create a primitive from its key, given the handle of the key if it
+    /// was looked up while preparing the display item.
+    fn create_primitive_from_key<P>(
+        &mut self,
+        prim_key: P::Key,
+        prim_data_handle: Option<Handle<P>>,
+        spatial_node_index: SpatialNodeIndex,
+        clip_leaf_id: ClipLeafId,
+    ) -> PrimitiveInstance
+    where
+        P: InternablePrimitive,
+        Interners: AsMut<Interner<P>>,
+    {
         let current_offset = self.current_offset(spatial_node_index);
         let interner = self.interners.as_mut();
-        let prim_data_handle = interner
-            .intern(&prim_key, || ());
+        let prim_data_handle = match prim_data_handle {
+            Some(handle) => {
+                interner.mark_used(handle);
+                handle
+            }
+            None => interner.intern(&prim_key, || ()),
+        };
 
         let instance_kind = P::make_instance_kind(
             prim_key,
@@ -2013,6 +3037,54 @@ impl<'a> SceneBuilder<'a> {
         }
     }
 
+    /// Return the primitive of a prepared display item, unless a shadow
+    /// context is active, in which case the item is added as usual.
+    fn take_prepared_primitive(
+        &self,
+        prepared: PreparedItem,
+    ) -> Option<PreparedPrimitive> {
+        if self.pending_shadow_items.is_empty() {
+            prepared.into_prim()
+        } else {
+            None
+        }
+    }
+
+    /// Add a primitive whose key was built while preparing its display
+    /// item. This is only valid if no shadow context is active.
+    fn add_prepared_primitive<P>(
+        &mut self,
+        spatial_node_index: SpatialNodeIndex,
+        clip_node_id: ClipNodeId,
+        prim: PreparedKey<P>,
+    )
+    where
+        P: InternablePrimitive,
+        Interners: AsMut<Interner<P>>,
+    {
+        debug_assert!(self.pending_shadow_items.is_empty());
+
+        let clip_leaf_id = self.clip_tree_builder.build_for_prim(
+            clip_node_id,
+            &prim.info,
+            &[],
+            &mut self.interners,
+        );
+
+        let prim_instance = self.create_primitive_from_key(
+            prim.key,
+            prim.handle,
+            spatial_node_index,
+            clip_leaf_id,
+        );
+        self.add_primitive_to_draw_list(
+            prim_instance,
+            prim.info.rect,
+            spatial_node_index,
+            prim.info.flags,
+        );
+    }
+
     fn add_prim_to_draw_list<P>(
         &mut self,
         info: &LayoutPrimitiveInfo,
@@ -2099,16 +3171,10 @@ impl<'a> SceneBuilder<'a> {
             !composite_ops.is_empty(),
         );
 
-        let new_space = match (self.raster_space_stack.last(), requested_raster_space) {
-            // If no parent space, just use the requested space
-            (None, _) => requested_raster_space,
-            // If screen, use the parent
-            (Some(parent_space), RasterSpace::Screen) => *parent_space,
-            // If currently screen, select the requested
-            (Some(RasterSpace::Screen), space) => space,
-            // If both local, take the maximum scale
-            (Some(RasterSpace::Local(parent_scale)), RasterSpace::Local(scale)) => RasterSpace::Local(parent_scale.max(scale)),
-        };
+        let new_space = raster_space_for_child(
+            self.raster_space_stack.last(),
+            requested_raster_space,
+        );
         self.raster_space_stack.push(new_space);
 
         // Get the transform-style of the parent stacking context,
@@ -2888,6 +3954,15 @@ impl<'a> SceneBuilder<'a> {
                                     blur_is_noop,
                                 )
                             }
+                            ShadowItem::SkipInkLine(ref pending_line_dec, skip_ink) => {
+                                let (mut instance, info, spatial_node_index) = self.create_shadow_prim(
+                                    &pending_shadow,
+                                    pending_line_dec,
+                                    blur_is_noop,
+                                );
+                                self.set_line_skip_ink(&mut instance, *skip_ink);
+                                (instance, info, spatial_node_index)
+                            }
                             ShadowItem::NormalBorder(ref pending_border) => {
                                 self.create_shadow_prim(
                                     &pending_shadow,
@@ -2909,6 +3984,13 @@ impl<'a> SceneBuilder<'a> {
                                     blur_is_noop,
                                 )
                             }
+                            ShadowItem::VectorPath(ref pending_path) => {
+                                self.create_shadow_prim(
+                                    &pending_shadow,
+                                    pending_path,
+                                    blur_is_noop,
+                                )
+                            }
                             _ => {
                                 continue;
                             }
@@ -2999,6 +4081,12 @@ impl<'a> SceneBuilder<'a> {
                         pending_line_dec,
                     )
                 },
+                ShadowItem::SkipInkLine(pending_line_dec, skip_ink) => {
+                    self.add_skip_ink_line_to_draw_list(
+                        pending_line_dec,
+                        skip_ink,
+                    )
+                },
                 ShadowItem::NormalBorder(pending_border) => {
                     self.add_shadow_prim_to_draw_list(
                         pending_border,
@@ -3014,6 +4102,11 @@ impl<'a> SceneBuilder<'a> {
                         pending_text_run,
                     )
                 },
+                ShadowItem::VectorPath(pending_path) => {
+                    self.add_shadow_prim_to_draw_list(
+                        pending_path,
+                    )
+                },
             }
         }
 
@@ -3150,6 +4243,117 @@ impl<'a> SceneBuilder<'a> {
         );
     }
 
+    /// Adds a solid line that the frame builder breaks wherever the ink of
+    /// the given glyphs crosses it.
+    pub fn add_skip_ink_line(
+        &mut self,
+        spatial_node_index: SpatialNodeIndex,
+        clip_node_id: ClipNodeId,
+        info: &LayoutPrimitiveInfo,
+        line: &SkipInkLineDisplayItem,
+        glyphs: ItemRange<GlyphInstance>,
+    ) {
+        let font = match create_font_instance(
+            &self.fonts,
+            &self.config,
+            &line.font_key,
+            &line.color,
+            line.glyph_options,
+        ) {
+            Some(font) => font,
+            None => return,
+        };
+
+        // Like text runs, the glyphs are stored relative to the primitive.
+        let offset = self.current_offset(spatial_node_index);
+        let prim_offset = info.rect.min.to_vector() - offset;
+        let glyphs = glyphs
+            .iter()
+            .map(|glyph| {
+                GlyphInstance {
+                    index: glyph.index,
+                    point: glyph.point - prim_offset,
+                }
+            })
+            .collect();
+
+        let skip_ink = self.interners
+            .line_skip_ink
+            .intern(&LineSkipInk {
+                font,
+                glyphs,
+                clearance: Au::from_f32_px(line.clearance.max(0.0)),
+            }, || ());
+
+        let pending_line_dec = PendingPrimitive {
+            spatial_node_index,
+            clip_node_id,
+            info: *info,
+            prim: LineDecoration {
+                cache_key: None,
+                color: line.color.into(),
+            },
+        };
+
+        // Like add_primitive, defer the line until the shadows are popped if
+        // there is an active shadow context.
+        if self.pending_shadow_items.is_empty() {
+            self.add_skip_ink_line_to_draw_list(pending_line_dec, skip_ink);
+        } else {
+            self.pending_shadow_items.push_back(
+                ShadowItem::SkipInkLine(pending_line_dec, skip_ink),
+            );
+        }
+    }
+
+    fn add_skip_ink_line_to_draw_list(
+        &mut self,
+        pending_line_dec: PendingPrimitive<LineDecoration>,
+        skip_ink: LineSkipInkDataHandle,
+    ) {
+        if !pending_line_dec.prim.is_visible() {
+            return;
+        }
+
+        let clip_leaf_id = self.clip_tree_builder.build_for_prim(
+            pending_line_dec.clip_node_id,
+            &pending_line_dec.info,
+            &[],
+            &mut self.interners,
+        );
+
+        let mut prim_instance = self.create_primitive(
+            &pending_line_dec.info,
+            pending_line_dec.spatial_node_index,
+            clip_leaf_id,
+            pending_line_dec.prim,
+        );
+        self.set_line_skip_ink(&mut prim_instance, skip_ink);
+
+        self.add_primitive_to_draw_list(
+            prim_instance,
+            pending_line_dec.info.rect,
+            pending_line_dec.spatial_node_index,
+            pending_line_dec.info.flags,
+        );
+    }
+
+    /// Makes a line decoration instance skip the ink of some text. This is
+    /// stored per instance rather than in the line's template, to keep the
+    /// templates of the (much more common) other lines small.
+    fn set_line_skip_ink(
+        &mut self,
+        prim_instance: &mut PrimitiveInstance,
+        data_handle: LineSkipInkDataHandle,
+    ) {
+        if let PrimitiveInstanceKind::LineDecoration { ref mut skip_ink, .. } = prim_instance.kind {
+            *skip_ink = self.prim_store.line_skip_inks.push(LineSkipInkInstance {
+                data_handle,
+                ink_segments: LineSegmentRangeIndex::INVALID,
+            });
+        }
+    }
+
     pub fn add_border(
         &mut self,
         spatial_node_index: SpatialNodeIndex,
@@ -3417,90 +4621,171 @@ impl<'a> SceneBuilder<'a> {
         }
     }
 
-    pub fn add_text(
+    pub fn add_text<I: IntoIterator<Item = GlyphInstance>>(
         &mut self,
         spatial_node_index: SpatialNodeIndex,
         clip_node_id: ClipNodeId,
         prim_info: &LayoutPrimitiveInfo,
         font_instance_key: &FontInstanceKey,
         text_color: &ColorF,
-        glyph_range: ItemRange<GlyphInstance>,
+        glyphs: I,
         glyph_options: Option<GlyphOptions>,
     ) {
         let offset = self.current_offset(spatial_node_index);
 
-        let text_run = {
-            let shared_key = self.fonts.instance_keys.map_key(font_instance_key);
-            let font_instance = match self.fonts.instances.get_font_instance(shared_key) {
-                Some(instance) => instance,
-                None => {
-                    warn!("Unknown font instance key");
-                    debug!("key={:?} shared={:?}", font_instance_key, shared_key);
-                    return;
-                }
-            };
+        let font = match create_font_instance(
+            &self.fonts,
+            &self.config,
+            font_instance_key,
+            text_color,
+            glyph_options,
+        ) {
+            Some(font) => font,
+            None => return,
+        };
 
-            // Trivial early out checks
-            if font_instance.size <= FontSize::zero() {
-                return;
-            }
+        // Query the current requested raster space (stack handled by push/pop
+        // stacking context).
+        let requested_raster_space = self.raster_space_stack
+            .last()
+            .cloned()
+            .unwrap();
+
+        let text_run = create_text_run(
+            font,
+            glyphs,
+            prim_info.rect.min.to_vector() - offset,
+            requested_raster_space,
+        );
+
+        self.add_primitive(
+            spatial_node_index,
+            clip_node_id,
+            prim_info,
+            Vec::new(),
+            text_run,
+        );
+    }
+
+    /// Adds the glyphs of a glyph grid as one text run per color, so that a
+    /// grid with a handful of colors only costs a handful of primitives.
+    pub fn add_glyph_grid(
+        &mut self,
+        spatial_node_index: SpatialNodeIndex,
+        clip_node_id: ClipNodeId,
+        prim_info: &LayoutPrimitiveInfo,
+        grid: &GlyphGridDisplayItem,
+        cells: ItemRange<GlyphGridCell>,
+    ) {
+        if grid.columns == 0 {
+            return;
+        }
 
-            // TODO(gw): Use a proper algorithm to select
-            // whether this item should be rendered with
-            // subpixel AA!
-            let mut render_mode = self.config
-                .default_font_render_mode
-                .limit_by(font_instance.render_mode);
-            let mut flags = font_instance.flags;
-            if let Some(options) = glyph_options {
-                render_mode = render_mode.limit_by(options.render_mode);
-                flags |= options.flags;
+        // Runs are kept in the order their color first appears in.
+        let mut run_indices: FastHashMap<ColorU, usize> = FastHashMap::default();
+        let mut runs: Vec<(ColorU, Vec<GlyphInstance>)> = Vec::new();
+
+        for (i, cell) in cells.iter().enumerate() {
+            if cell.is_empty() {
+                continue;
             }
 
-            let font = FontInstance::new(
-                font_instance,
-                (*text_color).into(),
-                render_mode,
-                flags,
+            let column = i as u32 % grid.columns;
+            let row = i as u32 / grid.columns;
+            let point = LayoutPoint::new(
+                grid.origin.x + column as f32 * grid.cell_size.width,
+                grid.origin.y + row as f32 * grid.cell_size.height + grid.baseline,
             );
 
-            // TODO(gw): It'd be nice not to have to allocate here for creating
-            //           the primitive key, when the common case is that the
-            //           hash will match and we won't end up creating a new
-            //           primitive template.
-            let prim_offset = prim_info.rect.min.to_vector() - offset;
-            let glyphs = glyph_range
-                .iter()
-                .map(|glyph| {
-                    GlyphInstance {
-                        index: glyph.index,
-                        point: glyph.point - prim_offset,
-                    }
-                })
-                .collect();
+            let index = *run_indices.entry(cell.color).or_insert_with(|| {
+                runs.push((cell.color, Vec::new()));
+                runs.len() - 1
+            });
+            runs[index].1.push(GlyphInstance { index: cell.glyph, point });
+        }
 
-            // Query the current requested raster space (stack handled by push/pop
-            // stacking context).
-            let requested_raster_space = self.raster_space_stack
-                .last()
-                .cloned()
-                .unwrap();
+        for (color, glyphs) in runs {
+            let color = ColorF::from(color);
+            for glyphs in glyphs.chunks(MAX_TEXT_RUN_LENGTH) {
+                self.add_text(
+                    spatial_node_index,
+                    clip_node_id,
+                    prim_info,
+                    &grid.font_key,
+                    &color,
+                    glyphs.iter().cloned(),
+                    grid.glyph_options,
+                );
+            }
+        }
+    }
 
-            TextRun {
-                glyphs: Arc::new(glyphs),
-                font,
-                shadow: false,
-                requested_raster_space,
+    /// Adds the lines of a selection as one rectangle each. The lines are made
+    /// vertically disjoint so that overlapping line boxes don't blend twice,
+    /// and only the corners that lie on the outline of the whole selection
+    /// are rounded, so that adjacent lines join seamlessly.
+    pub fn add_selection_highlight(
+        &mut self,
+        spatial_node_index: SpatialNodeIndex,
+        clip_node_id: ClipNodeId,
+        prim_info: &LayoutPrimitiveInfo,
+        highlight: &SelectionHighlightDisplayItem,
+        rects: ItemRange<LayoutRect>,
+    ) {
+        let current_offset = self.current_offset(spatial_node_index);
+
+        let mut lines: Vec<LayoutRect> = Vec::new();
+        for rect in rects.iter() {
+            let mut line = self.snap_rect(&rect.translate(current_offset), spatial_node_index);
+            if let Some(prev) = lines.last() {
+                line.min.y = line.min.y.max(prev.max.y);
+            }
+            if !line.is_empty() {
+                lines.push(line);
+            }
+        }
+
+        let radius = highlight.corner_radius.max(0.0);
+        let radius = LayoutSize::new(radius, radius);
+        // A corner of a line is rounded unless the line it touches on that
+        // side extends over it.
+        let corner = |neighbor: Option<&LayoutRect>, covered: &dyn Fn(&LayoutRect) -> bool| {
+            match neighbor {
+                Some(neighbor) if covered(neighbor) => LayoutSize::zero(),
+                _ => radius,
             }
         };
 
-        self.add_primitive(
-            spatial_node_index,
-            clip_node_id,
-            prim_info,
-            Vec::new(),
-            text_run,
-        );
+        for (i, line) in lines.iter().enumerate() {
+            let above = i.checked_sub(1)
+                .map(|prev| &lines[prev])
+                .filter(|above| above.max.y == line.min.y);
+            let below = lines.get(i + 1).filter(|below| below.min.y == line.max.y);
+            let covers_left = |other: &LayoutRect| other.min.x <= line.min.x && line.min.x < other.max.x;
+            let covers_right = |other: &LayoutRect| other.min.x < line.max.x && line.max.x <= other.max.x;
+
+            let radii = BorderRadius {
+                top_left: corner(above, &covers_left),
+                top_right: corner(above, &covers_right),
+                bottom_left: corner(below, &covers_left),
+                bottom_right: corner(below, &covers_right),
+            };
+
+            let clips = vec![ClipItemKey {
+                kind: ClipItemKeyKind::rounded_rect(*line, radii, ClipMode::Clip),
+                spatial_node_index,
+            }];
+
+            self.add_primitive(
+                spatial_node_index,
+                clip_node_id,
+                &LayoutPrimitiveInfo { rect: *line, .. *prim_info },
+                clips,
+                PrimitiveKeyKind::Rectangle {
+                    color: PropertyBinding::Value(highlight.color.into()),
+                },
+            );
+        }
     }
 
     pub fn add_image(
@@ -3509,32 +4794,28 @@ impl<'a> SceneBuilder<'a> {
         clip_node_id: ClipNodeId,
         info: &LayoutPrimitiveInfo,
         stretch_size: LayoutSize,
-        mut tile_spacing: LayoutSize,
+        tile_spacing: LayoutSize,
         image_key: ImageKey,
         image_rendering: ImageRendering,
         alpha_type: AlphaType,
         color: ColorF,
     ) {
-        let mut prim_rect = info.rect;
-        simplify_repeated_primitive(&stretch_size, &mut tile_spacing, &mut prim_rect);
-        let info = LayoutPrimitiveInfo {
-            rect: prim_rect,
-            .. *info
-        };
+        let (info, image) = create_image(
+            info,
+            stretch_size,
+            tile_spacing,
+            image_key,
+            image_rendering,
+            alpha_type,
+            color,
+        );
 
         self.add_primitive(
             spatial_node_index,
             clip_node_id,
             &info,
             Vec::new(),
-            Image {
-                key: image_key,
-                tile_spacing: tile_spacing.into(),
-                stretch_size: stretch_size.into(),
-                color: color.into(),
-                image_rendering,
-                alpha_type,
-            },
+            image,
         );
     }
 
@@ -4055,9 +5336,11 @@ pub enum ShadowItem {
     Shadow(PendingShadow),
     Image(PendingPrimitive<Image>),
     LineDecoration(PendingPrimitive<LineDecoration>),
+    SkipInkLine(PendingPrimitive<LineDecoration>, LineSkipInkDataHandle),
     NormalBorder(PendingPrimitive<NormalBorderPrim>),
     Primitive(PendingPrimitive<PrimitiveKeyKind>),
     TextRun(PendingPrimitive<TextRun>),
+    VectorPath(PendingPrimitive<VectorPath>),
 }
 
 impl From<PendingPrimitive<Image>> for ShadowItem {
@@ -4090,6 +5373,12 @@ impl From<PendingPrimitive<TextRun>> for ShadowItem {
     }
 }
 
+impl From<PendingPrimitive<VectorPath>> for ShadowItem {
+    fn from(path: PendingPrimitive<VectorPath>) -> Self {
+        ShadowItem::VectorPath(path)
+    }
+}
+
 fn create_prim_instance(
     pic_index: PictureIndex,
     composite_mode_key: PictureCompositeKey,
@@ -4198,3 +5487,177 @@ fn read_gradient_stops(stops: ItemRange<GradientStop>) -> Vec<GradientStopKey> {
         }
     }).collect()
 }
+
+/// Return the raster space of a stacking context, given the one of its
+/// parent and the one it requests.
+fn raster_space_for_child(
+    parent_space: Option<&RasterSpace>,
+    requested_raster_space: RasterSpace,
+) -> RasterSpace {
+    match (parent_space, requested_raster_space) {
+        // If no parent space, just use the requested space
+        (None, _) => requested_raster_space,
+        // If screen, use the parent
+        (Some(parent_space), RasterSpace::Screen) => *parent_space,
+        // If currently screen, select the requested
+        (Some(RasterSpace::Screen), space) => space,
+        // If both local, take the maximum scale
+        (Some(RasterSpace::Local(parent_scale)), RasterSpace::Local(scale)) => RasterSpace::Local(parent_scale.max(scale)),
+    }
+}
+
+/// Retrieve the offset to allow converting a stacking context relative
+/// coordinate to be relative to the owning reference frame, also
+/// considering any external scroll offset on the provided spatial node.
+fn item_offset(
+    rf_offset: LayoutVector2D,
+    spatial_node_index: SpatialNodeIndex,
+    external_scroll_mapper: &mut ScrollOffsetMapper,
+    spatial_tree: &SceneSpatialTree,
+    content_band_offsets: &FastHashMap<SpatialNodeIndex, LayoutVector2D>,
+) -> LayoutVector2D {
+    // Get the external scroll offset, if applicable.
+    let scroll_offset = external_scroll_mapper.external_scroll_offset(
+        spatial_node_index,
+        spatial_tree,
+    );
+
+    let mut offset = rf_offset + scroll_offset;
+
+    // Items in a content band are offset from the start of the band.
+    if !content_band_offsets.is_empty() {
+        if let Some(band_offset) = content_band_offsets.get(&spatial_node_index) {
+            offset += *band_offset;
+        }
+    }
+
+    offset
+}
+
+/// Resolves the font a text item is drawn with, or returns `None` if
+/// nothing should be drawn with it.
+fn create_font_instance(
+    fonts: &SharedFontResources,
+    config: &FrameBuilderConfig,
+    font_instance_key: &FontInstanceKey,
+    color: &ColorF,
+    glyph_options: Option<GlyphOptions>,
+) -> Option<FontInstance> {
+    let shared_key = fonts.instance_keys.map_key(font_instance_key);
+    let font_instance = match fonts.instances.get_font_instance(shared_key) {
+        Some(instance) => instance,
+        None => {
+            warn!("Unknown font instance key");
+            debug!("key={:?} shared={:?}", font_instance_key, shared_key);
+            return None;
+        }
+    };
+
+    // Trivial early out checks
+    if font_instance.size <= FontSize::zero() {
+        return None;
+    }
+
+    // TODO(gw): Use a proper algorithm to select
+    // whether this item should be rendered with
+    // subpixel AA!
+    let mut render_mode = config
+        .default_font_render_mode
+        .limit_by(font_instance.render_mode);
+    let mut flags = font_instance.flags;
+    if let Some(options) = glyph_options {
+        render_mode = render_mode.limit_by(options.render_mode);
+        flags |= options.flags;
+    }
+
+    Some(FontInstance::new(
+        font_instance,
+        (*color).into(),
+        render_mode,
+        flags,
+    ))
+}
+
+/// Build a text run with glyphs relative to the origin of its primitive,
+/// which is at `prim_offset` in the space of its reference frame.
+fn create_text_run<I: IntoIterator<Item = GlyphInstance>>(
+    font: FontInstance,
+    glyphs: I,
+    prim_offset: LayoutVector2D,
+    requested_raster_space: RasterSpace,
+) -> TextRun {
+    // TODO(gw): It'd be nice not to have to allocate here for creating
+    //           the primitive key, when the common case is that the
+    //           hash will match and we won't end up creating a new
+    //           primitive template.
+    let glyphs = glyphs
+        .into_iter()
+        .map(|glyph| {
+            GlyphInstance {
+                index: glyph.index,
+                point: glyph.point - prim_offset,
+            }
+        })
+        .collect();
+
+    TextRun {
+        glyphs: Arc::new(glyphs),
+        font,
+        shadow: false,
+        requested_raster_space,
+    }
+}
+
+/// Build an image primitive, along with the info it's added with, whose rect
+/// is reduced to a single tile if it only shows one.
+fn create_image(
+    info: &LayoutPrimitiveInfo,
+    stretch_size: LayoutSize,
+    mut tile_spacing: LayoutSize,
+    image_key: ImageKey,
+    image_rendering: ImageRendering,
+    alpha_type: AlphaType,
+    color: ColorF,
+) -> (LayoutPrimitiveInfo, Image) {
+    let mut prim_rect = info.rect;
+    simplify_repeated_primitive(&stretch_size, &mut tile_spacing, &mut prim_rect);
+    let info = LayoutPrimitiveInfo {
+        rect: prim_rect,
+        .. *info
+    };
+
+    let image = Image {
+        key: image_key,
+        tile_spacing: tile_spacing.into(),
+        stretch_size: stretch_size.into(),
+        color: color.into(),
+        image_rendering,
+        alpha_type,
+    };
+
+    (info, image)
+}
+
+/// Build the key of a visible primitive while preparing its pipeline
+/// instance, and look it up in the interner.
+fn prepare_key<P>(
+    info: &LayoutPrimitiveInfo,
+    prim: P,
+    interner: &Interner<P>,
+) -> Option<Box<PreparedKey<P>>>
+where
+    P: InternablePrimitive + IsVisible,
+{
+    if !prim.is_visible() {
+        return None;
+    }
+
+    let key = prim.into_key(info);
+    let handle = interner.lookup(&key);
+
+    Some(Box::new(PreparedKey {
+        info: *info,
+        key,
+        handle,
+    }))
+}
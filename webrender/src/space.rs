@@ -65,9 +65,7 @@ impl<F, T> SpaceMapper<F, T> where F: fmt::Debug {
         self.kind = if self.ref_spatial_node_index == target_node_index {
             CoordinateSpaceMapping::Local
         } else if ref_spatial_node.coordinate_system_id == target_spatial_node.coordinate_system_id {
-            let scale_offset = ref_spatial_node.content_transform
-                .inverse()
-                .accumulate(&target_spatial_node.content_transform);
+            let scale_offset = target_spatial_node.content_transform_relative_to(ref_spatial_node);
             CoordinateSpaceMapping::ScaleOffset(scale_offset)
         } else {
             let transform = spatial_tree
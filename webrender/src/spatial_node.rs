@@ -12,7 +12,7 @@ use crate::spatial_tree::{CoordinateSystem, SpatialNodeIndex, TransformUpdateSta
 use crate::spatial_tree::{CoordinateSystemId};
 use euclid::{Vector2D, SideOffsets2D};
 use crate::scene::SceneProperties;
-use crate::util::{LayoutFastTransform, MatrixHelpers, ScaleOffset, TransformedRectKind, PointHelpers};
+use crate::util::{LayoutFastTransform, MatrixHelpers, PreciseOffset, ScaleOffset, TransformedRectKind, PointHelpers};
 
 /// The kind of a spatial node uid. These are required because we currently create external
 /// nodes during DL building, but the internal nodes aren't created until scene building.
@@ -33,6 +33,16 @@ pub enum SpatialNodeUidKind {
     External {
         key: SpatialTreeItemKey,
     },
+    /// Internal reference frame created during scene building to hold the content
+    /// band of a very tall scroll frame
+    ContentBand {
+        key: SpatialTreeItemKey,
+    },
+    /// Internal reference frame created during scene building to move a scrollbar
+    /// thumb along its track, identified by the binding of its transform
+    ScrollbarThumb {
+        key: PropertyBindingId,
+    },
 }
 
 /// A unique identifier for a spatial node, that is stable across display lists
@@ -92,6 +102,34 @@ impl SpatialNodeUid {
             instance_id,
         }
     }
+
+    pub fn content_band(
+        key: SpatialTreeItemKey,
+        pipeline_id: PipelineId,
+        instance_id: PipelineInstanceId,
+    ) -> Self {
+        SpatialNodeUid {
+            kind: SpatialNodeUidKind::ContentBand {
+                key,
+            },
+            pipeline_id,
+            instance_id,
+        }
+    }
+
+    pub fn scrollbar_thumb(
+        key: PropertyBindingId,
+        pipeline_id: PipelineId,
+        instance_id: PipelineInstanceId,
+    ) -> Self {
+        SpatialNodeUid {
+            kind: SpatialNodeUidKind::ScrollbarThumb {
+                key,
+            },
+            pipeline_id,
+            instance_id,
+        }
+    }
 }
 
 /// Defines the content of a spatial node. If the values in the descriptor don't
@@ -171,6 +209,7 @@ impl SceneSpatialNode {
         pipeline_id: PipelineId,
         is_root_coord_system: bool,
         is_pipeline_root: bool,
+        is_content_band: bool,
     ) -> Self {
         let info = ReferenceFrameInfo {
             transform_style,
@@ -178,6 +217,7 @@ impl SceneSpatialNode {
             kind,
             origin_in_parent_reference_frame,
             is_pipeline_root,
+            is_content_band,
         };
         Self::new(
             pipeline_id,
@@ -265,6 +305,11 @@ pub struct SpatialNode {
     /// Content scale/offset relative to the coordinate system.
     pub content_transform: ScaleOffset,
 
+    /// The offset of `content_transform`, in double precision. Relative transforms
+    /// between nodes in the same coordinate system are computed from this, so that
+    /// they stay accurate far away from the coordinate system origin.
+    pub precise_content_offset: PreciseOffset,
+
     /// Snapping scale/offset relative to the coordinate system. If None, then
     /// we should not snap entities bound to this spatial node.
     pub snapping_transform: Option<ScaleOffset>,
@@ -320,6 +365,35 @@ fn snap_offset<OffsetUnits, ScaleUnits>(
     )
 }
 
+/// Equivalent of `snap_offset` for offsets in double precision.
+fn snap_precise_offset<ScaleUnits>(
+    offset: PreciseOffset,
+    scale: Vector2D<f32, ScaleUnits>,
+) -> PreciseOffset {
+    let snap = |offset: f64, scale: f32| {
+        if scale != 0.0 {
+            let scale = scale as f64;
+            (offset * scale + 0.5).floor() / scale
+        } else {
+            offset
+        }
+    };
+
+    PreciseOffset::new(snap(offset.x, scale.x), snap(offset.y, scale.y))
+}
+
+/// Accumulate a scaled offset in to a precise offset.
+fn accumulate_precise_offset<ScaleUnits>(
+    precise_offset: PreciseOffset,
+    scale: Vector2D<f32, ScaleUnits>,
+    offset: PreciseOffset,
+) -> PreciseOffset {
+    PreciseOffset::new(
+        precise_offset.x + scale.x as f64 * offset.x,
+        precise_offset.y + scale.y as f64 * offset.y,
+    )
+}
+
 impl SpatialNode {
     pub fn add_child(&mut self, child: SpatialNodeIndex) {
         self.children.push(child);
@@ -355,6 +429,7 @@ impl SpatialNode {
         self.invertible = false;
         self.viewport_transform = ScaleOffset::identity();
         self.content_transform = ScaleOffset::identity();
+        self.precise_content_offset = PreciseOffset::zero();
         self.coordinate_system_id = state.current_coordinate_system_id;
     }
 
@@ -400,6 +475,7 @@ impl SpatialNode {
         match self.node_type {
             SpatialNodeType::ReferenceFrame(ref mut info) => {
                 let mut cs_scale_offset = ScaleOffset::identity();
+                let mut precise_offset = PreciseOffset::zero();
                 let mut coordinate_system_id = state.current_coordinate_system_id;
 
                 // Resolve the transform against any property bindings.
@@ -463,18 +539,34 @@ impl SpatialNode {
                     // incompatible coordinate system.
                     match ScaleOffset::from_transform(&relative_transform) {
                         Some(ref scale_offset) => {
+                            let scale = state.coordinate_system_relative_scale_offset.scale;
+
+                            // Recompute the offset in double precision, since the accumulated
+                            // scroll offset may be large. The origin and the translation of the
+                            // source transform are added separately, as they may cancel out.
+                            let source = source_transform.to_transform();
+                            let origin = info.origin_in_parent_reference_frame;
+                            let mut local_offset = snap_precise_offset(
+                                state.parent_accumulated_precise_scroll_offset,
+                                scale,
+                            );
+                            local_offset.x += origin.x as f64 + source.m41 as f64;
+                            local_offset.y += origin.y as f64 + source.m42 as f64;
+
                             // We generally do not want to snap animated transforms as it causes jitter.
                             // However, we do want to snap the visual viewport offset when scrolling.
                             // This may still cause jitter when zooming, unfortunately.
-                            let mut maybe_snapped = scale_offset.clone();
                             if let ReferenceFrameKind::Transform { should_snap: true, .. } = info.kind {
-                                maybe_snapped.offset = snap_offset(
-                                    scale_offset.offset,
-                                    state.coordinate_system_relative_scale_offset.scale,
-                                );
+                                local_offset = snap_precise_offset(local_offset, scale);
                             }
+                            precise_offset = accumulate_precise_offset(
+                                state.coordinate_system_relative_precise_offset,
+                                scale,
+                                local_offset,
+                            );
                             cs_scale_offset =
-                                state.coordinate_system_relative_scale_offset.accumulate(&maybe_snapped);
+                                state.coordinate_system_relative_scale_offset.accumulate(scale_offset);
+                            cs_scale_offset.offset = precise_offset.cast::<f32>();
                         }
                         None => reset_cs_id = true,
                     }
@@ -517,6 +609,7 @@ impl SpatialNode {
                 self.coordinate_system_id = coordinate_system_id;
                 self.viewport_transform = cs_scale_offset;
                 self.content_transform = cs_scale_offset;
+                self.precise_content_offset = precise_offset;
             }
             _ => {
                 // We calculate this here to avoid a double-borrow later.
@@ -528,15 +621,33 @@ impl SpatialNode {
                 // The transformation for the bounds of our viewport is the parent reference frame
                 // transform, plus any accumulated scroll offset from our parents, plus any offset
                 // provided by our own sticky positioning.
-                let accumulated_offset = state.parent_accumulated_scroll_offset + sticky_offset;
-                self.viewport_transform = state.coordinate_system_relative_scale_offset
-                    .offset(snap_offset(accumulated_offset, state.coordinate_system_relative_scale_offset.scale).to_untyped());
+                // These are accumulated in double precision, and then rebased to the
+                // coordinate system in single precision.
+                let scale = state.coordinate_system_relative_scale_offset.scale;
+                let accumulated_offset = state.parent_accumulated_precise_scroll_offset +
+                    sticky_offset.to_untyped().cast::<f64>();
+                let viewport_offset = accumulate_precise_offset(
+                    state.coordinate_system_relative_precise_offset,
+                    scale,
+                    snap_precise_offset(accumulated_offset, scale),
+                );
+                self.viewport_transform = ScaleOffset {
+                    scale,
+                    offset: viewport_offset.cast::<f32>(),
+                };
 
                 // The transformation for any content inside of us is the viewport transformation, plus
                 // whatever scrolling offset we supply as well.
-                let added_offset = accumulated_offset + self.scroll_offset();
-                self.content_transform = state.coordinate_system_relative_scale_offset
-                    .offset(snap_offset(added_offset, state.coordinate_system_relative_scale_offset.scale).to_untyped());
+                let added_offset = accumulated_offset + self.scroll_offset().to_untyped().cast::<f64>();
+                self.precise_content_offset = accumulate_precise_offset(
+                    state.coordinate_system_relative_precise_offset,
+                    scale,
+                    snap_precise_offset(added_offset, scale),
+                );
+                self.content_transform = ScaleOffset {
+                    scale,
+                    offset: self.precise_content_offset.cast::<f32>(),
+                };
 
                 if let SpatialNodeType::StickyFrame(ref mut info) = self.node_type {
                     info.current_offset = sticky_offset;
@@ -686,6 +797,7 @@ impl SpatialNode {
                 // offsets actually adjust the node position itself, whereas scroll offsets
                 // only apply to contents inside the node.
                 state.parent_accumulated_scroll_offset += info.current_offset;
+                state.parent_accumulated_precise_scroll_offset += info.current_offset.to_untyped().cast::<f64>();
                 // We want nested sticky items to take into account the shift
                 // we applied as well.
                 state.nearest_scrolling_ancestor_offset += info.current_offset;
@@ -695,6 +807,7 @@ impl SpatialNode {
             }
             SpatialNodeType::ScrollFrame(ref scrolling) => {
                 state.parent_accumulated_scroll_offset += scrolling.offset();
+                state.parent_accumulated_precise_scroll_offset += scrolling.offset().to_untyped().cast::<f64>();
                 state.nearest_scrolling_ancestor_offset = scrolling.offset();
                 state.nearest_scrolling_ancestor_viewport = scrolling.viewport_rect;
                 state.preserves_3d = false;
@@ -706,7 +819,9 @@ impl SpatialNode {
                 state.scroll_offset = LayoutVector2D::zero();
                 state.preserves_3d = info.transform_style == TransformStyle::Preserve3D;
                 state.parent_accumulated_scroll_offset = LayoutVector2D::zero();
+                state.parent_accumulated_precise_scroll_offset = PreciseOffset::zero();
                 state.coordinate_system_relative_scale_offset = self.content_transform;
+                state.coordinate_system_relative_precise_offset = self.precise_content_offset;
                 let translation = -info.origin_in_parent_reference_frame;
                 state.nearest_scrolling_ancestor_viewport =
                     state.nearest_scrolling_ancestor_viewport
@@ -715,6 +830,27 @@ impl SpatialNode {
         }
     }
 
+    /// The scale/offset that maps the content of this node in to the content of
+    /// `other`, which must be in the same coordinate system. The offset is computed
+    /// in double precision, so is accurate even if both nodes are a long way from
+    /// the origin of the coordinate system.
+    pub fn content_transform_relative_to(&self, other: &SpatialNode) -> ScaleOffset {
+        let mut scale_offset = other.content_transform
+            .inverse()
+            .accumulate(&self.content_transform);
+
+        let scale = other.content_transform.scale;
+        if scale.x != 0.0 && scale.y != 0.0 {
+            let offset = self.precise_content_offset - other.precise_content_offset;
+            scale_offset.offset = Vector2D::new(
+                (offset.x / scale.x as f64) as f32,
+                (offset.y / scale.y as f64) as f32,
+            );
+        }
+
+        scale_offset
+    }
+
     pub fn scroll_offset(&self) -> LayoutVector2D {
         match self.node_type {
             SpatialNodeType::ScrollFrame(ref scrolling) => scrolling.offset(),
@@ -879,6 +1015,12 @@ pub struct ReferenceFrameInfo {
     /// True if this is the root reference frame for a given pipeline. This is only used
     /// by the hit-test code, perhaps we can change the interface to not require this.
     pub is_pipeline_root: bool,
+
+    /// True if this reference frame was created by scene building to hold the
+    /// content band of a tall scroll frame. These are selected as scroll roots
+    /// in place of their parent scroll frame, so that the picture cache slice
+    /// is positioned around the viewport.
+    pub is_content_band: bool,
 }
 
 #[derive(Clone, Debug, PartialEq)]
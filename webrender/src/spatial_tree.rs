@@ -13,7 +13,7 @@ use crate::scene::SceneProperties;
 use crate::spatial_node::{ReferenceFrameInfo, SpatialNode, SpatialNodeType, StickyFrameInfo, SpatialNodeDescriptor};
 use crate::spatial_node::{SpatialNodeUid, ScrollFrameKind, SceneSpatialNode, SpatialNodeInfo, SpatialNodeUidKind};
 use std::{ops, u32};
-use crate::util::{FastTransform, LayoutToWorldFastTransform, MatrixHelpers, ScaleOffset, scale_factors};
+use crate::util::{FastTransform, LayoutToWorldFastTransform, MatrixHelpers, PreciseOffset, ScaleOffset, scale_factors};
 use smallvec::SmallVec;
 use std::collections::hash_map::Entry;
 use crate::util::TransformedRectKind;
@@ -263,6 +263,7 @@ impl SceneSpatialTree {
             PipelineId::dummy(),
             true,
             true,
+            false,
         );
 
         tree.add_spatial_node(node, SpatialNodeUid::root());
@@ -339,11 +340,15 @@ impl SceneSpatialTree {
         let mut real_scroll_root = self.root_reference_frame_index;
         let mut outermost_scroll_root = self.root_reference_frame_index;
         let mut node_index = spatial_node_index;
+        // The content band (if any) we walked up through to reach the current node
+        let mut content_band = None;
 
         while node_index != self.root_reference_frame_index {
             let node = self.get_node_info(node_index);
+            let mut is_content_band = false;
             match node.node_type {
                 SpatialNodeType::ReferenceFrame(ref info) => {
+                    is_content_band = info.is_content_band;
                     match info.kind {
                         ReferenceFrameKind::Transform { is_2d_scale_translation: true, .. } => {
                             // We can handle scroll nodes that pass through a 2d scale/translation node
@@ -387,14 +392,17 @@ impl SceneSpatialTree {
                                 if info.viewport_rect.width() > MIN_SCROLL_ROOT_SIZE &&
                                    info.viewport_rect.height() > MIN_SCROLL_ROOT_SIZE {
                                     // If we've found a root that is scrollable, and a reasonable
-                                    // size, select that as the current root for this node
-                                    real_scroll_root = node_index;
+                                    // size, select that as the current root for this node. If the
+                                    // content is in a content band, select the band instead, so
+                                    // that the slice is positioned around the viewport.
+                                    real_scroll_root = content_band.unwrap_or(node_index);
                                 }
                             }
                         }
                     }
                 }
             }
+            content_band = if is_content_band { Some(node_index) } else { None };
             node_index = node.parent.expect("unable to find parent node");
         }
 
@@ -517,6 +525,10 @@ impl SceneSpatialTree {
             SpatialNodeUidKind::InternalReferenceFrame { .. } => true,
             _ => false,
         };
+        let is_content_band = match uid.kind {
+            SpatialNodeUidKind::ContentBand { .. } => true,
+            _ => false,
+        };
 
         let node = SceneSpatialNode::new_reference_frame(
             Some(parent_index),
@@ -527,6 +539,7 @@ impl SceneSpatialTree {
             pipeline_id,
             is_root_coord_system,
             is_pipeline_root,
+            is_content_band,
         );
         self.add_spatial_node(node, uid)
     }
@@ -661,6 +674,12 @@ pub struct TransformUpdateState {
     /// Scale and offset from the coordinate system that started this compatible coordinate system.
     pub coordinate_system_relative_scale_offset: ScaleOffset,
 
+    /// The offset of `coordinate_system_relative_scale_offset`, in double precision.
+    pub coordinate_system_relative_precise_offset: PreciseOffset,
+
+    /// `parent_accumulated_scroll_offset`, in double precision.
+    pub parent_accumulated_precise_scroll_offset: PreciseOffset,
+
     /// True if this node is transformed by an invertible transform.  If not, display items
     /// transformed by this node will not be displayed and display items not transformed by this
     /// node will not be clipped by clips that are transformed by this node.
@@ -842,6 +861,7 @@ impl SpatialTree {
                     let node = SpatialNode {
                         viewport_transform: ScaleOffset::identity(),
                         content_transform: ScaleOffset::identity(),
+                        precise_content_offset: PreciseOffset::zero(),
                         snapping_transform: None,
                         coordinate_system_id: CoordinateSystemId(0),
                         transform_kind: TransformedRectKind::AxisAligned,
@@ -1010,9 +1030,7 @@ impl SpatialTree {
         );
 
         if child.coordinate_system_id == parent.coordinate_system_id {
-            let scale_offset = parent.content_transform
-                .inverse()
-                .accumulate(&child.content_transform);
+            let scale_offset = child.content_transform_relative_to(parent);
             return CoordinateSpaceMapping::ScaleOffset(scale_offset);
         }
 
@@ -1153,6 +1171,8 @@ impl SpatialTree {
             nearest_scrolling_ancestor_viewport: LayoutRect::zero(),
             current_coordinate_system_id: CoordinateSystemId::root(),
             coordinate_system_relative_scale_offset: ScaleOffset::identity(),
+            coordinate_system_relative_precise_offset: PreciseOffset::zero(),
+            parent_accumulated_precise_scroll_offset: PreciseOffset::zero(),
             invertible: true,
             preserves_3d: false,
             is_ancestor_or_self_zooming: false,
@@ -1494,6 +1514,46 @@ fn test_cst_simple_translation() {
     test_pt(100.0, 100.0, &st, child3, root, 400.0, 350.0);
 }
 
+#[test]
+fn test_cst_large_translation() {
+    // Translations that cancel out far away from the origin, beyond the
+    // range in which f32 can represent fractional offsets.
+
+    let mut cst = SceneSpatialTree::new();
+    let root_reference_frame_index = cst.root_reference_frame_index();
+
+    let root = add_reference_frame(
+        &mut cst,
+        root_reference_frame_index,
+        LayoutTransform::identity(),
+        LayoutVector2D::zero(),
+        SpatialTreeItemKey::new(0, 0),
+    );
+
+    let child1 = add_reference_frame(
+        &mut cst,
+        root,
+        LayoutTransform::translation(0.0, 0.5, 0.0),
+        LayoutVector2D::new(0.0, 33554432.0),
+        SpatialTreeItemKey::new(0, 1),
+    );
+
+    let child2 = add_reference_frame(
+        &mut cst,
+        child1,
+        LayoutTransform::translation(0.25, 0.25, 0.0),
+        LayoutVector2D::new(0.0, -33554432.0),
+        SpatialTreeItemKey::new(0, 2),
+    );
+
+    let mut st = SpatialTree::new();
+    st.apply_updates(cst.end_frame_and_get_pending_updates());
+    st.update_tree(&SceneProperties::new());
+
+    test_pt(0.0, 0.0, &st, child2, root, 0.25, 0.75);
+    test_pt(100.0, 100.0, &st, child2, root, 100.25, 100.75);
+}
+
 #[test]
 fn test_cst_simple_scale() {
     // Basic scale only
@@ -1781,6 +1841,100 @@ fn test_find_scroll_root_sub_scroll_frame() {
     assert_eq!(st.find_scroll_root(sub_scroll), root_scroll);
 }
 
+/// Tests that we select the content band rather than the scroll frame it splits.
+#[test]
+fn test_find_scroll_root_content_band() {
+    let mut st = SceneSpatialTree::new();
+    let pid = PipelineInstanceId::new(0);
+
+    let root = st.add_reference_frame(
+        st.root_reference_frame_index(),
+        TransformStyle::Flat,
+        PropertyBinding::Value(LayoutTransform::identity()),
+        ReferenceFrameKind::Transform {
+            is_2d_scale_translation: true,
+            should_snap: true,
+            paired_with_perspective: false,
+        },
+        LayoutVector2D::new(0.0, 0.0),
+        PipelineId::dummy(),
+        SpatialNodeUid::external(SpatialTreeItemKey::new(0, 0), PipelineId::dummy(), pid),
+    );
+
+    let scroll = st.add_scroll_frame(
+        root,
+        ExternalScrollId(1, PipelineId::dummy()),
+        PipelineId::dummy(),
+        &LayoutRect::from_size(LayoutSize::new(400.0, 400.0)),
+        &LayoutSize::new(400.0, 1000000.0),
+        ScrollFrameKind::Explicit,
+        LayoutVector2D::new(0.0, 0.0),
+        APZScrollGeneration::default(),
+        HasScrollLinkedEffect::No,
+        SpatialNodeUid::external(SpatialTreeItemKey::new(0, 1), PipelineId::dummy(), pid),
+    );
+
+    let band = st.add_reference_frame(
+        scroll,
+        TransformStyle::Flat,
+        PropertyBinding::Value(LayoutTransform::identity()),
+        ReferenceFrameKind::Transform {
+            is_2d_scale_translation: true,
+            should_snap: false,
+            paired_with_perspective: false,
+        },
+        LayoutVector2D::new(0.0, 65536.0),
+        PipelineId::dummy(),
+        SpatialNodeUid::content_band(SpatialTreeItemKey::new(0, 1), PipelineId::dummy(), pid),
+    );
+
+    let sub_scroll = st.add_scroll_frame(
+        band,
+        ExternalScrollId(2, PipelineId::dummy()),
+        PipelineId::dummy(),
+        &LayoutRect::from_size(LayoutSize::new(400.0, 400.0)),
+        &LayoutSize::new(800.0, 400.0),
+        ScrollFrameKind::Explicit,
+        LayoutVector2D::new(0.0, 0.0),
+        APZScrollGeneration::default(),
+        HasScrollLinkedEffect::No,
+        SpatialNodeUid::external(SpatialTreeItemKey::new(0, 2), PipelineId::dummy(), pid),
+    );
+
+    let nested = st.add_reference_frame(
+        band,
+        TransformStyle::Flat,
+        PropertyBinding::Value(LayoutTransform::identity()),
+        ReferenceFrameKind::Transform {
+            is_2d_scale_translation: true,
+            should_snap: false,
+            paired_with_perspective: false,
+        },
+        LayoutVector2D::new(0.0, -65536.0),
+        PipelineId::dummy(),
+        SpatialNodeUid::external(SpatialTreeItemKey::new(0, 3), PipelineId::dummy(), pid),
+    );
+
+    let nested_band = st.add_reference_frame(
+        nested,
+        TransformStyle::Flat,
+        PropertyBinding::Value(LayoutTransform::identity()),
+        ReferenceFrameKind::Transform {
+            is_2d_scale_translation: true,
+            should_snap: false,
+            paired_with_perspective: false,
+        },
+        LayoutVector2D::new(0.0, 65536.0),
+        PipelineId::dummy(),
+        SpatialNodeUid::content_band(SpatialTreeItemKey::new(0, 3), PipelineId::dummy(), pid),
+    );
+
+    assert_eq!(st.find_scroll_root(scroll), scroll);
+    assert_eq!(st.find_scroll_root(band), band);
+    assert_eq!(st.find_scroll_root(sub_scroll), band);
+    assert_eq!(st.find_scroll_root(nested_band), band);
+}
+
 /// Tests that we select the sub scroll frame when the root scroll frame is not scrollable.
 #[test]
 fn test_find_scroll_root_not_scrollable() {
@@ -25,7 +25,6 @@ use crate::texture_pack::{
 use std::cell::Cell;
 use std::mem;
 use std::rc::Rc;
-use euclid::size2;
 use malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
 
 /// Information about which shader will use the entry.
@@ -297,6 +296,27 @@ impl BudgetType {
     pub fn iter() -> impl Iterator<Item = BudgetType> {
         BudgetType::VALUES.iter().cloned()
     }
+
+    fn is_glyphs(self) -> bool {
+        match self {
+            BudgetType::SharedColor8Glyphs | BudgetType::SharedAlpha8Glyphs => true,
+            _ => false,
+        }
+    }
+}
+
+/// Returns the fraction of the pixels of the given atlases that are not
+/// allocated, or zero if no atlas texture exists.
+fn fragmentation(lists: &[&AllocatorList<ShelfAllocator, TextureParameters>]) -> f32 {
+    let mut allocated = 0.0;
+    let mut total = 0.0;
+    for list in lists {
+        let size = list.size() as f32;
+        allocated += list.allocated_space() as f32;
+        total += list.allocated_textures() as f32 * size * size;
+    }
+
+    if total > 0.0 { 1.0 - allocated / total } else { 0.0 }
 }
 
 /// A set of lazily allocated, fixed size, texture arrays for each format the
@@ -312,12 +332,18 @@ struct SharedTextures {
     color8_glyphs: AllocatorList<ShelfAllocator, TextureParameters>,
     bytes_per_texture_of_type: [i32 ; BudgetType::COUNT],
     next_compaction_idx: usize,
+    glyph_ideal_utilization: f32,
+    image_ideal_utilization: f32,
+    compaction_pixels_per_frame: i32,
 }
 
 impl SharedTextures {
     /// Mints a new set of shared textures.
     fn new(color_formats: TextureFormatPair<ImageFormat>, config: &TextureCacheConfig) -> Self {
         let mut bytes_per_texture_of_type = [0 ; BudgetType::COUNT];
+        let num_columns = |size: i32| {
+            if size >= config.two_columns_min_texture_size { 2 } else { 1 }
+        };
 
         // Used primarily for cached shadow masks. There can be lots of
         // these on some pages like francine, but most pages don't use it
@@ -331,7 +357,7 @@ impl SharedTextures {
             config.alpha8_texture_size,
             ShelfAllocatorOptions {
                 num_columns: 1,
-                alignment: size2(8, 8),
+                alignment: config.mask_alignment.to_untyped(),
                 .. ShelfAllocatorOptions::default()
             },
             TextureParameters {
@@ -346,8 +372,8 @@ impl SharedTextures {
         let alpha8_glyphs = AllocatorList::new(
             config.alpha8_glyph_texture_size,
             ShelfAllocatorOptions {
-                num_columns: if config.alpha8_glyph_texture_size >= 1024 { 2 } else { 1 },
-                alignment: size2(4, 8),
+                num_columns: num_columns(config.alpha8_glyph_texture_size),
+                alignment: config.glyph_alignment.to_untyped(),
                 .. ShelfAllocatorOptions::default()
             },
             TextureParameters {
@@ -363,8 +389,8 @@ impl SharedTextures {
         let alpha16_linear = AllocatorList::new(
             config.alpha16_texture_size,
             ShelfAllocatorOptions {
-                num_columns: if config.alpha16_texture_size >= 1024 { 2 } else { 1 },
-                alignment: size2(8, 8),
+                num_columns: num_columns(config.alpha16_texture_size),
+                alignment: config.mask_alignment.to_untyped(),
                 .. ShelfAllocatorOptions::default()
             },
             TextureParameters {
@@ -380,8 +406,8 @@ impl SharedTextures {
         let color8_linear = AllocatorList::new(
             config.color8_linear_texture_size,
             ShelfAllocatorOptions {
-                num_columns: if config.color8_linear_texture_size >= 1024 { 2 } else { 1 },
-                alignment: size2(16, 16),
+                num_columns: num_columns(config.color8_linear_texture_size),
+                alignment: config.image_alignment.to_untyped(),
                 .. ShelfAllocatorOptions::default()
             },
             TextureParameters {
@@ -397,8 +423,8 @@ impl SharedTextures {
         let color8_glyphs = AllocatorList::new(
             config.color8_glyph_texture_size,
             ShelfAllocatorOptions {
-                num_columns: if config.color8_glyph_texture_size >= 1024 { 2 } else { 1 },
-                alignment: size2(4, 8),
+                num_columns: num_columns(config.color8_glyph_texture_size),
+                alignment: config.glyph_alignment.to_untyped(),
                 .. ShelfAllocatorOptions::default()
             },
             TextureParameters {
@@ -434,6 +460,9 @@ impl SharedTextures {
             color8_nearest,
             bytes_per_texture_of_type,
             next_compaction_idx: 0,
+            glyph_ideal_utilization: config.glyph_ideal_utilization,
+            image_ideal_utilization: config.image_ideal_utilization,
+            compaction_pixels_per_frame: config.compaction_pixels_per_frame,
         }
     }
 
@@ -518,8 +547,11 @@ struct CacheAllocParams {
 
 /// Startup parameters for the texture cache.
 ///
-/// Texture sizes must be at least 512.
-#[derive(Clone)]
+/// Texture sizes must be at least 512. `DEFAULT` is tuned for general web
+/// content, `GLYPH_HEAVY` and `IMAGE_HEAVY` are starting points for workloads
+/// dominated by text or by images. The "Atlas * fragmentation" profiler
+/// counters help validate a configuration.
+#[derive(Clone, Debug)]
 pub struct TextureCacheConfig {
     pub color8_linear_texture_size: i32,
     pub color8_nearest_texture_size: i32,
@@ -527,6 +559,22 @@ pub struct TextureCacheConfig {
     pub alpha8_texture_size: i32,
     pub alpha8_glyph_texture_size: i32,
     pub alpha16_texture_size: i32,
+    /// Allocation granularity of the glyph atlases.
+    pub glyph_alignment: DeviceIntSize,
+    /// Allocation granularity of the linear color image atlas.
+    pub image_alignment: DeviceIntSize,
+    /// Allocation granularity of the A8 and A16 mask atlases.
+    pub mask_alignment: DeviceIntSize,
+    /// Atlases at least this large are split into two columns of shelves.
+    pub two_columns_min_texture_size: i32,
+    /// Fraction of a glyph atlas that can be allocated before cold glyphs
+    /// start being evicted. Higher values let the cache hold more glyphs,
+    /// lower values leave room to avoid allocating another texture.
+    pub glyph_ideal_utilization: f32,
+    /// Same as `glyph_ideal_utilization`, for the other atlases.
+    pub image_ideal_utilization: f32,
+    /// Maximum number of pixels moved per frame when compacting atlases.
+    pub compaction_pixels_per_frame: i32,
 }
 
 impl TextureCacheConfig {
@@ -537,6 +585,47 @@ impl TextureCacheConfig {
         alpha8_texture_size: 1024,
         alpha8_glyph_texture_size: 2048,
         alpha16_texture_size: 512,
+        glyph_alignment: DeviceIntSize::new(4, 8),
+        image_alignment: DeviceIntSize::new(16, 16),
+        mask_alignment: DeviceIntSize::new(8, 8),
+        two_columns_min_texture_size: 1024,
+        glyph_ideal_utilization: 2.0 / 3.0,
+        image_ideal_utilization: 1.0 / 3.0,
+        compaction_pixels_per_frame: 512 * 512,
+    };
+
+    /// For devices with little memory: half the default atlas sizes, so that
+    /// each additional atlas texture costs less. Content that doesn't fit in
+    /// a single atlas ends up in more textures, which can break batches.
+    pub const LOW_MEMORY: Self = TextureCacheConfig {
+        color8_linear_texture_size: 1024,
+        color8_glyph_texture_size: 1024,
+        alpha8_texture_size: 512,
+        alpha8_glyph_texture_size: 1024,
+        .. TextureCacheConfig::DEFAULT
+    };
+
+    /// For text-heavy content such as editors and terminals: larger glyph
+    /// atlases that are allowed to fill up more before evicting.
+    pub const GLYPH_HEAVY: Self = TextureCacheConfig {
+        color8_linear_texture_size: 1024,
+        color8_glyph_texture_size: 4096,
+        alpha8_glyph_texture_size: 4096,
+        glyph_alignment: DeviceIntSize::new(2, 4),
+        glyph_ideal_utilization: 0.8,
+        .. TextureCacheConfig::DEFAULT
+    };
+
+    /// For image-heavy content: a larger image atlas with a coarser alignment,
+    /// and more compaction work per frame to keep it from fragmenting.
+    pub const IMAGE_HEAVY: Self = TextureCacheConfig {
+        color8_linear_texture_size: 4096,
+        color8_glyph_texture_size: 1024,
+        alpha8_glyph_texture_size: 1024,
+        image_alignment: DeviceIntSize::new(32, 32),
+        image_ideal_utilization: 0.5,
+        compaction_pixels_per_frame: 1024 * 1024,
+        .. TextureCacheConfig::DEFAULT
     };
 }
 
@@ -694,6 +783,31 @@ impl TextureCache {
         self.pending_updates.note_clear();
     }
 
+    /// Evicts all of the automatically managed entries holding glyphs, or all
+    /// of the other ones if `glyphs` is false. Entries with manual eviction are
+    /// left alone. Shared textures that end up empty are released at the end
+    /// of the next frame.
+    pub fn evict_automatic_entries(&mut self, glyphs: bool) {
+        for budget_type in BudgetType::iter() {
+            if budget_type.is_glyphs() != glyphs {
+                continue;
+            }
+            while let Some(entry) = self.lru_cache.pop_oldest(budget_type as u8) {
+                entry.evict();
+                self.free(&entry);
+            }
+        }
+    }
+
+    /// Memory used by the entries of the cache, in bytes. `glyphs` selects
+    /// between the glyph entries and all the other ones.
+    pub fn bytes_allocated(&self, glyphs: bool) -> usize {
+        BudgetType::iter()
+            .filter(|budget_type| budget_type.is_glyphs() == glyphs)
+            .map(|budget_type| self.bytes_allocated[budget_type as usize])
+            .sum()
+    }
+
     /// Called at the beginning of each frame.
     pub fn begin_frame(&mut self, stamp: FrameStamp, profile: &mut TransactionProfile) {
         debug_assert!(!self.now.is_valid());
@@ -741,6 +855,16 @@ impl TextureCache {
         profile.set(profiler::ATLAS_RGBA8_NEAREST_TEXTURES, self.shared_textures.color8_nearest.allocated_textures());
         profile.set(profiler::ATLAS_RGBA8_GLYPHS_PIXELS, self.shared_textures.color8_glyphs.allocated_space());
         profile.set(profiler::ATLAS_RGBA8_GLYPHS_TEXTURES, self.shared_textures.color8_glyphs.allocated_textures());
+        profile.set(profiler::ATLAS_GLYPHS_FRAGMENTATION, fragmentation(&[
+            &self.shared_textures.color8_glyphs,
+            &self.shared_textures.alpha8_glyphs,
+        ]));
+        profile.set(profiler::ATLAS_IMAGES_FRAGMENTATION, fragmentation(&[
+            &self.shared_textures.color8_linear,
+            &self.shared_textures.color8_nearest,
+            &self.shared_textures.alpha8_linear,
+            &self.shared_textures.alpha16_linear,
+        ]));
 
         let shared_bytes = [
             BudgetType::SharedColor8Linear,
@@ -772,9 +896,8 @@ impl TextureCache {
         let idx = self.shared_textures.next_compaction_idx;
 
         // Number of moved pixels after which we stop attempting to move more items for this frame.
-        // The constant is up for adjustment, the main goal is to avoid causing frame spikes on
-        // low end GPUs.
-        let area_threshold = 512*512; 
+        // The main goal is to avoid causing frame spikes on low end GPUs.
+        let area_threshold = self.shared_textures.compaction_pixels_per_frame;
 
         let mut changes = Vec::new();
         allocator_lists[idx].try_compaction(area_threshold, &mut changes);
@@ -1092,6 +1215,58 @@ impl TextureCache {
         self.shared_textures.alpha8_linear.dump_as_svg(output)
     }
 
+    /// Write a description of every live entry (texture, rect, format and
+    /// last used frame) to `output`, one entry per line.
+    pub fn write_manifest(&self, output: &mut dyn std::io::Write) -> std::io::Result<()> {
+        writeln!(output, "# frame {}", self.now.frame_id().as_u64())?;
+        writeln!(output, "# texture kind x y width height format filter last_used_frame")?;
+
+        let entries = self.lru_cache.iter().map(|entry| (entry, "auto"))
+            .chain(self.manual_entries.iter().map(|entry| (entry, "manual")));
+
+        for (entry, eviction) in entries {
+            let kind = match entry.details {
+                EntryDetails::Standalone { .. } => "standalone",
+                EntryDetails::Cache { .. } => "shared",
+            };
+            let origin = entry.details.describe();
+            writeln!(
+                output,
+                "{} {}-{} {} {} {} {} {:?} {:?} {}",
+                entry.texture_id.0,
+                kind,
+                eviction,
+                origin.x,
+                origin.y,
+                entry.size.width,
+                entry.size.height,
+                entry.input_format,
+                entry.filter,
+                entry.last_access.frame_id().as_u64(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the texture cache manifest and the layout of each shared atlas
+    /// into `root`.
+    pub fn dump_to_directory(&self, root: &std::path::Path) -> std::io::Result<()> {
+        use std::fs::{create_dir_all, File};
+        use std::io::BufWriter;
+
+        create_dir_all(root)?;
+
+        self.write_manifest(&mut BufWriter::new(File::create(root.join("entries.txt"))?))?;
+
+        self.dump_color8_linear_as_svg(&mut File::create(root.join("color8-linear.svg"))?)?;
+        self.dump_color8_glyphs_as_svg(&mut File::create(root.join("color8-glyphs.svg"))?)?;
+        self.dump_alpha8_glyphs_as_svg(&mut File::create(root.join("alpha8-glyphs.svg"))?)?;
+        self.dump_alpha8_linear_as_svg(&mut File::create(root.join("alpha8-linear.svg"))?)?;
+
+        Ok(())
+    }
+
     /// Get the eviction threshold, in bytes, for the given budget type.
     fn get_eviction_threshold(&self, budget_type: BudgetType) -> usize {
         if budget_type == BudgetType::Standalone {
@@ -1133,13 +1308,13 @@ impl TextureCache {
             BudgetType::SharedAlpha8Glyphs | BudgetType::SharedColor8Glyphs => {
                 // Glyphs are usually small and tightly packed so they waste very little
                 // space in the cache.
-                bytes_per_texture * 2 / 3
+                (bytes_per_texture as f32 * self.shared_textures.glyph_ideal_utilization) as usize
             }
             _ => {
                 // Other types of images come with a variety of sizes making them more
                 // prone to wasting pixels and causing fragmentation issues so we put
                 // more pressure on them.
-                bytes_per_texture / 3
+                (bytes_per_texture as f32 * self.shared_textures.image_ideal_utilization) as usize
             }
         };
 
@@ -1385,6 +1560,12 @@ impl TextureCache {
             allowed_in_shared_cache = false;
         }
 
+        // Mipmaps are generated for whole textures, so mipmapped items can't
+        // share them.
+        if filter == TextureFilter::Trilinear {
+            allowed_in_shared_cache = false;
+        }
+
         allowed_in_shared_cache
     }
 
@@ -135,6 +135,11 @@ pub struct ScaleOffset {
     pub offset: euclid::Vector2D<f32, euclid::UnknownUnit>,
 }
 
+/// A translation in double precision. Used by the spatial tree to accumulate
+/// scroll offsets and translations, which can be large enough in very tall
+/// documents that f32 accumulation causes visible jitter.
+pub type PreciseOffset = euclid::default::Vector2D<f64>;
+
 impl ScaleOffset {
     pub fn new(sx: f32, sy: f32, tx: f32, ty: f32) -> Self {
         ScaleOffset {
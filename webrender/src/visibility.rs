@@ -35,6 +35,8 @@ pub struct FrameVisibilityContext<'a> {
     pub scene_properties: &'a SceneProperties,
     pub config: FrameBuilderConfig,
     pub root_spatial_node_index: SpatialNodeIndex,
+    /// True if only scroll offsets changed since the previous frame.
+    pub scroll_only: bool,
 }
 
 pub struct FrameVisibilityState<'a> {
@@ -76,6 +78,9 @@ bitflags! {
         /// Implies that this primitive covers the entire picture cache slice,
         /// and can thus be dropped during batching and drawn with clear color.
         const IS_BACKDROP = 1;
+        /// Implies that this text run is drawn over an image and has asked
+        /// not to use subpixel anti-aliasing there.
+        const GRAYSCALE_TEXT = 2;
     }
 }
 
@@ -320,6 +325,7 @@ pub fn update_prim_visibility(
                 &store.pictures,
                 frame_state.resource_cache,
                 &store.color_bindings,
+                &store.line_skip_inks,
                 &frame_state.surface_stack,
                 &mut frame_state.composite_state,
                 &mut frame_state.gpu_cache,
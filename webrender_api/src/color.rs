@@ -159,3 +159,93 @@ impl From<ColorU> for ColorF {
         }
     }
 }
+
+/// A color filter applied to the whole document when compositing, typically
+/// to assist users with impaired color vision.
+///
+/// Filters are applied to the premultiplied color of each composited pixel
+/// and do not require wrapping content in a filtered stacking context.
+/// The vision deficiency matrices are the full severity ones from Machado et
+/// al. (2009), and the correction filters shift the color information lost to
+/// the simulated deficiency into channels that remain distinguishable.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum AccessibilityColorFilter {
+    /// Converts colors to shades of gray using Rec. 709 luma coefficients.
+    Grayscale,
+    /// Inverts each color channel.
+    Invert,
+    /// Simulates the absence of long wavelength (red) cones.
+    SimulateProtanopia,
+    /// Simulates the absence of medium wavelength (green) cones.
+    SimulateDeuteranopia,
+    /// Simulates the absence of short wavelength (blue) cones.
+    SimulateTritanopia,
+    /// Improves the distinction of colors for users with protanopia.
+    CorrectProtanopia,
+    /// Improves the distinction of colors for users with deuteranopia.
+    CorrectDeuteranopia,
+    /// Improves the distinction of colors for users with tritanopia.
+    CorrectTritanopia,
+}
+
+impl AccessibilityColorFilter {
+    /// Returns the row-major 3x3 matrix and offset that the filter applies to
+    /// the RGB channels. The offset is scaled by alpha when applied to
+    /// premultiplied colors.
+    pub fn to_matrix(&self) -> ([[f32; 3]; 3], [f32; 3]) {
+        const IDENTITY: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        const PROTANOPIA: [[f32; 3]; 3] = [
+            [0.152286, 1.052583, -0.204868],
+            [0.114503, 0.786281, 0.099216],
+            [-0.003882, -0.048116, 1.051998],
+        ];
+        const DEUTERANOPIA: [[f32; 3]; 3] = [
+            [0.367322, 0.860646, -0.227968],
+            [0.280085, 0.672501, 0.047413],
+            [-0.011820, 0.042940, 0.968881],
+        ];
+        const TRITANOPIA: [[f32; 3]; 3] = [
+            [1.255528, -0.076749, -0.178779],
+            [-0.078411, 0.930809, 0.147602],
+            [0.004733, 0.691367, 0.303900],
+        ];
+        // Where the error lost to a red/green deficiency is redistributed.
+        const RED_GREEN_ERROR_SHIFT: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [0.7, 1.0, 0.0], [0.7, 0.0, 1.0]];
+        const BLUE_YELLOW_ERROR_SHIFT: [[f32; 3]; 3] = [[1.0, 0.0, 0.7], [0.0, 1.0, 0.7], [0.0, 0.0, 0.0]];
+
+        // c' = c + shift * (c - simulate(c)) = (I + shift * (I - simulate)) * c
+        fn correction(simulation: &[[f32; 3]; 3], shift: &[[f32; 3]; 3]) -> [[f32; 3]; 3] {
+            let mut result = IDENTITY;
+            for (i, row) in result.iter_mut().enumerate() {
+                for (j, value) in row.iter_mut().enumerate() {
+                    for k in 0 .. 3 {
+                        *value += shift[i][k] * (IDENTITY[k][j] - simulation[k][j]);
+                    }
+                }
+            }
+            result
+        }
+
+        match *self {
+            AccessibilityColorFilter::Grayscale => {
+                let luma = [0.2126, 0.7152, 0.0722];
+                ([luma, luma, luma], [0.0; 3])
+            }
+            AccessibilityColorFilter::Invert => {
+                ([[-1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, -1.0]], [1.0; 3])
+            }
+            AccessibilityColorFilter::SimulateProtanopia => (PROTANOPIA, [0.0; 3]),
+            AccessibilityColorFilter::SimulateDeuteranopia => (DEUTERANOPIA, [0.0; 3]),
+            AccessibilityColorFilter::SimulateTritanopia => (TRITANOPIA, [0.0; 3]),
+            AccessibilityColorFilter::CorrectProtanopia => {
+                (correction(&PROTANOPIA, &RED_GREEN_ERROR_SHIFT), [0.0; 3])
+            }
+            AccessibilityColorFilter::CorrectDeuteranopia => {
+                (correction(&DEUTERANOPIA, &RED_GREEN_ERROR_SHIFT), [0.0; 3])
+            }
+            AccessibilityColorFilter::CorrectTritanopia => {
+                (correction(&TRITANOPIA, &BLUE_YELLOW_ERROR_SHIFT), [0.0; 3])
+            }
+        }
+    }
+}
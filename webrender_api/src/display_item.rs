@@ -7,8 +7,8 @@ use peek_poke::PeekPoke;
 use std::ops::Not;
 // local imports
 use crate::font;
-use crate::{APZScrollGeneration, HasScrollLinkedEffect, PipelineId, PropertyBinding};
-use crate::color::ColorF;
+use crate::{APZScrollGeneration, HasScrollLinkedEffect, PipelineId, PropertyBinding, PropertyBindingKey};
+use crate::color::{ColorF, ColorU};
 use crate::image::{ColorDepth, ImageKey};
 use crate::units::*;
 use std::hash::{Hash, Hasher};
@@ -54,6 +54,10 @@ bitflags! {
         const ANTIALISED = 1 << 4;
         /// If true, this primitive is used as a background for checkerboarding
         const CHECKERBOARD_BACKGROUND = 1 << 5;
+        /// If set on a text item, the text is drawn with grayscale anti-aliasing
+        /// rather than subpixel anti-aliasing where it is over an image or a
+        /// video in the same slice, to avoid color fringes.
+        const GRAYSCALE_TEXT_OVER_IMAGES = 1 << 6;
     }
 }
 
@@ -154,7 +158,12 @@ pub enum DisplayItem {
     ClearRectangle(ClearRectangleDisplayItem),
     HitTest(HitTestDisplayItem),
     Text(TextDisplayItem),
+    GlyphGrid(GlyphGridDisplayItem),
+    Caret(CaretDisplayItem),
+    ScrollbarThumb(ScrollbarThumbDisplayItem),
+    SelectionHighlight(SelectionHighlightDisplayItem),
     Line(LineDisplayItem),
+    SkipInkLine(SkipInkLineDisplayItem),
     Border(BorderDisplayItem),
     BoxShadow(BoxShadowDisplayItem),
     PushShadow(PushShadowDisplayItem),
@@ -165,6 +174,9 @@ pub enum DisplayItem {
     RepeatingImage(RepeatingImageDisplayItem),
     YuvImage(YuvImageDisplayItem),
     BackdropFilter(BackdropFilterDisplayItem),
+    EmbedderDraw(EmbedderDrawDisplayItem),
+    Path(PathDisplayItem),
+    VectorPath(VectorPathDisplayItem),
 
     // Clips
     RectClip(RectClipDisplayItem),
@@ -184,6 +196,7 @@ pub enum DisplayItem {
     SetFilterData,
     SetFilterPrimitives,
     SetPoints,
+    SetPathOps,
 
     // These marker items terminate a scope introduced by a previous item.
     PopReferenceFrame,
@@ -204,7 +217,12 @@ pub enum DebugDisplayItem {
     ClearRectangle(ClearRectangleDisplayItem),
     HitTest(HitTestDisplayItem),
     Text(TextDisplayItem, Vec<font::GlyphInstance>),
+    GlyphGrid(GlyphGridDisplayItem, Vec<GlyphGridCell>),
+    Caret(CaretDisplayItem),
+    ScrollbarThumb(ScrollbarThumbDisplayItem),
+    SelectionHighlight(SelectionHighlightDisplayItem, Vec<LayoutRect>),
     Line(LineDisplayItem),
+    SkipInkLine(SkipInkLineDisplayItem, Vec<font::GlyphInstance>),
     Border(BorderDisplayItem),
     BoxShadow(BoxShadowDisplayItem),
     PushShadow(PushShadowDisplayItem),
@@ -215,6 +233,9 @@ pub enum DebugDisplayItem {
     RepeatingImage(RepeatingImageDisplayItem),
     YuvImage(YuvImageDisplayItem),
     BackdropFilter(BackdropFilterDisplayItem),
+    EmbedderDraw(EmbedderDrawDisplayItem),
+    Path(PathDisplayItem),
+    VectorPath(VectorPathDisplayItem),
 
     ImageMaskClip(ImageMaskClipDisplayItem),
     RoundedRectClip(RoundedRectClipDisplayItem),
@@ -230,6 +251,7 @@ pub enum DebugDisplayItem {
     SetFilterData(FilterData),
     SetFilterPrimitives(Vec<FilterPrimitive>),
     SetPoints(Vec<LayoutPoint>),
+    SetPathOps(Vec<PathOp>),
 
     PopReferenceFrame,
     PopStackingContext,
@@ -345,6 +367,69 @@ pub struct RectangleDisplayItem {
     pub color: PropertyBinding<ColorF>,
 }
 
+/// A text caret whose visibility WebRender toggles every `blink_interval_ms`,
+/// so that editors don't have to send a transaction for each blink.
+///
+/// The caret is visible whenever a new scene is rendered, so it doesn't blink
+/// while new display lists arrive, for example while the user is typing.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, PeekPoke)]
+pub struct CaretDisplayItem {
+    pub common: CommonItemProperties,
+    pub bounds: LayoutRect,
+    pub color: ColorF,
+    /// The binding used to animate the color of the caret. It must not be
+    /// given values through `Transaction::append_dynamic_properties`.
+    pub key: PropertyBindingKey<ColorF>,
+    /// How long the caret stays visible, then hidden. Zero disables blinking.
+    pub blink_interval_ms: u32,
+}
+
+/// The thumb of an overlay scrollbar, which WebRender moves along its track
+/// as `scroll_frame` scrolls, so that it stays in sync with async scrolling
+/// and flings instead of lagging behind until the next display list.
+///
+/// `bounds` is the thumb when the frame isn't scrolled. Scrolling through the
+/// whole scrollable range moves the thumb to the end of the track.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, PeekPoke)]
+pub struct ScrollbarThumbDisplayItem {
+    pub common: CommonItemProperties,
+    pub bounds: LayoutRect,
+    pub color: ColorF,
+    /// The scroll frame whose offset positions the thumb.
+    pub scroll_frame: SpatialId,
+    pub orientation: ScrollbarOrientation,
+    /// The length of the track, which starts at the origin of `bounds`.
+    pub track_length: f32,
+    /// The bindings used to move and fade the thumb. They must be unique to
+    /// the thumb, and must not be given values through
+    /// `Transaction::append_dynamic_properties`.
+    pub transform_key: PropertyBindingKey<LayoutTransform>,
+    pub color_key: PropertyBindingKey<ColorF>,
+    /// How long the thumb stays visible after the frame stopped scrolling
+    /// before fading out. Zero keeps the thumb visible.
+    pub fade_delay_ms: u32,
+    /// How long fading out takes.
+    pub fade_duration_ms: u32,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Deserialize, MallocSizeOf, PartialEq, Serialize, Eq, Hash, PeekPoke)]
+pub enum ScrollbarOrientation {
+    Vertical,
+    Horizontal,
+}
+
+/// A text selection spanning several lines, drawn as a single region so
+/// that the translucent highlight doesn't blend twice where lines overlap.
+/// The outer corners of the region are rounded.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, PeekPoke)]
+pub struct SelectionHighlightDisplayItem {
+    pub common: CommonItemProperties,
+    pub bounds: LayoutRect,
+    pub color: ColorF,
+    pub corner_radius: f32,
+} // IMPLICIT: rects: Vec<LayoutRect>, one per line, from top to bottom
+
 /// Clears all colors from the area, making it possible to cut holes in the window.
 /// (useful for things like the macos frosted-glass effect).
 #[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, PeekPoke)]
@@ -353,6 +438,117 @@ pub struct ClearRectangleDisplayItem {
     pub bounds: LayoutRect,
 }
 
+/// An area whose content is drawn by the embedder with its own GL calls when
+/// the frame is composited, see `Renderer::set_embedder_draw_handler`. The
+/// content is composited at the item's place in the display list, above the
+/// items before it and below the items after it.
+///
+/// The item is drawn in a compositor surface of its own, so it is only drawn
+/// when it can be promoted to one (no complex clips or transforms other than
+/// scales and translations), and only with the draw compositor.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, PeekPoke)]
+pub struct EmbedderDrawDisplayItem {
+    pub common: CommonItemProperties,
+    pub bounds: LayoutRect,
+    pub id: EmbedderDrawId,
+}
+
+/// Identifies an embedder draw item to the embedder's draw handler.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, Serialize, PeekPoke)]
+pub struct EmbedderDrawId(pub u64);
+
+/// A vector path, rasterized by the embedder's path renderer in to a cached
+/// image that is then drawn like any other image, see
+/// `Renderer::set_path_renderer`. The geometry and paint of the path are known
+/// only to the path renderer, which `key` identifies them to. The rendered
+/// image is reused for as long as the key and the size it is rendered at stay
+/// the same, so the embedder has to use a new key when the path changes.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, PeekPoke)]
+pub struct PathDisplayItem {
+    pub common: CommonItemProperties,
+    /// The path is rendered to fill these bounds.
+    pub bounds: LayoutRect,
+    pub key: PathKey,
+}
+
+/// Identifies a path to the embedder's path renderer.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, Serialize, PeekPoke)]
+pub struct PathKey(pub u64);
+
+/// A vector path filled or stroked like on a 2D canvas, rasterized by
+/// WebRender. The path is rasterized at the scale it is drawn at and cached,
+/// so it is best suited to small shapes like icons and chart lines.
+///
+/// The path is given by the `PathOp`s pushed before the item, with points
+/// relative to the top left corner of `bounds`, and is clipped to `bounds`.
+/// Gradient paints use the gradient stops pushed before the item, see
+/// `DisplayListBuilder::push_vector_path`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, PeekPoke)]
+pub struct VectorPathDisplayItem {
+    pub common: CommonItemProperties,
+    pub bounds: LayoutRect,
+    pub mode: PathDrawMode,
+    pub paint: PathPaint,
+} // IMPLICIT: ops: Vec<PathOp>, stops: Vec<GradientStop>
+
+/// A segment of a vector path. A path is made of contours, each started by a
+/// `MoveTo`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, PeekPoke)]
+pub enum PathOp {
+    MoveTo(LayoutPoint),
+    LineTo(LayoutPoint),
+    /// A quadratic bezier curve with a control point and an end point.
+    QuadTo(LayoutPoint, LayoutPoint),
+    /// A cubic bezier curve with two control points and an end point.
+    CubicTo(LayoutPoint, LayoutPoint, LayoutPoint),
+    /// Closes the current contour with a line back to its start.
+    Close,
+}
+
+/// Whether a vector path is filled or stroked.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, PeekPoke)]
+pub enum PathDrawMode {
+    Fill(FillRule),
+    Stroke(StrokeStyle),
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, PeekPoke)]
+pub struct StrokeStyle {
+    pub width: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    /// The longest miter join, relative to the stroke width, that isn't
+    /// replaced by a bevel join.
+    pub miter_limit: f32,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, Serialize, PeekPoke)]
+pub enum LineJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, Serialize, PeekPoke)]
+pub enum LineCap {
+    Butt,
+    Round,
+    Square,
+}
+
+/// What a vector path is painted with. Gradient points are relative to the
+/// top left corner of the item's bounds.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, PeekPoke)]
+pub enum PathPaint {
+    Solid(ColorF),
+    LinearGradient(Gradient),
+    RadialGradient(RadialGradient),
+}
+
 /// A minimal hit-testable item for the parent browser's convenience, and is
 /// slimmer than a RectangleDisplayItem (no color). The existence of this as a
 /// distinct item also makes it easier to inspect/debug display items.
@@ -386,6 +582,22 @@ pub struct LineDisplayItem {
     pub style: LineStyle,
 }
 
+/// A solid horizontal line, typically an underline, that is interrupted
+/// wherever the ink of the glyphs of the text it decorates crosses it, such
+/// as around descenders.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, PeekPoke)]
+pub struct SkipInkLineDisplayItem {
+    pub common: CommonItemProperties,
+    /// The area of the line, as for `LineDisplayItem`.
+    pub area: LayoutRect,
+    pub color: ColorF,
+    /// The font the glyphs are drawn with.
+    pub font_key: font::FontInstanceKey,
+    pub glyph_options: Option<font::GlyphOptions>,
+    /// The space left between the line and the ink of the glyphs.
+    pub clearance: f32,
+} // IMPLICIT: glyphs: Vec<font::GlyphInstance>
+
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, Deserialize, MallocSizeOf, PartialEq, Serialize, Eq, Hash, PeekPoke)]
 pub enum LineOrientation {
@@ -418,6 +630,40 @@ pub struct TextDisplayItem {
     pub glyph_options: Option<font::GlyphOptions>,
 } // IMPLICIT: glyphs: Vec<font::GlyphInstance>
 
+/// A grid of monospaced glyphs with uniform metrics, such as the contents of
+/// a terminal. This is much more compact than the equivalent text items, as
+/// glyph positions are implied by the cell they are in.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, PeekPoke)]
+pub struct GlyphGridDisplayItem {
+    pub common: CommonItemProperties,
+    /// The area all the glyphs should be found in, see `TextDisplayItem::bounds`.
+    pub bounds: LayoutRect,
+    /// The top-left corner of the first cell.
+    pub origin: LayoutPoint,
+    pub cell_size: LayoutSize,
+    /// The distance from the top of a cell to the baseline of its glyph.
+    pub baseline: f32,
+    /// The number of cells per row. Cells are stored row by row, and the last
+    /// row can be incomplete.
+    pub columns: u32,
+    pub font_key: font::FontInstanceKey,
+    pub glyph_options: Option<font::GlyphOptions>,
+} // IMPLICIT: cells: Vec<GlyphGridCell>
+
+/// A cell of a `GlyphGridDisplayItem`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize, PeekPoke)]
+pub struct GlyphGridCell {
+    pub glyph: font::GlyphIndex,
+    /// The color of the glyph. Cells with a transparent color are empty.
+    pub color: ColorU,
+}
+
+impl GlyphGridCell {
+    pub fn is_empty(&self) -> bool {
+        self.color.a == 0
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default, Deserialize, MallocSizeOf, PartialEq, Serialize, PeekPoke)]
 pub struct NormalBorder {
     pub left: BorderSide,
@@ -1350,9 +1596,15 @@ pub struct RepeatingImageDisplayItem {
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, Serialize, PeekPoke)]
 pub enum ImageRendering {
+    /// Smooth, bilinear scaling.
     Auto = 0,
     CrispEdges = 1,
+    /// Nearest-neighbor scaling, for pixel art.
     Pixelated = 2,
+    /// Mipmapped, trilinear scaling, which avoids the aliasing of bilinear
+    /// scaling when an image is drawn much smaller than its size. The image
+    /// gets a texture of its own, whether or not it allows mipmaps.
+    HighQuality = 3,
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, Serialize, PeekPoke)]
@@ -1730,6 +1982,7 @@ impl DisplayItem {
             DisplayItem::Image(..) => "image",
             DisplayItem::RepeatingImage(..) => "repeating_image",
             DisplayItem::Line(..) => "line",
+            DisplayItem::SkipInkLine(..) => "skip_ink_line",
             DisplayItem::PopAllShadows => "pop_all_shadows",
             DisplayItem::PopReferenceFrame => "pop_reference_frame",
             DisplayItem::PopStackingContext => "pop_stacking_context",
@@ -1740,14 +1993,22 @@ impl DisplayItem {
             DisplayItem::SetFilterData => "set_filter_data",
             DisplayItem::SetFilterPrimitives => "set_filter_primitives",
             DisplayItem::SetPoints => "set_points",
+            DisplayItem::SetPathOps => "set_path_ops",
             DisplayItem::RadialGradient(..) => "radial_gradient",
             DisplayItem::Rectangle(..) => "rectangle",
             DisplayItem::SetGradientStops => "set_gradient_stops",
             DisplayItem::ReuseItems(..) => "reuse_item",
             DisplayItem::RetainedItems(..) => "retained_items",
             DisplayItem::Text(..) => "text",
+            DisplayItem::GlyphGrid(..) => "glyph_grid",
+            DisplayItem::Caret(..) => "caret",
+            DisplayItem::ScrollbarThumb(..) => "scrollbar_thumb",
+            DisplayItem::SelectionHighlight(..) => "selection_highlight",
             DisplayItem::YuvImage(..) => "yuv_image",
             DisplayItem::BackdropFilter(..) => "backdrop_filter",
+            DisplayItem::EmbedderDraw(..) => "embedder_draw",
+            DisplayItem::Path(..) => "path",
+            DisplayItem::VectorPath(..) => "vector_path",
         }
     }
 }
@@ -1768,6 +2029,7 @@ impl_default_for_enums! {
     DisplayItem => PopStackingContext,
     LineOrientation => Vertical,
     LineStyle => Solid,
+    ScrollbarOrientation => Vertical,
     RepeatMode => Stretch,
     NinePatchBorderSource => Image(ImageKey::default(), ImageRendering::Auto),
     BorderDetails => Normal(NormalBorder::default()),
@@ -1779,6 +2041,11 @@ impl_default_for_enums! {
     ComponentTransferFuncType => Identity,
     ClipMode => Clip,
     FillRule => Nonzero,
+    PathOp => Close,
+    PathDrawMode => Fill(FillRule::Nonzero),
+    LineJoin => Miter,
+    LineCap => Butt,
+    PathPaint => Solid(ColorF::default()),
     ReferenceFrameKind => Transform {
         is_2d_scale_translation: false,
         should_snap: false,
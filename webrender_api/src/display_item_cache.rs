@@ -4,8 +4,19 @@
 
 use crate::display_item::*;
 use crate::display_list::*;
-use malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
+use peek_poke::{peek_from_slice, Poke};
+use serde::de::{self, Deserializer};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+use std::mem;
+use std::ops::Range;
+use std::sync::Arc;
+use malloc_size_of::{MallocShallowSizeOf, MallocSizeOf, MallocSizeOfOps};
 
+/// A copy of a cached item and its glyphs.
+///
+/// The cache no longer stores its items this way, it is only kept for
+/// `DisplayItemCache::get_items`.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct CachedDisplayItem {
     item: DisplayItem,
@@ -45,43 +56,99 @@ impl From<DisplayItemRef<'_, '_>> for CachedDisplayItem {
     }
 }
 
-#[derive(Clone, Deserialize, MallocSizeOf, Serialize)]
+/// The items retained for an item key.
+///
+/// The items are kept in the serialized form they were received in, in the
+/// cache data of the display list that sent them: each item followed by its
+/// auxiliary slices (such as the glyphs of text items). Entries updated by the
+/// same display list share its buffer, which is followed by a red zone, so the
+/// display list iterator can read them in place.
+#[derive(Clone)]
 struct CacheEntry {
-    items: Vec<CachedDisplayItem>,
-    occupied: bool,
+    data: Arc<Vec<u8>>,
+    range: Range<usize>,
 }
 
-#[derive(Clone, Deserialize, MallocSizeOf, Serialize)]
+#[derive(Clone)]
 pub struct DisplayItemCache {
-    entries: Vec<CacheEntry>,
+    entries: Vec<Option<CacheEntry>>,
 }
 
-impl DisplayItemCache {
-    fn add_item(&mut self, key: ItemKey, item: CachedDisplayItem) {
-        let entry = &mut self.entries[key as usize];
-        entry.items.push(item);
-        entry.occupied = true;
+/// Returns the number of auxiliary slices serialized after an item.
+fn aux_slice_count(item: &DisplayItem) -> usize {
+    match *item {
+        DisplayItem::SetFilterData => 5,
+        DisplayItem::SetGradientStops |
+        DisplayItem::SetFilterOps |
+        DisplayItem::SetFilterPrimitives |
+        DisplayItem::SetPoints |
+        DisplayItem::SetPathOps |
+        DisplayItem::ClipChain(..) |
+        DisplayItem::Text(..) |
+        DisplayItem::SkipInkLine(..) |
+        DisplayItem::GlyphGrid(..) |
+        DisplayItem::SelectionHighlight(..) => 1,
+        _ => 0,
+    }
+}
+
+/// Reads the item at the start of `data` and returns the number of bytes it
+/// occupies, including its auxiliary slices.
+///
+/// This is the validation pass for cached items: it returns `None` if the
+/// item or one of its slices would extend past the end of the data, so that
+/// reading the item in place later on can't run off the buffer.
+fn read_item_size(data: &[u8], item: &mut DisplayItem) -> Option<usize> {
+    if data.len() <= DisplayItem::max_size() {
+        return None;
     }
 
-    fn clear_entry(&mut self, key: ItemKey) {
-        let entry = &mut self.entries[key as usize];
-        entry.items.clear();
-        entry.occupied = false;
+    let mut rest = peek_from_slice(data, item);
+    for _ in 0 .. aux_slice_count(item) {
+        if rest.len() <= mem::size_of::<usize>() {
+            return None;
+        }
+        let mut byte_size = 0usize;
+        rest = peek_from_slice(rest, &mut byte_size);
+        if byte_size < mem::size_of::<usize>() || byte_size > rest.len() {
+            return None;
+        }
+        rest = &rest[byte_size ..];
     }
 
+    Some(data.len() - rest.len())
+}
+
+impl DisplayItemCache {
     fn grow_if_needed(&mut self, capacity: usize) {
         if capacity > self.entries.len() {
-            self.entries.resize_with(capacity, || CacheEntry {
-                items: Vec::new(),
-                occupied: false,
-            });
+            self.entries.resize(capacity, None);
         }
     }
 
-    pub fn get_items(&self, key: ItemKey) -> &[CachedDisplayItem] {
-        let entry = &self.entries[key as usize];
-        debug_assert!(entry.occupied);
-        entry.items.as_slice()
+    /// Returns the serialized items of an entry, followed by a red zone, see
+    /// `CacheEntry`. An empty or unknown entry has no items.
+    pub fn get_data(&self, key: ItemKey) -> &[u8] {
+        match self.entries.get(key as usize) {
+            Some(Some(entry)) => {
+                &entry.data[entry.range.start .. entry.range.end + DisplayItem::max_size()]
+            }
+            _ => {
+                debug_assert!(false, "WRDL: reusing an empty cache entry");
+                &[]
+            }
+        }
+    }
+
+    /// Returns copies of the items of an entry.
+    #[deprecated(note = "cached items are read in place, use `get_data`")]
+    pub fn get_items(&self, key: ItemKey) -> Vec<CachedDisplayItem> {
+        let mut items = Vec::new();
+        let mut iter = BuiltDisplayListIter::new(self.get_data(key), None);
+        while let Some(item) = iter.next() {
+            items.push(CachedDisplayItem::from(item));
+        }
+        items
     }
 
     pub fn new() -> Self {
@@ -90,26 +157,145 @@ impl DisplayItemCache {
         }
     }
 
-    pub fn update(&mut self, display_list: &BuiltDisplayList) {
+    /// Updates the entries retained by `display_list`, taking its cache data
+    /// instead of copying the items out of it.
+    ///
+    /// If the cache data is malformed, the keys of the items that can't be
+    /// read are unknown, so every entry that wasn't updated before the error
+    /// is cleared rather than left with the items of a previous display list.
+    pub fn update(&mut self, display_list: &mut BuiltDisplayList) {
         self.grow_if_needed(display_list.cache_size());
 
-        let mut iter = display_list.cache_data_iter();
-        let mut current_key: Option<ItemKey> = None;
-        loop {
-            let item = match iter.next() {
-                Some(item) => item,
-                None => break,
+        let data = Arc::new(display_list.take_cache_data());
+        let mut offset = 0;
+        let mut item = DisplayItem::PopStackingContext;
+        let mut current: Option<(ItemKey, usize)> = None;
+        let mut updated = vec![false; self.entries.len()];
+        while data.len() - offset > DisplayItem::max_size() {
+            let size = match read_item_size(&data[offset ..], &mut item) {
+                // The items of an entry are read up to a red zone past their end.
+                Some(size) if data.len() - offset - size >= DisplayItem::max_size() => size,
+                _ => {
+                    debug_assert!(false, "WRDL: malformed cached display item");
+                    self.clear_entries_not_updated(&updated);
+                    return;
+                }
             };
 
-            if let DisplayItem::RetainedItems(key) = item.item() {
-                current_key = Some(*key);
-                self.clear_entry(*key);
-                continue;
+            match item {
+                DisplayItem::RetainedItems(key) => {
+                    if let Some((key, start)) = current.take() {
+                        self.end_entry(key, &data, start .. offset);
+                        updated[key as usize] = true;
+                    }
+                    if key as usize >= self.entries.len() {
+                        debug_assert!(false, "WRDL: item key {} out of bounds", key);
+                        self.clear_entries_not_updated(&updated);
+                        return;
+                    }
+                    current = Some((key, offset + size));
+                }
+                DisplayItem::ReuseItems(..) => {
+                    debug_assert!(false, "WRDL: unexpected ReuseItems in cache data");
+                }
+                _ => {
+                    if current.is_none() {
+                        debug_assert!(false, "WRDL: missing RetainedItems marker");
+                        self.clear_entries_not_updated(&updated);
+                        return;
+                    }
+                }
             }
 
-            let key = current_key.expect("Missing RetainedItems marker");
-            let cached_item = CachedDisplayItem::from(item);
-            self.add_item(key, cached_item);
+            offset += size;
         }
+
+        if let Some((key, start)) = current {
+            self.end_entry(key, &data, start .. offset);
+        }
+    }
+
+    fn end_entry(&mut self, key: ItemKey, data: &Arc<Vec<u8>>, range: Range<usize>) {
+        self.entries[key as usize] = Some(CacheEntry {
+            data: Arc::clone(data),
+            range,
+        });
+    }
+
+    fn clear_entries_not_updated(&mut self, updated: &[bool]) {
+        for (entry, updated) in self.entries.iter_mut().zip(updated) {
+            if !updated {
+                *entry = None;
+            }
+        }
+    }
+}
+
+impl MallocSizeOf for DisplayItemCache {
+    fn size_of(&self, ops: &mut MallocSizeOfOps) -> usize {
+        // Entries updated by the same display list share its buffer, count
+        // each buffer once.
+        let mut buffers: Vec<*const Vec<u8>> = Vec::new();
+        let mut size = self.entries.shallow_size_of(ops);
+        for entry in self.entries.iter().flatten() {
+            let ptr = Arc::as_ptr(&entry.data);
+            if !buffers.contains(&ptr) {
+                buffers.push(ptr);
+                size += entry.data.size_of(ops);
+            }
+        }
+        size
+    }
+}
+
+/// The cache is serialized as the items of each entry, in their wire format.
+impl Serialize for DisplayItemCache {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S
+    ) -> Result<S::Ok, S::Error> {
+        let entries: Vec<Option<&[u8]>> = self.entries
+            .iter()
+            .map(|entry| entry.as_ref().map(|entry| &entry.data[entry.range.clone()]))
+            .collect();
+        entries.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DisplayItemCache {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let serialized: Vec<Option<Vec<u8>>> = Vec::deserialize(deserializer)?;
+        let mut entries = Vec::with_capacity(serialized.len());
+        for items in serialized {
+            entries.push(match items {
+                Some(mut data) => {
+                    let len = data.len();
+                    data.resize(len + DisplayItem::max_size(), 0);
+
+                    // Validate the items, which are read in place later on.
+                    let mut offset = 0;
+                    let mut item = DisplayItem::PopStackingContext;
+                    while offset < len {
+                        match read_item_size(&data[offset ..], &mut item) {
+                            Some(size) if offset + size <= len => offset += size,
+                            _ => return Err(de::Error::custom("malformed cached display item")),
+                        }
+                    }
+
+                    Some(CacheEntry {
+                        data: Arc::new(data),
+                        range: 0 .. len,
+                    })
+                }
+                None => None,
+            });
+        }
+
+        Ok(DisplayItemCache {
+            entries,
+        })
     }
 }
@@ -21,9 +21,12 @@ use malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
 use crate::display_item as di;
 use crate::display_item_cache::*;
 use crate::{APZScrollGeneration, HasScrollLinkedEffect, PipelineId, PropertyBinding};
+use crate::PropertyBindingKey;
 use crate::gradient_builder::GradientBuilder;
 use crate::color::ColorF;
 use crate::font::{FontInstanceKey, GlyphInstance, GlyphOptions};
+#[cfg(feature = "text_shaping")]
+use crate::shaping::{ShapingFontInstance, TextShaper};
 use crate::image::{ColorDepth, ImageKey};
 use crate::units::*;
 
@@ -189,6 +192,9 @@ impl MallocSizeOf for DisplayListPayload {
 pub struct BuiltDisplayList {
     payload: DisplayListPayload,
     descriptor: BuiltDisplayListDescriptor,
+    /// The size of the cache data taken by the display item cache, which is
+    /// still counted in the size of the display list.
+    taken_cache_data_size: usize,
 }
 
 #[repr(C)]
@@ -237,9 +243,9 @@ impl DisplayListWithCache {
         self.display_list.iter_with_cache(&self.cache)
     }
 
-    pub fn new_from_list(display_list: BuiltDisplayList) -> Self {
+    pub fn new_from_list(mut display_list: BuiltDisplayList) -> Self {
         let mut cache = DisplayItemCache::new();
-        cache.update(&display_list);
+        cache.update(&mut display_list);
 
         DisplayListWithCache {
             display_list,
@@ -247,8 +253,8 @@ impl DisplayListWithCache {
         }
     }
 
-    pub fn update(&mut self, display_list: BuiltDisplayList) {
-        self.cache.update(&display_list);
+    pub fn update(&mut self, mut display_list: BuiltDisplayList) {
+        self.cache.update(&mut display_list);
         self.display_list = display_list;
     }
 
@@ -330,6 +336,18 @@ impl<'de> Deserialize<'de> for DisplayListWithCache {
                     DisplayListBuilder::push_iter_impl(&mut temp, glyphs);
                     Real::Text(v)
                 },
+                Debug::SkipInkLine(v, glyphs) => {
+                    DisplayListBuilder::push_iter_impl(&mut temp, glyphs);
+                    Real::SkipInkLine(v)
+                },
+                Debug::GlyphGrid(v, cells) => {
+                    DisplayListBuilder::push_iter_impl(&mut temp, cells);
+                    Real::GlyphGrid(v)
+                },
+                Debug::SelectionHighlight(v, rects) => {
+                    DisplayListBuilder::push_iter_impl(&mut temp, rects);
+                    Real::SelectionHighlight(v)
+                },
                 Debug::Iframe(v) => {
                     Real::Iframe(v)
                 }
@@ -365,6 +383,10 @@ impl<'de> Deserialize<'de> for DisplayListWithCache {
                     DisplayListBuilder::push_iter_impl(&mut temp, points);
                     Real::SetPoints
                 },
+                Debug::SetPathOps(ops) => {
+                    DisplayListBuilder::push_iter_impl(&mut temp, ops);
+                    Real::SetPathOps
+                },
                 Debug::RectClip(v) => Real::RectClip(v),
                 Debug::RoundedRectClip(v) => Real::RoundedRectClip(v),
                 Debug::ImageMaskClip(v) => Real::ImageMaskClip(v),
@@ -372,6 +394,8 @@ impl<'de> Deserialize<'de> for DisplayListWithCache {
                 Debug::ClearRectangle(v) => Real::ClearRectangle(v),
                 Debug::HitTest(v) => Real::HitTest(v),
                 Debug::Line(v) => Real::Line(v),
+                Debug::Caret(v) => Real::Caret(v),
+                Debug::ScrollbarThumb(v) => Real::ScrollbarThumb(v),
                 Debug::Image(v) => Real::Image(v),
                 Debug::RepeatingImage(v) => Real::RepeatingImage(v),
                 Debug::YuvImage(v) => Real::YuvImage(v),
@@ -383,6 +407,9 @@ impl<'de> Deserialize<'de> for DisplayListWithCache {
                 Debug::PushStackingContext(v) => Real::PushStackingContext(v),
                 Debug::PushShadow(v) => Real::PushShadow(v),
                 Debug::BackdropFilter(v) => Real::BackdropFilter(v),
+                Debug::EmbedderDraw(v) => Real::EmbedderDraw(v),
+                Debug::Path(v) => Real::Path(v),
+                Debug::VectorPath(v) => Real::VectorPath(v),
 
                 Debug::PopStackingContext => Real::PopStackingContext,
                 Debug::PopReferenceFrame => Real::PopReferenceFrame,
@@ -406,6 +433,7 @@ impl<'de> Deserialize<'de> for DisplayListWithCache {
                     items_data,
                     spatial_tree,
                 },
+                taken_cache_data_size: 0,
             },
             cache: DisplayItemCache::new(),
         })
@@ -415,16 +443,20 @@ impl<'de> Deserialize<'de> for DisplayListWithCache {
 pub struct BuiltDisplayListIter<'a> {
     data: &'a [u8],
     cache: Option<&'a DisplayItemCache>,
-    pending_items: std::slice::Iter<'a, CachedDisplayItem>,
-    cur_cached_item: Option<&'a CachedDisplayItem>,
+    /// The serialized items of the cache entry being reused, which are read
+    /// before continuing with `data`.
+    pending_data: &'a [u8],
     cur_item: di::DisplayItem,
     cur_stops: ItemRange<'a, di::GradientStop>,
     cur_glyphs: ItemRange<'a, GlyphInstance>,
+    cur_glyph_grid_cells: ItemRange<'a, di::GlyphGridCell>,
+    cur_selection_rects: ItemRange<'a, LayoutRect>,
     cur_filters: ItemRange<'a, di::FilterOp>,
     cur_filter_data: Vec<TempFilterData<'a>>,
     cur_filter_primitives: ItemRange<'a, di::FilterPrimitive>,
     cur_clip_chain_items: ItemRange<'a, di::ClipId>,
     cur_points: ItemRange<'a, LayoutPoint>,
+    cur_path_ops: ItemRange<'a, di::PathOp>,
     peeking: Peek,
     /// Should just be initialized but never populated in release builds
     debug_stats: DebugStats,
@@ -518,10 +550,22 @@ impl<'a, 'b> DisplayItemRef<'a, 'b> {
         self.iter.cur_points
     }
 
+    pub fn path_ops(&self) -> ItemRange<di::PathOp> {
+        self.iter.cur_path_ops
+    }
+
     pub fn glyphs(&self) -> ItemRange<GlyphInstance> {
         self.iter.glyphs()
     }
 
+    pub fn glyph_grid_cells(&self) -> ItemRange<di::GlyphGridCell> {
+        self.iter.glyph_grid_cells()
+    }
+
+    pub fn selection_rects(&self) -> ItemRange<LayoutRect> {
+        self.iter.selection_rects()
+    }
+
     pub fn gradient_stops(&self) -> ItemRange<di::GradientStop> {
         self.iter.gradient_stops()
     }
@@ -562,6 +606,7 @@ impl BuiltDisplayList {
         BuiltDisplayList {
             payload,
             descriptor,
+            taken_cache_data_size: 0,
         }
     }
 
@@ -573,10 +618,20 @@ impl BuiltDisplayList {
         &self.payload.items_data
     }
 
+    /// The cache data is moved to the display item cache when it is updated
+    /// with this display list, after which this is empty.
+    #[deprecated(note = "the cache data is moved to the `DisplayItemCache`, use `iter_with_cache`")]
     pub fn cache_data(&self) -> &[u8] {
         &self.payload.cache_data
     }
 
+    /// Moves the cache data out of the display list, for the display item
+    /// cache to keep.
+    pub(crate) fn take_cache_data(&mut self) -> Vec<u8> {
+        self.taken_cache_data_size += self.payload.cache_data.len();
+        mem::take(&mut self.payload.cache_data)
+    }
+
     pub fn descriptor(&self) -> &BuiltDisplayListDescriptor {
         &self.descriptor
     }
@@ -613,6 +668,8 @@ impl BuiltDisplayList {
         BuiltDisplayListIter::new(self.items_data(), None)
     }
 
+    #[deprecated(note = "the cache data is moved to the `DisplayItemCache`, use `iter_with_cache`")]
+    #[allow(deprecated)]
     pub fn cache_data_iter(&self) -> BuiltDisplayListIter {
         BuiltDisplayListIter::new(self.cache_data(), None)
     }
@@ -629,7 +686,7 @@ impl BuiltDisplayList {
     }
 
     pub fn size_in_bytes(&self) -> usize {
-        self.payload.size_in_bytes()
+        self.payload.size_in_bytes() + self.taken_cache_data_size
     }
 
     pub fn iter_spatial_tree<F>(&self, f: F) where F: FnMut(&di::SpatialTreeItem) {
@@ -654,6 +711,18 @@ impl BuiltDisplayList {
                     v,
                     item.iter.cur_glyphs.iter().collect()
                 ),
+                Real::SkipInkLine(v) => Debug::SkipInkLine(
+                    v,
+                    item.iter.cur_glyphs.iter().collect()
+                ),
+                Real::GlyphGrid(v) => Debug::GlyphGrid(
+                    v,
+                    item.iter.cur_glyph_grid_cells.iter().collect()
+                ),
+                Real::SelectionHighlight(v) => Debug::SelectionHighlight(
+                    v,
+                    item.iter.cur_selection_rects.iter().collect()
+                ),
                 Real::SetFilterOps => Debug::SetFilterOps(
                     item.iter.cur_filters.iter().collect()
                 ),
@@ -686,6 +755,9 @@ impl BuiltDisplayList {
                 Real::SetPoints => Debug::SetPoints(
                     item.iter.cur_points.iter().collect()
                 ),
+                Real::SetPathOps => Debug::SetPathOps(
+                    item.iter.cur_path_ops.iter().collect()
+                ),
                 Real::RectClip(v) => Debug::RectClip(v),
                 Real::RoundedRectClip(v) => Debug::RoundedRectClip(v),
                 Real::ImageMaskClip(v) => Debug::ImageMaskClip(v),
@@ -693,6 +765,8 @@ impl BuiltDisplayList {
                 Real::ClearRectangle(v) => Debug::ClearRectangle(v),
                 Real::HitTest(v) => Debug::HitTest(v),
                 Real::Line(v) => Debug::Line(v),
+                Real::Caret(v) => Debug::Caret(v),
+                Real::ScrollbarThumb(v) => Debug::ScrollbarThumb(v),
                 Real::Image(v) => Debug::Image(v),
                 Real::RepeatingImage(v) => Debug::RepeatingImage(v),
                 Real::YuvImage(v) => Debug::YuvImage(v),
@@ -706,6 +780,9 @@ impl BuiltDisplayList {
                 Real::PushStackingContext(v) => Debug::PushStackingContext(v),
                 Real::PushShadow(v) => Debug::PushShadow(v),
                 Real::BackdropFilter(v) => Debug::BackdropFilter(v),
+                Real::EmbedderDraw(v) => Debug::EmbedderDraw(v),
+                Real::Path(v) => Debug::Path(v),
+                Real::VectorPath(v) => Debug::VectorPath(v),
 
                 Real::PopReferenceFrame => Debug::PopReferenceFrame,
                 Real::PopStackingContext => Debug::PopStackingContext,
@@ -743,16 +820,18 @@ impl<'a> BuiltDisplayListIter<'a> {
         Self {
             data,
             cache,
-            pending_items: [].iter(),
-            cur_cached_item: None,
+            pending_data: &[],
             cur_item: di::DisplayItem::PopStackingContext,
             cur_stops: ItemRange::default(),
             cur_glyphs: ItemRange::default(),
+            cur_glyph_grid_cells: ItemRange::default(),
+            cur_selection_rects: ItemRange::default(),
             cur_filters: ItemRange::default(),
             cur_filter_data: Vec::new(),
             cur_filter_primitives: ItemRange::default(),
             cur_clip_chain_items: ItemRange::default(),
             cur_points: ItemRange::default(),
+            cur_path_ops: ItemRange::default(),
             peeking: Peek::NotPeeking,
             debug_stats: DebugStats {
                 last_addr: data.as_ptr() as usize,
@@ -765,38 +844,28 @@ impl<'a> BuiltDisplayListIter<'a> {
         let mut iter = BuiltDisplayListIter::new(
             self.data, self.cache
         );
-        iter.pending_items = self.pending_items.clone();
+        iter.pending_data = self.pending_data;
         iter
     }
 
     pub fn current_item(&self) -> &di::DisplayItem {
-        match self.cur_cached_item {
-            Some(cached_item) => cached_item.display_item(),
-            None => &self.cur_item
-        }
+        &self.cur_item
     }
 
-    fn cached_item_range_or<T>(
-        &self,
-        data: ItemRange<'a, T>
-    ) -> ItemRange<'a, T> {
-        match self.cur_cached_item {
-            Some(cached_item) => cached_item.data_as_item_range(),
-            None => data,
-        }
+    pub fn glyphs(&self) -> ItemRange<GlyphInstance> {
+        self.cur_glyphs
     }
 
-    pub fn glyphs(&self) -> ItemRange<GlyphInstance> {
-        self.cached_item_range_or(self.cur_glyphs)
+    pub fn glyph_grid_cells(&self) -> ItemRange<di::GlyphGridCell> {
+        self.cur_glyph_grid_cells
     }
 
-    pub fn gradient_stops(&self) -> ItemRange<di::GradientStop> {
-        self.cached_item_range_or(self.cur_stops)
+    pub fn selection_rects(&self) -> ItemRange<LayoutRect> {
+        self.cur_selection_rects
     }
 
-    fn advance_pending_items(&mut self) -> bool {
-        self.cur_cached_item = self.pending_items.next();
-        self.cur_cached_item.is_some()
+    pub fn gradient_stops(&self) -> ItemRange<di::GradientStop> {
+        self.cur_stops
     }
 
     pub fn next<'b>(&'b mut self) -> Option<DisplayItemRef<'a, 'b>> {
@@ -817,6 +886,7 @@ impl<'a> BuiltDisplayListIter<'a> {
         self.cur_stops = ItemRange::default();
         self.cur_clip_chain_items = ItemRange::default();
         self.cur_points = ItemRange::default();
+        self.cur_path_ops = ItemRange::default();
         self.cur_filters = ItemRange::default();
         self.cur_filter_primitives = ItemRange::default();
         self.cur_filter_data.clear();
@@ -828,7 +898,8 @@ impl<'a> BuiltDisplayListIter<'a> {
                 SetFilterOps |
                 SetFilterData |
                 SetFilterPrimitives |
-                SetPoints => {
+                SetPoints |
+                SetPathOps => {
                     // These are marker items for populating other display items, don't yield them.
                     continue;
                 }
@@ -847,76 +918,103 @@ impl<'a> BuiltDisplayListIter<'a> {
     pub fn next_raw<'b>(&'b mut self) -> Option<DisplayItemRef<'a, 'b>> {
         use crate::DisplayItem::*;
 
-        if self.advance_pending_items() {
-            return Some(self.as_ref());
-        }
-
-        // A "red zone" of DisplayItem::max_size() bytes has been added to the
-        // end of the serialized display list. If this amount, or less, is
-        // remaining then we've reached the end of the display list.
-        if self.data.len() <= di::DisplayItem::max_size() {
-            return None;
-        }
+        loop {
+            // Items of a reused cache entry are read in place from the cache,
+            // which stores them in the same format as the display list.
+            let reading_cache = self.pending_data.len() > di::DisplayItem::max_size();
+            let mut data = if reading_cache {
+                self.pending_data
+            } else {
+                // A "red zone" of DisplayItem::max_size() bytes has been added to the
+                // end of the serialized display list. If this amount, or less, is
+                // remaining then we've reached the end of the display list.
+                if self.data.len() <= di::DisplayItem::max_size() {
+                    return None;
+                }
+                self.data
+            };
 
-        self.data = peek_from_slice(self.data, &mut self.cur_item);
-        self.log_item_stats();
+            data = peek_from_slice(data, &mut self.cur_item);
+            self.log_item_stats(data);
 
-        match self.cur_item {
-            SetGradientStops => {
-                self.cur_stops = skip_slice::<di::GradientStop>(&mut self.data);
-                self.debug_stats.log_slice("set_gradient_stops.stops", &self.cur_stops);
-            }
-            SetFilterOps => {
-                self.cur_filters = skip_slice::<di::FilterOp>(&mut self.data);
-                self.debug_stats.log_slice("set_filter_ops.ops", &self.cur_filters);
-            }
-            SetFilterData => {
-                self.cur_filter_data.push(TempFilterData {
-                    func_types: skip_slice::<di::ComponentTransferFuncType>(&mut self.data),
-                    r_values: skip_slice::<f32>(&mut self.data),
-                    g_values: skip_slice::<f32>(&mut self.data),
-                    b_values: skip_slice::<f32>(&mut self.data),
-                    a_values: skip_slice::<f32>(&mut self.data),
-                });
-
-                let data = *self.cur_filter_data.last().unwrap();
-                self.debug_stats.log_slice("set_filter_data.func_types", &data.func_types);
-                self.debug_stats.log_slice("set_filter_data.r_values", &data.r_values);
-                self.debug_stats.log_slice("set_filter_data.g_values", &data.g_values);
-                self.debug_stats.log_slice("set_filter_data.b_values", &data.b_values);
-                self.debug_stats.log_slice("set_filter_data.a_values", &data.a_values);
-            }
-            SetFilterPrimitives => {
-                self.cur_filter_primitives = skip_slice::<di::FilterPrimitive>(&mut self.data);
-                self.debug_stats.log_slice("set_filter_primitives.primitives", &self.cur_filter_primitives);
-            }
-            SetPoints => {
-                self.cur_points = skip_slice::<LayoutPoint>(&mut self.data);
-                self.debug_stats.log_slice("set_points.points", &self.cur_points);
-            }
-            ClipChain(_) => {
-                self.cur_clip_chain_items = skip_slice::<di::ClipId>(&mut self.data);
-                self.debug_stats.log_slice("clip_chain.clip_ids", &self.cur_clip_chain_items);
-            }
-            Text(_) => {
-                self.cur_glyphs = skip_slice::<GlyphInstance>(&mut self.data);
-                self.debug_stats.log_slice("text.glyphs", &self.cur_glyphs);
-            }
-            ReuseItems(key) => {
-                match self.cache {
-                    Some(cache) => {
-                        self.pending_items = cache.get_items(key).iter();
-                        self.advance_pending_items();
-                    }
-                    None => {
-                        unreachable!("Cache marker without cache!");
+            match self.cur_item {
+                SetGradientStops => {
+                    self.cur_stops = skip_slice::<di::GradientStop>(&mut data);
+                    self.debug_stats.log_slice("set_gradient_stops.stops", &self.cur_stops);
+                }
+                SetFilterOps => {
+                    self.cur_filters = skip_slice::<di::FilterOp>(&mut data);
+                    self.debug_stats.log_slice("set_filter_ops.ops", &self.cur_filters);
+                }
+                SetFilterData => {
+                    self.cur_filter_data.push(TempFilterData {
+                        func_types: skip_slice::<di::ComponentTransferFuncType>(&mut data),
+                        r_values: skip_slice::<f32>(&mut data),
+                        g_values: skip_slice::<f32>(&mut data),
+                        b_values: skip_slice::<f32>(&mut data),
+                        a_values: skip_slice::<f32>(&mut data),
+                    });
+
+                    let data = *self.cur_filter_data.last().unwrap();
+                    self.debug_stats.log_slice("set_filter_data.func_types", &data.func_types);
+                    self.debug_stats.log_slice("set_filter_data.r_values", &data.r_values);
+                    self.debug_stats.log_slice("set_filter_data.g_values", &data.g_values);
+                    self.debug_stats.log_slice("set_filter_data.b_values", &data.b_values);
+                    self.debug_stats.log_slice("set_filter_data.a_values", &data.a_values);
+                }
+                SetFilterPrimitives => {
+                    self.cur_filter_primitives = skip_slice::<di::FilterPrimitive>(&mut data);
+                    self.debug_stats.log_slice("set_filter_primitives.primitives", &self.cur_filter_primitives);
+                }
+                SetPoints => {
+                    self.cur_points = skip_slice::<LayoutPoint>(&mut data);
+                    self.debug_stats.log_slice("set_points.points", &self.cur_points);
+                }
+                SetPathOps => {
+                    self.cur_path_ops = skip_slice::<di::PathOp>(&mut data);
+                    self.debug_stats.log_slice("set_path_ops.ops", &self.cur_path_ops);
+                }
+                ClipChain(_) => {
+                    self.cur_clip_chain_items = skip_slice::<di::ClipId>(&mut data);
+                    self.debug_stats.log_slice("clip_chain.clip_ids", &self.cur_clip_chain_items);
+                }
+                Text(_) | SkipInkLine(_) => {
+                    self.cur_glyphs = skip_slice::<GlyphInstance>(&mut data);
+                    self.debug_stats.log_slice("text.glyphs", &self.cur_glyphs);
+                }
+                GlyphGrid(_) => {
+                    self.cur_glyph_grid_cells = skip_slice::<di::GlyphGridCell>(&mut data);
+                    self.debug_stats.log_slice("glyph_grid.cells", &self.cur_glyph_grid_cells);
+                }
+                SelectionHighlight(_) => {
+                    self.cur_selection_rects = skip_slice::<LayoutRect>(&mut data);
+                    self.debug_stats.log_slice("selection_highlight.rects", &self.cur_selection_rects);
+                }
+                ReuseItems(key) => {
+                    match self.cache {
+                        Some(cache) => {
+                            self.pending_data = cache.get_data(key);
+                        }
+                        None => {
+                            unreachable!("Cache marker without cache!");
+                        }
                     }
                 }
+                _ => { /* do nothing */ }
             }
-            _ => { /* do nothing */ }
-        }
 
-        Some(self.as_ref())
+            if reading_cache {
+                self.pending_data = data;
+            } else {
+                self.data = data;
+            }
+
+            match self.cur_item {
+                // The items of the entry are yielded instead of the marker.
+                ReuseItems(..) => continue,
+                _ => return Some(self.as_ref()),
+            }
+        }
     }
 
     pub fn as_ref<'b>(&'b self) -> DisplayItemRef<'a, 'b> {
@@ -974,12 +1072,12 @@ impl<'a> BuiltDisplayListIter<'a> {
 
     /// Logs stats for the last deserialized display item
     #[cfg(feature = "display_list_stats")]
-    fn log_item_stats(&mut self) {
-        self.debug_stats.log_item(self.data, &self.cur_item);
+    fn log_item_stats(&mut self, data: &[u8]) {
+        self.debug_stats.log_item(data, &self.cur_item);
     }
 
     #[cfg(not(feature = "display_list_stats"))]
-    fn log_item_stats(&mut self) { /* no-op */ }
+    fn log_item_stats(&mut self, _data: &[u8]) { /* no-op */ }
 }
 
 impl<'a, T> AuxIter<'a, T> {
@@ -1078,6 +1176,10 @@ pub struct DisplayListBuilder {
 
     /// Minimal info about encountered spatial nodes to allow snapping during DL building
     spatial_nodes: Vec<SpatialNodeInfo>,
+
+    /// Shapes the strings of `push_text_str`.
+    #[cfg(feature = "text_shaping")]
+    text_shaper: TextShaper,
 }
 
 #[repr(C)]
@@ -1117,6 +1219,9 @@ impl DisplayListBuilder {
 
             rf_mapper: ReferenceFrameMapper::new(),
             spatial_nodes: vec![SpatialNodeInfo::identity(); FIRST_SPATIAL_NODE_INDEX + 1],
+
+            #[cfg(feature = "text_shaping")]
+            text_shaper: TextShaper::new(),
         }
     }
 
@@ -1200,7 +1305,7 @@ impl DisplayListBuilder {
         let mut index: usize = 0;
         {
             let mut cache = DisplayItemCache::new();
-            cache.update(&temp);
+            cache.update(&mut temp);
             let mut iter = temp.iter_with_cache(&cache);
             while let Some(item) = iter.next_raw() {
                 if index >= range.start.unwrap_or(0) && range.end.map_or(true, |e| index < e) {
@@ -1353,6 +1458,76 @@ impl DisplayListBuilder {
         self.push_item(&item);
     }
 
+    /// Pushes a text selection made of one rectangle per line, from top to
+    /// bottom, see `SelectionHighlightDisplayItem`.
+    pub fn push_selection_highlight(
+        &mut self,
+        common: &di::CommonItemProperties,
+        bounds: LayoutRect,
+        rects: &[LayoutRect],
+        color: ColorF,
+        corner_radius: f32,
+    ) {
+        let item = di::DisplayItem::SelectionHighlight(di::SelectionHighlightDisplayItem {
+            common: *common,
+            bounds,
+            color,
+            corner_radius,
+        });
+
+        self.push_item(&item);
+        self.push_iter(rects);
+    }
+
+    /// Pushes a blinking caret, see `CaretDisplayItem`.
+    pub fn push_caret(
+        &mut self,
+        common: &di::CommonItemProperties,
+        bounds: LayoutRect,
+        color: ColorF,
+        key: PropertyBindingKey<ColorF>,
+        blink_interval_ms: u32,
+    ) {
+        let item = di::DisplayItem::Caret(di::CaretDisplayItem {
+            common: *common,
+            bounds,
+            color,
+            key,
+            blink_interval_ms,
+        });
+        self.push_item(&item);
+    }
+
+    /// Pushes the thumb of an overlay scrollbar, see
+    /// `ScrollbarThumbDisplayItem`.
+    pub fn push_scrollbar_thumb(
+        &mut self,
+        common: &di::CommonItemProperties,
+        bounds: LayoutRect,
+        color: ColorF,
+        scroll_frame: di::SpatialId,
+        orientation: di::ScrollbarOrientation,
+        track_length: f32,
+        transform_key: PropertyBindingKey<LayoutTransform>,
+        color_key: PropertyBindingKey<ColorF>,
+        fade_delay_ms: u32,
+        fade_duration_ms: u32,
+    ) {
+        let item = di::DisplayItem::ScrollbarThumb(di::ScrollbarThumbDisplayItem {
+            common: *common,
+            bounds,
+            color,
+            scroll_frame,
+            orientation,
+            track_length,
+            transform_key,
+            color_key,
+            fade_delay_ms,
+            fade_duration_ms,
+        });
+        self.push_item(&item);
+    }
+
     pub fn push_clear_rect(
         &mut self,
         common: &di::CommonItemProperties,
@@ -1365,6 +1540,61 @@ impl DisplayListBuilder {
         self.push_item(&item);
     }
 
+    pub fn push_embedder_draw(
+        &mut self,
+        common: &di::CommonItemProperties,
+        bounds: LayoutRect,
+        id: di::EmbedderDrawId,
+    ) {
+        let item = di::DisplayItem::EmbedderDraw(di::EmbedderDrawDisplayItem {
+            common: *common,
+            bounds,
+            id,
+        });
+        self.push_item(&item);
+    }
+
+    pub fn push_path(
+        &mut self,
+        common: &di::CommonItemProperties,
+        bounds: LayoutRect,
+        key: di::PathKey,
+    ) {
+        let item = di::DisplayItem::Path(di::PathDisplayItem {
+            common: *common,
+            bounds,
+            key,
+        });
+        self.push_item(&item);
+    }
+
+    /// Pushes a vector path, see `VectorPathDisplayItem`. Gradient paints
+    /// have to be created with `create_gradient` or `create_radial_gradient`
+    /// right before.
+    pub fn push_vector_path(
+        &mut self,
+        common: &di::CommonItemProperties,
+        bounds: LayoutRect,
+        ops: &[di::PathOp],
+        mode: di::PathDrawMode,
+        paint: di::PathPaint,
+    ) {
+        if ops.is_empty() {
+            return;
+        }
+
+        let item = di::DisplayItem::VectorPath(di::VectorPathDisplayItem {
+            common: *common,
+            bounds,
+            mode,
+            paint,
+        });
+
+        self.push_item(&di::DisplayItem::SetPathOps);
+        self.push_iter(ops);
+        self.push_item(&item);
+    }
+
     pub fn push_hit_test(
         &mut self,
         rect: LayoutRect,
@@ -1404,6 +1634,31 @@ impl DisplayListBuilder {
         self.push_item(&item);
     }
 
+    /// Pushes an underline that skips the ink of `glyphs`, see
+    /// `SkipInkLineDisplayItem`. The glyphs are positioned as for `push_text`.
+    pub fn push_skip_ink_line(
+        &mut self,
+        common: &di::CommonItemProperties,
+        area: &LayoutRect,
+        glyphs: &[GlyphInstance],
+        font_key: FontInstanceKey,
+        color: &ColorF,
+        glyph_options: Option<GlyphOptions>,
+        clearance: f32,
+    ) {
+        let item = di::DisplayItem::SkipInkLine(di::SkipInkLineDisplayItem {
+            common: *common,
+            area: *area,
+            color: *color,
+            font_key,
+            glyph_options,
+            clearance,
+        });
+
+        self.push_item(&item);
+        self.push_iter(glyphs);
+    }
+
     pub fn push_image(
         &mut self,
         common: &di::CommonItemProperties,
@@ -1496,6 +1751,55 @@ impl DisplayListBuilder {
         }
     }
 
+    /// Shapes `text` on a single line starting at `origin`, the left end of
+    /// its baseline, and pushes its glyphs. Returns the bounds of the text,
+    /// see `ShapedText::bounds`, whose right edge is where following text
+    /// starts.
+    #[cfg(feature = "text_shaping")]
+    pub fn push_text_str(
+        &mut self,
+        common: &di::CommonItemProperties,
+        text: &str,
+        font: &ShapingFontInstance,
+        origin: LayoutPoint,
+        color: ColorF,
+        glyph_options: Option<GlyphOptions>,
+    ) -> LayoutRect {
+        let shaped = self.text_shaper.shape(text, font, origin);
+        self.push_text(common, shaped.bounds, &shaped.glyphs, font.key, color, glyph_options);
+        shaped.bounds
+    }
+
+    /// Pushes a grid of monospaced glyphs, see `GlyphGridDisplayItem`. The
+    /// cells are given row by row, `columns` cells per row.
+    pub fn push_glyph_grid(
+        &mut self,
+        common: &di::CommonItemProperties,
+        bounds: LayoutRect,
+        origin: LayoutPoint,
+        cell_size: LayoutSize,
+        baseline: f32,
+        columns: u32,
+        cells: &[di::GlyphGridCell],
+        font_key: FontInstanceKey,
+        glyph_options: Option<GlyphOptions>,
+    ) {
+        debug_assert!(columns > 0);
+        let item = di::DisplayItem::GlyphGrid(di::GlyphGridDisplayItem {
+            common: *common,
+            bounds,
+            origin,
+            cell_size,
+            baseline,
+            columns,
+            font_key,
+            glyph_options,
+        });
+
+        self.push_item(&item);
+        self.push_iter(cells);
+    }
+
     /// NOTE: gradients must be pushed in the order they're created
     /// because create_gradient stores the stops in anticipation.
     pub fn create_gradient(
@@ -2219,6 +2523,7 @@ impl DisplayListBuilder {
                     cache_size: self.cache_size,
                 },
                 payload,
+                taken_cache_data_size: 0,
             },
         )
     }
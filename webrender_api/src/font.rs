@@ -5,7 +5,6 @@
 use peek_poke::PeekPoke;
 use std::cmp::Ordering;
 use std::hash::{Hash, Hasher};
-#[cfg(all(not(feature = "font_backend_swash"), not(feature = "font_backend_fontdue"), not(target_os = "macos")))]
 use std::path::PathBuf;
 use std::sync::Arc;
 // local imports
@@ -13,6 +12,7 @@ use crate::IdNamespace;
 use crate::channel::Sender;
 use crate::color::ColorU;
 use crate::units::LayoutPoint;
+use euclid::default::Point2D;
 
 /// Hashable floating-point storage for font size.
 #[repr(C)]
@@ -53,19 +53,25 @@ impl FontSize {
     pub fn to_f64_px(&self) -> f64 { self.0 as f64 }
 }
 
-/// Using Rust crate swash and font-index over native fonts
-#[cfg(feature = "font_backend_swash")]
+/// Using Rust crate swash and font-index over native fonts. The other pure-Rust
+/// backends share the type but don't resolve native fonts.
+#[cfg(any(
+    feature = "font_backend_swash",
+    feature = "font_backend_fontdue",
+    feature = "font_backend_ab_glyph",
+    feature = "font_backend_tiny_skia",
+))]
 #[derive(Clone, Debug, Hash, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct NativeFontHandle(pub u32);
 
-#[cfg(all(not(feature = "font_backend_swash"), not(feature = "font_backend_fontdue"), not(target_os = "macos")))]
+#[cfg(all(not(feature = "font_backend_swash"), not(feature = "font_backend_fontdue"), not(feature = "font_backend_ab_glyph"), not(feature = "font_backend_tiny_skia"), not(target_os = "macos")))]
 #[derive(Clone, Debug, Hash, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct NativeFontHandle {
     pub path: PathBuf,
     pub index: u32,
 }
 
-#[cfg(all(not(feature = "font_backend_swash"), not(feature = "font_backend_fontdue"), target_os = "macos"))]
+#[cfg(all(not(feature = "font_backend_swash"), not(feature = "font_backend_fontdue"), not(feature = "font_backend_ab_glyph"), not(feature = "font_backend_tiny_skia"), target_os = "macos"))]
 #[derive(Clone, Debug, Hash, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct NativeFontHandle {
     pub name: String,
@@ -93,6 +99,32 @@ pub struct GlyphIndexRequest {
     pub sender: Sender<Vec<Option<u32>>>,
 }
 
+pub struct GlyphVariantIndexRequest {
+    pub key: FontKey,
+    pub sequences: Vec<(char, char)>,
+    pub sender: Sender<Vec<Option<u32>>>,
+}
+
+pub struct GlyphOutlineRequest {
+    pub key: FontKey,
+    pub glyph_index: GlyphIndex,
+    pub sender: Sender<Vec<PathCommand>>,
+}
+
+/// A segment of a glyph outline. Points are in font units, with the y axis
+/// pointing up, and an outline is made of contours each started by a `MoveTo`.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum PathCommand {
+    MoveTo(Point2D<f32>),
+    LineTo(Point2D<f32>),
+    /// A quadratic bezier curve with a control point and an end point.
+    QuadTo(Point2D<f32>, Point2D<f32>),
+    /// A cubic bezier curve with two control points and an end point.
+    CubicTo(Point2D<f32>, Point2D<f32>, Point2D<f32>),
+    /// Closes the current contour with a line back to its start.
+    Close,
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, Serialize, Ord, PartialOrd)]
 pub struct FontKey(pub IdNamespace, pub u32);
@@ -104,8 +136,8 @@ impl FontKey {
 }
 
 /// Container for the raw data describing a font. This might be a stream of
-/// bytes corresponding to a downloaded font, or a handle to a native font from
-/// the operating system.
+/// bytes corresponding to a downloaded font, a font file to load from disk, or
+/// a handle to a native font from the operating system.
 ///
 /// Note that fonts need to be instantiated before being used, which involves
 /// assigning size and various other options. The word 'template' here is
@@ -113,9 +145,24 @@ impl FontKey {
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 pub enum FontTemplate {
     Raw(Arc<Vec<u8>>, u32),
+    /// The font at `index` in the font file at `path`, which the glyph
+    /// rasterizer loads itself rather than keeping a copy of its data around.
+    /// The file must not change while the font is in use.
+    File { path: PathBuf, index: u32 },
     Native(NativeFontHandle),
 }
 
+/// What an embedder that already parsed a font for shaping knows about the
+/// font's data. This lets the glyph rasterizer backends that support it use
+/// the font without parsing it again.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, Serialize)]
+pub struct ParsedFontInfo {
+    /// The offset of the font's table directory in the font data, which
+    /// selects the font in a collection.
+    pub offset: u32,
+}
+
 #[repr(u8)]
 #[derive(Debug, Copy, Clone, Hash, Eq, MallocSizeOf, PartialEq, Serialize, Deserialize, Ord, PartialOrd, PeekPoke)]
 pub enum FontRenderMode {
@@ -130,6 +177,32 @@ impl Default for FontRenderMode {
     }
 }
 
+/// How a document draws text that asks for subpixel anti-aliasing.
+///
+/// Subpixel text is drawn with dual-source blending. Where the renderer
+/// doesn't use it (see `Renderer::uses_dual_source_blending`), or where the
+/// text can't be blended correctly, such as over a transparent surface, the
+/// text falls back to grayscale anti-aliasing. The number of text runs that
+/// fell back in a frame is reported by the "Grayscale text fallbacks"
+/// profiler counter.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, Serialize)]
+pub enum SubpixelTextPolicy {
+    /// Subpixel anti-aliasing wherever the text can be blended correctly.
+    Auto,
+    /// Subpixel anti-aliasing wherever dual-source blending is used, even
+    /// where the text can't be blended correctly.
+    Force,
+    /// Grayscale anti-aliasing for all text, except text that is drawn over
+    /// a known background color.
+    Disable,
+}
+
+impl Default for SubpixelTextPolicy {
+    fn default() -> Self {
+        SubpixelTextPolicy::Auto
+    }
+}
+
 impl FontRenderMode {
     // Combine two font render modes such that the lesser amount of AA limits the AA of the result.
     pub fn limit_by(self, other: FontRenderMode) -> FontRenderMode {
@@ -140,6 +213,16 @@ impl FontRenderMode {
     }
 }
 
+/// A color that replaces an entry of the palette of a font instance, as CSS
+/// `override-colors` does.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, MallocSizeOf, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct FontPaletteOverride {
+    /// The index of the entry in the palette.
+    pub index: u16,
+    pub color: ColorU,
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, MallocSizeOf, PartialOrd, Deserialize, Serialize)]
 pub struct FontVariation {
@@ -202,6 +285,9 @@ bitflags! {
         const VERTICAL          = 1 << 8;
         // Explicitly use multi-strike bold emulation.
         const MULTISTRIKE_BOLD  = 1 << 9;
+        // Render glyphs as signed distance fields, rasterized once at a fixed
+        // size and scaled to the size they are drawn at.
+        const SDF               = 1 << 10;
 
         // Internal flags
         const TRANSFORM_GLYPHS  = 1 << 12;
@@ -252,6 +338,13 @@ pub struct SyntheticItalics {
 impl SyntheticItalics {
     pub const ANGLE_SCALE: f32 = 256.0;
 
+    /// The angle of `SyntheticItalics::enabled`, which is also the default
+    /// angle of CSS `font-style: oblique`.
+    pub const DEFAULT_DEGREES: f32 = 14.0;
+
+    /// Slants glyphs by `degrees`, leaning right for positive angles, as CSS
+    /// `font-style: oblique <angle>` does. The angle is clamped to
+    /// -89..89 degrees, and 0 disables synthetic italics.
     pub fn from_degrees(degrees: f32) -> Self {
         SyntheticItalics { angle: (degrees.max(-89.0).min(89.0) * Self::ANGLE_SCALE) as i16 }
     }
@@ -269,7 +362,7 @@ impl SyntheticItalics {
     }
 
     pub fn enabled() -> Self {
-        Self::from_degrees(14.0)
+        Self::from_degrees(Self::DEFAULT_DEGREES)
     }
 
     pub fn disabled() -> Self {
@@ -287,6 +380,75 @@ impl Default for SyntheticItalics {
     }
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Deserialize, Hash, Eq, MallocSizeOf, PartialEq, PartialOrd, Ord, Serialize)]
+pub struct SyntheticStretch {
+    // Horizontal scale of glyphs (0.5..2) for synthetic stretch in 8.8 fixed-point.
+    pub scale: u16,
+}
+
+impl SyntheticStretch {
+    pub const SCALE_ONE: u16 = 256;
+
+    /// Scales glyphs horizontally to `percentage` of their width, as the
+    /// percentages of CSS `font-stretch` do, to emulate condensed or expanded
+    /// faces. The percentage is clamped to 50..200%, and 100% disables
+    /// synthetic stretch. Glyph advances are left as they are, the embedder
+    /// positions stretched glyphs, as it does for synthetic italics.
+    pub fn from_percentage(percentage: f32) -> Self {
+        let scale = percentage.max(50.0).min(200.0) / 100.0;
+        SyntheticStretch { scale: (scale * Self::SCALE_ONE as f32).round() as u16 }
+    }
+
+    pub fn to_percentage(self) -> f32 {
+        self.to_scale() * 100.0
+    }
+
+    pub fn to_scale(self) -> f32 {
+        self.scale as f32 / Self::SCALE_ONE as f32
+    }
+
+    pub fn normal() -> Self {
+        SyntheticStretch { scale: Self::SCALE_ONE }
+    }
+
+    pub fn is_enabled(self) -> bool {
+        self.scale != Self::SCALE_ONE
+    }
+}
+
+impl Default for SyntheticStretch {
+    fn default() -> Self {
+        SyntheticStretch::normal()
+    }
+}
+
+/// How bitmap glyphs, such as embedded bitmap strikes and raster emoji, are
+/// scaled when the font has no strike of the size they are drawn at.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, PartialOrd, Ord, Serialize)]
+pub enum FontBitmapFilter {
+    /// Glyphs are scaled with bilinear filtering when drawn, after being
+    /// halved in size as many times as needed to be drawn at more than half
+    /// their size.
+    Shader,
+    /// Glyphs are resampled to their size when rasterized, to the nearest
+    /// pixel, which keeps the edges of pixel art glyphs crisp.
+    Nearest,
+    /// Glyphs are resampled to their size when rasterized, interpolating
+    /// linearly between pixels, or averaging them when scaled down.
+    Bilinear,
+    /// Glyphs are resampled to their size when rasterized with a Lanczos
+    /// filter of 3 lobes, which is the sharpest and slowest to rasterize.
+    Lanczos3,
+}
+
+impl Default for FontBitmapFilter {
+    fn default() -> Self {
+        FontBitmapFilter::Shader
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Deserialize, Hash, Eq, MallocSizeOf, PartialEq, PartialOrd, Ord, Serialize)]
 pub struct FontInstanceOptions {
@@ -296,7 +458,20 @@ pub struct FontInstanceOptions {
     /// the text will be rendered with bg_color.r/g/b as an opaque estimated
     /// background color.
     pub bg_color: ColorU,
+    /// The slant of synthesized oblique glyphs, for fonts without an italic
+    /// or oblique face, see `SyntheticItalics::from_degrees`.
     pub synthetic_italics: SyntheticItalics,
+    /// The width of synthesized condensed or expanded glyphs, for fonts
+    /// without a face of that width, see `SyntheticStretch::from_percentage`.
+    pub synthetic_stretch: SyntheticStretch,
+    /// The CPAL palette color glyphs are drawn with, for fonts that ship
+    /// several, such as light and dark palettes. Palettes the font doesn't
+    /// have fall back to the colors of the text. Of the font backends, only
+    /// swash draws COLR glyphs, the others use bitmap or SVG glyphs of color
+    /// fonts, which don't have palettes.
+    pub palette_index: u16,
+    /// How bitmap glyphs are scaled to the size they are drawn at.
+    pub bitmap_filter: FontBitmapFilter,
 }
 
 impl Default for FontInstanceOptions {
@@ -306,11 +481,14 @@ impl Default for FontInstanceOptions {
             flags: Default::default(),
             bg_color: ColorU::new(0, 0, 0, 0),
             synthetic_italics: SyntheticItalics::disabled(),
+            synthetic_stretch: SyntheticStretch::normal(),
+            palette_index: 0,
+            bitmap_filter: FontBitmapFilter::Shader,
         }
     }
 }
 
-#[cfg(target_os = "windows")]
+#[cfg(all(not(any(feature = "font_backend_swash", feature = "font_backend_fontdue", feature = "font_backend_ab_glyph", feature = "font_backend_tiny_skia")), target_os = "windows"))]
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Deserialize, Hash, Eq, MallocSizeOf, PartialEq, PartialOrd, Ord, Serialize)]
 pub struct FontInstancePlatformOptions {
@@ -319,7 +497,7 @@ pub struct FontInstancePlatformOptions {
     pub cleartype_level: u8, // percent
 }
 
-#[cfg(target_os = "windows")]
+#[cfg(all(not(any(feature = "font_backend_swash", feature = "font_backend_fontdue", feature = "font_backend_ab_glyph", feature = "font_backend_tiny_skia")), target_os = "windows"))]
 impl Default for FontInstancePlatformOptions {
     fn default() -> FontInstancePlatformOptions {
         FontInstancePlatformOptions {
@@ -330,14 +508,14 @@ impl Default for FontInstancePlatformOptions {
     }
 }
 
-#[cfg(target_os = "macos")]
+#[cfg(all(not(any(feature = "font_backend_swash", feature = "font_backend_fontdue", feature = "font_backend_ab_glyph", feature = "font_backend_tiny_skia")), target_os = "macos"))]
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Deserialize, Hash, Eq, MallocSizeOf, PartialEq, PartialOrd, Ord, Serialize)]
 pub struct FontInstancePlatformOptions {
     pub unused: u32,
 }
 
-#[cfg(target_os = "macos")]
+#[cfg(all(not(any(feature = "font_backend_swash", feature = "font_backend_fontdue", feature = "font_backend_ab_glyph", feature = "font_backend_tiny_skia")), target_os = "macos"))]
 impl Default for FontInstancePlatformOptions {
     fn default() -> FontInstancePlatformOptions {
         FontInstancePlatformOptions {
@@ -346,7 +524,13 @@ impl Default for FontInstancePlatformOptions {
     }
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+#[cfg(any(
+    not(any(target_os = "macos", target_os = "windows")),
+    feature = "font_backend_swash",
+    feature = "font_backend_fontdue",
+    feature = "font_backend_ab_glyph",
+    feature = "font_backend_tiny_skia",
+))]
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, PartialOrd, Ord, Serialize)]
 pub enum FontLCDFilter {
@@ -356,7 +540,13 @@ pub enum FontLCDFilter {
     Legacy,
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+#[cfg(any(
+    not(any(target_os = "macos", target_os = "windows")),
+    feature = "font_backend_swash",
+    feature = "font_backend_fontdue",
+    feature = "font_backend_ab_glyph",
+    feature = "font_backend_tiny_skia",
+))]
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, PartialOrd, Ord, Serialize)]
 pub enum FontHinting {
@@ -367,7 +557,7 @@ pub enum FontHinting {
     LCD,
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+#[cfg(all(not(any(feature = "font_backend_swash", feature = "font_backend_fontdue", feature = "font_backend_ab_glyph", feature = "font_backend_tiny_skia")), not(any(target_os = "macos", target_os = "windows"))))]
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Deserialize, Hash, Eq, MallocSizeOf, PartialEq, PartialOrd, Ord, Serialize)]
 pub struct FontInstancePlatformOptions {
@@ -375,7 +565,7 @@ pub struct FontInstancePlatformOptions {
     pub hinting: FontHinting,
 }
 
-#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+#[cfg(all(not(any(feature = "font_backend_swash", feature = "font_backend_fontdue", feature = "font_backend_ab_glyph", feature = "font_backend_tiny_skia")), not(any(target_os = "macos", target_os = "windows"))))]
 impl Default for FontInstancePlatformOptions {
     fn default() -> FontInstancePlatformOptions {
         FontInstancePlatformOptions {
@@ -385,6 +575,79 @@ impl Default for FontInstancePlatformOptions {
     }
 }
 
+/// Which glyph images of color fonts, such as emoji fonts, the swash and
+/// fontdue backends draw. Color outlines, drawn with COLR or SVG, scale to
+/// any size, while bitmap strikes look sharpest at the size they were drawn
+/// for and blurry when scaled up.
+#[cfg(any(feature = "font_backend_swash", feature = "font_backend_fontdue", feature = "font_backend_ab_glyph", feature = "font_backend_tiny_skia"))]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, MallocSizeOf, PartialEq, PartialOrd, Ord, Serialize)]
+pub enum FontStrikeSelection {
+    /// Color outlines, then the bitmap strike closest to the size, then
+    /// outlines.
+    BestFit,
+    /// Color outlines, then a bitmap strike of the exact size, then outlines,
+    /// and only then a strike of another size.
+    ExactSize,
+    /// Color outlines, then the smallest bitmap strike at least as large as
+    /// the size, which is scaled down, or the largest strike.
+    NearestLarger,
+    /// Color outlines, then outlines, then bitmap strikes.
+    OutlinesFirst,
+}
+
+/// Options of the swash, fontdue, ab_glyph and tiny-skia glyph rasterizer
+/// backends.
+///
+/// The coverage of anti-aliased glyphs is preblended with the text color
+/// using `gamma` and `contrast`, as DirectWrite does, so that text has the
+/// same weight as with the platform rasterizers. A gamma of 100 and no
+/// contrast blend linearly.
+#[cfg(any(feature = "font_backend_swash", feature = "font_backend_fontdue", feature = "font_backend_ab_glyph", feature = "font_backend_tiny_skia"))]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Deserialize, Hash, Eq, MallocSizeOf, PartialEq, PartialOrd, Ord, Serialize)]
+pub struct FontInstancePlatformOptions {
+    /// The gamma of the preblending, in percent.
+    pub gamma: u16,
+    /// The contrast added by the preblending, in percent.
+    pub contrast: u8,
+    /// How far glyphs are widened to the right to embolden them, in percent
+    /// of a pixel. Only the fontdue backend, which renders text thinner than
+    /// the platform rasterizers at small sizes, supports this.
+    pub dilation: u8,
+    /// How subpixel anti-aliased glyphs trade color fringes for sharpness.
+    /// Only the swash backend supports this.
+    pub lcd_filter: FontLCDFilter,
+    /// How glyphs trade fidelity to their outlines for sharpness. The swash
+    /// backend leaves `None` glyphs unhinted and snaps `Light` glyphs to
+    /// whole pixels vertically, hints `Normal` and `LCD` glyphs with the
+    /// font's instructions, and also snaps `Mono` glyphs to whole pixels
+    /// horizontally. The fontdue and ab_glyph backends have no hinter, they
+    /// only round the size of `Mono` glyphs to whole pixels, and the
+    /// tiny-skia backend never hints glyphs. Glyphs with a non axis-aligned
+    /// transform aren't hinted.
+    pub hinting: FontHinting,
+    /// Which glyph images of color fonts are drawn. The fontdue backend
+    /// picks the nearest larger strike for `BestFit` as well, and the
+    /// ab_glyph and tiny-skia backends only draw outlines.
+    pub strike_selection: FontStrikeSelection,
+}
+
+#[cfg(any(feature = "font_backend_swash", feature = "font_backend_fontdue", feature = "font_backend_ab_glyph", feature = "font_backend_tiny_skia"))]
+impl Default for FontInstancePlatformOptions {
+    fn default() -> FontInstancePlatformOptions {
+        FontInstancePlatformOptions {
+            gamma: 180, // Default DWrite gamma
+            contrast: 100,
+            dilation: 0,
+            lcd_filter: FontLCDFilter::Default,
+            // Hinted text looks out of place on macOS.
+            hinting: if cfg!(target_os = "macos") { FontHinting::None } else { FontHinting::Normal },
+            strike_selection: FontStrikeSelection::BestFit,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Ord, PartialOrd, MallocSizeOf, PeekPoke)]
 #[derive(Deserialize, Serialize)]
@@ -407,10 +670,21 @@ pub struct FontInstanceData {
     pub options: Option<FontInstanceOptions>,
     pub platform_options: Option<FontInstancePlatformOptions>,
     pub variations: Vec<FontVariation>,
+    pub palette_overrides: Vec<FontPaletteOverride>,
 }
 
 pub type GlyphIndex = u32;
 
+/// A glyph that a font has no outline or image for, see
+/// `RenderNotifier::missing_glyphs`.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct MissingGlyph {
+    /// The font, with the key the document's namespace knows it by.
+    pub font_key: FontKey,
+    /// The index of the glyph, 0 for the .notdef glyph.
+    pub glyph_index: GlyphIndex,
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Deserialize, MallocSizeOf, PartialEq, Serialize, PeekPoke)]
 pub struct GlyphInstance {
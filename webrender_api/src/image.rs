@@ -8,8 +8,9 @@ use euclid::{size2, Box2D, num::Zero};
 use peek_poke::PeekPoke;
 use std::ops::{Add, Sub};
 use std::sync::Arc;
+use std::time::Duration;
 // local imports
-use crate::{IdNamespace, TileSize};
+use crate::{IdNamespace, ImageRendering, TileSize};
 use crate::font::{FontInstanceKey, FontInstanceData, FontKey, FontTemplate};
 use crate::units::*;
 
@@ -67,10 +68,46 @@ pub enum ExternalImageSource<'a> {
     RawData(&'a [u8]),
     /// A gl::GLuint texture handle.
     NativeTexture(u32),
+    /// A gl::GLuint texture handle, with hints on how to sample it.
+    NativeTextureWithParams(u32, ExternalTextureParams),
     /// An invalid source.
     Invalid,
 }
 
+/// Sampling hints for a native external texture.
+///
+/// The texture target (e.g. `ImageBufferKind::TextureRect` for IOSurfaces or
+/// `ImageBufferKind::TextureExternal` for Android surface textures) is given
+/// by the `ExternalImageType` the image was added with, since it determines
+/// the shaders used to draw the image.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ExternalTextureParams {
+    /// The filtering to sample the texture with, instead of the one requested
+    /// by the display item.
+    pub filter: Option<ImageRendering>,
+    /// Set the wrap mode of the texture to clamp to edge when binding it, for
+    /// textures that may have been configured differently by another user.
+    pub clamp_to_edge: bool,
+    /// If set, the `uv` of the `ExternalImage` is normalized rather than in
+    /// texels, and is scaled by this texture size.
+    pub normalized_uv_size: Option<DeviceSize>,
+}
+
+impl ExternalTextureParams {
+    /// Returns `uv` in texels.
+    pub fn texel_uv(&self, uv: TexelRect) -> TexelRect {
+        match self.normalized_uv_size {
+            Some(size) => TexelRect::new(
+                uv.uv0.x * size.width,
+                uv.uv0.y * size.height,
+                uv.uv1.x * size.width,
+                uv.uv1.y * size.height,
+            ),
+            None => uv,
+        }
+    }
+}
+
 /// The data that an external client should provide about
 /// an external image. For instance, if providing video frames,
 /// the application could call wr.render() whenever a new
@@ -83,6 +120,15 @@ pub struct ExternalImage<'a> {
     pub source: ExternalImageSource<'a>,
 }
 
+/// The reason why an external image couldn't be locked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExternalImageLockError {
+    /// The image didn't become available before the timeout expired.
+    Timeout,
+    /// The image can't be provided.
+    Unavailable,
+}
+
 /// The interfaces that an application can implement to support providing
 /// external image buffers.
 /// When the application passes an external image to WR, it should keep that
@@ -94,6 +140,23 @@ pub trait ExternalImageHandler {
     /// The WR client should not change the image content until the unlock()
     /// call.
     fn lock(&mut self, key: ExternalImageId, channel_index: u8) -> ExternalImage;
+    /// Lock the external image like `lock`, but give up if it isn't available
+    /// within `timeout`.
+    ///
+    /// This is called instead of `lock` when the renderer is configured with
+    /// an external image lock timeout. WR doesn't call `unlock` for images
+    /// that failed to lock, and renders them transparent, or as if their source
+    /// was `ExternalImageSource::Invalid` for `TextureExternal` images, which
+    /// WR can't create a placeholder for. The default implementation calls
+    /// `lock` and ignores the timeout.
+    fn try_lock(
+        &mut self,
+        key: ExternalImageId,
+        channel_index: u8,
+        _timeout: Duration,
+    ) -> Result<ExternalImage, ExternalImageLockError> {
+        Ok(self.lock(key, channel_index))
+    }
     /// Unlock the external image. WR should not read the image content
     /// after this call.
     fn unlock(&mut self, key: ExternalImageId, channel_index: u8);
@@ -142,6 +205,18 @@ pub struct ExternalImageData {
     pub image_type: ExternalImageType,
 }
 
+/// A frame enqueued for an external image, to be presented at a given time.
+#[derive(Debug, Copy, Clone, Eq, Hash, PartialEq, Serialize, Deserialize)]
+pub struct VideoFrame {
+    /// The external image holding the contents of the frame.
+    pub id: ExternalImageId,
+    /// The plane of the frame, see `ExternalImageData::channel_index`.
+    pub channel_index: u8,
+    /// When the frame should start being displayed, in nanoseconds, on the
+    /// same clock as the vsync times given to the renderer.
+    pub presentation_time: u64,
+}
+
 /// Specifies the format of a series of pixels, in driver terms.
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
@@ -240,6 +315,10 @@ bitflags! {
         ///
         /// See https://github.com/servo/webrender/pull/2555/
         const ALLOW_MIPMAPS = 2;
+        /// Whether this `ImageFormat::R8` image is an alpha mask. Image items
+        /// draw it filled with their color, which suits monochrome bitmaps
+        /// that are recolored at draw time.
+        const ALPHA_MASK = 4;
     }
 }
 
@@ -349,6 +428,30 @@ impl ImageData {
     pub fn new_shared(bytes: Arc<Vec<u8>>) -> Self {
         ImageData::Raw(bytes)
     }
+
+    /// Mints a new raw `ImageFormat::R8` ImageData from a packed 1-bit
+    /// bitmap, such as a fringe or stipple bitmap, to be used with the
+    /// `ImageDescriptorFlags::ALPHA_MASK` flag. Each row starts on a new
+    /// byte of `bits`, with the leftmost pixel in the most significant bit,
+    /// and set bits are opaque.
+    ///
+    /// The bitmap is expanded to one byte per pixel, which is what the
+    /// texture cache stores.
+    pub fn new_a1(bits: &[u8], size: DeviceIntSize) -> Self {
+        let width = size.width.max(0) as usize;
+        let height = size.height.max(0) as usize;
+        let stride = (width + 7) / 8;
+        assert!(bits.len() >= stride * height, "Bitmap data too short");
+
+        let mut bytes = Vec::with_capacity(width * height);
+        for row in bits.chunks(stride).take(height) {
+            bytes.extend((0 .. width).map(|x| {
+                if row[x / 8] & (0x80 >> (x % 8)) != 0 { 0xff } else { 0 }
+            }));
+        }
+
+        ImageData::new(bytes)
+    }
 }
 
 /// The resources exposed by the resource cache available for use by the blob rasterizer.
@@ -42,6 +42,8 @@ mod display_list;
 mod font;
 mod gradient_builder;
 mod image;
+#[cfg(feature = "text_shaping")]
+mod shaping;
 pub mod units;
 
 pub use crate::color::*;
@@ -51,6 +53,8 @@ pub use crate::display_list::*;
 pub use crate::font::*;
 pub use crate::gradient_builder::*;
 pub use crate::image::*;
+#[cfg(feature = "text_shaping")]
+pub use crate::shaping::*;
 
 use crate::units::*;
 use crate::channel::Receiver;
@@ -86,6 +90,17 @@ impl Default for QualitySettings {
     }
 }
 
+/// Uniform values passed to the embedder supplied post-processing shader,
+/// see `Renderer::set_post_process_shader`.
+///
+/// The values are exposed to the shader as `uniform vec4 uParams[4]`, in
+/// order, and can be changed every frame with
+/// `Transaction::set_post_process_params`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct PostProcessParams {
+    pub values: [f32; 16],
+}
+
 /// An epoch identifies the state of a pipeline in time.
 ///
 /// This is mostly used as a synchronization mechanism to observe how/when particular pipeline
@@ -259,6 +274,46 @@ pub trait RenderNotifier: Send {
     /// Notify the thread containing the `Renderer` that the render backend has been
     /// shut down.
     fn shut_down(&self) {}
+    /// Notify that the render backend applied all of the transactions sent to it,
+    /// or published a frame, after an API object started holding transactions
+    /// back because of its maximum number of pending transactions. This is
+    /// called on the render backend thread.
+    fn ready_for_more_transactions(&self) {}
+    /// Notify that WebRender became idle: all of the transactions sent to it
+    /// were applied, the last frame is up to date, and no animation driven by
+    /// WebRender itself (blinking carets, fading scrollbars, deferred frames)
+    /// will change it. Until more transactions are sent, requesting a frame
+    /// does no work. Frames that `new_frame_ready` asked to composite may
+    /// still need to be rendered. This is called on the render backend thread.
+    fn idle(&self) {}
+    /// Notify that a frame of the document used glyphs that their font can't
+    /// render, because they are the .notdef glyph or failed to rasterize, so
+    /// that the embedder can fall back to another font for their characters.
+    /// Blank glyphs such as spaces aren't reported. Each glyph is reported
+    /// once, the first time it is rasterized for a font instance. This is
+    /// called on the render backend thread.
+    fn missing_glyphs(&self, _: DocumentId, _glyphs: Vec<MissingGlyph>) {}
+    /// Notify that an external image couldn't be locked, and was rendered
+    /// transparent instead. This is called on the `Renderer`'s thread.
+    fn external_image_lock_failed(
+        &self,
+        _id: ExternalImageId,
+        _channel_index: u8,
+        _error: ExternalImageLockError,
+    ) {}
+    /// Notify that resources of the document's namespace couldn't be used,
+    /// reported when the transaction adding them is applied if they are
+    /// rejected right away, such as font data without a valid font header,
+    /// or after a frame of the document is built otherwise. This is called
+    /// on the render backend thread.
+    fn resource_errors(&self, _: DocumentId, _errors: Vec<ResourceError>) {}
+}
+
+/// A resource that WebRender couldn't use, see `RenderNotifier::resource_errors`.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum ResourceError {
+    /// The glyph rasterizer couldn't load the font, so its glyphs are blank.
+    InvalidFont(FontKey),
 }
 
 /// A stage of the rendering pipeline.
@@ -276,6 +331,12 @@ pub enum Checkpoint {
     /// NotificationRequests get notified with this if they get dropped without having been
     /// notified. This provides the guarantee that if a request is created it will get notified.
     TransactionDropped,
+    /// The fonts added so far, including the ones of the transaction, have been
+    /// loaded by the glyph rasterizer, so that rasterizing glyphs with them won't
+    /// wait on font parsing. Fonts that failed to load are reported with
+    /// `RenderNotifier::resource_errors` instead. Requests for this checkpoint
+    /// are notified on a worker thread.
+    FontsLoaded,
 }
 
 /// A handler to notify when a transaction reaches certain stages of the rendering
@@ -567,6 +628,8 @@ pub enum BoolParameter {
     Multithreading = 1,
     BatchedUploads = 2,
     DrawCallsForTextureCopy = 3,
+    /// See `RenderApi::set_power_saving`.
+    PowerSaving = 4,
 }
 
 /// Integer configuration option.
@@ -574,6 +637,8 @@ pub enum BoolParameter {
 #[repr(u32)]
 pub enum IntParameter {
     BatchedUploadThreshold = 0,
+    /// See `RenderApi::set_text_contrast`.
+    TextContrast = 1,
 }
 
 bitflags! {
@@ -689,6 +754,31 @@ bitflags! {
         /// Render large blobs with at a smaller size (incorrectly). This is a temporary workaround for
         /// fuzzing.
         const RESTRICT_BLOB_SIZE        = 1 << 28;
+        /// Check that every GPU cache address resolved during batching refers to
+        /// live data, and report the offending primitive instead of sampling garbage.
+        const GPU_CACHE_VALIDATION      = 1 << 29;
+        /// Collect the display list metrics reported by `RenderApi::scene_metrics`
+        /// while building scenes.
+        const SCENE_METRICS             = 1 << 30;
+    }
+}
+
+/// A 64 bit id stored as two 32 bit halves. Unlike a `u64`, it doesn't raise
+/// the alignment of the primitive keys and templates it is stored in, which
+/// would grow all of them.
+#[derive(Debug, Copy, Clone, Eq, MallocSizeOf, PartialEq, Hash, Serialize, Deserialize)]
+pub struct PackedId([u32; 2]);
+
+impl PackedId {
+    ///
+    pub fn get(self) -> u64 {
+        (self.0[1] as u64) << 32 | self.0[0] as u64
+    }
+}
+
+impl From<u64> for PackedId {
+    fn from(id: u64) -> Self {
+        PackedId([id as u32, (id >> 32) as u32])
     }
 }
 
@@ -703,6 +793,16 @@ pub enum PrimitiveKeyKind {
         ///
         color: PropertyBinding<ColorU>,
     },
+    /// An area drawn by the embedder when compositing.
+    EmbedderDraw {
+        /// The `EmbedderDrawId`.
+        id: PackedId,
+    },
+    /// A path rendered by the embedder's path renderer.
+    Path {
+        /// The `PathKey`.
+        key: PackedId,
+    },
 }
 
 ///
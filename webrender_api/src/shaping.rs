@@ -0,0 +1,159 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Text shaping for `DisplayListBuilder::push_text_str`, for embedders that
+//! draw strings without shaping them themselves.
+//!
+//! Shaping maps characters to glyph indices and positions the glyphs,
+//! applying kerning and ligatures. It runs where the display list is built,
+//! so it needs the data of the fonts, which the embedder provides with
+//! `ShapingFont`. Text is shaped on a single line, without bidi reordering
+//! or font fallback.
+
+use std::sync::Arc;
+use swash::{CacheKey, FontRef};
+use swash::shape::ShapeContext;
+
+use crate::font::{FontInstanceKey, GlyphIndex, GlyphInstance};
+use crate::units::{LayoutPoint, LayoutRect};
+
+/// The data of a font, for shaping the text drawn with its instances.
+pub struct ShapingFont {
+    data: Arc<Vec<u8>>,
+    offset: u32,
+    key: CacheKey,
+}
+
+impl ShapingFont {
+    /// Returns None if `data` isn't a font, or has no font at `index`.
+    pub fn new(data: Arc<Vec<u8>>, index: u32) -> Option<Self> {
+        let font = FontRef::from_index(&data, index as usize)?;
+        let (offset, key) = (font.offset, font.key);
+        Some(ShapingFont { data, offset, key })
+    }
+
+    fn font_ref(&self) -> FontRef<'_> {
+        FontRef {
+            data: &self.data,
+            offset: self.offset,
+            key: self.key,
+        }
+    }
+}
+
+/// A font instance, along with what shaping needs to know about it.
+#[derive(Clone)]
+pub struct ShapingFontInstance {
+    pub key: FontInstanceKey,
+    /// The size of the font instance, in pixels.
+    pub size: f32,
+    pub font: Arc<ShapingFont>,
+}
+
+/// Glyphs positioned by `TextShaper::shape`.
+pub struct ShapedText {
+    pub glyphs: Vec<GlyphInstance>,
+    /// The advance of the text and the ascent and descent of the font, from
+    /// the origin of the text.
+    pub bounds: LayoutRect,
+}
+
+/// Shapes text, keeping the caches of the shaper between strings.
+pub struct TextShaper {
+    context: ShapeContext,
+}
+
+impl TextShaper {
+    pub fn new() -> Self {
+        TextShaper {
+            context: ShapeContext::new(),
+        }
+    }
+
+    /// Shapes `text` on a single line starting at `origin`, the left end of
+    /// its baseline.
+    pub fn shape(&mut self, text: &str, font: &ShapingFontInstance, origin: LayoutPoint) -> ShapedText {
+        let font_ref = font.font.font_ref();
+        let mut shaper = self.context
+            .builder(font_ref)
+            .size(font.size)
+            .build();
+        shaper.add_str(text);
+
+        let mut glyphs = Vec::new();
+        let mut pen = origin;
+        shaper.shape_with(|cluster| {
+            for glyph in cluster.glyphs {
+                glyphs.push(GlyphInstance {
+                    index: glyph.id as GlyphIndex,
+                    // swash offsets are y-up, while layout space is y-down.
+                    point: LayoutPoint::new(pen.x + glyph.x, pen.y - glyph.y),
+                });
+                pen.x += glyph.advance;
+            }
+        });
+
+        let metrics = font_ref.metrics(&[]).scale(font.size);
+        let bounds = LayoutRect::new(
+            LayoutPoint::new(origin.x, origin.y - metrics.ascent),
+            LayoutPoint::new(pen.x, origin.y + metrics.descent),
+        );
+        ShapedText { glyphs, bounds }
+    }
+}
+
+impl Default for TextShaper {
+    fn default() -> Self {
+        TextShaper::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::IdNamespace;
+    use std::fs;
+
+    fn font_instance(size: f32) -> ShapingFontInstance {
+        let data = fs::read("../wrench/reftests/text/VeraBd.ttf").expect("Couldn't open font file");
+        ShapingFontInstance {
+            key: FontInstanceKey::new(IdNamespace(0), 1),
+            size,
+            font: Arc::new(ShapingFont::new(Arc::new(data), 0).unwrap()),
+        }
+    }
+
+    #[test]
+    fn rejects_non_fonts() {
+        assert!(ShapingFont::new(Arc::new(vec![0; 64]), 0).is_none());
+    }
+
+    #[test]
+    fn shapes_a_line() {
+        let font = font_instance(20.0);
+        let origin = LayoutPoint::new(10.0, 50.0);
+        let shaped = TextShaper::new().shape("Hello", &font, origin);
+
+        assert_eq!(shaped.glyphs.len(), 5);
+        assert!(shaped.glyphs.iter().all(|glyph| glyph.index != 0));
+        assert_eq!(shaped.glyphs[0].point, origin);
+        assert!(shaped.glyphs.windows(2).all(|pair| pair[0].point.x < pair[1].point.x));
+        assert!(shaped.glyphs.iter().all(|glyph| glyph.point.y == origin.y));
+        // The two "l"s map to the same glyph.
+        assert_eq!(shaped.glyphs[2].index, shaped.glyphs[3].index);
+
+        assert_eq!(shaped.bounds.min.x, origin.x);
+        assert!(shaped.bounds.max.x > shaped.glyphs[4].point.x);
+        assert!(shaped.bounds.min.y < origin.y && shaped.bounds.max.y > origin.y);
+    }
+
+    #[test]
+    fn advances_scale_with_the_size() {
+        let mut shaper = TextShaper::new();
+        let small = shaper.shape("Hello", &font_instance(10.0), LayoutPoint::zero());
+        let large = shaper.shape("Hello", &font_instance(20.0), LayoutPoint::zero());
+        let ratio = large.bounds.width() / small.bounds.width();
+        assert!((ratio - 2.0).abs() < 0.01, "{}", ratio);
+    }
+}
@@ -18,7 +18,7 @@ use rayon::ThreadPoolBuilder;
 use wr_glyph_rasterizer::RasterizedGlyph;
 use wr_glyph_rasterizer::{
     SharedFontResources, BaseFontInstance, GlyphRasterizer, FontInstance, GlyphKey,
-    SubpixelDirection, profiler::GlyphRasterizeProfiler,
+    GlyphRequestPriority, SubpixelDirection, profiler::GlyphRasterizeProfiler,
 };
 
 #[path = "common/boilerplate.rs"]
@@ -40,10 +40,10 @@ fn load_glyphs() -> Vec<RasterizedGlyph> {
 
     let font_key = FontKey::new(namespace, 0);
     let raw_font_data = include_bytes!("../../wrench/reftests/text/FreeSans.ttf");
-    let font_template = FontTemplate::Raw(Arc::new(raw_font_data.to_vec()), 0);
+    let mut font_template = FontTemplate::Raw(Arc::new(raw_font_data.to_vec()), 0);
     let shared_font_key = fonts
         .font_keys
-        .add_key(&font_key, &font_template)
+        .add_key(&font_key, &mut font_template)
         .expect("Failed to add font key");
 
     let font_instance_key = FontInstanceKey::new(namespace, 1);
@@ -59,6 +59,7 @@ fn load_glyphs() -> Vec<RasterizedGlyph> {
         mem::take(&mut Some(FontInstanceOptions::default())),
         mem::take(&mut Some(FontInstancePlatformOptions::default())),
         mem::take(&mut Vec::new()),
+        Vec::new(),
     );
     let shared_instance = fonts
         .instance_keys
@@ -114,7 +115,7 @@ fn load_glyphs() -> Vec<RasterizedGlyph> {
         )
     });
 
-    glyph_rasterizer.request_glyphs(font, &glyph_keys, |_| true);
+    glyph_rasterizer.request_glyphs(font, GlyphRequestPriority::Visible, &glyph_keys, |_| true);
 
     let mut glyphs = vec![];
     glyph_rasterizer.resolve_glyphs(
@@ -0,0 +1,296 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A glyph rasterizer backend on top of ab_glyph, which only rasterizes the
+//! outlines of glyphs, with grayscale anti-aliasing. Color glyphs and subpixel
+//! anti-aliasing aren't supported, and glyph transforms are ignored, as with
+//! the fontdue backend. Synthetic italics and stretch are drawn by skewing
+//! and scaling the outlines.
+
+use ab_glyph::{Font, FontVec, Glyph, GlyphId, OutlineCurve, OutlinedGlyph, Point, PxScale, Rect, ScaleFont, point};
+use api::{ColorU, GlyphDimensions, GlyphIndex, FontKey, FontRenderMode, PathCommand};
+use api::{FontHinting, FontInstancePlatformOptions, NativeFontHandle, ParsedFontInfo};
+use crate::backend::font_data::{FontBytes, FontDataCache};
+use crate::gamma_lut::{ColorLut, GammaLut};
+use crate::rasterizer::{FontInstance, GlyphKey};
+use crate::rasterizer::{GlyphRasterError, GlyphRasterResult, RasterizedGlyph};
+use crate::types::FastHashMap;
+use euclid::point2;
+use std::path::Path;
+use std::sync::Arc;
+
+lazy_static! {
+    /// The fonts parsed by the font contexts of all the rasterizer threads.
+    static ref FONT_DATA: FontDataCache<FontVec> = FontDataCache::new();
+}
+
+fn load_font(bytes: &FontBytes, index: u32) -> Result<FontVec, GlyphRasterError> {
+    FontVec::try_from_vec_and_index(bytes.to_vec(), index).map_err(|e| {
+        error!("Failed to create ab_glyph font: index={} err={:?}", index, e);
+        GlyphRasterError::LoadFailed
+    })
+}
+
+pub struct FontContext {
+    fonts: FastHashMap<FontKey, Arc<FontVec>>,
+    /// The index of each font in its data, to read it with ttf-parser.
+    font_indices: FastHashMap<FontKey, u32>,
+    gamma_luts: FastHashMap<(u16, u8), GammaLut>,
+}
+
+impl FontContext {
+    pub fn distribute_across_threads() -> bool {
+        true
+    }
+
+    pub fn new() -> FontContext {
+        FontContext {
+            fonts: FastHashMap::default(),
+            font_indices: FastHashMap::default(),
+            gamma_luts: FastHashMap::default(),
+        }
+    }
+
+    pub fn add_raw_font(&mut self, font_key: &FontKey, bytes: Arc<Vec<u8>>, index: u32) -> Result<(), GlyphRasterError> {
+        if self.fonts.contains_key(font_key) {
+            return Ok(());
+        }
+        self.add_font_bytes(font_key, bytes.into(), index)
+    }
+
+    /// Loads the font at `index` in the font file at `path`, see
+    /// `FontBytes::from_file`. ab_glyph keeps its own copy of the font data,
+    /// mapping the file only saves reading it.
+    pub fn add_file_font(&mut self, font_key: &FontKey, path: &Path, index: u32) -> Result<(), GlyphRasterError> {
+        if self.fonts.contains_key(font_key) {
+            return Ok(());
+        }
+        self.add_font_bytes(font_key, FontBytes::from_file(path)?, index)
+    }
+
+    fn add_font_bytes(&mut self, font_key: &FontKey, bytes: FontBytes, index: u32) -> Result<(), GlyphRasterError> {
+        let font = FONT_DATA.get_or_load(*font_key, &bytes, index, load_font)?;
+        self.fonts.insert(*font_key, font);
+        self.font_indices.insert(*font_key, index);
+        Ok(())
+    }
+
+    /// ab_glyph parses fonts itself, so the font is loaded as a raw font.
+    pub fn add_parsed_font(
+        &mut self,
+        font_key: &FontKey,
+        bytes: Arc<Vec<u8>>,
+        index: u32,
+        _: ParsedFontInfo,
+    ) -> Result<(), GlyphRasterError> {
+        self.add_raw_font(font_key, bytes, index)
+    }
+
+    pub fn add_native_font(&mut self, _: &FontKey, _: NativeFontHandle) -> Result<(), GlyphRasterError> {
+        error!("ab_glyph can't load native fonts");
+        Err(GlyphRasterError::LoadFailed)
+    }
+
+    pub fn delete_font(&mut self, font_key: &FontKey) {
+        if self.fonts.remove(font_key).is_some() {
+            self.font_indices.remove(font_key);
+            FONT_DATA.remove(font_key);
+        }
+    }
+
+    pub fn delete_font_instance(&mut self, _: &FontInstance) {}
+
+    pub fn get_glyph_index(&self, font_key: FontKey, ch: char) -> Option<u32> {
+        let font = self.fonts.get(&font_key)?;
+        match font.glyph_id(ch) {
+            GlyphId(0) => None,
+            GlyphId(index) => Some(index as u32),
+        }
+    }
+
+    /// ab_glyph doesn't map variation sequences, so they are looked up with
+    /// ttf-parser.
+    pub fn get_glyph_variant_index(&self, font_key: FontKey, ch: char, selector: char) -> Option<u32> {
+        let font = self.fonts.get(&font_key)?;
+        let index = *self.font_indices.get(&font_key)?;
+        let face = ttf_parser::Face::parse(font.as_slice(), index).ok()?;
+        face.glyph_variation_index(ch, selector).map(|id| id.0 as u32)
+    }
+
+    pub fn get_glyph_outline(&self, font_key: FontKey, glyph_index: GlyphIndex) -> Vec<PathCommand> {
+        let outline = match self.fonts.get(&font_key).and_then(|font| font.outline(GlyphId(glyph_index as u16))) {
+            Some(outline) => outline,
+            None => return Vec::new(),
+        };
+        // ab_glyph keeps the segments of the outline but not its contours, a
+        // contour ends where a segment doesn't start from the previous end.
+        let mut commands = Vec::new();
+        let mut end = None;
+        for curve in outline.curves {
+            let (start, command, new_end) = match curve {
+                OutlineCurve::Line(p0, p1) => (p0, PathCommand::LineTo(point2(p1.x, p1.y)), p1),
+                OutlineCurve::Quad(p0, p1, p2) => {
+                    (p0, PathCommand::QuadTo(point2(p1.x, p1.y), point2(p2.x, p2.y)), p2)
+                }
+                OutlineCurve::Cubic(p0, p1, p2, p3) => {
+                    (p0, PathCommand::CubicTo(point2(p1.x, p1.y), point2(p2.x, p2.y), point2(p3.x, p3.y)), p3)
+                }
+            };
+            if end != Some(start) {
+                if end.is_some() {
+                    commands.push(PathCommand::Close);
+                }
+                commands.push(PathCommand::MoveTo(point2(start.x, start.y)));
+            }
+            commands.push(command);
+            end = Some(new_end);
+        }
+        if end.is_some() {
+            commands.push(PathCommand::Close);
+        }
+        commands
+    }
+
+    pub fn get_glyph_dimensions(
+        &mut self,
+        font: &FontInstance,
+        key: &GlyphKey,
+    ) -> Option<GlyphDimensions> {
+        let cached = self.fonts.get(&font.font_key)?;
+        let scale = px_scale(cached, font);
+        let glyph_id = GlyphId(key.index() as u16);
+        let outlined = outline_glyph(cached, font, glyph_id.with_scale(scale))?;
+        let bounds = outlined.px_bounds();
+        let (width, height) = (bounds.width() as i32, bounds.height() as i32);
+        if width == 0 || height == 0 {
+            return None;
+        }
+        Some(GlyphDimensions {
+            left: bounds.min.x as i32,
+            top: -bounds.min.y as i32,
+            width,
+            height,
+            advance: cached.as_scaled(scale).h_advance(glyph_id),
+        })
+    }
+
+    pub fn prepare_font(font: &mut FontInstance) {
+        // ab_glyph only renders coverage, glyphs are drawn with grayscale
+        // anti-aliasing instead.
+        font.disable_subpixel_aa();
+        match font.render_mode {
+            FontRenderMode::Mono => {
+                // In mono mode the color of the font is irrelevant.
+                font.color = ColorU::new(0xFF, 0xFF, 0xFF, 0xFF);
+                // Subpixel positioning is disabled in mono mode.
+                font.disable_subpixel_position();
+            }
+            FontRenderMode::Alpha | FontRenderMode::Subpixel => {
+                font.color = font.color.luminance_color().quantize();
+            }
+        }
+    }
+
+    pub fn begin_rasterize(_: &FontInstance) {}
+
+    pub fn end_rasterize(_: &FontInstance) {}
+
+    pub fn rasterize_glyph(&mut self, font: &FontInstance, key: &GlyphKey) -> GlyphRasterResult {
+        let cached = self.fonts.get(&font.font_key).ok_or(GlyphRasterError::LoadFailed)?;
+        let (x_offset, y_offset) = font.get_subpx_offset(key);
+        let glyph = GlyphId(key.index() as u16).with_scale_and_position(
+            px_scale(cached, font),
+            // Glyph positions are y-down, as in WebRender.
+            point(x_offset as f32, y_offset as f32),
+        );
+        let outlined = outline_glyph(cached, font, glyph).ok_or(GlyphRasterError::LoadFailed)?;
+
+        // The bounds are whole pixels, y-down from the baseline.
+        let bounds = outlined.px_bounds();
+        let (width, height) = (bounds.width() as usize, bounds.height() as usize);
+        if width == 0 || height == 0 {
+            return Err(GlyphRasterError::LoadFailed);
+        }
+
+        let mut bytes = vec![0; width * height * 4];
+        outlined.draw(|x, y, coverage| {
+            let alpha = (coverage.min(1.0) * 255.0).round() as u8;
+            let offset = (y as usize * width + x as usize) * 4;
+            bytes[offset .. offset + 4].copy_from_slice(&[alpha; 4]);
+        });
+
+        if font.render_mode != FontRenderMode::Mono {
+            let options = font.platform_options.unwrap_or_default();
+            let gamma_lut = self.gamma_luts
+                .entry((options.gamma, options.contrast))
+                .or_insert_with(|| GammaLut::new(
+                    options.contrast as f32 / 100.0,
+                    options.gamma as f32 / 100.0,
+                    options.gamma as f32 / 100.0,
+                ));
+            gamma_lut.preblend(&mut bytes, font.color);
+        }
+
+        Ok(RasterizedGlyph {
+            left: bounds.min.x,
+            top: -bounds.min.y,
+            width: width as i32,
+            height: height as i32,
+            scale: 1.0,
+            format: font.get_alpha_glyph_format(),
+            bytes,
+        })
+    }
+}
+
+/// Outlines `glyph`, stretched and slanted for the synthetic stretch and
+/// italics of `font`. ab_glyph can't transform glyphs, but their outlines can
+/// be scaled and skewed before they are drawn.
+fn outline_glyph(cached: &FontVec, font: &FontInstance, glyph: Glyph) -> Option<OutlinedGlyph> {
+    if !font.synthetic_italics.is_enabled() && !font.synthetic_stretch.is_enabled() {
+        return cached.outline_glyph(glyph);
+    }
+    let mut outline = cached.outline(glyph.id)?;
+    // Outlines are in font units, y-up, so points above the baseline move
+    // right.
+    let stretch = font.synthetic_stretch.to_scale();
+    let skew = font.synthetic_italics.to_skew();
+    let transform_point = |p: Point| point(p.x * stretch + skew * p.y, p.y);
+    for curve in &mut outline.curves {
+        *curve = match *curve {
+            OutlineCurve::Line(p0, p1) => OutlineCurve::Line(transform_point(p0), transform_point(p1)),
+            OutlineCurve::Quad(p0, p1, p2) => {
+                OutlineCurve::Quad(transform_point(p0), transform_point(p1), transform_point(p2))
+            }
+            OutlineCurve::Cubic(p0, p1, p2, p3) => OutlineCurve::Cubic(
+                transform_point(p0),
+                transform_point(p1),
+                transform_point(p2),
+                transform_point(p3),
+            ),
+        };
+    }
+    // The bounds go from the top left to the bottom right corner.
+    let Rect { min, max } = outline.bounds;
+    let (top_offset, bottom_offset) = (skew * min.y, skew * max.y);
+    outline.bounds = Rect {
+        min: point(min.x * stretch + top_offset.min(bottom_offset), min.y),
+        max: point(max.x * stretch + top_offset.max(bottom_offset), max.y),
+    };
+    let scale_factor = cached.as_scaled(glyph.scale).scale_factor();
+    Some(OutlinedGlyph::new(glyph, outline, scale_factor))
+}
+
+/// The scale glyphs of `font` are rendered at. ab_glyph scales fonts by their
+/// height from descent to ascent rather than by their em size. It has no
+/// hinter, full hinting only rounds the size to whole pixels.
+fn px_scale(cached: &FontVec, font: &FontInstance) -> PxScale {
+    let size = match font.platform_options.unwrap_or_default().hinting {
+        FontHinting::Mono => font.size.to_f32_px().round().max(1.0),
+        _ => font.size.to_f32_px(),
+    };
+    let height = cached.height_unscaled();
+    let units_per_em = cached.units_per_em().unwrap_or(height);
+    PxScale::from(size * height / units_per_em)
+}
@@ -0,0 +1,152 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Forwards to the font context of the backend selected with `GlyphBackend`,
+//! among the backends the crate is built with.
+
+use api::{FontKey, GlyphDimensions, GlyphIndex, NativeFontHandle, ParsedFontInfo, PathCommand};
+use crate::rasterizer::{FontInstance, GlyphBackend, GlyphKey, GlyphRasterError, GlyphRasterResult};
+use malloc_size_of::MallocSizeOfOps;
+use std::path::Path;
+use std::sync::Arc;
+
+macro_rules! dispatch {
+    ($self:ident, $context:ident => $e:expr) => {
+        match *$self {
+            #[cfg(feature = "backend_swash")]
+            FontContext::Swash(ref mut $context) => $e,
+            #[cfg(feature = "backend_fontdue")]
+            FontContext::Fontdue(ref mut $context) => $e,
+            #[cfg(feature = "backend_ab_glyph")]
+            FontContext::AbGlyph(ref mut $context) => $e,
+            #[cfg(feature = "backend_tiny_skia")]
+            FontContext::TinySkia(ref mut $context) => $e,
+        }
+    };
+}
+
+pub enum FontContext {
+    #[cfg(feature = "backend_swash")]
+    Swash(super::swash::font::FontContext),
+    #[cfg(feature = "backend_fontdue")]
+    Fontdue(super::fontdue::font::FontContext),
+    #[cfg(feature = "backend_ab_glyph")]
+    AbGlyph(super::ab_glyph::font::FontContext),
+    #[cfg(feature = "backend_tiny_skia")]
+    TinySkia(super::tiny_skia::font::FontContext),
+}
+
+impl FontContext {
+    pub fn distribute_across_threads() -> bool {
+        true
+    }
+
+    /// Creates a font context of `backend`, which must be available.
+    pub fn new(backend: GlyphBackend) -> FontContext {
+        match backend {
+            #[cfg(feature = "backend_swash")]
+            GlyphBackend::Swash => FontContext::Swash(super::swash::font::FontContext::new()),
+            #[cfg(feature = "backend_fontdue")]
+            GlyphBackend::Fontdue => FontContext::Fontdue(super::fontdue::font::FontContext::new()),
+            #[cfg(feature = "backend_ab_glyph")]
+            GlyphBackend::AbGlyph => FontContext::AbGlyph(super::ab_glyph::font::FontContext::new()),
+            #[cfg(feature = "backend_tiny_skia")]
+            GlyphBackend::TinySkia => FontContext::TinySkia(super::tiny_skia::font::FontContext::new()),
+            _ => panic!("Glyph backend {:?} isn't available", backend),
+        }
+    }
+
+    pub fn add_raw_font(&mut self, font_key: &FontKey, bytes: Arc<Vec<u8>>, index: u32) -> Result<(), GlyphRasterError> {
+        dispatch!(self, context => context.add_raw_font(font_key, bytes, index))
+    }
+
+    pub fn add_parsed_font(
+        &mut self,
+        font_key: &FontKey,
+        bytes: Arc<Vec<u8>>,
+        index: u32,
+        info: ParsedFontInfo,
+    ) -> Result<(), GlyphRasterError> {
+        dispatch!(self, context => context.add_parsed_font(font_key, bytes, index, info))
+    }
+
+    pub fn add_file_font(&mut self, font_key: &FontKey, path: &Path, index: u32) -> Result<(), GlyphRasterError> {
+        dispatch!(self, context => context.add_file_font(font_key, path, index))
+    }
+
+    pub fn add_native_font(&mut self, font_key: &FontKey, native_font_handle: NativeFontHandle) -> Result<(), GlyphRasterError> {
+        dispatch!(self, context => context.add_native_font(font_key, native_font_handle))
+    }
+
+    pub fn delete_font(&mut self, font_key: &FontKey) {
+        dispatch!(self, context => context.delete_font(font_key))
+    }
+
+    pub fn delete_font_instance(&mut self, instance: &FontInstance) {
+        dispatch!(self, context => context.delete_font_instance(instance))
+    }
+
+    pub fn get_glyph_index(&mut self, font_key: FontKey, ch: char) -> Option<u32> {
+        dispatch!(self, context => context.get_glyph_index(font_key, ch))
+    }
+
+    pub fn get_glyph_variant_index(&mut self, font_key: FontKey, ch: char, selector: char) -> Option<u32> {
+        dispatch!(self, context => context.get_glyph_variant_index(font_key, ch, selector))
+    }
+
+    pub fn get_glyph_outline(&mut self, font_key: FontKey, glyph_index: GlyphIndex) -> Vec<PathCommand> {
+        dispatch!(self, context => context.get_glyph_outline(font_key, glyph_index))
+    }
+
+    pub fn get_glyph_dimensions(
+        &mut self,
+        font: &FontInstance,
+        key: &GlyphKey,
+    ) -> Option<GlyphDimensions> {
+        dispatch!(self, context => context.get_glyph_dimensions(font, key))
+    }
+
+    /// Prepares `font` for `backend`, which must be available.
+    pub fn prepare_font(backend: GlyphBackend, font: &mut FontInstance) {
+        match backend {
+            #[cfg(feature = "backend_swash")]
+            GlyphBackend::Swash => super::swash::font::FontContext::prepare_font(font),
+            #[cfg(feature = "backend_fontdue")]
+            GlyphBackend::Fontdue => super::fontdue::font::FontContext::prepare_font(font),
+            #[cfg(feature = "backend_ab_glyph")]
+            GlyphBackend::AbGlyph => super::ab_glyph::font::FontContext::prepare_font(font),
+            #[cfg(feature = "backend_tiny_skia")]
+            GlyphBackend::TinySkia => super::tiny_skia::font::FontContext::prepare_font(font),
+            _ => panic!("Glyph backend {:?} isn't available", backend),
+        }
+    }
+
+    /// Only the swash backend caches glyph images.
+    pub fn set_image_cache_budget(_bytes: usize) {
+        #[cfg(feature = "backend_swash")]
+        super::swash::font::FontContext::set_image_cache_budget(_bytes);
+    }
+
+    pub fn on_memory_pressure(_critical: bool) -> usize {
+        #[cfg(feature = "backend_swash")]
+        return super::swash::font::FontContext::on_memory_pressure(_critical);
+        #[cfg(not(feature = "backend_swash"))]
+        0
+    }
+
+    pub fn report_memory(_ops: &mut MallocSizeOfOps) -> usize {
+        #[cfg(feature = "backend_swash")]
+        return super::swash::font::FontContext::report_memory(_ops);
+        #[cfg(not(feature = "backend_swash"))]
+        0
+    }
+
+    pub fn begin_rasterize(_: &FontInstance) {}
+
+    pub fn end_rasterize(_: &FontInstance) {}
+
+    pub fn rasterize_glyph(&mut self, font: &FontInstance, key: &GlyphKey) -> GlyphRasterResult {
+        dispatch!(self, context => context.rasterize_glyph(font, key))
+    }
+}
@@ -0,0 +1,194 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Fonts shared by the font contexts of all the rasterizer threads.
+//!
+//! A backend keeps the fonts it parses in a `FontDataCache`, so that a font
+//! is parsed and stored once however many threads rasterize it, and fonts
+//! added with different keys but the same data share the parsed font.
+
+use api::FontKey;
+use crate::rasterizer::GlyphRasterError;
+use crate::types::FastHashMap;
+use fxhash::FxHasher;
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// The data of a font, either shared with its template or loaded from a font
+/// file.
+#[derive(Clone)]
+pub enum FontBytes {
+    Shared(Arc<Vec<u8>>),
+    #[cfg(feature = "mmap")]
+    Mapped(Arc<memmap2::Mmap>),
+}
+
+impl FontBytes {
+    /// Loads the font file at `path`. With the `mmap` feature the file is
+    /// mapped into memory, so that only the pages rasterizing reads are
+    /// resident, otherwise or if mapping fails it is read.
+    pub fn from_file(path: &Path) -> Result<Self, GlyphRasterError> {
+        #[cfg(feature = "mmap")]
+        {
+            // Safe as long as the file isn't modified while it is mapped, which
+            // `FontTemplate::File` requires of the embedder.
+            match fs::File::open(path).and_then(|file| unsafe { memmap2::Mmap::map(&file) }) {
+                Ok(map) => return Ok(FontBytes::Mapped(Arc::new(map))),
+                Err(e) => debug!("Failed to map font file {:?}, reading it: {}", path, e),
+            }
+        }
+        match fs::read(path) {
+            Ok(data) => Ok(FontBytes::Shared(Arc::new(data))),
+            Err(e) => {
+                error!("Failed to read font file {:?}: {}", path, e);
+                Err(GlyphRasterError::LoadFailed)
+            }
+        }
+    }
+
+    fn ptr_eq(&self, other: &FontBytes) -> bool {
+        self.as_ptr() == other.as_ptr() && self.len() == other.len()
+    }
+}
+
+impl Deref for FontBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match *self {
+            FontBytes::Shared(ref data) => data,
+            #[cfg(feature = "mmap")]
+            FontBytes::Mapped(ref map) => map,
+        }
+    }
+}
+
+impl fmt::Debug for FontBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The font data itself is too large to be useful in debug output.
+        let kind = match *self {
+            FontBytes::Shared(..) => "Shared",
+            #[cfg(feature = "mmap")]
+            FontBytes::Mapped(..) => "Mapped",
+        };
+        write!(f, "FontBytes::{}({} bytes)", kind, self.len())
+    }
+}
+
+impl From<Arc<Vec<u8>>> for FontBytes {
+    fn from(data: Arc<Vec<u8>>) -> Self {
+        FontBytes::Shared(data)
+    }
+}
+
+/// Identifies a font by the content of its data.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct ContentKey {
+    hash: u64,
+    len: usize,
+    index: u32,
+}
+
+impl ContentKey {
+    fn new(data: &[u8], index: u32) -> Self {
+        let mut hasher = FxHasher::default();
+        data.hash(&mut hasher);
+        ContentKey {
+            hash: hasher.finish(),
+            len: data.len(),
+            index,
+        }
+    }
+}
+
+struct CachedFont<T> {
+    data: FontBytes,
+    font: Arc<T>,
+    /// The number of font keys using the font.
+    keys: usize,
+}
+
+struct Fonts<T> {
+    keys: FastHashMap<FontKey, ContentKey>,
+    fonts: FastHashMap<ContentKey, CachedFont<T>>,
+}
+
+pub struct FontDataCache<T> {
+    fonts: Mutex<Fonts<T>>,
+}
+
+impl<T> FontDataCache<T> {
+    pub fn new() -> Self {
+        FontDataCache {
+            fonts: Mutex::new(Fonts {
+                keys: FastHashMap::default(),
+                fonts: FastHashMap::default(),
+            }),
+        }
+    }
+
+    /// Returns the font of `font_key`, loading it from `data` with `load` if
+    /// no font with the same key or data is cached.
+    ///
+    /// The cache stays locked while loading, so that threads adding the same
+    /// font wait for it to be loaded once rather than loading it again.
+    pub fn get_or_load<F, E>(
+        &self,
+        font_key: FontKey,
+        data: &FontBytes,
+        index: u32,
+        load: F,
+    ) -> Result<Arc<T>, E>
+    where
+        F: FnOnce(&FontBytes, u32) -> Result<T, E>,
+    {
+        let mut locked = self.fonts.lock().unwrap();
+        let Fonts { ref mut keys, ref mut fonts } = *locked;
+        if let Some(content) = keys.get(&font_key) {
+            return Ok(Arc::clone(&fonts[content].font));
+        }
+
+        let content = ContentKey::new(data, index);
+        let cached = match fonts.entry(content) {
+            Entry::Occupied(entry) => {
+                let cached = entry.into_mut();
+                if !cached.data.ptr_eq(data) && *cached.data != **data {
+                    // A hash collision, the font isn't shared.
+                    return load(data, index).map(Arc::new);
+                }
+                cached
+            }
+            Entry::Vacant(entry) => entry.insert(CachedFont {
+                data: data.clone(),
+                font: Arc::new(load(data, index)?),
+                keys: 0,
+            }),
+        };
+        cached.keys += 1;
+        keys.insert(font_key, content);
+        Ok(Arc::clone(&cached.font))
+    }
+
+    /// Forgets `font_key`, and the font once no other key uses it. Font
+    /// contexts holding the font keep it alive until they delete it too.
+    pub fn remove(&self, font_key: &FontKey) {
+        let mut locked = self.fonts.lock().unwrap();
+        let Fonts { ref mut keys, ref mut fonts } = *locked;
+        let content = match keys.remove(font_key) {
+            Some(content) => content,
+            None => return,
+        };
+        if let Entry::Occupied(mut entry) = fonts.entry(content) {
+            entry.get_mut().keys -= 1;
+            if entry.get().keys == 0 {
+                entry.remove();
+            }
+        }
+    }
+}
@@ -4,120 +4,53 @@
 
 use std::mem;
 use std::cmp::max;
-use parking_lot::Mutex;
-use api::{ColorU, GlyphDimensions, FontKey, FontRenderMode};
-use api::{FontInstanceFlags, FontTemplate, NativeFontHandle};
+use api::{ColorU, GlyphDimensions, GlyphIndex, FontKey, FontRenderMode, PathCommand};
+use api::{FontHinting, FontInstanceFlags, FontInstancePlatformOptions, FontStrikeSelection};
+use api::{NativeFontHandle, ParsedFontInfo};
+use crate::backend::font_data::{FontBytes, FontDataCache};
+use crate::gamma_lut::{ColorLut, GammaLut};
 use crate::rasterizer::{FontInstance, GlyphKey};
 use crate::rasterizer::{GlyphFormat, GlyphRasterError, GlyphRasterResult, RasterizedGlyph};
 use crate::types::FastHashMap;
+use euclid::point2;
+use std::path::Path;
 use std::sync::{Arc};
-use std::sync::OnceLock;
 
-type FontHash = FontKey;
-type RawTemplate = (Arc<Vec<u8>>, u32);
+type RawTemplate = (FontBytes, u32);
 #[derive(Debug)]
 struct CachedFont {
-    hash: FontHash,
     data: RawTemplate,
     font: fontdue::Font,
 }
 
-// Maps a template to a cached font that may be used across all threads.
-struct FontCache {
-    fonts: FastHashMap<FontHash, Arc<CachedFont>>,
+lazy_static! {
+    /// The fonts parsed by the font contexts of all the rasterizer threads.
+    static ref FONT_DATA: FontDataCache<CachedFont> = FontDataCache::new();
 }
 
-// Fontdue resources are safe to move between threads as long as they
-// are not concurrently accessed. In our case, everything is behind a
-// Mutex so it is safe to move them between threads.
-// unsafe impl Send for CachedFont {}
-unsafe impl Send for FontCache {}
-
-static FONT_CACHE: OnceLock<Arc<Mutex<FontCache>>> = OnceLock::new();
-
-impl FontCache {
-    fn new() -> Self {
-        FontCache {
-            fonts: FastHashMap::default(),
+fn load_font(bytes: &FontBytes, index: u32) -> Result<CachedFont, GlyphRasterError> {
+    let settings = fontdue::FontSettings {
+        collection_index: index,
+        ..fontdue::FontSettings::default()
+    };
+    match fontdue::Font::from_bytes(&**bytes, settings) {
+        Ok(font) => Ok(CachedFont {
+            data: (bytes.clone(), index),
+            font,
+        }),
+        Err(e) => {
+            error!(
+                "Failed to create fontdue instance: collection_index={} err={:?}",
+                settings.collection_index, e
+            );
+            Err(GlyphRasterError::LoadFailed)
         }
     }
-
-    pub fn global() -> &'static Mutex<FontCache> {
-        FONT_CACHE.get_or_init(|| {
-            log::trace!("font cache is being created...");
-            Arc::new(Mutex::new(Self::new()))
-        })
-    }
-
-    fn cache_mut<P, T>(p: P) -> Option<T>
-    where
-        P: FnOnce(&mut FontCache) -> T,
-    {
-        match Self::global().clone().try_lock() {
-            Some(mut cache) => Some(p(&mut cache)),
-            None => {
-                error!("font cache not available...");
-                None
-            }
-        }
-    }
-
-    pub fn with_font<P, T>(font_key: FontKey, font_template: FontTemplate, p: P) -> Option<T>
-    where
-        P: FnOnce(Arc<CachedFont>) -> T,
-    {
-        let hash = font_key;
-
-        FontCache::cache_mut(|cache| {
-            if let Some(cached) = cache.fonts.get(&hash) {
-                return p(cached.clone());
-            }
-
-            let (bytes, index) = match font_template {
-                FontTemplate::Raw(ref bytes, index) => (bytes.clone(), index),
-                FontTemplate::Native(_) => {
-                    todo!()
-                }
-            };
-
-            let settings = fontdue::FontSettings {
-                collection_index: index,
-                ..fontdue::FontSettings::default()
-            };
-
-            let cached = match fontdue::Font::from_bytes(bytes.as_slice(), settings) {
-                Ok(font) => Arc::new(CachedFont {
-                    hash: hash.clone(),
-                    data: (bytes, index),
-                    font,
-                }),
-                Err(e) => {
-                    panic!(
-                        "Faile to create fontdue instance: scale={} collection_index={} err={:?}",
-                        settings.scale, settings.collection_index, e
-                    );
-                }
-            };
-            cache.fonts.insert(hash, cached.clone());
-            p(cached)
-        })
-    }
-
-    fn delete_font(cached: Arc<CachedFont>) {
-        FontCache::cache_mut(|cache| {
-            cache.fonts.remove(&cached.hash);
-        });
-    }
-}
-
-impl Drop for FontCache {
-    fn drop(&mut self) {
-        self.fonts.clear();
-    }
 }
 
 pub struct FontContext {
-    fonts: FastHashMap<FontHash, Arc<CachedFont>>,
+    fonts: FastHashMap<FontKey, Arc<CachedFont>>,
+    gamma_luts: FastHashMap<(u16, u8), GammaLut>,
 }
 
 impl FontContext {
@@ -128,35 +61,53 @@ impl FontContext {
     pub fn new() -> FontContext {
         FontContext {
             fonts: FastHashMap::default(),
+            gamma_luts: FastHashMap::default(),
         }
     }
 
-    pub fn add_raw_font(&mut self, font_key: &FontKey, bytes: Arc<Vec<u8>>, index: u32) {
-        let cached =
-            FontCache::with_font(*font_key, FontTemplate::Raw(bytes, index), |cached| cached);
-        if let Some(cached) = cached {
-            self.fonts.entry(*font_key).or_insert_with(|| cached);
+    pub fn add_raw_font(&mut self, font_key: &FontKey, bytes: Arc<Vec<u8>>, index: u32) -> Result<(), GlyphRasterError> {
+        if self.fonts.contains_key(font_key) {
+            return Ok(());
         }
+        self.add_font_bytes(font_key, bytes.into(), index)
     }
 
-    pub fn add_native_font(&mut self, font_key: &FontKey, native_font_handle: NativeFontHandle) {
-        let cached = FontCache::with_font(
-            *font_key,
-            FontTemplate::Native(native_font_handle),
-            |cached| cached,
-        );
-        if let Some(cached) = cached {
-            self.fonts.entry(*font_key).or_insert_with(|| cached);
+    /// Loads the font at `index` in the font file at `path`, see
+    /// `FontBytes::from_file`.
+    pub fn add_file_font(&mut self, font_key: &FontKey, path: &Path, index: u32) -> Result<(), GlyphRasterError> {
+        if self.fonts.contains_key(font_key) {
+            return Ok(());
         }
+        self.add_font_bytes(font_key, FontBytes::from_file(path)?, index)
+    }
+
+    fn add_font_bytes(&mut self, font_key: &FontKey, bytes: FontBytes, index: u32) -> Result<(), GlyphRasterError> {
+        let cached = FONT_DATA.get_or_load(*font_key, &bytes, index, load_font)?;
+        self.fonts.insert(*font_key, cached);
+        Ok(())
+    }
+
+    /// fontdue can only create fonts by parsing their data, so the font is
+    /// loaded as a raw font. It is parsed once for all the contexts though,
+    /// see `FONT_DATA`.
+    pub fn add_parsed_font(
+        &mut self,
+        font_key: &FontKey,
+        bytes: Arc<Vec<u8>>,
+        index: u32,
+        _: ParsedFontInfo,
+    ) -> Result<(), GlyphRasterError> {
+        self.add_raw_font(font_key, bytes, index)
+    }
+
+    pub fn add_native_font(&mut self, _: &FontKey, _: NativeFontHandle) -> Result<(), GlyphRasterError> {
+        error!("fontdue can't load native fonts");
+        Err(GlyphRasterError::LoadFailed)
     }
 
     pub fn delete_font(&mut self, font_key: &FontKey) {
-        if let Some(cached) = self.fonts.remove(font_key) {
-            // If the only references to this font are the FontCache and this FontContext,
-            // then delete the font as there are no other existing users.
-            if Arc::strong_count(&cached) <= 2 {
-                FontCache::delete_font(cached);
-            }
+        if self.fonts.remove(font_key).is_some() {
+            FONT_DATA.remove(font_key);
         }
     }
 
@@ -177,6 +128,30 @@ impl FontContext {
         }
     }
 
+    /// fontdue doesn't map variation sequences, so they are looked up with
+    /// ttf-parser.
+    pub fn get_glyph_variant_index(&self, font_key: FontKey, ch: char, selector: char) -> Option<u32> {
+        let (bytes, index) = &self.fonts.get(&font_key)?.data;
+        let face = ttf_parser::Face::parse(bytes, *index).ok()?;
+        face.glyph_variation_index(ch, selector).map(|id| id.0 as u32)
+    }
+
+    pub fn get_glyph_outline(&self, font_key: FontKey, glyph_index: GlyphIndex) -> Vec<PathCommand> {
+        let (bytes, index) = match self.fonts.get(&font_key) {
+            Some(cached) => &cached.data,
+            None => return Vec::new(),
+        };
+        // fontdue doesn't expose the outlines it rasterizes, so they are
+        // read from the font again.
+        let face = match ttf_parser::Face::parse(bytes, *index) {
+            Ok(face) => face,
+            Err(_) => return Vec::new(),
+        };
+        let mut builder = OutlineBuilder(Vec::new());
+        face.outline_glyph(ttf_parser::GlyphId(glyph_index as u16), &mut builder);
+        builder.0
+    }
+
     pub fn get_glyph_dimensions(
         &mut self,
         font: &FontInstance,
@@ -189,16 +164,29 @@ impl FontContext {
 
         let rasterizer = rasterizer.unwrap();
         let glyph = key.index() as u16;
-        let size = font.size.to_f32_px();
+        let options = font.platform_options.unwrap_or_default();
+        let size = hinted_size(font, &options);
         let metrics = rasterizer.font.metrics_indexed(glyph, size);
 
         if metrics.width == 0 || metrics.height == 0 {
             None
         } else {
+            let (mut left, mut width) = (metrics.xmin, dilated_width(metrics.width, &options));
+            if font.synthetic_stretch.is_enabled() {
+                let (stretched_left, stretched_width) = stretch_extent(left, width, font.synthetic_stretch.to_scale());
+                left = stretched_left;
+                width = stretched_width;
+            }
+            if font.synthetic_italics.is_enabled() {
+                let top = metrics.height as f32 + metrics.ymin as f32;
+                let (left_offset, extra_width) = skew_extent(top, metrics.height, font.synthetic_italics.to_skew());
+                left += left_offset;
+                width += extra_width;
+            }
             Some(GlyphDimensions {
-                left: metrics.xmin as i32,
+                left,
                 top: metrics.ymin as i32,
-                width: metrics.width as i32,
+                width: width as i32,
                 height: metrics.height as i32,
                 advance: metrics.advance_width,
             })
@@ -213,9 +201,11 @@ impl FontContext {
                 // Subpixel positioning is disabled in mono mode.
                 font.disable_subpixel_position();
             }
-            FontRenderMode::Alpha | FontRenderMode::Subpixel => {
-                // We don't do any preblending with FreeType currently, so the color is not used.
-                font.color = ColorU::new(0xFF, 0xFF, 0xFF, 0xFF);
+            FontRenderMode::Alpha => {
+                font.color = font.color.luminance_color().quantize();
+            }
+            FontRenderMode::Subpixel => {
+                font.color = font.color.quantize();
             }
         }
     }
@@ -234,9 +224,25 @@ impl FontContext {
         let rasterizer = rasterizer.unwrap();
 
         let render_mode = font.render_mode;
-        let size = font.size.to_f32_px();
+        let options = font.platform_options.unwrap_or_default();
+        let size = hinted_size(font, &options);
 
         let glyph = key.index() as u16;
+        let glyph_id = ttf_parser::GlyphId(glyph);
+
+        // Color glyphs are drawn rather than outlines, unless outlines are
+        // preferred to bitmaps.
+        let (bytes, index) = &rasterizer.data;
+        let face = ttf_parser::Face::parse(bytes, *index).ok();
+        if let Some(ref face) = face {
+            let color_glyph = glyph_using_svg(face, glyph_id).or_else(|| match options.strike_selection {
+                FontStrikeSelection::OutlinesFirst => None,
+                selection => glyph_using_raster(face, glyph_id, size, selection == FontStrikeSelection::ExactSize),
+            });
+            if let Some((pixmap, x, y)) = color_glyph {
+                return Ok(color_bitmap_glyph(pixmap, x, y, size));
+            }
+        }
 
         let (metrics, mut bitmap) = if render_mode == FontRenderMode::Subpixel {
             rasterizer
@@ -251,37 +257,36 @@ impl FontContext {
             key, render_mode, metrics
         );
 
+        let channels = if render_mode == FontRenderMode::Subpixel { 3 } else { 1 };
+        let top = metrics.height as f32 + metrics.ymin as f32;
+        let mut left = metrics.xmin;
+        let mut width = dilated_width(metrics.width, &options);
+        if metrics.width != 0 && metrics.height != 0 {
+            bitmap = dilate_coverage(bitmap, metrics.width, channels, &options);
+            if font.synthetic_stretch.is_enabled() {
+                let stretch = font.synthetic_stretch.to_scale();
+                bitmap = stretch_coverage(&bitmap, width, channels, left, stretch);
+                let (stretched_left, stretched_width) = stretch_extent(left, width, stretch);
+                left = stretched_left;
+                width = stretched_width;
+            }
+            if font.synthetic_italics.is_enabled() {
+                let skew = font.synthetic_italics.to_skew();
+                bitmap = skew_coverage(&bitmap, width, channels, top, skew);
+                let (left_offset, extra_width) = skew_extent(top, metrics.height, skew);
+                left += left_offset;
+                width += extra_width;
+            }
+        }
+
         let mut gbra8_pixels: Vec<u8> = Vec::new();
 
         if metrics.width == 0 || metrics.height == 0 {
-            if let Some((mut pixmap, x, y)) = glyph_using_svg_or_raster(
-                &rasterizer.data,
-                ttf_parser::GlyphId(glyph as u16),
-                size,
-            ) {
-                for src in pixmap.data_mut().iter_mut().collect::<Vec<_>>().chunks(4) {
-                    let (r, g, b, a) = (*src[0], *src[1], *src[2], *src[3]);
-                    gbra8_pixels.push(b); // u8
-                    gbra8_pixels.push(g); // u8
-                    gbra8_pixels.push(r); // u8
-                    gbra8_pixels.push(a); // u8
-                }
-
-                let scale = size / max(pixmap.width(), pixmap.height()) as f32;
-
-                let top = pixmap.height() as f32 + y;
-                return Ok(RasterizedGlyph {
-                    left: x,
-                    top,
-                    width: pixmap.width() as i32,
-                    height: pixmap.height() as i32,
-                    scale,
-                    format: GlyphFormat::ColorBitmap,
-                    bytes: gbra8_pixels,
-                });
-            } else {
-                return Err(GlyphRasterError::LoadFailed);
-            }
+            // Fall back to a strike of any size.
+            return face
+                .and_then(|face| glyph_using_raster(&face, glyph_id, size, false))
+                .map(|(pixmap, x, y)| color_bitmap_glyph(pixmap, x, y, size))
+                .ok_or(GlyphRasterError::LoadFailed);
         } else {
             let format = match render_mode {
                 FontRenderMode::Subpixel => {
@@ -309,11 +314,20 @@ impl FontContext {
                     GlyphFormat::Bitmap
                 }
             };
-            let top = metrics.height as f32 + metrics.ymin as f32;
+            if render_mode != FontRenderMode::Mono {
+                let gamma_lut = self.gamma_luts
+                    .entry((options.gamma, options.contrast))
+                    .or_insert_with(|| GammaLut::new(
+                        options.contrast as f32 / 100.0,
+                        options.gamma as f32 / 100.0,
+                        options.gamma as f32 / 100.0,
+                    ));
+                gamma_lut.preblend(&mut gbra8_pixels, font.color);
+            }
             return Ok(RasterizedGlyph {
-                left: metrics.xmin as f32,
+                left: left as f32,
                 top,
-                width: metrics.width as i32,
+                width: width as i32,
                 height: metrics.height as i32,
                 scale: 1.0,
                 format,
@@ -323,19 +337,182 @@ impl FontContext {
     }
 }
 
-fn glyph_using_svg_or_raster(
-    (bytes, index): &RawTemplate,
-    glyph_id: ttf_parser::GlyphId,
-    size: f32,
-) -> Option<(tiny_skia::Pixmap, f32, f32)> {
-    let face = ttf_parser::Face::parse(bytes.as_slice(), *index);
+/// The size glyphs are rendered at. fontdue has no hinter, full hinting only
+/// rounds the size to whole pixels.
+fn hinted_size(font: &FontInstance, options: &FontInstancePlatformOptions) -> f32 {
+    match options.hinting {
+        FontHinting::Mono => font.size.to_f32_px().round().max(1.0),
+        _ => font.size.to_f32_px(),
+    }
+}
+
+/// The width of a glyph bitmap after `dilate_coverage`.
+fn dilated_width(width: usize, options: &FontInstancePlatformOptions) -> usize {
+    if options.dilation > 0 { width + 1 } else { width }
+}
+
+/// Applies the dilation of `FontInstancePlatformOptions` to a glyph bitmap
+/// with `channels` coverage values per pixel, which makes it one pixel wider.
+fn dilate_coverage(
+    bitmap: Vec<u8>,
+    width: usize,
+    channels: usize,
+    options: &FontInstancePlatformOptions,
+) -> Vec<u8> {
+    if options.dilation > 0 {
+        // Spread the coverage of each pixel over a fraction of the next one.
+        let spread = options.dilation.min(100) as u32;
+        let stride = width * channels;
+        let dilated_stride = stride + channels;
+        let mut dilated = vec![0u8; bitmap.len() / stride * dilated_stride];
+        for (src, dst) in bitmap.chunks_exact(stride).zip(dilated.chunks_exact_mut(dilated_stride)) {
+            dst[.. stride].copy_from_slice(src);
+            for i in 0 .. stride {
+                let spread_coverage = (src[i] as u32 * spread / 100) as u8;
+                dst[i + channels] = dst[i + channels].max(spread_coverage);
+            }
+        }
+        dilated
+    } else {
+        bitmap
+    }
+}
+
+/// The horizontal extent of the slant of synthetic italics over the `height`
+/// rows of a glyph whose top is `top` pixels above the baseline: the offset
+/// of the left edge of the slanted glyph, and how much wider it is.
+fn skew_extent(top: f32, height: usize, skew: f32) -> (i32, usize) {
+    let first = skew * (top - 0.5);
+    let last = skew * (top - height as f32 + 0.5);
+    let (min, max) = (first.min(last).floor(), first.max(last).ceil());
+    (min as i32, (max - min) as usize)
+}
+
+/// Slants a glyph bitmap with `channels` coverage values per pixel for
+/// synthetic italics, since fontdue can't transform outlines. Each row is
+/// shifted right by `skew` times its height above the baseline, and filtered
+/// linearly, into a bitmap widened as `skew_extent` computes.
+fn skew_coverage(bitmap: &[u8], width: usize, channels: usize, top: f32, skew: f32) -> Vec<u8> {
+    let stride = width * channels;
+    let height = bitmap.len() / stride;
+    let (left, extra_width) = skew_extent(top, height, skew);
+    let skewed_width = width + extra_width;
+    let skewed_stride = skewed_width * channels;
+    let mut skewed = vec![0u8; height * skewed_stride];
+    for (row, (src, dst)) in bitmap.chunks_exact(stride).zip(skewed.chunks_exact_mut(skewed_stride)).enumerate() {
+        let shift = skew * (top - row as f32 - 0.5);
+        let sample = |x: i32, channel: usize| -> f32 {
+            if x >= 0 && (x as usize) < width {
+                src[x as usize * channels + channel] as f32
+            } else {
+                0.0
+            }
+        };
+        for x in 0 .. skewed_width {
+            let src_x = (x as i32 + left) as f32 - shift;
+            let x0 = src_x.floor();
+            let frac = src_x - x0;
+            for channel in 0 .. channels {
+                let value = sample(x0 as i32, channel) * (1.0 - frac) + sample(x0 as i32 + 1, channel) * frac;
+                dst[x * channels + channel] = value.round() as u8;
+            }
+        }
+    }
+    skewed
+}
+
+/// The left edge and width of the pixels a glyph at `left` and `width` covers
+/// once scaled horizontally by `stretch`, from the glyph origin.
+fn stretch_extent(left: i32, width: usize, stretch: f32) -> (i32, usize) {
+    let min = (left as f32 * stretch).floor();
+    let max = ((left + width as i32) as f32 * stretch).ceil();
+    (min as i32, (max - min) as usize)
+}
+
+/// Scales a glyph bitmap with `channels` coverage values per pixel
+/// horizontally for synthetic stretch, since fontdue can't transform
+/// outlines. Each pixel of the stretched bitmap, which `stretch_extent`
+/// places, averages the coverage of the span of the row it covers.
+fn stretch_coverage(bitmap: &[u8], width: usize, channels: usize, left: i32, stretch: f32) -> Vec<u8> {
+    let stride = width * channels;
+    let height = bitmap.len() / stride;
+    let (stretched_left, stretched_width) = stretch_extent(left, width, stretch);
+    let stretched_stride = stretched_width * channels;
+    let mut stretched = vec![0u8; height * stretched_stride];
+    for (src, dst) in bitmap.chunks_exact(stride).zip(stretched.chunks_exact_mut(stretched_stride)) {
+        for x in 0 .. stretched_width {
+            let x = stretched_left + x as i32;
+            let start = (x as f32 / stretch - left as f32).max(0.0);
+            let end = ((x + 1) as f32 / stretch - left as f32).min(width as f32);
+            for channel in 0 .. channels {
+                let mut coverage = 0.0;
+                let mut src_x = start;
+                while src_x < end {
+                    let next = (src_x.floor() + 1.0).min(end);
+                    coverage += src[src_x as usize * channels + channel] as f32 * (next - src_x);
+                    src_x = next;
+                }
+                let dst_x = (x - stretched_left) as usize;
+                dst[dst_x * channels + channel] = (coverage * stretch).round().min(255.0) as u8;
+            }
+        }
+    }
+    stretched
+}
+
+/// Collects the segments of a glyph outline, in font units.
+struct OutlineBuilder(Vec<PathCommand>);
+
+impl ttf_parser::OutlineBuilder for OutlineBuilder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.0.push(PathCommand::MoveTo(point2(x, y)));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.0.push(PathCommand::LineTo(point2(x, y)));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.0.push(PathCommand::QuadTo(point2(x1, y1), point2(x, y)));
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.0.push(PathCommand::CubicTo(point2(x1, y1), point2(x2, y2), point2(x, y)));
+    }
+
+    fn close(&mut self) {
+        self.0.push(PathCommand::Close);
+    }
+}
 
-    if face.is_ok() {
-        return None;
+fn color_bitmap_glyph(mut pixmap: tiny_skia::Pixmap, x: f32, y: f32, size: f32) -> RasterizedGlyph {
+    let mut gbra8_pixels = Vec::with_capacity(pixmap.data().len());
+    for src in pixmap.data_mut().chunks(4) {
+        let (r, g, b, a) = (src[0], src[1], src[2], src[3]);
+        gbra8_pixels.push(b); // u8
+        gbra8_pixels.push(g); // u8
+        gbra8_pixels.push(r); // u8
+        gbra8_pixels.push(a); // u8
     }
 
-    let face = face.unwrap();
+    let scale = size / max(pixmap.width(), pixmap.height()) as f32;
+
+    let top = pixmap.height() as f32 + y;
+    RasterizedGlyph {
+        left: x,
+        top,
+        width: pixmap.width() as i32,
+        height: pixmap.height() as i32,
+        scale,
+        format: GlyphFormat::ColorBitmap,
+        bytes: gbra8_pixels,
+    }
+}
 
+fn glyph_using_svg(
+    face: &ttf_parser::Face,
+    glyph_id: ttf_parser::GlyphId,
+) -> Option<(tiny_skia::Pixmap, f32, f32)> {
     if let Some(svg_data) = face.glyph_svg_image(glyph_id) {
         let opts = usvg::Options {
             ..usvg::Options::default()
@@ -371,7 +548,24 @@ fn glyph_using_svg_or_raster(
 
         debug!("Glyph using svg: {:?}", glyph_id);
         return Some((pixmap, 0.0, 0.0));
-    } else if let Some(raster) = face.glyph_raster_image(glyph_id, size as u16) {
+    }
+    return None;
+}
+
+/// Draws the glyph from the bitmap strike ttf-parser picks for `size`, the
+/// smallest at least as large or else the largest. With `exact_size`, only
+/// a strike of the size is used.
+fn glyph_using_raster(
+    face: &ttf_parser::Face,
+    glyph_id: ttf_parser::GlyphId,
+    size: f32,
+    exact_size: bool,
+) -> Option<(tiny_skia::Pixmap, f32, f32)> {
+    let pixels_per_em = size.round() as u16;
+    if let Some(raster) = face.glyph_raster_image(glyph_id, pixels_per_em) {
+        if exact_size && raster.pixels_per_em != pixels_per_em {
+            return None;
+        }
         match tiny_skia::Pixmap::decode_png(raster.data) {
             Ok(pixmap) => {
                 debug!("Glyph using raster: {:?}", glyph_id);
@@ -2,19 +2,28 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use api::{ColorU, GlyphDimensions, FontKey, FontRenderMode, FontSize};
-use api::{FontInstanceFlags, NativeFontHandle};
+use api::{ColorU, GlyphDimensions, GlyphIndex, FontKey, FontRenderMode, FontSize, PathCommand};
+use api::{FontHinting, FontInstanceFlags, FontInstancePlatformOptions, FontLCDFilter, FontStrikeSelection};
+use api::{NativeFontHandle, ParsedFontInfo};
 use font_index::{FontCache, FontId, Font};
 use zeno::Placement;
-use crate::rasterizer::{FontInstance, GlyphKey};
+use crate::backend::font_data::{FontBytes, FontDataCache};
+use crate::gamma_lut::{ColorLut, GammaLut};
+use crate::rasterizer::{FontInstance, GlyphKey, DEFAULT_GLYPH_IMAGE_CACHE_BUDGET};
 use crate::rasterizer::{
-    GlyphFormat, GlyphRasterError, GlyphRasterResult, RasterizedGlyph, FontTransform,
+    apply_multistrike_bold, GlyphFormat, GlyphRasterError, GlyphRasterResult, RasterizedGlyph,
+    FontTransform,
 };
 use crate::types::FastHashMap;
-use std::collections::hash_map::Entry;
-use std::sync::Arc;
-use swash::FontRef;
-use swash::scale::ScaleContext;
+use fxhash::FxHasher;
+use malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use swash::{CacheKey, FontRef, Setting};
+use swash::scale::{ScaleContext, Scaler};
 use swash::scale::StrikeWith;
 use swash::scale::image::{Image as GlyphImage, Content};
 use swash::scale::Source;
@@ -28,11 +37,204 @@ fn is_bitmap_font(font: &FontInstance) -> bool {
     font.flags.contains(FontInstanceFlags::EMBEDDED_BITMAPS)
 }
 
+/// Returns the number of extra strikes of multistrike bold, and the distance
+/// between strikes in pixels.
+fn get_extra_strikes(font: &FontInstance) -> (usize, f64) {
+    let (x_scale, y_scale) = font.transform.compute_scale().unwrap_or((1.0, 1.0));
+    let (strike_scale, pixel_step) = if is_bitmap_font(font) {
+        (y_scale, 1.0)
+    } else {
+        (x_scale, y_scale / x_scale)
+    };
+    (font.get_extra_strikes(FontInstanceFlags::MULTISTRIKE_BOLD, strike_scale), pixel_step)
+}
+
+/// The number of independently locked parts of the glyph image cache.
+const GLYPH_IMAGE_CACHE_SHARDS: usize = 16;
+
+/// Font instances with different variations have different shared instance
+/// keys, so the key of a glyph image identifies the variations it was
+/// rendered with.
+type GlyphImageKey = (FontInstance, GlyphKey);
+
+static GLYPH_IMAGE_CACHE_BUDGET: AtomicUsize = AtomicUsize::new(DEFAULT_GLYPH_IMAGE_CACHE_BUDGET);
+
+/// The bytes of pixels of a glyph image, which is what the cache budget
+/// accounts for.
+fn image_bytes(image: &GlyphImage) -> usize {
+    image.data.len()
+}
+
+struct CachedGlyphImage {
+    image: Arc<GlyphImage>,
+    last_used: u64,
+}
+
+/// A part of the glyph image cache, with its images in least recently used
+/// order.
+#[derive(Default)]
+struct GlyphImageShard {
+    images: FastHashMap<GlyphImageKey, CachedGlyphImage>,
+    lru: BTreeMap<u64, GlyphImageKey>,
+    next_use: u64,
+    bytes: usize,
+}
+
+impl GlyphImageShard {
+    fn touch(&mut self, key: &GlyphImageKey) -> Option<Arc<GlyphImage>> {
+        let entry = self.images.get_mut(key)?;
+        let key = self.lru.remove(&entry.last_used).unwrap();
+        entry.last_used = self.next_use;
+        self.lru.insert(self.next_use, key);
+        self.next_use += 1;
+        Some(Arc::clone(&entry.image))
+    }
+
+    /// Evicts the least recently used images until the shard holds at most
+    /// `budget` bytes. Returns the number of bytes freed.
+    fn trim(&mut self, budget: usize) -> usize {
+        let bytes_before = self.bytes;
+        while self.bytes > budget {
+            let (_, key) = match self.lru.pop_first() {
+                Some(entry) => entry,
+                None => break,
+            };
+            let entry = self.images.remove(&key).unwrap();
+            self.bytes -= image_bytes(&entry.image);
+        }
+        bytes_before - self.bytes
+    }
+
+    fn retain<F>(&mut self, f: F) where F: Fn(&GlyphImageKey) -> bool {
+        let GlyphImageShard { ref mut images, ref mut lru, ref mut bytes, .. } = *self;
+        images.retain(|key, entry| {
+            let keep = f(key);
+            if !keep {
+                lru.remove(&entry.last_used);
+                *bytes -= image_bytes(&entry.image);
+            }
+            keep
+        });
+    }
+}
+
+/// Glyph images shared by the font contexts of all the rasterizer workers, so
+/// that a glyph rendered by one worker isn't rendered again by another. The
+/// cache is split in shards, so that workers seldom wait for each other, and
+/// each shard evicts its least recently used images once it holds more than
+/// its part of `GLYPH_IMAGE_CACHE_BUDGET`.
+struct GlyphImageCache {
+    shards: Vec<Mutex<GlyphImageShard>>,
+}
+
+impl GlyphImageCache {
+    fn new() -> Self {
+        GlyphImageCache {
+            shards: (0 .. GLYPH_IMAGE_CACHE_SHARDS).map(|_| Mutex::new(GlyphImageShard::default())).collect(),
+        }
+    }
+
+    fn shard(&self, key: &GlyphImageKey) -> &Mutex<GlyphImageShard> {
+        let mut hasher = FxHasher::default();
+        key.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % GLYPH_IMAGE_CACHE_SHARDS]
+    }
+
+    fn shard_budget() -> usize {
+        GLYPH_IMAGE_CACHE_BUDGET.load(Ordering::Relaxed) / GLYPH_IMAGE_CACHE_SHARDS
+    }
+
+    fn get(&self, key: &GlyphImageKey) -> Option<Arc<GlyphImage>> {
+        self.shard(key).lock().unwrap().touch(key)
+    }
+
+    /// Inserts the image of a glyph, unless another worker rendered it in the
+    /// meantime. Returns the cached image.
+    fn insert(&self, key: GlyphImageKey, image: GlyphImage) -> Arc<GlyphImage> {
+        let mut shard = self.shard(&key).lock().unwrap();
+        if let Some(image) = shard.touch(&key) {
+            return image;
+        }
+        let image = Arc::new(image);
+        let last_used = shard.next_use;
+        shard.next_use += 1;
+        shard.bytes += image_bytes(&image);
+        shard.lru.insert(last_used, key.clone());
+        shard.images.insert(key, CachedGlyphImage { image: Arc::clone(&image), last_used });
+        // The image that was just inserted is evicted last, rasterization
+        // still gets it if it alone exceeds the budget.
+        shard.trim(Self::shard_budget());
+        image
+    }
+
+    fn retain<F>(&self, f: F) where F: Fn(&GlyphImageKey) -> bool {
+        for shard in &self.shards {
+            shard.lock().unwrap().retain(&f);
+        }
+    }
+
+    /// Evicts images until each shard holds at most `budget` bytes. Returns
+    /// the number of bytes freed.
+    fn trim(&self, budget: usize) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap().trim(budget)).sum()
+    }
+}
+
+impl MallocSizeOf for GlyphImageCache {
+    fn size_of(&self, ops: &mut MallocSizeOfOps) -> usize {
+        let mut size = 0;
+        for shard in &self.shards {
+            let shard = shard.lock().unwrap();
+            size += shard.images.capacity() * mem::size_of::<(GlyphImageKey, CachedGlyphImage)>();
+            size += shard.lru.len() * mem::size_of::<(u64, GlyphImageKey)>();
+            for entry in shard.images.values() {
+                size += entry.image.data.size_of(ops);
+            }
+        }
+        size
+    }
+}
+
+lazy_static! {
+    static ref GLYPH_IMAGE_CACHE: GlyphImageCache = GlyphImageCache::new();
+}
+
+/// A font in its data, at the offset of its table directory.
+struct FontData {
+    data: FontBytes,
+    offset: u32,
+    key: CacheKey,
+}
+
+lazy_static! {
+    /// The fonts of the font contexts of all the rasterizer threads.
+    static ref FONT_DATA: FontDataCache<FontData> = FontDataCache::new();
+}
+
+/// A system font, which font-index loads, or a font added with its data.
+enum LoadedFont {
+    Native(Font),
+    Data(Arc<FontData>),
+}
+
+impl LoadedFont {
+    fn as_ref(&self) -> FontRef {
+        match *self {
+            LoadedFont::Native(ref font) => font.as_ref(),
+            LoadedFont::Data(ref font) => FontRef {
+                data: &font.data,
+                offset: font.offset,
+                key: font.key,
+            },
+        }
+    }
+}
+
 pub struct FontContext {
-    fonts: FastHashMap<FontKey, Font>,
+    fonts: FastHashMap<FontKey, LoadedFont>,
     font_cache: FontCache,
     scale_context: ScaleContext,
-    cache: FastHashMap<(FontInstance, GlyphKey), GlyphImage>,
+    gamma_luts: FastHashMap<(u16, u8), GammaLut>,
 }
 
 impl FontContext {
@@ -44,87 +246,170 @@ impl FontContext {
         FontContext {
             fonts: FastHashMap::default(),
             font_cache: FontCache::default(),
-            cache: FastHashMap::default(),
             scale_context: ScaleContext::new(),
+            gamma_luts: FastHashMap::default(),
         }
     }
 
-    pub fn add_raw_font(&mut self, font_key: &FontKey, data: Arc<Vec<u8>>, index: u32) {
+    pub fn add_raw_font(&mut self, font_key: &FontKey, data: Arc<Vec<u8>>, index: u32) -> Result<(), GlyphRasterError> {
         if self.fonts.contains_key(font_key) {
-            return;
+            return Ok(());
         }
-        if let Some(font) = Font::from_data(data.to_vec(), index as usize) {
-            self.fonts.insert(*font_key, font);
+        self.add_font_bytes(font_key, data.into(), index)
+    }
+
+    /// Loads the font at `index` in the font file at `path`, see
+    /// `FontBytes::from_file`.
+    pub fn add_file_font(&mut self, font_key: &FontKey, path: &Path, index: u32) -> Result<(), GlyphRasterError> {
+        if self.fonts.contains_key(font_key) {
+            return Ok(());
         }
+        self.add_font_bytes(font_key, FontBytes::from_file(path)?, index)
+    }
+
+    fn add_font_bytes(&mut self, font_key: &FontKey, data: FontBytes, index: u32) -> Result<(), GlyphRasterError> {
+        let font = FONT_DATA.get_or_load(*font_key, &data, index, |data, index| {
+            let font = FontRef::from_index(data, index as usize).ok_or(GlyphRasterError::LoadFailed)?;
+            Ok(FontData {
+                data: data.clone(),
+                offset: font.offset,
+                key: font.key,
+            })
+        })?;
+        self.fonts.insert(*font_key, LoadedFont::Data(font));
+        Ok(())
     }
 
-    pub fn add_native_font(&mut self, font_key: &FontKey, handle: NativeFontHandle) {
+    /// Adds a font without parsing its data.
+    pub fn add_parsed_font(
+        &mut self,
+        font_key: &FontKey,
+        data: Arc<Vec<u8>>,
+        index: u32,
+        info: ParsedFontInfo,
+    ) -> Result<(), GlyphRasterError> {
         if self.fonts.contains_key(font_key) {
-            return;
+            return Ok(());
         }
-        if let Some(font) = self.font_cache.get(FontId(handle.0)) {
-            self.fonts.insert(*font_key, font);
+        let font = FONT_DATA.get_or_load(*font_key, &data.into(), index, |data, _| {
+            Ok::<_, GlyphRasterError>(FontData {
+                data: data.clone(),
+                offset: info.offset,
+                key: CacheKey::new(),
+            })
+        })?;
+        self.fonts.insert(*font_key, LoadedFont::Data(font));
+        Ok(())
+    }
+
+    pub fn add_native_font(&mut self, font_key: &FontKey, handle: NativeFontHandle) -> Result<(), GlyphRasterError> {
+        if self.fonts.contains_key(font_key) {
+            return Ok(());
         }
+        let font = self.font_cache.get(FontId(handle.0)).ok_or(GlyphRasterError::LoadFailed)?;
+        self.fonts.insert(*font_key, LoadedFont::Native(font));
+        Ok(())
     }
 
     pub fn delete_font(&mut self, font_key: &FontKey) {
         if let Some(_) = self.fonts.remove(font_key) {
-            self.cache.retain(|k, _| k.0.font_key != *font_key);
+            FONT_DATA.remove(font_key);
+            GLYPH_IMAGE_CACHE.retain(|k| k.0.font_key != *font_key);
         }
     }
 
     pub fn delete_font_instance(&mut self, instance: &FontInstance) {
         // Remove the Swash image corresponding to this instance.
-        self.cache
-            .retain(|k, _| k.0.instance_key != instance.instance_key);
+        GLYPH_IMAGE_CACHE.retain(|k| k.0.instance_key != instance.instance_key);
     }
 
     pub fn get_glyph_index(&mut self, font_key: FontKey, ch: char) -> Option<u32> {
         match self.fonts.get(&font_key) {
             None => None,
             Some(font) => {
-                let index: u32 = font.charmap().map(ch).into();
+                let index: u32 = font.as_ref().charmap().map(ch).into();
                 return Some(index);
             }
         }
     }
 
+    /// swash's charmap doesn't map variation sequences, so they are looked
+    /// up in the font's cmap table with ttf-parser.
+    pub fn get_glyph_variant_index(&mut self, font_key: FontKey, ch: char, selector: char) -> Option<u32> {
+        use ttf_parser::cmap::{GlyphVariationResult, Table};
+
+        let font = self.fonts.get(&font_key)?.as_ref();
+        let cmap = Table::parse(font.table(swash::tag_from_bytes(b"cmap"))?)?;
+        for subtable in cmap.subtables {
+            match subtable.glyph_variation_index(ch as u32, selector as u32) {
+                Some(GlyphVariationResult::Found(id)) => return Some(id.0 as u32),
+                Some(GlyphVariationResult::UseDefault) => {
+                    return match font.charmap().map(ch) {
+                        0 => None,
+                        index => Some(index as u32),
+                    };
+                }
+                None => {}
+            }
+        }
+        None
+    }
+
+    pub fn get_glyph_outline(&mut self, font_key: FontKey, glyph_index: GlyphIndex) -> Vec<PathCommand> {
+        use euclid::default::Point2D;
+        use zeno::{Command, PathData};
+
+        let font = match self.fonts.get(&font_key) {
+            Some(font) => font.as_ref(),
+            None => return Vec::new(),
+        };
+        // Without a size, the scaler leaves the outline in font units.
+        let mut scaler = self.scale_context.builder(font).build();
+        let outline = match scaler.scale_outline(glyph_index as GlyphId) {
+            Some(outline) => outline,
+            None => return Vec::new(),
+        };
+        let point = |v: zeno::Vector| Point2D::new(v.x, v.y);
+        // Collected before returning, the commands borrow the outline.
+        let commands = outline
+            .path()
+            .commands()
+            .map(|command| match command {
+                Command::MoveTo(p) => PathCommand::MoveTo(point(p)),
+                Command::LineTo(p) => PathCommand::LineTo(point(p)),
+                Command::QuadTo(c, p) => PathCommand::QuadTo(point(c), point(p)),
+                Command::CurveTo(c1, c2, p) => PathCommand::CubicTo(point(c1), point(c2), point(p)),
+                Command::Close => PathCommand::Close,
+            })
+            .collect();
+        commands
+    }
+
     pub fn get_glyph_dimensions(
         &mut self,
         instance: &FontInstance,
         key: &GlyphKey,
     ) -> Option<GlyphDimensions> {
         let size = FontSize::from_f64_px(instance.get_transformed_size());
-        if let Some(GlyphImage {
-            placement:
-                Placement {
-                    left,
-                    top,
-                    width,
-                    height,
-                },
-            ..
-        }) = self.get_or_create_cache(instance, key)
-        {
-            if let Some(font) = self.fonts.get(&instance.font_key) {
-                let advance = font
-                    .as_ref()
-                    .glyph_metrics(&[])
-                    .scale(size.to_f32_px())
-                    .advance_width(key.index() as GlyphId);
-                return Some(GlyphDimensions {
-                    left: left as i32,
-                    top: top as i32,
-                    width: width as i32,
-                    height: height as i32,
-                    advance,
-                });
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+        let glyph_image = self.get_or_create_cache(instance, key)?;
+        let Placement { left, top, width, height } = glyph_image.placement;
+        let font = self.fonts.get(&instance.font_key)?;
+        // The advance is the default instance's, embedders shape variable
+        // fonts with the variations themselves.
+        let advance = font
+            .as_ref()
+            .glyph_metrics(&[])
+            .scale(size.to_f32_px())
+            .advance_width(key.index() as GlyphId);
+        let (extra_strikes, pixel_step) = get_extra_strikes(instance);
+        let extra_width = extra_strikes as f64 * pixel_step;
+        Some(GlyphDimensions {
+            left: left as i32,
+            top: top as i32,
+            width: width as i32 + extra_width.ceil() as i32,
+            height: height as i32,
+            advance: advance + extra_width as f32,
+        })
     }
 
     pub fn prepare_font(font: &mut FontInstance) {
@@ -135,37 +420,55 @@ impl FontContext {
                 // Subpixel positioning is disabled in mono mode.
                 font.disable_subpixel_position();
             }
-            FontRenderMode::Alpha | FontRenderMode::Subpixel => {
-                // We don't do any preblending with FreeType currently, so the color is not used.
-                font.color = ColorU::new(0xFF, 0xFF, 0xFF, 0xFF);
+            FontRenderMode::Alpha => {
+                font.color = font.color.luminance_color().quantize();
+            }
+            FontRenderMode::Subpixel => {
+                font.color = font.color.quantize();
             }
         }
     }
 
+    /// Sets the number of bytes of glyph images kept for all the font
+    /// contexts, evicting the least recently used images over the budget.
+    pub fn set_image_cache_budget(bytes: usize) {
+        GLYPH_IMAGE_CACHE_BUDGET.store(bytes, Ordering::Relaxed);
+        GLYPH_IMAGE_CACHE.trim(GlyphImageCache::shard_budget());
+    }
+
+    /// Evicts all the cached glyph images under critical memory pressure,
+    /// or the least recently used half of the budget otherwise. Returns the
+    /// number of bytes freed.
+    pub fn on_memory_pressure(critical: bool) -> usize {
+        let budget = if critical { 0 } else { GlyphImageCache::shard_budget() / 2 };
+        GLYPH_IMAGE_CACHE.trim(budget)
+    }
+
+    pub fn report_memory(ops: &mut MallocSizeOfOps) -> usize {
+        GLYPH_IMAGE_CACHE.size_of(ops)
+    }
+
     pub fn begin_rasterize(_: &FontInstance) {}
 
     pub fn end_rasterize(_: &FontInstance) {}
 
-    /// Create a swash Image from a cache key, caching results
+    /// Create a swash Image from a cache key, caching results in the cache
+    /// shared by all the workers.
     pub fn get_or_create_cache(
         &mut self,
         instance: &FontInstance,
         glyph_key: &GlyphKey,
-    ) -> Option<GlyphImage> {
-        match self.cache.entry((instance.clone(), glyph_key.clone())) {
-            Entry::Occupied(entry) => Some(entry.get().clone()),
-            Entry::Vacant(entry) => {
-                let font = self.fonts.get(&instance.font_key).unwrap();
-                if let Some(glyph) =
-                    render_glyph(&mut self.scale_context, &font.as_ref(), instance, glyph_key)
-                {
-                    entry.insert(glyph.clone());
-                    return Some(glyph);
-                } else {
-                    return None;
-                }
-            }
+    ) -> Option<Arc<GlyphImage>> {
+        let key = (instance.clone(), glyph_key.clone());
+        if let Some(glyph) = GLYPH_IMAGE_CACHE.get(&key) {
+            return Some(glyph);
         }
+
+        // The shard isn't locked while rendering, so that other workers can
+        // use it in the meantime.
+        let font = self.fonts.get(&instance.font_key).unwrap();
+        let glyph = render_glyph(&mut self.scale_context, &font.as_ref(), instance, glyph_key)?;
+        Some(GLYPH_IMAGE_CACHE.insert(key, glyph))
     }
     pub fn rasterize_glyph(
         &mut self,
@@ -173,24 +476,14 @@ impl FontContext {
         glyph_key: &GlyphKey,
     ) -> GlyphRasterResult {
         info!("todo");
-        let glyph_image = self.get_or_create_cache(instance, glyph_key);
-
-        if glyph_image.is_none() {
-            return Err(GlyphRasterError::LoadFailed);
-        }
+        let glyph_image = match self.get_or_create_cache(instance, glyph_key) {
+            Some(glyph_image) => glyph_image,
+            None => return Err(GlyphRasterError::LoadFailed),
+        };
 
-        let GlyphImage {
-            placement:
-                Placement {
-                    left,
-                    top,
-                    width,
-                    height,
-                },
-            data: pixels,
-            content,
-            ..
-        } = glyph_image.unwrap();
+        let Placement { left, top, width, height } = glyph_image.placement;
+        let pixels = &glyph_image.data;
+        let content = glyph_image.content;
 
         // Alpha texture bounds can sometimes return an empty rect
         // Such as for spaces
@@ -198,7 +491,7 @@ impl FontContext {
             return Err(GlyphRasterError::LoadFailed);
         }
 
-        let bgra_pixels = match content {
+        let mut bgra_pixels: Vec<u8> = match content {
             Content::Color | Content::SubpixelMask => {
                 assert!(width * height * 4 == pixels.len() as u32);
                 // let _ = image::RgbaImage::from_raw(width, height, pixels.clone()).unwrap().save("/tmp/emoji_".to_string() + glyph_key.index().to_string().as_str() + ".png");
@@ -220,6 +513,36 @@ impl FontContext {
                 .collect(),
         };
 
+        // Apply multistrike bold, if necessary, and replace the current pixels with it.
+        let mut width = width as usize;
+        let (extra_strikes, pixel_step) = get_extra_strikes(instance);
+        if extra_strikes > 0 {
+            let (bold_pixels, bold_width) = apply_multistrike_bold(
+                &bgra_pixels,
+                width,
+                height as usize,
+                matches!(content, Content::SubpixelMask),
+                extra_strikes,
+                pixel_step,
+            );
+            width = bold_width;
+            bgra_pixels = bold_pixels;
+        }
+
+        // Preblend the coverage of anti-aliased glyphs with the text color.
+        if !matches!(content, Content::Color) && instance.render_mode != FontRenderMode::Mono {
+            let FontInstancePlatformOptions { gamma, contrast, .. } =
+                instance.platform_options.unwrap_or_default();
+            let gamma_lut = self.gamma_luts
+                .entry((gamma, contrast))
+                .or_insert_with(|| GammaLut::new(
+                    contrast as f32 / 100.0,
+                    gamma as f32 / 100.0,
+                    gamma as f32 / 100.0,
+                ));
+            gamma_lut.preblend(&mut bgra_pixels, instance.color);
+        }
+
         let format = match content {
             Content::Mask => instance.get_alpha_glyph_format(),
             Content::SubpixelMask => instance.get_subpixel_glyph_format(),
@@ -238,18 +561,103 @@ impl FontContext {
     }
 }
 
+/// Draws the color outline of `glyph_id` as swash does, with the palette of
+/// `instance` and its overrides.
+fn render_color_outline(
+    scaler: &mut Scaler,
+    font: &FontRef,
+    instance: &FontInstance,
+    glyph_id: GlyphId,
+    offset: zeno::Vector,
+    transform: Option<zeno::Transform>,
+) -> Option<GlyphImage> {
+    use zeno::{Mask, Origin};
+    if !scaler.has_color_outlines() {
+        return None;
+    }
+    let mut outline = scaler.scale_color_outline(glyph_id)?;
+    if let Some(ref transform) = transform {
+        outline.transform(transform);
+    }
+    let palette = font.color_palettes().nth(instance.palette_index as usize);
+    let layer_color = |index: Option<u16>| -> [u8; 4] {
+        let index = match index {
+            Some(index) => index,
+            None => return [instance.color.r, instance.color.g, instance.color.b, instance.color.a],
+        };
+        match instance.palette_overrides.iter().find(|o| o.index == index) {
+            Some(o) => [o.color.r, o.color.g, o.color.b, o.color.a],
+            None => match palette {
+                Some(ref palette) => palette.get(index),
+                None => [instance.color.r, instance.color.g, instance.color.b, instance.color.a],
+            },
+        }
+    };
+
+    let bounds = outline.bounds();
+    let left = (bounds.min.x + offset.x).floor() as i32;
+    let bottom = (bounds.min.y + offset.y).ceil() as i32;
+    let width = bounds.width().ceil() as u32;
+    let height = bounds.height().ceil() as u32;
+    let mut image = GlyphImage::new();
+    image.source = Source::ColorOutline(instance.palette_index);
+    image.content = Content::Color;
+    image.placement = Placement { left, top: height as i32 + bottom, width, height };
+    image.data = vec![0; (width * height * 4) as usize];
+
+    // Blend the layers in order over the image, in non-premultiplied RGBA.
+    for i in 0 .. outline.len() {
+        let layer = outline.get(i)?;
+        let color = layer_color(layer.color_index());
+        let (mask, placement) = Mask::new(layer.path())
+            .origin(Origin::BottomLeft)
+            .render_offset(offset)
+            .render();
+        let x0 = placement.left - left;
+        let y0 = image.placement.top - placement.top;
+        for y in 0 .. placement.height as i32 {
+            let dst_y = y0 + y;
+            if dst_y < 0 || dst_y >= height as i32 {
+                continue;
+            }
+            for x in 0 .. placement.width as i32 {
+                let dst_x = x0 + x;
+                if dst_x < 0 || dst_x >= width as i32 {
+                    continue;
+                }
+                let a = (mask[(y * placement.width as i32 + x) as usize] as u32 * color[3] as u32) >> 8;
+                if a == 0 {
+                    continue;
+                }
+                let dst = &mut image.data[((dst_y * width as i32 + dst_x) * 4) as usize ..][.. 4];
+                if a >= 255 {
+                    dst[.. 3].copy_from_slice(&color[.. 3]);
+                    dst[3] = 255;
+                } else {
+                    let inverse_a = 255 - a;
+                    for (d, &c) in dst[.. 3].iter_mut().zip(&color[.. 3]) {
+                        *d = ((inverse_a * *d as u32 + a * c as u32) >> 8) as u8;
+                    }
+                    dst[3] = ((inverse_a * dst[3] as u32 + a * 255) >> 8) as u8;
+                }
+            }
+        }
+    }
+    Some(image)
+}
+
 fn render_glyph(
     context: &mut ScaleContext,
     font: &FontRef,
     instance: &FontInstance,
     glyph_key: &GlyphKey,
 ) -> Option<GlyphImage> {
-    use zeno::{Format, Vector};
-    let (x_scale, y_scale) = instance.transform.compute_scale().unwrap_or((1.0, 1.0));
+    use zeno::{Format, Transform, Vector};
+    let (_, y_scale) = instance.transform.compute_scale().unwrap_or((1.0, 1.0));
     let size = instance.size.to_f32_px() * y_scale as f32;
 
     // Transform
-    let (mut _transform, (x_offset, y_offset)) = if is_bitmap_font(instance) {
+    let (mut transform, (x_offset, y_offset)) = if is_bitmap_font(instance) {
         (FontTransform::identity(), (0.0, 0.0))
     } else {
         (
@@ -258,65 +666,135 @@ fn render_glyph(
         )
     };
 
-    // if instance.flags.contains(FontInstanceFlags::FLIP_X) {
-    //     transform = transform.flip_x();
-    // }
-    // if instance.flags.contains(FontInstanceFlags::FLIP_Y) {
-    //     transform = transform.flip_y();
-    // }
-    // if instance.flags.contains(FontInstanceFlags::TRANSPOSE) {
-    //     transform = transform.swap_xy();
-    // }
-
-    // let (transform, (tx, ty)) = if instance.synthetic_italics.is_enabled() {
-    //     instance.synthesize_italics(transform, size as f64)
-    // } else {
-    //     (transform, (0.0, 0.0))
-    // };
-
-    // Strike
-    let (strike_scale, _pixel_step) = if is_bitmap_font(instance) {
-        (y_scale, 1.0)
+    if instance.flags.contains(FontInstanceFlags::FLIP_X) {
+        transform = transform.flip_x();
+    }
+    if instance.flags.contains(FontInstanceFlags::FLIP_Y) {
+        transform = transform.flip_y();
+    }
+    if instance.flags.contains(FontInstanceFlags::TRANSPOSE) {
+        transform = transform.swap_xy();
+    }
+
+    // Oblique the outlines of fonts without an italic face.
+    let (transform, (tx, ty)) = if instance.synthetic_italics.is_enabled() && !is_bitmap_font(instance) {
+        instance.synthesize_italics(transform, size as f64)
     } else {
-        (x_scale, y_scale / x_scale)
+        (transform, (0.0, 0.0))
+    };
+    // And stretch them for fonts without a condensed or expanded face.
+    let transform = if instance.synthetic_stretch.is_enabled() && !is_bitmap_font(instance) {
+        instance.synthesize_stretch(transform)
+    } else {
+        transform
     };
-    let _extra_strikes = instance.get_extra_strikes(
-        FontInstanceFlags::SYNTHETIC_BOLD | FontInstanceFlags::MULTISTRIKE_BOLD,
-        strike_scale,
-    );
 
+    // Outlines are y-up, as for FreeType, whereas font transforms are y-down.
+    let transform = if transform.is_identity() && tx == 0.0 && ty == 0.0 {
+        None
+    } else {
+        Some(Transform::new(
+            transform.scale_x,
+            -transform.skew_y,
+            -transform.skew_x,
+            transform.scale_y,
+            tx as f32,
+            -ty as f32,
+        ))
+    };
+
+    let options = instance.platform_options.unwrap_or_default();
     let format = match instance.render_mode {
         FontRenderMode::Mono | FontRenderMode::Alpha => Format::Alpha,
-        FontRenderMode::Subpixel => Format::Subpixel,
+        FontRenderMode::Subpixel => {
+            // swash renders the color channels at horizontal offsets rather
+            // than filtering them. Smaller offsets reduce color fringes, at
+            // the cost of sharpness.
+            let offset = match options.lcd_filter {
+                FontLCDFilter::None => 1.0 / 3.0,
+                FontLCDFilter::Default => 0.3,
+                FontLCDFilter::Light => 0.2,
+                FontLCDFilter::Legacy => 0.15,
+            };
+            Format::CustomSubpixel([offset, 0.0, -offset])
+        }
     };
 
-    // let format = Format::CustomSubpixel([0.3, 0., -0.3]);
-
-    // TODO transform/strike
-    // check Render's embolden/style/transform
-    // is embolden strike?
+    // Hinting is for axis-aligned outlines.
+    let hinting = match transform {
+        Some(_) => FontHinting::None,
+        None => options.hinting,
+    };
 
     // Build the scaler
     let mut scaler = context
         .builder(*font)
         .size(size)
-        .hint(cfg!(not(target_os = "macos")))
-        // .variations(instance.variations.clone())
+        .hint(matches!(hinting, FontHinting::Normal | FontHinting::LCD | FontHinting::Mono))
+        .variations(instance.variations.iter().map(|variation| Setting {
+            tag: variation.tag,
+            value: variation.value,
+        }))
         .build();
     // Compute the fractional offset-- you'll likely want to quantize this
     // in a real renderer
-    let offset = Vector::new((x_offset as f32).fract(), (y_offset as f32).fract());
-    let embolden = if cfg!(target_os = "macos") { 0.25 } else { 0. };
+    let offset = match hinting {
+        FontHinting::None | FontHinting::Normal | FontHinting::LCD => {
+            Vector::new((x_offset as f32).fract(), (y_offset as f32).fract())
+        }
+        FontHinting::Light => Vector::new((x_offset as f32).fract(), 0.0),
+        FontHinting::Mono => Vector::new(0.0, 0.0),
+    };
+    let mut embolden = if cfg!(target_os = "macos") { 0.25 } else { 0. };
+    if instance.flags.contains(FontInstanceFlags::SYNTHETIC_BOLD) && !is_bitmap_font(instance) {
+        // Widen the outlines by a 48th of the size, as FreeType does.
+        embolden += size / 48.0;
+    }
     // Select our source order
-    Render::new(&[
-        Source::ColorOutline(0),
-        Source::ColorBitmap(StrikeWith::BestFit),
-        Source::Outline,
-    ])
+    let sources = match options.strike_selection {
+        FontStrikeSelection::BestFit => vec![
+            Source::ColorOutline(instance.palette_index),
+            Source::ColorBitmap(StrikeWith::BestFit),
+            Source::Outline,
+        ],
+        FontStrikeSelection::ExactSize => vec![
+            Source::ColorOutline(instance.palette_index),
+            Source::ColorBitmap(StrikeWith::ExactSize),
+            Source::Outline,
+            Source::ColorBitmap(StrikeWith::BestFit),
+        ],
+        FontStrikeSelection::NearestLarger => {
+            // swash takes the index of a strike in the order the font lists
+            // them.
+            let glyph_id = glyph_key.index() as GlyphId;
+            let strike = font
+                .color_strikes()
+                .enumerate()
+                .filter(|(_, strike)| strike.ppem() as f32 >= size && strike.contains(glyph_id))
+                .min_by_key(|(_, strike)| strike.ppem())
+                .map_or(StrikeWith::LargestSize, |(index, _)| StrikeWith::Index(index as u32));
+            vec![Source::ColorOutline(instance.palette_index), Source::ColorBitmap(strike), Source::Outline]
+        }
+        FontStrikeSelection::OutlinesFirst => vec![
+            Source::ColorOutline(instance.palette_index),
+            Source::Outline,
+            Source::ColorBitmap(StrikeWith::BestFit),
+        ],
+    };
+    // swash draws color outlines with the palettes of the font only, so the
+    // ones with palette overrides are drawn here.
+    if !instance.palette_overrides.is_empty() {
+        let image = render_color_outline(&mut scaler, font, instance, glyph_key.index() as GlyphId, offset, transform);
+        if image.is_some() {
+            return image;
+        }
+    }
+    Render::new(&sources)
     // Select a subpixel format
     .format(format)
     // Apply the fractional offset
     .offset(offset)
+    .transform(transform)
     .embolden(embolden)
     .default_color([
         instance.color.r,
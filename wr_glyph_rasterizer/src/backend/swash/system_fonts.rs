@@ -0,0 +1,152 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! The fonts installed on the system, as found by font-index.
+//!
+//! The swash backend loads native fonts with the same index, so the handles
+//! returned here can be added with `Transaction::add_native_font`. The data
+//! of a font can also be read to shape text with it, or to add it as a raw
+//! or parsed font.
+
+use api::{NativeFontHandle, ParsedFontInfo};
+use font_index::{FontCache, FontId};
+use std::sync::Arc;
+use swash::Style;
+
+/// The slant of a font.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SystemFontStyle {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+impl From<Style> for SystemFontStyle {
+    fn from(style: Style) -> Self {
+        match style {
+            Style::Normal => SystemFontStyle::Normal,
+            Style::Italic => SystemFontStyle::Italic,
+            Style::Oblique(..) => SystemFontStyle::Oblique,
+        }
+    }
+}
+
+/// A font installed on the system.
+#[derive(Clone, Debug)]
+pub struct SystemFont {
+    pub handle: NativeFontHandle,
+    pub family: String,
+    /// The weight, from 1 to 1000, 400 being normal and 700 bold.
+    pub weight: u16,
+    pub style: SystemFontStyle,
+}
+
+/// The data of a system font, see `SystemFonts::font_data`.
+pub struct SystemFontData {
+    /// The data of the font file, which may be a collection of fonts.
+    pub data: Arc<Vec<u8>>,
+    /// The index of the font in the collection.
+    pub index: u32,
+    pub info: ParsedFontInfo,
+}
+
+/// Enumerates and matches the fonts installed on the system.
+pub struct SystemFonts {
+    cache: FontCache,
+}
+
+impl SystemFonts {
+    /// Indexes the system fonts, which reads the font directories of the
+    /// system the first time.
+    pub fn new() -> Self {
+        SystemFonts {
+            cache: FontCache::default(),
+        }
+    }
+
+    /// Returns the names of the font families installed on the system, in
+    /// alphabetical order.
+    pub fn families(&self) -> Vec<String> {
+        let index = &self.cache.index;
+        let mut families: Vec<String> = index
+            .families
+            .iter()
+            .filter_map(|family| index.family_by_id(family.id))
+            .map(|family| family.name().to_string())
+            .collect();
+        families.sort();
+        families.dedup();
+        families
+    }
+
+    /// Returns the fonts of `family`, which is matched case-insensitively.
+    /// Generic family names, like "serif" or "monospace", select the family
+    /// the system uses for them.
+    pub fn family_fonts(&self, family: &str) -> Vec<SystemFont> {
+        let entry = match self.cache.index.family_by_key(family) {
+            Some(entry) => entry,
+            None => return Vec::new(),
+        };
+        entry.fonts().map(|font| {
+            let attributes = font.attributes();
+            SystemFont {
+                handle: NativeFontHandle(font.id().0),
+                family: entry.name().to_string(),
+                weight: attributes.weight().0,
+                style: attributes.style().into(),
+            }
+        }).collect()
+    }
+
+    /// Returns the font of `family` closest to `weight` and `style`, as CSS
+    /// matches fonts: the style is matched first, italic and oblique fonts
+    /// standing in for each other, and then the weight. Lighter weights are
+    /// preferred up to 500 and heavier weights above.
+    pub fn match_font(&self, family: &str, weight: u16, style: SystemFontStyle) -> Option<SystemFont> {
+        let style_rank = |font: &SystemFont| -> u32 {
+            match (style, font.style) {
+                (a, b) if a == b => 0,
+                (SystemFontStyle::Normal, _) | (_, SystemFontStyle::Normal) => 2,
+                _ => 1,
+            }
+        };
+        let weight_rank = |font: &SystemFont| -> u32 {
+            let (w, target) = (font.weight as i32, weight as i32);
+            let in_range = (400 ..= 500).contains(&target) && (target ..= 500).contains(&w);
+            let preferred_side = if target <= 500 { w <= target } else { w >= target };
+            let distance = (w - target).unsigned_abs();
+            if in_range {
+                distance
+            } else if preferred_side {
+                1000 + distance
+            } else {
+                2000 + distance
+            }
+        };
+        self.family_fonts(family)
+            .into_iter()
+            .min_by_key(|font| (style_rank(font), weight_rank(font)))
+    }
+
+    /// Reads the data of a system font. Returns None if the font can't be
+    /// loaded.
+    pub fn font_data(&mut self, handle: &NativeFontHandle) -> Option<SystemFontData> {
+        // The backends that parse fonts again need the index of the font in
+        // its file, besides the offset swash uses.
+        let index = self.cache.font_by_id(FontId(handle.0))?.index();
+        let font = self.cache.get(FontId(handle.0))?;
+        let font = font.as_ref();
+        Some(SystemFontData {
+            data: Arc::new(font.data.to_vec()),
+            index,
+            info: ParsedFontInfo { offset: font.offset },
+        })
+    }
+}
+
+impl Default for SystemFonts {
+    fn default() -> Self {
+        SystemFonts::new()
+    }
+}
@@ -0,0 +1,317 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! A glyph rasterizer backend that reads outlines with ttf-parser and fills
+//! them with tiny-skia, without a font rasterizer library. It is the simplest
+//! of the backends, and a reference for writing new ones: glyphs are drawn
+//! from their outlines only, with grayscale anti-aliasing, and without
+//! hinting or color glyphs. Unlike the fontdue and ab_glyph backends, it
+//! supports glyph transforms and synthetic italics, since the outlines are
+//! transformed before being filled.
+
+use api::{ColorU, GlyphDimensions, GlyphIndex, FontKey, FontRenderMode, PathCommand};
+use api::{FontInstanceFlags, NativeFontHandle, ParsedFontInfo};
+use crate::backend::font_data::FontBytes;
+use crate::gamma_lut::{ColorLut, GammaLut};
+use crate::rasterizer::{FontInstance, FontTransform, GlyphKey};
+use crate::rasterizer::{GlyphRasterError, GlyphRasterResult, RasterizedGlyph};
+use crate::types::FastHashMap;
+use euclid::point2;
+use std::path::Path as FilePath;
+use std::sync::Arc;
+use tiny_skia::{FillRule, Paint, Path, PathBuilder, Pixmap, Transform};
+use ttf_parser::{Face, GlyphId};
+
+/// Collects the outline of a glyph as ttf-parser reads it, in font units.
+struct OutlineBuilder<T>(T);
+
+impl ttf_parser::OutlineBuilder for OutlineBuilder<PathBuilder> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.0.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.0.line_to(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.0.quad_to(x1, y1, x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.0.cubic_to(x1, y1, x2, y2, x, y);
+    }
+
+    fn close(&mut self) {
+        self.0.close();
+    }
+}
+
+impl ttf_parser::OutlineBuilder for OutlineBuilder<Vec<PathCommand>> {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.0.push(PathCommand::MoveTo(point2(x, y)));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.0.push(PathCommand::LineTo(point2(x, y)));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.0.push(PathCommand::QuadTo(point2(x1, y1), point2(x, y)));
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.0.push(PathCommand::CubicTo(point2(x1, y1), point2(x2, y2), point2(x, y)));
+    }
+
+    fn close(&mut self) {
+        self.0.push(PathCommand::Close);
+    }
+}
+
+/// The data of a font and its index in the data. Faces borrow the data, so
+/// they are parsed again for each use, which only reads the table directory.
+struct FontData {
+    bytes: FontBytes,
+    index: u32,
+}
+
+impl FontData {
+    fn face(&self) -> Option<Face> {
+        Face::parse(&self.bytes, self.index).ok()
+    }
+}
+
+/// The outline of a glyph in device pixels, y-down, and the whole pixels it
+/// covers.
+struct DeviceOutline {
+    path: Path,
+    left: i32,
+    top: i32,
+    width: u32,
+    height: u32,
+}
+
+pub struct FontContext {
+    fonts: FastHashMap<FontKey, FontData>,
+    gamma_luts: FastHashMap<(u16, u8), GammaLut>,
+}
+
+impl FontContext {
+    pub fn distribute_across_threads() -> bool {
+        true
+    }
+
+    pub fn new() -> FontContext {
+        FontContext {
+            fonts: FastHashMap::default(),
+            gamma_luts: FastHashMap::default(),
+        }
+    }
+
+    pub fn add_raw_font(&mut self, font_key: &FontKey, bytes: Arc<Vec<u8>>, index: u32) -> Result<(), GlyphRasterError> {
+        self.add_font_bytes(font_key, bytes.into(), index)
+    }
+
+    /// Loads the font at `index` in the font file at `path`, see
+    /// `FontBytes::from_file`.
+    pub fn add_file_font(&mut self, font_key: &FontKey, path: &FilePath, index: u32) -> Result<(), GlyphRasterError> {
+        self.add_font_bytes(font_key, FontBytes::from_file(path)?, index)
+    }
+
+    fn add_font_bytes(&mut self, font_key: &FontKey, bytes: FontBytes, index: u32) -> Result<(), GlyphRasterError> {
+        if let Err(e) = Face::parse(&bytes, index) {
+            error!("Failed to parse font: index={} err={:?}", index, e);
+            return Err(GlyphRasterError::LoadFailed);
+        }
+        self.fonts.insert(*font_key, FontData { bytes, index });
+        Ok(())
+    }
+
+    pub fn add_parsed_font(
+        &mut self,
+        font_key: &FontKey,
+        bytes: Arc<Vec<u8>>,
+        index: u32,
+        _: ParsedFontInfo,
+    ) -> Result<(), GlyphRasterError> {
+        self.add_raw_font(font_key, bytes, index)
+    }
+
+    pub fn add_native_font(&mut self, _: &FontKey, _: NativeFontHandle) -> Result<(), GlyphRasterError> {
+        error!("The tiny-skia backend can't load native fonts");
+        Err(GlyphRasterError::LoadFailed)
+    }
+
+    pub fn delete_font(&mut self, font_key: &FontKey) {
+        self.fonts.remove(font_key);
+    }
+
+    pub fn delete_font_instance(&mut self, _: &FontInstance) {}
+
+    pub fn get_glyph_index(&self, font_key: FontKey, ch: char) -> Option<u32> {
+        let face = self.fonts.get(&font_key)?.face()?;
+        face.glyph_index(ch).map(|id| id.0 as u32)
+    }
+
+    pub fn get_glyph_variant_index(&self, font_key: FontKey, ch: char, selector: char) -> Option<u32> {
+        let face = self.fonts.get(&font_key)?.face()?;
+        face.glyph_variation_index(ch, selector).map(|id| id.0 as u32)
+    }
+
+    pub fn get_glyph_outline(&self, font_key: FontKey, glyph_index: GlyphIndex) -> Vec<PathCommand> {
+        let face = match self.fonts.get(&font_key).and_then(FontData::face) {
+            Some(face) => face,
+            None => return Vec::new(),
+        };
+        let mut builder = OutlineBuilder(Vec::new());
+        face.outline_glyph(GlyphId(glyph_index as u16), &mut builder);
+        builder.0
+    }
+
+    pub fn get_glyph_dimensions(
+        &mut self,
+        font: &FontInstance,
+        key: &GlyphKey,
+    ) -> Option<GlyphDimensions> {
+        let face = self.fonts.get(&font.font_key)?.face()?;
+        let glyph_id = GlyphId(key.index() as u16);
+        let outline = device_outline(&face, font, glyph_id, (0.0, 0.0))?;
+        let scale = font.get_transformed_size() as f32 / face.units_per_em() as f32;
+        let advance = face.glyph_hor_advance(glyph_id).unwrap_or(0) as f32 * scale;
+        Some(GlyphDimensions {
+            left: outline.left,
+            top: -outline.top,
+            width: outline.width as i32,
+            height: outline.height as i32,
+            advance,
+        })
+    }
+
+    pub fn prepare_font(font: &mut FontInstance) {
+        // Paths are filled with grayscale anti-aliasing only.
+        font.disable_subpixel_aa();
+        match font.render_mode {
+            FontRenderMode::Mono => {
+                // In mono mode the color of the font is irrelevant.
+                font.color = ColorU::new(0xFF, 0xFF, 0xFF, 0xFF);
+                // Subpixel positioning is disabled in mono mode.
+                font.disable_subpixel_position();
+            }
+            FontRenderMode::Alpha | FontRenderMode::Subpixel => {
+                font.color = font.color.luminance_color().quantize();
+            }
+        }
+    }
+
+    pub fn begin_rasterize(_: &FontInstance) {}
+
+    pub fn end_rasterize(_: &FontInstance) {}
+
+    pub fn rasterize_glyph(&mut self, font: &FontInstance, key: &GlyphKey) -> GlyphRasterResult {
+        let face = self.fonts
+            .get(&font.font_key)
+            .and_then(FontData::face)
+            .ok_or(GlyphRasterError::LoadFailed)?;
+        let offset = font.get_subpx_offset(key);
+        let outline = device_outline(&face, font, GlyphId(key.index() as u16), offset)
+            .ok_or(GlyphRasterError::LoadFailed)?;
+
+        let mut pixmap = Pixmap::new(outline.width, outline.height).ok_or(GlyphRasterError::LoadFailed)?;
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(0xFF, 0xFF, 0xFF, 0xFF);
+        paint.anti_alias = font.render_mode != FontRenderMode::Mono;
+        pixmap.fill_path(
+            &outline.path,
+            &paint,
+            FillRule::Winding,
+            Transform::from_translate(-outline.left as f32, -outline.top as f32),
+            None,
+        );
+
+        // The pixmap is premultiplied white, so its alpha is the coverage.
+        let mut bytes: Vec<u8> = pixmap.data()
+            .chunks_exact(4)
+            .flat_map(|pixel| [pixel[3]; 4])
+            .collect();
+
+        if font.render_mode != FontRenderMode::Mono {
+            let options = font.platform_options.unwrap_or_default();
+            let gamma_lut = self.gamma_luts
+                .entry((options.gamma, options.contrast))
+                .or_insert_with(|| GammaLut::new(
+                    options.contrast as f32 / 100.0,
+                    options.gamma as f32 / 100.0,
+                    options.gamma as f32 / 100.0,
+                ));
+            gamma_lut.preblend(&mut bytes, font.color);
+        }
+
+        Ok(RasterizedGlyph {
+            left: outline.left as f32,
+            top: -outline.top as f32,
+            width: outline.width as i32,
+            height: outline.height as i32,
+            scale: 1.0,
+            format: font.get_alpha_glyph_format(),
+            bytes,
+        })
+    }
+}
+
+/// Transforms the outline of a glyph from font units, y-up, to device pixels,
+/// y-down, applying the font's size and transform and the subpixel `offset`.
+/// Returns None for glyphs without an outline.
+fn device_outline(
+    face: &Face,
+    font: &FontInstance,
+    glyph_id: GlyphId,
+    (x_offset, y_offset): (f64, f64),
+) -> Option<DeviceOutline> {
+    let mut builder = OutlineBuilder(PathBuilder::new());
+    face.outline_glyph(glyph_id, &mut builder)?;
+    let path = builder.0.finish()?;
+
+    let mut transform = font.transform;
+    if font.flags.contains(FontInstanceFlags::FLIP_X) {
+        transform = transform.flip_x();
+    }
+    if font.flags.contains(FontInstanceFlags::FLIP_Y) {
+        transform = transform.flip_y();
+    }
+    if font.flags.contains(FontInstanceFlags::TRANSPOSE) {
+        transform = transform.swap_xy();
+    }
+    let (mut transform, (tx, ty)) = if font.synthetic_italics.is_enabled() {
+        font.synthesize_italics(transform, font.size.to_f64_px())
+    } else {
+        (transform, (0.0, 0.0))
+    };
+    if font.synthetic_stretch.is_enabled() {
+        transform = font.synthesize_stretch(transform);
+    }
+    let FontTransform { scale_x, skew_x, skew_y, scale_y } = transform;
+
+    // Scale font units to pixels and flip them to y-down, then apply the
+    // font transform, which is y-down.
+    let scale = font.size.to_f32_px() / face.units_per_em() as f32;
+    let path = path.transform(Transform::from_row(
+        scale_x * scale,
+        skew_y * scale,
+        -skew_x * scale,
+        -scale_y * scale,
+        (tx + x_offset) as f32,
+        (ty + y_offset) as f32,
+    ))?;
+
+    let bounds = path.bounds();
+    let left = bounds.left().floor() as i32;
+    let top = bounds.top().floor() as i32;
+    let width = (bounds.right().ceil() as i32 - left) as u32;
+    let height = (bounds.bottom().ceil() as i32 - top) as u32;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    Some(DeviceOutline { path, left, top, width, height })
+}
@@ -9,13 +9,15 @@
 //! ## Usage
 //!
 
-#[cfg(any(target_os = "macos", target_os = "windows"))]
+#[cfg(any(target_os = "macos", target_os = "windows", not(feature = "backend_native")))]
 mod gamma_lut;
 mod rasterizer;
 mod telemetry;
 mod types;
 
 pub mod profiler;
+#[cfg(feature = "woff")]
+pub mod woff;
 
 pub use rasterizer::*;
 pub use types::*;
@@ -62,18 +64,27 @@ pub mod platform {
 
 #[cfg(not(feature = "backend_native"))]
 pub mod backend {
-    #[cfg(feature = "backend_swash")]
-    pub use crate::backend::swash::font;
-    #[cfg(feature = "backend_fontdue")]
-    pub use crate::backend::fontdue::font;
+    pub mod font;
+    mod font_data;
 
     #[cfg(feature = "backend_swash")]
     pub mod swash {
         pub mod font;
+        pub mod system_fonts;
     }
 
     #[cfg(feature = "backend_fontdue")]
     pub mod fontdue {
         pub mod font;
     }
+
+    #[cfg(feature = "backend_ab_glyph")]
+    pub mod ab_glyph {
+        pub mod font;
+    }
+
+    #[cfg(feature = "backend_tiny_skia")]
+    pub mod tiny_skia {
+        pub mod font;
+    }
 }
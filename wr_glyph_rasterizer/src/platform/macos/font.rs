@@ -27,6 +27,8 @@ use crate::rasterizer::{FontInstance, FontTransform, GlyphKey};
 use crate::rasterizer::{GlyphFormat, GlyphRasterError, GlyphRasterResult, RasterizedGlyph};
 use crate::types::FastHashMap;
 use std::collections::hash_map::Entry;
+use std::fs;
+use std::path::Path;
 use std::sync::Arc;
 
 const INITIAL_CG_CONTEXT_SIDE_LENGTH: u32 = 32;
@@ -265,6 +267,19 @@ impl FontContext {
         self.ct_font_descs.insert(*font_key, ct_font_desc);
     }
 
+    /// Core Text font descriptors need the data of the font, so the file is
+    /// read in full.
+    pub fn add_file_font(&mut self, font_key: &FontKey, path: &Path, index: u32) {
+        if self.ct_font_descs.contains_key(font_key) {
+            return;
+        }
+
+        match fs::read(path) {
+            Ok(bytes) => self.add_raw_font(font_key, Arc::new(bytes), index),
+            Err(e) => debug!("Failed to read font file {:?}: {}", path, e),
+        }
+    }
+
     pub fn add_native_font(&mut self, font_key: &FontKey, native_font_handle: NativeFontHandle) {
         if self.ct_font_descs.contains_key(font_key) {
             return;
@@ -354,6 +369,12 @@ impl FontContext {
             })
     }
 
+    /// CoreText maps the characters of a string to glyphs one at a time, so
+    /// the variation selector is ignored and the base character is mapped.
+    pub fn get_glyph_variant_index(&mut self, font_key: FontKey, ch: char, _selector: char) -> Option<u32> {
+        self.get_glyph_index(font_key, ch)
+    }
+
     pub fn get_glyph_dimensions(
         &mut self,
         font: &FontInstance,
@@ -386,6 +407,9 @@ impl FontContext {
                     tx = tx_;
                     ty = ty_;
                 }
+                if font.synthetic_stretch.is_enabled() {
+                    shape = font.synthesize_stretch(shape);
+                }
                 let transform = if !shape.is_identity() || (tx, ty) != (0.0, 0.0) {
                     Some(CGAffineTransform {
                         a: shape.scale_x as f64,
@@ -559,6 +583,9 @@ impl FontContext {
             tx = tx_;
             ty = ty_;
         }
+        if font.synthetic_stretch.is_enabled() {
+            shape = font.synthesize_stretch(shape);
+        }
         let transform = if !shape.is_identity() || (tx, ty) != (0.0, 0.0) {
             Some(CGAffineTransform {
                 a: shape.scale_x as f64,
@@ -7,6 +7,7 @@ use api::{FontInstancePlatformOptions, FontLCDFilter, FontHinting};
 use api::{FontInstanceFlags, FontTemplate, FontVariation, NativeFontHandle};
 use freetype::freetype::{FT_BBox, FT_Outline_Translate, FT_Pixel_Mode, FT_Render_Mode};
 use freetype::freetype::{FT_Done_Face, FT_Error, FT_Get_Char_Index, FT_Int32};
+use freetype::freetype::FT_Face_GetCharVariantIndex;
 use freetype::freetype::{FT_Done_FreeType, FT_Library_SetLcdFilter, FT_Pos};
 use freetype::freetype::{FT_F26Dot6, FT_Face, FT_Glyph_Format, FT_Long, FT_UInt};
 use freetype::freetype::{FT_GlyphSlot, FT_LcdFilter, FT_New_Face, FT_New_Memory_Face};
@@ -29,6 +30,7 @@ use libc::free;
 use std::{cmp, mem, ptr, slice};
 use std::cmp::max;
 use std::ffi::CString;
+use std::path::Path;
 use std::sync::{Arc, Condvar, Mutex, MutexGuard};
 
 // These constants are not present in the freetype
@@ -220,6 +222,7 @@ impl FontCache {
                         &mut face,
                     )
                 }
+                FontTemplate::File { ref path, index } |
                 FontTemplate::Native(NativeFontHandle { ref path, index }) => {
                     let str = path.as_os_str().to_str().unwrap();
                     let cstr = CString::new(str).unwrap();
@@ -391,6 +394,18 @@ impl FontContext {
         }
     }
 
+    /// FreeType reads the file itself, as it needs, so the file's data is
+    /// never held in memory as a whole.
+    pub fn add_file_font(&mut self, font_key: &FontKey, path: &Path, index: u32) {
+        if !self.fonts.contains_key(font_key) {
+            let template = FontTemplate::File { path: path.to_path_buf(), index };
+            match FONT_CACHE.lock().unwrap().add_font(template) {
+                Ok(font) => self.fonts.insert(*font_key, font),
+                Err(result) => panic!("adding file font failed: file={:?} err={:?}", path, result),
+            };
+        }
+    }
+
     pub fn add_native_font(&mut self, font_key: &FontKey, native_font_handle: NativeFontHandle) {
         if !self.fonts.contains_key(font_key) {
             let path = native_font_handle.path.to_string_lossy().into_owned();
@@ -517,6 +532,9 @@ impl FontContext {
                 tx = tx_;
                 ty = ty_;
             };
+            if font.synthetic_stretch.is_enabled() {
+                shape = font.synthesize_stretch(shape);
+            }
             let mut ft_shape = FT_Matrix {
                 xx: (shape.scale_x * 65536.0) as FT_Fixed,
                 xy: (shape.skew_x * -65536.0) as FT_Fixed,
@@ -746,6 +764,19 @@ impl FontContext {
         }
     }
 
+    pub fn get_glyph_variant_index(&mut self, font_key: FontKey, ch: char, selector: char) -> Option<u32> {
+        let cached = self.fonts.get(&font_key)?.lock().ok()?;
+        let face = cached.face;
+        unsafe {
+            let idx = FT_Face_GetCharVariantIndex(face, ch as _, selector as _);
+            if idx != 0 {
+                Some(idx)
+            } else {
+                None
+            }
+        }
+    }
+
     pub fn get_glyph_dimensions(
         &mut self,
         font: &FontInstance,
@@ -180,6 +180,11 @@ impl FontContext {
         self.add_font_descriptor(font_key, &DEFAULT_FONT_DESCRIPTOR);
     }
 
+    /// DirectWrite loads font files itself, as it does for native fonts.
+    pub fn add_file_font(&mut self, font_key: &FontKey, path: &Path, index: u32) {
+        self.add_native_font(font_key, NativeFontHandle { path: path.to_path_buf(), index });
+    }
+
     pub fn add_native_font(&mut self, font_key: &FontKey, font_handle: NativeFontHandle) {
         if self.fonts.contains_key(font_key) {
             return;
@@ -372,6 +377,13 @@ impl FontContext {
         indices.first().map(|idx| *idx as u32)
     }
 
+    /// DirectWrite only maps variation sequences from IDWriteFontFace5, which
+    /// dwrote doesn't wrap, so the variation selector is ignored and the base
+    /// character is mapped.
+    pub fn get_glyph_variant_index(&mut self, font_key: FontKey, ch: char, _selector: char) -> Option<u32> {
+        self.get_glyph_index(font_key, ch)
+    }
+
     pub fn get_glyph_dimensions(
         &mut self,
         font: &FontInstance,
@@ -534,6 +546,9 @@ impl FontContext {
             tx = tx_;
             ty = ty_;
         };
+        if font.synthetic_stretch.is_enabled() {
+            shape = font.synthesize_stretch(shape);
+        }
         x_offset += tx;
         y_offset += ty;
         let transform = if !shape.is_identity() || (x_offset, y_offset) != (0.0, 0.0) {
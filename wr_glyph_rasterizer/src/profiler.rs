@@ -2,6 +2,9 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use crate::rasterizer::{FontInstance, GlyphBackend, GlyphFormat, GlyphKey};
+use std::time::Duration;
+
 /// A profiler for profiling rasterize time.
 pub trait GlyphRasterizeProfiler {
     fn start_time(&mut self);
@@ -10,3 +13,26 @@ pub trait GlyphRasterizeProfiler {
 
     fn set(&mut self, value: f64);
 }
+
+/// Receives per-glyph statistics from the glyph rasterizer, for embedders to
+/// forward them into their own telemetry systems. It is registered with
+/// `GlyphRasterizer::set_telemetry_sink`, and is called from the threads that
+/// request and rasterize glyphs.
+pub trait GlyphTelemetrySink: Send + Sync {
+    /// Called for each requested glyph. `cache_hit` is true if the glyph was
+    /// already rasterized or requested, and false if it is queued to be
+    /// rasterized.
+    fn glyph_requested(&self, _font: &FontInstance, _key: &GlyphKey, _cache_hit: bool) {}
+
+    /// Called for each rasterized glyph, with the backend that rasterized it,
+    /// the format it was rasterized to, or None if it failed to rasterize,
+    /// and the time rasterizing it took.
+    fn glyph_rasterized(
+        &self,
+        _font: &FontInstance,
+        _key: &GlyphKey,
+        _backend: GlyphBackend,
+        _format: Option<GlyphFormat>,
+        _time: Duration,
+    ) {}
+}
@@ -3,9 +3,9 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use api::{FontInstanceData, FontInstanceFlags, FontInstanceKey};
-use api::{FontInstanceOptions, FontInstancePlatformOptions};
-use api::{FontKey, FontRenderMode, FontSize, FontTemplate, FontVariation};
-use api::{ColorU, GlyphIndex, GlyphDimensions, SyntheticItalics};
+use api::{FontBitmapFilter, FontInstanceOptions, FontInstancePlatformOptions, FontPaletteOverride};
+use api::{FontKey, FontRenderMode, FontSize, FontTemplate, FontVariation, ParsedFontInfo};
+use api::{ColorU, GlyphIndex, GlyphDimensions, PathCommand, SyntheticItalics};
 use api::{IdNamespace, BlobImageResources};
 use api::channel::crossbeam::{unbounded, Receiver, Sender};
 use api::units::*;
@@ -14,8 +14,9 @@ use api::ImageFormat;
 use crate::platform::font::FontContext;
 #[cfg(not(feature = "backend_native"))]
 use crate::backend::font::FontContext;
-use crate::profiler::GlyphRasterizeProfiler;
+use crate::profiler::{GlyphRasterizeProfiler, GlyphTelemetrySink};
 use crate::types::{FastHashMap, FastHashSet};
+use fxhash::FxHasher;
 use crate::telemetry::Telemetry;
 use malloc_size_of::{MallocSizeOf, MallocSizeOfOps};
 use rayon::ThreadPool;
@@ -28,8 +29,11 @@ use std::hash::{Hash, Hasher};
 use std::mem;
 use std::ops::Deref;
 use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+#[cfg(feature = "woff")]
+use std::sync::Once;
 use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
 pub static GLYPH_FLASHING: AtomicBool = AtomicBool::new(false);
 
@@ -63,6 +67,7 @@ impl GlyphRasterizer {
     pub fn request_glyphs<F>(
         &mut self,
         font: FontInstance,
+        priority: GlyphRequestPriority,
         glyph_keys: &[GlyphKey],
         mut handle: F,
     )
@@ -74,7 +79,11 @@ impl GlyphRasterizer {
 
         // select glyphs that have not been requested yet.
         for key in glyph_keys {
-            if !handle(key) {
+            let needs_raster = handle(key);
+            if let Some(ref sink) = self.telemetry_sink {
+                sink.glyph_requested(&font, key, !needs_raster);
+            }
+            if !needs_raster {
                 continue;
             }
 
@@ -85,14 +94,15 @@ impl GlyphRasterizer {
 
             // Find a batch container for the font instance for this glyph. Use get_mut to avoid
             // cloning the font instance, since this is the common path.
-            match self.pending_glyph_requests.get_mut(&font) {
+            let pending_glyph_requests = &mut self.pending_glyph_requests[priority as usize];
+            match pending_glyph_requests.get_mut(&font) {
                 Some(container) => {
                     container.push(*key);
                     batch_size = container.len();
                 }
                 None => {
                     // If no batch exists for this font instance, add the glyph to a new one.
-                    self.pending_glyph_requests.insert(
+                    pending_glyph_requests.insert(
                         font.clone(),
                         smallvec![*key],
                     );
@@ -101,9 +111,10 @@ impl GlyphRasterizer {
         }
 
         // If the batch for this font instance is big enough, kick off an async
-        // job to start rasterizing these glyphs on other threads now.
-        if batch_size >= 8 {
-            let container = self.pending_glyph_requests.get_mut(&font).unwrap();
+        // job to start rasterizing these glyphs on other threads now. Glyphs of
+        // lower priority wait for the visible ones to be rasterized first.
+        if batch_size >= 8 && priority == GlyphRequestPriority::Visible {
+            let container = self.pending_glyph_requests[priority as usize].get_mut(&font).unwrap();
             let glyphs = mem::replace(container, SmallVec::new());
             self.flush_glyph_requests(font, glyphs, true);
         }
@@ -113,6 +124,18 @@ impl GlyphRasterizer {
         self.enable_multithreading = enable;
     }
 
+    /// Sets whether the glyphs of a font are rasterized on several threads,
+    /// or all on one thread. None leaves it to the backend.
+    pub fn set_distribute_across_threads(&mut self, distribute: Option<bool>) {
+        self.distribute_across_threads = distribute;
+    }
+
+    /// Registers a sink that receives statistics about each requested and
+    /// rasterized glyph, or unregisters it with None.
+    pub fn set_telemetry_sink(&mut self, sink: Option<Arc<dyn GlyphTelemetrySink>>) {
+        self.telemetry_sink = sink;
+    }
+
     /// Internal method to flush a list of glyph requests to a set of worker threads,
     /// or process on this thread if there isn't much work to do (in which case the
     /// overhead of processing these on a thread is unlikely to be a performance win).
@@ -128,16 +151,26 @@ impl GlyphRasterizer {
         self.pending_glyph_count -= glyphs.len();
 
         let can_use_r8_format = self.can_use_r8_format;
+        let distribute_across_threads = self.distribute_across_threads
+            .unwrap_or_else(FontContext::distribute_across_threads);
+
+        let backend = self.backend;
+        let telemetry_sink = self.telemetry_sink.clone();
 
         let job_font = font.clone();
         let process_glyph = move |key: &GlyphKey| -> GlyphRasterJob {
             profile_scope!("glyph-raster");
             let mut context = font_contexts.lock_current_context();
+            let start = telemetry_sink.as_ref().map(|_| Instant::now());
             let mut job = GlyphRasterJob {
                 font: Arc::clone(&job_font),
                 key: key.clone(),
                 result: context.rasterize_glyph(&job_font, key),
             };
+            if let (Some(sink), Some(start)) = (telemetry_sink.as_ref(), start) {
+                let format = job.result.as_ref().ok().map(|glyph| glyph.format);
+                sink.glyph_rasterized(&job_font, key, backend, format, start.elapsed());
+            }
 
             if let Ok(ref mut glyph) = job.result {
                 // Sanity check.
@@ -164,9 +197,15 @@ impl GlyphRasterizer {
 
                 assert_eq!((glyph.left.fract(), glyph.top.fract()), (0.0, 0.0));
 
-                // Check if the glyph has a bitmap that needs to be downscaled.
+                // Check if the glyph has a bitmap that needs to be resampled or
+                // downscaled.
+                glyph.resample_bitmap(&job_font);
                 glyph.downscale_bitmap_if_required(&job_font);
 
+                if job_font.flags.contains(FontInstanceFlags::SDF) {
+                    glyph.convert_to_distance_field();
+                }
+
                 // Convert from BGRA8 to R8 if required. In the future we can make it the
                 // backends' responsibility to output glyphs in the desired format,
                 // potentially reducing the number of copies.
@@ -193,7 +232,7 @@ impl GlyphRasterizer {
                 // If the FontContext supports distributing a font across multiple threads,
                 // then use par_iter so different glyphs of the same font are processed on
                 // multiple threads.
-                if FontContext::distribute_across_threads() {
+                if distribute_across_threads {
                     glyphs.par_iter().for_each(|key| {
                         let job = process_glyph(key);
                         self.glyph_tx.send(job).unwrap();
@@ -231,24 +270,25 @@ impl GlyphRasterizer {
         let timer_id = Telemetry::start_rasterize_glyphs_time();
 
         // Work around the borrow checker, since we call flush_glyph_requests below
-        let mut pending_glyph_requests = mem::replace(
-            &mut self.pending_glyph_requests,
-            FastHashMap::default(),
-        );
+        let mut pending_glyph_requests = mem::take(&mut self.pending_glyph_requests);
         // If we have a large amount of remaining work to do, spawn to worker threads,
         // even if that work is shared among a number of different font instances.
         let use_workers = self.pending_glyph_count >= 8;
-        for (font, pending_glyphs) in pending_glyph_requests.drain() {
-            self.flush_glyph_requests(
-                font,
-                pending_glyphs,
-                use_workers,
-            );
+        // Flushing waits for the batch to be rasterized, so the batches are
+        // rasterized in priority order.
+        for requests in &mut pending_glyph_requests {
+            for (font, pending_glyphs) in requests.drain() {
+                self.flush_glyph_requests(
+                    font,
+                    pending_glyphs,
+                    use_workers,
+                );
+            }
         }
         // Restore this so that we don't heap allocate next frame
         self.pending_glyph_requests = pending_glyph_requests;
         debug_assert_eq!(self.pending_glyph_count, 0);
-        debug_assert!(self.pending_glyph_requests.is_empty());
+        debug_assert!(self.pending_glyph_requests.iter().all(|requests| requests.is_empty()));
 
         if self.glyph_request_count > 0 {
             profile.set(self.glyph_request_count as f64);
@@ -440,6 +480,18 @@ impl<'a> From<&'a LayoutToWorldTransform> for FontTransform {
 // Ensure glyph sizes are reasonably limited to avoid that scenario.
 pub const FONT_SIZE_LIMIT: f32 = 320.0;
 
+/// The size glyphs of fonts with `FontInstanceFlags::SDF` are rasterized at,
+/// whatever size they are drawn at.
+pub const SDF_FONT_SIZE: f32 = 64.0;
+
+/// How far from the edges of a glyph, in pixels at `SDF_FONT_SIZE`, its
+/// distance field extends. Distance field glyphs are padded by this much.
+const SDF_SPREAD: usize = 6;
+
+/// The default number of bytes of rasterized glyph images that backends
+/// caching them keep, see `GlyphRasterizer::set_image_cache_budget`.
+pub const DEFAULT_GLYPH_IMAGE_CACHE_BUDGET: usize = 32 * 1024 * 1024;
+
 /// Immutable description of a font instance's shared state.
 ///
 /// `BaseFontInstance` can be identified by a `FontInstanceKey` to avoid hashing it.
@@ -460,6 +512,8 @@ pub struct BaseFontInstance {
     pub platform_options: Option<FontInstancePlatformOptions>,
     ///
     pub variations: Vec<FontVariation>,
+    /// Colors replacing entries of the palette of color glyphs.
+    pub palette_overrides: Vec<FontPaletteOverride>,
 }
 
 impl BaseFontInstance {
@@ -470,6 +524,7 @@ impl BaseFontInstance {
         options: Option<FontInstanceOptions>,
         platform_options: Option<FontInstancePlatformOptions>,
         variations: Vec<FontVariation>,
+        palette_overrides: Vec<FontPaletteOverride>,
     ) -> Self {
         BaseFontInstance {
             instance_key,
@@ -478,6 +533,7 @@ impl BaseFontInstance {
             options: options.unwrap_or_default(),
             platform_options,
             variations,
+            palette_overrides,
         }
     }
 }
@@ -497,6 +553,7 @@ impl Hash for BaseFontInstance {
         self.options.hash(state);
         self.platform_options.hash(state);
         self.variations.hash(state);
+        self.palette_overrides.hash(state);
     }
 }
 
@@ -507,7 +564,8 @@ impl PartialEq for BaseFontInstance {
             self.size == other.size &&
             self.options == other.options &&
             self.platform_options == other.platform_options &&
-            self.variations == other.variations
+            self.variations == other.variations &&
+            self.palette_overrides == other.palette_overrides
     }
 }
 impl Eq for BaseFontInstance {}
@@ -522,6 +580,58 @@ struct FontKeyMapLocked {
     next_id: u32,
     template_map: FastHashMap<FontTemplate, Arc<MappedFontKey>>,
     key_map: FastHashMap<FontKey, Arc<MappedFontKey>>,
+    /// The data of the mapped raw templates by hash of their contents, with
+    /// the number of templates sharing each.
+    raw_data_map: FastHashMap<u64, Vec<SharedRawData>>,
+}
+
+/// Raw font data and the number of mapped templates sharing it.
+type SharedRawData = (Arc<Vec<u8>>, usize);
+
+fn hash_raw_data(data: &[u8]) -> u64 {
+    let mut hasher = FxHasher::default();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl FontKeyMapLocked {
+    /// Makes a raw `template` share the data of a mapped template with the
+    /// same contents, if there is one. Only the contents of data with the
+    /// same hash are compared.
+    fn add_raw_data(&mut self, template: &mut FontTemplate) {
+        let data = match *template {
+            FontTemplate::Raw(ref mut data, _) => data,
+            _ => return,
+        };
+        let entries = self.raw_data_map.entry(hash_raw_data(data)).or_default();
+        match entries.iter_mut().find(|(other, _)| other == data) {
+            Some((other, count)) => {
+                *data = Arc::clone(other);
+                *count += 1;
+            }
+            None => entries.push((Arc::clone(data), 1)),
+        }
+    }
+
+    /// Releases the data of a raw `template` that is no longer mapped.
+    fn remove_raw_data(&mut self, template: &FontTemplate) {
+        let data = match *template {
+            FontTemplate::Raw(ref data, _) => data,
+            _ => return,
+        };
+        let hash = hash_raw_data(data);
+        if let Some(entries) = self.raw_data_map.get_mut(&hash) {
+            if let Some(index) = entries.iter().position(|(other, _)| Arc::ptr_eq(other, data)) {
+                entries[index].1 -= 1;
+                if entries[index].1 == 0 {
+                    entries.swap_remove(index);
+                }
+            }
+            if entries.is_empty() {
+                self.raw_data_map.remove(&hash);
+            }
+        }
+    }
 }
 
 /// A shared map from fonts key local to a namespace to shared font keys that
@@ -543,6 +653,7 @@ impl FontKeyMap {
             next_id: 1,
             template_map: FastHashMap::default(),
             key_map: FastHashMap::default(),
+            raw_data_map: FastHashMap::default(),
         })))
     }
 
@@ -558,6 +669,13 @@ impl FontKeyMap {
         self.lock().key_map.keys().cloned().collect()
     }
 
+    /// Finds a key of `namespace` that maps to `shared_key`.
+    pub fn find_key(&self, namespace: IdNamespace, shared_key: &FontKey) -> Option<FontKey> {
+        self.lock().key_map.iter()
+            .find(|(key, mapped)| key.0 == namespace && mapped.font_key == *shared_key)
+            .map(|(key, _)| *key)
+    }
+
     pub fn map_key(&self, font_key: &FontKey) -> FontKey {
         match self.lock().key_map.get(font_key) {
             Some(mapped) => mapped.font_key,
@@ -565,7 +683,13 @@ impl FontKeyMap {
         }
     }
 
-    pub fn add_key(&mut self, font_key: &FontKey, template: &FontTemplate) -> Option<FontKey> {
+    /// Maps `font_key` to the shared key of `template`, returning the shared
+    /// key if the template wasn't mapped yet and needs to be added.
+    ///
+    /// A raw template whose data matches an already mapped template with a
+    /// different face index is updated to share that data, so that the faces
+    /// of a font collection are only stored once.
+    pub fn add_key(&mut self, font_key: &FontKey, template: &mut FontTemplate) -> Option<FontKey> {
         let mut locked = self.lock_mut();
         if locked.key_map.contains_key(font_key) {
             return None;
@@ -574,6 +698,7 @@ impl FontKeyMap {
             locked.key_map.insert(*font_key, mapped);
             return None;
         }
+        locked.add_raw_data(template);
         let shared_key = FontKey::new(locked.namespace, locked.next_id);
         locked.next_id += 1;
         let mapped = Arc::new(MappedFontKey {
@@ -594,6 +719,7 @@ impl FontKeyMap {
         if Arc::strong_count(&mapped) <= 2 {
             // Only the last mapped key and template map point to it.
             locked.template_map.remove(&mapped.template);
+            locked.remove_raw_data(&mapped.template);
             Some(mapped.font_key)
         } else {
             None
@@ -610,15 +736,20 @@ impl FontKeyMap {
             }
         });
         let mut deleted_keys = Vec::new();
-        locked.template_map.retain(|_, mapped| {
+        let mut deleted_templates = Vec::new();
+        locked.template_map.retain(|template, mapped| {
             if Arc::strong_count(mapped) <= 1 {
                 // Only the template map points to it.
                 deleted_keys.push(mapped.font_key);
+                deleted_templates.push(template.clone());
                 false
             } else {
                 true
             }
         });
+        for template in &deleted_templates {
+            locked.remove_raw_data(template);
+        }
         deleted_keys
     }
 }
@@ -832,9 +963,13 @@ impl FontInstanceMap {
                   flags: instance.flags,
                   bg_color: instance.bg_color,
                   synthetic_italics: instance.synthetic_italics,
+                  synthetic_stretch: instance.synthetic_stretch,
+                  palette_index: instance.palette_index,
+                  bitmap_filter: instance.bitmap_filter,
                 }),
                 platform_options: instance.platform_options,
                 variations: instance.variations.clone(),
+                palette_overrides: instance.palette_overrides.clone(),
             }),
             None => None,
         }
@@ -1063,6 +1198,12 @@ impl FontInstance {
         transform.synthesize_italics(self.synthetic_italics, size, self.flags.contains(FontInstanceFlags::VERTICAL))
     }
 
+    /// Scales glyphs horizontally by the synthetic stretch of the font, before
+    /// `transform` and any synthetic italics.
+    pub fn synthesize_stretch(&self, transform: FontTransform) -> FontTransform {
+        transform.pre_scale(self.synthetic_stretch.to_scale(), 1.0)
+    }
+
     #[allow(dead_code)]
     pub fn get_transformed_size(&self) -> f64 {
         let (_, y_scale) = self.transform.compute_scale().unwrap_or((1.0, 1.0));
@@ -1084,7 +1225,8 @@ impl SubpixelDirection {
     pub fn limit_by(self, glyph_format: GlyphFormat) -> Self {
         match glyph_format {
             GlyphFormat::Bitmap |
-            GlyphFormat::ColorBitmap => SubpixelDirection::None,
+            GlyphFormat::ColorBitmap |
+            GlyphFormat::Sdf => SubpixelDirection::None,
             _ => self,
         }
     }
@@ -1191,6 +1333,9 @@ pub enum GlyphFormat {
     TransformedSubpixel,
     Bitmap,
     ColorBitmap,
+    /// A signed distance field of the glyph's outline, see
+    /// `RasterizedGlyph::convert_to_distance_field`.
+    Sdf,
 }
 
 impl GlyphFormat {
@@ -1201,7 +1346,8 @@ impl GlyphFormat {
         match *self {
             GlyphFormat::Alpha |
             GlyphFormat::TransformedAlpha |
-            GlyphFormat::Bitmap => {
+            GlyphFormat::Bitmap |
+            GlyphFormat::Sdf => {
                 if can_use_r8_format {
                     ImageFormat::R8
                 } else {
@@ -1419,6 +1565,328 @@ impl RasterizedGlyph {
         self.scale = new_scale;
         self.bytes = new_bytes;
     }
+
+    /// Resamples a bitmap glyph that is drawn scaled to the size it is drawn
+    /// at, with the `FontBitmapFilter` of `font`, unless the glyph is left
+    /// for the shader to scale.
+    pub fn resample_bitmap(&mut self, font: &FontInstance) {
+        match self.format {
+            GlyphFormat::Bitmap | GlyphFormat::ColorBitmap => {},
+            _ => return,
+        }
+        if font.bitmap_filter == FontBitmapFilter::Shader || self.width <= 0 || self.height <= 0 {
+            return;
+        }
+        let (x_scale, y_scale) = font.transform.compute_scale().unwrap_or((1.0, 1.0));
+        let upscaled = x_scale.max(y_scale) as f32;
+        // The size of a pixel of the glyph once drawn, in device pixels.
+        let ratio = self.scale * upscaled;
+        if ratio <= 0.0 || ratio == 1.0 {
+            return;
+        }
+        let (width, height) = (self.width as usize, self.height as usize);
+        let new_width = ((width as f32 * ratio).round() as usize).max(1);
+        let new_height = ((height as f32 * ratio).round() as usize).max(1);
+
+        // Resample the rows, then the columns of the transposed rows.
+        let rows = resample_rows(&self.bytes, width, height, new_width, ratio, font.bitmap_filter);
+        let columns = transpose_pixels(&rows, new_width, height);
+        let columns = resample_rows(&columns, height, new_width, new_height, ratio, font.bitmap_filter);
+        let mut bytes = transpose_pixels(&columns, new_height, new_width);
+        // The Lanczos filter rings, keep the pixels premultiplied.
+        for pixel in bytes.chunks_exact_mut(4) {
+            let alpha = pixel[3];
+            for channel in &mut pixel[.. 3] {
+                *channel = (*channel).min(alpha);
+            }
+        }
+
+        self.top = (self.top * ratio).round();
+        self.left = (self.left * ratio).round();
+        self.width = new_width as i32;
+        self.height = new_height as i32;
+        self.scale /= ratio;
+        self.bytes = bytes;
+    }
+
+    /// Replaces the coverage of an alpha glyph by the signed distance from
+    /// each pixel to the glyph's outline, and pads the glyph by `SDF_SPREAD`
+    /// pixels on each side. Distances are biased so that 0.5 is on the
+    /// outline, increase inwards, and a pixel maps to `1 / (2 * SDF_SPREAD)`.
+    /// Other glyph formats, such as color bitmaps, are left as they are.
+    pub fn convert_to_distance_field(&mut self) {
+        match self.format {
+            GlyphFormat::Alpha | GlyphFormat::TransformedAlpha => {},
+            _ => return,
+        }
+        let (src_width, src_height) = (self.width as usize, self.height as usize);
+        let width = src_width + 2 * SDF_SPREAD;
+        let height = src_height + 2 * SDF_SPREAD;
+        let coverage: Vec<f32> = (0 .. width * height).map(|i| {
+            let (x, y) = (i % width, i / width);
+            if x < SDF_SPREAD || y < SDF_SPREAD || x >= src_width + SDF_SPREAD || y >= src_height + SDF_SPREAD {
+                0.0
+            } else {
+                let src_idx = (y - SDF_SPREAD) * src_width + x - SDF_SPREAD;
+                self.bytes[src_idx * 4 + 3] as f32 / 255.0
+            }
+        }).collect();
+
+        // Squared distances from each pixel to the nearest pixel inside and
+        // outside the glyph.
+        let mut to_inside: Vec<f32> = coverage
+            .iter()
+            .map(|&c| if c >= 0.5 { 0.0 } else { f32::INFINITY })
+            .collect();
+        let mut to_outside: Vec<f32> = coverage
+            .iter()
+            .map(|&c| if c >= 0.5 { f32::INFINITY } else { 0.0 })
+            .collect();
+        distance_transform(&mut to_inside, width, height);
+        distance_transform(&mut to_outside, width, height);
+
+        let mut new_bytes: Vec<u8> = Vec::with_capacity(width * height * 4);
+        for (i, &c) in coverage.iter().enumerate() {
+            // The coverage of pixels on the outline estimates their distance
+            // to it better than the distance between pixel centers does.
+            let distance = if c > 0.0 && c < 1.0 {
+                c - 0.5
+            } else if c >= 0.5 {
+                to_outside[i].sqrt() - 0.5
+            } else {
+                0.5 - to_inside[i].sqrt()
+            };
+            let value = (0.5 + distance / (2 * SDF_SPREAD) as f32).max(0.0).min(1.0);
+            new_bytes.extend_from_slice(&[(value * 255.0).round() as u8; 4]);
+        }
+
+        self.top += SDF_SPREAD as f32;
+        self.left -= SDF_SPREAD as f32;
+        self.width = width as i32;
+        self.height = height as i32;
+        self.format = GlyphFormat::Sdf;
+        self.bytes = new_bytes;
+    }
+}
+
+/// The ink of a glyph: the columns covered by the pixels of each row of its
+/// rasterized bitmap.
+#[derive(Clone, Debug, Default, MallocSizeOf)]
+pub struct GlyphInk {
+    /// The offset of the bitmap from the origin of the glyph, with `top`
+    /// pointing up as in `RasterizedGlyph`.
+    pub left: f32,
+    pub top: f32,
+    /// The size of a bitmap pixel.
+    pub scale: f32,
+    /// The first and last covered columns of each row, from the top.
+    rows: Vec<Option<(u16, u16)>>,
+}
+
+impl GlyphInk {
+    /// Returns the ink of a rasterized glyph, before its conversion to the
+    /// format of the glyph cache.
+    pub fn new(glyph: &RasterizedGlyph) -> Self {
+        let width = glyph.width.max(0) as usize;
+        let rows = if width == 0 {
+            Vec::new()
+        } else {
+            glyph.bytes
+                .chunks(width * 4)
+                .map(|row| {
+                    let mut covered = row
+                        .chunks(4)
+                        .enumerate()
+                        .filter(|(_, pixel)| pixel.iter().any(|&c| c != 0))
+                        .map(|(x, _)| x as u16);
+                    let first = covered.next()?;
+                    Some((first, covered.next_back().unwrap_or(first)))
+                })
+                .collect()
+        };
+
+        GlyphInk {
+            left: glyph.left,
+            top: glyph.top,
+            scale: glyph.scale,
+            rows,
+        }
+    }
+
+    /// Returns the horizontal extent of the ink between `y0` and `y1`, both
+    /// relative to the origin of the glyph and pointing down, or None if
+    /// there is no ink there.
+    pub fn horizontal_extent(&self, y0: f32, y1: f32) -> Option<(f32, f32)> {
+        if y0.partial_cmp(&y1) != Some(cmp::Ordering::Less) || self.scale <= 0.0 {
+            return None;
+        }
+        let first_row = ((y0 + self.top) / self.scale).floor().max(0.0);
+        let last_row = ((y1 + self.top) / self.scale).ceil().min(self.rows.len() as f32);
+        if first_row >= last_row {
+            return None;
+        }
+
+        self.rows[first_row as usize .. last_row as usize]
+            .iter()
+            .flatten()
+            .fold(None, |extent, &(first, last)| {
+                let (first, last) = (first as f32, last as f32 + 1.0);
+                Some(match extent {
+                    Some((left, right)) => (f32::min(left, first), f32::max(right, last)),
+                    None => (first, last),
+                })
+            })
+            .map(|(first, last)| (self.left + first * self.scale, self.left + last * self.scale))
+    }
+}
+
+/// Computes in place the squared euclidean distance transform of a grid
+/// whose cells are either 0 or infinite, by transforming its columns then its
+/// rows, as described in "Distance Transforms of Sampled Functions" by
+/// Felzenszwalb and Huttenlocher.
+fn distance_transform(grid: &mut [f32], width: usize, height: usize) {
+    let len = width.max(height);
+    let mut src = vec![0.0; len];
+    let mut dest = vec![0.0; len];
+    let mut roots = vec![0; len];
+    let mut bounds = vec![0.0; len + 1];
+    for x in 0 .. width {
+        for y in 0 .. height {
+            src[y] = grid[y * width + x];
+        }
+        distance_transform_1d(&src[.. height], &mut dest[.. height], &mut roots, &mut bounds);
+        for y in 0 .. height {
+            grid[y * width + x] = dest[y];
+        }
+    }
+    for y in 0 .. height {
+        let row = &mut grid[y * width .. (y + 1) * width];
+        src[.. width].copy_from_slice(row);
+        distance_transform_1d(&src[.. width], row, &mut roots, &mut bounds);
+    }
+}
+
+/// The one-dimensional distance transform of `src` into `dest`: the lower
+/// envelope of the parabolas rooted at each finite cell, the parabola rooted
+/// at `roots[k]` being the lowest between `bounds[k]` and `bounds[k + 1]`.
+fn distance_transform_1d(src: &[f32], dest: &mut [f32], roots: &mut [usize], bounds: &mut [f32]) {
+    let mut count = 0;
+    for q in 0 .. src.len() {
+        if src[q] == f32::INFINITY {
+            continue;
+        }
+        let mut intersection = f32::NEG_INFINITY;
+        while count > 0 {
+            let p = roots[count - 1];
+            intersection = ((src[q] + (q * q) as f32) - (src[p] + (p * p) as f32)) / (2 * (q - p)) as f32;
+            if intersection > bounds[count - 1] {
+                break;
+            }
+            count -= 1;
+            intersection = f32::NEG_INFINITY;
+        }
+        roots[count] = q;
+        bounds[count] = intersection;
+        count += 1;
+        bounds[count] = f32::INFINITY;
+    }
+
+    if count == 0 {
+        for d in dest.iter_mut() {
+            *d = f32::INFINITY;
+        }
+        return;
+    }
+    let mut k = 0;
+    for q in 0 .. src.len() {
+        while bounds[k + 1] < q as f32 {
+            k += 1;
+        }
+        let offset = q as f32 - roots[k] as f32;
+        dest[q] = offset * offset + src[roots[k]];
+    }
+}
+
+/// The Lanczos kernel of 3 lobes.
+fn lanczos3(x: f32) -> f32 {
+    use std::f32::consts::PI;
+    if x == 0.0 {
+        1.0
+    } else if x.abs() < 3.0 {
+        let px = PI * x;
+        3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+    } else {
+        0.0
+    }
+}
+
+/// The first of the `len` pixels each of `dest_len` pixels resampled from
+/// `len` pixels by `ratio` is a weighted sum of, and their weights.
+fn resample_weights(len: usize, dest_len: usize, ratio: f32, filter: FontBitmapFilter) -> Vec<(usize, Vec<f32>)> {
+    let (support, kernel): (f32, fn(f32) -> f32) = match filter {
+        FontBitmapFilter::Bilinear => (1.0, |x| (1.0 - x.abs()).max(0.0)),
+        FontBitmapFilter::Lanczos3 => (3.0, lanczos3),
+        FontBitmapFilter::Shader | FontBitmapFilter::Nearest => (0.0, |_| 1.0),
+    };
+    // Widen the kernel when scaling down, so that all pixels contribute.
+    let kernel_scale = ratio.recip().max(1.0);
+    (0 .. dest_len).map(|x| {
+        let center = (x as f32 + 0.5) / ratio;
+        if support == 0.0 {
+            return ((center as usize).min(len - 1), vec![1.0]);
+        }
+        let start = (center - support * kernel_scale).floor().max(0.0) as usize;
+        let end = ((center + support * kernel_scale).ceil() as usize).min(len);
+        let mut weights: Vec<f32> = (start .. end)
+            .map(|i| kernel((i as f32 + 0.5 - center) / kernel_scale))
+            .collect();
+        let sum: f32 = weights.iter().sum();
+        if sum != 0.0 {
+            for weight in &mut weights {
+                *weight /= sum;
+            }
+        }
+        (start, weights)
+    }).collect()
+}
+
+/// Resamples each of the `height` rows of `width` BGRA pixels of `bytes` to
+/// `new_width` pixels.
+fn resample_rows(
+    bytes: &[u8],
+    width: usize,
+    height: usize,
+    new_width: usize,
+    ratio: f32,
+    filter: FontBitmapFilter,
+) -> Vec<u8> {
+    let weights = resample_weights(width, new_width, ratio, filter);
+    let mut resampled = Vec::with_capacity(new_width * height * 4);
+    for row in bytes.chunks_exact(width * 4) {
+        for &(start, ref weights) in &weights {
+            let mut accum = [0.0f32; 4];
+            for (pixel, weight) in row[start * 4 ..].chunks_exact(4).zip(weights) {
+                for (sum, &value) in accum.iter_mut().zip(pixel) {
+                    *sum += value as f32 * weight;
+                }
+            }
+            resampled.extend(accum.iter().map(|sum| sum.round().max(0.0).min(255.0) as u8));
+        }
+    }
+    resampled
+}
+
+/// Transposes `height` rows of `width` BGRA pixels.
+fn transpose_pixels(bytes: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut transposed = vec![0; bytes.len()];
+    for y in 0 .. height {
+        for x in 0 .. width {
+            let src = (y * width + x) * 4;
+            let dest = (x * height + y) * 4;
+            transposed[dest .. dest + 4].copy_from_slice(&bytes[src .. src + 4]);
+        }
+    }
+    transposed
 }
 
 pub struct FontContexts {
@@ -1497,11 +1965,127 @@ impl AsyncForEach<FontContext> for Arc<FontContexts> {
     }
 }
 
+/// Replaces WOFF and WOFF2 font data with the sfnt data the backends load.
+/// Data that fails to decode is left as is, for the backends to report the
+/// font as invalid.
+#[cfg(feature = "woff")]
+fn decode_woff_font(font_key: &FontKey, template: &mut FontTemplate, parsed: &mut Option<ParsedFontInfo>) {
+    let (data, index) = match *template {
+        FontTemplate::Raw(ref data, index) if crate::woff::is_woff(data) => (data, index),
+        _ => return,
+    };
+    match crate::woff::decode(data) {
+        Ok(data) => {
+            *template = FontTemplate::Raw(Arc::new(data), index);
+            // The offsets of parsed info don't apply to the decoded font.
+            *parsed = None;
+        }
+        Err(error) => warn!("Failed to decode WOFF font {:?}: {:?}", font_key, error),
+    }
+}
+
+/// Checks that `data` starts with the header of a font or font collection
+/// that has face `index`, so that data which can't be a font is rejected
+/// before it is sent to the font contexts. The tables of the font are only
+/// parsed by the backends.
+fn check_raw_font(data: &[u8], index: u32) -> Result<(), GlyphRasterError> {
+    let read_u16 = |offset: usize| -> Option<u32> {
+        let bytes = data.get(offset .. offset + 2)?;
+        Some(u32::from(bytes[0]) << 8 | u32::from(bytes[1]))
+    };
+    let read_u32 = |offset: usize| -> Option<u32> {
+        Some(read_u16(offset)? << 16 | read_u16(offset + 2)?)
+    };
+    let valid = match read_u32(0) {
+        // TrueType, 'OTTO', 'true' and 'typ1' fonts, with a 16 byte record
+        // per table after the 12 byte offset table.
+        Some(0x0001_0000) | Some(0x4F54_544F) | Some(0x7472_7565) | Some(0x7479_7031) => {
+            let num_tables = read_u16(4).unwrap_or(0) as usize;
+            index == 0 && num_tables > 0 && data.len() >= 12 + 16 * num_tables
+        }
+        // 'ttcf' collections, with the offset of each font after the header.
+        Some(0x7474_6366) => {
+            let num_fonts = read_u32(8).unwrap_or(0);
+            index < num_fonts && read_u32(12 + 4 * index as usize).is_some()
+        }
+        // 'wOFF' and 'wOF2' fonts are checked when they are decoded.
+        Some(0x774F_4646) | Some(0x774F_4632) => cfg!(feature = "woff"),
+        _ => false,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(GlyphRasterError::LoadFailed)
+    }
+}
+
+/// A glyph rasterizer backend. Which backends are available depends on the
+/// cargo features the crate is built with: `backend_native` for the platform's
+/// font library, or `backend_swash`, `backend_fontdue`, `backend_ab_glyph` and
+/// `backend_tiny_skia`, which can be built together. The native backend can't be built along with the others, since
+/// they use different font handles and platform options.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum GlyphBackend {
+    Native,
+    Swash,
+    Fontdue,
+    AbGlyph,
+    TinySkia,
+}
+
+impl GlyphBackend {
+    /// Whether the crate was built with this backend.
+    pub fn is_available(self) -> bool {
+        match self {
+            GlyphBackend::Native => cfg!(feature = "backend_native"),
+            GlyphBackend::Swash => cfg!(all(feature = "backend_swash", not(feature = "backend_native"))),
+            GlyphBackend::Fontdue => cfg!(all(feature = "backend_fontdue", not(feature = "backend_native"))),
+            GlyphBackend::AbGlyph => cfg!(all(feature = "backend_ab_glyph", not(feature = "backend_native"))),
+            GlyphBackend::TinySkia => cfg!(all(feature = "backend_tiny_skia", not(feature = "backend_native"))),
+        }
+    }
+}
+
+impl Default for GlyphBackend {
+    fn default() -> Self {
+        if cfg!(feature = "backend_native") {
+            GlyphBackend::Native
+        } else if cfg!(feature = "backend_swash") {
+            GlyphBackend::Swash
+        } else if cfg!(feature = "backend_fontdue") {
+            GlyphBackend::Fontdue
+        } else if cfg!(feature = "backend_ab_glyph") {
+            GlyphBackend::AbGlyph
+        } else {
+            GlyphBackend::TinySkia
+        }
+    }
+}
+
+/// How urgently requested glyphs are needed. The glyphs of a frame are
+/// rasterized in priority order.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum GlyphRequestPriority {
+    /// Glyphs of text in the viewport.
+    Visible = 0,
+    /// Glyphs of text outside the viewport but drawn in the frame, such as
+    /// text in picture cache tiles about to be scrolled into view.
+    NearViewport = 1,
+    /// Glyphs that may be needed in a later frame.
+    Speculative = 2,
+}
+
+impl GlyphRequestPriority {
+    pub const COUNT: usize = 3;
+}
+
 pub struct GlyphRasterizer {
-    #[allow(dead_code)]
     workers: Arc<ThreadPool>,
     font_contexts: Arc<FontContexts>,
 
+    /// The backend the font contexts use.
+    backend: GlyphBackend,
+
     /// The current set of loaded fonts.
     fonts: FastHashSet<FontKey>,
 
@@ -1514,8 +2098,8 @@ pub struct GlyphRasterizer {
     /// The number of glyphs requested this frame.
     glyph_request_count: usize,
 
-    /// A map of current glyph request batches.
-    pending_glyph_requests: FastHashMap<FontInstance, SmallVec<[GlyphKey; 16]>>,
+    /// Maps of current glyph request batches, by priority.
+    pending_glyph_requests: [FastHashMap<FontInstance, SmallVec<[GlyphKey; 16]>>; GlyphRequestPriority::COUNT],
 
     // Receives the rendered glyphs.
     glyph_rx: Receiver<GlyphRasterJob>,
@@ -1532,19 +2116,44 @@ pub struct GlyphRasterizer {
     // Whether to parallelize glyph rasterization with rayon.
     enable_multithreading: bool,
 
+    // Overrides whether the backend spreads the glyphs of a font across threads.
+    distribute_across_threads: Option<bool>,
+
     // Whether glyphs can be rasterized in r8 format when it makes sense.
     can_use_r8_format: bool,
+
+    // The fonts that failed to load, added to by the font contexts.
+    failed_fonts: Arc<Mutex<FastHashSet<FontKey>>>,
+
+    // Receives the statistics of requested and rasterized glyphs.
+    telemetry_sink: Option<Arc<dyn GlyphTelemetrySink>>,
 }
 
 impl GlyphRasterizer {
     pub fn new(workers: Arc<ThreadPool>, can_use_r8_format: bool) -> Self {
+        GlyphRasterizer::with_backend(workers, can_use_r8_format, GlyphBackend::default())
+    }
+
+    /// Creates a rasterizer using `backend`, or the default backend if the
+    /// crate wasn't built with it.
+    pub fn with_backend(workers: Arc<ThreadPool>, can_use_r8_format: bool, backend: GlyphBackend) -> Self {
+        let backend = if backend.is_available() {
+            backend
+        } else {
+            warn!("Glyph backend {:?} isn't available, using the default one", backend);
+            GlyphBackend::default()
+        };
         let (glyph_tx, glyph_rx) = unbounded();
 
         let num_workers = workers.current_num_threads();
         let mut contexts = Vec::with_capacity(num_workers);
 
         for _ in 0 .. num_workers {
-            contexts.push(Mutex::new(FontContext::new()));
+            #[cfg(feature = "backend_native")]
+            let context = FontContext::new();
+            #[cfg(not(feature = "backend_native"))]
+            let context = FontContext::new(backend);
+            contexts.push(Mutex::new(context));
         }
 
         let font_context = FontContexts {
@@ -1556,6 +2165,7 @@ impl GlyphRasterizer {
 
         GlyphRasterizer {
             font_contexts: Arc::new(font_context),
+            backend,
             fonts: FastHashSet::default(),
             pending_glyph_jobs: 0,
             pending_glyph_count: 0,
@@ -1566,24 +2176,116 @@ impl GlyphRasterizer {
             fonts_to_remove: Vec::new(),
             font_instances_to_remove: Vec::new(),
             enable_multithreading: true,
-            pending_glyph_requests: FastHashMap::default(),
+            distribute_across_threads: None,
+            pending_glyph_requests: Default::default(),
             can_use_r8_format,
+            failed_fonts: Arc::new(Mutex::new(FastHashSet::default())),
+            telemetry_sink: None,
+        }
+    }
+
+    /// The backend glyphs are rasterized with.
+    pub fn backend(&self) -> GlyphBackend {
+        self.backend
+    }
+
+    /// Sets the number of bytes of rasterized glyph images the backend keeps
+    /// between rasterizations. The budget is shared by all the rasterizers
+    /// of the process, and is ignored by backends that don't keep images.
+    pub fn set_image_cache_budget(&self, bytes: usize) {
+        #[cfg(not(feature = "backend_native"))]
+        FontContext::set_image_cache_budget(bytes);
+        #[cfg(feature = "backend_native")]
+        let _ = bytes;
+    }
+
+    /// Evicts the glyph images the backend keeps, all of them if `critical`.
+    /// Returns the number of bytes freed.
+    pub fn on_memory_pressure(&self, critical: bool) -> usize {
+        #[cfg(not(feature = "backend_native"))]
+        return FontContext::on_memory_pressure(critical);
+        #[cfg(feature = "backend_native")]
+        {
+            let _ = critical;
+            0
+        }
+    }
+
+    /// Reports the heap memory of the glyph images the backend keeps.
+    pub fn report_memory(&self, ops: &mut MallocSizeOfOps) -> usize {
+        #[cfg(not(feature = "backend_native"))]
+        return FontContext::report_memory(ops);
+        #[cfg(feature = "backend_native")]
+        {
+            let _ = ops;
+            0
         }
     }
 
     pub fn add_font(&mut self, font_key: FontKey, template: FontTemplate) {
+        self.add_font_impl(font_key, template, None);
+    }
+
+    /// Adds a font that the embedder already parsed. Backends that can't use
+    /// `ParsedFontInfo` load the font from its template as usual.
+    pub fn add_parsed_font(&mut self, font_key: FontKey, template: FontTemplate, info: ParsedFontInfo) {
+        self.add_font_impl(font_key, template, Some(info));
+    }
+
+    fn add_font_impl(&mut self, font_key: FontKey, template: FontTemplate, parsed: Option<ParsedFontInfo>) {
         if self.fonts.insert(font_key.clone()) {
+            if let FontTemplate::Raw(ref data, index) = template {
+                if check_raw_font(data, index).is_err() {
+                    warn!("Font {:?} isn't a valid font", font_key);
+                    self.failed_fonts.lock().unwrap().insert(font_key);
+                    return;
+                }
+            }
             // Only add font to FontContexts if not previously added.
+            let failed_fonts = Arc::clone(&self.failed_fonts);
+            // WOFF data is decoded once, by the worker adding the font to the
+            // contexts, rather than by the thread adding the font here.
+            #[cfg(feature = "woff")]
+            let decoded = Once::new();
+            let font = Mutex::new((template, parsed));
             self.font_contexts.async_for_each(move |mut context| {
-                context.add_font(&font_key, &template);
+                let mut font = font.lock().unwrap();
+                let (ref mut template, ref mut parsed) = *font;
+                #[cfg(feature = "woff")]
+                decoded.call_once(|| decode_woff_font(&font_key, template, parsed));
+                if context.add_font(&font_key, template, *parsed).is_err() {
+                    failed_fonts.lock().unwrap().insert(font_key);
+                }
             });
         }
     }
 
+    /// Returns the fonts that failed to load since the last call. Their
+    /// glyphs fail to rasterize.
+    pub fn take_failed_fonts(&mut self) -> Vec<FontKey> {
+        let mut failed_fonts = self.failed_fonts.lock().unwrap();
+        failed_fonts.drain().collect()
+    }
+
     pub fn delete_font(&mut self, font_key: FontKey) {
         self.fonts_to_remove.push(font_key);
     }
 
+    /// Calls `callback` on a worker thread once the fonts added so far have
+    /// been loaded by every font context.
+    pub fn notify_fonts_loaded<F: FnOnce() + Send + 'static>(&self, callback: F) {
+        let font_contexts = Arc::clone(&self.font_contexts);
+        self.workers.spawn(move || {
+            // Fonts are added to all contexts by a job that locks them up front
+            // and processes them in order, so once a context can be locked, the
+            // pending fonts have been added to it.
+            for i in 0 .. font_contexts.num_worker_contexts() {
+                drop(font_contexts.lock_context(i));
+            }
+            callback();
+        });
+    }
+
     pub fn delete_fonts(&mut self, font_keys: &[FontKey]) {
         self.fonts_to_remove.extend_from_slice(font_keys);
     }
@@ -1593,7 +2295,17 @@ impl GlyphRasterizer {
     }
 
     pub fn prepare_font(&self, font: &mut FontInstance) {
+        #[cfg(feature = "backend_native")]
         FontContext::prepare_font(font);
+        #[cfg(not(feature = "backend_native"))]
+        FontContext::prepare_font(self.backend, font);
+
+        if font.flags.contains(FontInstanceFlags::SDF) {
+            // Distance fields are computed from the glyph's coverage and drawn
+            // with the text color, so glyphs are rendered without gamma
+            // preblending for a particular color.
+            font.color = ColorU::new(0xFF, 0xFF, 0xFF, 0xFF);
+        }
 
         // Quantize the transform to minimize thrashing of the glyph cache, but
         // only quantize the transform when preparing to access the glyph cache.
@@ -1623,12 +2335,55 @@ impl GlyphRasterizer {
             .get_glyph_dimensions(font, &glyph_key)
     }
 
+    /// Rasterizes a glyph on the calling thread to find out where its ink is.
+    pub fn get_glyph_ink(
+        &mut self,
+        font: &FontInstance,
+        glyph_index: GlyphIndex,
+    ) -> Option<GlyphInk> {
+        let glyph_key = GlyphKey::new(
+            glyph_index,
+            DevicePoint::zero(),
+            SubpixelDirection::None,
+        );
+
+        FontContext::begin_rasterize(font);
+        let glyph = self.font_contexts
+            .lock_any_context()
+            .rasterize_glyph(font, &glyph_key);
+        FontContext::end_rasterize(font);
+
+        glyph.ok().map(|glyph| GlyphInk::new(&glyph))
+    }
+
     pub fn get_glyph_index(&mut self, font_key: FontKey, ch: char) -> Option<u32> {
         self.font_contexts
             .lock_any_context()
             .get_glyph_index(font_key, ch)
     }
 
+    /// The glyph of the variation sequence of `ch` and the variation selector
+    /// `selector`, or None if the font doesn't map the sequence.
+    pub fn get_glyph_variant_index(&mut self, font_key: FontKey, ch: char, selector: char) -> Option<u32> {
+        self.font_contexts
+            .lock_any_context()
+            .get_glyph_variant_index(font_key, ch, selector)
+    }
+
+    /// The native backends don't extract outlines, so their outlines are
+    /// always empty.
+    pub fn get_glyph_outline(&mut self, font_key: FontKey, glyph_index: GlyphIndex) -> Vec<PathCommand> {
+        #[cfg(feature = "backend_native")]
+        {
+            let _ = (font_key, glyph_index);
+            Vec::new()
+        }
+        #[cfg(not(feature = "backend_native"))]
+        self.font_contexts
+            .lock_any_context()
+            .get_glyph_outline(font_key, glyph_index)
+    }
+
     fn remove_dead_fonts(&mut self) {
         if self.fonts_to_remove.is_empty() && self.font_instances_to_remove.is_empty() {
             return
@@ -1661,19 +2416,60 @@ impl GlyphRasterizer {
 }
 
 trait AddFont {
-    fn add_font(&mut self, font_key: &FontKey, template: &FontTemplate);
+    fn add_font(
+        &mut self,
+        font_key: &FontKey,
+        template: &FontTemplate,
+        parsed: Option<ParsedFontInfo>,
+    ) -> Result<(), GlyphRasterError>;
 }
 
+#[cfg(feature = "backend_native")]
 impl AddFont for FontContext {
-    fn add_font(&mut self, font_key: &FontKey, template: &FontTemplate) {
+    fn add_font(
+        &mut self,
+        font_key: &FontKey,
+        template: &FontTemplate,
+        _: Option<ParsedFontInfo>,
+    ) -> Result<(), GlyphRasterError> {
         match *template {
             FontTemplate::Raw(ref bytes, index) => {
                 self.add_raw_font(font_key, bytes.clone(), index);
             }
+            FontTemplate::File { ref path, index } => {
+                self.add_file_font(font_key, path, index);
+            }
             FontTemplate::Native(ref native_font_handle) => {
                 self.add_native_font(font_key, (*native_font_handle).clone());
             }
         }
+        // The native backends log the fonts they fail to load.
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "backend_native"))]
+impl AddFont for FontContext {
+    fn add_font(
+        &mut self,
+        font_key: &FontKey,
+        template: &FontTemplate,
+        parsed: Option<ParsedFontInfo>,
+    ) -> Result<(), GlyphRasterError> {
+        match (template, parsed) {
+            (&FontTemplate::Raw(ref bytes, index), Some(info)) => {
+                self.add_parsed_font(font_key, bytes.clone(), index, info)
+            }
+            (&FontTemplate::Raw(ref bytes, index), None) => {
+                self.add_raw_font(font_key, bytes.clone(), index)
+            }
+            (&FontTemplate::File { ref path, index }, _) => {
+                self.add_file_font(font_key, path, index)
+            }
+            (&FontTemplate::Native(ref native_font_handle), _) => {
+                self.add_native_font(font_key, (*native_font_handle).clone())
+            }
+        }
     }
 }
 
@@ -1722,7 +2518,7 @@ mod test_glyph_rasterizer {
         use api::{FontKey, FontInstanceKey, FontTemplate, IdNamespace};
         use api::units::DevicePoint;
         use std::sync::Arc;
-        use crate::rasterizer::{FontInstance, BaseFontInstance, GlyphKey, GlyphRasterizer};
+        use crate::rasterizer::{FontInstance, BaseFontInstance, GlyphKey, GlyphRasterizer, GlyphRequestPriority};
 
         let worker = ThreadPoolBuilder::new()
             .thread_name(|idx|{ format!("WRWorker#{}", idx) })
@@ -1746,6 +2542,7 @@ mod test_glyph_rasterizer {
             None,
             None,
             Vec::new(),
+            Vec::new(),
         )));
 
         let subpx_dir = font.get_subpx_dir();
@@ -1762,6 +2559,7 @@ mod test_glyph_rasterizer {
         for i in 0 .. 4 {
             glyph_rasterizer.request_glyphs(
                 font.clone(),
+                GlyphRequestPriority::Visible,
                 &glyph_keys[(50 * i) .. (50 * (i + 1))],
                 |_| true,
             );
@@ -1785,7 +2583,7 @@ mod test_glyph_rasterizer {
         use api::{FontKey, FontInstanceKey, FontTemplate, IdNamespace};
         use api::units::DevicePoint;
         use std::sync::Arc;
-        use crate::rasterizer::{FontInstance, BaseFontInstance, GlyphKey, GlyphRasterizer};
+        use crate::rasterizer::{FontInstance, BaseFontInstance, GlyphKey, GlyphRasterizer, GlyphRequestPriority};
 
         let worker = ThreadPoolBuilder::new()
             .thread_name(|idx|{ format!("WRWorker#{}", idx) })
@@ -1809,6 +2607,7 @@ mod test_glyph_rasterizer {
             None,
             None,
             Vec::new(),
+            Vec::new(),
         )));
 
         let subpx_dir = font.get_subpx_dir();
@@ -1824,6 +2623,7 @@ mod test_glyph_rasterizer {
 
         glyph_rasterizer.request_glyphs(
             font.clone(),
+            GlyphRequestPriority::Visible,
             &glyph_keys,
             |_| true,
         );
@@ -1879,4 +2679,179 @@ mod test_glyph_rasterizer {
         assert_eq!(SubpixelOffset::quantize(-1.625), SubpixelOffset::Half);
         assert_eq!(SubpixelOffset::quantize(-4.33), SubpixelOffset::ThreeQuarters);
     }
+
+    #[test]
+    fn test_distance_field() {
+        use crate::rasterizer::{GlyphFormat, RasterizedGlyph, SDF_SPREAD};
+
+        // A fully covered 4x4 square.
+        let mut glyph = RasterizedGlyph {
+            top: 4.0,
+            left: 0.0,
+            width: 4,
+            height: 4,
+            scale: 1.0,
+            format: GlyphFormat::Alpha,
+            bytes: vec![0xFF; 4 * 4 * 4],
+        };
+        glyph.convert_to_distance_field();
+
+        let size = 4 + 2 * SDF_SPREAD;
+        assert_eq!(glyph.format, GlyphFormat::Sdf);
+        assert_eq!((glyph.width, glyph.height), (size as i32, size as i32));
+        assert_eq!((glyph.left, glyph.top), (-(SDF_SPREAD as f32), 4.0 + SDF_SPREAD as f32));
+        assert_eq!(glyph.bytes.len(), size * size * 4);
+
+        let value = |x: usize, y: usize| glyph.bytes[(y * size + x) * 4];
+        // Far outside the square.
+        assert_eq!(value(0, 0), 0);
+        // Inside the square, 1.5 pixels from its edge.
+        let center = SDF_SPREAD + 2;
+        assert_eq!(value(center, center), 159);
+        // On either side of its edge.
+        assert_eq!(value(SDF_SPREAD, center), 138);
+        assert_eq!(value(SDF_SPREAD - 1, center), 117);
+    }
+
+    #[test]
+    fn test_glyph_ink() {
+        use crate::rasterizer::{GlyphFormat, GlyphInk, RasterizedGlyph};
+
+        // A 4x4 bitmap above the baseline, with ink in columns 1-2 of the
+        // top row and column 3 of the bottom row only.
+        let mut bytes = vec![0; 4 * 4 * 4];
+        for &(x, y) in &[(1, 0), (2, 0), (3, 3)] {
+            bytes[(y * 4 + x) * 4 + 3] = 0xFF;
+        }
+        let ink = GlyphInk::new(&RasterizedGlyph {
+            top: 4.0,
+            left: 1.0,
+            width: 4,
+            height: 4,
+            scale: 1.0,
+            format: GlyphFormat::Alpha,
+            bytes,
+        });
+
+        assert_eq!(ink.horizontal_extent(-4.0, -3.0), Some((2.0, 4.0)));
+        assert_eq!(ink.horizontal_extent(-1.0, 0.0), Some((4.0, 5.0)));
+        assert_eq!(ink.horizontal_extent(-4.0, 0.0), Some((2.0, 5.0)));
+        // The empty rows in between, and below the baseline.
+        assert_eq!(ink.horizontal_extent(-3.0, -1.0), None);
+        assert_eq!(ink.horizontal_extent(0.0, 2.0), None);
+        assert_eq!(ink.horizontal_extent(f32::NAN, 0.0), None);
+    }
+
+    #[test]
+    fn test_resample_bitmap() {
+        use api::{FontBitmapFilter, FontInstanceKey, FontInstanceOptions, FontKey, IdNamespace};
+        use crate::rasterizer::{BaseFontInstance, FontInstance, GlyphFormat, RasterizedGlyph};
+        use std::sync::Arc;
+
+        let font = |bitmap_filter| FontInstance::from_base(Arc::new(BaseFontInstance::new(
+            FontInstanceKey::new(IdNamespace(0), 0),
+            FontKey::new(IdNamespace(0), 0),
+            32.0,
+            Some(FontInstanceOptions { bitmap_filter, ..Default::default() }),
+            None,
+            Vec::new(),
+            Vec::new(),
+        )));
+        // A 2x2 checkerboard strike drawn at twice its size.
+        let (black, white) = ([0, 0, 0, 0xFF], [0xFF; 4]);
+        let checkerboard = || RasterizedGlyph {
+            top: 2.0,
+            left: -1.0,
+            width: 2,
+            height: 2,
+            scale: 2.0,
+            format: GlyphFormat::ColorBitmap,
+            bytes: [black, white, white, black].concat(),
+        };
+
+        let mut glyph = checkerboard();
+        glyph.resample_bitmap(&font(FontBitmapFilter::Shader));
+        assert_eq!((glyph.width, glyph.scale), (2, 2.0));
+
+        let mut glyph = checkerboard();
+        glyph.resample_bitmap(&font(FontBitmapFilter::Nearest));
+        assert_eq!((glyph.width, glyph.height, glyph.scale), (4, 4, 1.0));
+        assert_eq!((glyph.left, glyph.top), (-2.0, 4.0));
+        let pixel = |glyph: &RasterizedGlyph, x: usize, y: usize| {
+            glyph.bytes[(y * glyph.width as usize + x) * 4 ..][.. 4].to_vec()
+        };
+        assert_eq!(pixel(&glyph, 1, 1), black);
+        assert_eq!(pixel(&glyph, 2, 1), white);
+        assert_eq!(pixel(&glyph, 1, 2), white);
+
+        let mut glyph = checkerboard();
+        glyph.resample_bitmap(&font(FontBitmapFilter::Bilinear));
+        assert_eq!((glyph.width, glyph.height, glyph.scale), (4, 4, 1.0));
+        // Corners keep their color, the middle is interpolated.
+        assert_eq!(pixel(&glyph, 0, 0), black);
+        assert_eq!(pixel(&glyph, 1, 1)[0], 96);
+        assert_eq!(pixel(&glyph, 1, 2)[0], 159);
+    }
+
+    #[test]
+    fn test_font_key_map_shares_raw_data() {
+        use api::{FontKey, FontTemplate, IdNamespace};
+        use crate::rasterizer::FontKeyMap;
+        use std::sync::Arc;
+
+        let raw_data = |template: &FontTemplate| match *template {
+            FontTemplate::Raw(ref data, _) => Arc::clone(data),
+            _ => unreachable!(),
+        };
+
+        let mut map = FontKeyMap::new(IdNamespace(0));
+        let bytes = vec![1u8, 2, 3, 4];
+        let key0 = FontKey::new(IdNamespace(1), 0);
+        let key1 = FontKey::new(IdNamespace(2), 0);
+        let key2 = FontKey::new(IdNamespace(2), 1);
+
+        // Faces of the same data share it.
+        let mut face0 = FontTemplate::Raw(Arc::new(bytes.clone()), 0);
+        let mut face1 = FontTemplate::Raw(Arc::new(bytes.clone()), 1);
+        let shared0 = map.add_key(&key0, &mut face0).unwrap();
+        let shared1 = map.add_key(&key1, &mut face1).unwrap();
+        assert_ne!(shared0, shared1);
+        assert!(Arc::ptr_eq(&raw_data(&face0), &raw_data(&face1)));
+
+        // Different data isn't shared.
+        let mut other = FontTemplate::Raw(Arc::new(vec![4u8, 3, 2, 1]), 1);
+        map.add_key(&key2, &mut other).unwrap();
+        assert!(!Arc::ptr_eq(&raw_data(&face0), &raw_data(&other)));
+
+        // Data is released once none of its templates are mapped.
+        assert_eq!(map.delete_key(&key0), Some(shared0));
+        assert_eq!(map.clear_namespace(IdNamespace(2)).len(), 2);
+        let mut face0 = FontTemplate::Raw(Arc::new(bytes), 0);
+        let data = raw_data(&face0);
+        map.add_key(&key0, &mut face0).unwrap();
+        assert!(Arc::ptr_eq(&raw_data(&face0), &data));
+    }
+
+    #[test]
+    fn test_check_raw_font() {
+        use crate::rasterizer::check_raw_font;
+
+        // An offset table with one table record.
+        let mut font = vec![0x00, 0x01, 0x00, 0x00, 0x00, 0x01];
+        font.resize(28, 0);
+        assert!(check_raw_font(&font, 0).is_ok());
+        assert!(check_raw_font(&font, 1).is_err());
+        assert!(check_raw_font(&font[.. 27], 0).is_err());
+
+        // A collection of two fonts.
+        let mut collection = b"ttcf".to_vec();
+        collection.extend_from_slice(&[0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02]);
+        collection.resize(20, 0);
+        assert!(check_raw_font(&collection, 1).is_ok());
+        assert!(check_raw_font(&collection, 2).is_err());
+        assert!(check_raw_font(&collection[.. 19], 1).is_err());
+
+        assert!(check_raw_font(&[], 0).is_err());
+        assert!(check_raw_font(b"<html>", 0).is_err());
+    }
 }
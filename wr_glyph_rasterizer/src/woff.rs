@@ -0,0 +1,958 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+//! Decoding of WOFF and WOFF2 fonts to the sfnt data the glyph rasterizer
+//! backends load.
+//!
+//! WOFF compresses each table with zlib. WOFF2 compresses all the tables
+//! together with brotli, after transforming the glyf, loca and optionally
+//! hmtx tables, which are reconstructed here. WOFF2 font collections aren't
+//! supported. Metadata and private data blocks are dropped.
+
+use std::convert::TryFrom;
+use std::io::Read;
+
+const WOFF_SIGNATURE: u32 = 0x774F_4646; // 'wOFF'
+const WOFF2_SIGNATURE: u32 = 0x774F_4632; // 'wOF2'
+const TTC_FLAVOR: u32 = 0x7474_6366; // 'ttcf'
+
+const TAG_GLYF: u32 = 0x676C_7966;
+const TAG_LOCA: u32 = 0x6C6F_6361;
+const TAG_HMTX: u32 = 0x686D_7478;
+const TAG_HEAD: u32 = 0x6865_6164;
+const TAG_HHEA: u32 = 0x6868_6561;
+const TAG_MAXP: u32 = 0x6D61_7870;
+
+/// The largest size of the tables of a decoded font. The sizes the WOFF data
+/// declares are checked against it before they are decompressed, so that a
+/// crafted font can't exhaust memory.
+const MAX_DECODED_SIZE: usize = 128 * 1024 * 1024;
+
+/// Why a font couldn't be decoded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WoffError {
+    /// The data ends before a table or field it declares.
+    Truncated,
+    /// A table or field has an invalid value.
+    Invalid,
+    /// The data failed to decompress.
+    Decompression,
+    /// The font is a WOFF2 font collection.
+    Collection,
+}
+
+/// Returns whether `data` is a WOFF or WOFF2 font.
+pub fn is_woff(data: &[u8]) -> bool {
+    match Reader::new(data).u32() {
+        Ok(signature) => signature == WOFF_SIGNATURE || signature == WOFF2_SIGNATURE,
+        Err(_) => false,
+    }
+}
+
+/// Decodes a WOFF or WOFF2 font to sfnt data.
+pub fn decode(data: &[u8]) -> Result<Vec<u8>, WoffError> {
+    match Reader::new(data).u32()? {
+        WOFF_SIGNATURE => decode_woff(data),
+        WOFF2_SIGNATURE => decode_woff2(data),
+        _ => Err(WoffError::Invalid),
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], WoffError> {
+        let end = self.pos.checked_add(len).ok_or(WoffError::Truncated)?;
+        let bytes = self.data.get(self.pos .. end).ok_or(WoffError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), WoffError> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, WoffError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, WoffError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn i16(&mut self) -> Result<i16, WoffError> {
+        self.u16().map(|v| v as i16)
+    }
+
+    fn u32(&mut self) -> Result<u32, WoffError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Reads a WOFF2 UIntBase128.
+    fn base128(&mut self) -> Result<u32, WoffError> {
+        let mut value: u32 = 0;
+        for i in 0 .. 5 {
+            let byte = self.u8()?;
+            // Leading zeros aren't allowed.
+            if i == 0 && byte == 0x80 {
+                return Err(WoffError::Invalid);
+            }
+            if value & 0xFE00_0000 != 0 {
+                return Err(WoffError::Invalid);
+            }
+            value = (value << 7) | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(WoffError::Invalid)
+    }
+
+    /// Reads a WOFF2 255UInt16.
+    fn u255(&mut self) -> Result<u16, WoffError> {
+        const WORD_CODE: u8 = 253;
+        const ONE_MORE_BYTE_CODE_2: u8 = 254;
+        const ONE_MORE_BYTE_CODE_1: u8 = 255;
+        const LOWEST_U_CODE: u16 = 253;
+        match self.u8()? {
+            WORD_CODE => self.u16(),
+            ONE_MORE_BYTE_CODE_1 => Ok(self.u8()? as u16 + LOWEST_U_CODE),
+            ONE_MORE_BYTE_CODE_2 => Ok(self.u8()? as u16 + LOWEST_U_CODE * 2),
+            code => Ok(code as u16),
+        }
+    }
+}
+
+fn pad4(data: &mut Vec<u8>) {
+    while data.len() % 4 != 0 {
+        data.push(0);
+    }
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[.. chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+/// Lays out the tables of a font, sorted by tag, in sfnt data.
+fn write_sfnt(flavor: u32, mut tables: Vec<(u32, u32, Vec<u8>)>) -> Result<Vec<u8>, WoffError> {
+    tables.sort_by_key(|&(tag, ..)| tag);
+    // The search range is 16 times the largest power of two up to the number
+    // of tables, which only fits in 16 bits up to 4095 tables.
+    let num_tables = match u16::try_from(tables.len()) {
+        Ok(num_tables) if num_tables > 0 && num_tables < 4096 => num_tables,
+        _ => return Err(WoffError::Invalid),
+    };
+    let entry_selector = 15 - num_tables.leading_zeros() as u16;
+    let search_range = (1u16 << entry_selector) * 16;
+
+    let mut sfnt = Vec::new();
+    sfnt.extend_from_slice(&flavor.to_be_bytes());
+    sfnt.extend_from_slice(&num_tables.to_be_bytes());
+    sfnt.extend_from_slice(&search_range.to_be_bytes());
+    sfnt.extend_from_slice(&entry_selector.to_be_bytes());
+    sfnt.extend_from_slice(&(num_tables * 16 - search_range).to_be_bytes());
+
+    let mut offset = 12 + 16 * tables.len();
+    for &(tag, checksum, ref data) in &tables {
+        sfnt.extend_from_slice(&tag.to_be_bytes());
+        sfnt.extend_from_slice(&checksum.to_be_bytes());
+        sfnt.extend_from_slice(&(offset as u32).to_be_bytes());
+        sfnt.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        offset += (data.len() + 3) & !3;
+    }
+    for (_, _, data) in tables {
+        sfnt.extend_from_slice(&data);
+        pad4(&mut sfnt);
+    }
+    Ok(sfnt)
+}
+
+fn decode_woff(data: &[u8]) -> Result<Vec<u8>, WoffError> {
+    let mut header = Reader::new(data);
+    header.u32()?;
+    let flavor = header.u32()?;
+    header.u32()?;
+    let num_tables = header.u16()?;
+    header.skip(44 - 14)?;
+
+    let mut tables = Vec::with_capacity(num_tables as usize);
+    let mut decoded_size = 0usize;
+    for _ in 0 .. num_tables {
+        let tag = header.u32()?;
+        let offset = header.u32()? as usize;
+        let comp_length = header.u32()? as usize;
+        let orig_length = header.u32()? as usize;
+        let orig_checksum = header.u32()?;
+
+        decoded_size = decoded_size.checked_add(orig_length).ok_or(WoffError::Invalid)?;
+        if decoded_size > MAX_DECODED_SIZE {
+            return Err(WoffError::Invalid);
+        }
+        let compressed = Reader { data, pos: offset }.bytes(comp_length)?;
+        let table = if comp_length < orig_length {
+            // The table grows as it is decompressed, rather than up front
+            // to the size the font declares.
+            let mut table = Vec::new();
+            flate2::read::ZlibDecoder::new(compressed)
+                .take(orig_length as u64)
+                .read_to_end(&mut table)
+                .map_err(|_| WoffError::Decompression)?;
+            table
+        } else if comp_length == orig_length {
+            compressed.to_vec()
+        } else {
+            return Err(WoffError::Invalid);
+        };
+        if table.len() != orig_length {
+            return Err(WoffError::Decompression);
+        }
+        tables.push((tag, orig_checksum, table));
+    }
+    write_sfnt(flavor, tables)
+}
+
+const KNOWN_TAGS: [&[u8; 4]; 63] = [
+    b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post",
+    b"cvt ", b"fpgm", b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT",
+    b"EBLC", b"gasp", b"hdmx", b"kern", b"LTSH", b"PCLT", b"VDMX", b"vhea",
+    b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC", b"JSTF", b"MATH",
+    b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+    b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar",
+    b"gvar", b"hsty", b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop",
+    b"trak", b"Zapf", b"Silf", b"Glat", b"Gloc", b"Feat", b"Sill",
+];
+
+struct Woff2Table {
+    tag: u32,
+    transformed: bool,
+    orig_length: usize,
+    /// The range of the table in the decompressed data.
+    start: usize,
+    end: usize,
+}
+
+fn decode_woff2(data: &[u8]) -> Result<Vec<u8>, WoffError> {
+    let mut header = Reader::new(data);
+    header.u32()?;
+    let flavor = header.u32()?;
+    header.u32()?;
+    let num_tables = header.u16()?;
+    header.u16()?;
+    header.u32()?;
+    let total_compressed_size = header.u32()? as usize;
+    header.skip(48 - 24)?;
+
+    if flavor == TTC_FLAVOR {
+        return Err(WoffError::Collection);
+    }
+
+    let mut tables = Vec::with_capacity(num_tables as usize);
+    let mut start = 0usize;
+    for _ in 0 .. num_tables {
+        let flags = header.u8()?;
+        let tag = match flags & 0x3F {
+            63 => header.u32()?,
+            index => u32::from_be_bytes(*KNOWN_TAGS[index as usize]),
+        };
+        let version = flags >> 6;
+        let orig_length = header.base128()? as usize;
+        // The null transform of glyf and loca is version 3, the one of the
+        // other tables is version 0.
+        let transformed = if tag == TAG_GLYF || tag == TAG_LOCA { version == 0 } else { version != 0 };
+        let length = if transformed { header.base128()? as usize } else { orig_length };
+        if transformed && tag != TAG_GLYF && tag != TAG_LOCA && tag != TAG_HMTX {
+            return Err(WoffError::Invalid);
+        }
+        let end = start.checked_add(length).ok_or(WoffError::Invalid)?;
+        if end > MAX_DECODED_SIZE || orig_length > MAX_DECODED_SIZE {
+            return Err(WoffError::Invalid);
+        }
+        tables.push(Woff2Table { tag, transformed, orig_length, start, end });
+        start = end;
+    }
+
+    let compressed = header.bytes(total_compressed_size)?;
+    let mut stream = Vec::new();
+    brotli_decompressor::Decompressor::new(compressed, 4096)
+        .take(start as u64)
+        .read_to_end(&mut stream)
+        .map_err(|_| WoffError::Decompression)?;
+    if stream.len() != start {
+        return Err(WoffError::Decompression);
+    }
+
+    let table_data = |tag: u32| tables.iter().find(|t| t.tag == tag).map(|t| &stream[t.start .. t.end]);
+
+    let mut output = Vec::with_capacity(tables.len());
+    let mut x_mins = None;
+    if let Some(glyf) = tables.iter().find(|t| t.tag == TAG_GLYF && t.transformed) {
+        let loca = tables.iter().find(|t| t.tag == TAG_LOCA).ok_or(WoffError::Invalid)?;
+        let glyphs = reconstruct_glyf(&stream[glyf.start .. glyf.end])?;
+        if glyphs.loca.len() != loca.orig_length {
+            return Err(WoffError::Invalid);
+        }
+        output.push((TAG_GLYF, checksum(&glyphs.glyf), glyphs.glyf));
+        output.push((TAG_LOCA, checksum(&glyphs.loca), glyphs.loca));
+        x_mins = Some(glyphs.x_mins);
+    }
+
+    for table in &tables {
+        if table.tag == TAG_GLYF || table.tag == TAG_LOCA {
+            if table.transformed {
+                if x_mins.is_none() {
+                    // A transformed loca table without a transformed glyf
+                    // table to rebuild it from.
+                    return Err(WoffError::Invalid);
+                }
+                continue;
+            }
+            if x_mins.is_some() {
+                // glyf and loca are transformed together.
+                return Err(WoffError::Invalid);
+            }
+        }
+        let mut data = if table.transformed {
+            // Only hmtx is left, which needs the glyph bounding boxes.
+            let x_mins = x_mins.as_ref().ok_or(WoffError::Invalid)?;
+            let hhea = table_data(TAG_HHEA).ok_or(WoffError::Invalid)?;
+            let num_h_metrics = Reader { data: hhea, pos: 34 }.u16()?;
+            reconstruct_hmtx(&stream[table.start .. table.end], num_h_metrics, x_mins)?
+        } else {
+            stream[table.start .. table.end].to_vec()
+        };
+        if table.tag == TAG_HEAD {
+            // The adjustment is recomputed once the font is laid out.
+            data.get_mut(8 .. 12).ok_or(WoffError::Truncated)?.fill(0);
+        }
+        output.push((table.tag, checksum(&data), data));
+    }
+
+    if let Some(maxp) = table_data(TAG_MAXP) {
+        let num_glyphs = Reader { data: maxp, pos: 4 }.u16()? as usize;
+        if x_mins.as_ref().map_or(false, |x_mins| x_mins.len() != num_glyphs) {
+            return Err(WoffError::Invalid);
+        }
+    }
+
+    let num_output_tables = output.len();
+    let mut sfnt = write_sfnt(flavor, output)?;
+    // Set head.checkSumAdjustment so that the font sums to the magic number.
+    let head_offset = {
+        let mut directory = Reader { data: &sfnt, pos: 12 };
+        let mut offset = None;
+        for _ in 0 .. num_output_tables {
+            let tag = directory.u32()?;
+            directory.u32()?;
+            let table_offset = directory.u32()? as usize;
+            directory.u32()?;
+            if tag == TAG_HEAD {
+                offset = Some(table_offset);
+            }
+        }
+        offset
+    };
+    if let Some(offset) = head_offset {
+        let adjustment = 0xB1B0_AFBAu32.wrapping_sub(checksum(&sfnt));
+        sfnt[offset + 8 .. offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    Ok(sfnt)
+}
+
+struct Glyphs {
+    glyf: Vec<u8>,
+    loca: Vec<u8>,
+    x_mins: Vec<i16>,
+}
+
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+
+const ON_CURVE_POINT: u8 = 0x01;
+const OVERLAP_SIMPLE: u8 = 0x40;
+
+/// Decodes the coordinate delta of a point of a simple glyph.
+fn decode_triplet(flag: u8, glyph_stream: &mut Reader) -> Result<(i32, i32), WoffError> {
+    fn with_sign(flag: u8, value: i32) -> i32 {
+        if flag & 1 != 0 { value } else { -value }
+    }
+    let flag = flag & 0x7F;
+    let (dx, dy) = if flag < 10 {
+        let b0 = glyph_stream.u8()? as i32;
+        (0, with_sign(flag, (((flag & 14) as i32) << 7) + b0))
+    } else if flag < 20 {
+        let b0 = glyph_stream.u8()? as i32;
+        (with_sign(flag, ((((flag - 10) & 14) as i32) << 7) + b0), 0)
+    } else if flag < 84 {
+        let b0 = (flag - 20) as i32;
+        let b1 = glyph_stream.u8()? as i32;
+        (
+            with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)),
+            with_sign(flag >> 1, 1 + ((b0 & 0x0C) << 2) + (b1 & 0x0F)),
+        )
+    } else if flag < 120 {
+        let b0 = (flag - 84) as i32;
+        let b1 = glyph_stream.u8()? as i32;
+        let b2 = glyph_stream.u8()? as i32;
+        (
+            with_sign(flag, 1 + ((b0 / 12) << 8) + b1),
+            with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + b2),
+        )
+    } else if flag < 124 {
+        let b = glyph_stream.bytes(3)?;
+        let (b0, b1, b2) = (b[0] as i32, b[1] as i32, b[2] as i32);
+        (
+            with_sign(flag, (b0 << 4) + (b1 >> 4)),
+            with_sign(flag >> 1, ((b1 & 0x0F) << 8) + b2),
+        )
+    } else {
+        let b = glyph_stream.bytes(4)?;
+        (
+            with_sign(flag, ((b[0] as i32) << 8) + b[1] as i32),
+            with_sign(flag >> 1, ((b[2] as i32) << 8) + b[3] as i32),
+        )
+    };
+    Ok((dx, dy))
+}
+
+fn push_i16(data: &mut Vec<u8>, value: i16) {
+    data.extend_from_slice(&value.to_be_bytes());
+}
+
+fn push_u16(data: &mut Vec<u8>, value: u16) {
+    data.extend_from_slice(&value.to_be_bytes());
+}
+
+/// Rebuilds the glyf and loca tables from a transformed glyf table.
+fn reconstruct_glyf(data: &[u8]) -> Result<Glyphs, WoffError> {
+    let mut header = Reader::new(data);
+    header.u16()?;
+    let option_flags = header.u16()?;
+    let num_glyphs = header.u16()? as usize;
+    let index_format = header.u16()?;
+    let mut stream_sizes = [0usize; 7];
+    for size in &mut stream_sizes {
+        *size = header.u32()? as usize;
+    }
+
+    let mut streams = Vec::with_capacity(7);
+    for &size in &stream_sizes {
+        streams.push(Reader::new(header.bytes(size)?));
+    }
+    let overlap_bitmap = if option_flags & 1 != 0 {
+        Some(header.bytes((num_glyphs + 7) / 8)?)
+    } else {
+        None
+    };
+    let mut streams = streams.into_iter();
+    let mut n_contour_stream = streams.next().unwrap();
+    let mut n_points_stream = streams.next().unwrap();
+    let mut flag_stream = streams.next().unwrap();
+    let mut glyph_stream = streams.next().unwrap();
+    let mut composite_stream = streams.next().unwrap();
+    let mut bbox_stream = streams.next().unwrap();
+    let mut instruction_stream = streams.next().unwrap();
+
+    let bbox_bitmap = bbox_stream.bytes(((num_glyphs + 31) / 32) * 4)?;
+    let has_bbox = |glyph: usize| bbox_bitmap[glyph / 8] & (0x80 >> (glyph % 8)) != 0;
+
+    let mut glyf = Vec::new();
+    let mut offsets = Vec::with_capacity(num_glyphs + 1);
+    let mut x_mins = Vec::with_capacity(num_glyphs);
+    for glyph in 0 .. num_glyphs {
+        offsets.push(glyf.len());
+        let n_contours = n_contour_stream.i16()?;
+        if n_contours == 0 {
+            if has_bbox(glyph) {
+                return Err(WoffError::Invalid);
+            }
+            x_mins.push(0);
+            continue;
+        }
+
+        let bbox = if has_bbox(glyph) {
+            Some([bbox_stream.i16()?, bbox_stream.i16()?, bbox_stream.i16()?, bbox_stream.i16()?])
+        } else {
+            None
+        };
+
+        if n_contours > 0 {
+            let mut end_points = Vec::with_capacity(n_contours as usize);
+            let mut num_points = 0usize;
+            for _ in 0 .. n_contours {
+                num_points += n_points_stream.u255()? as usize;
+                end_points.push(num_points.checked_sub(1).ok_or(WoffError::Invalid)?);
+            }
+            if num_points > 0xFFFF {
+                return Err(WoffError::Invalid);
+            }
+
+            let flags = flag_stream.bytes(num_points)?;
+            let mut points = Vec::with_capacity(num_points);
+            let (mut x, mut y) = (0i32, 0i32);
+            for &flag in flags {
+                let (dx, dy) = decode_triplet(flag, &mut glyph_stream)?;
+                x += dx;
+                y += dy;
+                points.push((x, y, flag & 0x80 == 0));
+            }
+            let instruction_length = glyph_stream.u255()?;
+            let instructions = instruction_stream.bytes(instruction_length as usize)?;
+
+            let [x_min, y_min, x_max, y_max] = match bbox {
+                Some(bbox) => bbox,
+                None => {
+                    let x_min = points.iter().map(|p| p.0).min().unwrap_or(0);
+                    let y_min = points.iter().map(|p| p.1).min().unwrap_or(0);
+                    let x_max = points.iter().map(|p| p.0).max().unwrap_or(0);
+                    let y_max = points.iter().map(|p| p.1).max().unwrap_or(0);
+                    [x_min as i16, y_min as i16, x_max as i16, y_max as i16]
+                }
+            };
+            x_mins.push(x_min);
+
+            push_i16(&mut glyf, n_contours);
+            for value in [x_min, y_min, x_max, y_max] {
+                push_i16(&mut glyf, value);
+            }
+            for end_point in end_points {
+                push_u16(&mut glyf, end_point as u16);
+            }
+            push_u16(&mut glyf, instruction_length);
+            glyf.extend_from_slice(instructions);
+            // Coordinates are written as 16 bit deltas, without the short
+            // and repeat encodings.
+            let overlap = overlap_bitmap.map_or(false, |bitmap| bitmap[glyph / 8] & (0x80 >> (glyph % 8)) != 0);
+            for (i, point) in points.iter().enumerate() {
+                let mut flag = if point.2 { ON_CURVE_POINT } else { 0 };
+                if i == 0 && overlap {
+                    flag |= OVERLAP_SIMPLE;
+                }
+                glyf.push(flag);
+            }
+            let mut previous = 0;
+            for point in &points {
+                push_i16(&mut glyf, (point.0 - previous) as i16);
+                previous = point.0;
+            }
+            previous = 0;
+            for point in &points {
+                push_i16(&mut glyf, (point.1 - previous) as i16);
+                previous = point.1;
+            }
+        } else if n_contours == -1 {
+            let bbox = bbox.ok_or(WoffError::Invalid)?;
+            x_mins.push(bbox[0]);
+
+            let start = composite_stream.pos;
+            let mut have_instructions = false;
+            loop {
+                let flags = composite_stream.u16()?;
+                have_instructions |= flags & WE_HAVE_INSTRUCTIONS != 0;
+                let mut length = 2 + if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 };
+                if flags & WE_HAVE_A_SCALE != 0 {
+                    length += 2;
+                } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                    length += 4;
+                } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                    length += 8;
+                }
+                composite_stream.skip(length)?;
+                if flags & MORE_COMPONENTS == 0 {
+                    break;
+                }
+            }
+            let components = &composite_stream.data[start .. composite_stream.pos];
+
+            push_i16(&mut glyf, -1);
+            for value in bbox {
+                push_i16(&mut glyf, value);
+            }
+            glyf.extend_from_slice(components);
+            if have_instructions {
+                let instruction_length = glyph_stream.u255()?;
+                push_u16(&mut glyf, instruction_length);
+                glyf.extend_from_slice(instruction_stream.bytes(instruction_length as usize)?);
+            }
+        } else {
+            return Err(WoffError::Invalid);
+        }
+        pad4(&mut glyf);
+    }
+    offsets.push(glyf.len());
+
+    let mut loca = Vec::with_capacity(offsets.len() * 4);
+    for offset in offsets {
+        if index_format == 0 {
+            push_u16(&mut loca, u16::try_from(offset / 2).map_err(|_| WoffError::Invalid)?);
+        } else {
+            loca.extend_from_slice(&(offset as u32).to_be_bytes());
+        }
+    }
+
+    Ok(Glyphs { glyf, loca, x_mins })
+}
+
+/// Rebuilds the hmtx table from a transformed one, whose left side bearings
+/// may be left out when they equal the glyphs' xMin.
+fn reconstruct_hmtx(data: &[u8], num_h_metrics: u16, x_mins: &[i16]) -> Result<Vec<u8>, WoffError> {
+    let num_h_metrics = num_h_metrics as usize;
+    let num_glyphs = x_mins.len();
+    if num_h_metrics == 0 || num_h_metrics > num_glyphs {
+        return Err(WoffError::Invalid);
+    }
+
+    let mut reader = Reader::new(data);
+    let flags = reader.u8()?;
+    let mut advances = Vec::with_capacity(num_h_metrics);
+    for _ in 0 .. num_h_metrics {
+        advances.push(reader.u16()?);
+    }
+    let mut lsbs = Vec::with_capacity(num_glyphs);
+    for glyph in 0 .. num_h_metrics {
+        lsbs.push(if flags & 1 != 0 { x_mins[glyph] } else { reader.i16()? });
+    }
+    for glyph in num_h_metrics .. num_glyphs {
+        lsbs.push(if flags & 2 != 0 { x_mins[glyph] } else { reader.i16()? });
+    }
+
+    let mut hmtx = Vec::with_capacity(num_h_metrics * 2 + num_glyphs * 2);
+    for (glyph, lsb) in lsbs.into_iter().enumerate() {
+        if glyph < num_h_metrics {
+            push_u16(&mut hmtx, advances[glyph]);
+        }
+        push_i16(&mut hmtx, lsb);
+    }
+    Ok(hmtx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn push_u32(data: &mut Vec<u8>, value: u32) {
+        data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn tag(tag: &[u8; 4]) -> u32 {
+        u32::from_be_bytes(*tag)
+    }
+
+    fn head_table() -> Vec<u8> {
+        let mut head: Vec<u8> = (0 .. 54).collect();
+        // A checkSumAdjustment that decoding WOFF2 recomputes.
+        head[8 .. 12].copy_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF]);
+        head
+    }
+
+    fn test_tables() -> Vec<(u32, Vec<u8>)> {
+        vec![
+            (tag(b"head"), head_table()),
+            (tag(b"name"), b"WebRender ".iter().cycle().take(200).cloned().collect()),
+            (tag(b"cmap"), vec![1, 2, 3, 4, 5, 6, 7]),
+        ]
+    }
+
+    /// Returns the tables of sfnt data, in the order of its directory.
+    fn sfnt_tables(sfnt: &[u8]) -> Vec<(u32, Vec<u8>)> {
+        let mut reader = Reader::new(sfnt);
+        reader.u32().unwrap();
+        let num_tables = reader.u16().unwrap();
+        reader.skip(6).unwrap();
+        (0 .. num_tables).map(|_| {
+            let tag = reader.u32().unwrap();
+            reader.u32().unwrap();
+            let offset = reader.u32().unwrap() as usize;
+            let length = reader.u32().unwrap() as usize;
+            assert_eq!(offset % 4, 0);
+            (tag, sfnt[offset .. offset + length].to_vec())
+        }).collect()
+    }
+
+    fn sorted(mut tables: Vec<(u32, Vec<u8>)>) -> Vec<(u32, Vec<u8>)> {
+        tables.sort_by_key(|&(tag, _)| tag);
+        tables
+    }
+
+    /// Encodes tables as WOFF, compressing the ones that get smaller.
+    fn encode_woff(tables: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        let mut directory = Vec::new();
+        let mut table_data = Vec::new();
+        let data_start = 44 + 20 * tables.len();
+        for &(tag, ref data) in tables {
+            let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).unwrap();
+            let compressed = encoder.finish().unwrap();
+            let stored = if compressed.len() < data.len() { compressed } else { data.clone() };
+            // Tables are padded to 4 bytes, except the last one, so that
+            // any truncation cuts table data.
+            pad4(&mut table_data);
+            push_u32(&mut directory, tag);
+            push_u32(&mut directory, (data_start + table_data.len()) as u32);
+            push_u32(&mut directory, stored.len() as u32);
+            push_u32(&mut directory, data.len() as u32);
+            push_u32(&mut directory, checksum(data));
+            table_data.extend_from_slice(&stored);
+        }
+
+        let mut woff = Vec::new();
+        push_u32(&mut woff, WOFF_SIGNATURE);
+        push_u32(&mut woff, 0x0001_0000);
+        push_u32(&mut woff, (data_start + table_data.len()) as u32);
+        push_u16(&mut woff, tables.len() as u16);
+        push_u16(&mut woff, 0);
+        // totalSfntSize, the version, and the metadata and private blocks.
+        woff.extend_from_slice(&[0; 28]);
+        woff.extend_from_slice(&directory);
+        woff.extend_from_slice(&table_data);
+        woff
+    }
+
+    fn push_base128(data: &mut Vec<u8>, value: u32) {
+        let mut bytes = vec![(value & 0x7F) as u8];
+        let mut value = value >> 7;
+        while value != 0 {
+            bytes.push((value & 0x7F) as u8 | 0x80);
+            value >>= 7;
+        }
+        data.extend(bytes.iter().rev());
+    }
+
+    /// Wraps data in a brotli stream of one uncompressed meta-block.
+    fn brotli_stored(data: &[u8]) -> Vec<u8> {
+        assert!(!data.is_empty() && data.len() <= 0x10000);
+        // A 16 bit window, a meta-block of four length nibbles that isn't
+        // the last one, and the uncompressed flag, padded to whole bytes.
+        let header = ((data.len() as u32 - 1) << 4) | (1 << 20);
+        let mut stream = header.to_le_bytes()[.. 3].to_vec();
+        stream.extend_from_slice(data);
+        // An empty last meta-block.
+        stream.push(0x03);
+        stream
+    }
+
+    /// A table of a WOFF2 font: its flags, original length, transformed
+    /// length if any, and its data as stored.
+    struct Woff2TestTable {
+        flags: u8,
+        orig_length: u32,
+        transform_length: Option<u32>,
+        data: Vec<u8>,
+    }
+
+    fn known_tag(tag: &[u8; 4]) -> u8 {
+        KNOWN_TAGS.iter().position(|known| *known == tag).unwrap() as u8
+    }
+
+    /// A table stored with the null transform.
+    fn woff2_table(tag: &[u8; 4], data: Vec<u8>) -> Woff2TestTable {
+        let version = if tag == b"glyf" || tag == b"loca" { 3 << 6 } else { 0 };
+        Woff2TestTable {
+            flags: version | known_tag(tag),
+            orig_length: data.len() as u32,
+            transform_length: None,
+            data,
+        }
+    }
+
+    fn encode_woff2(tables: &[Woff2TestTable]) -> Vec<u8> {
+        let mut directory = Vec::new();
+        let mut stream = Vec::new();
+        for table in tables {
+            directory.push(table.flags);
+            push_base128(&mut directory, table.orig_length);
+            if let Some(length) = table.transform_length {
+                push_base128(&mut directory, length);
+            }
+            stream.extend_from_slice(&table.data);
+        }
+        let compressed = brotli_stored(&stream);
+
+        let mut woff2 = Vec::new();
+        push_u32(&mut woff2, WOFF2_SIGNATURE);
+        push_u32(&mut woff2, 0x0001_0000);
+        push_u32(&mut woff2, (48 + directory.len() + compressed.len()) as u32);
+        push_u16(&mut woff2, tables.len() as u16);
+        push_u16(&mut woff2, 0);
+        push_u32(&mut woff2, 0);
+        push_u32(&mut woff2, compressed.len() as u32);
+        // The version, and the metadata and private blocks.
+        woff2.extend_from_slice(&[0; 24]);
+        woff2.extend_from_slice(&directory);
+        woff2.extend_from_slice(&compressed);
+        woff2
+    }
+
+    /// A transformed glyf table of one triangle, (0, 0), (100, 0), (50, 100),
+    /// and the glyf and loca tables it decodes to.
+    fn transformed_triangle() -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let n_contour_stream = vec![0, 1];
+        let n_points_stream = vec![3];
+        let flag_stream = vec![127, 127, 126];
+        let glyph_stream = vec![0, 0, 0, 0, 0, 100, 0, 0, 0, 50, 0, 100, 0];
+        let bbox_stream = vec![0; 4];
+        let streams = [n_contour_stream, n_points_stream, flag_stream, glyph_stream, vec![], bbox_stream, vec![]];
+
+        let mut transformed = Vec::new();
+        push_u16(&mut transformed, 0);
+        push_u16(&mut transformed, 0);
+        push_u16(&mut transformed, 1);
+        push_u16(&mut transformed, 0);
+        for stream in &streams {
+            push_u32(&mut transformed, stream.len() as u32);
+        }
+        for stream in &streams {
+            transformed.extend_from_slice(stream);
+        }
+
+        let mut glyf = Vec::new();
+        for value in [1, 0, 0, 100, 100, 2, 0] {
+            push_i16(&mut glyf, value);
+        }
+        glyf.extend_from_slice(&[ON_CURVE_POINT; 3]);
+        for value in [0, 100, -50, 0, 0, 100] {
+            push_i16(&mut glyf, value);
+        }
+        pad4(&mut glyf);
+        let mut loca = Vec::new();
+        push_u16(&mut loca, 0);
+        push_u16(&mut loca, glyf.len() as u16 / 2);
+        (transformed, glyf, loca)
+    }
+
+    #[test]
+    fn woff_round_trip() {
+        let tables = test_tables();
+        let woff = encode_woff(&tables);
+        assert!(is_woff(&woff));
+        let sfnt = decode(&woff).unwrap();
+        assert_eq!(sfnt_tables(&sfnt), sorted(tables));
+    }
+
+    #[test]
+    fn woff2_round_trip() {
+        let tables = test_tables();
+        let woff2 = encode_woff2(&[
+            woff2_table(b"head", tables[0].1.clone()),
+            woff2_table(b"name", tables[1].1.clone()),
+            woff2_table(b"cmap", tables[2].1.clone()),
+        ]);
+        assert!(is_woff(&woff2));
+        let sfnt = decode(&woff2).unwrap();
+
+        let decoded = sfnt_tables(&sfnt);
+        let mut expected = sorted(tables);
+        let head = expected.iter_mut().find(|&&mut (tag, _)| tag == TAG_HEAD).unwrap();
+        head.1[8 .. 12].copy_from_slice(&decoded[1].1[8 .. 12]);
+        assert_eq!(decoded, expected);
+        assert_eq!(checksum(&sfnt), 0xB1B0_AFBA);
+    }
+
+    /// A WOFF2 font of the triangle, and its glyf, loca and maxp tables.
+    fn triangle_woff2() -> (Vec<u8>, Vec<(u32, Vec<u8>)>) {
+        let (transformed, glyf, loca) = transformed_triangle();
+        let maxp = vec![0, 0, 0x50, 0, 0, 1];
+        let woff2 = encode_woff2(&[
+            Woff2TestTable {
+                flags: known_tag(b"glyf"),
+                orig_length: glyf.len() as u32,
+                transform_length: Some(transformed.len() as u32),
+                data: transformed,
+            },
+            Woff2TestTable {
+                flags: known_tag(b"loca"),
+                orig_length: loca.len() as u32,
+                transform_length: Some(0),
+                data: Vec::new(),
+            },
+            woff2_table(b"maxp", maxp.clone()),
+        ]);
+        (woff2, vec![(TAG_GLYF, glyf), (TAG_LOCA, loca), (TAG_MAXP, maxp)])
+    }
+
+    #[test]
+    fn woff2_rebuilds_glyf_and_loca() {
+        let (woff2, tables) = triangle_woff2();
+        let sfnt = decode(&woff2).unwrap();
+        assert_eq!(sfnt_tables(&sfnt), tables);
+    }
+
+    #[test]
+    fn woff2_rejects_transformed_loca_without_glyf() {
+        let woff2 = encode_woff2(&[
+            woff2_table(b"head", head_table()),
+            Woff2TestTable {
+                flags: known_tag(b"loca"),
+                orig_length: 4,
+                transform_length: Some(0),
+                data: Vec::new(),
+            },
+        ]);
+        assert_eq!(decode(&woff2), Err(WoffError::Invalid));
+    }
+
+    #[test]
+    fn rejects_oversized_tables() {
+        let mut woff = encode_woff(&test_tables());
+        // The original length of the first table.
+        woff[44 + 12 .. 44 + 16].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(decode(&woff), Err(WoffError::Invalid));
+
+        let woff2 = encode_woff2(&[Woff2TestTable {
+            flags: known_tag(b"name"),
+            orig_length: u32::MAX >> 4,
+            transform_length: None,
+            data: vec![0; 4],
+        }]);
+        assert_eq!(decode(&woff2), Err(WoffError::Invalid));
+    }
+
+    #[test]
+    fn write_sfnt_limits_table_count() {
+        let tables = |count: u32| (0 .. count).map(|tag| (tag, 0, Vec::new())).collect();
+        assert_eq!(write_sfnt(0x0001_0000, tables(0)), Err(WoffError::Invalid));
+        assert!(write_sfnt(0x0001_0000, tables(4095)).is_ok());
+        assert_eq!(write_sfnt(0x0001_0000, tables(4096)), Err(WoffError::Invalid));
+    }
+
+    #[test]
+    fn malformed_input() {
+        let tables = test_tables();
+        let woff = encode_woff(&tables);
+        let woff2 = encode_woff2(&[
+            woff2_table(b"head", tables[0].1.clone()),
+            woff2_table(b"name", tables[1].1.clone()),
+        ]);
+        let (triangle, _) = triangle_woff2();
+        for data in [woff, woff2, triangle] {
+            // Truncated data fails to decode, and corrupt data may decode or
+            // not, but doesn't panic.
+            for len in 0 .. data.len() {
+                assert!(decode(&data[.. len]).is_err());
+            }
+            for i in 0 .. data.len() {
+                let mut corrupt = data.clone();
+                corrupt[i] ^= 0xFF;
+                let _ = decode(&corrupt);
+            }
+        }
+    }
+}
@@ -126,8 +126,22 @@ else:
 # For CI purposes, don't build if WRENCH_HEADLESS_TARGET is set.
 # This environment variable is used to point to the location of a cross-compiled
 # wrench for the CI on some platforms.
+# The glyph backend is selected at build time, so `--glyph-backend` also
+# picks the cargo features to build wrench with.
+glyph_backend = 'native'
+for i, arg in enumerate(sys.argv):
+    if arg == '--glyph-backend' and i + 1 < len(sys.argv):
+        glyph_backend = sys.argv[i + 1]
+    elif arg.startswith('--glyph-backend='):
+        glyph_backend = arg.split('=', 1)[1]
+
 if not wrench_headless_target:
-    build_cmd = ['cargo', 'build'] + extra_flags + ['--verbose', '--features', 'headless']
+    build_cmd = ['cargo', 'build'] + extra_flags + ['--verbose']
+    if glyph_backend == 'native':
+        build_cmd += ['--features', 'headless']
+    else:
+        build_cmd += ['--no-default-features', '--features',
+                      'headless,env_logger,font_backend_' + glyph_backend]
     if optimized_build():
         build_cmd += ['--release']
     subprocess.check_call(build_cmd)
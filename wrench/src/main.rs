@@ -16,6 +16,7 @@ mod blob;
 mod egl;
 mod parse_function;
 mod perf;
+mod play_recording;
 mod png;
 mod premultiply;
 mod rawtest;
@@ -62,8 +63,8 @@ pub static mut CURRENT_FRAME_NUMBER: u32 = 0;
 pub struct HeadlessContext {
     width: i32,
     height: i32,
-    _context: osmesa_sys::OSMesaContext,
-    _buffer: Vec<u32>,
+    context: osmesa_sys::OSMesaContext,
+    buffer: Vec<u32>,
 }
 
 #[cfg(not(feature = "headless"))]
@@ -92,12 +93,30 @@ impl HeadlessContext {
 
         assert!(!context.is_null());
 
-        let mut buffer = vec![0; (width * height) as usize];
+        let mut headless = HeadlessContext {
+            width: 0,
+            height: 0,
+            context,
+            buffer: Vec::new(),
+        };
+        headless.resize(width, height);
+        headless
+    }
+
+    #[cfg(not(feature = "headless"))]
+    fn new(width: i32, height: i32) -> Self {
+        HeadlessContext { width, height }
+    }
+
+    /// Reallocates the offscreen color buffer that is rendered to.
+    #[cfg(feature = "headless")]
+    fn resize(&mut self, width: i32, height: i32) {
+        self.buffer = vec![0; (width * height) as usize];
 
         unsafe {
             let ret = osmesa_sys::OSMesaMakeCurrent(
-                context,
-                buffer.as_mut_ptr() as *mut _,
+                self.context,
+                self.buffer.as_mut_ptr() as *mut _,
                 gl::UNSIGNED_BYTE,
                 width,
                 height,
@@ -105,17 +124,16 @@ impl HeadlessContext {
             assert!(ret != 0);
         };
 
-        HeadlessContext {
-            width,
-            height,
-            _context: context,
-            _buffer: buffer,
-        }
+        self.width = width;
+        self.height = height;
     }
 
+    /// Without OSMesa, headless rendering goes through SWGL, whose default
+    /// framebuffer is resized on every update.
     #[cfg(not(feature = "headless"))]
-    fn new(width: i32, height: i32) -> Self {
-        HeadlessContext { width, height }
+    fn resize(&mut self, width: i32, height: i32) {
+        self.width = width;
+        self.height = height;
     }
 
     #[cfg(feature = "headless")]
@@ -215,7 +233,9 @@ impl WindowWrapper {
             WindowWrapper::Angle(ref mut window, ..) => {
                 window.set_inner_size(LogicalSize::new(size.width as f64, size.height as f64))
             },
-            WindowWrapper::Headless(..) => unimplemented!(), // requites Glutin update
+            WindowWrapper::Headless(ref mut context, ..) => {
+                context.resize(size.width, size.height)
+            },
         }
     }
 
@@ -507,6 +527,17 @@ fn reftest<'a>(
         reftest_options.allow_max_difference = allow_max_diff.parse().unwrap_or(1);
         reftest_options.allow_num_differences = dim.width as usize * dim.height as usize;
     }
+    if let Some(glyph_backend) = subargs.value_of("glyph_backend") {
+        let built_with = reftest::glyph_backend();
+        if glyph_backend != built_with {
+            panic!(
+                "Requested the {} glyph backend, but wrench was built with {}. Rebuild with --features font_backend_{}.",
+                glyph_backend,
+                built_with,
+                glyph_backend,
+            );
+        }
+    }
     let num_failures = ReftestHarness::new(&mut wrench, window, &rx)
         .run(&base_manifest, specific_reftest, &reftest_options);
     wrench.shut_down(rx);
@@ -661,7 +692,12 @@ pub fn main() {
         }
     };
 
-    let software = args.is_present("software");
+    // Without OSMesa, a headless GL context can only come from SWGL.
+    let software = args.is_present("software") ||
+        (events_loop.is_none() && cfg!(not(feature = "headless")));
+    if software && cfg!(not(feature = "software")) {
+        panic!("Software and headless rendering require wrench to be built with the `software` or `headless` feature");
+    }
 
     // On Android we can only create an OpenGL context when we have a
     // native_window handle, so wait here until we are resumed and have a
@@ -689,7 +725,7 @@ pub fn main() {
     let dim = window.get_inner_size();
 
     let needs_frame_notifier = args.subcommand_name().map_or(false, |name| {
-        ["perf", "reftest", "png", "rawtest", "test_invalidation"].contains(&name)
+        ["perf", "reftest", "png", "rawtest", "test_invalidation", "play_recording"].contains(&name)
     });
     let (notifier, rx) = if needs_frame_notifier {
         let (notifier, rx) = create_notifier();
@@ -732,7 +768,7 @@ pub fn main() {
         render(
             &mut wrench,
             &mut window,
-            events_loop.as_mut().expect("`wrench show` is not supported in headless mode"),
+            events_loop.as_mut().expect("`wrench show` is not supported in headless mode, use `wrench png`"),
             subargs,
             no_block,
             no_batch,
@@ -743,9 +779,11 @@ pub fn main() {
             Some("gpu-cache") => png::ReadSurface::GpuCache,
             _ => panic!("Unknown surface argument value")
         };
-        let output_path = subargs.value_of("OUTPUT").map(PathBuf::from);
-        let reader = YamlFrameReader::new_from_args(subargs);
-        png::png(&mut wrench, surface, &mut window, reader, rx.unwrap(), output_path);
+        let output_path = subargs.value_of("OUTPUT").map(PathBuf::from).unwrap_or_else(|| {
+            subargs.value_of("INPUT").map(PathBuf::from).unwrap().with_extension("png")
+        });
+        let thing = load_input(&mut wrench, subargs);
+        png::png(&mut wrench, surface, &mut window, thing, rx.unwrap(), output_path);
     } else if let Some(subargs) = args.subcommand_matches("reftest") {
         // Exit with an error code in order to ensure the CI job fails.
         process::exit(reftest(wrench, &mut window, subargs, rx.unwrap()) as _);
@@ -791,11 +829,24 @@ pub fn main() {
         );
 
         harness.run();
+    } else if let Some(subargs) = args.subcommand_matches("play_recording") {
+        let path = PathBuf::from(subargs.value_of("INPUT").unwrap());
+        play_recording::play_recording(
+            &mut wrench,
+            &mut window,
+            rx.unwrap(),
+            &path,
+            subargs.is_present("fast"),
+        );
     } else if let Some(subargs) = args.subcommand_matches("compare_perf") {
         let first_filename = subargs.value_of("first_filename").unwrap();
         let second_filename = subargs.value_of("second_filename").unwrap();
-        perf::compare(first_filename, second_filename);
-        return;
+        let threshold = subargs
+            .value_of("threshold")
+            .map_or(perf::DEFAULT_REGRESSION_THRESHOLD, |s| s.parse().expect("Invalid threshold"));
+        let regressions = perf::compare(first_filename, second_filename, threshold);
+        // Exit with an error code so that perf gates can fail on regressions.
+        process::exit((regressions > 0) as _);
     } else if args.subcommand_matches("test_init").is_some() {
         // Wrench::new() unwraps the Renderer initialization, so if
         // we reach this point then we have initialized successfully.
@@ -813,18 +864,12 @@ pub fn main() {
     process::exit(0);
 }
 
-fn render<'a>(
-    wrench: &mut Wrench,
-    window: &mut WindowWrapper,
-    events_loop: &mut winit::event_loop::EventLoop<()>,
-    subargs: &clap::ArgMatches,
-    no_block: bool,
-    no_batch: bool,
-) {
+/// Loads a YAML file, binary capture or capture sequence to render.
+fn load_input(wrench: &mut Wrench, subargs: &clap::ArgMatches) -> Box<dyn WrenchThing> {
     let input_path = subargs.value_of("INPUT").map(PathBuf::from).unwrap();
 
     // If the input is a directory, we are looking at a capture.
-    let mut thing = if input_path.join("scenes").as_path().is_dir() {
+    if input_path.join("scenes").as_path().is_dir() {
         let scene_id = subargs.value_of("scene-id").map(|z| z.parse::<u32>().unwrap());
         let frame_id = subargs.value_of("frame-id").map(|z| z.parse::<u32>().unwrap());
         Box::new(CapturedSequence::new(
@@ -845,7 +890,18 @@ fn render<'a>(
             }
             _ => panic!("Tried to render with an unknown file type."),
         }
-    };
+    }
+}
+
+fn render<'a>(
+    wrench: &mut Wrench,
+    window: &mut WindowWrapper,
+    events_loop: &mut winit::event_loop::EventLoop<()>,
+    subargs: &clap::ArgMatches,
+    no_block: bool,
+    no_batch: bool,
+) {
+    let mut thing = load_input(wrench, subargs);
 
     window.update(wrench);
     thing.do_frame(wrench);
@@ -23,6 +23,10 @@ const COLOR_MAGENTA: &str = "\x1b[95m";
 const MIN_SAMPLE_COUNT: usize = 50;
 const SAMPLE_EXCLUDE_COUNT: usize = 10;
 
+/// Relative change, in percent, past which a timing is considered a regression
+/// by `compare`, unless specified otherwise.
+pub const DEFAULT_REGRESSION_THRESHOLD: f32 = 10.0;
+
 pub struct Benchmark {
     pub test: PathBuf,
 }
@@ -77,6 +81,9 @@ struct TestProfileRange {
     min: u64,
     avg: u64,
     max: u64,
+    // Missing from profiles saved by older versions of wrench.
+    #[serde(default)]
+    stddev: u64,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -91,22 +98,25 @@ struct TestProfile {
 impl TestProfile {
     fn csv_header() -> String {
         "name,\
-        backend_time_ns min, avg, max,\
-        composite_time_ns min, avg, max,\
-        paint_time_ns min, avg, max,\
+        backend_time_ns min, avg, max, stddev,\
+        composite_time_ns min, avg, max, stddev,\
+        paint_time_ns min, avg, max, stddev,\
         draw_calls\n".to_string()
     }
 
     fn convert_to_csv(&self) -> String {
         format!("{},\
-                 {},{},{},\
-                 {},{},{},\
-                 {},{},{},\
+                 {},{},{},{},\
+                 {},{},{},{},\
+                 {},{},{},{},\
                  {}\n",
                 self.name,
                 self.backend_time_ns.min,   self.backend_time_ns.avg,   self.backend_time_ns.max,
+                self.backend_time_ns.stddev,
                 self.composite_time_ns.min, self.composite_time_ns.avg, self.composite_time_ns.max,
+                self.composite_time_ns.stddev,
                 self.paint_time_ns.min,     self.paint_time_ns.avg,     self.paint_time_ns.max,
+                self.paint_time_ns.stddev,
                 self.draw_calls)
     }
 }
@@ -252,8 +262,8 @@ impl<'a> PerfHarness<'a> {
     }
 }
 
-// returns min, average, max, after removing the lowest and highest SAMPLE_EXCLUDE_COUNT
-// samples (each).
+// returns min, average, max and standard deviation, after removing the lowest and highest
+// SAMPLE_EXCLUDE_COUNT samples (each).
 fn extract_sample<F, T>(profiles: &mut [T], f: F) -> TestProfileRange
 where
     F: Fn(&T) -> u64,
@@ -262,15 +272,24 @@ where
     samples.sort_unstable();
     let useful_samples = &samples[SAMPLE_EXCLUDE_COUNT .. samples.len() - SAMPLE_EXCLUDE_COUNT];
     let total_time: u64 = useful_samples.iter().sum();
+    let avg = total_time / useful_samples.len() as u64;
+    let variance = useful_samples
+        .iter()
+        .map(|&sample| {
+            let delta = sample as f64 - avg as f64;
+            delta * delta
+        })
+        .sum::<f64>() / useful_samples.len() as f64;
     TestProfileRange {
         min: useful_samples[0],
-        avg: total_time / useful_samples.len() as u64,
-        max: useful_samples[useful_samples.len()-1]
+        avg,
+        max: useful_samples[useful_samples.len()-1],
+        stddev: variance.sqrt() as u64,
     }
 }
 
-fn select_color(base: f32, value: f32) -> &'static str {
-    let tolerance = base * 0.1;
+fn select_color(base: f32, value: f32, threshold: f32) -> &'static str {
+    let tolerance = base * threshold / 100.0;
     if (value - base).abs() < tolerance {
         COLOR_DEFAULT
     } else if value > base {
@@ -280,28 +299,38 @@ fn select_color(base: f32, value: f32) -> &'static str {
     }
 }
 
-pub fn compare(first_filename: &str, second_filename: &str) {
+/// Whether `value` is slower than `base` by more than `threshold` percent.
+fn is_regression(base: &TestProfileRange, value: &TestProfileRange, threshold: f32) -> bool {
+    value.avg as f32 > base.avg as f32 * (1.0 + threshold / 100.0)
+}
+
+/// Prints a comparison of two profiles, and returns the number of tests whose
+/// timings regressed by more than `threshold` percent.
+pub fn compare(first_filename: &str, second_filename: &str, threshold: f32) -> usize {
     let profile0 = Profile::load(first_filename);
     let profile1 = Profile::load(second_filename);
 
     let (set0, map0) = profile0.build_set_and_map_of_tests();
     let (set1, map1) = profile1.build_set_and_map_of_tests();
 
+    let mut regressions = Vec::new();
+
     print!("+------------------------------------------------");
-    println!("+--------------+------------------+------------------+");
+    println!("+--------------+------------------+------------------+------------------+");
     print!("|  Test name                                     ");
-    println!("| Draw Calls   | Composite (ms)   | Paint (ms)       |");
+    println!("| Draw Calls   | Backend (ms)     | Composite (ms)   | Paint (ms)       |");
     print!("+------------------------------------------------");
-    println!("+--------------+------------------+------------------+");
+    println!("+--------------+------------------+------------------+------------------+");
 
     for test_name in set0.symmetric_difference(&set1) {
         println!(
-            "| {}{:47}{}|{:14}|{:18}|{:18}|",
+            "| {}{:47}{}|{:14}|{:18}|{:18}|{:18}|",
             COLOR_MAGENTA,
             test_name,
             COLOR_DEFAULT,
             " -",
             " -",
+            " -",
             " -"
         );
     }
@@ -310,6 +339,9 @@ pub fn compare(first_filename: &str, second_filename: &str) {
         let test0 = &map0[test_name];
         let test1 = &map1[test_name];
 
+        let backend_time0 = test0.backend_time_ns.avg as f32 / 1000000.0;
+        let backend_time1 = test1.backend_time_ns.avg as f32 / 1000000.0;
+
         let composite_time0 = test0.composite_time_ns.avg as f32 / 1000000.0;
         let composite_time1 = test1.composite_time_ns.avg as f32 / 1000000.0;
 
@@ -322,19 +354,31 @@ pub fn compare(first_filename: &str, second_filename: &str) {
             std::cmp::Ordering::Less => COLOR_RED,
         };
 
-        let composite_time_color = select_color(composite_time0, composite_time1);
-        let paint_time_color = select_color(paint_time0, paint_time1);
+        let backend_time_color = select_color(backend_time0, backend_time1, threshold);
+        let composite_time_color = select_color(composite_time0, composite_time1, threshold);
+        let paint_time_color = select_color(paint_time0, paint_time1, threshold);
+
+        if is_regression(&test0.backend_time_ns, &test1.backend_time_ns, threshold) ||
+            is_regression(&test0.composite_time_ns, &test1.composite_time_ns, threshold) ||
+            is_regression(&test0.paint_time_ns, &test1.paint_time_ns, threshold)
+        {
+            regressions.push(test_name.clone());
+        }
 
         let draw_call_string = format!(" {} -> {}", test0.draw_calls, test1.draw_calls);
+        let backend_time_string = format!(" {:.2} -> {:.2}", backend_time0, backend_time1);
         let composite_time_string = format!(" {:.2} -> {:.2}", composite_time0, composite_time1);
         let paint_time_string = format!(" {:.2} -> {:.2}", paint_time0, paint_time1);
 
         println!(
-            "| {:47}|{}{:14}{}|{}{:18}{}|{}{:18}{}|",
+            "| {:47}|{}{:14}{}|{}{:18}{}|{}{:18}{}|{}{:18}{}|",
             test_name,
             draw_calls_color,
             draw_call_string,
             COLOR_DEFAULT,
+            backend_time_color,
+            backend_time_string,
+            COLOR_DEFAULT,
             composite_time_color,
             composite_time_string,
             COLOR_DEFAULT,
@@ -345,5 +389,12 @@ pub fn compare(first_filename: &str, second_filename: &str) {
     }
 
     print!("+------------------------------------------------");
-    println!("+--------------+------------------+------------------+");
+    println!("+--------------+------------------+------------------+------------------+");
+
+    regressions.sort();
+    for test_name in &regressions {
+        println!("{}REGRESSION{} {} (threshold {}%)", COLOR_RED, COLOR_DEFAULT, test_name, threshold);
+    }
+
+    regressions.len()
 }
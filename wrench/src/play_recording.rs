@@ -0,0 +1,64 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::NotifierEvent;
+use crate::WindowWrapper;
+use crate::wrench::Wrench;
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+use std::thread;
+use std::time::{Duration, Instant};
+use webrender::recording::RecordingReader;
+
+/// Replays a recording made with `DebugCommand::StartTransactionRecording`.
+///
+/// Transactions are sent with the same timing as when they were recorded,
+/// unless `fast` is set, in which case each one is sent as soon as the frame
+/// generated by the previous one has been rendered. All the transactions are
+/// sent to the wrench document, whichever document they were recorded from.
+pub fn play_recording(
+    wrench: &mut Wrench,
+    window: &mut WindowWrapper,
+    rx: Receiver<NotifierEvent>,
+    path: &Path,
+    fast: bool,
+) {
+    let reader = match RecordingReader::open(path) {
+        Ok(reader) => reader,
+        Err(err) => panic!("Unable to open recording {:?}: {}", path, err),
+    };
+
+    let start = Instant::now();
+    let mut transaction_count = 0;
+    let mut frame_count = 0;
+
+    for recorded in reader {
+        if !fast {
+            let target = Duration::from_nanos(recorded.time_ns);
+            let elapsed = start.elapsed();
+            if target > elapsed {
+                thread::sleep(target - elapsed);
+            }
+        }
+
+        let generate_frame = recorded.generate_frame;
+        let txn = recorded.into_transaction();
+        wrench.api.send_transaction(wrench.document_id, txn);
+        transaction_count += 1;
+
+        if generate_frame {
+            rx.recv().unwrap();
+            wrench.render();
+            window.swap_buffers();
+            frame_count += 1;
+        }
+    }
+
+    println!(
+        "Replayed {} transactions and {} frames in {:.2} ms",
+        transaction_count,
+        frame_count,
+        start.elapsed().as_secs_f64() * 1000.0,
+    );
+}
@@ -10,7 +10,6 @@ use std::path::{Path, PathBuf};
 use std::sync::mpsc::Receiver;
 use webrender::api::units::*;
 use crate::wrench::{Wrench, WrenchThing};
-use crate::yaml_frame_reader::YamlFrameReader;
 
 pub enum ReadSurface {
     Screen,
@@ -78,11 +77,11 @@ pub fn png(
     wrench: &mut Wrench,
     surface: ReadSurface,
     window: &mut WindowWrapper,
-    mut reader: YamlFrameReader,
+    mut thing: Box<dyn WrenchThing>,
     rx: Receiver<NotifierEvent>,
-    out_path: Option<PathBuf>,
+    out_path: PathBuf,
 ) {
-    reader.do_frame(wrench);
+    thing.do_frame(wrench);
 
     // wait for the frame
     rx.recv().unwrap();
@@ -108,11 +107,5 @@ pub fn png(
         }
     };
 
-    let out_path = out_path.unwrap_or_else(|| {
-        let mut path = reader.yaml_path().clone();
-        path.set_extension("png");
-        path
-    });
-
     save(out_path, data, fb_size, settings);
 }
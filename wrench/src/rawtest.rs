@@ -6,6 +6,7 @@ use euclid::{point2, size2, rect, Box2D};
 use std::sync::Arc;
 use std::sync::atomic::{AtomicIsize, Ordering};
 use std::sync::mpsc::Receiver;
+use std::time::Duration;
 use webrender::api::*;
 use webrender::render_api::*;
 use webrender::api::units::*;
@@ -48,6 +49,8 @@ impl<'a> RawtestHarness<'a> {
         self.test_capture();
         self.test_zero_height_window();
         self.test_clear_cache();
+        self.test_video_frame_queue();
+        self.test_scrollbar_thumb_in_repeated_pipeline();
     }
 
     fn render_and_get_pixels(&mut self, window_rect: FramebufferIntRect) -> Vec<u8> {
@@ -1376,8 +1379,41 @@ impl<'a> RawtestHarness<'a> {
             (0, 5),
         );
 
+        // Add a rectangle that is clipped by an image mask with an L-shaped
+        // polygon, which leaves out its bottom right quarter.
+        let mut txn = Transaction::new();
+        let mask_image = self.wrench.api.generate_image_key();
+        txn.add_image(
+            mask_image,
+            ImageDescriptor::new(1, 1, ImageFormat::BGRA8, ImageDescriptorFlags::IS_OPAQUE),
+            ImageData::new(vec![0xFF; 4]),
+            None,
+        );
+        let rect = LayoutRect::from_origin_and_size(LayoutPoint::new(0., 200.), LayoutSize::new(100., 100.));
+        let points = [
+            LayoutPoint::new(0., 0.),
+            LayoutPoint::new(100., 0.),
+            LayoutPoint::new(100., 50.),
+            LayoutPoint::new(50., 50.),
+            LayoutPoint::new(50., 100.),
+            LayoutPoint::new(0., 100.),
+        ];
+        let clip_id = builder.define_clip_image_mask(
+            space_and_clip.spatial_id,
+            ImageMask { image: mask_image, rect },
+            &points,
+            FillRule::Nonzero,
+        );
+        let clip_chain_id = builder.define_clip_chain(None, vec![clip_id]);
+        builder.push_hit_test(
+            rect,
+            clip_chain_id,
+            space_and_clip.spatial_id,
+            PrimitiveFlags::default(),
+            (0, 6),
+        );
+
         let mut epoch = Epoch(0);
-        let txn = Transaction::new();
         self.submit_dl(&mut epoch, builder, txn);
 
         // We render to ensure that the hit tester is up to date with the current scene.
@@ -1430,6 +1466,12 @@ impl<'a> RawtestHarness<'a> {
 
         test_rounded_rectangle(WorldPoint::new(100., 100.), WorldSize::new(100., 100.), (0, 4));
         test_rounded_rectangle(WorldPoint::new(200., 100.), WorldSize::new(100., 100.), (0, 5));
+
+        // Only the L-shaped polygon of the image mask should be hit.
+        assert_hit_test(WorldPoint::new(25., 225.), vec![(0, 6), (0, 1)]);
+        assert_hit_test(WorldPoint::new(75., 225.), vec![(0, 6), (0, 1)]);
+        assert_hit_test(WorldPoint::new(25., 275.), vec![(0, 6), (0, 1)]);
+        assert_hit_test(WorldPoint::new(75., 275.), vec![(0, 1)]);
     }
 
     fn test_clear_cache(&mut self) {
@@ -1447,4 +1489,158 @@ impl<'a> RawtestHarness<'a> {
         self.rx.recv().unwrap();
         self.wrench.render();
     }
+
+    fn test_video_frame_queue(&mut self) {
+        println!("\tvideo frame queue...");
+
+        // Locks image 1 as red and image 2 as green.
+        struct VideoHandler {
+            frames: [Vec<u8>; 2],
+        }
+
+        impl ExternalImageHandler for VideoHandler {
+            fn lock(&mut self, key: ExternalImageId, _channel_index: u8) -> ExternalImage {
+                ExternalImage {
+                    uv: TexelRect::new(0.0, 0.0, 64.0, 64.0),
+                    source: ExternalImageSource::RawData(&self.frames[key.0 as usize - 1]),
+                }
+            }
+            fn unlock(&mut self, _key: ExternalImageId, _channel_index: u8) {}
+        }
+
+        // BGRA8 pixels.
+        self.wrench.renderer.set_external_image_handler(Box::new(VideoHandler {
+            frames: [[0, 0, 255, 255].repeat(64 * 64), [0, 255, 0, 255].repeat(64 * 64)],
+        }));
+
+        let window_size = self.window.get_inner_size();
+        let test_size = FramebufferIntSize::new(64, 64);
+        let window_rect = FramebufferIntRect::from_origin_and_size(
+            point2(0, window_size.height - test_size.height),
+            test_size,
+        );
+
+        let queue_id = ExternalImageId(1);
+        let mut txn = Transaction::new();
+        let img = self.wrench.api.generate_image_key();
+        txn.add_image(
+            img,
+            ImageDescriptor::new(64, 64, ImageFormat::BGRA8, ImageDescriptorFlags::IS_OPAQUE),
+            ImageData::External(ExternalImageData {
+                id: queue_id,
+                channel_index: 0,
+                image_type: ExternalImageType::Buffer,
+            }),
+            None,
+        );
+
+        let mut builder = DisplayListBuilder::new(self.wrench.root_pipeline_id);
+        builder.begin();
+        let info = self.make_common_properties(rect(0.0, 0.0, 64.0, 64.0).to_box2d());
+        builder.push_image(
+            &info,
+            info.clip_rect,
+            ImageRendering::Auto,
+            AlphaType::PremultipliedAlpha,
+            img,
+            ColorF::WHITE,
+        );
+
+        let mut epoch = Epoch(0);
+        self.submit_dl(&mut epoch, builder, txn);
+        let pixels = self.render_and_get_pixels(window_rect);
+        assert_eq!(&pixels[.. 4], &[255, 0, 0, 255]);
+
+        // Composite with the green frame due, which makes the backend build
+        // a frame showing it without another transaction.
+        self.wrench.renderer.enqueue_video_frames(queue_id, vec![VideoFrame {
+            id: ExternalImageId(2),
+            channel_index: 0,
+            presentation_time: 10,
+        }]);
+        self.wrench.renderer.set_next_vsync_time(20);
+        let results = self.wrench.render();
+        assert_eq!(results.video_frames.len(), 1);
+
+        self.rx.recv_timeout(Duration::from_secs(5))
+            .expect("no frame was built for the new video frame");
+        self.wrench.render();
+        let pixels = self.wrench.renderer.read_pixels_rgba8(window_rect);
+        assert_eq!(&pixels[.. 4], &[0, 255, 0, 255]);
+
+        self.wrench.renderer.remove_video_frame_queue(queue_id);
+        let mut txn = Transaction::new();
+        txn.delete_image(img);
+        self.wrench.api.send_transaction(self.wrench.document_id, txn);
+    }
+
+    fn test_scrollbar_thumb_in_repeated_pipeline(&mut self) {
+        println!("\tscrollbar thumb in repeated pipeline...");
+
+        let window_size = self.window.get_inner_size();
+        let test_size = FramebufferIntSize::new(100, 100);
+        let iframe_rect = |x| FramebufferIntRect::from_origin_and_size(
+            point2(x, window_size.height - test_size.height),
+            test_size,
+        );
+
+        // A pipeline with a scroll frame and the thumb of its scrollbar.
+        let child_pipeline_id = PipelineId(1, 1);
+        let mut builder = DisplayListBuilder::new(child_pipeline_id);
+        builder.begin();
+        let root_space_and_clip = SpaceAndClipInfo::root_scroll(child_pipeline_id);
+        let scroll_frame = builder.define_scroll_frame(
+            root_space_and_clip.spatial_id,
+            ExternalScrollId(1, child_pipeline_id),
+            rect(0.0, 0.0, 100.0, 400.0).to_box2d(),
+            rect(0.0, 0.0, 100.0, 100.0).to_box2d(),
+            LayoutVector2D::zero(),
+            APZScrollGeneration::default(),
+            HasScrollLinkedEffect::No,
+            SpatialTreeItemKey::new(0, 0),
+        );
+        let info = self.make_common_properties_with_clip_and_spatial(
+            rect(80.0, 0.0, 20.0, 25.0).to_box2d(),
+            root_space_and_clip.clip_chain_id,
+            root_space_and_clip.spatial_id,
+        );
+        builder.push_scrollbar_thumb(
+            &info,
+            info.clip_rect,
+            ColorF::new(0.0, 1.0, 0.0, 1.0),
+            scroll_frame,
+            ScrollbarOrientation::Vertical,
+            100.0,
+            self.wrench.api.generate_property_binding_key(),
+            self.wrench.api.generate_property_binding_key(),
+            0,
+            0,
+        );
+        let mut txn = Transaction::new();
+        txn.set_display_list(Epoch(0), builder.end());
+        self.wrench.api.send_transaction(self.wrench.document_id, txn);
+
+        // Embedding it twice builds each thumb in its own pipeline instance.
+        let mut builder = DisplayListBuilder::new(self.wrench.root_pipeline_id);
+        builder.begin();
+        let root_space_and_clip = SpaceAndClipInfo::root_scroll(self.wrench.root_pipeline_id);
+        for x in [0.0, 200.0] {
+            let bounds = rect(x, 0.0, 100.0, 100.0).to_box2d();
+            builder.push_iframe(
+                bounds,
+                bounds,
+                &root_space_and_clip,
+                child_pipeline_id,
+                false,
+            );
+        }
+
+        let txn = Transaction::new();
+        self.submit_dl(&mut Epoch(1), builder, txn);
+        let first = self.render_and_get_pixels(iframe_rect(0));
+        let second = self.wrench.renderer.read_pixels_rgba8(iframe_rect(200));
+
+        assert!(first.chunks(4).any(|pixel| pixel == [0, 255, 0, 255]));
+        self.compare_pixels(first, second, test_size);
+    }
 }
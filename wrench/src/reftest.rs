@@ -562,10 +562,26 @@ struct YamlRenderOutput {
     results: RenderResults,
 }
 
+/// The glyph rasterizer backend wrench was built with.
+pub fn glyph_backend() -> &'static str {
+    if cfg!(feature = "font_backend_swash") {
+        "swash"
+    } else if cfg!(feature = "font_backend_fontdue") {
+        "fontdue"
+    } else if cfg!(feature = "font_backend_ab_glyph") {
+        "ab_glyph"
+    } else if cfg!(feature = "font_backend_tiny_skia") {
+        "tiny_skia"
+    } else {
+        "native"
+    }
+}
+
 struct ReftestEnvironment {
     pub platform: &'static str,
     pub version: Option<semver::Version>,
     pub mode: &'static str,
+    pub glyph_backend: &'static str,
 }
 
 impl ReftestEnvironment {
@@ -574,11 +590,12 @@ impl ReftestEnvironment {
             platform: Self::platform(wrench, window),
             version: Self::version(wrench, window),
             mode: Self::mode(),
+            glyph_backend: glyph_backend(),
         }
     }
 
     fn has(&self, condition: &str) -> bool {
-        if self.platform == condition || self.mode == condition {
+        if self.platform == condition || self.mode == condition || self.glyph_backend == condition {
             return true;
         }
         if let (Some(v), Ok(r)) = (&self.version, &semver::VersionReq::parse(condition)) {
@@ -661,6 +678,12 @@ impl ReftestEnvironment {
                 // Skip due to platform not matching
                 Some(args.iter().any(|arg| arg == &self.platform))
             }
+            backend if backend.starts_with("glyph_backend(") => {
+                // e.g. fuzzy-if(glyph_backend(swash),2,100) for tests whose
+                // glyphs are rasterized slightly differently by swash.
+                let (_, args, _) = parse_function(backend);
+                Some(args.iter().any(|arg| arg == &self.glyph_backend))
+            }
             op if op.starts_with("not(") => {
                 let (_, args, _) = parse_function(op);
                 Some(!self.parse_condition(args[0]).expect("unknown condition"))
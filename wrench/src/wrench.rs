@@ -513,6 +513,7 @@ impl Wrench {
         render_mode: Option<FontRenderMode>,
         bg_color: Option<ColorU>,
         synthetic_italics: SyntheticItalics,
+        synthetic_stretch: SyntheticStretch,
     ) -> FontInstanceKey {
         let key = self.api.generate_font_instance_key();
         let mut txn = Transaction::new();
@@ -525,6 +526,7 @@ impl Wrench {
             options.bg_color = bg_color;
         }
         options.synthetic_italics = synthetic_italics;
+        options.synthetic_stretch = synthetic_stretch;
         txn.add_font_instance(key, font_key, size, Some(options), None, Vec::new());
         self.api.send_transaction(self.document_id, txn);
         key
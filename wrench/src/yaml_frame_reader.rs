@@ -329,7 +329,10 @@ pub struct YamlFrameReader {
     image_map: HashMap<(PathBuf, Option<i64>), (ImageKey, LayoutSize)>,
 
     fonts: HashMap<FontDescriptor, FontKey>,
-    font_instances: HashMap<(FontKey, FontSize, FontInstanceFlags, Option<ColorU>, SyntheticItalics), FontInstanceKey>,
+    font_instances: HashMap<
+        (FontKey, FontSize, FontInstanceFlags, Option<ColorU>, SyntheticItalics, SyntheticStretch),
+        FontInstanceKey,
+    >,
     font_render_mode: Option<FontRenderMode>,
     allow_mipmaps: bool,
 
@@ -399,10 +402,6 @@ impl YamlFrameReader {
         *self.spatial_id_stack.last().unwrap()
     }
 
-    pub fn yaml_path(&self) -> &PathBuf {
-        &self.yaml_path
-    }
-
     pub fn new_from_args(args: &clap::ArgMatches) -> YamlFrameReader {
         let yaml_file = args.value_of("INPUT").map(PathBuf::from).unwrap();
 
@@ -748,12 +747,13 @@ impl YamlFrameReader {
         bg_color: Option<ColorU>,
         flags: FontInstanceFlags,
         synthetic_italics: SyntheticItalics,
+        synthetic_stretch: SyntheticStretch,
         wrench: &mut Wrench,
     ) -> FontInstanceKey {
         let font_render_mode = self.font_render_mode;
 
         *self.font_instances
-            .entry((font_key, size.into(), flags, bg_color, synthetic_italics))
+            .entry((font_key, size.into(), flags, bg_color, synthetic_italics, synthetic_stretch))
             .or_insert_with(|| {
                 wrench.add_font_instance(
                     font_key,
@@ -762,6 +762,7 @@ impl YamlFrameReader {
                     font_render_mode,
                     bg_color,
                     synthetic_italics,
+                    synthetic_stretch,
                 )
             })
     }
@@ -1300,8 +1301,9 @@ impl YamlFrameReader {
             Some("auto") | None => ImageRendering::Auto,
             Some("crisp-edges") => ImageRendering::CrispEdges,
             Some("pixelated") => ImageRendering::Pixelated,
+            Some("high-quality") => ImageRendering::HighQuality,
             Some(_) => panic!(
-                "ImageRendering can be auto, crisp-edges, or pixelated -- got {:?}",
+                "ImageRendering can be auto, crisp-edges, pixelated, or high-quality -- got {:?}",
                 item
             ),
         };
@@ -1358,6 +1360,10 @@ impl YamlFrameReader {
         } else {
             SyntheticItalics::disabled()
         };
+        let synthetic_stretch = match item["synthetic-stretch"].as_f32() {
+            Some(percentage) => SyntheticStretch::from_percentage(percentage),
+            None => SyntheticStretch::normal(),
+        };
 
         let mut flags = FontInstanceFlags::empty();
         if item["synthetic-bold"].as_bool().unwrap_or(false) {
@@ -1375,6 +1381,9 @@ impl YamlFrameReader {
         if item["flip-y"].as_bool().unwrap_or(false) {
             flags |= FontInstanceFlags::FLIP_Y;
         }
+        if item["distance-field"].as_bool().unwrap_or(false) {
+            flags |= FontInstanceFlags::SDF;
+        }
 
         assert!(
             item["blur-radius"].is_badvalue(),
@@ -1388,6 +1397,7 @@ impl YamlFrameReader {
                                                                  bg_color,
                                                                  flags,
                                                                  synthetic_italics,
+                                                                 synthetic_stretch,
                                                                  wrench);
 
         assert!(
@@ -1801,11 +1811,17 @@ impl YamlFrameReader {
         if let Some(image_mask) = self.as_image_mask(&yaml["image-mask"], wrench) {
             assert!(clip_id.is_none(), "invalid clip definition");
 
+            // An optional polygon further restricts the mask.
+            let points = yaml["image-mask"]["points"].as_vec_points().unwrap_or_default();
+            let fill_rule = yaml["image-mask"]["fill-rule"]
+                .as_fill_rule()
+                .unwrap_or(FillRule::Nonzero);
+
             clip_id = Some(dl.define_clip_image_mask(
                 spatial_id,
                 image_mask,
-                &[],
-                FillRule::Nonzero,
+                &points,
+                fill_rule,
             ));
         }
 
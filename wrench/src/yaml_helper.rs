@@ -33,6 +33,8 @@ pub trait YamlHelper {
     fn as_transform_style(&self) -> Option<TransformStyle>;
     fn as_raster_space(&self) -> Option<RasterSpace>;
     fn as_clip_mode(&self) -> Option<ClipMode>;
+    fn as_fill_rule(&self) -> Option<FillRule>;
+    fn as_vec_points(&self) -> Option<Vec<LayoutPoint>>;
     fn as_mix_blend_mode(&self) -> Option<MixBlendMode>;
     fn as_filter_op(&self) -> Option<FilterOp>;
     fn as_vec_filter_op(&self) -> Option<Vec<FilterOp>>;
@@ -155,6 +157,8 @@ define_string_enum!(
 
 define_string_enum!(ClipMode, [Clip = "clip", ClipOut = "clip-out"]);
 
+define_string_enum!(FillRule, [Nonzero = "nonzero", Evenodd = "evenodd"]);
+
 define_string_enum!(
     ComponentTransferFuncType,
     [
@@ -537,6 +541,14 @@ impl YamlHelper for Yaml {
         self.as_str().and_then(StringEnum::from_str)
     }
 
+    fn as_fill_rule(&self) -> Option<FillRule> {
+        self.as_str().and_then(StringEnum::from_str)
+    }
+
+    fn as_vec_points(&self) -> Option<Vec<LayoutPoint>> {
+        self.as_vec().map(|v| v.iter().map(|p| p.as_point().unwrap()).collect())
+    }
+
     fn as_filter_op(&self) -> Option<FilterOp> {
         if let Some(s) = self.as_str() {
             match parse_function(s) {